@@ -0,0 +1,15 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// Throughput of trigger condition evaluation against decoded events.
+// Run with `cargo bench --features bench --bench conditions`.
+
+use triggr::bench::condition_throughput;
+
+/// Evaluations per run.
+const ITERATIONS: u64 = 1_000_000;
+
+fn main() {
+    let result = condition_throughput(ITERATIONS);
+    println!("{:>16} {:>12}", "evaluations/sec", "mean");
+    println!("{:>16.0} {:>12?}", result.per_sec(), result.mean());
+}
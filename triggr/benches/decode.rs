@@ -0,0 +1,23 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// Throughput of contract event decoding, the first step of every event's path.
+// Run with `cargo bench --features bench --bench decode`.
+
+use triggr::bench::{decode_throughput, DecodeMode};
+
+/// Events decoded per run.
+const ITERATIONS: u64 = 200_000;
+
+fn main() {
+    println!("{:>8} {:>16} {:>12}", "mode", "events/sec", "mean");
+
+    for mode in [DecodeMode::Lenient, DecodeMode::Strict] {
+        let result = decode_throughput(ITERATIONS, mode);
+        println!(
+            "{:>8} {:>16.0} {:>12?}",
+            format!("{mode:?}").to_lowercase(),
+            result.per_sec(),
+            result.mean()
+        );
+    }
+}
@@ -0,0 +1,23 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// Throughput of document writes with concurrent writers.
+// Run with `cargo bench --features bench --bench documents`.
+
+use triggr::bench::document_write_throughput;
+
+/// Documents written per run.
+const DOCS: u64 = 20_000;
+
+fn main() {
+    println!("{:>8} {:>16} {:>12}", "writers", "writes/sec", "mean");
+
+    for writers in [1, 4, 16] {
+        let result = document_write_throughput(DOCS, writers);
+        println!(
+            "{:>8} {:>16.0} {:>12?}",
+            writers,
+            result.per_sec(),
+            result.mean()
+        );
+    }
+}
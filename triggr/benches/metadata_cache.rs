@@ -0,0 +1,28 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// Throughput of metadata cache lookups while metadata is being updated concurrently.
+// Run with `cargo bench --features bench --bench metadata_cache`.
+
+use std::time::Duration;
+
+use triggr::bench::metadata_cache_throughput;
+
+/// Number of contracts held in cache.
+const CONTRACTS: usize = 64;
+
+/// Duration of each run.
+const RUN_FOR: Duration = Duration::from_secs(2);
+
+fn main() {
+    println!("{:>8} {:>16} {:>12}", "readers", "lookups/sec", "updates");
+
+    for readers in [1, 2, 4, 8, 16] {
+        let result = metadata_cache_throughput(readers, CONTRACTS, RUN_FOR);
+        println!(
+            "{:>8} {:>16.0} {:>12}",
+            result.readers,
+            result.reads_per_sec(),
+            result.updates
+        );
+    }
+}
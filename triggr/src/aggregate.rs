@@ -0,0 +1,249 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module aggregates the documents of a collection into time series.
+// Documents are grouped in fixed-width time buckets and a numeric field is aggregated per bucket.
+// Numbers are summed as exact decimals, so amounts stored as big integer strings stay exact.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    prelude::Document,
+    util::{to_decimal, value_at},
+};
+
+/// Max number of points of a series, empty buckets included.
+pub const MAX_SERIES_POINTS: u64 = 10_000;
+
+/// How the values of a bucket are combined.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    /// Number of documents
+    #[default]
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+/// Time a document is placed in a bucket by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TimeField {
+    /// Creation time of the document
+    CreatedAt,
+    /// Last update of the document
+    UpdatedAt,
+    /// A data field holding a unix timestamp in milliseconds
+    Data(String),
+}
+
+impl FromStr for TimeField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "created_at" => TimeField::CreatedAt,
+            "updated_at" => TimeField::UpdatedAt,
+            field => TimeField::Data(field.to_string()),
+        })
+    }
+}
+
+/// A time series query.
+#[derive(Clone, Debug)]
+pub struct SeriesQuery {
+    /// Field aggregated (not needed to count)
+    pub field: Option<String>,
+    pub aggregation: Aggregation,
+    /// Width of the buckets (milliseconds)
+    pub bucket: u64,
+    pub time: TimeField,
+    /// Start of the series (unix ms, inclusive)
+    pub from: Option<u64>,
+    /// End of the series (unix ms, exclusive)
+    pub to: Option<u64>,
+}
+
+/// A point of a series.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct SeriesPoint {
+    /// Start of the bucket (unix ms)
+    pub t: u64,
+    /// Aggregated value, null for an empty bucket without a neutral value (avg, min, max)
+    pub value: Value,
+    /// Documents in the bucket
+    pub count: u64,
+}
+
+/// A time series, ready to plot.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Series {
+    pub field: Option<String>,
+    pub aggregation: Aggregation,
+    /// Width of the buckets (milliseconds)
+    pub bucket: u64,
+    /// Points in time order, one per bucket
+    pub points: Vec<SeriesPoint>,
+    /// Documents without a time or a numeric value, left out of the series
+    pub skipped: u64,
+}
+
+/// Running aggregate of a bucket.
+#[derive(Default)]
+struct Accumulator {
+    count: u64,
+    sum: BigDecimal,
+    min: Option<BigDecimal>,
+    max: Option<BigDecimal>,
+}
+
+impl Accumulator {
+    fn add(&mut self, value: Option<BigDecimal>) {
+        self.count += 1;
+        let Some(value) = value else {
+            return;
+        };
+
+        if self.min.as_ref().is_none_or(|min| value < *min) {
+            self.min = Some(value.clone());
+        }
+        if self.max.as_ref().is_none_or(|max| value > *max) {
+            self.max = Some(value.clone());
+        }
+        self.sum += value;
+    }
+
+    fn value(&self, aggregation: Aggregation) -> Value {
+        let decimal = match aggregation {
+            Aggregation::Count => return Value::from(self.count),
+            Aggregation::Sum => Some(self.sum.clone()),
+            Aggregation::Avg => {
+                (self.count > 0).then(|| (&self.sum / BigDecimal::from(self.count)).round(18))
+            }
+            Aggregation::Min => self.min.clone(),
+            Aggregation::Max => self.max.clone(),
+        };
+
+        decimal
+            .and_then(|d| serde_json::from_str(&d.normalized().to_string()).ok())
+            .unwrap_or(Value::Null)
+    }
+}
+
+/// Parse a bucket width such as `30s`, `5m`, `1h`, `1d` or `1w` into milliseconds.
+pub fn parse_bucket(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("Missing unit in bucket '{input}' (s, m, h, d or w)"))?;
+    let (amount, unit) = input.split_at(split);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid bucket '{input}'"))?;
+    let unit_ms = match unit {
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        "w" => 604_800_000,
+        _ => return Err(format!("Unknown unit '{unit}' in bucket (s, m, h, d or w)")),
+    };
+
+    match amount.checked_mul(unit_ms) {
+        Some(0) | None => Err(format!("Invalid bucket '{input}'")),
+        Some(ms) => Ok(ms),
+    }
+}
+
+/// Aggregate documents into a series.
+/// Buckets without documents between the first and last one (or `from` and `to`) are filled in.
+pub fn series(
+    docs: impl Iterator<Item = Document>,
+    query: &SeriesQuery,
+) -> Result<Series, String> {
+    if query.aggregation != Aggregation::Count && query.field.is_none() {
+        return Err("A field is required to aggregate values".to_string());
+    }
+
+    let mut buckets: BTreeMap<u64, Accumulator> = BTreeMap::new();
+    let mut skipped = 0;
+    for doc in docs {
+        let time = match &query.time {
+            TimeField::CreatedAt => Some(doc.metadata.created_at),
+            TimeField::UpdatedAt => Some(doc.metadata.updated_at),
+            TimeField::Data(field) => value_at(&doc.data, field)
+                .and_then(to_decimal)
+                .and_then(|t| t.to_string().parse::<u64>().ok()),
+        };
+        let Some(time) = time.filter(|t| {
+            query.from.is_none_or(|from| *t >= from) && query.to.is_none_or(|to| *t < to)
+        }) else {
+            skipped += 1;
+            continue;
+        };
+
+        let value = match &query.field {
+            Some(field) => match value_at(&doc.data, field).and_then(to_decimal) {
+                Some(value) => Some(value),
+                // Counting doesn't need the value
+                None if query.aggregation == Aggregation::Count => None,
+                None => {
+                    skipped += 1;
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        buckets
+            .entry(time - time % query.bucket)
+            .or_default()
+            .add(value);
+    }
+
+    // Span of the series, aligned on buckets
+    let first = query
+        .from
+        .map(|from| from - from % query.bucket)
+        .or_else(|| buckets.keys().next().copied());
+    let last = query
+        .to
+        .map(|to| to.saturating_sub(1) / query.bucket * query.bucket)
+        .or_else(|| buckets.keys().next_back().copied());
+
+    let mut points = Vec::new();
+    if let (Some(first), Some(last)) = (first, last) {
+        if last >= first && (last - first) / query.bucket >= MAX_SERIES_POINTS {
+            return Err(format!(
+                "The series would have more than {MAX_SERIES_POINTS} points, use a wider bucket"
+            ));
+        }
+
+        let empty = Accumulator::default();
+        let mut t = first;
+        while t <= last {
+            let bucket = buckets.get(&t).unwrap_or(&empty);
+            points.push(SeriesPoint {
+                t,
+                value: bucket.value(query.aggregation),
+                count: bucket.count,
+            });
+            t += query.bucket;
+        }
+    }
+
+    Ok(Series {
+        field: query.field.clone(),
+        aggregation: query.aggregation,
+        bucket: query.bucket,
+        points,
+        skipped,
+    })
+}
@@ -0,0 +1,182 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the anomaly detector of numeric event fields.
+// Every numeric field of the events of a contract keeps an exponentially weighted mean and variance.
+// An event is scored against the statistics seen before it (how many standard deviations above the
+// mean each field is), then folded into them. Triggers read the scores with `anomaly(...)`.
+
+use bigdecimal::ToPrimitive;
+use dashmap::DashMap;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{chain::polkadot::prelude::EventData, util::to_decimal};
+
+/// Score (standard deviations above the mean) from which a value is an anomaly, when not given.
+pub const DEFAULT_ANOMALY_THRESHOLD: f64 = 3.0;
+
+/// Default weight of a new value in the moving statistics.
+const DEFAULT_ANOMALY_ALPHA: f64 = 0.05;
+
+/// Default number of values a field needs before it is scored.
+const DEFAULT_ANOMALY_WARMUP: u64 = 30;
+
+/// Bound of the scores, reached by any change of a field that never varied.
+const MAX_SCORE: f64 = 1e9;
+
+/// Moving statistics of a numeric field.
+#[derive(Clone, Copy, Debug, Default)]
+struct FieldStats {
+    mean: f64,
+    variance: f64,
+    samples: u64,
+}
+
+impl FieldStats {
+    /// Score a value, once enough values were seen.
+    fn score(&self, value: f64, warmup: u64) -> Option<f64> {
+        if self.samples < warmup.max(1) {
+            return None;
+        }
+
+        let deviation = self.variance.sqrt();
+        let score = match deviation > 0.0 {
+            true => (value - self.mean) / deviation,
+            false if value == self.mean => 0.0,
+            false => (value - self.mean).signum() * MAX_SCORE,
+        };
+        Some(score.clamp(-MAX_SCORE, MAX_SCORE))
+    }
+
+    /// Fold a value into the statistics.
+    fn update(&mut self, value: f64, alpha: f64) {
+        if self.samples == 0 {
+            self.mean = value;
+        } else {
+            let diff = value - self.mean;
+            let increment = alpha * diff;
+            self.mean += increment;
+            self.variance = (1.0 - alpha) * (self.variance + diff * increment);
+        }
+        self.samples += 1;
+    }
+}
+
+/// Baseline of a numeric event field, as seen by the detector.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct FieldBaseline {
+    pub event_name: String,
+    pub field: String,
+    /// Moving mean of the field
+    pub mean: f64,
+    /// Moving standard deviation of the field
+    pub deviation: f64,
+    /// Values seen since the instance started
+    pub samples: u64,
+    /// Whether enough values were seen to score the field
+    pub ready: bool,
+}
+
+/// Anomaly detector of the numeric fields of contract events.
+/// Statistics are kept in memory, so they are learned again after a restart.
+pub struct AnomalyDetector {
+    /// Statistics per `{contract}:{event}:{field}`
+    stats: DashMap<String, FieldStats>,
+    alpha: f64,
+    warmup: u64,
+}
+
+impl Default for AnomalyDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_ANOMALY_ALPHA, DEFAULT_ANOMALY_WARMUP)
+    }
+}
+
+impl AnomalyDetector {
+    /// Create a detector giving a new value the weight `alpha` (between 0 and 1),
+    /// scoring fields once they have seen `warmup` values.
+    pub fn new(alpha: f64, warmup: u64) -> Self {
+        Self {
+            stats: DashMap::new(),
+            alpha,
+            warmup,
+        }
+    }
+
+    /// Configure the detector from `TRIGGR_ANOMALY_ALPHA` and `TRIGGR_ANOMALY_WARMUP`.
+    pub fn from_env() -> Self {
+        let alpha = std::env::var("TRIGGR_ANOMALY_ALPHA")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|alpha| *alpha > 0.0 && *alpha < 1.0)
+            .unwrap_or(DEFAULT_ANOMALY_ALPHA);
+        let warmup = std::env::var("TRIGGR_ANOMALY_WARMUP")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_ANOMALY_WARMUP);
+
+        Self::new(alpha, warmup)
+    }
+
+    /// Score the numeric fields of an event of a contract, then learn from them.
+    pub fn observe(&self, contract_addr: &str, event: &mut EventData) {
+        for (field, value) in &event.fields {
+            let Some(value) = to_decimal(value).and_then(|v| v.to_f64()) else {
+                continue;
+            };
+
+            let key = Self::key(contract_addr, &event.event_name, field);
+            let mut stats = self.stats.entry(key).or_default();
+            if let Some(score) = stats.score(value, self.warmup) {
+                event.scores.insert(field.clone(), score);
+            }
+            stats.update(value, self.alpha);
+        }
+    }
+
+    /// Score the numeric fields of an event of a contract without learning from them.
+    pub fn score(&self, contract_addr: &str, event: &mut EventData) {
+        for (field, value) in &event.fields {
+            let Some(value) = to_decimal(value).and_then(|v| v.to_f64()) else {
+                continue;
+            };
+
+            let key = Self::key(contract_addr, &event.event_name, field);
+            if let Some(score) = self
+                .stats
+                .get(&key)
+                .and_then(|stats| stats.score(value, self.warmup))
+            {
+                event.scores.insert(field.clone(), score);
+            }
+        }
+    }
+
+    /// Return the baselines of the event fields of a contract.
+    pub fn baselines(&self, contract_addr: &str) -> Vec<FieldBaseline> {
+        let prefix = format!("{contract_addr}:");
+        let mut baselines: Vec<FieldBaseline> = self
+            .stats
+            .iter()
+            .filter_map(|entry| {
+                let (event_name, field) = entry.key().strip_prefix(&prefix)?.split_once(':')?;
+                let stats = entry.value();
+                Some(FieldBaseline {
+                    event_name: event_name.to_string(),
+                    field: field.to_string(),
+                    mean: stats.mean,
+                    deviation: stats.variance.sqrt(),
+                    samples: stats.samples,
+                    ready: stats.samples >= self.warmup.max(1),
+                })
+            })
+            .collect();
+
+        baselines.sort_by(|a, b| (&a.event_name, &a.field).cmp(&(&b.event_name, &b.field)));
+        baselines
+    }
+
+    fn key(contract_addr: &str, event_name: &str, field: &str) -> String {
+        format!("{contract_addr}:{event_name}:{field}")
+    }
+}
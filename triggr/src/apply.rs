@@ -0,0 +1,432 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the declarative configuration of projects.
+// A spec lists the collections of a project with their geo index and state machine, and the
+// triggers of its contract with their webhook. Applying a spec diffs it against the stored
+// configuration and only writes what changed, so applying the same spec twice changes nothing and
+// configurations can live in a git repository. Configuration missing from the spec is removed;
+// data (documents, key-value entries) is never touched. A spec can be planned to preview its
+// changes, and the last applied spec is kept to detect edits made outside of it (drift).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    dsl::{DslAnalyzer, DslParser, Rule, Severity},
+    geo::GeoIndex,
+    lifecycle::StateMachine,
+    name::Name,
+    prelude::{Project, StorageError, StorageResult, Trigger, TriggerStore, Triggr},
+    webhook,
+};
+
+fn default_active() -> bool {
+    true
+}
+
+/// Declarative configuration of a project.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct ProjectSpec {
+    /// Collections with a configuration, by name
+    #[serde(default)]
+    pub collections: BTreeMap<String, CollectionSpec>,
+    /// Triggers of the project's contract
+    #[serde(default)]
+    pub triggers: Vec<TriggerSpec>,
+}
+
+/// Configuration of a collection.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct CollectionSpec {
+    /// Geo point field of the documents
+    #[serde(default)]
+    pub geo_index: Option<GeoIndex>,
+    /// Allowed states and transitions of a field, enforced on writes
+    #[serde(default)]
+    pub state_machine: Option<StateMachine>,
+}
+
+/// Configuration of a trigger.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TriggerSpec {
+    pub id: String,
+    #[serde(default)]
+    pub description: String,
+    /// Trigger DSL
+    pub dsl: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// URL the `notify` actions of the trigger are POSTed to
+    #[serde(default)]
+    pub webhook: Option<String>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+/// What applying a spec does to a resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeAction {
+    Create,
+    Update,
+    Delete,
+}
+
+/// Change of a resource of a project.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Change {
+    /// Kind of resource (`trigger`, `geo_index` or `state_machine`)
+    pub resource: String,
+    /// Trigger id, or collection name of an index or state machine
+    pub name: String,
+    pub action: ChangeAction,
+}
+
+/// Changes applying a spec makes, in order.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ApplyPlan {
+    pub changes: Vec<Change>,
+}
+
+/// Spec last applied to a project.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AppliedSpec {
+    pub spec: ProjectSpec,
+    /// When the spec was applied (ms since epoch)
+    pub applied_at: u64,
+}
+
+/// Difference between the live configuration of a project and the spec last applied to it.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Drift {
+    /// When the spec was applied (ms since epoch)
+    pub applied_at: u64,
+    /// Whether the configuration was changed since
+    pub drifted: bool,
+    /// Changes applying the spec again would make
+    pub changes: Vec<Change>,
+}
+
+/// A trigger of a spec, ready to be stored.
+struct PlannedTrigger {
+    spec: TriggerSpec,
+    rules: Vec<Rule>,
+}
+
+/// Write bringing the configuration of a project in line with a spec.
+enum Operation {
+    PutGeoIndex(String, GeoIndex),
+    DeleteGeoIndex(String),
+    PutStateMachine(String, StateMachine),
+    DeleteStateMachine(String),
+    StoreTrigger(Trigger),
+    DeleteTrigger(String),
+}
+
+/// Changes of a spec with the writes making them.
+#[derive(Default)]
+struct Diff {
+    changes: Vec<Change>,
+    operations: Vec<Operation>,
+}
+
+impl Diff {
+    fn push(&mut self, resource: &str, name: &str, action: ChangeAction, operation: Operation) {
+        self.changes.push(Change {
+            resource: resource.to_string(),
+            name: name.to_string(),
+            action,
+        });
+        self.operations.push(operation);
+    }
+}
+
+/// Return the changes applying a spec to a project would make, without making them.
+pub async fn plan(triggr: &Triggr, project: &Project, spec: ProjectSpec) -> StorageResult<ApplyPlan> {
+    let diff = diff(triggr, project, spec).await?;
+    Ok(ApplyPlan {
+        changes: diff.changes,
+    })
+}
+
+/// Apply a spec to a project, returning the changes made.
+/// The whole spec is checked before anything is written, so an invalid spec changes nothing.
+pub async fn apply(triggr: &Triggr, project: &Project, spec: ProjectSpec) -> StorageResult<ApplyPlan> {
+    let contract_addr = project.contract_address.to_lowercase();
+    let store = &triggr.store;
+
+    let applied = AppliedSpec {
+        spec: spec.clone(),
+        applied_at: Utc::now().timestamp_millis() as u64,
+    };
+    let diff = diff(triggr, project, spec).await?;
+    for operation in diff.operations {
+        match operation {
+            Operation::PutGeoIndex(name, index) => {
+                store.put_geo_index(&project.id, &name, &index)?;
+            }
+            Operation::DeleteGeoIndex(name) => {
+                store.delete_geo_index(&project.id, &name)?;
+            }
+            Operation::PutStateMachine(name, machine) => {
+                store.put_state_machine(&project.id, &name, &machine)?;
+            }
+            Operation::DeleteStateMachine(name) => {
+                store.delete_state_machine(&project.id, &name)?;
+            }
+            Operation::StoreTrigger(trigger) => store.store_trigger(&contract_addr, trigger)?,
+            Operation::DeleteTrigger(id) => store.delete_trigger(&contract_addr, &id)?,
+        }
+    }
+    // Kept even when nothing changed, so drift is checked against the latest spec
+    store.put_applied_spec(&project.id, &applied)?;
+
+    if !diff.changes.is_empty() {
+        tracing::info!(
+            "Applied {} change(s) to the configuration of project {}",
+            diff.changes.len(),
+            project.id
+        );
+    }
+    Ok(ApplyPlan {
+        changes: diff.changes,
+    })
+}
+
+/// Compare the configuration of a project with the spec last applied to it.
+/// Returns `None` if no spec was ever applied.
+pub async fn drift(triggr: &Triggr, project: &Project) -> StorageResult<Option<Drift>> {
+    let Some(applied) = triggr.store.get_applied_spec(&project.id)? else {
+        return Ok(None);
+    };
+
+    let diff = diff(triggr, project, applied.spec).await?;
+    Ok(Some(Drift {
+        applied_at: applied.applied_at,
+        drifted: !diff.changes.is_empty(),
+        changes: diff.changes,
+    }))
+}
+
+/// Check a spec and compute the writes bringing the project in line with it.
+async fn diff(triggr: &Triggr, project: &Project, spec: ProjectSpec) -> StorageResult<Diff> {
+    let contract_addr = project.contract_address.to_lowercase();
+    let collections = validate_collections(&spec.collections)?;
+    let triggers = validate_triggers(triggr, project, &contract_addr, spec.triggers).await?;
+
+    let mut diff = Diff::default();
+    diff_collections(triggr, project, &collections, &mut diff)?;
+    diff_triggers(triggr, project, &contract_addr, triggers, &mut diff)?;
+    Ok(diff)
+}
+
+/// Check the collections of a spec. Returns them by validated name.
+fn validate_collections(
+    collections: &BTreeMap<String, CollectionSpec>,
+) -> StorageResult<BTreeMap<String, CollectionSpec>> {
+    let mut validated = BTreeMap::new();
+    for (name, collection) in collections {
+        let name = Name::collection(name).map_err(|e| invalid("collections", e.to_string()))?;
+        if let Some(index) = &collection.geo_index {
+            index
+                .validate()
+                .map_err(|e| invalid("geo_index", format!("{name}: {e}")))?;
+        }
+        if let Some(machine) = &collection.state_machine {
+            machine
+                .validate()
+                .map_err(|e| invalid("state_machine", format!("{name}: {e}")))?;
+        }
+        validated.insert(name.to_string(), collection.clone());
+    }
+
+    Ok(validated)
+}
+
+/// Check the triggers of a spec: ids, webhooks and DSL, against the contract metadata.
+async fn validate_triggers(
+    triggr: &Triggr,
+    project: &Project,
+    contract_addr: &str,
+    triggers: Vec<TriggerSpec>,
+) -> StorageResult<Vec<PlannedTrigger>> {
+    let existing = triggr.store.list_triggers(contract_addr)?;
+    let metadata = triggr.contract_metadata(contract_addr).await;
+
+    let mut ids = BTreeSet::new();
+    let mut planned = Vec::new();
+    for mut spec in triggers {
+        spec.id = spec.id.trim().to_string();
+        if spec.id.is_empty() {
+            return Err(invalid("triggers", "A trigger has no id".to_string()));
+        }
+        if !ids.insert(spec.id.clone()) {
+            return Err(invalid("triggers", format!("Trigger {} is declared twice", spec.id)));
+        }
+        // Trigger ids are unique per contract, which other projects may watch too
+        if existing
+            .iter()
+            .any(|t| t.id == spec.id && t.project_id != project.id)
+        {
+            return Err(invalid(
+                "triggers",
+                format!("Trigger id {} is taken by another project", spec.id),
+            ));
+        }
+        if let Some(url) = &spec.webhook {
+            webhook::validate_url(url).map_err(|e| invalid("webhook", e))?;
+        }
+
+        let script = DslParser::parse_script(&spec.dsl)
+            .map_err(|e| invalid("trigger", format!("{}: {e}", spec.id)))?;
+        let errors: Vec<String> = metadata
+            .as_ref()
+            .map(|metadata| DslAnalyzer::check_against_metadata(&script, metadata))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message)
+            .collect();
+        if !errors.is_empty() {
+            return Err(invalid("trigger", format!("{}: {}", spec.id, errors.join("; "))));
+        }
+
+        spec.tags = Trigger::normalize_tags(spec.tags);
+        planned.push(PlannedTrigger {
+            spec,
+            rules: script.rules,
+        });
+    }
+
+    Ok(planned)
+}
+
+/// Compare the geo indexes and state machines of the project with the spec.
+fn diff_collections(
+    triggr: &Triggr,
+    project: &Project,
+    collections: &BTreeMap<String, CollectionSpec>,
+    diff: &mut Diff,
+) -> StorageResult<()> {
+    let store = &triggr.store;
+
+    // Configured collections, whether or not they are in the spec
+    let mut names: BTreeSet<String> = collections.keys().cloned().collect();
+    names.extend(store.configured_collections(&project.id)?);
+
+    for name in &names {
+        let wanted = collections.get(name).cloned().unwrap_or_default();
+
+        let current = store.get_geo_index(&project.id, name)?;
+        let put = |index| Operation::PutGeoIndex(name.clone(), index);
+        match (current, wanted.geo_index) {
+            (None, Some(index)) => {
+                diff.push("geo_index", name, ChangeAction::Create, put(index));
+            }
+            (Some(current), Some(index)) if !same(&current, &index) => {
+                diff.push("geo_index", name, ChangeAction::Update, put(index));
+            }
+            (Some(_), None) => {
+                let delete = Operation::DeleteGeoIndex(name.clone());
+                diff.push("geo_index", name, ChangeAction::Delete, delete);
+            }
+            _ => {}
+        }
+
+        let current = store.get_state_machine(&project.id, name)?;
+        let put = |machine| Operation::PutStateMachine(name.clone(), machine);
+        match (current, wanted.state_machine) {
+            (None, Some(machine)) => {
+                diff.push("state_machine", name, ChangeAction::Create, put(machine));
+            }
+            (Some(current), Some(machine)) if !same(&current, &machine) => {
+                diff.push("state_machine", name, ChangeAction::Update, put(machine));
+            }
+            (Some(_), None) => {
+                let delete = Operation::DeleteStateMachine(name.clone());
+                diff.push("state_machine", name, ChangeAction::Delete, delete);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare the triggers of the project with the spec.
+fn diff_triggers(
+    triggr: &Triggr,
+    project: &Project,
+    contract_addr: &str,
+    triggers: Vec<PlannedTrigger>,
+    diff: &mut Diff,
+) -> StorageResult<()> {
+    let existing: Vec<Trigger> = triggr
+        .store
+        .list_triggers(contract_addr)?
+        .into_iter()
+        .filter(|t| t.project_id == project.id)
+        .collect();
+
+    // Triggers left out of the spec are removed
+    for trigger in &existing {
+        if !triggers.iter().any(|t| t.spec.id == trigger.id) {
+            let delete = Operation::DeleteTrigger(trigger.id.clone());
+            diff.push("trigger", &trigger.id, ChangeAction::Delete, delete);
+        }
+    }
+
+    for PlannedTrigger { spec, rules } in triggers {
+        let current = existing.iter().find(|t| t.id == spec.id);
+        let action = match current {
+            None => ChangeAction::Create,
+            Some(current) if !matches_spec(current, &spec) => ChangeAction::Update,
+            Some(_) => continue,
+        };
+
+        let trigger = Trigger {
+            id: spec.id,
+            description: spec.description,
+            project_id: project.id.clone(),
+            dsl: spec.dsl,
+            rules,
+            active: spec.active,
+            created: current.map_or(Utc::now().timestamp_millis() as u64, |t| t.created),
+            last_run: current.map_or(0, |t| t.last_run),
+            tags: spec.tags,
+            webhook: spec.webhook,
+            owner: None,
+        };
+        let id = trigger.id.clone();
+        diff.push("trigger", &id, action, Operation::StoreTrigger(trigger));
+    }
+
+    Ok(())
+}
+
+/// Whether a stored trigger is configured as in a spec.
+fn matches_spec(trigger: &Trigger, spec: &TriggerSpec) -> bool {
+    trigger.dsl == spec.dsl
+        && trigger.description == spec.description
+        && trigger.tags == spec.tags
+        && trigger.webhook == spec.webhook
+        && trigger.active == spec.active
+}
+
+/// Whether two configurations are the same, as stored.
+fn same<T: Serialize>(left: &T, right: &T) -> bool {
+    match (serde_json::to_value(left), serde_json::to_value(right)) {
+        (Ok(left), Ok(right)) => left == right,
+        _ => false,
+    }
+}
+
+fn invalid(field: &str, message: String) -> StorageError {
+    StorageError::InvalidField {
+        field: field.to_string(),
+        message,
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module batches the document writes of the trigger executions of a block.
+// Writes made while a batch is in scope are deferred, then applied once every execution of the
+// block finished: one transaction per collection and a single broadcast per document, so
+// subscribers see the state of the block rather than each write leading to it.
+
+use std::{
+    collections::HashMap,
+    env,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{
+    prelude::{ChangeSource, Document},
+    storage::Sled,
+};
+
+/// Default number of milliseconds without a new event of a block after which it is committed.
+const DEFAULT_BLOCK_BATCH_IDLE_MS: u64 = 50;
+
+/// Document write deferred to the end of a block.
+#[derive(Clone, Debug)]
+pub enum DeferredWrite {
+    /// Insert or update of a document
+    Put {
+        doc: Document,
+        update: bool,
+        source: ChangeSource,
+    },
+    /// Deletion of a document
+    Delete { id: String, source: ChangeSource },
+    /// Items pushed onto array fields of a document
+    Append {
+        id: String,
+        items: HashMap<String, Value>,
+        max: usize,
+        source: ChangeSource,
+    },
+    /// Fields merged into a document (RFC 7386)
+    Merge {
+        id: String,
+        patch: Value,
+        source: ChangeSource,
+    },
+}
+
+impl DeferredWrite {
+    /// Id of the document written.
+    pub fn doc_id(&self) -> &str {
+        match self {
+            Self::Put { doc, .. } => &doc.id,
+            Self::Delete { id, .. } | Self::Append { id, .. } | Self::Merge { id, .. } => id,
+        }
+    }
+}
+
+tokio::task_local! {
+    /// Batch the document writes of the current task are deferred to.
+    static BLOCK_BATCH: Arc<BlockBatch>;
+}
+
+/// Document writes of the trigger executions of a block.
+pub struct BlockBatch {
+    /// Hash of the block
+    pub block: String,
+    /// Deferred writes, by project and collection, in the order they were made
+    writes: Mutex<Vec<(String, String, DeferredWrite)>>,
+}
+
+impl BlockBatch {
+    pub fn new(block: String) -> Self {
+        Self {
+            block,
+            writes: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// How long a block stays open without new events, or `None` if writes are not batched.
+    /// Set with `TRIGGR_BLOCK_BATCH_IDLE_MS`, 0 disabling batching.
+    pub fn idle() -> Option<Duration> {
+        let ms = env::var("TRIGGR_BLOCK_BATCH_IDLE_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_BLOCK_BATCH_IDLE_MS);
+        (ms > 0).then(|| Duration::from_millis(ms))
+    }
+
+    /// Run a future whose document writes are deferred to `batch`, if any.
+    pub async fn scope<F: Future>(batch: Option<Arc<Self>>, f: F) -> F::Output {
+        match batch {
+            Some(batch) => BLOCK_BATCH.scope(batch, f).await,
+            None => f.await,
+        }
+    }
+
+    /// Batch of the current task, if its writes are deferred.
+    pub fn current() -> Option<Arc<Self>> {
+        BLOCK_BATCH.try_with(Clone::clone).ok()
+    }
+
+    /// Defer a write to the end of the block.
+    pub fn defer(&self, project_id: &str, collection: &str, write: DeferredWrite) {
+        if let Ok(mut writes) = self.writes.lock() {
+            writes.push((project_id.to_string(), collection.to_string(), write));
+        }
+    }
+
+    /// Apply the deferred writes, one transaction per collection.
+    /// A collection whose transaction fails doesn't prevent the others from being written.
+    pub async fn commit(&self, store: &Sled) {
+        let writes = self
+            .writes
+            .lock()
+            .map(|mut writes| std::mem::take(&mut *writes))
+            .unwrap_or_default();
+        if writes.is_empty() {
+            return;
+        }
+
+        // Group by collection, keeping the order of the writes within each
+        let total = writes.len();
+        let mut groups: Vec<((String, String), Vec<DeferredWrite>)> = Vec::new();
+        let mut index = HashMap::new();
+        for (project_id, collection, write) in writes {
+            let key = (project_id, collection);
+            let i = *index.entry(key.clone()).or_insert_with(|| {
+                groups.push((key, Vec::new()));
+                groups.len() - 1
+            });
+            groups[i].1.push(write);
+        }
+
+        for ((project_id, collection), writes) in &groups {
+            if let Err(e) = store.apply_writes(project_id, collection, writes).await {
+                tracing::error!(
+                    "Failed to commit the writes of block {} to {collection}: {e}",
+                    self.block
+                );
+            }
+        }
+        tracing::debug!(
+            "Committed {total} write(s) of block {} to {} collection(s)",
+            self.block,
+            groups.len()
+        );
+    }
+}
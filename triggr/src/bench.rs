@@ -0,0 +1,258 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains workloads used by the benchmarks in `benches/`.
+// They live in the crate so they can reach internal types without making them public.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use parity_scale_codec::Encode;
+use serde_json::json;
+
+pub use crate::chain::polkadot::prelude::DecodeMode;
+
+use crate::{
+    chain::polkadot::{metadata::ContractMetadata, prelude::EventData, util::decode_contract_event},
+    dsl::{DslExecutor, DslParser},
+    namespace::Namespace,
+    storage::Sled,
+    util::generate_uuid,
+    DocMetadata, Document, DocumentStore, HighSpeedCache,
+};
+
+/// Minimal ink! v5 metadata used to fill the cache.
+const SAMPLE_METADATA: &str = r#"{
+    "version": 5,
+    "spec": { "events": [] },
+    "types": []
+}"#;
+
+/// ink! v5 metadata with a single `Transfer(from: u32, to: u32, value: u128)` event.
+const TRANSFER_METADATA: &str = r#"{
+    "version": 5,
+    "spec": {
+        "events": [{
+            "label": "Transfer",
+            "signature_topic": null,
+            "args": [
+                { "label": "from", "indexed": true, "type": { "type": 0, "displayName": ["u32"] } },
+                { "label": "to", "indexed": true, "type": { "type": 0, "displayName": ["u32"] } },
+                { "label": "value", "indexed": false, "type": { "type": 1, "displayName": ["u128"] } }
+            ]
+        }]
+    },
+    "types": [
+        { "id": 0, "type": { "def": { "primitive": "u32" } } },
+        { "id": 1, "type": { "def": { "primitive": "u128" } } }
+    ]
+}"#;
+
+/// Condition evaluated by the condition benchmark.
+const SAMPLE_CONDITION: &str = "value > 1000 && from == 7 || to == 9";
+
+/// Result of a run that repeats a single operation.
+#[derive(Debug)]
+pub struct Throughput {
+    /// Operations performed
+    pub ops: u64,
+    /// Wall time of the run
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    /// Operations per second.
+    pub fn per_sec(&self) -> f64 {
+        self.ops as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Mean time of one operation.
+    pub fn mean(&self) -> Duration {
+        self.elapsed / self.ops.max(1) as u32
+    }
+}
+
+/// Decode `iterations` `Transfer` events, as the watcher does for every contract event.
+pub fn decode_throughput(iterations: u64, mode: DecodeMode) -> Throughput {
+    let metadata: ContractMetadata =
+        serde_json::from_str(TRANSFER_METADATA).expect("transfer metadata is valid");
+
+    // Selector, then the arguments in order
+    let mut bytes = vec![0u8];
+    bytes.extend(7u32.encode());
+    bytes.extend(9u32.encode());
+    bytes.extend(1_000_000u128.encode());
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let decoded = decode_contract_event(&bytes, &[], &metadata, mode);
+        assert!(decoded.is_ok(), "sample event decodes");
+    }
+
+    Throughput {
+        ops: iterations,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Evaluate a compound trigger condition `iterations` times against a decoded event.
+pub fn condition_throughput(iterations: u64) -> Throughput {
+    let condition = DslParser::parse_condition(SAMPLE_CONDITION).expect("sample condition parses");
+    let event = EventData {
+        event_name: "Transfer".to_string(),
+        fields: HashMap::from([
+            ("from".to_string(), json!(7)),
+            ("to".to_string(), json!(9)),
+            ("value".to_string(), json!("1000000")),
+        ]),
+        raw: None,
+        scores: HashMap::new(),
+        trace: None,
+        namespace: Namespace::Live,
+        block: None,
+        block_number: None,
+    };
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        std::hint::black_box(DslExecutor::evaluate_condition(&condition, &event));
+    }
+
+    Throughput {
+        ops: iterations,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Insert `docs` documents into a fresh store, `concurrency` writers at a time,
+/// as trigger actions and the REST API do. The store is removed afterwards.
+pub fn document_write_throughput(docs: u64, concurrency: u64) -> Throughput {
+    let root = std::env::temp_dir().join(format!("triggr-bench-{}", generate_uuid()));
+    let store = Arc::new(Sled::at(&root).expect("failed to open store"));
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start runtime");
+
+    let start = Instant::now();
+    runtime.block_on(async {
+        let writers = (0..concurrency)
+            .map(|w| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    for i in (w..docs).step_by(concurrency as usize) {
+                        let doc = Document {
+                            id: format!("doc-{i}"),
+                            data: json!({ "from": 7, "to": 9, "value": i.to_string() }),
+                            metadata: DocMetadata::default(),
+                        };
+                        let _ = store.insert("bench", "transfers", doc, false).await;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for writer in writers {
+            let _ = writer.await;
+        }
+    });
+    let elapsed = start.elapsed();
+
+    drop(store);
+    let _ = std::fs::remove_dir_all(&root);
+
+    Throughput { ops: docs, elapsed }
+}
+
+/// Result of a metadata cache throughput run.
+#[derive(Debug)]
+pub struct CacheThroughput {
+    /// Concurrent reader threads
+    pub readers: usize,
+    /// Lookups served
+    pub reads: u64,
+    /// Metadata updates applied meanwhile
+    pub updates: u64,
+    /// Wall time of the run
+    pub elapsed: Duration,
+}
+
+impl CacheThroughput {
+    /// Lookups served per second.
+    pub fn reads_per_sec(&self) -> f64 {
+        self.reads as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Hammer the metadata cache with lookups from `readers` threads while another thread keeps
+/// replacing metadata, as console uploads do.
+pub fn metadata_cache_throughput(
+    readers: usize,
+    contracts: usize,
+    duration: Duration,
+) -> CacheThroughput {
+    let cache = Arc::new(HighSpeedCache::with_capacity(usize::MAX));
+    let addrs: Arc<Vec<String>> = Arc::new((0..contracts).map(|i| format!("{i:064x}")).collect());
+
+    for addr in addrs.iter() {
+        cache.save_metadata(addr.clone(), sample_metadata(), SAMPLE_METADATA.len());
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let reads = Arc::new(AtomicU64::new(0));
+    let start = Instant::now();
+
+    // Writer: replace metadata in a loop
+    let writer = {
+        let (cache, addrs, stop) = (cache.clone(), addrs.clone(), stop.clone());
+        thread::spawn(move || {
+            let mut updates = 0u64;
+            while !stop.load(Ordering::Relaxed) {
+                let addr = &addrs[updates as usize % addrs.len()];
+                cache.save_metadata(addr.clone(), sample_metadata(), SAMPLE_METADATA.len());
+                updates += 1;
+            }
+            updates
+        })
+    };
+
+    // Readers: the event decoding hot path
+    let handles = (0..readers)
+        .map(|r| {
+            let (cache, addrs, stop, reads) =
+                (cache.clone(), addrs.clone(), stop.clone(), reads.clone());
+            thread::spawn(move || {
+                let mut local = 0u64;
+                let mut i = r;
+                while !stop.load(Ordering::Relaxed) {
+                    let _ = cache.get(&addrs[i % addrs.len()]);
+                    local += 1;
+                    i += 1;
+                }
+                reads.fetch_add(local, Ordering::Relaxed);
+            })
+        })
+        .collect::<Vec<_>>();
+
+    thread::sleep(duration);
+    stop.store(true, Ordering::Relaxed);
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+    let updates = writer.join().unwrap_or_default();
+
+    CacheThroughput {
+        readers,
+        reads: reads.load(Ordering::Relaxed),
+        updates,
+        elapsed: start.elapsed(),
+    }
+}
+
+fn sample_metadata() -> ContractMetadata {
+    serde_json::from_str(SAMPLE_METADATA).expect("sample metadata is valid")
+}
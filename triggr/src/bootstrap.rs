@@ -0,0 +1,224 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the demo fixtures an instance can be bootstrapped with.
+// The demo project watches the EventDemo contract (see `examples/demo`) and comes with sample
+// triggers and seed documents, so new users get a working end-to-end example without uploading
+// metadata or writing triggers first.
+
+use std::path::PathBuf;
+
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+use crate::{
+    chain::polkadot::{metadata::ContractMetadata, util::simplify_events},
+    dsl::DslParser,
+    prelude::{
+        DocMetadata, Document, DocumentStore, EventRoutes, Project, ProjectStore, StorageResult,
+        Trigger, TriggerStore, Triggr, CONTRACTS_DIR,
+    },
+    util::generate_uuid,
+};
+
+/// Prefix of the ids of demo projects.
+const DEMO_PROJECT_PREFIX: &str = "demo-";
+
+/// Address of the EventDemo contract deployed on PassetHub.
+pub const DEMO_CONTRACT: &str = "0x25b322c78c16e0a20dcebecaaef82a0a2976624b";
+
+/// Metadata of the EventDemo contract.
+const DEMO_METADATA: &str = include_str!("../../examples/demo/contract/event_demo.json");
+
+/// Collection the sample triggers and seed documents write to.
+const DEMO_COLLECTION: &str = "transactions";
+
+/// Sample triggers: id (within the project), description and DSL.
+const DEMO_TRIGGERS: [(&str, &str, &str); 2] = [
+    (
+        "record",
+        "Record every value change",
+        r#"const events = [
+    ValueChanged { from, value, message }
+]
+
+fn main(events) {
+    insert @transactions: with { from: events.ValueChanged.from, value: events.ValueChanged.value, message: events.ValueChanged.message }
+}"#,
+    ),
+    (
+        "large-values",
+        "Keep the latest large value and the last small one",
+        r#"const events = [
+    ValueChanged { from, value, message }
+]
+
+fn main(events) {
+    if (events.ValueChanged.value > 100) {
+        update @alerts:latest with { from: events.ValueChanged.from, value: events.ValueChanged.value }
+    } else {
+        set kv.last_small_value = events.ValueChanged.value
+    }
+}"#,
+    ),
+];
+
+/// Result of a bootstrap.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Bootstrap {
+    /// Whether the demo project was created (false if the user already had one)
+    pub created: bool,
+    pub project: Project,
+    /// API key of the project, only returned when it is created
+    pub secret: Option<String>,
+    /// Ids of the sample triggers
+    pub triggers: Vec<String>,
+    /// Number of seed documents written
+    pub documents: usize,
+}
+
+/// Provision the demo project of a user on an EventDemo contract (e.g. `DEMO_CONTRACT`): its
+/// metadata, the project, its sample triggers and seed documents. A user that already has a demo
+/// project on the contract gets it back unchanged.
+pub async fn provision(
+    triggr: &Triggr,
+    owner: &str,
+    contract_addr: &str,
+) -> StorageResult<Bootstrap> {
+    if let Some(project) = ProjectStore::get_user_projects(&*triggr.store, owner)?
+        .into_iter()
+        .find(|p| p.contract_address == contract_addr && p.id.starts_with(DEMO_PROJECT_PREFIX))
+    {
+        let triggers = TriggerStore::list_triggers(&*triggr.store, contract_addr)?
+            .into_iter()
+            .filter(|t| t.project_id == project.id)
+            .map(|t| t.id)
+            .collect();
+
+        return Ok(Bootstrap {
+            created: false,
+            project,
+            secret: None,
+            triggers,
+            documents: 0,
+        });
+    }
+
+    // Contract metadata, saved as if it was uploaded
+    let metadata: ContractMetadata = serde_json::from_str(DEMO_METADATA)?;
+    metadata.detect_version()?;
+    tokio::fs::create_dir_all(CONTRACTS_DIR).await?;
+    let path = PathBuf::from(CONTRACTS_DIR).join(format!("{contract_addr}.json"));
+    tokio::fs::write(&path, DEMO_METADATA).await?;
+
+    let path = path.display().to_string();
+    triggr.store.store_metadata_entry(contract_addr, &path)?;
+    let events = simplify_events(&*triggr.cache.load_metadata(contract_addr, &path)?);
+
+    // Project ids are global, so each user's demo project gets its own
+    let mut project = Project {
+        id: format!("{DEMO_PROJECT_PREFIX}{}", &generate_uuid()[..8]),
+        api_key: String::new(),
+        owner: owner.to_string(),
+        description: "Demo project watching the EventDemo contract".to_string(),
+        contract_address: contract_addr.to_string(),
+        contract_file_path: path,
+        contract_events: events,
+        // Demo projects share the contract, whose events are decoded once for all of them
+        decode_mode: triggr.cache.decode_mode(&contract_addr.to_lowercase()),
+        event_routes: EventRoutes::default(),
+        enrichers: Vec::new(),
+        sandbox_mirror: false,
+        strict_ordering: false,
+    };
+    let secret = ProjectStore::create(&*triggr.store, &mut project)?;
+
+    // Trigger ids are unique per contract, which other demo projects watch too
+    let now = Utc::now().timestamp_millis() as u64;
+    let mut triggers = Vec::new();
+    for (id, description, dsl) in DEMO_TRIGGERS {
+        let script = DslParser::parse_script(dsl).map_err(|e| e.to_string())?;
+        let trigger = Trigger {
+            id: format!("{}-{id}", project.id),
+            description: description.to_string(),
+            project_id: project.id.clone(),
+            dsl: dsl.to_string(),
+            rules: script.rules,
+            active: true,
+            created: now,
+            last_run: 0,
+            tags: vec!["demo".to_string()],
+            webhook: None,
+            owner: None,
+        };
+        triggers.push(trigger.id.clone());
+        TriggerStore::store_trigger(&*triggr.store, contract_addr, trigger)?;
+    }
+
+    // Seed documents, shaped like the ones the `record` trigger writes
+    let seeds = seed_documents();
+    let documents = seeds.len();
+    for (id, data) in seeds {
+        let doc = Document {
+            id,
+            data,
+            metadata: DocMetadata {
+                created_at: now,
+                updated_at: now,
+                version: None,
+                tags: vec!["seed".to_string()],
+                hash: None,
+            },
+        };
+        DocumentStore::insert(&*triggr.store, &project.id, DEMO_COLLECTION, doc, false).await?;
+    }
+
+    Ok(Bootstrap {
+        created: true,
+        project,
+        secret: Some(secret),
+        triggers,
+        documents,
+    })
+}
+
+/// Provision the demo project of `TRIGGR_BOOTSTRAP_OWNER` (a console user id), if set.
+pub async fn from_env(triggr: &Triggr) {
+    let Ok(owner) = std::env::var("TRIGGR_BOOTSTRAP_OWNER") else {
+        return;
+    };
+
+    match provision(triggr, &owner, DEMO_CONTRACT).await {
+        Ok(Bootstrap {
+            created: true,
+            project,
+            secret: Some(secret),
+            ..
+        }) => {
+            println!("🌱 Created demo project {} for {owner}", project.id);
+            println!("🔑 API key (shown once): {secret}");
+        }
+        Ok(bootstrap) => println!("🌱 Demo project {} is ready", bootstrap.project.id),
+        Err(e) => tracing::error!("Failed to bootstrap the demo project: {e}"),
+    }
+}
+
+/// Documents the demo collection starts with.
+fn seed_documents() -> Vec<(String, Value)> {
+    [
+        ("seed-1", 5, "Hello from Triggr"),
+        ("seed-2", 42, "Counter incremented"),
+        ("seed-3", 250, "Large value changed"),
+    ]
+    .into_iter()
+    .map(|(id, value, message)| {
+        let data = json!({
+            "from": "0x0000000000000000000000000000000000000000",
+            "value": value,
+            "message": message,
+        });
+        (id.to_string(), data)
+    })
+    .collect()
+}
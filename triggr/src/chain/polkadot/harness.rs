@@ -0,0 +1,207 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains a harness to guard the hand-written SCALE decode path against regressions.
+// It replays a corpus of recorded raw events through the decoder and fuzzes it with mutated inputs.
+
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
+
+use rand::Rng;
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    chain::polkadot::{
+        prelude::{DecodeMode, EventData},
+        metadata::ContractMetadata,
+    },
+    prelude::TriggerRun,
+};
+
+use super::util::decode_contract_event;
+
+/// Difference found while replaying a recorded event.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReplayDiff {
+    /// Run the event was recorded with
+    pub run_id: String,
+    /// Contract that emitted the event
+    pub contract_addr: String,
+    /// Event name recorded at the time
+    pub before: String,
+    /// Event name decoded now (None if decoding failed)
+    pub after: Option<String>,
+    /// Fields whose value changed (field -> [before, after])
+    pub changed_fields: HashMap<String, (Value, Value)>,
+    /// Decoding error, if any
+    pub error: Option<String>,
+}
+
+/// Report of a corpus replay.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ReplayReport {
+    /// Events replayed
+    pub checked: usize,
+    /// Events decoded to the same output
+    pub unchanged: usize,
+    /// Events skipped because their contract metadata is not loaded
+    pub skipped: usize,
+    /// Events whose output differs
+    pub diffs: Vec<ReplayDiff>,
+}
+
+/// Report of a fuzzing session.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct FuzzReport {
+    /// Mutated inputs fed to the decoder
+    pub iterations: usize,
+    /// Inputs that decoded into an event
+    pub decoded: usize,
+    /// Inputs rejected with an error
+    pub rejected: usize,
+    /// Inputs (hex) that made the decoder panic
+    pub panics: Vec<String>,
+}
+
+/// Replay recorded runs through the decoder and report any output differences.
+pub fn replay_corpus(
+    corpus: &[TriggerRun],
+    metadata: &HashMap<String, Arc<ContractMetadata>>,
+) -> ReplayReport {
+    let mut report = ReplayReport::default();
+
+    for run in corpus {
+        let Some(raw) = &run.event.raw else {
+            continue;
+        };
+        let Some(contract_metadata) = metadata.get(&run.contract_addr) else {
+            report.skipped += 1;
+            continue;
+        };
+        let Ok(bytes) = hex::decode(raw.data.trim_start_matches("0x")) else {
+            report.skipped += 1;
+            continue;
+        };
+
+        report.checked += 1;
+
+        match decode_contract_event(&bytes, &raw.topics, contract_metadata, DecodeMode::Lenient) {
+            Ok(decoded) => {
+                let changed_fields = diff_fields(&run.event, &decoded);
+                if decoded.event_name == run.event.event_name && changed_fields.is_empty() {
+                    report.unchanged += 1;
+                } else {
+                    report.diffs.push(ReplayDiff {
+                        run_id: run.id.clone(),
+                        contract_addr: run.contract_addr.clone(),
+                        before: run.event.event_name.clone(),
+                        after: Some(decoded.event_name),
+                        changed_fields,
+                        error: None,
+                    });
+                }
+            }
+            Err(failure) => report.diffs.push(ReplayDiff {
+                run_id: run.id.clone(),
+                contract_addr: run.contract_addr.clone(),
+                before: run.event.event_name.clone(),
+                after: None,
+                changed_fields: HashMap::new(),
+                error: Some(failure.error),
+            }),
+        }
+    }
+
+    report
+}
+
+/// Feed mutated corpus inputs to the decoder, catching panics.
+pub fn fuzz_corpus(
+    corpus: &[TriggerRun],
+    metadata: &HashMap<String, Arc<ContractMetadata>>,
+    iterations: usize,
+) -> FuzzReport {
+    let mut report = FuzzReport::default();
+
+    // Seeds are recorded payloads paired with their contract metadata
+    let seeds: Vec<(Vec<u8>, &ContractMetadata)> = corpus
+        .iter()
+        .filter_map(|run| {
+            let raw = run.event.raw.as_ref()?;
+            let bytes = hex::decode(raw.data.trim_start_matches("0x")).ok()?;
+            Some((bytes, metadata.get(&run.contract_addr)?.as_ref()))
+        })
+        .collect();
+
+    if seeds.is_empty() {
+        return report;
+    }
+
+    let mut rng = rand::rng();
+    for _ in 0..iterations {
+        let (seed, contract_metadata) = &seeds[rng.random_range(0..seeds.len())];
+        let input = mutate(seed, &mut rng);
+
+        report.iterations += 1;
+        match panic::catch_unwind(AssertUnwindSafe(|| {
+            decode_contract_event(&input, &[], contract_metadata, DecodeMode::Strict)
+        })) {
+            Ok(Ok(_)) => report.decoded += 1,
+            Ok(Err(_)) => report.rejected += 1,
+            Err(_) => report.panics.push(format!("0x{}", hex::encode(&input))),
+        }
+    }
+
+    report
+}
+
+/// Apply a random mutation to an input.
+fn mutate(seed: &[u8], rng: &mut impl Rng) -> Vec<u8> {
+    let mut input = seed.to_vec();
+
+    match rng.random_range(0..4) {
+        // Flip a byte
+        0 if !input.is_empty() => {
+            let i = rng.random_range(0..input.len());
+            input[i] ^= rng.random::<u8>() | 1;
+        }
+        // Truncate
+        1 if !input.is_empty() => {
+            let len = rng.random_range(0..input.len());
+            input.truncate(len);
+        }
+        // Append random bytes
+        2 => {
+            let extra = rng.random_range(1..16);
+            input.extend((0..extra).map(|_| rng.random::<u8>()));
+        }
+        // Replace with random bytes of the same length
+        _ => input.iter_mut().for_each(|b| *b = rng.random()),
+    }
+
+    input
+}
+
+/// Return the fields whose value differs between two decodings of the same payload.
+fn diff_fields(before: &EventData, after: &EventData) -> HashMap<String, (Value, Value)> {
+    let mut changed = HashMap::new();
+
+    for (name, old) in &before.fields {
+        let new = after.fields.get(name).cloned().unwrap_or(Value::Null);
+        if *old != new {
+            changed.insert(name.clone(), (old.clone(), new));
+        }
+    }
+
+    for (name, new) in &after.fields {
+        if !before.fields.contains_key(name) {
+            changed.insert(name.clone(), (Value::Null, new.clone()));
+        }
+    }
+
+    changed
+}
@@ -0,0 +1,197 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the ink! contract metadata model shared by the cache, decoder and console.
+// Only the parts of the metadata needed to decode events are modelled. Fields that differ between
+// ink! v4 and v5 layouts are optional so that both deserialize into the same type.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Contract metadata, as uploaded with a project.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContractMetadata {
+    /// Metadata version (`"4"` in ink! v4, `5` in ink! v5)
+    #[serde(default)]
+    pub version: Option<Value>,
+    /// Contract specification
+    pub spec: ContractSpec,
+    /// Type registry
+    pub types: Vec<TypeDef>,
+}
+
+/// Supported metadata layouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataVersion {
+    /// ink! v4: events are variants of one enum, selected by a leading index byte
+    V4,
+    /// ink! v5 (and v6): events are standalone and identified by their signature topic
+    V5,
+}
+
+impl ContractMetadata {
+    /// Detect the metadata layout.
+    /// Files without a version are treated as v5 if any event carries a signature topic.
+    pub fn detect_version(&self) -> Result<MetadataVersion, String> {
+        let version = match &self.version {
+            Some(Value::String(v)) => v.trim().parse::<u64>().ok(),
+            Some(Value::Number(v)) => v.as_u64(),
+            Some(other) => return Err(format!("Invalid metadata version: {other}")),
+            None => {
+                let has_topics = self.spec.events.iter().any(|e| e.signature_topic.is_some());
+                return Ok(if has_topics {
+                    MetadataVersion::V5
+                } else {
+                    MetadataVersion::V4
+                });
+            }
+        };
+
+        match version {
+            Some(4) => Ok(MetadataVersion::V4),
+            // ink! v6 (pallet-revive) keeps the event layout of v5
+            Some(5) | Some(6) => Ok(MetadataVersion::V5),
+            _ => Err(format!(
+                "Unsupported metadata version {}, expected ink! v4, v5 or v6",
+                self.version.as_ref().map(|v| v.to_string()).unwrap_or_default()
+            )),
+        }
+    }
+
+    /// Look up a type definition by its id.
+    pub fn type_def(&self, id: u32) -> Option<&TypeDef> {
+        self.types.iter().find(|t| t.id == id)
+    }
+
+    /// Look up an event by name, ignoring case like the trigger engine does.
+    pub fn event(&self, name: &str) -> Option<&EventSpec> {
+        self.spec
+            .events
+            .iter()
+            .find(|e| e.label.eq_ignore_ascii_case(name))
+    }
+
+    /// Signature topics of the events of the contract, so its events can be recognized before
+    /// decoding them. `None` if some event has no signature topic (ink! v4, anonymous events).
+    pub fn signature_topics(&self) -> Option<Vec<[u8; 32]>> {
+        if self.detect_version() != Ok(MetadataVersion::V5) {
+            return None;
+        }
+
+        self.spec
+            .events
+            .iter()
+            .map(|event| {
+                let topic = event.signature_topic.as_deref()?;
+                hex::decode(topic.trim_start_matches("0x"))
+                    .ok()?
+                    .try_into()
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Classify the values of a type, looking through single-field wrappers (e.g. `Balance`).
+    pub fn value_kind(&self, type_id: u32) -> ValueKind {
+        let Some(type_def) = self.type_def(type_id) else {
+            return ValueKind::Unknown;
+        };
+        let def = &type_def.type_def.def;
+
+        if let Some(primitive) = def.get("primitive").and_then(|p| p.as_str()) {
+            return match primitive {
+                "str" | "char" => ValueKind::Text,
+                "bool" => ValueKind::Bool,
+                p if p.starts_with('u') || p.starts_with('i') => ValueKind::Number,
+                _ => ValueKind::Unknown,
+            };
+        }
+
+        if def.get("compact").is_some() {
+            return ValueKind::Number;
+        }
+
+        // A composite of a single field (e.g. a balance newtype) is that field
+        if let Some(inner) = def
+            .get("composite")
+            .and_then(|c| c.get("fields"))
+            .and_then(|f| f.as_array())
+            .filter(|fields| fields.len() == 1)
+            .and_then(|fields| fields[0].get("type"))
+            .and_then(|t| t.as_u64())
+        {
+            return self.value_kind(inner as u32);
+        }
+
+        ValueKind::Other
+    }
+}
+
+/// Kind of value a contract type decodes into, as far as trigger conditions are concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    /// Integers (compared numerically)
+    Number,
+    /// Strings
+    Text,
+    /// Booleans
+    Bool,
+    /// Addresses, hashes, enums, structs...
+    Other,
+    /// Type not found in the registry
+    Unknown,
+}
+
+/// Contract specification.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContractSpec {
+    /// Events the contract can emit
+    pub events: Vec<EventSpec>,
+}
+
+/// Specification of a single event.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventSpec {
+    /// Event name
+    pub label: String,
+    /// First topic emitted with the event (absent in ink! v4)
+    #[serde(default)]
+    pub signature_topic: Option<String>,
+    /// Event arguments, in encoding order
+    pub args: Vec<EventArg>,
+}
+
+/// Specification of a single event argument.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventArg {
+    /// Argument name
+    pub label: String,
+    /// Whether the argument is also emitted as a topic
+    pub indexed: bool,
+    /// Argument type
+    #[serde(rename = "type")]
+    pub type_info: TypeInfo,
+}
+
+/// Reference to a type in the registry.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TypeInfo {
+    #[serde(rename = "type")]
+    pub type_id: u32,
+    #[serde(rename = "displayName", default)]
+    pub display_name: Vec<String>,
+}
+
+/// Entry of the type registry.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TypeDef {
+    pub id: u32,
+    #[serde(rename = "type")]
+    pub type_def: TypeDefDetails,
+}
+
+/// Definition of a registry type.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TypeDefDetails {
+    pub path: Option<Vec<String>>,
+    pub def: Value,
+}
@@ -4,17 +4,31 @@
 
 use scale_value::Value;
 use substrate_api_client::{
-    ac_primitives::DefaultRuntimeConfig, rpc::JsonrpseeClient, Api, SubscribeEvents,
+    ac_primitives::DefaultRuntimeConfig, rpc::JsonrpseeClient, Api, GetChainInfo, SubscribeEvents,
 };
-use tokio::sync::mpsc::Sender;
 
+pub mod harness;
+pub mod metadata;
 pub mod prelude;
 pub mod util;
 
-use prelude::*;
-use tracing::info;
+use chrono::Utc;
+use opentelemetry::{context::FutureExt, trace::SpanKind, Context, KeyValue};
+use std::time::Duration;
+use serde_json::json;
+use tracing::{info, warn};
 
-use crate::{chain::polkadot::util::*, prelude::Triggr};
+use crate::{
+    chain::polkadot::util::*,
+    prelude::{DecodeFailureSample, EventSender, Triggr},
+    telemetry,
+};
+
+/// Default number of seconds without a block before the watchdog alerts.
+const DEFAULT_WATCHDOG_THRESHOLD_SECS: u64 = 60;
+
+/// Topic watchdog alerts are broadcast on.
+const WATCHDOG_ALERT_TOPIC: &str = "alerts:watchdog";
 
 /// Interface to handle all operations relating to the Polkadot chain.
 #[derive(Clone, Default, Debug)]
@@ -39,7 +53,7 @@ impl Polkadot {
     /// Watch event and decode it before sending it to database layer.
     pub async fn watch_event(
         api: Api<DefaultRuntimeConfig, JsonrpseeClient>,
-        tx: Sender<(String, EventData)>,
+        tx: EventSender,
         triggr: Triggr,
     ) {
         // Subscribe to events
@@ -53,8 +67,42 @@ impl Polkadot {
                 Ok(events) => {
                     info!("📦 Block: #{:?}", events.block_hash());
 
+                    // Let the watchdog know the subscription is alive
+                    if triggr
+                        .chain_health
+                        .record_block(format!("{:?}", events.block_hash()))
+                    {
+                        info!("✅ Blocks are being received again");
+                    }
+
+                    // Number of the block, which the event log is ordered by
+                    let block_number = match api.get_header(Some(events.block_hash())).await {
+                        Ok(header) => header.map(|header| u64::from(header.number)),
+                        Err(e) => {
+                            warn!("Failed to read the header of the block: {:?}", e);
+                            None
+                        }
+                    };
+
+                    // Track how much of the block is left to decode
+                    let count = events.iter().count();
+                    triggr.pipeline.set_decode_pending(count);
+
+                    // Each block is the root of the traces of its events
+                    let block = telemetry::start(
+                        "block",
+                        SpanKind::Consumer,
+                        &Context::new(),
+                        vec![
+                            KeyValue::new("block.hash", format!("{:?}", events.block_hash())),
+                            KeyValue::new("block.events", count as i64),
+                        ],
+                    );
+
                     // Iterate through decoded events
                     for event in events.iter() {
+                        triggr.pipeline.decoded();
+
                         match event {
                             Ok(event_details) => {
                                 let pallet_name = event_details.pallet_name();
@@ -66,49 +114,97 @@ impl Polkadot {
                                     continue;
                                 }
 
+                                // Skip contract events no watched contract emits, before
+                                // decoding them
+                                if event_details.variant_name() == "ContractEmitted"
+                                    && !emitted_topics(event_details.field_bytes())
+                                        .is_none_or(|topics| triggr.cache.watches_topics(&topics))
+                                {
+                                    triggr.pipeline.prefiltered();
+                                    continue;
+                                }
+
                                 // Decode fields
                                 match event_details.field_values() {
                                     Ok(fields) => {
                                         let field_vec: Vec<&Value<u32>> = fields.values().collect();
 
                                         // Extract contract address (first field) and event data (second field)
-                                        if field_vec.len() >= 2 {
-                                            if let Some(contract_address) =
-                                                extract_bytes_from_nested(&field_vec[0])
+                                        let extracted = (field_vec.len() >= 2)
+                                            .then(|| {
+                                                extract_bytes_from_nested(field_vec[0])
+                                                    .zip(extract_bytes_from_nested(field_vec[1]))
+                                            })
+                                            .flatten();
+                                        if let Some((contract_address, event_bytes)) = extracted {
+                                            let addr_bytes = format!(
+                                                "0x{}",
+                                                hex::encode(&contract_address)
+                                            );
+
+                                            info!(
+                                                "   📍 Contract Address: {}",
+                                                addr_bytes
+                                            );
+                                            info!(
+                                                "   📦 Event Data (hex): 0x{}",
+                                                hex::encode(&event_bytes)
+                                            );
+
+                                            // Topics are the third field, when present
+                                            let topics = field_vec
+                                                .get(2)
+                                                .map(|v| extract_topics(v))
+                                                .unwrap_or_default();
+
+                                            // Only try to decode contracts we care about,
+                                            // and that this instance's shard owns
+                                            if let Some(metadata) = triggr
+                                                .contract_metadata(&addr_bytes)
+                                                .await
+                                                .filter(|_| triggr.owns_contract(&addr_bytes))
                                             {
-                                                if let Some(event_bytes) =
-                                                    extract_bytes_from_nested(&field_vec[1])
-                                                {
-                                                    let addr_bytes = format!(
-                                                        "0x{}",
-                                                        hex::encode(&contract_address)
-                                                    );
-
-                                                    info!(
-                                                        "   📍 Contract Address: {}",
-                                                        addr_bytes
-                                                    );
-                                                    info!(
-                                                        "   📦 Event Data (hex): 0x{}",
-                                                        hex::encode(&event_bytes)
-                                                    );
-
-                                                    // Only try to decode contracts we care about
-                                                    let cache = triggr.cache.read().await;
-                                                    info!("{:#?} -> {}", cache.contract.keys(), addr_bytes);
-                                                    if let Some(metadata) =
-                                                        cache.contract.get(&addr_bytes)
-                                                    {
-                                                        // Decode contract event and send to handler
-                                                        decode_contract_event_with_metadata(
-                                                            tx.clone(),
-                                                            addr_bytes,
-                                                            &event_bytes,
-                                                            metadata,
-                                                        )
-                                                        .await;
-                                                    }
+                                                let mode =
+                                                    triggr.cache.decode_mode(&addr_bytes);
+
+                                                let decode = telemetry::start(
+                                                    "decode",
+                                                    SpanKind::Internal,
+                                                    &block,
+                                                    vec![KeyValue::new(
+                                                        "contract.address",
+                                                        addr_bytes.clone(),
+                                                    )],
+                                                );
+
+                                                // Decode contract event and send to handler
+                                                let outcome =
+                                                    decode_contract_event_with_metadata(
+                                                        tx.clone(),
+                                                        addr_bytes.clone(),
+                                                        format!(
+                                                            "{:?}",
+                                                            events.block_hash()
+                                                        ),
+                                                        block_number,
+                                                        &event_bytes,
+                                                        topics,
+                                                        &metadata,
+                                                        mode,
+                                                        &triggr.pipeline,
+                                                    )
+                                                    .with_context(decode.clone())
+                                                    .await;
+                                                if let Err(failure) = &outcome {
+                                                    telemetry::fail(&decode, &failure.error);
                                                 }
+                                                Self::record_decode_outcome(
+                                                    &triggr,
+                                                    &addr_bytes,
+                                                    &event_bytes,
+                                                    outcome,
+                                                )
+                                                .await;
                                             }
                                         }
                                     }
@@ -129,4 +225,87 @@ impl Polkadot {
             }
         }
     }
+
+    /// Periodically check that blocks are still being received and alert when they are not.
+    /// A hung node subscription would otherwise silently stop every trigger.
+    pub async fn watchdog(triggr: Triggr) {
+        let threshold_secs = std::env::var("TRIGGR_WATCHDOG_THRESHOLD_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_WATCHDOG_THRESHOLD_SECS);
+
+        let mut interval =
+            tokio::time::interval(Duration::from_secs((threshold_secs / 4).max(1)));
+
+        loop {
+            interval.tick().await;
+
+            let Some(gap) = triggr.chain_health.check(threshold_secs * 1000) else {
+                continue;
+            };
+
+            warn!(
+                "🚨 No block received for {}s. Is the node subscription hung?",
+                gap / 1000
+            );
+
+            // Notify consoles listening for watchdog alerts
+            let topic = WATCHDOG_ALERT_TOPIC;
+            let message = json!({
+                "op": "alert",
+                "topic": topic,
+                "gap_ms": gap,
+                "health": triggr.chain_health.stats(),
+            });
+            triggr
+                .store
+                .subscriptions
+                .broadcast(topic, message.to_string())
+                .await;
+        }
+    }
+
+    /// Record a decoding outcome and raise an alert when the failure rate spikes.
+    async fn record_decode_outcome(
+        triggr: &Triggr,
+        contract_addr: &str,
+        event_bytes: &[u8],
+        outcome: Result<(), DecodeFailure>,
+    ) {
+        let sample = outcome.err().map(|failure| DecodeFailureSample {
+            event_hex: format!("0x{}", hex::encode(event_bytes)),
+            error: failure.error,
+            tried: failure.tried,
+            timestamp: Utc::now().timestamp_millis() as u64,
+        });
+
+        let (alert, stats) = {
+            let mut decode_stats = triggr.decode_stats.write().await;
+            let alert = decode_stats.record(contract_addr, sample);
+            (alert, decode_stats.get(contract_addr))
+        };
+
+        if alert {
+            warn!(
+                "⚠️ Decoding failure rate for {} is {:.0}%. Was the contract upgraded?",
+                contract_addr,
+                stats.failure_rate * 100.0
+            );
+
+            // Notify consoles listening for decoding alerts
+            let topic = format!("alerts:decoding:{contract_addr}");
+            let message = json!({
+                "op": "alert",
+                "topic": topic,
+                "contract_addr": contract_addr,
+                "failure_rate": stats.failure_rate,
+                "latest": stats.samples.back(),
+            });
+            triggr
+                .store
+                .subscriptions
+                .broadcast(&topic, message.to_string())
+                .await;
+        }
+    }
 }
@@ -1,14 +1,56 @@
 // Copyright (c) 2025, Algorealm Inc.
 
 use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::namespace::Namespace;
 
 /// (Ws) url of contracts chain to connect to
 pub const CONTRACTS_NODE_URL: &str = "wss://testnet-passet-hub.polkadot.io";
 
 /// Runtime event data
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EventData {
     pub event_name: String,
     pub fields: HashMap<String, Value>,
-}
\ No newline at end of file
+    /// Raw payload the event was decoded from
+    #[serde(default)]
+    pub raw: Option<RawEvent>,
+    /// Anomaly score of the numeric fields (standard deviations above their usual value)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub scores: HashMap<String, f64>,
+    /// W3C trace context (`traceparent`) of the span that decoded the event, when traced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<String>,
+    /// Namespace the triggers of the event read and write data in
+    #[serde(default, skip_serializing_if = "Namespace::is_live")]
+    pub namespace: Namespace,
+    /// Hash of the block the event was emitted in, for events read from the chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block: Option<String>,
+    /// Number of that block, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+}
+
+/// Raw (undecoded) contract event as received from the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawEvent {
+    /// SCALE encoded event data (hex)
+    pub data: String,
+    /// Event topics (hex)
+    pub topics: Vec<String>,
+}
+
+/// How strictly raw events are matched against contract metadata
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DecodeMode {
+    /// First event spec that decodes cleanly wins
+    #[default]
+    Lenient,
+    /// Exactly one event spec must decode cleanly and match the signature topic
+    Strict,
+}
@@ -4,62 +4,24 @@
 
 use std::collections::HashMap;
 
+use opentelemetry::{Context, KeyValue};
 use parity_scale_codec::Decode;
 use scale_value::{Composite, Primitive, Value, ValueDef};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JsonValue};
-use tokio::sync::mpsc::Sender;
-use tracing::info;
+use tracing::{error, info};
 use utoipa::ToSchema;
 
-use crate::chain::polkadot::prelude::EventData;
+use crate::{
+    chain::polkadot::{
+        metadata::{ContractMetadata, EventArg, EventSpec, MetadataVersion, TypeDef, TypeDefDetails},
+        prelude::{DecodeMode, EventData, RawEvent},
+    },
+    namespace::Namespace,
+    prelude::{EventSender, Pipeline},
+    telemetry,
+};
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct ContractMetadata {
-    spec: ContractSpec,
-    types: Vec<TypeDef>,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-struct ContractSpec {
-    events: Vec<EventSpec>,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-struct EventSpec {
-    label: String,
-    signature_topic: String,
-    args: Vec<EventArg>,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-struct EventArg {
-    label: String,
-    indexed: bool,
-    #[serde(rename = "type")]
-    type_info: TypeInfo,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-struct TypeInfo {
-    #[serde(rename = "type")]
-    type_id: u32,
-    #[serde(rename = "displayName")]
-    display_name: Vec<String>,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-struct TypeDef {
-    id: u32,
-    #[serde(rename = "type")]
-    type_def: TypeDefDetails,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-struct TypeDefDetails {
-    path: Option<Vec<String>>,
-    def: serde_json::Value,
-}
 /// Simplified output structure
 #[derive(Debug, Deserialize, Serialize, ToSchema, Clone)]
 pub struct SimplifiedEvent {
@@ -80,13 +42,9 @@ pub fn extract_bytes_from_nested(value: &Value<u32>) -> Option<Vec<u8>> {
             if is_byte_array(fields) {
                 let bytes: Vec<u8> = fields
                     .iter()
-                    .filter_map(|v| {
-                        if let ValueDef::Primitive(Primitive::U128(n)) = &v.value {
-                            if *n <= 255 {
-                                return Some(*n as u8);
-                            }
-                        }
-                        None
+                    .filter_map(|v| match &v.value {
+                        ValueDef::Primitive(Primitive::U128(n)) if *n <= 255 => Some(*n as u8),
+                        _ => None,
                     })
                     .collect();
 
@@ -100,6 +58,18 @@ pub fn extract_bytes_from_nested(value: &Value<u32>) -> Option<Vec<u8>> {
     }
 }
 
+// Extract a list of topics (hashes) as hex strings
+pub fn extract_topics(value: &Value<u32>) -> Vec<String> {
+    match &value.value {
+        ValueDef::Composite(Composite::Unnamed(fields)) => fields
+            .iter()
+            .filter_map(extract_bytes_from_nested)
+            .map(|bytes| format!("0x{}", hex::encode(bytes)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 fn is_byte_array(fields: &[Value<u32>]) -> bool {
     !fields.is_empty()
         && fields
@@ -143,46 +113,32 @@ fn build_type_map(types: &[TypeDef]) -> HashMap<u32, String> {
 /// Resolve a type name from TypeDefDetails
 fn resolve_type_name(details: &TypeDefDetails) -> String {
     // Check if it's a primitive type
-    if let Some(primitive) = details.def.get("primitive") {
-        if let Some(prim_str) = primitive.as_str() {
-            return prim_str.to_string();
-        }
+    if let Some(prim_str) = details.def.get("primitive").and_then(|p| p.as_str()) {
+        return prim_str.to_string();
     }
 
     // Check if it has a path (named type)
-    if let Some(path) = &details.path {
-        if !path.is_empty() {
-            // Use the last element of the path as the type name
-            return path.last().unwrap().clone();
-        }
+    if let Some(name) = details.path.as_ref().and_then(|path| path.last()) {
+        // Use the last element of the path as the type name
+        return name.clone();
     }
 
-    // Check for composite types
+    // Unnamed composite types
     if details.def.get("composite").is_some() {
-        if let Some(path) = &details.path {
-            if !path.is_empty() {
-                return path.last().unwrap().clone();
-            }
-        }
         return "composite".to_string();
     }
 
-    // Check for variant types (like Result, Option)
+    // Unnamed variant types (like Result, Option)
     if details.def.get("variant").is_some() {
-        if let Some(path) = &details.path {
-            if !path.is_empty() {
-                return path.last().unwrap().clone();
-            }
-        }
         return "variant".to_string();
     }
 
     // Check for array types
     if let Some(array_def) = details.def.get("array") {
-        if let Some(len) = array_def.get("len") {
-            return format!("array[{}]", len);
-        }
-        return "array".to_string();
+        return match array_def.get("len") {
+            Some(len) => format!("array[{}]", len),
+            None => "array".to_string(),
+        };
     }
 
     // Check for tuple types
@@ -213,140 +169,149 @@ fn format_event_arg(arg: &EventArg, type_map: &HashMap<u32, String>) -> String {
     }
 }
 
-/// Convenience function to deserialize and simplify from JSON string
-pub fn simplify_events_from_json(
-    json_str: &str,
-) -> Result<Vec<SimplifiedEvent>, serde_json::Error> {
-    let metadata: ContractMetadata = serde_json::from_str(json_str)?;
-    Ok(simplify_events(&metadata))
+/// Outcome of test-decoding a raw event against contract metadata.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DecodeReport {
+    /// Decode mode the event was matched in
+    pub mode: DecodeMode,
+    /// Label of the event spec the event was decoded as
+    pub matched: Option<String>,
+    /// Decoded fields of the event
+    #[schema(value_type = Option<Object>)]
+    pub fields: Option<HashMap<String, JsonValue>>,
+    /// Why the event could not be decoded
+    pub error: Option<String>,
+    /// Event specs that were tried and why each was rejected
+    pub rejected: Vec<String>,
 }
 
-/// Convenience function to get simplified events as JSON string
-pub fn simplify_events_to_json(metadata: &ContractMetadata) -> Result<String, serde_json::Error> {
-    let simplified = simplify_events(metadata);
-    serde_json::to_string_pretty(&simplified)
+/// Why an event could not be decoded.
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodeFailure {
+    /// Summary of the failure
+    pub error: String,
+    /// Event specs that were tried and why each was rejected
+    pub tried: Vec<String>,
 }
 
-pub fn value_to_json(value: &Value<u32>) -> JsonValue {
-    match &value.value {
-        ValueDef::Composite(composite) => composite_to_json(composite),
-        ValueDef::Variant(variant) => {
-            json!({
-                "variant": &variant.name,
-                "values": composite_to_json(&variant.values)
-            })
-        }
-        ValueDef::Primitive(primitive) => primitive_to_json(primitive),
-        ValueDef::BitSequence(bits) => {
-            json!({
-                "type": "BitSequence",
-                "bits": format!("{:?}", bits)
-            })
-        }
+// Decode contract event bytes using contract metadata and send the result to the handler
+#[allow(clippy::too_many_arguments)]
+pub async fn decode_contract_event_with_metadata(
+    tx: EventSender,
+    contract_addr: String,
+    block: String,
+    block_number: Option<u64>,
+    bytes: &[u8],
+    topics: Vec<String>,
+    metadata: &ContractMetadata,
+    mode: DecodeMode,
+    pipeline: &Pipeline,
+) -> Result<(), DecodeFailure> {
+    let mut event_data = decode_contract_event(bytes, &topics, metadata, mode)?;
+
+    // Keep the raw payload so the event can be re-decoded later
+    event_data.raw = Some(RawEvent {
+        data: format!("0x{}", hex::encode(bytes)),
+        topics,
+    });
+    event_data.block = Some(block);
+    event_data.block_number = block_number;
+
+    // Matching and executing the event continue the trace it was decoded in
+    let cx = Context::current();
+    telemetry::annotate(&cx, vec![KeyValue::new("event.name", event_data.event_name.clone())]);
+    event_data.trace = telemetry::traceparent(&cx);
+
+    // Push into stream
+    pipeline.enqueued();
+    if let Err(e) = tx.send(contract_addr, event_data).await {
+        error!("Failed to queue event: {e}");
     }
-}
 
-pub fn composite_to_json(composite: &Composite<u32>) -> JsonValue {
-    match composite {
-        Composite::Named(fields) => {
-            let mut obj = serde_json::Map::new();
-            for (name, value) in fields.iter() {
-                obj.insert(name.to_string(), value_to_json(value));
-            }
-            JsonValue::Object(obj)
-        }
-        Composite::Unnamed(fields) => {
-            // Check if this is a byte array that should be converted to hex
-            if is_byte_array(fields) {
-                let bytes: Vec<u8> = fields
-                    .iter()
-                    .filter_map(|v| {
-                        if let ValueDef::Primitive(Primitive::U128(n)) = &v.value {
-                            if *n <= 255 {
-                                return Some(*n as u8);
-                            }
-                        }
-                        None
-                    })
-                    .collect();
-
-                if bytes.len() == fields.len() {
-                    // Check if it looks like an AccountId (32 bytes) or other common types
-                    if bytes.len() == 32 {
-                        // Try to decode as string first (for pallet names, etc.)
-                        if let Ok(s) = std::str::from_utf8(&bytes) {
-                            if s.chars().all(|c| {
-                                c.is_ascii_alphanumeric() || c == '\0' || c.is_ascii_whitespace()
-                            }) {
-                                let trimmed = s.trim_end_matches('\0');
-                                if !trimmed.is_empty() {
-                                    return json!({
-                                        "type": "String",
-                                        "value": trimmed
-                                    });
-                                }
-                            }
-                        }
-                        // Otherwise it's likely an AccountId
-                        return json!({
-                            "type": "AccountId32",
-                            "value": format!("0x{}", hex::encode(&bytes))
-                        });
-                    } else {
-                        // Generic byte array
-                        return json!({
-                            "type": "Bytes",
-                            "value": format!("0x{}", hex::encode(&bytes))
-                        });
-                    }
-                }
-            }
+    Ok(())
+}
 
-            // Recursively decode the array
-            JsonValue::Array(fields.iter().map(|v| value_to_json(v)).collect())
-        }
-    }
+// Decode contract event bytes using contract metadata.
+// Lenient mode returns the first spec that decodes cleanly. Strict mode requires exactly one
+// spec to decode cleanly and, when topics are known, its signature topic to match.
+pub fn decode_contract_event(
+    bytes: &[u8],
+    topics: &[String],
+    metadata: &ContractMetadata,
+    mode: DecodeMode,
+) -> Result<EventData, DecodeFailure> {
+    let mut tried = Vec::new();
+    decode_event(bytes, topics, metadata, mode, &mut tried)
+        .map_err(|error| DecodeFailure { error, tried })
 }
 
-pub fn primitive_to_json(primitive: &Primitive) -> JsonValue {
-    match primitive {
-        Primitive::Bool(b) => json!(b),
-        Primitive::Char(c) => json!(c.to_string()),
-        Primitive::String(s) => json!(s),
-        Primitive::U128(n) => json!(n.to_string()),
-        Primitive::U256(n) => json!(format!("0x{}", hex::encode(n))),
-        Primitive::I128(n) => json!(n.to_string()),
-        Primitive::I256(n) => json!(format!("0x{}", hex::encode(n))),
+/// Test-decode contract event bytes, reporting which event spec matched and why the others
+/// were rejected.
+pub fn explain_decode(
+    bytes: &[u8],
+    topics: &[String],
+    metadata: &ContractMetadata,
+    mode: DecodeMode,
+) -> DecodeReport {
+    let mut rejected = Vec::new();
+    let decoded = decode_event(bytes, topics, metadata, mode, &mut rejected);
+
+    let (matched, fields, error) = match decoded {
+        Ok(event) => (Some(event.event_name), Some(event.fields), None),
+        Err(error) => (None, None, Some(error)),
+    };
+    DecodeReport {
+        mode,
+        matched,
+        fields,
+        error,
+        rejected,
     }
 }
 
-// Decode contract event bytes using contract metadata
-pub async fn decode_contract_event_with_metadata(
-    tx: Sender<(String, EventData)>,
-    contract_addr: String,
+/// Decode contract event bytes, recording why each event spec tried was rejected.
+fn decode_event(
     bytes: &[u8],
+    topics: &[String],
     metadata: &ContractMetadata,
-) {
+    mode: DecodeMode,
+    tried: &mut Vec<String>,
+) -> Result<EventData, String> {
     if bytes.is_empty() {
         info!("      Empty event data");
-        return;
+        return Err("Empty event data".to_string());
     }
 
-    let mut cursor = &bytes[..];
+    let mut cursor = bytes;
 
     // First byte is the event selector
     let selector = match u8::decode(&mut cursor) {
         Ok(s) => s,
         Err(e) => {
             info!("      ❌ Failed to decode selector: {:?}", e);
-            return;
+            return Err(format!("Failed to decode selector: {:?}", e));
         }
     };
 
+    // Clean matches (only collected in strict mode)
+    let mut matched: Vec<EventData> = Vec::new();
+
     info!("      Selector: 0x{:02x}", selector);
 
+    // ink! v4 events are enum variants, so the selector is the index of the event spec.
+    // ink! v5 events carry no index, so every event spec is tried.
+    let candidates: Vec<&EventSpec> = match metadata.detect_version() {
+        Ok(MetadataVersion::V4) => match metadata.spec.events.get(selector as usize) {
+            Some(event_spec) => vec![event_spec],
+            None => {
+                return Err(format!("No event at index {selector}"));
+            }
+        },
+        _ => metadata.spec.events.iter().collect(),
+    };
+
     // Try to find matching event by trying to decode with each event spec
-    for event_spec in &metadata.spec.events {
+    for event_spec in candidates {
         info!("      Trying event: {}", event_spec.label);
 
         let mut decode_cursor = cursor;
@@ -365,6 +330,10 @@ pub async fn decode_contract_event_with_metadata(
                 }
                 Err(e) => {
                     info!("        ❌ Failed to decode field '{}': {:?}", arg.label, e);
+                    tried.push(format!(
+                        "{}: failed to decode field '{}': {}",
+                        event_spec.label, arg.label, e
+                    ));
                     success = false;
                     break;
                 }
@@ -375,6 +344,13 @@ pub async fn decode_contract_event_with_metadata(
         let mut event_args: HashMap<String, JsonValue> = HashMap::new();
 
         if success && decode_cursor.is_empty() {
+            // In strict mode the signature topic must agree with the spec
+            if mode == DecodeMode::Strict && !signature_topic_matches(event_spec, topics) {
+                info!("        ⚠️ Signature topic mismatch for {}", event_spec.label);
+                tried.push(format!("{}: signature topic mismatch", event_spec.label));
+                continue;
+            }
+
             info!(
                 "      ✅ Successfully decoded as event: {}",
                 event_spec.label
@@ -392,12 +368,20 @@ pub async fn decode_contract_event_with_metadata(
             let event_data = EventData {
                 event_name: event_spec.label.clone(),
                 fields: event_args,
+                raw: None,
+                scores: HashMap::new(),
+                trace: None,
+                namespace: Namespace::Live,
+                block: None,
+                block_number: None,
             };
 
-            // Push into stream
-            let _ = tx.send((contract_addr, event_data)).await;
+            if mode == DecodeMode::Lenient {
+                return Ok(event_data);
+            }
 
-            return;
+            matched.push(event_data);
+            continue;
         } else if !success {
             // Reset and try next event
             continue;
@@ -406,6 +390,27 @@ pub async fn decode_contract_event_with_metadata(
                 "        ⚠️ Extra bytes remaining after decode: {} bytes",
                 decode_cursor.len()
             );
+            tried.push(format!(
+                "{}: {} extra bytes remaining after decode",
+                event_spec.label,
+                decode_cursor.len()
+            ));
+        }
+    }
+
+    // Strict mode only accepts an unambiguous match
+    if mode == DecodeMode::Strict {
+        if matched.len() == 1 {
+            return Ok(matched.remove(0));
+        } else if matched.len() > 1 {
+            let names = matched
+                .iter()
+                .map(|e| e.event_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            info!("      ⚠️ Ambiguous event, matches: {}", names);
+
+            return Err(format!("Ambiguous event, matches: {names}"));
         }
     }
 
@@ -417,17 +422,15 @@ pub async fn decode_contract_event_with_metadata(
     info!("        Attempting manual decode:");
 
     // Try H160 (20 bytes)
-    if manual_cursor.len() >= 20 {
-        if let Ok(addr_bytes) = <[u8; 20]>::decode(&mut manual_cursor) {
-            info!("        Possible H160: 0x{}", hex::encode(addr_bytes));
-        }
+    let h160 = (manual_cursor.len() >= 20).then(|| <[u8; 20]>::decode(&mut manual_cursor));
+    if let Some(Ok(addr_bytes)) = h160 {
+        info!("        Possible H160: 0x{}", hex::encode(addr_bytes));
     }
 
     // Try u128
-    if manual_cursor.len() >= 16 {
-        if let Ok(val) = u128::decode(&mut manual_cursor) {
-            info!("        Possible u128: {}", val);
-        }
+    let value = (manual_cursor.len() >= 16).then(|| u128::decode(&mut manual_cursor));
+    if let Some(Ok(val)) = value {
+        info!("        Possible u128: {}", val);
     }
 
     // Try String
@@ -436,6 +439,26 @@ pub async fn decode_contract_event_with_metadata(
     }
 
     info!("      Remaining bytes: 0x{}", hex::encode(cursor));
+
+    Err("Could not match event to metadata".to_string())
+}
+
+/// Read the topics of a `ContractEmitted` event from its raw fields (contract address, data and
+/// topics), without decoding it against the runtime metadata.
+pub fn emitted_topics(mut field_bytes: &[u8]) -> Option<Vec<[u8; 32]>> {
+    let (_, _, topics) = <([u8; 20], Vec<u8>, Vec<[u8; 32]>)>::decode(&mut field_bytes).ok()?;
+    field_bytes.is_empty().then_some(topics)
+}
+
+/// Check the first topic against the spec's signature topic, when both are known.
+fn signature_topic_matches(event_spec: &EventSpec, topics: &[String]) -> bool {
+    match topics.first() {
+        Some(topic) => match &event_spec.signature_topic {
+            Some(signature) => topic.eq_ignore_ascii_case(signature),
+            None => true,
+        },
+        _ => true,
+    }
 }
 
 fn decode_field_by_type(
@@ -445,303 +468,251 @@ fn decode_field_by_type(
 ) -> Result<String, String> {
     // Find the type definition
     let type_def = metadata
-        .types
-        .iter()
-        .find(|t| t.id == type_id)
+        .type_def(type_id)
         .ok_or_else(|| format!("Type {} not found", type_id))?;
 
+    let def = &type_def.type_def.def;
+
     // Handle primitive types
-    if let Some(def) = type_def.type_def.def.get("primitive") {
-        if let Some(prim_type) = def.as_str() {
-            return match prim_type {
-                "u128" => {
-                    let val = u128::decode(cursor)
-                        .map_err(|e| format!("Failed to decode u128: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                "u64" => {
-                    let val = u64::decode(cursor)
-                        .map_err(|e| format!("Failed to decode u64: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                "u32" => {
-                    let val = u32::decode(cursor)
-                        .map_err(|e| format!("Failed to decode u32: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                "u16" => {
-                    let val = u16::decode(cursor)
-                        .map_err(|e| format!("Failed to decode u16: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                "u8" => {
-                    let val =
-                        u8::decode(cursor).map_err(|e| format!("Failed to decode u8: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                "i128" => {
-                    let val = i128::decode(cursor)
-                        .map_err(|e| format!("Failed to decode i128: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                "i64" => {
-                    let val = i64::decode(cursor)
-                        .map_err(|e| format!("Failed to decode i64: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                "i32" => {
-                    let val = i32::decode(cursor)
-                        .map_err(|e| format!("Failed to decode i32: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                "i16" => {
-                    let val = i16::decode(cursor)
-                        .map_err(|e| format!("Failed to decode i16: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                "i8" => {
-                    let val =
-                        i8::decode(cursor).map_err(|e| format!("Failed to decode i8: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                "str" => {
-                    let val = String::decode(cursor)
-                        .map_err(|e| format!("Failed to decode string: {:?}", e))?;
-                    Ok(format!("{:?}", val))
-                }
-                "bool" => {
-                    let val = bool::decode(cursor)
-                        .map_err(|e| format!("Failed to decode bool: {:?}", e))?;
-                    Ok(val.to_string())
-                }
-                _ => Err(format!("Unknown primitive type: {}", prim_type)),
-            };
-        }
+    if let Some(prim_type) = def.get("primitive").and_then(|def| def.as_str()) {
+        return match prim_type {
+            "u128" => {
+                let val = u128::decode(cursor)
+                    .map_err(|e| format!("Failed to decode u128: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            "u64" => {
+                let val = u64::decode(cursor)
+                    .map_err(|e| format!("Failed to decode u64: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            "u32" => {
+                let val = u32::decode(cursor)
+                    .map_err(|e| format!("Failed to decode u32: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            "u16" => {
+                let val = u16::decode(cursor)
+                    .map_err(|e| format!("Failed to decode u16: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            "u8" => {
+                let val =
+                    u8::decode(cursor).map_err(|e| format!("Failed to decode u8: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            "i128" => {
+                let val = i128::decode(cursor)
+                    .map_err(|e| format!("Failed to decode i128: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            "i64" => {
+                let val = i64::decode(cursor)
+                    .map_err(|e| format!("Failed to decode i64: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            "i32" => {
+                let val = i32::decode(cursor)
+                    .map_err(|e| format!("Failed to decode i32: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            "i16" => {
+                let val = i16::decode(cursor)
+                    .map_err(|e| format!("Failed to decode i16: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            "i8" => {
+                let val =
+                    i8::decode(cursor).map_err(|e| format!("Failed to decode i8: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            "str" => {
+                let val = String::decode(cursor)
+                    .map_err(|e| format!("Failed to decode string: {:?}", e))?;
+                Ok(format!("{:?}", val))
+            }
+            "bool" => {
+                let val = bool::decode(cursor)
+                    .map_err(|e| format!("Failed to decode bool: {:?}", e))?;
+                Ok(val.to_string())
+            }
+            _ => Err(format!("Unknown primitive type: {}", prim_type)),
+        };
     }
 
     // Handle arrays
-    if let Some(def) = type_def.type_def.def.get("array") {
-        if let (Some(len), Some(inner_type)) = (def.get("len"), def.get("type")) {
-            let array_len = len.as_u64().ok_or("Invalid array length")? as usize;
-            let inner_type_id = inner_type.as_u64().ok_or("Invalid inner type")? as u32;
-
-            // Special case for byte arrays (common for addresses/hashes)
-            if inner_type_id == 10 {
-                // u8 type
-                let mut bytes = vec![0u8; array_len];
-                for i in 0..array_len {
-                    bytes[i] = u8::decode(cursor)
-                        .map_err(|e| format!("Failed to decode byte array: {:?}", e))?;
-                }
-                return Ok(format!("0x{}", hex::encode(bytes)));
+    let array = def
+        .get("array")
+        .and_then(|def| def.get("len").zip(def.get("type")));
+    if let Some((len, inner_type)) = array {
+        let array_len = len.as_u64().ok_or("Invalid array length")? as usize;
+        let inner_type_id = inner_type.as_u64().ok_or("Invalid inner type")? as u32;
+
+        // Special case for byte arrays (common for addresses/hashes)
+        if inner_type_id == 10 {
+            // u8 type
+            let mut bytes = vec![0u8; array_len];
+            for byte in bytes.iter_mut() {
+                *byte = u8::decode(cursor)
+                    .map_err(|e| format!("Failed to decode byte array: {:?}", e))?;
             }
+            return Ok(format!("0x{}", hex::encode(bytes)));
+        }
 
-            // Generic array decoding
-            let mut values = Vec::new();
-            for _ in 0..array_len {
-                let val = decode_field_by_type(cursor, inner_type_id, metadata)?;
-                values.push(val);
-            }
-            return Ok(format!("[{}]", values.join(", ")));
+        // Generic array decoding
+        let mut values = Vec::new();
+        for _ in 0..array_len {
+            let val = decode_field_by_type(cursor, inner_type_id, metadata)?;
+            values.push(val);
         }
+        return Ok(format!("[{}]", values.join(", ")));
     }
 
     // Handle composite types (structs)
-    if let Some(def) = type_def.type_def.def.get("composite") {
-        if let Some(fields) = def.get("fields") {
-            if let Some(fields_array) = fields.as_array() {
-                // Check if it's a tuple-like struct (unnamed fields)
-                if fields_array.len() == 1 {
-                    if let Some(field) = fields_array.get(0) {
-                        if let Some(inner_type) = field.get("type") {
-                            let inner_type_id = inner_type.as_u64().ok_or("Invalid type")? as u32;
-                            // Unwrap single-field composite
-                            return decode_field_by_type(cursor, inner_type_id, metadata);
-                        }
-                    }
-                }
+    let composite = def
+        .get("composite")
+        .and_then(|def| def.get("fields"))
+        .and_then(|fields| fields.as_array());
+    if let Some(fields_array) = composite {
+        // Check if it's a tuple-like struct (unnamed fields)
+        let single = fields_array
+            .first()
+            .and_then(|field| field.get("type"))
+            .filter(|_| fields_array.len() == 1);
+        if let Some(inner_type) = single {
+            let inner_type_id = inner_type.as_u64().ok_or("Invalid type")? as u32;
+            // Unwrap single-field composite
+            return decode_field_by_type(cursor, inner_type_id, metadata);
+        }
 
-                // Multiple fields - decode each
-                let mut field_values = Vec::new();
-                for field in fields_array {
-                    if let Some(inner_type) = field.get("type") {
-                        let inner_type_id = inner_type.as_u64().ok_or("Invalid type")? as u32;
-                        let val = decode_field_by_type(cursor, inner_type_id, metadata)?;
-
-                        if let Some(name) = field.get("name") {
-                            if let Some(name_str) = name.as_str() {
-                                field_values.push(format!("{}: {}", name_str, val));
-                            } else {
-                                field_values.push(val);
-                            }
-                        } else {
-                            field_values.push(val);
-                        }
-                    }
-                }
-                return Ok(format!("{{ {} }}", field_values.join(", ")));
+        // Multiple fields - decode each
+        let mut field_values = Vec::new();
+        for field in fields_array {
+            let Some(inner_type) = field.get("type") else {
+                continue;
+            };
+            let inner_type_id = inner_type.as_u64().ok_or("Invalid type")? as u32;
+            let val = decode_field_by_type(cursor, inner_type_id, metadata)?;
+
+            match field.get("name").and_then(|name| name.as_str()) {
+                Some(name_str) => field_values.push(format!("{}: {}", name_str, val)),
+                None => field_values.push(val),
             }
         }
+        return Ok(format!("{{ {} }}", field_values.join(", ")));
     }
 
     // Handle variant types (enums)
-    if let Some(def) = type_def.type_def.def.get("variant") {
-        if let Some(variants) = def.get("variants") {
-            if let Some(variants_array) = variants.as_array() {
-                // Check if this is an Option type - it might encode Some without discriminant for indexed fields
-                let is_option = type_def
-                    .type_def
-                    .path
-                    .as_ref()
-                    .map(|p| p.contains(&"Option".to_string()))
-                    .unwrap_or(false);
-
-                // Decode discriminant
-                let discriminant = u8::decode(cursor)
-                    .map_err(|e| format!("Failed to decode variant discriminant: {:?}", e))?;
-
-                // Find matching variant
-                for variant in variants_array {
-                    if let Some(index) = variant.get("index") {
-                        if index.as_u64() == Some(discriminant as u64) {
-                            if let Some(name) = variant.get("name") {
-                                let variant_name = name.as_str().unwrap_or("Unknown");
-
-                                // Check if variant has fields
-                                if let Some(fields) = variant.get("fields") {
-                                    if let Some(fields_array) = fields.as_array() {
-                                        if fields_array.is_empty() {
-                                            return Ok(variant_name.to_string());
-                                        }
-
-                                        // Decode variant fields
-                                        let mut field_values = Vec::new();
-                                        for field in fields_array {
-                                            if let Some(field_type) = field.get("type") {
-                                                let field_type_id = field_type
-                                                    .as_u64()
-                                                    .ok_or("Invalid field type")?
-                                                    as u32;
-                                                let val = decode_field_by_type(
-                                                    cursor,
-                                                    field_type_id,
-                                                    metadata,
-                                                )?;
-                                                field_values.push(val);
-                                            }
-                                        }
-
-                                        if field_values.is_empty() {
-                                            return Ok(variant_name.to_string());
-                                        } else {
-                                            return Ok(format!(
-                                                "{}({})",
-                                                variant_name,
-                                                field_values.join(", ")
-                                            ));
-                                        }
-                                    }
-                                }
-
-                                return Ok(variant_name.to_string());
-                            }
-                        }
-                    }
-                }
+    let variants = def
+        .get("variant")
+        .and_then(|def| def.get("variants"))
+        .and_then(|variants| variants.as_array());
+    if let Some(variants_array) = variants {
+        // Check if this is an Option type - it might encode Some without discriminant for indexed fields
+        let is_option = type_def
+            .type_def
+            .path
+            .as_ref()
+            .map(|p| p.contains(&"Option".to_string()))
+            .unwrap_or(false);
+
+        // Decode discriminant
+        let discriminant = u8::decode(cursor)
+            .map_err(|e| format!("Failed to decode variant discriminant: {:?}", e))?;
+
+        // Find matching variant
+        let matching = variants_array.iter().find_map(|variant| {
+            let index = variant.get("index")?.as_u64();
+            let name = variant.get("name")?;
+            (index == Some(discriminant as u64)).then_some((variant, name))
+        });
+        if let Some((variant, name)) = matching {
+            let variant_name = name.as_str().unwrap_or("Unknown");
+
+            // Check if variant has fields
+            let Some(fields_array) = variant.get("fields").and_then(|fields| fields.as_array())
+            else {
+                return Ok(variant_name.to_string());
+            };
 
-                // If we reach here and it's an Option, try assuming Some(T) without discriminant
-                // This happens in ink! indexed fields sometimes
-                if is_option {
-                    // Create a new slice that includes the discriminant byte we just read
-                    let remaining_len = cursor.len();
-                    let mut temp_buffer = vec![discriminant];
-                    temp_buffer.extend_from_slice(cursor);
-                    let mut temp_cursor = &temp_buffer[..];
-
-                    // Try to decode the inner type (assuming Some variant has one field)
-                    for variant in variants_array {
-                        if let Some(name) = variant.get("name") {
-                            if name.as_str() == Some("Some") {
-                                if let Some(fields) = variant.get("fields") {
-                                    if let Some(fields_array) = fields.as_array() {
-                                        if let Some(field) = fields_array.get(0) {
-                                            if let Some(field_type) = field.get("type") {
-                                                let field_type_id = field_type
-                                                    .as_u64()
-                                                    .ok_or("Invalid field type")?
-                                                    as u32;
-                                                match decode_field_by_type(
-                                                    &mut temp_cursor,
-                                                    field_type_id,
-                                                    metadata,
-                                                ) {
-                                                    Ok(val) => {
-                                                        // Success! Update the original cursor
-                                                        let consumed =
-                                                            temp_buffer.len() - temp_cursor.len();
-                                                        *cursor = &cursor[consumed - 1..]; // -1 because we added discriminant
-                                                        return Ok(format!("Some({})", val));
-                                                    }
-                                                    Err(_) => {
-                                                        // Failed, continue to error
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+            // Decode variant fields
+            let mut field_values = Vec::new();
+            for field in fields_array {
+                if let Some(field_type) = field.get("type") {
+                    let field_type_id = field_type.as_u64().ok_or("Invalid field type")? as u32;
+                    let val = decode_field_by_type(cursor, field_type_id, metadata)?;
+                    field_values.push(val);
                 }
+            }
 
-                return Err(format!("Unknown variant discriminant: {}", discriminant));
+            if field_values.is_empty() {
+                return Ok(variant_name.to_string());
             }
+            return Ok(format!("{}({})", variant_name, field_values.join(", ")));
         }
-    }
 
-    // Handle tuple types
-    if let Some(def) = type_def.type_def.def.get("tuple") {
-        if let Some(tuple_array) = def.as_array() {
-            if tuple_array.is_empty() {
-                // Unit type ()
-                return Ok("()".to_string());
-            }
+        // If we reach here and it's an Option, try assuming Some(T) without discriminant
+        // This happens in ink! indexed fields sometimes
+        if is_option {
+            // Create a new slice that includes the discriminant byte we just read
+            let mut temp_buffer = vec![discriminant];
+            temp_buffer.extend_from_slice(cursor);
+            let mut temp_cursor = &temp_buffer[..];
 
-            let mut values = Vec::new();
-            for item in tuple_array {
-                if let Some(type_id_val) = item.as_u64() {
-                    let val = decode_field_by_type(cursor, type_id_val as u32, metadata)?;
-                    values.push(val);
+            // Try to decode the inner type (assuming Some variant has one field)
+            let some_types = variants_array
+                .iter()
+                .filter(|variant| variant.get("name").and_then(|n| n.as_str()) == Some("Some"))
+                .filter_map(|variant| variant.get("fields")?.as_array()?.first()?.get("type"));
+            for field_type in some_types {
+                let field_type_id = field_type.as_u64().ok_or("Invalid field type")? as u32;
+                if let Ok(val) = decode_field_by_type(&mut temp_cursor, field_type_id, metadata) {
+                    // Success! Update the original cursor
+                    let consumed = temp_buffer.len() - temp_cursor.len();
+                    *cursor = &cursor[consumed - 1..]; // -1 because we added discriminant
+                    return Ok(format!("Some({})", val));
                 }
             }
-            return Ok(format!("({})", values.join(", ")));
         }
-    }
 
-    // Handle sequence types (Vec)
-    if let Some(def) = type_def.type_def.def.get("sequence") {
-        if let Some(inner_type) = def.get("type") {
-            let inner_type_id = inner_type.as_u64().ok_or("Invalid sequence type")? as u32;
+        return Err(format!("Unknown variant discriminant: {}", discriminant));
+    }
 
-            // Decode compact-encoded length
-            let length = parity_scale_codec::Compact::<u32>::decode(cursor)
-                .map_err(|e| format!("Failed to decode Vec length: {:?}", e))?;
+    // Handle tuple types
+    if let Some(tuple_array) = def.get("tuple").and_then(|def| def.as_array()) {
+        if tuple_array.is_empty() {
+            // Unit type ()
+            return Ok("()".to_string());
+        }
 
-            let mut values = Vec::new();
-            for _ in 0..length.0 {
-                let val = decode_field_by_type(cursor, inner_type_id, metadata)?;
+        let mut values = Vec::new();
+        for item in tuple_array {
+            if let Some(type_id_val) = item.as_u64() {
+                let val = decode_field_by_type(cursor, type_id_val as u32, metadata)?;
                 values.push(val);
             }
+        }
+        return Ok(format!("({})", values.join(", ")));
+    }
 
-            return Ok(format!("Vec[{}]", values.join(", ")));
+    // Handle sequence types (Vec)
+    if let Some(inner_type) = def.get("sequence").and_then(|def| def.get("type")) {
+        let inner_type_id = inner_type.as_u64().ok_or("Invalid sequence type")? as u32;
+
+        // Decode compact-encoded length
+        let length = parity_scale_codec::Compact::<u32>::decode(cursor)
+            .map_err(|e| format!("Failed to decode Vec length: {:?}", e))?;
+
+        let mut values = Vec::new();
+        for _ in 0..length.0 {
+            let val = decode_field_by_type(cursor, inner_type_id, metadata)?;
+            values.push(val);
         }
+
+        return Ok(format!("Vec[{}]", values.join(", ")));
     }
 
-    Err(format!(
-        "Unsupported type definition: {:?}",
-        type_def.type_def.def
-    ))
+    Err(format!("Unsupported type definition: {:?}", def))
 }
 
 /// Parse an event string value into a JSON value
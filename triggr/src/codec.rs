@@ -0,0 +1,82 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the formats documents and triggers are stored in.
+// Readers detect the format of each record from its first byte, so a tree can switch formats
+// without a migration step: old records are rewritten as they are read or written.
+
+use serde::{de::DeserializeOwned, Serialize};
+use sled::IVec;
+
+use crate::prelude::{StorageError, StorageResult};
+
+/// Serialization format of stored records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Codec {
+    /// JSON, readable with any sled tool
+    #[default]
+    Json,
+    /// CBOR, smaller and faster to decode
+    Cbor,
+}
+
+impl Codec {
+    /// Read the format of a tree from `TRIGGR_CODEC_{TREE}` (`json` or `cbor`).
+    pub fn from_env(tree: &str) -> Self {
+        let var = format!("TRIGGR_CODEC_{}", tree.to_uppercase());
+        match std::env::var(&var).map(|v| v.to_lowercase()).as_deref() {
+            Ok("cbor") => Codec::Cbor,
+            Ok("json") | Err(_) => Codec::Json,
+            Ok(other) => {
+                tracing::warn!("Unknown codec '{other}' in {var}, using json");
+                Codec::Json
+            }
+        }
+    }
+
+    /// Detect the format of a stored record.
+    /// Records are objects or arrays: `{`/`[` in JSON, major types 4 and 5 (0x80-0xbf) in CBOR.
+    pub fn detect(bytes: &[u8]) -> Self {
+        match bytes.first() {
+            Some(0x80..=0xbf) => Codec::Cbor,
+            _ => Codec::Json,
+        }
+    }
+
+    /// Whether a stored record should be rewritten in this format.
+    pub fn is_stale(self, bytes: &[u8]) -> bool {
+        Self::detect(bytes) != self
+    }
+
+    /// Serialize a record in this format.
+    pub fn encode<T: Serialize + ?Sized>(self, value: &T) -> StorageResult<Vec<u8>> {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(value)?),
+            Codec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map_err(|e| StorageError::Other(format!("Failed to encode CBOR: {e}")))?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Deserialize a record stored in any format.
+    pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> StorageResult<T> {
+        match Self::detect(bytes) {
+            Codec::Json => Ok(serde_json::from_slice(bytes)?),
+            Codec::Cbor => ciborium::from_reader(bytes)
+                .map_err(|e| StorageError::Other(format!("Failed to decode CBOR: {e}"))),
+        }
+    }
+
+    /// Return a stored record as JSON, transcoding it if needed.
+    pub fn to_json(bytes: IVec) -> StorageResult<IVec> {
+        match Self::detect(&bytes) {
+            Codec::Json => Ok(bytes),
+            Codec::Cbor => {
+                let value: serde_json::Value = Self::decode(&bytes)?;
+                Ok(serde_json::to_vec(&value)?.into())
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the local development mode, enabled with `--dev` (or `TRIGGR_DEV`).
+// Instead of PassetHub, the instance listens to a local contracts node, which it can launch itself.
+// The bundled EventDemo contract is deployed to it with `cargo contract`, registered as a demo
+// project, and called periodically, so its events flow through the whole pipeline.
+// Events can also be crafted and injected into the pipeline directly, without calling a contract.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    process::Stdio,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{
+    net::TcpStream,
+    process::{Child, Command},
+};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    bootstrap,
+    chain::polkadot::{
+        metadata::{ContractMetadata, ValueKind},
+        prelude::{DecodeMode, EventData, RawEvent},
+        util::decode_contract_event,
+    },
+    namespace::Namespace,
+    prelude::{EventSender, StorageResult, Triggr},
+};
+
+/// Default url of the local contracts node.
+const DEFAULT_DEV_NODE_URL: &str = "ws://127.0.0.1:9944";
+
+/// Default account deploying and calling the demo contract.
+const DEFAULT_DEV_SURI: &str = "//Alice";
+
+/// Default owner of the demo project.
+const DEFAULT_DEV_OWNER: &str = "dev";
+
+/// Default number of seconds between two test events.
+const DEFAULT_DEV_EVENT_INTERVAL_SECS: u64 = 10;
+
+/// Attempts to reach the node after launching it, every `NODE_POLL_INTERVAL`.
+const NODE_POLL_ATTEMPTS: u32 = 60;
+const NODE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Highest value carried by test events. Values above 100 reach the `large-values` demo trigger.
+const MAX_TEST_VALUE: u128 = 200;
+
+/// Configuration of the local development mode.
+pub struct DevMode {
+    /// Node the instance listens to
+    pub node_url: String,
+    /// Binary of the node launched at startup (a running node is used when unset)
+    node_bin: Option<String>,
+    /// Address of an already deployed demo contract (deployed at startup when unset)
+    contract: Option<String>,
+    /// Account deploying and calling the demo contract
+    suri: String,
+    /// Owner of the demo project
+    owner: String,
+    /// Interval between two test events (disabled if zero)
+    event_interval: Duration,
+    /// Node launched by the instance
+    node: Mutex<Option<Child>>,
+    /// Queue of the trigger engine, set when the engine starts
+    sender: OnceLock<EventSender>,
+}
+
+/// An event injected into the pipeline, either crafted or decoded from a raw payload.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EmitEvent {
+    /// Name of the crafted event
+    #[serde(default)]
+    pub event_name: Option<String>,
+    /// Fields of the crafted event (fields left out are absent from the event)
+    #[serde(default)]
+    pub fields: HashMap<String, Value>,
+    /// SCALE encoded event data (hex), decoded with the contract metadata like a chain event
+    #[serde(default)]
+    pub data: Option<String>,
+    /// Topics of the raw event (hex)
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+impl DevMode {
+    /// Return the development mode configuration if it is enabled.
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::args().skip(1).any(|arg| arg == "--dev")
+            || std::env::var("TRIGGR_DEV").is_ok_and(|v| v == "1" || v == "true");
+        if !enabled {
+            return None;
+        }
+
+        let event_interval = std::env::var("TRIGGR_DEV_EVENT_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_DEV_EVENT_INTERVAL_SECS);
+
+        Some(Self {
+            node_url: std::env::var("TRIGGR_DEV_NODE_URL")
+                .unwrap_or_else(|_| DEFAULT_DEV_NODE_URL.to_string()),
+            node_bin: std::env::var("TRIGGR_DEV_NODE_BIN").ok(),
+            contract: std::env::var("TRIGGR_DEV_CONTRACT")
+                .ok()
+                .map(|addr| addr.to_lowercase()),
+            suri: std::env::var("TRIGGR_DEV_SURI")
+                .unwrap_or_else(|_| DEFAULT_DEV_SURI.to_string()),
+            owner: std::env::var("TRIGGR_BOOTSTRAP_OWNER")
+                .unwrap_or_else(|_| DEFAULT_DEV_OWNER.to_string()),
+            event_interval: Duration::from_secs(event_interval),
+            node: Mutex::new(None),
+            sender: OnceLock::new(),
+        })
+    }
+
+    /// Attach dev mode to the trigger engine, so events can be injected.
+    pub fn attach(&self, sender: EventSender) {
+        let _ = self.sender.set(sender);
+    }
+
+    /// Build the event described by a request, checked against the contract metadata.
+    pub fn craft(
+        &self,
+        request: EmitEvent,
+        metadata: &ContractMetadata,
+        mode: DecodeMode,
+    ) -> Result<EventData, String> {
+        // Raw payloads take the decode path of chain events
+        if let Some(data) = request.data {
+            let bytes = hex::decode(data.trim_start_matches("0x"))
+                .map_err(|e| format!("Invalid event data: {e}"))?;
+            let mut event = decode_contract_event(&bytes, &request.topics, metadata, mode)
+                .map_err(|failure| format!("Failed to decode the event: {}", failure.error))?;
+            event.raw = Some(RawEvent {
+                data,
+                topics: request.topics,
+            });
+            return Ok(event);
+        }
+
+        let Some(event_name) = request.event_name else {
+            return Err("Either an event name and fields, or raw event data are required".into());
+        };
+        let spec = metadata
+            .event(&event_name)
+            .ok_or_else(|| format!("The contract has no event '{event_name}'"))?;
+
+        // Fields must be arguments of the event, with values of their type
+        for (name, value) in &request.fields {
+            let arg = spec
+                .args
+                .iter()
+                .find(|arg| arg.label == *name)
+                .ok_or_else(|| format!("{} has no field '{name}'", spec.label))?;
+
+            let valid = match metadata.value_kind(arg.type_info.type_id) {
+                ValueKind::Number => value.is_number(),
+                ValueKind::Text => value.is_string(),
+                ValueKind::Bool => value.is_boolean(),
+                ValueKind::Other | ValueKind::Unknown => true,
+            };
+            if !valid {
+                return Err(format!("Invalid value for {}.{name}: {value}", spec.label));
+            }
+        }
+
+        Ok(EventData {
+            event_name: spec.label.clone(),
+            fields: request.fields,
+            raw: None,
+            scores: HashMap::new(),
+            trace: None,
+            namespace: Namespace::Live,
+            block: None,
+            block_number: None,
+        })
+    }
+
+    /// Queue an event of a contract for the trigger engine.
+    pub async fn emit(
+        &self,
+        triggr: &Triggr,
+        contract_addr: &str,
+        event: EventData,
+    ) -> StorageResult<()> {
+        let Some(sender) = self.sender.get() else {
+            return Err("The trigger engine is not running".into());
+        };
+
+        info!("🧪 Injecting {} event on {contract_addr}", event.event_name);
+        triggr.pipeline.enqueued();
+        sender.send(contract_addr.to_lowercase(), event).await
+    }
+
+    /// Launch the local node if a binary is configured, and wait until it accepts connections.
+    pub async fn launch_node(&self) {
+        let Some(bin) = &self.node_bin else {
+            return;
+        };
+
+        let address = self.node_address();
+        let port = address.rsplit(':').next().unwrap_or_default();
+        println!("🧪 Launching {bin} --dev on port {port}...");
+
+        let child = Command::new(bin)
+            .args(["--dev", "--rpc-port", port])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn();
+        match child {
+            Ok(child) => {
+                if let Ok(mut node) = self.node.lock() {
+                    *node = Some(child);
+                }
+            }
+            Err(e) => {
+                warn!("Failed to launch {bin}: {e}");
+                return;
+            }
+        }
+
+        for _ in 0..NODE_POLL_ATTEMPTS {
+            if TcpStream::connect(&address).await.is_ok() {
+                return;
+            }
+            tokio::time::sleep(NODE_POLL_INTERVAL).await;
+        }
+        warn!("The local node is not reachable at {}", self.node_url);
+    }
+
+    /// Stop the node launched by the instance, if any.
+    pub fn stop_node(&self) {
+        let child = self.node.lock().ok().and_then(|mut node| node.take());
+        if let Some(mut child) = child {
+            let _ = child.start_kill();
+        }
+    }
+
+    /// Deploy the demo contract, register it as a demo project and emit test events.
+    pub async fn run(&self, triggr: Triggr) {
+        let contract_addr = match &self.contract {
+            Some(addr) => addr.clone(),
+            None => match self.deploy().await {
+                Ok(addr) => addr,
+                Err(e) => {
+                    warn!("Failed to deploy the demo contract: {e}");
+                    return;
+                }
+            },
+        };
+
+        match bootstrap::provision(&triggr, &self.owner, &contract_addr).await {
+            Ok(bootstrap) => {
+                println!(
+                    "🧪 Demo contract {contract_addr} is watched by project {}",
+                    bootstrap.project.id
+                );
+                if let Some(secret) = bootstrap.secret {
+                    println!("🔑 API key (shown once): {secret}");
+                }
+            }
+            Err(e) => {
+                warn!("Failed to register the demo contract: {e}");
+                return;
+            }
+        }
+
+        if self.event_interval.is_zero() {
+            return;
+        }
+
+        // Emit `ValueChanged` events through the contract
+        let mut interval = tokio::time::interval(self.event_interval);
+        for tick in 1u64.. {
+            interval.tick().await;
+
+            let value = rand::rng().random_range(1..=MAX_TEST_VALUE);
+            let message = format!("\"dev-tick-{tick}\"");
+            let number = value.to_string();
+            let args = [
+                "--contract",
+                contract_addr.as_str(),
+                "--message",
+                "emit_custom",
+                "--args",
+                message.as_str(),
+                number.as_str(),
+            ];
+            match self.cargo_contract("call", &args).await {
+                Ok(_) => info!("🧪 Emitted test event {tick} (value {value})"),
+                Err(e) => warn!("Failed to emit test event: {e}"),
+            }
+        }
+    }
+
+    /// Build and instantiate the demo contract. Returns its address.
+    async fn deploy(&self) -> Result<String, String> {
+        println!("🧪 Building the demo contract...");
+        self.cargo_contract("build", &["--release"]).await?;
+
+        println!("🧪 Deploying the demo contract to {}...", self.node_url);
+        let output = self
+            .cargo_contract("instantiate", &["--constructor", "new", "--output-json"])
+            .await?;
+
+        let result: Value = serde_json::from_str(&output)
+            .map_err(|e| format!("Unexpected output of cargo contract: {e}"))?;
+        result
+            .get("contract")
+            .and_then(Value::as_str)
+            .map(str::to_lowercase)
+            .ok_or_else(|| "cargo contract did not return the contract address".to_string())
+    }
+
+    /// Run a `cargo contract` command on the demo contract and return its output.
+    async fn cargo_contract(&self, command: &str, args: &[&str]) -> Result<String, String> {
+        let mut cmd = Command::new("cargo");
+        cmd.args(["contract", command, "--manifest-path"])
+            .arg(demo_manifest())
+            .args(args);
+        // Transactions are signed by the dev account and sent to the local node
+        if command != "build" {
+            cmd.args(["--suri", &self.suri, "--url", &self.node_url])
+                .args(["--execute", "--skip-confirm"]);
+        }
+
+        let output = cmd
+            .output()
+            .await
+            .map_err(|e| format!("Failed to run cargo contract (is it installed?): {e}"))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Return the `host:port` of the node url.
+    fn node_address(&self) -> String {
+        let address = self
+            .node_url
+            .split_once("://")
+            .map_or(self.node_url.as_str(), |(_, rest)| rest);
+        let address = address.split('/').next().unwrap_or_default();
+
+        match address.contains(':') {
+            true => address.to_string(),
+            false => format!("{address}:9944"),
+        }
+    }
+}
+
+/// Manifest of the bundled EventDemo contract.
+fn demo_manifest() -> PathBuf {
+    std::env::var("TRIGGR_DEV_CONTRACT_MANIFEST")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../examples/demo/contract/Cargo.toml")
+        })
+}
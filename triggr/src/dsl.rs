@@ -2,11 +2,24 @@
 
 // THis module contains code to parse and serialize triggers from the front end.
 
-use serde::{Deserialize, Serialize};
+use bigdecimal::BigDecimal;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
+use utoipa::ToSchema;
 
-use crate::{chain::polkadot::prelude::EventData, util::generate_uuid};
+use crate::{
+    chain::polkadot::{
+        metadata::{ContractMetadata, EventSpec, ValueKind},
+        prelude::EventData,
+    },
+    expr::{Comparison, Expr},
+    prelude::Trigger,
+    syntax::{self, Arm, Branch, ParseError, Stmt},
+    util::{resolve_path, to_decimal, values_equal},
+    watchlist,
+};
 /// Dsl Event Definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EventDefinition {
@@ -17,16 +30,78 @@ pub struct EventDefinition {
 /// Dsl Condition
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Condition {
-    GreaterThan(String, f64),    // field > value
-    LessThan(String, f64),       // field < value
-    Equals(String, Value),       // field == value
-    NotEquals(String, Value),    // field != value
-    GreaterOrEqual(String, f64), // field >= value
-    LessOrEqual(String, f64),    // field <= value
+    GreaterThan(String, BigDecimal),    // field > value
+    LessThan(String, BigDecimal),       // field < value
+    Equals(String, Value),              // field == value
+    NotEquals(String, Value),           // field != value
+    GreaterOrEqual(String, BigDecimal), // field >= value
+    LessOrEqual(String, BigDecimal),    // field <= value
+    Anomaly(String, f64),               // anomaly(field, score)
+    NotAnomaly(String, f64),            // !anomaly(field, score)
+    InWatchlist(String, String),        // field in watchlist("name")
+    NotInWatchlist(String, String),     // field not in watchlist("name")
+    Contains(String, String),           // field contains "text"
+    NotContains(String, String),        // !(field contains "text")
+    StartsWith(String, String),         // field starts_with "text"
+    NotStartsWith(String, String),      // !(field starts_with "text")
+    EndsWith(String, String),           // field ends_with "text"
+    NotEndsWith(String, String),        // !(field ends_with "text")
+    Matches(String, Pattern),           // field ~ "pattern"
+    NotMatches(String, Pattern),        // field !~ "pattern"
+    In(String, Vec<Value>),             // field in ["a", "b"]
+    NotIn(String, Vec<Value>),          // field not in ["a", "b"]
+    Compare(Expr, Comparison, Expr),    // amount / 1000 > fee * 2
     And(Box<Condition>, Box<Condition>),
     Or(Box<Condition>, Box<Condition>),
 }
 
+/// Max size of a compiled pattern (bytes), so a condition can't take up the memory of the
+/// instance. Matching takes linear time whatever the pattern.
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+/// Regular expression of a match condition.
+/// It is compiled when the trigger is parsed (and when it is loaded), and stored as written.
+#[derive(Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        RegexBuilder::new(pattern)
+            .size_limit(PATTERN_SIZE_LIMIT)
+            .build()
+            .map(Self)
+            .map_err(|e| format!("Invalid pattern '{pattern}': {e}"))
+    }
+
+    /// Pattern as written in the DSL.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Pattern").field(&self.as_str()).finish()
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Self::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
 /// Dsl Action
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Action {
@@ -34,6 +109,9 @@ pub enum Action {
         collection: String,
         id: String,
         fields: HashMap<String, Value>,
+        /// Merge the fields into the stored document (RFC 7386) instead of replacing its data
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        merge: bool,
     },
     Delete {
         collection: String,
@@ -47,6 +125,33 @@ pub enum Action {
     Notify {
         message: String,
     },
+    /// Push values onto array fields of a document, keeping the last `max` items of each
+    Append {
+        collection: String,
+        id: String,
+        fields: HashMap<String, Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<usize>,
+    },
+    /// Set a value of the project's key-value namespace
+    SetKv {
+        key: String,
+        value: Value,
+    },
+}
+
+impl Action {
+    /// Name of the action, as written in the DSL.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Action::Update { .. } => "update",
+            Action::Delete { .. } => "delete",
+            Action::Insert { .. } => "insert",
+            Action::Notify { .. } => "notify",
+            Action::Append { .. } => "append",
+            Action::SetKv { .. } => "set",
+        }
+    }
 }
 
 /// Dsl Rule
@@ -71,224 +176,140 @@ impl DslParser {
     /// Parse complete Dsl script from frontend format
     ///
     /// Example input:
-    /// ```
+    /// ```text
     /// const events = [
-    ///     transferred { amount },
-    ///     moneyWithdrawn { amount, recipient }
+    ///     Transferred { amount },
+    ///     MoneyWithdrawn { amount, recipient }
     /// ]
     ///
-    /// fn main(event) {
-    ///     if (event.transferred.amount > 200000) {
-    ///         update @id with { status: "flagged" }
+    /// fn main(events) {
+    ///     if events.Transferred.amount > 200000 {
+    ///         update @transfers:latest with { status: "flagged" }
     ///     } else {
-    ///         delete @id
+    ///         delete @transfers:latest
     ///     }
     /// }
     /// ```
-    pub fn parse_script(input: &str) -> Result<Script, String> {
-        let events = Self::parse_events(input)?;
-        let rules = Self::parse_main_function(input, &events)?;
+    pub fn parse_script(input: &str) -> Result<Script, ParseError> {
+        let program = syntax::parse_script(input)?;
+
+        let mut lowering = Lowering {
+            input,
+            events: &program.events,
+            rules: Vec::new(),
+        };
+        let scope = Scope {
+            events: program.events.iter().map(|e| e.name.as_str()).collect(),
+            condition: None,
+        };
+        lowering.block(&program.main, &scope)?;
+        let rules = lowering.rules;
 
-        Ok(Script { events, rules })
+        Ok(Script {
+            events: program.events,
+            rules,
+        })
     }
 
-    /// Parse the fn main(events) { ... } block
-    pub fn parse_main_function(
-        input: &str,
+    /// Strip the `events.<Event>.` prefix of the fields of a trigger condition.
+    /// Returns the event the condition is on, as a condition can't read several events.
+    fn scope_condition(
+        condition: &mut Condition,
         events: &[EventDefinition],
-    ) -> Result<Vec<Rule>, String> {
-        let mut rules = Vec::new();
-
-        // Find fn main block
-        let fn_start = input.find("fn main").ok_or("No fn main found")?;
-        let fn_section = &input[fn_start..];
-        let block_start = fn_section.find('{').ok_or("No opening brace for fn main")?;
-        let block_end = Self::find_matching_brace(fn_section, block_start)?;
-        let block_content = &fn_section[block_start + 1..block_end];
-
-        // Check if there's an if statement
-        let trimmed = block_content.trim();
-        if trimmed.contains("if ") {
-            // Parse if/else statements
-            rules.extend(Self::parse_if_else_blocks(block_content, events)?);
-        } else {
-            // No condition - parse actions directly for all events
-            let actions = Self::parse_action_block(block_content)?;
-
-            // Create a rule for each defined event with no condition
-            for event in events {
-                rules.push(Rule {
-                    event_name: event.name.clone(),
-                    condition: None,
-                    actions: actions.clone(),
-                });
-            }
-        }
-
-        Ok(rules)
+    ) -> Result<String, String> {
+        let mut event_name = None;
+        Self::scope_fields(condition, events, &mut event_name)?;
+        event_name.ok_or_else(|| {
+            "Condition must read a field of an event, as events.<Event>.<field>".to_string()
+        })
     }
 
-    /// Parse if/else blocks into rules
-    fn parse_if_else_blocks(input: &str, events: &[EventDefinition]) -> Result<Vec<Rule>, String> {
-        let mut rules = Vec::new();
-        let trimmed = input.trim();
-
-        // Find if statement
-        if let Some(if_pos) = trimmed.find("if ") {
-            let rest = &trimmed[if_pos + 3..];
-
-            // Extract condition (between if and {)
-            let condition_end = rest.find('{').ok_or("No opening brace for if block")?;
-            let condition_str = rest[..condition_end].trim();
-
-            // Parse condition
-            let condition = Self::parse_event_condition(condition_str, events)?;
-
-            // Extract if block
-            let if_block_start = condition_end;
-            let if_block_end = Self::find_matching_brace(rest, if_block_start)?;
-            let if_block_content = &rest[if_block_start + 1..if_block_end];
-
-            // Parse actions in if block
-            let if_actions = Self::parse_action_block(if_block_content)?;
-
-            // Create rule for if condition
-            if let Some((ref event_name, ref cond)) = condition {
-                rules.push(Rule {
-                    event_name: event_name.clone(),
-                    condition: Some(cond.clone()),
-                    actions: if_actions,
-                });
-            }
-
-            // Check for else block
-            let after_if = &rest[if_block_end + 1..].trim();
-            if after_if.starts_with("else ") || after_if.starts_with("else{") {
-                let else_start = after_if
-                    .find('{')
-                    .ok_or("No opening brace for else block")?;
-                let else_block_end = Self::find_matching_brace(after_if, else_start)?;
-                let else_block_content = &after_if[else_start + 1..else_block_end];
-
-                // Parse actions in else block
-                let else_actions = Self::parse_action_block(else_block_content)?;
-
-                // Create rule for else (negated condition)
-                if let Some((event_name, cond)) = condition {
-                    let negated_condition = Self::negate_condition(cond);
-                    rules.push(Rule {
-                        event_name,
-                        condition: Some(negated_condition),
-                        actions: else_actions,
-                    });
+    fn scope_fields(
+        condition: &mut Condition,
+        events: &[EventDefinition],
+        event_name: &mut Option<String>,
+    ) -> Result<(), String> {
+        match condition {
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                Self::scope_fields(left, events, event_name)?;
+                Self::scope_fields(right, events, event_name)
+            }
+            Condition::Compare(left, _, right) => {
+                // Expressions may read several fields of the event
+                let fields: Vec<String> = left
+                    .fields()
+                    .into_iter()
+                    .chain(right.fields())
+                    .map(String::from)
+                    .collect();
+                for mut field in fields {
+                    Self::scope_field(&mut field, events, event_name)?;
+                }
+                match event_name {
+                    Some(name) => {
+                        left.scope(name)?;
+                        right.scope(name)
+                    }
+                    None => Ok(()),
                 }
             }
+            Condition::GreaterThan(field, _)
+            | Condition::LessThan(field, _)
+            | Condition::GreaterOrEqual(field, _)
+            | Condition::LessOrEqual(field, _)
+            | Condition::Equals(field, _)
+            | Condition::NotEquals(field, _)
+            | Condition::In(field, _)
+            | Condition::NotIn(field, _)
+            | Condition::Anomaly(field, _)
+            | Condition::NotAnomaly(field, _)
+            | Condition::InWatchlist(field, _)
+            | Condition::NotInWatchlist(field, _)
+            | Condition::Contains(field, _)
+            | Condition::NotContains(field, _)
+            | Condition::StartsWith(field, _)
+            | Condition::NotStartsWith(field, _)
+            | Condition::EndsWith(field, _)
+            | Condition::NotEndsWith(field, _)
+            | Condition::Matches(field, _)
+            | Condition::NotMatches(field, _) => Self::scope_field(field, events, event_name),
         }
-
-        Ok(rules)
     }
 
-    /// Parse event condition: events.eventName.field > value
-    fn parse_event_condition(
-        input: &str,
+    /// Strip the `events.<Event>.` prefix of a field, checking its event.
+    fn scope_field(
+        field: &mut String,
         events: &[EventDefinition],
-    ) -> Result<Option<(String, Condition)>, String> {
-        let input = input.trim();
-
-        // Remove parentheses if present
-        let input = if input.starts_with('(') && input.ends_with(')') {
-            &input[1..input.len() - 1]
-        } else {
-            input
+        event_name: &mut Option<String>,
+    ) -> Result<(), String> {
+        let Some((name, rest)) = field
+            .strip_prefix("events.")
+            .and_then(|rest| rest.split_once('.'))
+            .filter(|(name, rest)| !name.is_empty() && !rest.is_empty())
+            .map(|(name, rest)| (name.to_string(), rest.to_string()))
+        else {
+            return Err(format!("Invalid field '{field}', expected events.<Event>.<field>"));
         };
 
-        // Expected format: events.eventName.field > value
-        if !input.starts_with("events.") {
-            return Err("Condition must start with 'events.'".to_string());
-        }
-
-        let rest = &input[7..]; // Skip "events."
-
-        // Find the event name
-        let parts: Vec<&str> = rest.split('.').collect();
-        if parts.len() < 2 {
-            return Err("Invalid event condition format".to_string());
-        }
-
-        let event_name = parts[0];
-        let field_and_op = parts[1..].join(".");
-
-        // Verify event exists
-        if !events.iter().any(|e| e.name == event_name) {
-            return Err(format!("Unknown event: {}", event_name));
-        }
-
-        // Parse the comparison
-        let condition = Self::parse_comparison(&field_and_op)?;
-
-        Ok(Some((event_name.to_string(), condition)))
-    }
-
-    /// Parse comparison: field > value, field < value, etc.
-    fn parse_comparison(input: &str) -> Result<Condition, String> {
-        let input = input.trim();
-
-        // Handle different operators
-        if let Some(pos) = input.find(">=") {
-            let field = input[..pos].trim().to_string();
-            let value_str = input[pos + 2..].trim().replace(",", "");
-            let value: f64 = value_str.parse().map_err(|_| "Invalid number")?;
-            return Ok(Condition::GreaterOrEqual(field, value));
-        }
-
-        if let Some(pos) = input.find("<=") {
-            let field = input[..pos].trim().to_string();
-            let value_str = input[pos + 2..].trim().replace(",", "");
-            let value: f64 = value_str.parse().map_err(|_| "Invalid number")?;
-            return Ok(Condition::LessOrEqual(field, value));
-        }
-
-        if let Some(pos) = input.find('>') {
-            let field = input[..pos].trim().to_string();
-            let value_str = input[pos + 1..].trim().replace(",", "");
-            let value: f64 = value_str.parse().map_err(|_| "Invalid number")?;
-            return Ok(Condition::GreaterThan(field, value));
-        }
-
-        if let Some(pos) = input.find('<') {
-            let field = input[..pos].trim().to_string();
-            let value_str = input[pos + 1..].trim().replace(",", "");
-            let value: f64 = value_str.parse().map_err(|_| "Invalid number")?;
-            return Ok(Condition::LessThan(field, value));
-        }
-
-        if let Some(pos) = input.find("==") {
-            let field = input[..pos].trim().to_string();
-            let value_str = input[pos + 2..].trim();
-            let value = if value_str.starts_with('"') {
-                Value::String(value_str.trim_matches('"').to_string())
-            } else {
-                Value::Number(value_str.parse().map_err(|_| "Invalid value")?)
-            };
-            return Ok(Condition::Equals(field, value));
-        }
-
-        if let Some(pos) = input.find("!=") {
-            let field = input[..pos].trim().to_string();
-            let value_str = input[pos + 2..].trim();
-            let value = if value_str.starts_with('"') {
-                Value::String(value_str.trim_matches('"').to_string())
-            } else {
-                Value::Number(value_str.parse().map_err(|_| "Invalid value")?)
-            };
-            return Ok(Condition::NotEquals(field, value));
+        match event_name.as_deref() {
+            Some(current) if current != name => {
+                return Err(format!("A condition can't read both {current} and {name}"));
+            }
+            Some(_) => {}
+            None => {
+                if !events.iter().any(|e| e.name == name) {
+                    return Err(format!("Unknown event: {}", name));
+                }
+                *event_name = Some(name);
+            }
         }
+        *field = rest;
 
-        Err("Unable to parse comparison".to_string())
+        Ok(())
     }
 
     /// Negate a condition for else block
-    fn negate_condition(condition: Condition) -> Condition {
+    pub(crate) fn negate_condition(condition: Condition) -> Condition {
         match condition {
             Condition::GreaterThan(field, value) => Condition::LessOrEqual(field, value),
             Condition::LessThan(field, value) => Condition::GreaterOrEqual(field, value),
@@ -296,6 +317,23 @@ impl DslParser {
             Condition::LessOrEqual(field, value) => Condition::GreaterThan(field, value),
             Condition::Equals(field, value) => Condition::NotEquals(field, value),
             Condition::NotEquals(field, value) => Condition::Equals(field, value),
+            Condition::Anomaly(field, score) => Condition::NotAnomaly(field, score),
+            Condition::NotAnomaly(field, score) => Condition::Anomaly(field, score),
+            Condition::InWatchlist(field, name) => Condition::NotInWatchlist(field, name),
+            Condition::NotInWatchlist(field, name) => Condition::InWatchlist(field, name),
+            Condition::Contains(field, text) => Condition::NotContains(field, text),
+            Condition::NotContains(field, text) => Condition::Contains(field, text),
+            Condition::StartsWith(field, text) => Condition::NotStartsWith(field, text),
+            Condition::NotStartsWith(field, text) => Condition::StartsWith(field, text),
+            Condition::EndsWith(field, text) => Condition::NotEndsWith(field, text),
+            Condition::NotEndsWith(field, text) => Condition::EndsWith(field, text),
+            Condition::Matches(field, pattern) => Condition::NotMatches(field, pattern),
+            Condition::NotMatches(field, pattern) => Condition::Matches(field, pattern),
+            Condition::In(field, values) => Condition::NotIn(field, values),
+            Condition::NotIn(field, values) => Condition::In(field, values),
+            Condition::Compare(left, comparison, right) => {
+                Condition::Compare(left, comparison.negate(), right)
+            }
             Condition::And(left, right) => Condition::Or(
                 Box::new(Self::negate_condition(*left)),
                 Box::new(Self::negate_condition(*right)),
@@ -307,489 +345,1053 @@ impl DslParser {
         }
     }
 
-    /// Parse action block (multiple actions separated by newlines)
-    fn parse_action_block(input: &str) -> Result<Vec<Action>, String> {
-        let mut actions = Vec::new();
-
-        for line in input.lines() {
-            let trimmed = line.trim();
-            if trimmed.is_empty() || trimmed.starts_with("//") {
-                continue;
-            }
+    /// Parse a simple condition
+    pub fn parse_condition(input: &str) -> Result<Condition, String> {
+        syntax::parse_condition(input)
+    }
+}
 
-            // Handle ${...} syntax - convert to {...}
-            let normalized = if trimmed.contains("${") {
-                trimmed.replace("${", "{")
-            } else {
-                trimmed.to_string()
-            };
+/// Turns the statements of a script into rules.
+struct Lowering<'a> {
+    input: &'a str,
+    events: &'a [EventDefinition],
+    rules: Vec<Rule>,
+}
 
-            // Try to parse as action - with error logging
-            match Self::parse_action(&normalized) {
-                Ok(action) => actions.push(action),
-                Err(e) => {
-                    // Log but don't fail - some lines might not be actions
-                    eprintln!("Warning: Could not parse action '{}': {}", normalized, e);
+impl Lowering<'_> {
+    /// Turn the statements of a block into rules.
+    /// The actions of the block make a rule, on the condition of the block (or on each of its
+    /// events, without a condition). Nested if/else chains and matches make rules of their own.
+    fn block(&mut self, stmts: &[Stmt], scope: &Scope) -> Result<(), ParseError> {
+        let actions: Vec<Action> = stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Action(action) => Some(action.clone()),
+                _ => None,
+            })
+            .collect();
+
+        // Blocks holding nothing but other blocks have no rule of their own
+        if !actions.is_empty() || stmts.is_empty() {
+            match &scope.condition {
+                Some((event_name, condition)) => self.rules.push(Rule {
+                    event_name: event_name.clone(),
+                    condition: Some(condition.clone()),
+                    actions,
+                }),
+                None => {
+                    for event_name in &scope.events {
+                        self.rules.push(Rule {
+                            event_name: event_name.to_string(),
+                            condition: None,
+                            actions: actions.clone(),
+                        });
+                    }
                 }
             }
         }
 
-        Ok(actions)
-    }
-
-    /// Find matching closing brace
-    fn find_matching_brace(input: &str, start: usize) -> Result<usize, String> {
-        let mut depth = 0;
-        let chars: Vec<char> = input.chars().collect();
-
-        for i in start..chars.len() {
-            if chars[i] == '{' {
-                depth += 1;
-            } else if chars[i] == '}' {
-                depth -= 1;
-                if depth == 0 {
-                    return Ok(i);
-                }
+        for stmt in stmts {
+            match stmt {
+                Stmt::Action(_) => {}
+                Stmt::If {
+                    branches,
+                    otherwise,
+                } => self.if_chain(branches, otherwise.as_deref(), scope)?,
+                Stmt::Match(arms) => self.match_arms(arms, scope)?,
             }
         }
 
-        Err("No matching closing brace found".to_string())
+        Ok(())
     }
 
-    /// Parse actions from Dsl string
-    ///
-    /// Supports:
-    /// - `update @collection:id with { key: value, ... }`
-    /// - `delete @collection:id`
-    /// - `insert @collection:id with { key: value, ... }`
-    /// - `notify "message"`
-    ///
-    /// # Example
-    /// ```
-    /// let action1 = DslParser::parse_action("update @transactions:tx_123 with { status: \"flagged\" }");
-    /// let action2 = DslParser::parse_action("delete @pending:tx_456");
-    /// let action3 = DslParser::parse_action("notify \"Large transfer detected\"");
-    /// ```
-    pub fn parse_action(input: &str) -> Result<Action, String> {
-        let trimmed = input.trim();
-
-        // Parse UPDATE action
-        if trimmed.starts_with("update ") {
-            return Self::parse_update_action(trimmed);
-        }
+    /// Turn an if/else chain into rules, one per branch.
+    /// Each branch also requires the branches before it not to hold, so at most one of them runs.
+    fn if_chain(
+        &mut self,
+        branches: &[Branch],
+        otherwise: Option<&[Stmt]>,
+        scope: &Scope,
+    ) -> Result<(), ParseError> {
+        // Event of the chain, and the condition of none of its branches holding so far
+        let mut chain: Option<String> = None;
+        let mut none_held = scope.condition.as_ref().map(|(_, c)| c.clone());
+
+        for branch in branches {
+            let error = |message: String| ParseError::at(self.input, branch.at, message);
+            let mut condition = branch.condition.clone();
+            let event_name =
+                DslParser::scope_condition(&mut condition, self.events).map_err(error)?;
+            if let Some(chain) = chain.as_ref().filter(|chain| **chain != event_name) {
+                return Err(error(format!(
+                    "All branches of an else if chain must be on the event {chain}"
+                )));
+            }
+            if scope.events.len() == 1 && scope.events[0] != event_name {
+                let event = scope.events[0];
+                return Err(error(format!(
+                    "Conditions of a block on {event} must be on events.{event}"
+                )));
+            }
 
-        // Parse DELETE action
-        if trimmed.starts_with("delete ") {
-            return Self::parse_delete_action(trimmed);
-        }
+            let negated = DslParser::negate_condition(condition.clone());
+            let inner = Scope {
+                events: vec![event_name.as_str()],
+                condition: Some((event_name.clone(), both(none_held.clone(), condition))),
+            };
+            self.block(&branch.block, &inner)?;
 
-        // Parse INSERT action
-        if trimmed.starts_with("insert ") {
-            return Self::parse_insert_action(trimmed);
+            none_held = Some(both(none_held, negated));
+            chain = Some(event_name);
         }
 
-        // Parse NOTIFY action
-        if trimmed.starts_with("notify ") {
-            return Self::parse_notify_action(trimmed);
+        if let (Some(block), Some(event_name), Some(condition)) = (otherwise, chain, none_held) {
+            let inner = Scope {
+                events: vec![event_name.as_str()],
+                condition: Some((event_name.clone(), condition)),
+            };
+            self.block(block, &inner)?;
         }
 
-        Err(format!("Unknown action: {}", trimmed))
+        Ok(())
     }
 
-    /// Parse update action: update @collection:id with { key: value, ... }
-    fn parse_update_action(input: &str) -> Result<Action, String> {
-        let input = input.trim_start_matches("update ").trim();
-
-        // Handle ${...} syntax - normalize it
-        let input = input.replace("${", "{");
+    /// Turn the arms of a `match events { ... }` into rules.
+    /// Each arm handles one event; the `_` arm handles the events without an arm, and can't have
+    /// conditions.
+    fn match_arms(&mut self, arms: &[Arm], scope: &Scope) -> Result<(), ParseError> {
+        let mut handled: Vec<&str> = Vec::new();
+        for arm in arms {
+            let name = arm.event.as_str();
+            let error = |message: String| ParseError::at(self.input, arm.at, message);
+            if handled.contains(&name) {
+                return Err(error(format!("Event {name} is matched twice")));
+            }
+            handled.push(name);
+            if name == "_" {
+                continue;
+            }
+            if !self.events.iter().any(|e| e.name == name) {
+                return Err(error(format!("Unknown event: {}", name)));
+            }
+            if !scope.events.contains(&name) {
+                return Err(error(format!(
+                    "Event {name} can't be matched in a block on {}",
+                    scope.events.join(", ")
+                )));
+            }
 
-        // Extract @collection:id
-        let with_pos = input.find(" with ").ok_or("Missing 'with' keyword")?;
-        let target = input[..with_pos].trim();
-        let (collection, id) = Self::parse_target(target)?;
+            let inner = Scope {
+                events: vec![name],
+                condition: scope.condition.clone(),
+            };
+            self.block(&arm.block, &inner)?;
+        }
 
-        // Extract fields { key: value, ... }
-        let fields_str = input[with_pos + 6..].trim();
-        let fields = Self::parse_fields(fields_str)?;
+        // Events without an arm of their own
+        if let Some(arm) = arms.iter().find(|arm| arm.event == "_") {
+            if arm.block.iter().any(|stmt| !matches!(stmt, Stmt::Action(_))) {
+                return Err(ParseError::at(
+                    self.input,
+                    arm.at,
+                    "The _ arm can't have conditions",
+                ));
+            }
+            let others = Scope {
+                events: scope
+                    .events
+                    .iter()
+                    .copied()
+                    .filter(|e| !handled.contains(e))
+                    .collect(),
+                condition: scope.condition.clone(),
+            };
+            if !others.events.is_empty() {
+                self.block(&arm.block, &others)?;
+            }
+        }
 
-        Ok(Action::Update {
-            collection,
-            id,
-            fields,
-        })
+        Ok(())
     }
+}
 
-    /// Parse delete action: delete @collection:id
-    fn parse_delete_action(input: &str) -> Result<Action, String> {
-        let input = input.trim_start_matches("delete ").trim();
-        let (collection, id) = Self::parse_target(input)?;
+/// Events and condition the statements of a block run under.
+struct Scope<'a> {
+    /// Events the block handles
+    events: Vec<&'a str>,
+    /// Condition of the enclosing blocks, with the event it is on
+    condition: Option<(String, Condition)>,
+}
 
-        Ok(Action::Delete { collection, id })
+/// Require a condition on top of another, if any.
+fn both(outer: Option<Condition>, condition: Condition) -> Condition {
+    match outer {
+        Some(outer) => Condition::And(Box::new(outer), Box::new(condition)),
+        None => condition,
     }
+}
 
-    /// Parse insert action: insert @collection:id with { key: value, ... }
-    fn parse_insert_action(input: &str) -> Result<Action, String> {
-        let input = input.trim_start_matches("insert ").trim();
-
-        // Handle ${...} syntax - normalize it
-        let input = input.replace("${", "{");
-
-        // Extract @collection:id
-        let with_pos = input.find(" with ").ok_or("Missing 'with' keyword")?;
-        let target = input[..with_pos].trim();
-        let (collection, id) = Self::parse_target(target)?;
+/// Tells whether an address is on a watchlist, given the watchlist name and the address.
+pub type WatchlistLookup<'a> = &'a dyn Fn(&str, &str) -> bool;
 
-        // Extract fields
-        let fields_str = input[with_pos + 6..].trim();
-        let fields = Self::parse_fields(fields_str)?;
+/// Dsl Executor
+pub struct DslExecutor;
 
-        Ok(Action::Insert {
-            collection,
-            id,
-            fields,
-        })
+impl DslExecutor {
+    /// Evaluate a condition against event data.
+    /// No address is on any watchlist, as watchlists belong to the project of a trigger.
+    pub fn evaluate_condition(condition: &Condition, event: &EventData) -> bool {
+        Self::evaluate(condition, event, &|_, _| false, None)
     }
 
-    /// Parse notify action: notify "message"
-    fn parse_notify_action(input: &str) -> Result<Action, String> {
-        let input = input.trim_start_matches("notify ").trim();
+    /// Evaluate a condition, recording each comparison in `trace` when given.
+    pub(crate) fn evaluate(
+        condition: &Condition,
+        event: &EventData,
+        watchlists: WatchlistLookup,
+        mut trace: Option<&mut Vec<ConditionStep>>,
+    ) -> bool {
+        let (field, expected) = match condition {
+            Condition::And(left, right) => {
+                return Self::evaluate(left, event, watchlists, trace.as_deref_mut())
+                    && Self::evaluate(right, event, watchlists, trace);
+            }
+            Condition::Or(left, right) => {
+                return Self::evaluate(left, event, watchlists, trace.as_deref_mut())
+                    || Self::evaluate(right, event, watchlists, trace);
+            }
+            Condition::Compare(left, comparison, right) => {
+                return Self::evaluate_arithmetic(left, *comparison, right, event, trace);
+            }
+            Condition::GreaterThan(field, value)
+            | Condition::LessThan(field, value)
+            | Condition::GreaterOrEqual(field, value)
+            | Condition::LessOrEqual(field, value) => (field, json!(value.to_string())),
+            Condition::Equals(field, value) | Condition::NotEquals(field, value) => {
+                (field, value.clone())
+            }
+            Condition::Anomaly(field, score) | Condition::NotAnomaly(field, score) => {
+                (field, json!(score))
+            }
+            Condition::InWatchlist(field, name) | Condition::NotInWatchlist(field, name) => {
+                (field, json!(name))
+            }
+            Condition::Contains(field, text)
+            | Condition::NotContains(field, text)
+            | Condition::StartsWith(field, text)
+            | Condition::NotStartsWith(field, text)
+            | Condition::EndsWith(field, text)
+            | Condition::NotEndsWith(field, text) => (field, json!(text)),
+            Condition::Matches(field, pattern) | Condition::NotMatches(field, pattern) => {
+                (field, json!(pattern.as_str()))
+            }
+            Condition::In(field, values) | Condition::NotIn(field, values) => {
+                (field, json!(values))
+            }
+        };
 
-        // Remove quotes
-        let message = if (input.starts_with('"') && input.ends_with('"'))
-            || (input.starts_with('\'') && input.ends_with('\''))
-        {
-            input[1..input.len() - 1].to_string()
-        } else {
-            input.to_string()
+        // Anomaly conditions compare the score of the field, not its value
+        let scored = matches!(condition, Condition::Anomaly(..) | Condition::NotAnomaly(..));
+        let actual = match scored {
+            true => event.scores.get(field).map(|score| json!(score)),
+            false => resolve_path(&event.fields, field).cloned(),
+        };
+        let result = match condition {
+            Condition::InWatchlist(_, name) | Condition::NotInWatchlist(_, name) => actual
+                .as_ref()
+                .and_then(watchlist::address_of)
+                .is_some_and(|address| {
+                    watchlists(name, &address) == matches!(condition, Condition::InWatchlist(..))
+                }),
+            _ => actual.as_ref().is_some_and(|v| Self::compare(condition, v)),
         };
 
-        Ok(Action::Notify { message })
-    }
+        if let Some(trace) = trace {
+            trace.push(ConditionStep {
+                field: field.clone(),
+                operator: Self::operator(condition).to_string(),
+                note: match &actual {
+                    None if scored && event.fields.contains_key(field) => {
+                        Some(format!("Not enough values of '{field}' seen yet to score it"))
+                    }
+                    None => Some(format!("Event has no field '{field}'")),
+                    Some(v) if Self::is_ordering(condition) && to_decimal(v).is_none() => {
+                        Some("Field value is not a number".to_string())
+                    }
+                    Some(v) if Self::is_textual(condition) && Self::text_of(v).is_none() => {
+                        Some("Field value is not text".to_string())
+                    }
+                    Some(v)
+                        if matches!(
+                            condition,
+                            Condition::InWatchlist(..) | Condition::NotInWatchlist(..)
+                        ) && watchlist::address_of(v).is_none() =>
+                    {
+                        Some("Field value is not an address".to_string())
+                    }
+                    _ => None,
+                },
+                actual,
+                expected,
+                result,
+            });
+        }
 
-    /// Parse target: @collection:id or @id (shorthand) or placeholders
-    fn parse_target(input: &str) -> Result<(String, String), String> {
-        let input = input.trim();
+        result
+    }
 
-        // Remove @ prefix if present
-        let input = if input.starts_with('@') {
-            &input[1..]
-        } else {
-            input
+    /// Evaluate a comparison of arithmetic expressions, recording it in `trace` when given.
+    fn evaluate_arithmetic(
+        left: &Expr,
+        comparison: Comparison,
+        right: &Expr,
+        event: &EventData,
+        trace: Option<&mut Vec<ConditionStep>>,
+    ) -> bool {
+        let value_of = |field: &str| {
+            let value = resolve_path(&event.fields, field)
+                .ok_or_else(|| format!("Event has no field '{field}'"))?;
+            to_decimal(value).ok_or_else(|| format!("Field '{field}' is not a number"))
+        };
+        let actual = left.evaluate(&value_of);
+        let expected = right.evaluate(&value_of);
+        let result = match (&actual, &expected) {
+            (Ok(actual), Ok(expected)) => comparison.holds(actual.cmp(expected)),
+            _ => false,
         };
 
-        // Handle common placeholders
-        if input == "id" || input == "ID" {
-            return Ok(("__placeholder__".to_string(), "id".to_string()));
+        if let Some(trace) = trace {
+            trace.push(ConditionStep {
+                field: left.to_string(),
+                operator: comparison.as_str().to_string(),
+                actual: actual.as_ref().ok().map(|n| json!(n.normalized().to_string())),
+                expected: match &expected {
+                    Ok(n) => json!(n.normalized().to_string()),
+                    Err(_) => json!(right.to_string()),
+                },
+                result,
+                note: actual.err().or(expected.err()),
+            });
         }
 
-        // Check if it has a colon (collection:id format)
-        if let Some(colon_pos) = input.find(':') {
-            let collection = input[..colon_pos].trim().to_string();
-            let mut id = input[colon_pos + 1..].trim().to_string();
+        result
+    }
 
-            if collection.is_empty() {
-                return Err("Empty collection name".to_string());
+    /// Compare an event field value against a leaf condition.
+    fn compare(condition: &Condition, field_value: &Value) -> bool {
+        match condition {
+            Condition::GreaterThan(_, value) => to_decimal(field_value).is_some_and(|n| n > *value),
+            Condition::LessThan(_, value) => to_decimal(field_value).is_some_and(|n| n < *value),
+            Condition::GreaterOrEqual(_, value) => {
+                to_decimal(field_value).is_some_and(|n| n >= *value)
             }
-            if id.is_empty() {
-                // Generate random UUID
-                id = generate_uuid();
-                // return Err("Empty id".to_string());
+            Condition::LessOrEqual(_, value) => {
+                to_decimal(field_value).is_some_and(|n| n <= *value)
             }
-
-            Ok((collection, id))
-        } else {
-            // No colon - treat as shorthand
-            let id_value = input.trim().to_string();
-            if id_value.is_empty() {
-                return Err("Empty target".to_string());
+            Condition::Equals(_, value) => values_equal(field_value, value),
+            Condition::NotEquals(_, value) => !values_equal(field_value, value),
+            Condition::Anomaly(_, score) => field_value.as_f64().is_some_and(|s| s >= *score),
+            Condition::NotAnomaly(_, score) => field_value.as_f64().is_some_and(|s| s < *score),
+            Condition::Contains(_, text) => {
+                Self::text_of(field_value).is_some_and(|t| t.contains(text.as_str()))
             }
-
-            // Use placeholder for collection when not specified
-            Ok(("__placeholder__".to_string(), id_value))
+            Condition::NotContains(_, text) => {
+                Self::text_of(field_value).is_some_and(|t| !t.contains(text.as_str()))
+            }
+            Condition::StartsWith(_, text) => {
+                Self::text_of(field_value).is_some_and(|t| t.starts_with(text.as_str()))
+            }
+            Condition::NotStartsWith(_, text) => {
+                Self::text_of(field_value).is_some_and(|t| !t.starts_with(text.as_str()))
+            }
+            Condition::EndsWith(_, text) => {
+                Self::text_of(field_value).is_some_and(|t| t.ends_with(text.as_str()))
+            }
+            Condition::NotEndsWith(_, text) => {
+                Self::text_of(field_value).is_some_and(|t| !t.ends_with(text.as_str()))
+            }
+            Condition::Matches(_, pattern) => {
+                Self::text_of(field_value).is_some_and(|t| pattern.is_match(&t))
+            }
+            Condition::NotMatches(_, pattern) => {
+                Self::text_of(field_value).is_some_and(|t| !pattern.is_match(&t))
+            }
+            Condition::In(_, values) => values.iter().any(|v| values_equal(field_value, v)),
+            Condition::NotIn(_, values) => !values.iter().any(|v| values_equal(field_value, v)),
+            // Membership needs the watchlists, see `evaluate`
+            Condition::InWatchlist(..)
+            | Condition::NotInWatchlist(..)
+            | Condition::Compare(..)
+            | Condition::And(..)
+            | Condition::Or(..) => false,
         }
     }
 
-    /// Parse fields: { key: value, key: value, ... } or { k:v, k:v ... }
-    fn parse_fields(input: &str) -> Result<HashMap<String, Value>, String> {
-        let input = input.trim();
-
-        // Remove curly braces
-        let content = if input.starts_with('{') && input.ends_with('}') {
-            &input[1..input.len() - 1]
-        } else {
-            return Err("Fields must be wrapped in { }".to_string());
-        };
+    /// Whether a condition is a numeric ordering comparison.
+    fn is_ordering(condition: &Condition) -> bool {
+        matches!(
+            condition,
+            Condition::GreaterThan(..)
+                | Condition::LessThan(..)
+                | Condition::GreaterOrEqual(..)
+                | Condition::LessOrEqual(..)
+        )
+    }
 
-        let mut fields = HashMap::new();
+    /// Whether a condition is a text comparison.
+    fn is_textual(condition: &Condition) -> bool {
+        matches!(
+            condition,
+            Condition::Contains(..)
+                | Condition::NotContains(..)
+                | Condition::StartsWith(..)
+                | Condition::NotStartsWith(..)
+                | Condition::EndsWith(..)
+                | Condition::NotEndsWith(..)
+                | Condition::Matches(..)
+                | Condition::NotMatches(..)
+        )
+    }
 
-        // Handle placeholder syntax: k:v, k:v ...
-        if content.trim() == "k:v, k:v ..." || content.trim() == "..." {
-            // Return empty map - this is a placeholder
-            return Ok(fields);
+    /// Text of a field value compared by text operators: strings, and numbers as written.
+    fn text_of(value: &Value) -> Option<String> {
+        match value {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
         }
+    }
 
-        // Split by comma (simple parser - doesn't handle nested objects)
-        for pair in content.split(',') {
-            let pair = pair.trim();
-            if pair.is_empty() || pair == "..." {
-                continue;
-            }
-
-            // Split by colon
-            let colon_pos = pair.find(':').ok_or("Missing ':' in field")?;
-            let key = pair[..colon_pos].trim().to_string();
-            let value_str = pair[colon_pos + 1..].trim();
-
-            // Parse value
-            let value = Self::parse_field_value(value_str)?;
-            fields.insert(key, value);
+    /// Operator of a leaf condition, as written in the DSL.
+    fn operator(condition: &Condition) -> &'static str {
+        match condition {
+            Condition::GreaterThan(..) => ">",
+            Condition::LessThan(..) => "<",
+            Condition::GreaterOrEqual(..) => ">=",
+            Condition::LessOrEqual(..) => "<=",
+            Condition::Equals(..) => "==",
+            Condition::NotEquals(..) => "!=",
+            Condition::Anomaly(..) => "anomaly",
+            Condition::NotAnomaly(..) => "!anomaly",
+            Condition::InWatchlist(..) => "in watchlist",
+            Condition::NotInWatchlist(..) => "not in watchlist",
+            Condition::Contains(..) => "contains",
+            Condition::NotContains(..) => "not contains",
+            Condition::StartsWith(..) => "starts_with",
+            Condition::NotStartsWith(..) => "not starts_with",
+            Condition::EndsWith(..) => "ends_with",
+            Condition::NotEndsWith(..) => "not ends_with",
+            Condition::Matches(..) => "~",
+            Condition::NotMatches(..) => "!~",
+            Condition::In(..) => "in",
+            Condition::NotIn(..) => "not in",
+            Condition::Compare(_, comparison, _) => comparison.as_str(),
+            Condition::And(..) => "&&",
+            Condition::Or(..) => "||",
         }
-
-        Ok(fields)
     }
 
-    /// Parse a single field value
-    fn parse_field_value(input: &str) -> Result<Value, String> {
-        let trimmed = input.trim();
+    /// Execute a rule against event data
+    pub fn execute_rule(
+        rule: &Rule,
+        event: &EventData,
+        watchlists: WatchlistLookup,
+    ) -> Option<Vec<Action>> {
+        // Check if event name matches
+        if rule.event_name.to_lowercase() != event.event_name.to_lowercase() {
+            return None;
+        }
 
-        // String values
-        if (trimmed.starts_with('"') && trimmed.ends_with('"'))
-            || (trimmed.starts_with('\'') && trimmed.ends_with('\''))
+        // Evaluate condition if present
+        if rule
+            .condition
+            .as_ref()
+            .is_some_and(|condition| !Self::evaluate(condition, event, watchlists, None))
         {
-            let unquoted = &trimmed[1..trimmed.len() - 1];
-            return Ok(json!(unquoted));
+            return None;
         }
 
-        // Boolean values
-        if trimmed == "true" {
-            return Ok(json!(true));
-        }
-        if trimmed == "false" {
-            return Ok(json!(false));
-        }
+        // Return actions to execute
+        Some(rule.actions.clone())
+    }
 
-        // Null
-        if trimmed == "null" {
-            return Ok(Value::Null);
+    /// Evaluate every rule of a trigger against an event, recording why each did or didn't fire.
+    pub fn explain(
+        trigger: &Trigger,
+        event: &EventData,
+        watchlists: WatchlistLookup,
+    ) -> Explanation {
+        let rules: Vec<RuleTrace> = trigger
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                let event_matched = rule.event_name.eq_ignore_ascii_case(&event.event_name);
+                let mut steps = Vec::new();
+
+                let fired = event_matched
+                    && rule
+                        .condition
+                        .as_ref()
+                        .is_none_or(|c| Self::evaluate(c, event, watchlists, Some(&mut steps)));
+
+                RuleTrace {
+                    index,
+                    event_name: rule.event_name.clone(),
+                    event_matched,
+                    steps,
+                    fired,
+                    actions: if fired { rule.actions.clone() } else { vec![] },
+                }
+            })
+            .collect();
+
+        Explanation {
+            trigger_id: trigger.id.clone(),
+            active: trigger.active,
+            fired: trigger.active && rules.iter().any(|r| r.fired),
+            rules,
         }
+    }
+}
 
-        // Numbers
-        if let Ok(num) = trimmed.parse::<i64>() {
-            return Ok(json!(num));
-        }
-        if let Ok(num) = trimmed.parse::<f64>() {
-            return Ok(json!(num));
-        }
+/// A comparison evaluated while explaining a trigger.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConditionStep {
+    /// Event field compared
+    pub field: String,
+    /// Comparison operator
+    pub operator: String,
+    /// Value of the field in the event (None if missing), or its score for anomaly conditions
+    pub actual: Option<Value>,
+    /// Value it was compared with
+    pub expected: Value,
+    pub result: bool,
+    /// Why the comparison failed, when not obvious
+    pub note: Option<String>,
+}
+
+/// Evaluation trace of a single rule.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RuleTrace {
+    /// Position of the rule in the trigger
+    pub index: usize,
+    /// Event the rule responds to
+    pub event_name: String,
+    /// Whether the event name matched
+    pub event_matched: bool,
+    /// Comparisons evaluated, in order (short-circuited ones are omitted)
+    pub steps: Vec<ConditionStep>,
+    /// Whether the rule fired
+    pub fired: bool,
+    /// Actions selected
+    #[schema(value_type = Vec<Object>)]
+    pub actions: Vec<Action>,
+}
+
+/// Step-by-step explanation of whether a trigger fires for an event.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Explanation {
+    pub trigger_id: String,
+    /// Inactive triggers never fire, whatever their rules say
+    pub active: bool,
+    /// Whether any action would run
+    pub fired: bool,
+    pub rules: Vec<RuleTrace>,
+}
+
+/// Advisory raised while analyzing a trigger before it is saved.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Advisory {
+    /// Kind of advisory (`overlap`, `contradiction`)
+    pub kind: String,
+    /// Existing trigger involved
+    pub trigger_id: String,
+    /// Event both triggers respond to
+    pub event_name: String,
+    /// Human readable explanation
+    pub message: String,
+}
+
+/// Dsl Analyzer
+pub struct DslAnalyzer;
+
+impl DslAnalyzer {
+    /// Compare the rules of a trigger about to be saved with the existing triggers of the same
+    /// contract, and report triggers that write to the same document on the same event.
+    pub fn detect_conflicts(trigger_id: &str, rules: &[Rule], existing: &[Trigger]) -> Vec<Advisory> {
+        let mut advisories = Vec::new();
+
+        for other in existing.iter().filter(|t| t.id != trigger_id) {
+            for rule in rules {
+                for other_rule in other
+                    .rules
+                    .iter()
+                    .filter(|r| r.event_name.eq_ignore_ascii_case(&rule.event_name))
+                {
+                    // Rules that can never fire together can't conflict
+                    if !Self::may_overlap(rule.condition.as_ref(), other_rule.condition.as_ref()) {
+                        continue;
+                    }
 
-        // Try parsing as JSON
-        if let Ok(val) = serde_json::from_str(trimmed) {
-            return Ok(val);
+                    for action in &rule.actions {
+                        for other_action in &other_rule.actions {
+                            if let Some((kind, message)) = Self::compare_actions(action, other_action)
+                            {
+                                advisories.push(Advisory {
+                                    kind: kind.to_string(),
+                                    trigger_id: other.id.clone(),
+                                    event_name: rule.event_name.clone(),
+                                    message,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
         }
 
-        // Default: treat as string
-        Ok(json!(trimmed))
+        advisories
     }
 
-    /// Parse event definitions from Dsl string
-    ///
-    /// Example:
-    /// ```
-    /// const events = [
-    ///     transferred { amount },
-    ///     moneyWithdrawn { amount, recipient },
-    ///     loanGiven { amount }
-    /// ]
-    /// ```
-    pub fn parse_events(input: &str) -> Result<Vec<EventDefinition>, String> {
-        let mut events = Vec::new();
-
-        // Find events section
-        let events_start = input
-            .find("const events")
-            .ok_or("No events definition found")?;
-        let events_section = &input[events_start..];
-        let bracket_start = events_section.find('[').ok_or("No opening bracket")?;
-        let bracket_end = events_section.find(']').ok_or("No closing bracket")?;
-        let events_content = &events_section[bracket_start + 1..bracket_end];
-
-        // Parse individual events
-        for line in events_content.lines() {
-            let trimmed = line.trim();
-            if trimmed.is_empty() || trimmed.starts_with("//") {
-                continue;
+    /// Compare two actions fired by the same event.
+    fn compare_actions(action: &Action, other: &Action) -> Option<(&'static str, String)> {
+        let target = Self::write_target(action)?;
+        if Self::write_target(other)? != target {
+            return None;
+        }
+        let (collection, id) = target;
+        let doc = format!("{collection}:{id}");
+
+        match (action, other) {
+            (Action::Delete { .. }, Action::Delete { .. }) => Some((
+                "overlap",
+                format!("Both triggers delete {doc}"),
+            )),
+            (Action::Delete { .. }, _) | (_, Action::Delete { .. }) => Some((
+                "contradiction",
+                format!("One trigger deletes {doc} while the other writes it; the outcome depends on execution order"),
+            )),
+            (
+                Action::Update { fields, .. } | Action::Insert { fields, .. },
+                Action::Update { fields: other_fields, .. } | Action::Insert { fields: other_fields, .. },
+            ) => {
+                // Same field set to different values
+                let mut clashing: Vec<&String> = fields
+                    .iter()
+                    .filter(|(k, v)| other_fields.get(*k).is_some_and(|o| !values_equal(o, v)))
+                    .map(|(k, _)| k)
+                    .collect();
+                clashing.sort();
+
+                if clashing.is_empty() {
+                    Some(("overlap", format!("Both triggers write {doc}")))
+                } else {
+                    Some((
+                        "contradiction",
+                        format!(
+                            "Both triggers write {doc} with different values for {:?}; the last one to run wins",
+                            clashing
+                        ),
+                    ))
+                }
             }
+            _ => None,
+        }
+    }
 
-            // Remove trailing comma
-            let line_clean = trimmed.trim_end_matches(',');
-
-            // Parse: eventName { field1, field2, ... }
-            if let Some(brace_pos) = line_clean.find('{') {
-                let name = line_clean[..brace_pos].trim().to_string();
-                let fields_str = &line_clean[brace_pos + 1..];
-                let fields_end = fields_str.find('}').ok_or("No closing brace")?;
-                let fields_content = &fields_str[..fields_end];
+    /// Return the document an action writes to, if any.
+    fn write_target(action: &Action) -> Option<(&str, &str)> {
+        match action {
+            Action::Update { collection, id, .. }
+            | Action::Insert { collection, id, .. }
+            | Action::Append { collection, id, .. }
+            | Action::Delete { collection, id } => Some((collection, id)),
+            Action::Notify { .. } | Action::SetKv { .. } => None,
+        }
+    }
 
-                let fields: Vec<String> = fields_content
-                    .split(',')
-                    .map(|f| f.trim().to_string())
-                    .filter(|f| !f.is_empty())
-                    .collect();
+    /// Whether two rule conditions can be true for the same event.
+    /// Only simple comparisons on the same field are proven disjoint; anything else may overlap.
+    fn may_overlap(left: Option<&Condition>, right: Option<&Condition>) -> bool {
+        let (Some(left), Some(right)) = (left, right) else {
+            return true;
+        };
 
-                events.push(EventDefinition { name, fields });
+        match (left, right) {
+            (Condition::And(a, b), other) | (other, Condition::And(a, b)) => {
+                Self::may_overlap(Some(a), Some(other)) && Self::may_overlap(Some(b), Some(other))
+            }
+            (Condition::Or(a, b), other) | (other, Condition::Or(a, b)) => {
+                Self::may_overlap(Some(a), Some(other)) || Self::may_overlap(Some(b), Some(other))
+            }
+            (Condition::Equals(f1, v1), Condition::Equals(f2, v2)) if f1 == f2 => {
+                values_equal(v1, v2)
+            }
+            (Condition::Equals(f1, v1), Condition::NotEquals(f2, v2))
+            | (Condition::NotEquals(f2, v2), Condition::Equals(f1, v1))
+                if f1 == f2 =>
+            {
+                !values_equal(v1, v2)
+            }
+            (Condition::Anomaly(f1, s1), Condition::NotAnomaly(f2, s2))
+            | (Condition::NotAnomaly(f2, s2), Condition::Anomaly(f1, s1))
+                if f1 == f2 =>
+            {
+                s1 < s2
+            }
+            (Condition::InWatchlist(f1, w1), Condition::NotInWatchlist(f2, w2))
+            | (Condition::NotInWatchlist(f2, w2), Condition::InWatchlist(f1, w1))
+                if f1 == f2 =>
+            {
+                w1 != w2
+            }
+            // A text containing (or starting, ending with) `t1` also does so with any part of `t1`
+            (Condition::Contains(f1, t1), Condition::NotContains(f2, t2))
+            | (Condition::NotContains(f2, t2), Condition::Contains(f1, t1))
+                if f1 == f2 =>
+            {
+                !t1.contains(t2.as_str())
+            }
+            (Condition::StartsWith(f1, t1), Condition::NotStartsWith(f2, t2))
+            | (Condition::NotStartsWith(f2, t2), Condition::StartsWith(f1, t1))
+                if f1 == f2 =>
+            {
+                !t1.starts_with(t2.as_str())
+            }
+            (Condition::EndsWith(f1, t1), Condition::NotEndsWith(f2, t2))
+            | (Condition::NotEndsWith(f2, t2), Condition::EndsWith(f1, t1))
+                if f1 == f2 =>
+            {
+                !t1.ends_with(t2.as_str())
+            }
+            (Condition::Matches(f1, p1), Condition::NotMatches(f2, p2))
+            | (Condition::NotMatches(f2, p2), Condition::Matches(f1, p1))
+                if f1 == f2 =>
+            {
+                p1.as_str() != p2.as_str()
+            }
+            (Condition::In(f1, v1), Condition::In(f2, v2)) if f1 == f2 => {
+                v1.iter().any(|a| v2.iter().any(|b| values_equal(a, b)))
+            }
+            // Disjoint unless every value of the set is excluded
+            (Condition::In(f1, v1), Condition::NotIn(f2, v2))
+            | (Condition::NotIn(f2, v2), Condition::In(f1, v1))
+                if f1 == f2 =>
+            {
+                v1.iter().any(|a| !v2.iter().any(|b| values_equal(a, b)))
+            }
+            (Condition::Equals(f1, v), Condition::In(f2, values))
+            | (Condition::In(f2, values), Condition::Equals(f1, v))
+                if f1 == f2 =>
+            {
+                values.iter().any(|b| values_equal(v, b))
+            }
+            (Condition::StartsWith(f1, t1), Condition::StartsWith(f2, t2)) if f1 == f2 => {
+                t1.starts_with(t2.as_str()) || t2.starts_with(t1.as_str())
             }
+            (Condition::EndsWith(f1, t1), Condition::EndsWith(f2, t2)) if f1 == f2 => {
+                t1.ends_with(t2.as_str()) || t2.ends_with(t1.as_str())
+            }
+            _ => match (Self::range(left), Self::range(right)) {
+                (Some((f1, lo1, hi1)), Some((f2, lo2, hi2))) if f1 == f2 => {
+                    Self::bounds_meet(lo1, hi2) && Self::bounds_meet(lo2, hi1)
+                }
+                _ => true,
+            },
         }
+    }
 
-        Ok(events)
+    /// Express a numeric comparison as a range: (field, lower bound, upper bound).
+    /// Bounds carry whether they are inclusive.
+    #[allow(clippy::type_complexity)]
+    fn range(
+        condition: &Condition,
+    ) -> Option<(&str, Option<(&BigDecimal, bool)>, Option<(&BigDecimal, bool)>)> {
+        match condition {
+            Condition::GreaterThan(f, v) => Some((f, Some((v, false)), None)),
+            Condition::GreaterOrEqual(f, v) => Some((f, Some((v, true)), None)),
+            Condition::LessThan(f, v) => Some((f, None, Some((v, false)))),
+            Condition::LessOrEqual(f, v) => Some((f, None, Some((v, true)))),
+            _ => None,
+        }
     }
 
-    /// Parse a simple condition
-    pub fn parse_condition(input: &str) -> Result<Condition, String> {
-        let input = input.trim();
-
-        // Handle AND/OR
-        if let Some(and_pos) = input.find(" && ") {
-            let left = Self::parse_condition(&input[..and_pos])?;
-            let right = Self::parse_condition(&input[and_pos + 4..])?;
-            return Ok(Condition::And(Box::new(left), Box::new(right)));
-        }
-
-        if let Some(or_pos) = input.find(" || ") {
-            let left = Self::parse_condition(&input[..or_pos])?;
-            let right = Self::parse_condition(&input[or_pos + 4..])?;
-            return Ok(Condition::Or(Box::new(left), Box::new(right)));
-        }
-
-        // Handle comparison operators
-        if let Some(pos) = input.find(">=") {
-            let field = input[..pos].trim().to_string();
-            let value: f64 = input[pos + 2..]
-                .trim()
-                .replace(",", "")
-                .parse()
-                .map_err(|_| "Invalid number")?;
-            return Ok(Condition::GreaterOrEqual(field, value));
-        }
-
-        if let Some(pos) = input.find("<=") {
-            let field = input[..pos].trim().to_string();
-            let value: f64 = input[pos + 2..]
-                .trim()
-                .replace(",", "")
-                .parse()
-                .map_err(|_| "Invalid number")?;
-            return Ok(Condition::LessOrEqual(field, value));
-        }
-
-        if let Some(pos) = input.find('>') {
-            let field = input[..pos].trim().to_string();
-            let value: f64 = input[pos + 1..]
-                .trim()
-                .replace(",", "")
-                .parse()
-                .map_err(|_| "Invalid number")?;
-            return Ok(Condition::GreaterThan(field, value));
-        }
-
-        if let Some(pos) = input.find('<') {
-            let field = input[..pos].trim().to_string();
-            let value: f64 = input[pos + 1..]
-                .trim()
-                .replace(",", "")
-                .parse()
-                .map_err(|_| "Invalid number")?;
-            return Ok(Condition::LessThan(field, value));
-        }
-
-        if let Some(pos) = input.find("==") {
-            let field = input[..pos].trim().to_string();
-            let value_str = input[pos + 2..].trim();
-            let value = if value_str.starts_with('"') {
-                Value::String(value_str.trim_matches('"').to_string())
-            } else {
-                Value::Number(value_str.parse().map_err(|_| "Invalid value")?)
-            };
-            return Ok(Condition::Equals(field, value));
+    /// Whether a lower bound is below an upper bound.
+    fn bounds_meet(lower: Option<(&BigDecimal, bool)>, upper: Option<(&BigDecimal, bool)>) -> bool {
+        match (lower, upper) {
+            (Some((lo, lo_incl)), Some((hi, hi_incl))) => {
+                lo < hi || (lo == hi && lo_incl && hi_incl)
+            }
+            _ => true,
         }
+    }
+}
+
+/// Severity of a diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The trigger can never fire as written
+    Error,
+    /// The trigger may not behave as intended
+    Warning,
+}
+
+/// Problem found by checking a script against the contract metadata.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Event the problem relates to
+    pub event_name: String,
+    /// Field the problem relates to, if any
+    pub field: Option<String>,
+    pub message: String,
+}
 
-        Err("Unable to parse condition".to_string())
+impl Diagnostic {
+    fn new(severity: Severity, event_name: &str, field: Option<&str>, message: String) -> Self {
+        Self {
+            severity,
+            event_name: event_name.to_string(),
+            field: field.map(String::from),
+            message,
+        }
     }
 }
 
-/// Dsl Executor
-pub struct DslExecutor;
+impl DslAnalyzer {
+    /// Cross-check the events and fields a script refers to against the contract metadata,
+    /// catching triggers that can never fire.
+    pub fn check_against_metadata(script: &Script, metadata: &ContractMetadata) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        // Declared events and their fields
+        for event in &script.events {
+            let Some(spec) = metadata.event(&event.name) else {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    &event.name,
+                    None,
+                    format!("The contract emits no event named '{}'", event.name),
+                ));
+                continue;
+            };
 
-impl DslExecutor {
-    /// Evaluate a condition against event data
-    pub fn evaluate_condition(condition: &Condition, event: &EventData) -> bool {
-        match condition {
-            Condition::GreaterThan(field, value) => {
-                if let Some(field_value) = event.fields.get(field) {
-                    if let Some(num) = field_value.as_f64() {
-                        return num > *value;
-                    }
+            for field in &event.fields {
+                if !spec.args.iter().any(|a| &a.label == field) {
+                    diagnostics.push(Self::unknown_field(Severity::Error, spec, field));
                 }
-                false
             }
-            Condition::LessThan(field, value) => {
-                if let Some(field_value) = event.fields.get(field) {
-                    if let Some(num) = field_value.as_f64() {
-                        return num < *value;
-                    }
-                }
-                false
+        }
+
+        for rule in &script.rules {
+            let Some(spec) = metadata.event(&rule.event_name) else {
+                continue; // Already reported with the declared events
+            };
+
+            if let Some(condition) = &rule.condition {
+                Self::check_condition(condition, spec, metadata, &mut diagnostics);
             }
-            Condition::GreaterOrEqual(field, value) => {
-                if let Some(field_value) = event.fields.get(field) {
-                    if let Some(num) = field_value.as_f64() {
-                        return num >= *value;
+
+            // Placeholders in action templates
+            for action in &rule.actions {
+                for (event_name, field) in Self::action_references(action) {
+                    match metadata.event(&event_name) {
+                        None => diagnostics.push(Diagnostic::new(
+                            Severity::Warning,
+                            &event_name,
+                            None,
+                            format!("Template refers to unknown event '{event_name}'"),
+                        )),
+                        Some(spec) if !spec.args.iter().any(|a| a.label == field) => {
+                            diagnostics.push(Self::unknown_field(Severity::Warning, spec, &field))
+                        }
+                        _ => {}
                     }
                 }
-                false
             }
-            Condition::LessOrEqual(field, value) => {
-                if let Some(field_value) = event.fields.get(field) {
-                    if let Some(num) = field_value.as_f64() {
-                        return num <= *value;
-                    }
-                }
-                false
+        }
+
+        diagnostics
+    }
+
+    /// Check that a field of an arithmetic expression is a number.
+    fn check_arithmetic_field(
+        field: &str,
+        spec: &EventSpec,
+        metadata: &ContractMetadata,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        if Self::check_nested_field(field, spec, diagnostics) {
+            return;
+        }
+        let Some(arg) = spec.args.iter().find(|a| a.label == field) else {
+            diagnostics.push(Self::unknown_field(Severity::Error, spec, field));
+            return;
+        };
+
+        let kind = metadata.value_kind(arg.type_info.type_id);
+        if !matches!(kind, ValueKind::Number | ValueKind::Unknown) {
+            let type_name = arg.type_info.display_name.join("::");
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                &spec.label,
+                Some(field),
+                format!("'{field}' is a {type_name} ({kind:?}) and can't be used in arithmetic"),
+            ));
+        }
+    }
+
+    /// Check the fields and operand types of a condition.
+    fn check_condition(
+        condition: &Condition,
+        spec: &EventSpec,
+        metadata: &ContractMetadata,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let (field, ordered, values) = match condition {
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                Self::check_condition(left, spec, metadata, diagnostics);
+                Self::check_condition(right, spec, metadata, diagnostics);
+                return;
             }
-            Condition::Equals(field, value) => {
-                if let Some(field_value) = event.fields.get(field) {
-                    return field_value == value;
-                }
-                false
+            Condition::GreaterThan(f, _)
+            | Condition::LessThan(f, _)
+            | Condition::GreaterOrEqual(f, _)
+            | Condition::LessOrEqual(f, _)
+            | Condition::Anomaly(f, _)
+            | Condition::NotAnomaly(f, _) => (f, true, &[][..]),
+            Condition::Equals(f, v) | Condition::NotEquals(f, v) => {
+                (f, false, std::slice::from_ref(v))
             }
-            Condition::NotEquals(field, value) => {
-                if let Some(field_value) = event.fields.get(field) {
-                    return field_value != value;
+            Condition::InWatchlist(f, _) | Condition::NotInWatchlist(f, _) => (f, false, &[][..]),
+            Condition::Contains(f, _)
+            | Condition::NotContains(f, _)
+            | Condition::StartsWith(f, _)
+            | Condition::NotStartsWith(f, _)
+            | Condition::EndsWith(f, _)
+            | Condition::NotEndsWith(f, _) => (f, false, &[][..]),
+            Condition::Matches(f, _) | Condition::NotMatches(f, _) => (f, false, &[][..]),
+            Condition::In(f, values) | Condition::NotIn(f, values) => (f, false, &values[..]),
+            Condition::Compare(left, _, right) => {
+                for field in left.fields().into_iter().chain(right.fields()) {
+                    Self::check_arithmetic_field(field, spec, metadata, diagnostics);
                 }
-                false
-            }
-            Condition::And(left, right) => {
-                Self::evaluate_condition(left, event) && Self::evaluate_condition(right, event)
-            }
-            Condition::Or(left, right) => {
-                Self::evaluate_condition(left, event) || Self::evaluate_condition(right, event)
+                return;
             }
+        };
+
+        if Self::check_nested_field(field, spec, diagnostics) {
+            return;
+        }
+        let Some(arg) = spec.args.iter().find(|a| &a.label == field) else {
+            diagnostics.push(Self::unknown_field(Severity::Error, spec, field));
+            return;
+        };
+
+        let kind = metadata.value_kind(arg.type_info.type_id);
+        let type_name = arg.type_info.display_name.join("::");
+
+        // Ordering comparisons only make sense on numbers
+        if ordered && !matches!(kind, ValueKind::Number | ValueKind::Unknown) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                &spec.label,
+                Some(field),
+                format!("'{field}' is a {type_name} ({kind:?}) and can't be compared with <, >, <= or >="),
+            ));
+        }
+
+        // Text operators only make sense on strings (numbers are compared as written)
+        if DslExecutor::is_textual(condition)
+            && !matches!(kind, ValueKind::Text | ValueKind::Number | ValueKind::Unknown)
+        {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                &spec.label,
+                Some(field),
+                format!("'{field}' is a {type_name} ({kind:?}) and can't be compared as text"),
+            ));
+        }
+
+        // Comparing a number field with a string (or the reverse) is most likely a mistake
+        let mismatch = values.iter().find(|v| match (kind, v) {
+            (ValueKind::Number, Value::String(_)) => to_decimal(v).is_none(),
+            (ValueKind::Text, Value::Number(_)) => true,
+            _ => false,
+        });
+        if let Some(value) = mismatch {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                &spec.label,
+                Some(field),
+                format!("'{field}' is a {type_name} ({kind:?}) but is compared with {value}"),
+            ));
         }
     }
 
-    /// Execute a rule against event data
-    pub fn execute_rule(rule: &Rule, event: &EventData) -> Option<Vec<Action>> {
-        // Check if event name matches
-        if rule.event_name.to_lowercase() != event.event_name.to_lowercase() {
-            return None;
+    /// Check the field a dot-path into a nested value starts with, the type of the value being
+    /// only known at runtime. Returns false if the field is not a dot-path.
+    fn check_nested_field(
+        field: &str,
+        spec: &EventSpec,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> bool {
+        let Some((root, _)) = field.split_once('.') else {
+            return false;
+        };
+        if !spec.args.iter().any(|a| a.label == root) {
+            diagnostics.push(Self::unknown_field(Severity::Error, spec, root));
         }
+        true
+    }
 
-        // Evaluate condition if present
-        if let Some(condition) = &rule.condition {
-            if !Self::evaluate_condition(condition, event) {
-                return None;
+    /// Diagnostic for a field the event does not have.
+    fn unknown_field(severity: Severity, spec: &EventSpec, field: &str) -> Diagnostic {
+        let known: Vec<&str> = spec.args.iter().map(|a| a.label.as_str()).collect();
+        Diagnostic::new(
+            severity,
+            &spec.label,
+            Some(field),
+            format!(
+                "Event '{}' has no field '{field}' (fields: {})",
+                spec.label,
+                known.join(", ")
+            ),
+        )
+    }
+
+    /// Collect the `events.<Event>.<field>` references in the templates of an action.
+    fn action_references(action: &Action) -> Vec<(String, String)> {
+        let mut texts: Vec<String> = Vec::new();
+        match action {
+            Action::Update { fields, .. }
+            | Action::Insert { fields, .. }
+            | Action::Append { fields, .. } => {
+                texts.extend(fields.values().map(|v| v.to_string()));
             }
-        } 
+            Action::Notify { message } => texts.push(message.clone()),
+            Action::SetKv { value, .. } => texts.push(value.to_string()),
+            Action::Delete { .. } => {}
+        }
 
-        // Return actions to execute
-        Some(rule.actions.clone())
+        let mut references = Vec::new();
+        for text in &texts {
+            for (pos, _) in text.match_indices("events.") {
+                let reference: String = text[pos + 7..]
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '.')
+                    .collect();
+                if let Some((event_name, field)) = reference.split_once('.') {
+                    let field = field.split('.').next().unwrap_or_default();
+                    if !event_name.is_empty() && !field.is_empty() {
+                        references.push((event_name.to_string(), field.to_string()));
+                    }
+                }
+            }
+        }
+
+        references
     }
 }
@@ -0,0 +1,161 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module decides when writes reach the disk.
+// Each database has a durability: synchronous databases are flushed on every write, batched ones
+// are flushed in the background once enough writes are pending or the flush interval elapses.
+// Critical writes (e.g. project creation) are flushed immediately whatever the durability.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use sled::Db;
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::prelude::StorageResult;
+
+/// Default interval between background flushes (milliseconds).
+const DEFAULT_FLUSH_INTERVAL_MS: u64 = 200;
+
+/// Default number of pending writes that triggers a flush before the interval elapses.
+const DEFAULT_FLUSH_BATCH: u64 = 512;
+
+/// When the writes to a database are flushed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Flush on every write
+    Sync,
+    /// Coalesce writes and flush them in the background
+    #[default]
+    Batched,
+}
+
+impl Durability {
+    /// Read the durability of a database from `TRIGGR_DURABILITY_{DB}` (`sync` or `batched`).
+    fn from_env(db: &str) -> Self {
+        let var = format!("TRIGGR_DURABILITY_{}", db.to_uppercase());
+        match std::env::var(&var).map(|v| v.to_lowercase()).as_deref() {
+            Ok("sync") => Durability::Sync,
+            Ok("batched") | Err(_) => Durability::Batched,
+            Ok(other) => {
+                warn!("Unknown durability '{other}' in {var}, using batched");
+                Durability::Batched
+            }
+        }
+    }
+}
+
+/// Coalesces the flushes of a database.
+pub struct Flusher {
+    db: Arc<Db>,
+    durability: Durability,
+    /// Writes since the last flush
+    pending: AtomicU64,
+    /// Pending writes that trigger an early flush
+    batch: u64,
+    /// Wakes the background task when a batch is full
+    full: Notify,
+}
+
+impl Flusher {
+    fn new(name: &str, db: Arc<Db>, batch: u64) -> Arc<Self> {
+        Arc::new(Self {
+            db,
+            durability: Durability::from_env(name),
+            pending: AtomicU64::new(0),
+            batch,
+            full: Notify::new(),
+        })
+    }
+
+    /// Record a write, flushing right away when the database is synchronous.
+    pub fn written(&self) -> StorageResult<()> {
+        match self.durability {
+            Durability::Sync => self.sync(),
+            Durability::Batched => {
+                if self.pending.fetch_add(1, Ordering::Relaxed) + 1 >= self.batch {
+                    self.full.notify_one();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Flush now, whatever the durability. Used for writes that must not be lost.
+    pub fn sync(&self) -> StorageResult<()> {
+        self.pending.store(0, Ordering::Relaxed);
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Flush pending writes on every tick or full batch.
+    async fn run(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.full.notified() => {}
+            }
+
+            if self.pending.swap(0, Ordering::Relaxed) == 0 {
+                continue;
+            }
+            if let Err(e) = self.db.flush_async().await {
+                warn!("Failed to flush database: {e}");
+            }
+        }
+    }
+}
+
+/// Flush policy of the databases of a store.
+/// Until `run` is called, batched writes are only flushed by sled's own background flush.
+pub struct FlushPolicy {
+    interval: Duration,
+    pub projects: Arc<Flusher>,
+    pub users: Arc<Flusher>,
+    pub metadata: Arc<Flusher>,
+    pub triggers: Arc<Flusher>,
+}
+
+impl FlushPolicy {
+    /// Read the policy from `TRIGGR_FLUSH_INTERVAL_MS`, `TRIGGR_FLUSH_BATCH` and the durability
+    /// of each database.
+    pub fn from_env(
+        projects: &Arc<Db>,
+        users: &Arc<Db>,
+        metadata: &Arc<Db>,
+        triggers: &Arc<Db>,
+    ) -> Self {
+        let env_u64 = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(default)
+        };
+        let interval = env_u64("TRIGGR_FLUSH_INTERVAL_MS", DEFAULT_FLUSH_INTERVAL_MS);
+        let batch = env_u64("TRIGGR_FLUSH_BATCH", DEFAULT_FLUSH_BATCH);
+
+        Self {
+            interval: Duration::from_millis(interval),
+            projects: Flusher::new("projects", projects.clone(), batch),
+            users: Flusher::new("users", users.clone(), batch),
+            metadata: Flusher::new("metadata", metadata.clone(), batch),
+            triggers: Flusher::new("triggers", triggers.clone(), batch),
+        }
+    }
+
+    /// Flush the batched databases in the background.
+    pub fn run(&self) {
+        for flusher in [&self.projects, &self.users, &self.metadata, &self.triggers] {
+            if flusher.durability == Durability::Batched {
+                tokio::task::spawn(flusher.clone().run(self.interval));
+            }
+        }
+    }
+}
@@ -0,0 +1,303 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the enrichers attaching off-chain context to events before rules run.
+// Each enricher of a project reads a key from an event field (e.g. a sender address), resolves it
+// through a static map, a document of the project or an HTTP lookup, and adds the result to the
+// event as a new field. Rules, templates and actions then use it like any decoded field.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::LazyLock,
+    time::Duration,
+};
+
+use opentelemetry::{trace::SpanKind, Context, KeyValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    chain::polkadot::prelude::EventData, name::Name, prelude::DocumentStore, storage::Sled,
+    telemetry, util,
+};
+
+/// Max number of enrichers of a project.
+pub const MAX_ENRICHERS: usize = 16;
+
+/// Default timeout of HTTP lookups (milliseconds).
+const DEFAULT_HTTP_TIMEOUT_MS: u64 = 2_000;
+
+/// Max timeout of HTTP lookups (milliseconds), so a slow service can't stall the triggers.
+const MAX_HTTP_TIMEOUT_MS: u64 = 10_000;
+
+/// Placeholder of the key in lookup URLs.
+const KEY_PLACEHOLDER: &str = "{key}";
+
+/// Variable listing the hosts HTTP lookups are allowed to reach.
+const ENRICH_HOSTS: &str = "TRIGGR_ENRICH_HOSTS";
+
+/// Client shared by HTTP lookups, so connections are reused.
+/// `TRIGGR_ENRICH_HOSTS` restricts lookups to a comma separated list of hosts, otherwise
+/// they may reach any host but the internal addresses of the instance's network.
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| util::outbound_client(ENRICH_HOSTS));
+
+fn default_timeout() -> u64 {
+    DEFAULT_HTTP_TIMEOUT_MS
+}
+
+/// Adds a field to the events of a project, resolved from another field.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Enricher {
+    /// Event field the lookup key is read from
+    pub field: String,
+    /// Field the result is added as (decoded fields are never overwritten)
+    pub target: String,
+    /// Only enrich these events (every event if empty)
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Part of the result to keep, as a JSON pointer (e.g. `/customer_id`)
+    #[serde(default)]
+    pub pointer: Option<String>,
+    /// Where the result comes from
+    pub source: EnrichSource,
+}
+
+/// Where an enricher resolves keys.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EnrichSource {
+    /// A fixed table from keys to values
+    Static {
+        values: HashMap<String, Value>,
+        /// Value of the keys missing from the table (nothing is added if not set)
+        #[serde(default)]
+        default: Option<Value>,
+    },
+    /// The data of the project document whose id is the key
+    Document { collection: String },
+    /// The JSON body returned by a GET request, `{key}` in the url being replaced by the key
+    Http {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        /// Timeout of the request (milliseconds)
+        #[serde(default = "default_timeout")]
+        timeout_ms: u64,
+    },
+}
+
+/// Enrichers of a project, applied to the events of its contract.
+#[derive(Clone, Debug)]
+pub struct Enrichment {
+    pub project_id: String,
+    pub enrichers: Vec<Enricher>,
+}
+
+impl Enricher {
+    /// Check that the definition is consistent.
+    pub fn validate(&self) -> Result<(), String> {
+        Name::internal("field", &self.field).map_err(|e| e.to_string())?;
+        Name::internal("target", &self.target).map_err(|e| e.to_string())?;
+        if self.field == self.target {
+            return Err(format!("'{}' can't be enriched into itself", self.field));
+        }
+        if self.pointer.as_ref().is_some_and(|p| !p.starts_with('/')) {
+            return Err("The pointer must start with '/'".to_string());
+        }
+
+        match &self.source {
+            EnrichSource::Static { .. } => Ok(()),
+            EnrichSource::Document { collection } => Name::collection(collection)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            EnrichSource::Http {
+                url, timeout_ms, ..
+            } => {
+                if !(url.starts_with("http://") || url.starts_with("https://")) {
+                    return Err(format!("Invalid url '{url}', expected http(s)://"));
+                }
+                if !util::host_allowed(url, ENRICH_HOSTS) {
+                    return Err(format!("Lookups to the host of '{url}' are not allowed"));
+                }
+                if *timeout_ms == 0 || *timeout_ms > MAX_HTTP_TIMEOUT_MS {
+                    return Err(format!(
+                        "The timeout must be between 1 and {MAX_HTTP_TIMEOUT_MS} milliseconds"
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether the enricher applies to an event.
+    fn applies_to(&self, event_name: &str) -> bool {
+        self.events.is_empty()
+            || self
+                .events
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(event_name))
+    }
+
+    /// Resolve a key. Returns `Ok(None)` when the source has nothing for it.
+    async fn resolve(
+        &self,
+        store: &Sled,
+        project_id: &str,
+        key: &str,
+    ) -> Result<Option<Value>, String> {
+        match &self.source {
+            EnrichSource::Static { values, default } => {
+                Ok(values.get(key).or(default.as_ref()).cloned())
+            }
+            EnrichSource::Document { collection } => {
+                // A key that can't be a document id has no document
+                if Name::document_id(key).is_err() {
+                    return Ok(None);
+                }
+                let doc = DocumentStore::get(store, project_id, collection, key)
+                    .map_err(|e| e.to_string())?;
+                Ok(doc.map(|doc| doc.data))
+            }
+            EnrichSource::Http {
+                url,
+                headers,
+                timeout_ms,
+            } => {
+                // The allowed hosts may have changed since the enricher was saved
+                if !util::host_allowed(url, ENRICH_HOSTS) {
+                    return Err(format!("Lookups to the host of '{url}' are not allowed"));
+                }
+
+                let url = url.replace(KEY_PLACEHOLDER, &encode_component(key));
+                let span = telemetry::start(
+                    "enrich",
+                    SpanKind::Client,
+                    &Context::current(),
+                    vec![
+                        KeyValue::new("http.request.method", "GET"),
+                        KeyValue::new("enrich.target", self.target.clone()),
+                    ],
+                );
+
+                let mut request = CLIENT.get(&url).timeout(Duration::from_millis(*timeout_ms));
+                for (name, value) in headers {
+                    request = request.header(name.as_str(), value.as_str());
+                }
+                // The service's own spans join the trace of the event
+                if let Some(traceparent) = telemetry::traceparent(&span) {
+                    request = request.header(telemetry::TRACEPARENT, traceparent.as_str());
+                }
+
+                let result = async {
+                    let response = request.send().await.map_err(|e| e.to_string())?;
+                    telemetry::annotate(
+                        &span,
+                        vec![KeyValue::new(
+                            "http.response.status_code",
+                            response.status().as_u16() as i64,
+                        )],
+                    );
+                    if response.status() == reqwest::StatusCode::NOT_FOUND {
+                        return Ok(None);
+                    }
+
+                    let body = response
+                        .error_for_status()
+                        .map_err(|e| e.to_string())?
+                        .bytes()
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    serde_json::from_slice(&body)
+                        .map(Some)
+                        .map_err(|e| format!("Invalid JSON from {url}: {e}"))
+                }
+                .await;
+
+                if let Err(e) = &result {
+                    telemetry::fail(&span, e);
+                }
+                result
+            }
+        }
+    }
+}
+
+/// Check the enrichers of a project.
+pub fn validate(enrichers: &[Enricher]) -> Result<(), String> {
+    if enrichers.len() > MAX_ENRICHERS {
+        return Err(format!(
+            "A project can have at most {MAX_ENRICHERS} enrichers"
+        ));
+    }
+
+    let mut targets = HashSet::new();
+    for enricher in enrichers {
+        enricher.validate()?;
+        if !targets.insert(enricher.target.as_str()) {
+            return Err(format!(
+                "'{}' is the target of two enrichers",
+                enricher.target
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the enrichers of a project over an event, in order, so an enricher can read the field
+/// added by a previous one. Failed lookups are logged and add nothing.
+/// Documents are looked up in the namespace of the event.
+pub async fn apply(store: &Sled, enrichment: &Enrichment, event: &mut EventData) {
+    let data_id = event.namespace.scope(&enrichment.project_id);
+    for enricher in &enrichment.enrichers {
+        if !enricher.applies_to(&event.event_name) || event.fields.contains_key(&enricher.target) {
+            continue;
+        }
+        let Some(key) = event.fields.get(&enricher.field).and_then(key_of) else {
+            continue;
+        };
+
+        match enricher.resolve(store, &data_id, &key).await {
+            Ok(value) => {
+                let value = match &enricher.pointer {
+                    Some(pointer) => value.and_then(|v| v.pointer(pointer).cloned()),
+                    None => value,
+                };
+                if let Some(value) = value {
+                    event.fields.insert(enricher.target.clone(), value);
+                }
+            }
+            Err(e) => tracing::warn!(
+                "Failed to enrich {} of project {} with '{}': {e}",
+                event.event_name,
+                enrichment.project_id,
+                enricher.target
+            ),
+        }
+    }
+}
+
+/// Read a lookup key from a field: a string, a number, or a decoded account (`{ "value": "0x.." }`).
+fn key_of(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Object(obj) => obj.get("value").and_then(Value::as_str).map(str::to_string),
+        _ => None,
+    }
+}
+
+/// Percent-encode a key placed in a URL.
+fn encode_component(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,282 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the arithmetic expressions of the DSL.
+// Conditions can compare expressions (e.g. `events.Transfer.amount / 1000000000000 > 100`) and
+// action fields can be computed (e.g. `total: events.Transfer.amount * 2`). Expressions combine
+// numbers and event fields with `+`, `-`, `*`, `/`, `%` and parentheses, and are evaluated as exact
+// decimals, so chain amounts don't lose precision.
+
+use std::{cmp::Ordering, fmt};
+
+use bigdecimal::{BigDecimal, ToPrimitive, Zero};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    chain::polkadot::prelude::EventData,
+    syntax, template,
+    util::to_decimal,
+};
+
+/// Arithmetic operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Operator {
+    pub(crate) fn from_char(c: char) -> Option<Self> {
+        match c {
+            '+' => Some(Self::Add),
+            '-' => Some(Self::Sub),
+            '*' => Some(Self::Mul),
+            '/' => Some(Self::Div),
+            '%' => Some(Self::Rem),
+            _ => None,
+        }
+    }
+
+    fn as_char(self) -> char {
+        match self {
+            Self::Add => '+',
+            Self::Sub => '-',
+            Self::Mul => '*',
+            Self::Div => '/',
+            Self::Rem => '%',
+        }
+    }
+
+    /// Binding strength of the operator.
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Add | Self::Sub => 1,
+            Self::Mul | Self::Div | Self::Rem => 2,
+        }
+    }
+}
+
+/// Comparison of two expressions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    GreaterOrEqual,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+}
+
+impl Comparison {
+    /// Comparisons, longest operators first so `>=` is not read as `>`.
+    pub const ALL: [Self; 6] = [
+        Self::GreaterOrEqual,
+        Self::LessOrEqual,
+        Self::Equal,
+        Self::NotEqual,
+        Self::Greater,
+        Self::Less,
+    ];
+
+    /// Operator of the comparison, as written in the DSL.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::GreaterOrEqual => ">=",
+            Self::LessOrEqual => "<=",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::Greater => ">",
+            Self::Less => "<",
+        }
+    }
+
+    /// Comparison holding exactly when this one doesn't.
+    pub fn negate(self) -> Self {
+        match self {
+            Self::GreaterOrEqual => Self::Less,
+            Self::LessOrEqual => Self::Greater,
+            Self::Equal => Self::NotEqual,
+            Self::NotEqual => Self::Equal,
+            Self::Greater => Self::LessOrEqual,
+            Self::Less => Self::GreaterOrEqual,
+        }
+    }
+
+    /// Whether the comparison holds for the ordering of its two sides.
+    pub fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Self::GreaterOrEqual => ordering != Ordering::Less,
+            Self::LessOrEqual => ordering != Ordering::Greater,
+            Self::Equal => ordering == Ordering::Equal,
+            Self::NotEqual => ordering != Ordering::Equal,
+            Self::Greater => ordering == Ordering::Greater,
+            Self::Less => ordering == Ordering::Less,
+        }
+    }
+}
+
+/// Arithmetic expression.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Expr {
+    Number(BigDecimal),
+    /// Event field (or document field), possibly as `events.<Event>.<field>`
+    Field(String),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, Operator, Box<Expr>),
+}
+
+impl Expr {
+    /// Parse an expression, e.g. `amount / 1000000000000` or `(fee + tip) * 2`.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        syntax::parse_expr(input)
+    }
+
+    /// Whether the expression computes something, rather than being a number or a field.
+    pub fn is_computed(&self) -> bool {
+        matches!(self, Self::Neg(..) | Self::Binary(..))
+    }
+
+    /// Fields the expression reads.
+    pub fn fields(&self) -> Vec<&str> {
+        match self {
+            Self::Number(_) => Vec::new(),
+            Self::Field(field) => vec![field.as_str()],
+            Self::Neg(inner) => inner.fields(),
+            Self::Binary(left, _, right) => {
+                let mut fields = left.fields();
+                fields.extend(right.fields());
+                fields
+            }
+        }
+    }
+
+    /// Value of the expression if it reads no field.
+    pub fn constant(&self) -> Option<BigDecimal> {
+        self.evaluate(&|field| Err(format!("'{field}' is not a constant")))
+            .ok()
+    }
+
+    /// Strip the `events.<Event>.` prefix of the fields of an expression in a condition on an
+    /// event. Fields of other events can't be read.
+    pub fn scope(&mut self, event_name: &str) -> Result<(), String> {
+        match self {
+            Self::Number(_) => Ok(()),
+            Self::Field(field) => {
+                let Some(reference) = field.strip_prefix("events.") else {
+                    return Ok(());
+                };
+                match reference.split_once('.') {
+                    Some((name, rest)) if name == event_name && !rest.is_empty() => {
+                        *field = rest.to_string();
+                        Ok(())
+                    }
+                    _ => Err(format!(
+                        "Invalid reference '{field}', expected events.{event_name}.<field>"
+                    )),
+                }
+            }
+            Self::Neg(inner) => inner.scope(event_name),
+            Self::Binary(left, _, right) => {
+                left.scope(event_name)?;
+                right.scope(event_name)
+            }
+        }
+    }
+
+    /// Evaluate the expression, reading fields with `value_of`.
+    pub fn evaluate(
+        &self,
+        value_of: &dyn Fn(&str) -> Result<BigDecimal, String>,
+    ) -> Result<BigDecimal, String> {
+        match self {
+            Self::Number(n) => Ok(n.clone()),
+            Self::Field(field) => value_of(field),
+            Self::Neg(inner) => Ok(-inner.evaluate(value_of)?),
+            Self::Binary(left, operator, right) => {
+                let left = left.evaluate(value_of)?;
+                let right = right.evaluate(value_of)?;
+                match operator {
+                    Operator::Add => Ok(left + right),
+                    Operator::Sub => Ok(left - right),
+                    Operator::Mul => Ok(left * right),
+                    Operator::Div | Operator::Rem if right.is_zero() => {
+                        Err("Division by zero".to_string())
+                    }
+                    Operator::Div => Ok(left / right),
+                    Operator::Rem => Ok(left % right),
+                }
+            }
+        }
+    }
+
+    /// Binding strength of the expression, to know where parentheses are needed.
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Binary(_, operator, _) => operator.precedence(),
+            Self::Number(_) | Self::Field(_) | Self::Neg(_) => u8::MAX,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Field(field) => write!(f, "{field}"),
+            Self::Neg(inner) if inner.is_computed() => write!(f, "-({inner})"),
+            Self::Neg(inner) => write!(f, "-{inner}"),
+            Self::Binary(left, operator, right) => {
+                // `a - (b - c)` is not `a - b - c`, so right operands of the same strength keep
+                // their parentheses
+                match left.precedence() < operator.precedence() {
+                    true => write!(f, "({left})")?,
+                    false => write!(f, "{left}")?,
+                }
+                write!(f, " {} ", operator.as_char())?;
+                match right.precedence() <= operator.precedence() {
+                    true => write!(f, "({right})"),
+                    false => write!(f, "{right}"),
+                }
+            }
+        }
+    }
+}
+
+/// Compute a value of an action field, e.g. `events.Transfer.amount * 2`, against an event.
+/// Returns `None` if the text is not an expression on event fields.
+pub fn compute(text: &str, event: &EventData) -> Option<Result<Value, String>> {
+    if !text.contains("events.") {
+        return None;
+    }
+    let expr = Expr::parse(text).ok().filter(Expr::is_computed)?;
+
+    let value_of = |field: &str| {
+        let value = template::resolve_reference(field, event)?;
+        to_decimal(&value).ok_or_else(|| format!("'{field}' is not a number ({value})"))
+    };
+
+    Some(expr.evaluate(&value_of).map(|n| to_value(&n)))
+}
+
+/// Convert a computed decimal into a JSON value.
+/// Integers too large for JSON numbers are kept as text, so they don't lose precision.
+pub fn to_value(n: &BigDecimal) -> Value {
+    let n = n.normalized();
+    if n.is_integer() {
+        if let Some(i) = n.to_i64() {
+            return json!(i);
+        }
+        if let Some(u) = n.to_u64() {
+            return json!(u);
+        }
+        return json!(n.with_scale(0).to_string());
+    }
+
+    n.to_f64()
+        .and_then(serde_json::Number::from_f64)
+        .map(Value::Number)
+        .unwrap_or_else(|| json!(n.to_string()))
+}
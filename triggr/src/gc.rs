@@ -0,0 +1,239 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module finds data left behind by deleted projects.
+// Instances that deleted projects before deletion cascaded may still hold the documents and run
+// logs of those projects, triggers that reference them and metadata entries whose contract file is
+// gone. A periodic task reports this debris, and removes it when cleaning is enabled.
+
+use std::{collections::HashSet, path::Path, time::Duration};
+
+use serde::Serialize;
+use sled::Db;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    codec::Codec,
+    namespace,
+    prelude::{HighSpeedCache, Project, StorageError, StorageResult, Trigger, Triggr},
+    storage::Sled,
+};
+
+/// Default interval between garbage collections (seconds).
+const DEFAULT_GC_INTERVAL_SECS: u64 = 3600;
+
+/// A project or run-log tree of a project that does not exist.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct OrphanedTree {
+    /// Store holding the tree (`app`, `triggers` or a data region)
+    pub store: String,
+    /// Name of the tree
+    pub tree: String,
+    /// Records in the tree
+    pub records: usize,
+}
+
+/// A trigger of a project that does not exist.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct OrphanedTrigger {
+    pub contract_addr: String,
+    pub trigger_id: String,
+    pub project_id: String,
+}
+
+/// A metadata entry whose contract file is missing.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct OrphanedMetadata {
+    pub addr: String,
+    pub path: String,
+}
+
+/// Result of a garbage collection.
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+pub struct GcReport {
+    pub trees: Vec<OrphanedTree>,
+    pub triggers: Vec<OrphanedTrigger>,
+    pub metadata: Vec<OrphanedMetadata>,
+    /// Whether the orphaned data was removed
+    pub cleaned: bool,
+}
+
+impl GcReport {
+    /// Whether no orphaned data was found.
+    pub fn is_empty(&self) -> bool {
+        self.trees.is_empty() && self.triggers.is_empty() && self.metadata.is_empty()
+    }
+}
+
+/// Find the data of projects that do not exist and metadata entries whose file is missing.
+pub fn scan(store: &Sled) -> StorageResult<GcReport> {
+    let projects = known_projects(store)?;
+    let mut report = GcReport::default();
+
+    // Trees of projects being purged are left to the purge job
+    let orphaned = |id: &[u8]| -> StorageResult<bool> {
+        let id = String::from_utf8_lossy(id);
+        let id = namespace::project_of(&id);
+        Ok(!projects.contains(id)
+            && !store.settings.contains_key(format!("purge:{id}"))?)
+    };
+
+    let mut stores = vec![("app".to_string(), &*store.app, "project::")];
+    for (name, region) in store.regions.iter() {
+        stores.push((name.clone(), &*region.app, "project::"));
+    }
+    stores.push(("triggers".to_string(), &*store.triggers, "runs::"));
+
+    for (name, db, prefix) in stores {
+        for tree in db.tree_names() {
+            let Some(id) = tree.strip_prefix(prefix.as_bytes()) else {
+                continue;
+            };
+            if orphaned(id)? {
+                report.trees.push(OrphanedTree {
+                    store: name.clone(),
+                    tree: String::from_utf8_lossy(&tree).to_string(),
+                    records: db.open_tree(&tree)?.len(),
+                });
+            }
+        }
+    }
+
+    for item in store.triggers.iter() {
+        let (contract_addr, bytes) = item?;
+        // Unreadable triggers are reported by the integrity check
+        let Ok(triggers) = Codec::decode::<Vec<Trigger>>(&bytes) else {
+            continue;
+        };
+        for trigger in triggers {
+            if !projects.contains(&trigger.project_id) {
+                report.triggers.push(OrphanedTrigger {
+                    contract_addr: String::from_utf8_lossy(&contract_addr).to_string(),
+                    trigger_id: trigger.id,
+                    project_id: trigger.project_id,
+                });
+            }
+        }
+    }
+
+    for entry in store.get_metadata_entries()? {
+        if !Path::new(&entry.path).exists() {
+            report.metadata.push(OrphanedMetadata {
+                addr: entry.addr,
+                path: entry.path,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Find and remove orphaned data.
+pub fn collect(store: &Sled, cache: &HighSpeedCache) -> StorageResult<GcReport> {
+    let mut report = scan(store)?;
+
+    // A project may have been created since the scan
+    let projects = known_projects(store)?;
+
+    for orphan in &report.trees {
+        let id = orphan.tree.split_once("::").map_or("", |(_, id)| id);
+        if projects.contains(namespace::project_of(id)) {
+            continue;
+        }
+        let db: &Db = match orphan.store.as_str() {
+            "app" => &store.app,
+            "triggers" => &store.triggers,
+            region => match store.regions.get(region) {
+                Some(region) => &region.app,
+                None => continue,
+            },
+        };
+        db.drop_tree(&orphan.tree)?;
+    }
+
+    let mut owners = HashSet::new();
+    for orphan in &report.triggers {
+        if !projects.contains(&orphan.project_id)
+            && owners.insert((&orphan.project_id, &orphan.contract_addr))
+        {
+            store.delete_project_triggers(&orphan.project_id, &orphan.contract_addr)?;
+        }
+    }
+
+    let contracts = store
+        .all_projects()?
+        .into_iter()
+        .map(|p| p.contract_address)
+        .collect::<HashSet<_>>();
+    for orphan in &report.metadata {
+        store.remove_metadata_entry(&orphan.addr)?;
+        if !contracts.contains(&orphan.addr) {
+            cache.forget(&orphan.addr);
+        }
+    }
+
+    report.cleaned = true;
+    Ok(report)
+}
+
+/// Collect garbage every `TRIGGR_GC_INTERVAL_SECS` (0 disables it).
+/// Orphaned data is only reported unless `TRIGGR_GC_CLEAN` is set.
+pub async fn run(triggr: Triggr) {
+    let secs = std::env::var("TRIGGR_GC_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_GC_INTERVAL_SECS);
+    if secs == 0 {
+        return;
+    }
+    let clean = std::env::var("TRIGGR_GC_CLEAN")
+        .is_ok_and(|v| matches!(v.to_lowercase().as_str(), "1" | "true"));
+
+    let mut interval = tokio::time::interval(Duration::from_secs(secs));
+    loop {
+        interval.tick().await;
+
+        let (store, cache) = (triggr.store.clone(), triggr.cache.clone());
+        let report = tokio::task::spawn_blocking(move || match clean {
+            true => collect(&store, &cache),
+            false => scan(&store),
+        })
+        .await;
+
+        match report {
+            Ok(Ok(report)) if report.is_empty() => {}
+            Ok(Ok(report)) => {
+                let (trees, triggers, metadata) =
+                    (report.trees.len(), report.triggers.len(), report.metadata.len());
+                let found = format!(
+                    "{trees} orphaned tree(s), {triggers} trigger(s) and {metadata} metadata entry(ies)"
+                );
+                if report.cleaned {
+                    info!("🧹 Removed {found}");
+                } else {
+                    warn!("Found {found}, set TRIGGR_GC_CLEAN to remove them");
+                }
+            }
+            Ok(Err(e)) => warn!("Garbage collection failed: {e}"),
+            Err(e) => warn!("Garbage collection task failed: {e}"),
+        }
+    }
+}
+
+/// Return the ids of every stored project.
+/// Fails on an unreadable project, whose data would otherwise look orphaned.
+fn known_projects(store: &Sled) -> StorageResult<HashSet<String>> {
+    let mut projects = HashSet::new();
+    for entry in store.projects.iter() {
+        let (key, value) = entry?;
+        let project = Codec::decode::<Project>(&value).map_err(|e| {
+            StorageError::Corrupted(format!(
+                "project {}: {e}, quarantine it before collecting garbage",
+                String::from_utf8_lossy(&key)
+            ))
+        })?;
+        projects.insert(project.id);
+    }
+
+    Ok(projects)
+}
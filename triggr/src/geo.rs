@@ -0,0 +1,206 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains geo point fields and the geohash index used for radius queries.
+// A collection can declare one field as a geo point (`{ "lat": .., "lon": .. }`). Documents are
+// indexed by the geohash of their point, so a `near(lat, lon, radius)` query only reads the
+// documents in the cells covering the circle, then keeps those within the radius.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::name::Name;
+
+/// Number of characters of the geohashes stored in the index (cells of a few centimeters).
+pub const GEOHASH_PRECISION: usize = 12;
+
+/// Mean radius of the Earth (meters).
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Geohash alphabet.
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Field a geo index applies to when none is given.
+const DEFAULT_GEO_FIELD: &str = "location";
+
+fn default_field() -> String {
+    DEFAULT_GEO_FIELD.to_string()
+}
+
+/// Geo point field of the documents of a collection.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct GeoIndex {
+    /// Document field holding the point (`{ "lat": .., "lon": .. }`)
+    #[serde(default = "default_field")]
+    pub field: String,
+}
+
+impl GeoIndex {
+    /// Check that the definition is consistent.
+    pub fn validate(&self) -> Result<(), String> {
+        Name::internal("geo field", &self.field)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Read the point of a document.
+    /// Returns `Ok(None)` when the document has no point, and an error when its field is not one.
+    pub fn point(&self, data: &Value) -> Result<Option<GeoPoint>, String> {
+        match data.get(&self.field) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => GeoPoint::from_value(value).map(Some).ok_or_else(|| {
+                format!("{value} is not a geo point, expected {{ \"lat\": .., \"lon\": .. }}")
+            }),
+        }
+    }
+}
+
+/// A point on Earth, in degrees.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl GeoPoint {
+    /// Read a point from `{ "lat": .., "lon": .. }`.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let lat = value.get("lat")?.as_f64()?;
+        let lon = value.get("lon")?.as_f64()?;
+        Self::new(lat, lon)
+    }
+
+    /// Return a point if the coordinates are in range.
+    pub fn new(lat: f64, lon: f64) -> Option<Self> {
+        ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon))
+            .then_some(Self { lat, lon })
+    }
+
+    /// Return the geohash of the point.
+    pub fn geohash(&self, precision: usize) -> String {
+        let (mut lat, mut lon) = ((-90.0, 90.0), (-180.0, 180.0));
+        let mut hash = String::with_capacity(precision);
+        let (mut bits, mut char_bits, mut even) = (0usize, 0u8, true);
+
+        while hash.len() < precision {
+            // Bits alternate between longitude and latitude, starting with longitude
+            let (range, value) = match even {
+                true => (&mut lon, self.lon),
+                false => (&mut lat, self.lat),
+            };
+            let mid = (range.0 + range.1) / 2.0;
+            char_bits <<= 1;
+            if value >= mid {
+                char_bits |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+
+            bits += 1;
+            if bits == 5 {
+                hash.push(BASE32[char_bits as usize] as char);
+                (bits, char_bits) = (0, 0);
+            }
+        }
+
+        hash
+    }
+
+    /// Great-circle distance to another point (meters).
+    pub fn distance(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+    }
+}
+
+/// A radius query: documents within `radius` meters of `center`.
+#[derive(Clone, Copy, Debug)]
+pub struct Near {
+    pub center: GeoPoint,
+    pub radius: f64,
+}
+
+impl Near {
+    /// Parse `near(lat, lon, radius)` or `lat,lon,radius`, with the radius in meters.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let args = input
+            .strip_prefix("near(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .unwrap_or(input);
+
+        let numbers = args
+            .split(',')
+            .map(|n| n.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("Invalid near query '{input}', expected near(lat, lon, radius)"))?;
+        let [lat, lon, radius] = numbers[..] else {
+            return Err(format!("Invalid near query '{input}', expected near(lat, lon, radius)"));
+        };
+
+        let center = GeoPoint::new(lat, lon).ok_or("Coordinates out of range")?;
+        if !radius.is_finite() || radius < 0.0 {
+            return Err("The radius must be a positive number of meters".to_string());
+        }
+
+        Ok(Self { center, radius })
+    }
+
+    /// Return the geohash prefixes of the cells covering the circle.
+    /// The cells are the one of the center and its neighbors, at the finest precision whose
+    /// cells are at least as large as the radius. An empty prefix covers the whole index.
+    pub fn cells(&self) -> Vec<String> {
+        // Size of the radius in degrees, wider in longitude away from the equator
+        let dlat = (self.radius / EARTH_RADIUS_M).to_degrees();
+        let cos = self.center.lat.to_radians().cos();
+        if cos < 1e-6 {
+            return vec![String::new()];
+        }
+        let dlon = dlat / cos;
+
+        let precision = (1..=GEOHASH_PRECISION)
+            .take_while(|&p| {
+                let (height, width) = cell_size(p);
+                height >= dlat && width >= dlon
+            })
+            .last();
+        let Some(precision) = precision else {
+            return vec![String::new()];
+        };
+
+        let (height, width) = cell_size(precision);
+        let mut cells = Vec::with_capacity(9);
+        for lat_step in [-1.0, 0.0, 1.0] {
+            for lon_step in [-1.0, 0.0, 1.0] {
+                let lat = (self.center.lat + lat_step * height).clamp(-90.0, 90.0);
+                let mut lon = self.center.lon + lon_step * width;
+                if lon > 180.0 {
+                    lon -= 360.0;
+                } else if lon < -180.0 {
+                    lon += 360.0;
+                }
+
+                let cell = GeoPoint { lat, lon }.geohash(precision);
+                if !cells.contains(&cell) {
+                    cells.push(cell);
+                }
+            }
+        }
+
+        cells
+    }
+}
+
+/// Height and width (degrees) of the cells of a geohash precision.
+fn cell_size(precision: usize) -> (f64, f64) {
+    let bits = 5 * precision as i32;
+    let lon_bits = (bits + 1) / 2;
+    let lat_bits = bits / 2;
+    (180.0 / 2f64.powi(lat_bits), 360.0 / 2f64.powi(lon_bits))
+}
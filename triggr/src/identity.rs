@@ -0,0 +1,319 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the providers console sessions are authenticated with.
+// `TRIGGR_AUTH_PROVIDER` selects one: `clerk` verifies Clerk session tokens against its JWKS,
+// `oidc` verifies ID tokens of any OpenID Connect issuer, and `static` accepts a fixed list of
+// tokens, so self-hosted instances don't need an identity vendor. Without a provider, every
+// console request is authenticated as the same user.
+
+use std::{
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use subtle::ConstantTimeEq;
+use tokio::sync::RwLock;
+
+/// User console requests are authenticated as when no provider is configured.
+/// Projects created before providers existed are owned by it.
+pub const ANONYMOUS_USER: &str = "jasonXX";
+
+/// Timeout of the requests fetching the keys of an OIDC issuer.
+const OIDC_FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Minimum delay between two fetches of the keys of an OIDC issuer, so tokens with unknown key
+/// ids can't make the instance hammer it.
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Client shared by OIDC key fetches, so connections are reused.
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// Claims identifying the user of a console session.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UserClaims {
+    #[serde(rename = "sub")]
+    pub user_id: String, // <- We alias "sub" directly to user_id
+}
+
+/// Verifies the bearer tokens of console sessions.
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Name the provider is selected by.
+    fn name(&self) -> &str;
+
+    /// Verify a token and return the user it belongs to.
+    async fn authenticate(&self, token: &str) -> Result<UserClaims, String>;
+}
+
+/// Verifies Clerk session tokens against the JWKS in `TRIGGR_CLERKS_JWKS`.
+pub struct ClerkProvider {
+    keys: JwkSet,
+}
+
+impl ClerkProvider {
+    pub fn from_env() -> Result<Self, String> {
+        let jwks = std::env::var("TRIGGR_CLERKS_JWKS")
+            .map_err(|_| "TRIGGR_CLERKS_JWKS is not set".to_string())?;
+        let keys = serde_json::from_str(&jwks)
+            .map_err(|e| format!("Invalid TRIGGR_CLERKS_JWKS: {e}"))?;
+
+        Ok(Self { keys })
+    }
+}
+
+#[async_trait]
+impl AuthProvider for ClerkProvider {
+    fn name(&self) -> &str {
+        "clerk"
+    }
+
+    async fn authenticate(&self, token: &str) -> Result<UserClaims, String> {
+        let (kid, _) = key_id(token)?;
+        let jwk = self
+            .keys
+            .find(&kid)
+            .ok_or_else(|| "No matching JWK found".to_string())?;
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.validate_aud = false;
+        verify(token, jwk, validation).map_err(|_| "Invalid or expired Clerk token".to_string())
+    }
+}
+
+/// Verifies the ID tokens of an OpenID Connect issuer (`TRIGGR_OIDC_ISSUER`), whose keys are
+/// discovered from its configuration. Tokens must be issued for `TRIGGR_OIDC_AUDIENCE`, if set.
+pub struct OidcProvider {
+    issuer: String,
+    audience: Option<String>,
+    /// Keys of the issuer and when they were fetched
+    keys: RwLock<Option<(Arc<JwkSet>, Instant)>>,
+}
+
+impl OidcProvider {
+    pub fn from_env() -> Result<Self, String> {
+        let issuer = std::env::var("TRIGGR_OIDC_ISSUER")
+            .map_err(|_| "TRIGGR_OIDC_ISSUER is not set".to_string())?;
+        if !(issuer.starts_with("https://") || issuer.starts_with("http://")) {
+            return Err(format!("Invalid OIDC issuer '{issuer}', expected http(s)://"));
+        }
+        let audience = std::env::var("TRIGGR_OIDC_AUDIENCE")
+            .ok()
+            .filter(|a| !a.is_empty());
+
+        Ok(Self {
+            issuer,
+            audience,
+            keys: RwLock::new(None),
+        })
+    }
+
+    /// Return the key a token was signed with, refreshing the keys when it is unknown (keys
+    /// are rotated by the issuer).
+    async fn key(&self, kid: &str) -> Result<Jwk, String> {
+        if let Some((keys, fetched)) = self.keys.read().await.as_ref() {
+            if let Some(jwk) = keys.find(kid) {
+                return Ok(jwk.clone());
+            }
+            if fetched.elapsed() < JWKS_REFRESH_INTERVAL {
+                return Err("No matching JWK found".to_string());
+            }
+        }
+
+        let keys = Arc::new(self.fetch_keys().await?);
+        *self.keys.write().await = Some((keys.clone(), Instant::now()));
+
+        keys.find(kid)
+            .cloned()
+            .ok_or_else(|| "No matching JWK found".to_string())
+    }
+
+    /// Fetch the keys of the issuer, from the `jwks_uri` of its configuration.
+    async fn fetch_keys(&self) -> Result<JwkSet, String> {
+        let issuer = self.issuer.trim_end_matches('/');
+        let url = format!("{issuer}/.well-known/openid-configuration");
+        let config: Value = fetch_json(&url).await?;
+        let jwks_uri = config
+            .get("jwks_uri")
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("No jwks_uri in the configuration of {issuer}"))?;
+
+        fetch_json(jwks_uri).await
+    }
+}
+
+#[async_trait]
+impl AuthProvider for OidcProvider {
+    fn name(&self) -> &str {
+        "oidc"
+    }
+
+    async fn authenticate(&self, token: &str) -> Result<UserClaims, String> {
+        let (kid, alg) = key_id(token)?;
+        let jwk = self.key(&kid).await?;
+
+        // The key decides the algorithm, so a token can't downgrade it
+        if jwk
+            .common
+            .key_algorithm
+            .is_some_and(|key_alg| key_alg.to_string() != format!("{alg:?}"))
+        {
+            return Err("The token algorithm does not match its key".to_string());
+        }
+
+        let mut validation = Validation::new(alg);
+        validation.set_issuer(&[&self.issuer]);
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        verify(token, &jwk, validation).map_err(|e| format!("Invalid or expired token: {e}"))
+    }
+}
+
+/// Accepts the fixed tokens of `TRIGGR_AUTH_TOKENS`, a comma separated list of
+/// `user_id:token` pairs.
+pub struct StaticTokens {
+    tokens: Vec<(String, String)>,
+}
+
+impl StaticTokens {
+    pub fn from_env() -> Result<Self, String> {
+        let tokens = std::env::var("TRIGGR_AUTH_TOKENS")
+            .map_err(|_| "TRIGGR_AUTH_TOKENS is not set".to_string())?;
+
+        let tokens = tokens
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once(':') {
+                Some((user, token)) if !user.is_empty() && !token.is_empty() => {
+                    Ok((user.to_string(), token.to_string()))
+                }
+                _ => Err("Invalid TRIGGR_AUTH_TOKENS, expected user_id:token pairs".to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if tokens.is_empty() {
+            return Err("TRIGGR_AUTH_TOKENS has no tokens".to_string());
+        }
+
+        Ok(Self { tokens })
+    }
+}
+
+#[async_trait]
+impl AuthProvider for StaticTokens {
+    fn name(&self) -> &str {
+        "static"
+    }
+
+    async fn authenticate(&self, token: &str) -> Result<UserClaims, String> {
+        // Compare every token in constant time, so none can be guessed byte by byte
+        let mut user = None;
+        for (user_id, expected) in &self.tokens {
+            if bool::from(token.as_bytes().ct_eq(expected.as_bytes())) {
+                user = Some(user_id);
+            }
+        }
+
+        user.map(|user_id| UserClaims {
+            user_id: user_id.clone(),
+        })
+        .ok_or_else(|| "Invalid token".to_string())
+    }
+}
+
+/// Rejects every session, in place of a provider whose configuration is invalid.
+struct Misconfigured {
+    name: String,
+}
+
+#[async_trait]
+impl AuthProvider for Misconfigured {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn authenticate(&self, _token: &str) -> Result<UserClaims, String> {
+        Err(format!("The {} auth provider is misconfigured", self.name))
+    }
+}
+
+/// Return the provider selected by `TRIGGR_AUTH_PROVIDER`, if any.
+/// A provider that can't be configured rejects every session instead of letting them through.
+pub fn from_env() -> Option<Arc<dyn AuthProvider>> {
+    let Ok(name) = std::env::var("TRIGGR_AUTH_PROVIDER") else {
+        tracing::warn!("🔓 No auth provider set, console requests are not authenticated");
+        return None;
+    };
+
+    let provider: Result<Arc<dyn AuthProvider>, String> = match name.as_str() {
+        "clerk" => ClerkProvider::from_env().map(|p| Arc::new(p) as _),
+        "oidc" => OidcProvider::from_env().map(|p| Arc::new(p) as _),
+        "static" => StaticTokens::from_env().map(|p| Arc::new(p) as _),
+        other => Err(format!(
+            "Unknown auth provider '{other}', expected clerk, oidc or static"
+        )),
+    };
+
+    match provider {
+        Ok(provider) => {
+            tracing::info!("🔐 Console sessions are verified by the {name} provider");
+            Some(provider)
+        }
+        Err(e) => {
+            tracing::error!("Console sessions are rejected: {e}");
+            Some(Arc::new(Misconfigured { name }))
+        }
+    }
+}
+
+/// Read the key id and algorithm from the header of a token.
+fn key_id(token: &str) -> Result<(String, Algorithm), String> {
+    let header = decode_header(token).map_err(|_| "Invalid JWT header".to_string())?;
+    let kid = header
+        .kid
+        .ok_or_else(|| "Missing kid in JWT header".to_string())?;
+
+    Ok((kid, header.alg))
+}
+
+/// Verify a token with a key of its issuer.
+fn verify(token: &str, jwk: &Jwk, validation: Validation) -> Result<UserClaims, String> {
+    // Keys published by an issuer are public, so tokens signed with a shared secret are refused
+    if matches!(
+        validation.algorithms.first(),
+        Some(Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)
+    ) {
+        return Err("Symmetric algorithms are not accepted".to_string());
+    }
+
+    let key = DecodingKey::from_jwk(jwk).map_err(|e| e.to_string())?;
+    decode::<UserClaims>(token, &key, &validation)
+        .map(|data| data.claims)
+        .map_err(|e| e.to_string())
+}
+
+/// GET a JSON document.
+async fn fetch_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T, String> {
+    let body = CLIENT
+        .get(url)
+        .timeout(OIDC_FETCH_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch {url}: {e}"))?
+        .error_for_status()
+        .map_err(|e| format!("Failed to fetch {url}: {e}"))?
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to fetch {url}: {e}"))?;
+
+    serde_json::from_slice(&body).map_err(|e| format!("Invalid JSON from {url}: {e}"))
+}
@@ -0,0 +1,455 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module accepts contract events pushed by external indexers, instead of the chain watcher.
+// Each provider has an adapter that checks the signature of its webhooks and normalizes their
+// payload into events, which are queued for the trigger engine like the events of the watcher.
+// Signatures cover the time a webhook was sent, and stale webhooks are refused, so a captured
+// webhook can't be replayed.
+// Raw events are decoded with the metadata of their contract; a provider can also push events
+// it already decoded.
+
+use std::{
+    collections::HashMap,
+    env,
+    fmt,
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use utoipa::ToSchema;
+
+use crate::{
+    chain::polkadot::{
+        prelude::{EventData, RawEvent},
+        util::decode_contract_event,
+    },
+    namespace::Namespace,
+    prelude::{EventSender, StorageResult, Triggr},
+};
+
+/// Default number of seconds a signed webhook is accepted for.
+const DEFAULT_INBOUND_MAX_AGE_SECS: i64 = 300;
+
+/// Providers whose webhooks can push events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Provider {
+    /// Alchemy custom webhooks: the logs of a block
+    Alchemy,
+    /// Triggr's own format (`Delivery`), for indexers posting events themselves
+    Generic,
+}
+
+impl Provider {
+    /// Header carrying the signature of a webhook.
+    pub fn signature_header(self) -> &'static str {
+        match self {
+            Provider::Alchemy => "x-alchemy-signature",
+            Provider::Generic => "x-triggr-signature",
+        }
+    }
+
+    /// Header carrying the time (unix seconds) a webhook was signed at, if the provider sends
+    /// it apart from the body. Alchemy webhooks carry it in their signed body.
+    pub fn timestamp_header(self) -> Option<&'static str> {
+        match self {
+            Provider::Alchemy => None,
+            Provider::Generic => Some("x-triggr-timestamp"),
+        }
+    }
+
+    /// Normalize the payload of a webhook.
+    pub fn normalize(self, body: &[u8]) -> Result<Delivery, String> {
+        match self {
+            Provider::Alchemy => alchemy(body),
+            Provider::Generic => serde_json::from_slice(body).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl FromStr for Provider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "alchemy" => Ok(Provider::Alchemy),
+            "generic" => Ok(Provider::Generic),
+            other => Err(format!("Unknown webhook provider '{other}'")),
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provider::Alchemy => write!(f, "alchemy"),
+            Provider::Generic => write!(f, "generic"),
+        }
+    }
+}
+
+/// Events pushed by a webhook, in the order they were emitted.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct Delivery {
+    /// Hash of the block the events were emitted in
+    pub block_hash: Option<String>,
+    /// Number of that block
+    pub block_number: Option<u64>,
+    pub events: Vec<PushedEvent>,
+}
+
+/// Event pushed by a webhook: a raw payload (`data` and `topics`) or an event already decoded
+/// (`event_name` and `fields`).
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PushedEvent {
+    /// Contract that emitted the event
+    pub contract_addr: String,
+    /// SCALE encoded event data (hex)
+    pub data: Option<String>,
+    /// Event topics (hex)
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// Name of the decoded event
+    pub event_name: Option<String>,
+    /// Fields of the decoded event
+    #[serde(default)]
+    pub fields: HashMap<String, Value>,
+}
+
+/// Outcome of a webhook.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct IngestReport {
+    /// Events queued for the trigger engine
+    pub accepted: usize,
+    /// Events left out, and why
+    pub skipped: Vec<String>,
+}
+
+/// Normalize the logs of an Alchemy custom webhook (`event.data.block.logs`).
+fn alchemy(body: &[u8]) -> Result<Delivery, String> {
+    let payload: Value = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    let block = payload
+        .pointer("/event/data/block")
+        .ok_or("Missing event.data.block")?;
+
+    let text = |value: &Value, pointer: &str| {
+        value.pointer(pointer).and_then(Value::as_str).map(str::to_string)
+    };
+    let events = block
+        .get("logs")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .map(|log| {
+            Ok(PushedEvent {
+                contract_addr: text(log, "/account/address").ok_or("Missing log address")?,
+                data: Some(text(log, "/data").ok_or("Missing log data")?),
+                topics: serde_json::from_value(log.get("topics").cloned().unwrap_or_default())
+                    .unwrap_or_default(),
+                event_name: None,
+                fields: HashMap::new(),
+            })
+        })
+        .collect::<Result<_, String>>()?;
+
+    Ok(Delivery {
+        block_hash: text(block, "/hash"),
+        block_number: block.get("number").and_then(Value::as_u64),
+        events,
+    })
+}
+
+/// Time an Alchemy webhook was created at (`createdAt`), in unix seconds.
+fn created_at(body: &[u8]) -> Result<i64, String> {
+    let payload: Value = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    let created_at = payload
+        .get("createdAt")
+        .and_then(Value::as_str)
+        .ok_or("Missing createdAt")?;
+
+    chrono::DateTime::parse_from_rfc3339(created_at)
+        .map(|time| time.timestamp())
+        .map_err(|e| format!("Malformed createdAt: {e}"))
+}
+
+/// Receives the events pushed by webhooks.
+pub struct Inbound {
+    /// Queue of the trigger engine, set when the engine starts
+    sender: OnceLock<EventSender>,
+    /// Signing secret of each enabled provider
+    secrets: HashMap<Provider, String>,
+    /// Number of seconds a signed webhook is accepted for
+    max_age: i64,
+}
+
+impl Inbound {
+    /// Enable the providers given a secret in `TRIGGR_INBOUND_SECRETS`
+    /// (`provider=secret,provider=secret`). Webhooks are accepted for
+    /// `TRIGGR_INBOUND_MAX_AGE_SECS` after they were signed.
+    pub fn from_env() -> Self {
+        let secrets = env::var("TRIGGR_INBOUND_SECRETS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|pair| {
+                let (provider, secret) = pair.split_once('=')?;
+                let provider = provider.parse::<Provider>().ok()?;
+                let secret = secret.trim();
+                (!secret.is_empty()).then(|| (provider, secret.to_string()))
+            })
+            .collect();
+        let max_age = env::var("TRIGGR_INBOUND_MAX_AGE_SECS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(DEFAULT_INBOUND_MAX_AGE_SECS);
+
+        Self {
+            sender: OnceLock::new(),
+            secrets,
+            max_age,
+        }
+    }
+
+    /// Attach the webhooks to the trigger engine.
+    pub fn attach(&self, sender: EventSender) {
+        let _ = self.sender.set(sender);
+    }
+
+    /// Signing secret of a provider, if its webhooks are enabled.
+    pub fn secret(&self, provider: Provider) -> Option<&str> {
+        self.secrets.get(&provider).map(String::as_str)
+    }
+
+    /// Check the HMAC-SHA256 signature (hex, optionally prefixed with `sha256=`) of a webhook
+    /// sent at `now`. Generic webhooks sign `{timestamp}.{body}`, with the timestamp of their
+    /// timestamp header, and Alchemy ones sign their body, which carries its creation time.
+    pub fn verify(
+        &self,
+        provider: Provider,
+        body: &[u8],
+        signature: Option<&str>,
+        timestamp: Option<&str>,
+        now: i64,
+    ) -> Result<(), String> {
+        let secret = self
+            .secret(provider)
+            .ok_or_else(|| format!("Webhooks of {provider} are not enabled"))?;
+        let signature = signature.ok_or("Missing signature")?.trim();
+        let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+        let signature = hex::decode(signature).map_err(|_| "Malformed signature")?;
+        let timestamp = match provider.timestamp_header() {
+            Some(header) => Some(timestamp.ok_or(format!("Missing {header} header"))?.trim()),
+            None => None,
+        };
+
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|e| e.to_string())?;
+        if let Some(timestamp) = timestamp {
+            mac.update(timestamp.as_bytes());
+            mac.update(b".");
+        }
+        mac.update(body);
+        mac.verify_slice(&signature)
+            .map_err(|_| "Invalid signature".to_string())?;
+
+        // The time is signed too, so a captured webhook is refused once it is stale
+        let sent_at = match timestamp {
+            Some(timestamp) => timestamp.parse().map_err(|_| "Malformed timestamp")?,
+            None => created_at(body)?,
+        };
+        let age = now - sent_at;
+        if age.abs() > self.max_age {
+            return Err(format!(
+                "Webhook timestamp {sent_at} is more than {}s from now",
+                self.max_age
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Queue the events of a delivery for the trigger engine.
+    /// Events of contracts without metadata, or owned by another shard, are skipped.
+    pub async fn ingest(&self, triggr: &Triggr, delivery: Delivery) -> StorageResult<IngestReport> {
+        let Some(sender) = self.sender.get() else {
+            return Err("The trigger engine is not running".into());
+        };
+
+        let mut report = IngestReport::default();
+        for pushed in delivery.events {
+            let contract_addr = pushed.contract_addr.to_lowercase();
+            match to_event(triggr, &contract_addr, pushed).await {
+                Ok(mut event) => {
+                    event.block = delivery.block_hash.clone();
+                    event.block_number = delivery.block_number;
+
+                    triggr.pipeline.enqueued();
+                    sender.send(contract_addr, event).await?;
+                    report.accepted += 1;
+                }
+                Err(reason) => report.skipped.push(format!("{contract_addr}: {reason}")),
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Build the event of a contract pushed by a webhook, decoding its raw payload if needed.
+async fn to_event(
+    triggr: &Triggr,
+    contract_addr: &str,
+    pushed: PushedEvent,
+) -> Result<EventData, String> {
+    let metadata = triggr
+        .contract_metadata(contract_addr)
+        .await
+        .filter(|_| triggr.owns_contract(contract_addr))
+        .ok_or("contract is not watched")?;
+
+    match (pushed.data, pushed.event_name) {
+        (Some(data), _) => {
+            let bytes = hex::decode(data.trim_start_matches("0x"))
+                .map_err(|e| format!("invalid data: {e}"))?;
+            let mode = triggr.cache.decode_mode(contract_addr);
+            let mut event = decode_contract_event(&bytes, &pushed.topics, &metadata, mode)
+                .map_err(|failure| failure.error)?;
+            event.raw = Some(RawEvent {
+                data,
+                topics: pushed.topics,
+            });
+            Ok(event)
+        }
+        (None, Some(event_name)) => Ok(EventData {
+            event_name,
+            fields: pushed.fields,
+            raw: None,
+            scores: HashMap::new(),
+            trace: None,
+            namespace: Namespace::Live,
+            block: None,
+            block_number: None,
+        }),
+        (None, None) => Err("event has neither data nor event_name".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "whsec_test";
+    const NOW: i64 = 1_700_000_000;
+    const BODY: &[u8] = br#"{"events":[]}"#;
+
+    fn inbound() -> Inbound {
+        Inbound {
+            sender: OnceLock::new(),
+            secrets: HashMap::from([
+                (Provider::Generic, SECRET.to_string()),
+                (Provider::Alchemy, SECRET.to_string()),
+            ]),
+            max_age: DEFAULT_INBOUND_MAX_AGE_SECS,
+        }
+    }
+
+    fn sign(payload: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(payload);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// Signature of a generic webhook sent at a time.
+    fn sign_generic(timestamp: i64, body: &[u8]) -> String {
+        sign(&[format!("{timestamp}.").as_bytes(), body].concat())
+    }
+
+    fn verify_generic(signature: Option<&str>, timestamp: Option<&str>) -> Result<(), String> {
+        inbound().verify(Provider::Generic, BODY, signature, timestamp, NOW)
+    }
+
+    #[test]
+    fn valid_signature_is_accepted() {
+        let signature = sign_generic(NOW, BODY);
+        assert_eq!(verify_generic(Some(&signature), Some(&NOW.to_string())), Ok(()));
+    }
+
+    #[test]
+    fn prefixed_signature_is_accepted() {
+        let signature = format!("sha256={}", sign_generic(NOW - 10, BODY));
+        let timestamp = (NOW - 10).to_string();
+        assert_eq!(verify_generic(Some(&signature), Some(&timestamp)), Ok(()));
+    }
+
+    #[test]
+    fn tampered_webhook_is_refused() {
+        let signature = sign_generic(NOW, BODY);
+        let tampered = inbound().verify(
+            Provider::Generic,
+            br#"{"events":[{}]}"#,
+            Some(&signature),
+            Some(&NOW.to_string()),
+            NOW,
+        );
+        assert_eq!(tampered, Err("Invalid signature".to_string()));
+
+        // Moving the timestamp of a signed webhook breaks its signature
+        let moved = verify_generic(Some(&signature), Some(&(NOW + 1).to_string()));
+        assert_eq!(moved, Err("Invalid signature".to_string()));
+
+        // A signature of the body alone is not enough
+        let unstamped = verify_generic(Some(&sign(BODY)), Some(&NOW.to_string()));
+        assert_eq!(unstamped, Err("Invalid signature".to_string()));
+    }
+
+    #[test]
+    fn missing_signature_or_timestamp_is_refused() {
+        let signature = sign_generic(NOW, BODY);
+        assert_eq!(
+            verify_generic(None, Some(&NOW.to_string())),
+            Err("Missing signature".to_string())
+        );
+        assert_eq!(
+            verify_generic(Some(&signature), None),
+            Err("Missing x-triggr-timestamp header".to_string())
+        );
+        assert_eq!(
+            verify_generic(Some("not hex"), Some(&NOW.to_string())),
+            Err("Malformed signature".to_string())
+        );
+    }
+
+    #[test]
+    fn replayed_webhook_is_refused_once_stale() {
+        let sent_at = NOW - DEFAULT_INBOUND_MAX_AGE_SECS - 1;
+        let signature = sign_generic(sent_at, BODY);
+        let replayed = verify_generic(Some(&signature), Some(&sent_at.to_string()));
+        assert!(replayed.is_err_and(|e| e.contains("more than 300s")));
+
+        let sent_at = NOW + DEFAULT_INBOUND_MAX_AGE_SECS + 1;
+        let signature = sign_generic(sent_at, BODY);
+        assert!(verify_generic(Some(&signature), Some(&sent_at.to_string())).is_err());
+    }
+
+    #[test]
+    fn alchemy_webhooks_are_dated_by_their_signed_body() {
+        let body = |created_at: &str| {
+            format!(r#"{{"createdAt":"{created_at}","event":{{"data":{{"block":{{}}}}}}}}"#)
+        };
+        let verify = |body: &str| {
+            let signature = sign(body.as_bytes());
+            inbound().verify(Provider::Alchemy, body.as_bytes(), Some(&signature), None, NOW)
+        };
+
+        // NOW is 2023-11-14T22:13:20Z
+        assert_eq!(verify(&body("2023-11-14T22:12:20.393Z")), Ok(()));
+        assert!(verify(&body("2023-11-14T21:13:20Z")).is_err());
+        assert_eq!(
+            verify(r#"{"event":{}}"#),
+            Err("Missing createdAt".to_string())
+        );
+    }
+}
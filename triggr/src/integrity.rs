@@ -0,0 +1,107 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module checks that stored records can still be decoded.
+// Corrupted records are reported at startup and on demand, and can be quarantined: moved, as they
+// are, to the `quarantine` tree of their database so that writes to their key can resume.
+
+use serde::{de::DeserializeOwned, Serialize};
+use sled::{Db, IVec};
+use utoipa::ToSchema;
+
+use crate::{
+    codec::Codec,
+    prelude::{Project, StorageResult, Trigger},
+    storage::{Metadata, Sled},
+};
+
+/// Tree corrupted records are moved to, in the database they come from.
+const QUARANTINE_TREE: &str = "quarantine";
+
+/// A stored record that cannot be decoded.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct CorruptedRecord {
+    /// Database of the record
+    pub db: String,
+    /// Key of the record (lossy UTF-8)
+    pub key: String,
+    /// Decoding error
+    pub error: String,
+    /// Stored key and value
+    #[serde(skip)]
+    stored: (IVec, IVec),
+}
+
+/// Result of an integrity check.
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+pub struct IntegrityReport {
+    /// Records checked
+    pub scanned: u64,
+    /// Records that cannot be decoded
+    pub corrupted: Vec<CorruptedRecord>,
+    /// Corrupted records moved to quarantine
+    pub quarantined: usize,
+}
+
+/// Check every record of the projects, users, metadata and trigger databases.
+pub fn scan(store: &Sled) -> StorageResult<IntegrityReport> {
+    let mut report = IntegrityReport::default();
+
+    check::<Project>(&mut report, "projects", &store.projects)?;
+    check::<Vec<Project>>(&mut report, "users", &store.users)?;
+    check::<Vec<Metadata>>(&mut report, "metadata", &store.metadata)?;
+    check::<Vec<Trigger>>(&mut report, "triggers", &store.triggers)?;
+
+    Ok(report)
+}
+
+/// Check every record and move the corrupted ones to quarantine.
+pub fn quarantine(store: &Sled) -> StorageResult<IntegrityReport> {
+    let mut report = scan(store)?;
+
+    for record in &report.corrupted {
+        let Some(db) = store.database(&record.db) else {
+            continue;
+        };
+        let (key, value) = &record.stored;
+
+        // Keep the record aside, then remove it unless it was rewritten since the scan
+        db.open_tree(QUARANTINE_TREE)?.insert(key, value)?;
+        if db
+            .compare_and_swap(key, Some(value), None as Option<IVec>)?
+            .is_ok()
+        {
+            report.quarantined += 1;
+        }
+        db.flush()?;
+    }
+
+    // Index entries of quarantined triggers point nowhere
+    if report.corrupted.iter().any(|r| r.db == "triggers") {
+        store.reindex_triggers()?;
+    }
+
+    Ok(report)
+}
+
+/// Decode every record of a database as `T`.
+fn check<T: DeserializeOwned>(
+    report: &mut IntegrityReport,
+    name: &str,
+    db: &Db,
+) -> StorageResult<()> {
+    for entry in db.iter() {
+        let (key, value) = entry?;
+        report.scanned += 1;
+
+        if let Err(e) = Codec::decode::<T>(&value) {
+            report.corrupted.push(CorruptedRecord {
+                db: name.to_string(),
+                key: String::from_utf8_lossy(&key).to_string(),
+                error: e.to_string(),
+                stored: (key, value),
+            });
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module records decoded events to a journal file and replays them later.
+// The journal is a JSON-lines file, one event per line, in the order the trigger engine received them.
+// Replaying it against a fresh instance reproduces what the triggers did, e.g. to debug an automation.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
+};
+use tracing::{info, warn};
+
+use crate::{chain::polkadot::prelude::EventData, EventSender, Triggr};
+
+/// Default replay speed (1.0 = the pace events were recorded at).
+const DEFAULT_REPLAY_SPEED: f64 = 1.0;
+
+/// A recorded event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// When the trigger engine received the event (unix ms)
+    pub timestamp: u64,
+    /// Contract that emitted the event
+    pub contract_addr: String,
+    /// The decoded event
+    pub event: EventData,
+}
+
+/// Appends received events to the journal file set in `TRIGGR_EVENT_JOURNAL`.
+pub struct Journal {
+    writer: Option<BufWriter<File>>,
+}
+
+impl Journal {
+    /// Open the journal, or return a disabled one if recording is off.
+    pub async fn from_env() -> Self {
+        let Ok(path) = std::env::var("TRIGGR_EVENT_JOURNAL") else {
+            return Self { writer: None };
+        };
+
+        match OpenOptions::new().create(true).append(true).open(&path).await {
+            Ok(file) => {
+                info!("📼 Recording events to {path}");
+                Self {
+                    writer: Some(BufWriter::new(file)),
+                }
+            }
+            Err(e) => {
+                warn!("Failed to open event journal {path}: {e}");
+                Self { writer: None }
+            }
+        }
+    }
+
+    /// Record an event. Failures are logged and never stop event processing.
+    pub async fn record(&mut self, contract_addr: &str, event: &EventData) {
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+
+        let entry = JournalEntry {
+            timestamp: Utc::now().timestamp_millis() as u64,
+            contract_addr: contract_addr.to_string(),
+            event: event.clone(),
+        };
+
+        let Ok(mut line) = serde_json::to_vec(&entry) else {
+            return;
+        };
+        line.push(b'\n');
+
+        // Flush each entry so the journal survives a crash
+        if let Err(e) = async {
+            writer.write_all(&line).await?;
+            writer.flush().await
+        }
+        .await
+        {
+            warn!("Failed to record event to journal: {e}");
+        }
+    }
+}
+
+/// Read every entry of a journal file, skipping malformed lines.
+pub async fn load(path: &str) -> std::io::Result<Vec<JournalEntry>> {
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    let mut entries = Vec::new();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!("Skipping malformed journal entry: {e}"),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Replay the journal set in `TRIGGR_REPLAY_JOURNAL` at `TRIGGR_REPLAY_SPEED`
+/// (a multiplier of the recorded pace, 0 for as fast as possible).
+/// Each event's triggers finish before the next event is sent, so runs happen in the recorded order.
+pub async fn replay(path: String, tx: EventSender, triggr: Triggr) {
+    let speed = std::env::var("TRIGGR_REPLAY_SPEED")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|s| *s >= 0.0)
+        .unwrap_or(DEFAULT_REPLAY_SPEED);
+
+    let entries = match load(&path).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read event journal {path}: {e}");
+            return;
+        }
+    };
+
+    info!("⏯️ Replaying {} event(s) from {path} at {speed}x", entries.len());
+
+    let mut previous: Option<u64> = None;
+    for entry in entries {
+        // Keep the recorded spacing between events, scaled by the speed
+        if let Some(previous) = previous {
+            let gap = entry.timestamp.saturating_sub(previous);
+            if speed > 0.0 && gap > 0 {
+                tokio::time::sleep(Duration::from_millis((gap as f64 / speed) as u64)).await;
+            }
+        }
+        previous = Some(entry.timestamp);
+
+        triggr.pipeline.enqueued();
+        if tx.send(entry.contract_addr, entry.event).await.is_err() {
+            break;
+        }
+
+        // Wait for the event's triggers before moving on
+        loop {
+            let stats = triggr.pipeline.stats();
+            if stats.channel_len == 0 && stats.executor_pending == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    info!("⏹️ Replay of {path} finished");
+}
@@ -2,22 +2,73 @@
 
 // Triggr - A reactive database for onchain events.
 
-use std::collections::HashMap;
+use std::{any::Any, collections::HashMap, panic::AssertUnwindSafe, sync::Arc, time::Duration};
 
 use crate::{
+    batch::BlockBatch,
     chain::polkadot::prelude::EventData,
     dsl::{Action, DslExecutor},
+    namespace::Namespace,
+    plan::EvaluationPlan,
 };
 use chrono::Utc;
+use futures::FutureExt as _;
+use journal::Journal;
+use opentelemetry::{context::FutureExt, trace::SpanKind, Context, KeyValue};
 use serde_json::{json, Value};
-use tokio::sync::mpsc::Receiver;
+use storage::Sled;
+use tokio::{
+    sync::{
+        mpsc::{self, Receiver, UnboundedReceiver, UnboundedSender},
+        OnceCell,
+    },
+    task::JoinHandle,
+};
 
+mod aggregate;
+mod anomaly;
+mod apply;
+mod batch;
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
+mod bootstrap;
 mod chain;
+mod codec;
+mod dev;
 mod dsl;
+mod durability;
+mod enrich;
+mod expr;
+mod gc;
+mod geo;
+mod identity;
+mod inbound;
+mod integrity;
+mod journal;
+mod lifecycle;
+mod load;
+mod metering;
+mod migrate;
+mod name;
+mod namespace;
+mod ordering;
+mod plan;
 mod prelude;
+mod quota;
+mod recompute;
 mod server;
+mod service;
+mod shard;
 mod storage;
+mod syntax;
+mod telemetry;
+pub mod tenancy;
+mod template;
+pub mod testing;
 mod util;
+mod watchlist;
+mod webhook;
 
 // Re-export prelude definitions
 pub(crate) use prelude::*;
@@ -25,69 +76,568 @@ pub(crate) use prelude::*;
 pub use server::startup::run as start;
 use util::{generate_uuid, is_uuid};
 
+/// First delay before retrying an event whose triggers could not be loaded.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Longest delay between retries of an event.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Default max number of items an `append` action keeps in an array.
+const DEFAULT_APPEND_MAX_ITEMS: usize = 100;
+
+/// Default number of blocks the event log of a contract keeps.
+const DEFAULT_EVENT_LOG_BLOCKS: u64 = 100_000;
+
+/// Default number of days the run log of a project keeps runs for.
+const DEFAULT_RUN_LOG_DAYS: u64 = 30;
+
+/// Progress of the trigger engine through the event queue, committed in queue order.
+enum Progress {
+    /// Trigger executions of a queued event, and whether its writes wait for its block
+    Event(u64, Vec<JoinHandle<()>>, bool),
+    /// End of a block, whose deferred writes can be committed
+    Block(Arc<BlockBatch>),
+}
+
 /// Function to handle blockchain events and execute triggers.
-pub async fn handle_chain_events(triggr: Triggr, mut rx: Receiver<(String, EventData)>) {
-    // Recieve stream data
-    while let Some((contract_addr, event_data)) = rx.recv().await {
-        // Load triggers from db
-        if let Ok(triggers) = TriggerStore::list_triggers(&*triggr.store, &contract_addr) {
-            // Filter triggers based on event name
-            let triggers = triggers
-                .iter()
-                .filter(|t| {
-                    t.rules.iter().any(|r| {
-                        r.event_name.to_lowercase() == event_data.event_name.to_lowercase()
-                    })
-                })
-                .cloned()
-                .collect::<Vec<Trigger>>();
-
-            // Spin up tasks to execute tiggers
-            for trigger in triggers {
-                // Make sure it hasn't been disabled
-                if trigger.active {
-                    tokio::task::spawn(execute_trigger(
-                        triggr.clone(),
-                        contract_addr.clone(),
-                        trigger,
-                        event_data.clone(),
-                    ));
+/// Events are read from the event queue, each message on `rx` announcing a newly queued event.
+pub async fn handle_chain_events(triggr: Triggr, mut rx: Receiver<()>) {
+    // Record events when a journal is configured
+    let mut journal = Journal::from_env().await;
+
+    // Offsets are committed in queue order once an event's triggers have run
+    let (done_tx, done_rx) = mpsc::unbounded_channel();
+    tokio::task::spawn(commit_events(triggr.store.clone(), done_rx));
+
+    // Resume after the last committed event.
+    // Events in flight when the instance stopped are delivered again
+    let mut next = triggr.store.event_offset(ENGINE_CONSUMER);
+    let pending = triggr.store.events.len();
+    if pending > 0 {
+        tracing::info!("Resuming {pending} queued event(s)");
+    }
+
+    // Trigger writes of a block are committed together, unless disabled
+    let idle = BlockBatch::idle();
+    let batching = idle.is_some();
+    let mut batch = None;
+
+    next = drain_events(&triggr, &mut journal, next, &done_tx, &mut batch, batching).await;
+
+    loop {
+        tokio::select! {
+            // Recieve stream data
+            received = rx.recv() => {
+                if received.is_none() {
+                    break;
+                }
+
+                // In maintenance, events stay queued until it ends
+                next = drain_events(&triggr, &mut journal, next, &done_tx, &mut batch, batching)
+                    .await;
+
+                // Only now, so the event is never seen as neither queued nor executing
+                triggr.pipeline.dequeued();
+            }
+
+            // Maintenance ended
+            _ = triggr.maintenance.resumed.notified() => {
+                next = drain_events(&triggr, &mut journal, next, &done_tx, &mut batch, batching)
+                    .await;
+            }
+
+            // No event of the open block arrived for a while, so it is complete
+            _ = tokio::time::sleep(idle.unwrap_or_default()), if batch.is_some() => {
+                close_batch(&mut batch, &done_tx);
+            }
+        }
+    }
+
+    close_batch(&mut batch, &done_tx);
+}
+
+/// Close the open block batch. Its writes are committed once its executions finished.
+fn close_batch(batch: &mut Option<Arc<BlockBatch>>, done: &UnboundedSender<Progress>) {
+    if let Some(batch) = batch.take() {
+        let _ = done.send(Progress::Block(batch));
+    }
+}
+
+/// Dispatch the queued events from position `next` on, oldest first,
+/// and return the position to continue from. Stops when maintenance starts.
+/// An event whose triggers can't be loaded is retried with backoff, so it is never skipped.
+/// An event that can't be read is quarantined (see `Sled::quarantine_event`) and skipped.
+/// When `batching`, the events of a chain block share a batch, closed by the next block.
+async fn drain_events(
+    triggr: &Triggr,
+    journal: &mut Journal,
+    mut next: u64,
+    done: &UnboundedSender<Progress>,
+    batch: &mut Option<Arc<BlockBatch>>,
+    batching: bool,
+) -> u64 {
+    let mut delay = RETRY_DELAY;
+    while !triggr.maintenance.is_active() {
+        match triggr.store.next_event(next) {
+            Ok(Some((seq, Err(e)))) => {
+                // An event that can't be read would block the queue forever, so it is set aside
+                tracing::error!("Quarantining queued event {seq}, as it can't be read: {e}");
+                if let Err(e) = triggr.store.quarantine_event(seq) {
+                    tracing::error!("Failed to quarantine queued event {seq}: {e}");
+                    break;
+                }
+                triggr.pipeline.event_quarantined();
+                let _ = done.send(Progress::Event(seq, Vec::new(), batch.is_some()));
+                next = seq + 1;
+            }
+            Ok(Some((seq, Ok((contract_addr, event_data))))) => {
+                if batch
+                    .as_ref()
+                    .is_some_and(|b| event_data.block.as_ref() != Some(&b.block))
+                {
+                    close_batch(batch, done);
+                }
+                if batching && batch.is_none() {
+                    *batch = event_data.block.clone().map(|block| Arc::new(BlockBatch::new(block)));
+                }
+
+                match dispatch_event(triggr, &contract_addr, &event_data, batch.clone()) {
+                    Ok(executions) => {
+                        journal.record(&contract_addr, &event_data).await;
+                        log_event(triggr, &contract_addr, seq, &event_data);
+                        let _ = done.send(Progress::Event(seq, executions, batch.is_some()));
+                        next = seq + 1;
+                        delay = RETRY_DELAY;
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            "Failed to load the triggers of {contract_addr}, retrying in {delay:?}: {e}"
+                        );
+                        tokio::time::sleep(delay).await;
+                        delay = (delay * 2).min(MAX_RETRY_DELAY);
+                    }
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                tracing::error!("Failed to read the event queue: {e}");
+                break;
+            }
+        }
+    }
+
+    next
+}
+
+/// Commit the engine's offset past each event once its trigger executions finished.
+/// Events arrive in queue order, so the offset never moves past an unfinished event.
+/// Events of a block batch are only committed with the writes of their block.
+async fn commit_events(store: Arc<Sled>, mut done: UnboundedReceiver<Progress>) {
+    let commit = |seq: u64| {
+        if let Err(e) = store.commit_event(ENGINE_CONSUMER, seq) {
+            tracing::error!("Failed to commit event offset: {e}");
+        }
+    };
+
+    let mut batched = Vec::new();
+    while let Some(progress) = done.recv().await {
+        match progress {
+            Progress::Event(seq, executions, deferred) => {
+                for execution in executions {
+                    // Panics are caught by the executions, so this is a cancelled task
+                    if let Err(e) = execution.await {
+                        tracing::error!("Trigger execution of event {seq} did not finish: {e}");
+                    }
+                }
+                if deferred {
+                    batched.push(seq);
+                } else {
+                    commit(seq);
                 }
             }
+            Progress::Block(batch) => {
+                batch.commit(&store).await;
+                batched.drain(..).for_each(commit);
+            }
         }
     }
 }
 
+/// Spawn the executions of the triggers matching an event.
+/// Fails when the triggers of the contract can't be loaded.
+fn dispatch_event(
+    triggr: &Triggr,
+    contract_addr: &str,
+    event_data: &EventData,
+    batch: Option<Arc<BlockBatch>>,
+) -> StorageResult<Vec<JoinHandle<()>>> {
+    let mut executions = Vec::new();
+
+    // Drop events the project routes away before matching any trigger
+    if !triggr.cache.routes_allow(contract_addr, event_data) {
+        triggr.pipeline.filtered();
+        return Ok(executions);
+    }
+
+    // Nobody set triggers on the contract
+    let Some(plan) = triggr.plans.get(&triggr.store, contract_addr)? else {
+        return Ok(executions);
+    };
+
+    // Score the event against the usual values of its fields, once it can no longer be retried
+    let mut event_data = event_data.clone();
+    triggr.anomalies.observe(contract_addr, &mut event_data);
+
+    // Matching continues the trace the event was decoded in
+    let triggers = plan.triggers(&event_data.event_name);
+    let matching = telemetry::start(
+        "match",
+        SpanKind::Internal,
+        &telemetry::resume(event_data.trace.as_deref()),
+        vec![
+            KeyValue::new("contract.address", contract_addr.to_string()),
+            KeyValue::new("event.name", event_data.event_name.clone()),
+            KeyValue::new("triggers.matched", triggers.len() as i64),
+        ],
+    );
+
+    // The event is metered once for each project whose triggers watch it
+    let mut projects: Vec<&str> = triggers.iter().map(|p| p.trigger.project_id.as_str()).collect();
+    projects.sort_unstable();
+    projects.dedup();
+    for project_id in projects {
+        triggr.metering.record_event(project_id);
+    }
+
+    // Events of mirrored contracts also run in the sandbox of their project
+    let mut namespaces = vec![event_data.namespace];
+    if event_data.namespace.is_live() && triggr.cache.mirrors_to_sandbox(contract_addr) {
+        namespaces.push(Namespace::Sandbox);
+    }
+
+    for namespace in namespaces {
+        let mut event_data = event_data.clone();
+        event_data.namespace = namespace;
+        executions.extend(spawn_executions(
+            triggr,
+            contract_addr,
+            &plan,
+            &event_data,
+            &matching,
+            batch.clone(),
+        ));
+    }
+
+    Ok(executions)
+}
+
+/// Spawn the executions of the triggers matching an event in the namespace of the event.
+/// Their document writes are deferred to `batch`, if any.
+fn spawn_executions(
+    triggr: &Triggr,
+    contract_addr: &str,
+    plan: &Arc<EvaluationPlan>,
+    event_data: &EventData,
+    matching: &Context,
+    batch: Option<Arc<BlockBatch>>,
+) -> Vec<JoinHandle<()>> {
+    let mut executions = Vec::new();
+    let triggers = plan.triggers(&event_data.event_name);
+
+    // Conditions shared by the triggers are evaluated once for the event
+    let evaluation = Arc::new(plan.evaluation());
+
+    // Executions of a strictly ordered contract run one at a time, in event order
+    let sequencer = triggr.sequencer.clone();
+    let sequence = triggr
+        .cache
+        .orders_strictly(contract_addr)
+        .then(|| format!("{contract_addr}:{:?}", event_data.namespace));
+
+    // Off-chain context is attached once, by the first execution that gets to it
+    let enrichment = triggr
+        .cache
+        .enrichment(contract_addr)
+        .map(|enrichment| (enrichment, Arc::new(OnceCell::new())));
+
+    // Spin up tasks to execute the active triggers watching the event
+    for planned in triggers {
+        let triggr = triggr.clone();
+        let contract_addr = contract_addr.to_string();
+        let mut event_data = event_data.clone();
+        let enrichment = enrichment.clone();
+        let (plan, planned, evaluation) = (plan.clone(), planned.clone(), evaluation.clone());
+        let execution = telemetry::start(
+            "execute",
+            SpanKind::Internal,
+            matching,
+            vec![
+                KeyValue::new("trigger.id", planned.trigger.id.clone()),
+                KeyValue::new("project.id", planned.trigger.project_id.clone()),
+            ],
+        );
+
+        triggr.pipeline.execution_started();
+        let crashed = (
+            triggr.clone(),
+            contract_addr.clone(),
+            planned.clone(),
+            event_data.clone(),
+        );
+        let execute = async move {
+            if let Some((enrichment, enriched)) = enrichment {
+                event_data = enriched
+                    .get_or_init(|| async {
+                        enrich::apply(&triggr.store, &enrichment, &mut event_data).await;
+                        event_data
+                    })
+                    .await
+                    .clone();
+            }
+
+            let project_id = &planned.trigger.project_id;
+            let watchlists = |name: &str, address: &str| {
+                in_watchlist(&triggr, project_id, name, address)
+            };
+            let actions = plan.actions(&planned, &event_data, &evaluation, &watchlists);
+
+            let trigger = planned.trigger.clone();
+            run_trigger(triggr, contract_addr, trigger, event_data, actions).await;
+        };
+
+        let guarded = async move {
+            let (triggr, contract_addr, planned, event_data) = crashed;
+
+            // A panicking execution is recorded instead of silently killing its task
+            if let Err(panic) = AssertUnwindSafe(execute).catch_unwind().await {
+                record_panic(&triggr, contract_addr, &planned.trigger, event_data, panic);
+            }
+            triggr.pipeline.execution_finished();
+        };
+        let guarded = BlockBatch::scope(batch.clone(), guarded);
+        let guarded = sequencer.after(sequence.clone(), guarded);
+        executions.push(tokio::task::spawn(guarded.with_context(execution)));
+    }
+
+    executions
+}
+
 /// Function to execute trigger.
-async fn execute_trigger(
+/// Every execution is recorded in the run log and the run is returned.
+pub(crate) async fn execute_trigger(
     triggr: Triggr,
     contract_addr: String,
     trigger: Trigger,
     event: EventData,
-) {
+) -> TriggerRun {
     // Get actions to execute
-    let actions = trigger
+    let actions = fired_actions(&triggr, &trigger, &event);
+
+    run_trigger(triggr, contract_addr, trigger, event, actions).await
+}
+
+/// Actions of the rules of a trigger that fire on an event.
+pub(crate) fn fired_actions(triggr: &Triggr, trigger: &Trigger, event: &EventData) -> Vec<Action> {
+    let watchlists = |name: &str, address: &str| {
+        in_watchlist(triggr, &trigger.project_id, name, address)
+    };
+
+    trigger
         .rules
         .iter()
-        .filter_map(|rule| DslExecutor::execute_rule(rule, &event))
+        .filter_map(|rule| DslExecutor::execute_rule(rule, event, &watchlists))
         .flatten()
-        .collect::<Vec<Action>>();
+        .collect()
+}
+
+/// Whether an address is on a watchlist of a project.
+/// Watchlists are read one lookup per checked address.
+fn in_watchlist(triggr: &Triggr, project_id: &str, name: &str, address: &str) -> bool {
+    triggr
+        .store
+        .in_watchlist(project_id, name, address)
+        .unwrap_or_else(|e| {
+            tracing::warn!("Failed to read watchlist {name}: {e}");
+            false
+        })
+}
+
+/// Record a run of a trigger and execute the actions of its rules that fired.
+async fn run_trigger(
+    triggr: Triggr,
+    contract_addr: String,
+    trigger: Trigger,
+    event: EventData,
+    actions: Vec<Action>,
+) -> TriggerRun {
+    // Record the run
+    let run = new_run(&contract_addr, &trigger, event.clone(), actions.len(), None);
+    let _ = TriggerStore::store_run(&*triggr.store, &run, run_log_retention());
+    triggr.metering.record_execution(&trigger.project_id);
+    telemetry::annotate(
+        &Context::current(),
+        vec![
+            KeyValue::new("run.id", run.id.clone()),
+            KeyValue::new("run.actions", run.actions as i64),
+        ],
+    );
 
+    let ran = !actions.is_empty();
     for action in actions {
-        // Execute actions and make db state changes
-        let _ = execute_actions(triggr.clone(), &trigger.project_id, action, event.clone()).await;
+        let span = telemetry::start(
+            "action",
+            SpanKind::Internal,
+            &Context::current(),
+            vec![KeyValue::new("action.kind", action.kind())],
+        );
+
+        // Execute actions and make db state changes, in the namespace of the event
+        // Changes are attributed to the trigger in subscription payloads
+        let source = ChangeSource::Trigger {
+            trigger_id: trigger.id.clone(),
+        };
+        let data_id = event.namespace.scope(&trigger.project_id);
+        let execution = execute_actions(
+            triggr.clone(),
+            &contract_addr,
+            &trigger,
+            &data_id,
+            action,
+            event.clone(),
+        );
+        let _ = source.scope(execution).with_context(span).await;
+    }
+
+    // Only the run time is saved, the trigger may have changed while its actions ran
+    if ran {
+        let now = Utc::now().timestamp_millis() as u64;
+        if let Err(e) = triggr.store.record_trigger_run(&contract_addr, &trigger.id, now) {
+            tracing::warn!("Failed to record the run of trigger {}: {e}", trigger.id);
+        }
+    }
+
+    run
+}
+
+/// Create the run log record of an execution, keeping the raw payload only when enabled.
+fn new_run(
+    contract_addr: &str,
+    trigger: &Trigger,
+    mut event: EventData,
+    actions: usize,
+    error: Option<String>,
+) -> TriggerRun {
+    if !store_raw_events() {
+        event.raw = None;
+    }
+
+    TriggerRun {
+        id: generate_uuid(),
+        trigger_id: trigger.id.clone(),
+        project_id: trigger.project_id.clone(),
+        contract_addr: contract_addr.to_string(),
+        event,
+        actions,
+        timestamp: Utc::now().timestamp_millis() as u64,
+        error,
+    }
+}
+
+/// Record an execution that panicked in the run log and the pipeline statistics.
+/// Actions executed before the panic are kept, they are not counted in the run.
+fn record_panic(
+    triggr: &Triggr,
+    contract_addr: String,
+    trigger: &Trigger,
+    event: EventData,
+    panic: Box<dyn Any + Send>,
+) {
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|m| m.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    tracing::error!(
+        "Execution of trigger {} on {contract_addr} panicked: {message}",
+        trigger.id
+    );
+
+    triggr.pipeline.execution_panicked();
+    triggr.metering.record_execution(&trigger.project_id);
+    telemetry::fail(&Context::current(), &message);
+
+    let run = new_run(&contract_addr, trigger, event, 0, Some(format!("Panicked: {message}")));
+    if let Err(e) = TriggerStore::store_run(&*triggr.store, &run, run_log_retention()) {
+        tracing::error!("Failed to record the panic of trigger {}: {e}", trigger.id);
+    }
+}
 
-        // Update modified timestamp
-        let mut updated_trigger = trigger.clone();
-        updated_trigger.last_run = Utc::now().timestamp_millis() as u64;
+/// Record a live chain event in the event log of its contract, served by the events API.
+/// Events without a block number (e.g. injected in dev mode) are not logged.
+fn log_event(triggr: &Triggr, contract_addr: &str, seq: u64, event: &EventData) {
+    let retention = event_log_blocks();
+    let Some(block_number) = event
+        .block_number
+        .filter(|_| retention > 0 && event.namespace.is_live())
+    else {
+        return;
+    };
 
-        // Save trigger
-        let _ = TriggerStore::store_trigger(&*triggr.store, &contract_addr, updated_trigger);
+    let mut event = event.clone();
+    if !store_raw_events() {
+        event.raw = None;
     }
+    if let Err(e) = triggr.store.log_event(contract_addr, block_number, seq, &event, retention) {
+        tracing::warn!("Failed to log an event of {contract_addr}: {e}");
+    }
+}
+
+/// Number of blocks the event log of a contract keeps, set with `TRIGGR_EVENT_LOG_BLOCKS`
+/// (0 disables the log).
+fn event_log_blocks() -> u64 {
+    std::env::var("TRIGGR_EVENT_LOG_BLOCKS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_EVENT_LOG_BLOCKS)
+}
+
+/// Number of milliseconds the run log of a project keeps runs for, set in days with
+/// `TRIGGR_RUN_LOG_DAYS` (0 keeps them forever).
+fn run_log_retention() -> u64 {
+    let days = std::env::var("TRIGGR_RUN_LOG_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_RUN_LOG_DAYS);
+    days.saturating_mul(24 * 60 * 60 * 1000)
+}
+
+/// Max number of items an `append` action without `max` keeps, set with
+/// `TRIGGR_APPEND_MAX_ITEMS`.
+fn append_max_items() -> usize {
+    std::env::var("TRIGGR_APPEND_MAX_ITEMS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|max| *max > 0)
+        .unwrap_or(DEFAULT_APPEND_MAX_ITEMS)
+}
+
+/// Whether raw event payloads should be persisted with trigger runs.
+fn store_raw_events() -> bool {
+    std::env::var("TRIGGR_STORE_RAW_EVENTS")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false)
 }
 
 /// Function to execute database actions and make database changes.
-async fn execute_actions(triggr: Triggr, project_id: &str, action: Action, event: EventData) {
+/// `project_id` is the id the data is stored under, in the namespace of the event.
+async fn execute_actions(
+    triggr: Triggr,
+    contract_addr: &str,
+    trigger: &Trigger,
+    project_id: &str,
+    action: Action,
+    event: EventData,
+) {
     // Unix timestamp
     let now = Utc::now().timestamp_millis() as u64;
 
@@ -97,23 +647,30 @@ async fn execute_actions(triggr: Triggr, project_id: &str, action: Action, event
             collection,
             mut id,
             fields,
+            merge,
         } => {
             // If ID was autogenerated, update it to prevent override
             if is_uuid(&id) {
                 id = generate_uuid()
             };
 
-            // We will check if any action field references event data
-            let new_fields = if fields
-                .iter()
-                .any(|(_, val)| val.to_string().contains("events."))
-            {
-                // Transpose it with event data
-                transpose_data_fields(fields, event)
-            } else {
-                fields
+            // Fill in event data, the write is dropped if some of it can't be resolved
+            let Some(new_fields) = transpose_data_fields(fields, &event) else {
+                return;
             };
 
+            // Only touch the given fields, keeping the rest of the document
+            if merge {
+                if let Err(e) = triggr
+                    .store
+                    .merge_document(project_id, &collection, &id, json!(new_fields))
+                    .await
+                {
+                    tracing::warn!("Failed to merge into {collection}:{id}: {e}");
+                }
+                return;
+            }
+
             // Construct document
             let doc = Document {
                 id,
@@ -123,16 +680,31 @@ async fn execute_actions(triggr: Triggr, project_id: &str, action: Action, event
                     updated_at: now,
                     version: None,
                     tags: Default::default(),
+                    hash: None,
                 },
             };
 
             // Execute database operation
-            // Make sure there are no fields left to transpose
-            if !new_fields
-                .iter()
-                .any(|(_, val)| val.to_string().contains("events."))
+            let _ = DocumentStore::update(&*triggr.store, project_id, &collection, doc).await;
+        }
+        // Push values onto array fields of a document
+        Action::Append {
+            collection,
+            id,
+            fields,
+            max,
+        } => {
+            let Some(items) = transpose_data_fields(fields, &event) else {
+                return;
+            };
+
+            let max = max.unwrap_or_else(append_max_items);
+            if let Err(e) = triggr
+                .store
+                .append_document(project_id, &collection, &id, items, max)
+                .await
             {
-                let _ = DocumentStore::update(&*triggr.store, project_id, &collection, doc).await;
+                tracing::warn!("Failed to append to {collection}:{id}: {e}");
             }
         }
         // Delete database entry
@@ -150,15 +722,9 @@ async fn execute_actions(triggr: Triggr, project_id: &str, action: Action, event
                 id = generate_uuid()
             };
             
-            // We will check if any action field references event data
-            let new_fields = if fields
-                .iter()
-                .any(|(_, val)| val.to_string().contains("events."))
-            {
-                // Transpose it with event data
-                transpose_data_fields(fields, event)
-            } else {
-                fields
+            // Fill in event data, the write is dropped if some of it can't be resolved
+            let Some(new_fields) = transpose_data_fields(fields, &event) else {
+                return;
             };
 
             // Construct document
@@ -170,71 +736,56 @@ async fn execute_actions(triggr: Triggr, project_id: &str, action: Action, event
                     updated_at: now,
                     version: None,
                     tags: Default::default(),
+                    hash: None,
                 },
             };
 
             // Execute database operation
-            // Make sure there are no fields left to transpose
-            if !new_fields
-                .iter()
-                .any(|(_, val)| val.to_string().contains("events."))
-            {
-                let _ = DocumentStore::insert(&*triggr.store, project_id, &collection, doc, false)
-                    .await;
+            let _ = DocumentStore::insert(&*triggr.store, project_id, &collection, doc, false).await;
+        }
+
+        // Set a key-value entry
+        Action::SetKv { key, value } => {
+            // Unresolved event references are not stored
+            let fields = HashMap::from([(key.clone(), value)]);
+            if let Some(fields) = transpose_data_fields(fields, &event) {
+                let _ = triggr.store.put_kv(project_id, &key, &fields[&key]);
             }
         }
 
-        // TODO!
-        Action::Notify { .. } => {}
+        // Deliver a notification to the webhook of the trigger
+        Action::Notify { message } => {
+            let Some(url) = &trigger.webhook else {
+                tracing::debug!("Trigger {} has no webhook, notification dropped", trigger.id);
+                return;
+            };
+
+            let (message, _) = template::render_message(&message, &event);
+            let notification = webhook::Notification {
+                trigger_id: &trigger.id,
+                project_id: &trigger.project_id,
+                contract_addr,
+                message,
+                event: &event,
+                timestamp: now,
+            };
+            triggr.outbox.dispatch(url, &notification);
+        }
     }
 }
 
-/// Transpose the fields in a document that references event data
+/// Transpose the fields in a document that references event data: whole references
+/// (`events.ValueChanged.value`), computed values and `${...}` placeholders inside strings.
+/// Returns `None` if a reference can't be resolved against the event.
 fn transpose_data_fields(
-    mut fields: HashMap<String, Value>,
-    event: EventData,
-) -> HashMap<String, Value> {
-    // Iterate through all fields and replace event references
-    for (_, field_value) in fields.iter_mut() {
-        if let Some(value_str) = field_value.as_str() {
-            // Check if this is an event reference (e.g., "events.ValueChanged.value")
-            if value_str.starts_with("events.") {
-                let parts: Vec<&str> = value_str.split('.').collect();
-
-                // Format: events.<EventName>.<field_name>
-                if parts.len() == 3 && parts[0] == "events" {
-                    let referenced_event = parts[1];
-                    let referenced_field = parts[2];
-
-                    // Check if this matches the current event
-                    if referenced_event.eq_ignore_ascii_case(&event.event_name) {
-                        // Try to get the actual value from event fields
-                        if let Some(actual_value) = event.fields.get(referenced_field) {
-                            // Clone and process the value
-                            let processed_value = util::process_event_value(actual_value);
-                            *field_value = processed_value;
-                        }
-                    }
-                }
+    fields: HashMap<String, Value>,
+    event: &EventData,
+) -> Option<HashMap<String, Value>> {
+    template::render_fields(fields, event)
+        .inspect_err(|errors| {
+            for error in errors {
+                tracing::debug!("Failed to resolve '{}': {}", error.placeholder, error.reason);
             }
-        } else if let Some(obj) = field_value.as_object_mut() {
-            // Recursively handle nested objects
-            let nested_fields: HashMap<String, Value> =
-                obj.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
-            let transposed = transpose_data_fields(nested_fields, event.clone());
-            *obj = transposed.into_iter().collect();
-        } else if let Some(arr) = field_value.as_array_mut() {
-            // Recursively handle arrays
-            for item in arr.iter_mut() {
-                if let Some(obj) = item.as_object_mut() {
-                    let nested_fields: HashMap<String, Value> =
-                        obj.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
-                    let transposed = transpose_data_fields(nested_fields, event.clone());
-                    *obj = transposed.into_iter().collect();
-                }
-            }
-        }
-    }
-
-    fields
+        })
+        .ok()
 }
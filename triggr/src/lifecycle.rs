@@ -0,0 +1,125 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the state machines documents of a collection can follow.
+// A state machine lists the states of a designated field (`status` by default) and the transitions
+// allowed between them. It is checked on every write of the collection, from the REST API and from
+// trigger actions alike, and the transitions it allows are published to subscribers.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::name::Name;
+
+/// Field a state machine applies to when none is given.
+const DEFAULT_STATE_FIELD: &str = "status";
+
+fn default_field() -> String {
+    DEFAULT_STATE_FIELD.to_string()
+}
+
+/// States and allowed transitions of a field of the documents of a collection.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct StateMachine {
+    /// Document field holding the state
+    #[serde(default = "default_field")]
+    pub field: String,
+    /// Every state of the field
+    pub states: Vec<String>,
+    /// States a new document can start in (the first state if empty)
+    #[serde(default)]
+    pub initial: Vec<String>,
+    /// States reachable from each state (e.g. `pending -> [confirmed, cancelled]`)
+    #[serde(default)]
+    pub transitions: HashMap<String, Vec<String>>,
+}
+
+/// A change of state of a document.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Transition {
+    /// Field holding the state
+    pub field: String,
+    /// Previous state (none for a new document or one that had no valid state)
+    pub from: Option<String>,
+    /// New state
+    pub to: String,
+}
+
+impl StateMachine {
+    /// Check that the definition is consistent.
+    pub fn validate(&self) -> Result<(), String> {
+        Name::internal("state field", &self.field).map_err(|e| e.to_string())?;
+        if self.states.is_empty() {
+            return Err("At least one state is required".to_string());
+        }
+
+        let mut states = HashSet::new();
+        for state in &self.states {
+            if !states.insert(state.as_str()) {
+                return Err(format!("State '{state}' is listed twice"));
+            }
+        }
+
+        let unknown = self
+            .initial
+            .iter()
+            .chain(self.transitions.keys())
+            .chain(self.transitions.values().flatten())
+            .find(|state| !states.contains(state.as_str()));
+        match unknown {
+            Some(state) => Err(format!("Unknown state '{state}'")),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the state of a document written over its stored copy (if any).
+    /// Returns the transition made, if the state changed.
+    /// A stored document without a valid state (e.g. written before the state machine was
+    /// defined) can move to any state.
+    pub fn check(&self, stored: Option<&Value>, data: &Value) -> Result<Option<Transition>, String> {
+        let to = match data.get(&self.field) {
+            Some(Value::String(state)) if self.states.contains(state) => state,
+            Some(value) => {
+                return Err(format!("{value} is not a state, expected one of {:?}", self.states));
+            }
+            None => return Err(format!("Missing state, expected one of {:?}", self.states)),
+        };
+
+        let Some(stored) = stored else {
+            let initial = match self.initial.is_empty() {
+                true => &self.states[..1],
+                false => &self.initial[..],
+            };
+            if !initial.contains(to) {
+                return Err(format!("New documents must start in one of {initial:?}"));
+            }
+            return Ok(Some(self.transition(None, to)));
+        };
+
+        let from = stored
+            .get(&self.field)
+            .and_then(Value::as_str)
+            .filter(|state| self.states.iter().any(|s| s == state));
+        match from {
+            Some(from) if from == to => Ok(None),
+            Some(from) => {
+                let allowed = self.transitions.get(from).is_some_and(|next| next.contains(to));
+                if !allowed {
+                    return Err(format!("Cannot move from '{from}' to '{to}'"));
+                }
+                Ok(Some(self.transition(Some(from), to)))
+            }
+            None => Ok(Some(self.transition(None, to))),
+        }
+    }
+
+    fn transition(&self, from: Option<&str>, to: &str) -> Transition {
+        Transition {
+            field: self.field.clone(),
+            from: from.map(str::to_string),
+            to: to.to_string(),
+        }
+    }
+}
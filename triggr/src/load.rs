@@ -0,0 +1,197 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module generates synthetic event load for capacity planning.
+// Synthetic events take the same path as decoded chain events: the event queue, routing and triggers.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{chain::polkadot::prelude::EventData, namespace::Namespace, EventSender, Pipeline};
+
+/// Highest rate (events/sec) a synthetic load can be run at.
+pub const MAX_LOAD_RATE: u32 = 100_000;
+
+/// Interval at which batches of synthetic events are sent.
+const LOAD_TICK: Duration = Duration::from_millis(10);
+
+/// Shape of a synthetic load.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct SyntheticLoad {
+    /// Contract the events are attributed to
+    pub contract_addr: String,
+    /// Name of the emitted event
+    pub event_name: String,
+    /// Event fields. `"$seq"` is replaced by the event's sequence number and `"$random"` by a random number
+    #[serde(default)]
+    pub fields: HashMap<String, Value>,
+    /// Events per second
+    pub rate: u32,
+    /// Duration of the run (seconds), until stopped when unset
+    pub duration_secs: Option<u64>,
+}
+
+/// Status of the synthetic load generator.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct LoadStatus {
+    /// Whether a load is running
+    pub running: bool,
+    /// Shape of the current or last load
+    pub load: Option<SyntheticLoad>,
+    /// Events sent by the current or last load
+    pub sent: u64,
+    /// Start of the current or last load (unix ms)
+    pub started_at: Option<u64>,
+}
+
+/// A load that was started.
+struct Run {
+    load: SyntheticLoad,
+    started_at: u64,
+    task: JoinHandle<()>,
+}
+
+/// Injects synthetic events into the trigger engine.
+#[derive(Default)]
+pub struct LoadGenerator {
+    /// Queue of the trigger engine, set when the engine starts
+    sender: OnceLock<EventSender>,
+    run: Mutex<Option<Run>>,
+    sent: Arc<AtomicU64>,
+}
+
+impl LoadGenerator {
+    /// Attach the generator to the trigger engine.
+    pub fn attach(&self, sender: EventSender) {
+        let _ = self.sender.set(sender);
+    }
+
+    /// Start a load, replacing the running one if any.
+    pub fn start(&self, load: SyntheticLoad, pipeline: Arc<Pipeline>) -> Result<(), String> {
+        if load.rate == 0 || load.rate > MAX_LOAD_RATE {
+            return Err(format!("Rate must be between 1 and {MAX_LOAD_RATE} events/sec"));
+        }
+        let Some(sender) = self.sender.get().cloned() else {
+            return Err("The trigger engine is not running".to_string());
+        };
+
+        self.stop();
+        self.sent.store(0, Ordering::Relaxed);
+
+        info!(
+            "📈 Starting synthetic load of {} {} event(s)/sec on {}",
+            load.rate, load.event_name, load.contract_addr
+        );
+
+        let task = tokio::task::spawn(generate(load.clone(), sender, pipeline, self.sent.clone()));
+        if let Ok(mut run) = self.run.lock() {
+            *run = Some(Run {
+                load,
+                started_at: Utc::now().timestamp_millis() as u64,
+                task,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Stop the running load. Returns whether one was running.
+    pub fn stop(&self) -> bool {
+        let Ok(run) = self.run.lock() else {
+            return false;
+        };
+
+        match run.as_ref().filter(|r| !r.task.is_finished()) {
+            Some(run) => {
+                run.task.abort();
+                info!("📉 Stopped synthetic load");
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return the generator status.
+    pub fn status(&self) -> LoadStatus {
+        let run = self.run.lock().ok();
+        let run = run.as_ref().and_then(|r| r.as_ref());
+
+        LoadStatus {
+            running: run.is_some_and(|r| !r.task.is_finished()),
+            load: run.map(|r| r.load.clone()),
+            sent: self.sent.load(Ordering::Relaxed),
+            started_at: run.map(|r| r.started_at),
+        }
+    }
+}
+
+/// Send events at the requested rate until the load's duration elapses.
+async fn generate(
+    load: SyntheticLoad,
+    sender: EventSender,
+    pipeline: Arc<Pipeline>,
+    sent: Arc<AtomicU64>,
+) {
+    let contract_addr = load.contract_addr.to_lowercase();
+    let duration = load.duration_secs.map(Duration::from_secs);
+    let start = Instant::now();
+    let mut interval = tokio::time::interval(LOAD_TICK);
+    let mut seq = 0u64;
+
+    loop {
+        interval.tick().await;
+        let elapsed = start.elapsed();
+        if duration.is_some_and(|d| elapsed >= d) {
+            break;
+        }
+
+        // Catch up with the rate, even when sending fell behind
+        let due = (elapsed.as_secs_f64() * load.rate as f64) as u64;
+        while seq < due {
+            let event = EventData {
+                event_name: load.event_name.clone(),
+                fields: load
+                    .fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), fill(v, seq)))
+                    .collect(),
+                raw: None,
+                scores: HashMap::new(),
+                trace: None,
+                namespace: Namespace::Live,
+                block: None,
+                block_number: None,
+            };
+
+            pipeline.enqueued();
+            if sender.send(contract_addr.clone(), event).await.is_err() {
+                return;
+            }
+            seq += 1;
+            sent.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    info!("📉 Synthetic load finished after {seq} event(s)");
+}
+
+/// Replace the placeholders of a field value.
+fn fill(value: &Value, seq: u64) -> Value {
+    match value.as_str() {
+        Some("$seq") => Value::from(seq),
+        Some("$random") => Value::from(rand::random::<u64>()),
+        _ => value.clone(),
+    }
+}
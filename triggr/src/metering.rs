@@ -0,0 +1,326 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the usage metering of projects, the raw data hosted instances invoice from.
+// Events processed, trigger executions and webhook deliveries are counted per project. On a
+// schedule, every project gets a usage record for the period (with the number of documents it
+// stores) and the records are exported to the sink set in `TRIGGR_METERING_SINK`: a JSON lines
+// file, a webhook or a collection of a project. Counts of a failed export are carried over.
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use chrono::Utc;
+use dashmap::DashMap;
+use opentelemetry::KeyValue;
+use serde::Serialize;
+use serde_json::json;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    name::Name,
+    namespace::Namespace,
+    prelude::{DocMetadata, Document, DocumentStore, StorageResult, Triggr},
+    webhook,
+};
+
+/// Default interval between two exports (seconds).
+const DEFAULT_METERING_INTERVAL_SECS: u64 = 3600;
+
+/// Usage counters of a project for the current period.
+#[derive(Default)]
+struct Counters {
+    events: AtomicU64,
+    executions: AtomicU64,
+    webhooks: AtomicU64,
+}
+
+/// Usage of a project over a period.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct UsageRecord {
+    pub project_id: String,
+    /// Start of the period (unix ms)
+    pub period_start: u64,
+    /// End of the period (unix ms)
+    pub period_end: u64,
+    /// Events that reached the triggers of the project
+    pub events: u64,
+    /// Trigger executions
+    pub executions: u64,
+    /// Successful webhook deliveries
+    pub webhooks: u64,
+    /// Documents stored at the end of the period, in both namespaces
+    pub documents: u64,
+}
+
+/// Where usage records are exported.
+#[derive(Clone, Debug)]
+pub enum MeteringSink {
+    /// Appended to a file, one JSON record per line
+    File(PathBuf),
+    /// POSTed to a webhook as a JSON array
+    Webhook(String),
+    /// Stored as documents of a collection of a project
+    Collection { project_id: String, collection: String },
+}
+
+impl MeteringSink {
+    /// Parse a sink: `file:<path>`, `webhook:<url>` or `collection:<project_id>/<collection>`.
+    pub fn parse(sink: &str) -> Result<Self, String> {
+        let (kind, target) = sink
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid metering sink '{sink}'"))?;
+
+        match kind {
+            "file" => Ok(Self::File(PathBuf::from(target))),
+            "webhook" => {
+                webhook::validate_url(target)?;
+                Ok(Self::Webhook(target.to_string()))
+            }
+            "collection" => {
+                let (project_id, collection) = target
+                    .split_once('/')
+                    .ok_or("Invalid collection sink, expected collection:<project_id>/<name>")?;
+                Name::collection(collection).map_err(|e| e.to_string())?;
+                Ok(Self::Collection {
+                    project_id: project_id.to_string(),
+                    collection: collection.to_string(),
+                })
+            }
+            other => Err(format!(
+                "Unknown metering sink '{other}', expected file, webhook or collection"
+            )),
+        }
+    }
+}
+
+/// Usage counters of the projects, drained into records at every export.
+pub struct Metering {
+    counters: DashMap<String, Counters>,
+    /// Start of the current period (unix ms)
+    period_start: AtomicU64,
+}
+
+impl Default for Metering {
+    fn default() -> Self {
+        Self {
+            counters: DashMap::new(),
+            period_start: AtomicU64::new(Utc::now().timestamp_millis() as u64),
+        }
+    }
+}
+
+impl Metering {
+    /// Count an event reaching the triggers of a project.
+    pub fn record_event(&self, project_id: &str) {
+        self.count(project_id, |c| &c.events);
+    }
+
+    /// Count an execution of a trigger of a project.
+    pub fn record_execution(&self, project_id: &str) {
+        self.count(project_id, |c| &c.executions);
+    }
+
+    /// Count a webhook delivered for a project.
+    pub fn record_webhook(&self, project_id: &str) {
+        self.count(project_id, |c| &c.webhooks);
+    }
+
+    fn count(&self, project_id: &str, counter: impl Fn(&Counters) -> &AtomicU64) {
+        if let Some(counters) = self.counters.get(project_id) {
+            counter(&counters).fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let counters = self.counters.entry(project_id.to_string()).or_default();
+        counter(&counters).fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Return the usage of every project over the current period, without ending it.
+    pub fn usage(&self, triggr: &Triggr) -> StorageResult<Vec<UsageRecord>> {
+        let now = Utc::now().timestamp_millis() as u64;
+        let start = self.period_start.load(Ordering::Relaxed);
+
+        self.records(triggr, start, now, |counters| {
+            [&counters.events, &counters.executions, &counters.webhooks]
+                .map(|counter| counter.load(Ordering::Relaxed))
+        })
+    }
+
+    /// End the current period and return the usage of every project over it.
+    fn drain(&self, triggr: &Triggr) -> StorageResult<Vec<UsageRecord>> {
+        let now = Utc::now().timestamp_millis() as u64;
+        let start = self.period_start.load(Ordering::Relaxed);
+
+        let records = self.records(triggr, start, now, |counters| {
+            [&counters.events, &counters.executions, &counters.webhooks]
+                .map(|counter| counter.swap(0, Ordering::Relaxed))
+        })?;
+        self.period_start.store(now, Ordering::Relaxed);
+
+        Ok(records)
+    }
+
+    /// Carry the counts of records that could not be exported over to the current period.
+    fn restore(&self, records: &[UsageRecord]) {
+        for record in records {
+            let counters = self.counters.entry(record.project_id.clone()).or_default();
+            counters.events.fetch_add(record.events, Ordering::Relaxed);
+            counters.executions.fetch_add(record.executions, Ordering::Relaxed);
+            counters.webhooks.fetch_add(record.webhooks, Ordering::Relaxed);
+        }
+        if let Some(start) = records.iter().map(|r| r.period_start).min() {
+            self.period_start.fetch_min(start, Ordering::Relaxed);
+        }
+    }
+
+    /// Build the records of every project, reading its counts with `read`.
+    fn records(
+        &self,
+        triggr: &Triggr,
+        period_start: u64,
+        period_end: u64,
+        read: impl Fn(&Counters) -> [u64; 3],
+    ) -> StorageResult<Vec<UsageRecord>> {
+        // Documents are counted first, so a storage error leaves the counters untouched
+        let mut documents = Vec::new();
+        for project in triggr.store.all_projects()? {
+            let mut count = 0;
+            for namespace in [Namespace::Live, Namespace::Sandbox] {
+                count += DocumentStore::list_collections(
+                    &*triggr.store,
+                    &namespace.scope(&project.id),
+                )?
+                .iter()
+                .map(|c| c.count as u64)
+                .sum::<u64>();
+            }
+            documents.push((project.id, count));
+        }
+
+        let records: Vec<UsageRecord> = documents
+            .into_iter()
+            .map(|(project_id, documents)| {
+                let [events, executions, webhooks] = self
+                    .counters
+                    .get(&project_id)
+                    .map(|counters| read(&counters))
+                    .unwrap_or_default();
+
+                UsageRecord {
+                    project_id,
+                    period_start,
+                    period_end,
+                    events,
+                    executions,
+                    webhooks,
+                    documents,
+                }
+            })
+            .collect();
+
+        // Deleted projects are not billed
+        self.counters
+            .retain(|id, _| records.iter().any(|r| r.project_id == *id));
+
+        Ok(records)
+    }
+}
+
+/// Export usage records to the sink set in `TRIGGR_METERING_SINK`, every
+/// `TRIGGR_METERING_INTERVAL_SECS` seconds.
+pub async fn run(triggr: Triggr) {
+    let Ok(sink) = std::env::var("TRIGGR_METERING_SINK") else {
+        return;
+    };
+    let sink = match MeteringSink::parse(&sink) {
+        Ok(sink) => sink,
+        Err(e) => {
+            warn!("Usage metering is disabled: {e}");
+            return;
+        }
+    };
+    let secs = std::env::var("TRIGGR_METERING_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_METERING_INTERVAL_SECS)
+        .max(1);
+
+    info!("📊 Exporting usage records every {secs}s");
+    let mut interval = tokio::time::interval(Duration::from_secs(secs));
+    // The first tick completes immediately, before anything was counted
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+
+        let records = match triggr.metering.drain(&triggr) {
+            Ok(records) => records,
+            Err(e) => {
+                warn!("Failed to read usage: {e}");
+                continue;
+            }
+        };
+        if let Err(e) = export(&triggr, &sink, &records).await {
+            warn!("Failed to export usage records, carrying them over: {e}");
+            triggr.metering.restore(&records);
+        }
+    }
+}
+
+/// Write usage records to a sink.
+async fn export(
+    triggr: &Triggr,
+    sink: &MeteringSink,
+    records: &[UsageRecord],
+) -> Result<(), String> {
+    match sink {
+        MeteringSink::File(path) => {
+            let mut lines = String::new();
+            for record in records {
+                lines.push_str(&serde_json::to_string(record).map_err(|e| e.to_string())?);
+                lines.push('\n');
+            }
+
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await
+                .map_err(|e| e.to_string())?;
+            file.write_all(lines.as_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+            file.flush().await.map_err(|e| e.to_string())
+        }
+        MeteringSink::Webhook(url) => {
+            let body = serde_json::to_vec(records).map_err(|e| e.to_string())?;
+            let attributes = vec![KeyValue::new("metering.records", records.len() as i64)];
+            webhook::deliver(url, body, attributes).await
+        }
+        MeteringSink::Collection {
+            project_id,
+            collection,
+        } => {
+            for record in records {
+                let doc = Document {
+                    id: format!("{}-{}", record.project_id, record.period_end),
+                    data: json!(record),
+                    metadata: DocMetadata {
+                        created_at: record.period_end,
+                        updated_at: record.period_end,
+                        version: None,
+                        tags: vec!["usage".to_string()],
+                        hash: None,
+                    },
+                };
+                DocumentStore::insert(&*triggr.store, project_id, collection, doc, false)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+    }
+}
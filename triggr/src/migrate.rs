@@ -0,0 +1,253 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module migrates stored data to new formats at startup.
+// Each database carries a schema version. Migrations newer than the version of their database
+// are run in order, and the version is bumped after each one succeeds.
+// Databases can be backed up before they are migrated, and migrations can be dry-run.
+
+use std::path::PathBuf;
+
+use chrono::Utc;
+use sled::Db;
+use tracing::{info, warn};
+
+use crate::{
+    codec::Codec,
+    prelude::{StorageError, StorageResult},
+    storage::Sled,
+};
+
+/// A change to the format of the data of a database.
+pub struct Migration {
+    /// Database the migration rewrites
+    pub db: &'static str,
+    /// Schema version of the database once migrated
+    pub version: u32,
+    /// What the migration changes, as written in logs
+    pub description: &'static str,
+    /// Rewrite the data and return the number of records changed.
+    /// On a dry run, count the records without changing them.
+    pub run: fn(&Sled, bool) -> StorageResult<usize>,
+}
+
+/// Every migration, ordered by version within each database.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        db: "projects",
+        version: 1,
+        description: "index projects by the hash of their API key",
+        run: Sled::migrate_project_keys,
+    },
+    Migration {
+        db: "app",
+        version: 1,
+        description: "move documents and attachments into per-project trees",
+        run: Sled::migrate_project_trees,
+    },
+    Migration {
+        db: "triggers",
+        version: 1,
+        description: "move trigger runs into per-project run logs",
+        run: Sled::migrate_run_trees,
+    },
+];
+
+/// Called with the name and handle of a database before its pending migrations run.
+pub type BackupHook = Box<dyn Fn(&str, &Db) -> StorageResult<()> + Send + Sync>;
+
+/// How migrations are run.
+#[derive(Default)]
+pub struct MigrationOptions {
+    /// Report what would change without changing anything
+    pub dry_run: bool,
+    /// Backup taken before a database is migrated
+    pub backup: Option<BackupHook>,
+}
+
+impl MigrationOptions {
+    /// Read the options from `TRIGGR_MIGRATE_DRY_RUN` and `TRIGGR_MIGRATE_BACKUP_DIR`.
+    pub fn from_env() -> Self {
+        let dry_run = std::env::var("TRIGGR_MIGRATE_DRY_RUN")
+            .is_ok_and(|v| matches!(v.to_lowercase().as_str(), "1" | "true"));
+        let backup = std::env::var("TRIGGR_MIGRATE_BACKUP_DIR")
+            .ok()
+            .map(|dir| backup_to(PathBuf::from(dir)));
+
+        Self { dry_run, backup }
+    }
+}
+
+/// Back databases up as CBOR files in a directory.
+/// A backup holds `(collection type, tree name, [key, value] pairs)` per tree, the shape
+/// `sled::Db::import` restores from.
+pub fn backup_to(dir: PathBuf) -> BackupHook {
+    Box::new(move |name, db| {
+        std::fs::create_dir_all(&dir)?;
+
+        let mut export = Vec::new();
+        for tree_name in db.tree_names() {
+            let tree = db.open_tree(&tree_name)?;
+            let entries = tree
+                .iter()
+                .map(|entry| entry.map(|(k, v)| vec![k.to_vec(), v.to_vec()]))
+                .collect::<Result<Vec<_>, _>>()?;
+            export.push((b"tree".to_vec(), tree_name.to_vec(), entries));
+        }
+
+        let path = dir.join(format!("{name}-{}.cbor", Utc::now().format("%Y%m%dT%H%M%S")));
+        std::fs::write(&path, Codec::Cbor.encode(&export)?)?;
+        info!("💾 Backed up the {name} database to {}", path.display());
+        Ok(())
+    })
+}
+
+/// Run the migrations the databases of a store have not seen yet.
+/// Returns the number of pending migrations, run or dry-run.
+pub fn run(store: &Sled, options: &MigrationOptions) -> StorageResult<usize> {
+    let mut ran = 0;
+    let mut backed_up = Vec::new();
+
+    for migration in MIGRATIONS {
+        if store.schema_version(migration.db) >= migration.version {
+            continue;
+        }
+
+        let db = store.database(migration.db).ok_or_else(|| {
+            StorageError::Other(format!("Unknown database '{}' in migration", migration.db))
+        })?;
+
+        // Back the database up once, before its first pending migration
+        if !options.dry_run && !backed_up.contains(&migration.db) {
+            if let Some(backup) = &options.backup {
+                backup(migration.db, db)?;
+            }
+            backed_up.push(migration.db);
+        }
+
+        let changed = (migration.run)(store, options.dry_run).map_err(|e| {
+            StorageError::Other(format!(
+                "Migration {} v{} ({}) failed: {e}",
+                migration.db, migration.version, migration.description
+            ))
+        })?;
+        ran += 1;
+        if options.dry_run {
+            info!(
+                "🧪 Migration {} v{} would {}: {changed} record(s)",
+                migration.db, migration.version, migration.description
+            );
+            continue;
+        }
+
+        store.set_schema_version(migration.db, migration.version)?;
+        info!(
+            "📦 Migrated {} to v{}, {}: {changed} record(s)",
+            migration.db, migration.version, migration.description
+        );
+    }
+
+    if options.dry_run && ran > 0 {
+        warn!("Migrations were dry-run, stored data was left unchanged");
+    }
+
+    Ok(ran)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::testing::TempStore;
+
+    /// Backup hook recording the databases it is called with.
+    fn recording_backup() -> (BackupHook, Arc<Mutex<Vec<String>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        let hook: BackupHook = Box::new(move |name, _| {
+            recorded.lock().unwrap().push(name.to_string());
+            Ok(())
+        });
+        (hook, calls)
+    }
+
+    fn versions(store: &Sled) -> Vec<u32> {
+        ["projects", "app", "triggers"]
+            .iter()
+            .map(|db| store.schema_version(db))
+            .collect()
+    }
+
+    #[test]
+    fn dry_run_leaves_data_and_versions_unchanged() {
+        let temp = TempStore::new().unwrap();
+        let projects = temp.store.database("projects").unwrap();
+        projects.insert("plain-api-key", "project").unwrap();
+
+        let (backup, calls) = recording_backup();
+        let options = MigrationOptions {
+            dry_run: true,
+            backup: Some(backup),
+        };
+
+        assert_eq!(run(&temp.store, &options).unwrap(), MIGRATIONS.len());
+        assert_eq!(versions(&temp.store), vec![0, 0, 0]);
+        assert!(projects.contains_key("plain-api-key").unwrap());
+        assert!(calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn backs_up_each_database_once_before_migrating_it() {
+        let temp = TempStore::new().unwrap();
+        let (backup, calls) = recording_backup();
+        let options = MigrationOptions {
+            dry_run: false,
+            backup: Some(backup),
+        };
+
+        assert_eq!(run(&temp.store, &options).unwrap(), MIGRATIONS.len());
+        assert_eq!(*calls.lock().unwrap(), vec!["projects", "app", "triggers"]);
+        assert_eq!(versions(&temp.store), vec![1, 1, 1]);
+
+        // Nothing is pending anymore
+        assert_eq!(run(&temp.store, &options).unwrap(), 0);
+        assert_eq!(calls.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn failed_backup_stops_the_migration() {
+        let temp = TempStore::new().unwrap();
+        let options = MigrationOptions {
+            dry_run: false,
+            backup: Some(Box::new(|_, _| Err("disk full".into()))),
+        };
+
+        assert!(run(&temp.store, &options).is_err());
+        assert_eq!(versions(&temp.store), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn backup_to_writes_an_importable_export() {
+        let temp = TempStore::new().unwrap();
+        let app = temp.store.database("app").unwrap();
+        app.insert("document::p::c::d", "{}").unwrap();
+
+        let dir = temp.dir.path().join("backups");
+        backup_to(dir.clone())("app", app).unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let bytes = std::fs::read(files[0].as_ref().unwrap().path()).unwrap();
+        type Export = Vec<(Vec<u8>, Vec<u8>, Vec<Vec<Vec<u8>>>)>;
+        let export: Export = Codec::decode(&bytes).unwrap();
+
+        let restored = sled::Config::new().temporary(true).open().unwrap();
+        restored.import(
+            export
+                .into_iter()
+                .map(|(kind, name, entries)| (kind, name, entries.into_iter()))
+                .collect(),
+        );
+        assert_eq!(restored.get("document::p::c::d").unwrap().unwrap(), "{}");
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the validated `Name` type used for collection names and document ids.
+// Names end up inside `::` separated storage keys, so anything that could break prefix scans
+// (separators, whitespace, control characters, giant inputs) is rejected before it reaches storage.
+
+use std::{fmt, ops::Deref};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Max length of a name in bytes.
+pub const MAX_NAME_LEN: usize = 128;
+
+/// Prefix reserved for internal names (e.g. `__placeholder__`).
+pub const RESERVED_PREFIX: &str = "__";
+
+/// Why a name was rejected.
+#[derive(Debug, Clone, Error)]
+#[error("Invalid {kind} '{name}': {reason}")]
+pub struct NameError {
+    /// What the name identifies (collection, document id, ...)
+    pub kind: &'static str,
+    /// The rejected input (truncated)
+    pub name: String,
+    /// Why it was rejected
+    pub reason: String,
+}
+
+/// A validated collection name or document id.
+/// Allowed characters are ASCII letters, digits, `_`, `-`, `.` and `@`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Name(String);
+
+impl Name {
+    /// Validate a collection name.
+    pub fn collection(name: &str) -> Result<Self, NameError> {
+        Self::parse("collection name", name)
+    }
+
+    /// Validate a document id.
+    pub fn document_id(id: &str) -> Result<Self, NameError> {
+        Self::parse("document id", id)
+    }
+
+    /// Validate a key of a project's key-value namespace.
+    pub fn kv_key(key: &str) -> Result<Self, NameError> {
+        Self::parse("kv key", key)
+    }
+
+    /// Validate the name of a watchlist.
+    pub fn watchlist(name: &str) -> Result<Self, NameError> {
+        Self::parse("watchlist name", name)
+    }
+
+    /// Validate a name of the given kind.
+    pub fn parse(kind: &'static str, name: &str) -> Result<Self, NameError> {
+        Self::validate(kind, name, false)
+    }
+
+    /// Validate a name generated internally, which may use the reserved prefix.
+    /// Used by the storage key builder, which only cares about names being safe inside keys.
+    pub fn internal(kind: &'static str, name: &str) -> Result<Self, NameError> {
+        Self::validate(kind, name, true)
+    }
+
+    fn validate(kind: &'static str, name: &str, allow_reserved: bool) -> Result<Self, NameError> {
+        let error = |reason: String| NameError {
+            kind,
+            name: name.chars().take(32).collect(),
+            reason,
+        };
+
+        if name.is_empty() {
+            return Err(error("must not be empty".to_string()));
+        }
+        if name.len() > MAX_NAME_LEN {
+            return Err(error(format!("must be at most {MAX_NAME_LEN} bytes")));
+        }
+        if !allow_reserved && name.starts_with(RESERVED_PREFIX) {
+            return Err(error(format!("'{RESERVED_PREFIX}' prefix is reserved")));
+        }
+        if name == "." || name == ".." {
+            return Err(error("is reserved".to_string()));
+        }
+        if let Some(c) = name
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '@')))
+        {
+            return Err(error(format!("character {c:?} is not allowed")));
+        }
+
+        Ok(Self(name.to_string()))
+    }
+
+    /// Return the name as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Name {
+    type Error = NameError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse("name", &value)
+    }
+}
+
+impl From<Name> for String {
+    fn from(name: Name) -> Self {
+        name.0
+    }
+}
+
+impl Deref for Name {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Name {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
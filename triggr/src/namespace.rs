@@ -0,0 +1,79 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the data namespaces of projects.
+// Besides its `live` data, every project has a `sandbox` namespace sharing its metadata, triggers
+// and watchlists, but with its own documents, key-value entries and subscription topics. Requests
+// select it with the `x-triggr-namespace` header or a sandbox key, and events dispatched in it only
+// write sandbox data, so automation changes can be tried without touching real data.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::util::{encrypt, CryptoError};
+
+/// Header selecting the namespace of a request.
+pub const NAMESPACE_HEADER: &str = "x-triggr-namespace";
+
+/// Suffix of the storage id of sandbox data. Project ids can't contain `~`.
+const SANDBOX_SUFFIX: &str = "~sandbox";
+
+/// Suffix of the project key a sandbox key is encrypted from.
+const SANDBOX_KEY_SUFFIX: &str = "#sandbox";
+
+/// Data namespace of a project.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Namespace {
+    /// Real data (default)
+    #[default]
+    Live,
+    /// Test data, isolated from the live data
+    Sandbox,
+}
+
+impl Namespace {
+    /// Whether this is the live namespace.
+    pub fn is_live(&self) -> bool {
+        *self == Namespace::Live
+    }
+
+    /// Return the id the data of a project is stored under in this namespace.
+    pub fn scope(&self, project_id: &str) -> String {
+        match self {
+            Namespace::Live => project_id.to_string(),
+            Namespace::Sandbox => format!("{project_id}{SANDBOX_SUFFIX}"),
+        }
+    }
+}
+
+impl FromStr for Namespace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "live" => Ok(Namespace::Live),
+            "sandbox" => Ok(Namespace::Sandbox),
+            other => Err(format!("Unknown namespace '{other}', expected live or sandbox")),
+        }
+    }
+}
+
+/// Return the project a storage id belongs to, whatever its namespace.
+pub fn project_of(data_id: &str) -> &str {
+    data_id.strip_suffix(SANDBOX_SUFFIX).unwrap_or(data_id)
+}
+
+/// Split a decrypted key into the project key and the namespace it is restricted to, if any.
+pub fn split_key(key: &str) -> (&str, Option<Namespace>) {
+    match key.strip_suffix(SANDBOX_KEY_SUFFIX) {
+        Some(key) => (key, Some(Namespace::Sandbox)),
+        None => (key, None),
+    }
+}
+
+/// Return a key of a project that can only reach its sandbox.
+pub fn sandbox_key(project_key: &str, encryption_key: &str) -> Result<String, CryptoError> {
+    encrypt(&format!("{project_key}{SANDBOX_KEY_SUFFIX}"), encryption_key)
+}
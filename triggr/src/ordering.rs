@@ -0,0 +1,42 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module keeps the trigger executions of contracts in strict ordering mode in chain order.
+// Executions are normally spawned side by side, so the events of a contract can finish out of
+// order. In strict mode, each execution waits for the previous one of its contract, which
+// triggers keeping running balances or other sequence-sensitive state rely on.
+
+use std::future::Future;
+
+use dashmap::DashMap;
+use tokio::sync::oneshot;
+
+/// Chains the executions of strictly ordered contracts, each waiting for the previous one.
+#[derive(Default)]
+pub struct Sequencer {
+    /// Completion of the last execution queued, per contract and namespace
+    last: DashMap<String, oneshot::Receiver<()>>,
+}
+
+impl Sequencer {
+    /// Run `f` once the previous execution queued under `key` finished.
+    /// Without a key, `f` runs right away.
+    pub fn after<F: Future>(
+        &self,
+        key: Option<String>,
+        f: F,
+    ) -> impl Future<Output = F::Output> + use<F> {
+        let (done, finished) = oneshot::channel::<()>();
+        let previous = key.and_then(|key| self.last.insert(key, finished));
+
+        async move {
+            // Also resolves when the previous execution was cancelled
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+
+            let output = f.await;
+            drop(done);
+            output
+        }
+    }
+}
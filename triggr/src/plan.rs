@@ -0,0 +1,248 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the evaluation plans of contract triggers.
+// A plan is built from the stored triggers of a contract when an event arrives after they changed.
+// It groups the rules by event name, so an event only visits the rules watching it, and merges
+// identical conditions and sub-conditions across rules and triggers, so each is evaluated at most
+// once per event however many rules repeat it.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+use dashmap::DashMap;
+use sled::IVec;
+
+use crate::{
+    chain::polkadot::prelude::EventData,
+    dsl::{Action, Condition, DslExecutor, WatchlistLookup},
+    prelude::{StorageResult, Trigger, TriggerStore},
+    storage::Sled,
+};
+
+/// Results of the nodes of a plan while an event is evaluated.
+const UNKNOWN: u8 = 0;
+const FALSE: u8 = 1;
+const TRUE: u8 = 2;
+
+/// A condition of a plan. The operands of compound conditions are other nodes.
+#[derive(Debug)]
+enum Node {
+    Leaf(Condition),
+    And(usize, usize),
+    Or(usize, usize),
+}
+
+/// A rule of a plan: the node of its condition (if any) and its actions.
+#[derive(Debug)]
+struct PlannedRule {
+    condition: Option<usize>,
+    actions: Vec<Action>,
+}
+
+/// An active trigger, with its rules watching one event.
+#[derive(Debug)]
+pub struct PlannedTrigger {
+    pub trigger: Trigger,
+    rules: Vec<PlannedRule>,
+}
+
+/// Evaluation plan of the triggers of a contract.
+#[derive(Debug)]
+pub struct EvaluationPlan {
+    /// Stored triggers the plan was built from. Runs are recorded apart from them.
+    source: IVec,
+    /// Distinct conditions of the rules
+    nodes: Vec<Node>,
+    /// Lowercased event name -> Triggers watching it, in stored order
+    events: HashMap<String, Vec<Arc<PlannedTrigger>>>,
+}
+
+/// Results of the conditions of a plan for one event, shared by the triggers it runs.
+pub struct Evaluation {
+    results: Vec<AtomicU8>,
+}
+
+/// Builds the nodes of a plan, giving identical conditions the same node.
+#[derive(Default)]
+struct NodeBuilder {
+    nodes: Vec<Node>,
+    ids: HashMap<String, usize>,
+}
+
+impl NodeBuilder {
+    /// Return the node of a condition of a trigger of a project.
+    fn intern(&mut self, condition: &Condition, project_id: &str) -> usize {
+        let (node, key) = match condition {
+            Condition::And(left, right) => {
+                let left = self.intern(left, project_id);
+                let right = self.intern(right, project_id);
+                (Node::And(left, right), format!("and:{left}:{right}"))
+            }
+            Condition::Or(left, right) => {
+                let left = self.intern(left, project_id);
+                let right = self.intern(right, project_id);
+                (Node::Or(left, right), format!("or:{left}:{right}"))
+            }
+            // Watchlists belong to the project of the trigger, so memberships are shared within it
+            Condition::InWatchlist(..) | Condition::NotInWatchlist(..) => (
+                Node::Leaf(condition.clone()),
+                format!("{project_id}:{condition:?}"),
+            ),
+            _ => (Node::Leaf(condition.clone()), format!("{condition:?}")),
+        };
+
+        if let Some(id) = self.ids.get(&key) {
+            return *id;
+        }
+        self.nodes.push(node);
+        self.ids.insert(key, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+}
+
+impl EvaluationPlan {
+    /// Build the plan of the triggers of a contract. Disabled triggers are left out.
+    fn build(source: IVec, triggers: Vec<Trigger>) -> Self {
+        let mut builder = NodeBuilder::default();
+        let mut events: HashMap<String, Vec<Arc<PlannedTrigger>>> = HashMap::new();
+
+        for trigger in triggers.into_iter().filter(|t| t.active) {
+            // Split the rules of the trigger by event, keeping their order
+            let mut rules: HashMap<String, Vec<PlannedRule>> = HashMap::new();
+            for rule in &trigger.rules {
+                let condition = rule
+                    .condition
+                    .as_ref()
+                    .map(|c| builder.intern(c, &trigger.project_id));
+                rules
+                    .entry(rule.event_name.to_lowercase())
+                    .or_default()
+                    .push(PlannedRule {
+                        condition,
+                        actions: rule.actions.clone(),
+                    });
+            }
+
+            for (event_name, rules) in rules {
+                events
+                    .entry(event_name)
+                    .or_default()
+                    .push(Arc::new(PlannedTrigger {
+                        trigger: trigger.clone(),
+                        rules,
+                    }));
+            }
+        }
+
+        Self {
+            source,
+            nodes: builder.nodes,
+            events,
+        }
+    }
+
+    /// Return the active triggers watching an event.
+    pub fn triggers(&self, event_name: &str) -> &[Arc<PlannedTrigger>] {
+        self.events
+            .get(&event_name.to_lowercase())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Start the evaluation of an event.
+    pub fn evaluation(&self) -> Evaluation {
+        Evaluation {
+            results: self.nodes.iter().map(|_| AtomicU8::new(UNKNOWN)).collect(),
+        }
+    }
+
+    /// Return the actions of the rules of a trigger that fire on an event.
+    pub fn actions(
+        &self,
+        planned: &PlannedTrigger,
+        event: &EventData,
+        evaluation: &Evaluation,
+        watchlists: WatchlistLookup,
+    ) -> Vec<Action> {
+        planned
+            .rules
+            .iter()
+            .filter(|rule| {
+                rule.condition
+                    .is_none_or(|node| self.evaluate(node, event, evaluation, watchlists))
+            })
+            .flat_map(|rule| rule.actions.clone())
+            .collect()
+    }
+
+    /// Evaluate a node, reusing its result if another rule already needed it.
+    fn evaluate(
+        &self,
+        node: usize,
+        event: &EventData,
+        evaluation: &Evaluation,
+        watchlists: WatchlistLookup,
+    ) -> bool {
+        match evaluation.results[node].load(Ordering::Relaxed) {
+            TRUE => return true,
+            FALSE => return false,
+            _ => {}
+        }
+
+        let result = match &self.nodes[node] {
+            Node::Leaf(condition) => DslExecutor::evaluate(condition, event, watchlists, None),
+            Node::And(left, right) => {
+                self.evaluate(*left, event, evaluation, watchlists)
+                    && self.evaluate(*right, event, evaluation, watchlists)
+            }
+            Node::Or(left, right) => {
+                self.evaluate(*left, event, evaluation, watchlists)
+                    || self.evaluate(*right, event, evaluation, watchlists)
+            }
+        };
+
+        let stored = if result { TRUE } else { FALSE };
+        evaluation.results[node].store(stored, Ordering::Relaxed);
+        result
+    }
+}
+
+/// Evaluation plans of the contracts, rebuilt when their stored triggers change.
+#[derive(Default)]
+pub struct PlanCache {
+    plans: DashMap<String, Arc<EvaluationPlan>>,
+}
+
+impl PlanCache {
+    /// Return the plan of the triggers of a contract (`None` if nobody set triggers on it).
+    pub fn get(
+        &self,
+        store: &Sled,
+        contract_addr: &str,
+    ) -> StorageResult<Option<Arc<EvaluationPlan>>> {
+        let Some(source) = store.triggers.get(contract_addr.as_bytes())? else {
+            self.plans.remove(contract_addr);
+            return Ok(None);
+        };
+
+        // The plan is current as long as the stored triggers are unchanged
+        if let Some(plan) = self
+            .plans
+            .get(contract_addr)
+            .filter(|plan| plan.source == source)
+        {
+            return Ok(Some(plan.clone()));
+        }
+
+        let triggers = TriggerStore::list_triggers(store, contract_addr)?;
+        let plan = Arc::new(EvaluationPlan::build(source, triggers));
+        self.plans.insert(contract_addr.to_string(), plan.clone());
+
+        Ok(Some(plan))
+    }
+}
@@ -5,21 +5,51 @@
 #![allow(dead_code)]
 
 use async_trait::async_trait;
+use chrono::Utc;
+use dashmap::{DashMap, DashSet};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{collections::HashMap, env::VarError, string::FromUtf8Error, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    env::VarError,
+    string::FromUtf8Error,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
 use thiserror::Error;
 use tokio::sync::RwLock;
 use utoipa::ToSchema;
 
 use crate::{
+    anomaly::AnomalyDetector,
     chain::{
-        polkadot::util::{ContractMetadata, SimplifiedEvent},
+        polkadot::{
+            prelude::{DecodeMode, EventData},
+            metadata::ContractMetadata,
+            util::SimplifiedEvent,
+        },
         Blockchain,
     },
+    dev::DevMode,
     dsl::Rule,
+    enrich::{Enricher, Enrichment},
+    identity::{self, AuthProvider},
+    inbound::Inbound,
+    integrity,
+    lifecycle::Transition,
+    load::LoadGenerator,
+    metering::Metering,
+    migrate::{self, MigrationOptions},
+    name::NameError,
+    ordering::Sequencer,
+    plan::PlanCache,
+    quota::QuotaMonitor,
+    shard::Sharding,
     storage::{CollectionSummary, Sled},
     util::CryptoError,
+    webhook::Outbox,
 };
 
 /// Errors from internal database operations.
@@ -34,6 +64,18 @@ pub enum StorageError {
     #[error("Not found: {0}")]
     NotFound(String),
 
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+
+    #[error("Corrupted record: {0}")]
+    Corrupted(String),
+
+    #[error("Invalid transition of {field}: {message}")]
+    InvalidTransition { field: String, message: String },
+
+    #[error("Invalid {field}: {message}")]
+    InvalidField { field: String, message: String },
+
     #[error("Other: {0}")]
     Other(String),
 }
@@ -58,9 +100,20 @@ impl_storage_error_from!(
     std::io::Error,
     FromUtf8Error,
     VarError,
-    CryptoError
+    CryptoError,
+    NameError
 );
 
+// Errors of transactions aborted with a StorageError.
+impl From<sled::transaction::TransactionError<StorageError>> for StorageError {
+    fn from(err: sled::transaction::TransactionError<StorageError>) -> Self {
+        match err {
+            sled::transaction::TransactionError::Abort(e) => e,
+            sled::transaction::TransactionError::Storage(e) => StorageError::Sled(e),
+        }
+    }
+}
+
 /// Result type for storage operations.
 pub type StorageResult<T> = Result<T, StorageError>;
 
@@ -82,9 +135,15 @@ pub static DEFAULT_TRIGGER_PATH_METADATA: &str = "./.data/triggers";
 /// Contracts file directory.
 pub const CONTRACTS_DIR: &str = "./.data/contracts";
 
+/// Default directory of the exports taken before a project is deleted.
+pub const DEFAULT_EXPORT_DIR: &str = "./.data/exports";
+
 /// The API key type.
 pub type ApiKey = String;
 
+/// A queued event: its position, then its contract and data, or the error it can't be read with.
+pub type QueuedEvent = (u64, Result<(String, EventData), serde_json::Error>);
+
 /// The entire state of the database system.
 #[derive(Clone)]
 pub struct Triggr {
@@ -93,66 +152,1235 @@ pub struct Triggr {
     /// Supported chains
     pub chains: Arc<Blockchain>,
     /// High speed cache
-    pub cache: Arc<RwLock<HighSpeedCache>>,
+    pub cache: Arc<HighSpeedCache>,
+    /// Event decoding statistics per contract
+    pub decode_stats: Arc<RwLock<DecodeStats>>,
+    /// Failed authentication tracking
+    pub auth_guard: Arc<AuthGuard>,
+    /// Liveness of the chain subscription
+    pub chain_health: Arc<ChainHealth>,
+    /// Active WebSocket connections
+    pub ws_connections: Arc<WsRegistry>,
+    /// Occupancy and lag of the event pipeline
+    pub pipeline: Arc<Pipeline>,
+    /// Instance maintenance switch
+    pub maintenance: Arc<Maintenance>,
+    /// Share of the contracts handled by this instance, when sharding is enabled
+    pub sharding: Option<Arc<Sharding>>,
+    /// Synthetic event load for capacity planning
+    pub load: Arc<LoadGenerator>,
+    /// Events pushed by the webhooks of external indexers
+    pub inbound: Arc<Inbound>,
+    /// Moving statistics of numeric event fields
+    pub anomalies: Arc<AnomalyDetector>,
+    /// Evaluation plans of the contract triggers
+    pub plans: Arc<PlanCache>,
+    /// Local development mode, when enabled
+    pub dev: Option<Arc<DevMode>>,
+    /// Keeps the executions of strictly ordered contracts in chain order
+    pub sequencer: Arc<Sequencer>,
+    /// Warning thresholds of project quotas
+    pub quotas: Arc<QuotaMonitor>,
+    /// Usage counters of the projects
+    pub metering: Arc<Metering>,
+    /// Notifications waiting for delivery to their webhook
+    pub outbox: Arc<Outbox>,
+    /// Verifies console sessions (every session is the anonymous user when not set)
+    pub auth_provider: Option<Arc<dyn AuthProvider>>,
 }
 
 impl Triggr {
     /// Initialize system state.
-    pub fn new() -> Self {
+    pub fn new() -> StorageResult<Self> {
+        Self::with_store(Sled::new()?)
+    }
+
+    /// Initialize the state on top of an already opened store.
+    /// Fails if the stored data can't be migrated to the current formats, as serving
+    /// half-migrated data could corrupt it further.
+    pub fn with_store(store: Sled) -> StorageResult<Self> {
         let triggr = Self {
-            store: Arc::new(Sled::new()),
+            store: Arc::new(store),
             chains: Arc::new(Blockchain::default()),
-            cache: Arc::new(RwLock::new(HighSpeedCache::default())),
+            cache: Arc::new(HighSpeedCache::default()),
+            decode_stats: Arc::new(RwLock::new(DecodeStats::default())),
+            auth_guard: Arc::new(AuthGuard::default()),
+            chain_health: Arc::new(ChainHealth::default()),
+            ws_connections: Arc::new(WsRegistry::default()),
+            pipeline: Arc::new(Pipeline::default()),
+            maintenance: Arc::new(Maintenance::default()),
+            sharding: Sharding::from_env(),
+            load: Arc::new(LoadGenerator::default()),
+            inbound: Arc::new(Inbound::from_env()),
+            anomalies: Arc::new(AnomalyDetector::from_env()),
+            plans: Arc::new(PlanCache::default()),
+            dev: DevMode::from_env().map(Arc::new),
+            sequencer: Arc::new(Sequencer::default()),
+            quotas: Arc::new(QuotaMonitor::from_env()),
+            metering: Arc::new(Metering::default()),
+            outbox: Arc::new(Outbox::from_env()),
+            auth_provider: identity::from_env(),
         };
 
+        // Maintenance survives restarts
+        if triggr.store.maintenance() {
+            triggr.maintenance.active.store(true, Ordering::Relaxed);
+            tracing::warn!("🚧 Instance is in maintenance, events are queued");
+        }
+
+        // Bring stored data up to the current formats
+        migrate::run(&triggr.store, &MigrationOptions::from_env())?;
+
+        // Report records that can no longer be decoded
+        match integrity::scan(&triggr.store) {
+            Ok(report) => {
+                for record in &report.corrupted {
+                    tracing::warn!(
+                        "Corrupted {} record {}: {}",
+                        record.db, record.key, record.error
+                    );
+                }
+            }
+            Err(e) => tracing::warn!("Failed to check stored data: {e}"),
+        }
+
         // Load metadata into cache
-        let mut cache = HighSpeedCache::default();
-        cache.init_contract_metadata(triggr.store.clone());
+        triggr.cache.init_contract_metadata(triggr.store.clone());
+
+        Ok(triggr)
+    }
+
+    /// Fetch the metadata of a contract, loading it back from disk if it was evicted.
+    /// The file is read on a blocking thread, so runtime workers never wait on disk.
+    pub async fn contract_metadata(&self, addr: &str) -> Option<Arc<ContractMetadata>> {
+        if let Some(metadata) = self.cache.get(addr) {
+            return Some(metadata);
+        }
+
+        let (cache, addr) = (self.cache.clone(), addr.to_string());
+        tokio::task::spawn_blocking(move || cache.reload(&addr))
+            .await
+            .ok()
+            .flatten()
+    }
 
-        Triggr {
-            cache: Arc::new(RwLock::new(cache)),
-            ..triggr
+    /// Fetch the metadata of a contract from a blocking context, e.g. inside `spawn_blocking`.
+    pub fn contract_metadata_blocking(&self, addr: &str) -> Option<Arc<ContractMetadata>> {
+        self.cache.get(addr).or_else(|| self.cache.reload(addr))
+    }
+
+    /// Whether this instance handles the events of a contract.
+    pub fn owns_contract(&self, addr: &str) -> bool {
+        self.sharding.as_ref().is_none_or(|s| s.owns(addr))
+    }
+}
+
+/// Instance-level maintenance switch.
+/// While active, decoded events are queued on disk instead of triggering anything,
+/// and the queue is drained in order when maintenance ends.
+#[derive(Default)]
+pub struct Maintenance {
+    active: AtomicBool,
+    /// Notified when maintenance ends
+    pub resumed: tokio::sync::Notify,
+}
+
+impl Maintenance {
+    /// Whether the instance is in maintenance.
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Enter or leave maintenance, persisting the switch.
+    pub fn set(&self, store: &Sled, active: bool) -> StorageResult<()> {
+        store.set_maintenance(active)?;
+        self.active.store(active, Ordering::Relaxed);
+
+        // Wake the trigger engine up to drain the backlog
+        if !active {
+            self.resumed.notify_one();
         }
+        Ok(())
     }
 }
 
-/// High speed cache to retrieve important data quickly.
+/// Maintenance status.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MaintenanceStatus {
+    pub active: bool,
+    /// Queued events whose triggers have not run yet
+    pub backlog: usize,
+}
+
+/// Default memory budget of the contract metadata cache (64MB).
+const DEFAULT_METADATA_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Contract metadata held in cache.
+struct CachedMetadata {
+    /// Parsed metadata
+    metadata: Arc<ContractMetadata>,
+    /// Size of the metadata file, used for memory accounting
+    size: usize,
+    /// Whether it was used since the clock hand last passed it
+    referenced: AtomicBool,
+}
+
+/// Signature topics of the events of the contracts with metadata.
+/// Contract events whose topics hold none of them are skipped without being decoded.
 #[derive(Default)]
+struct TopicFilter {
+    /// Contract hash -> Signature topics of its events (`None` if they can't all be recognized)
+    contracts: HashMap<String, Option<Vec<[u8; 32]>>>,
+    /// Signature topics of all contracts
+    topics: HashSet<[u8; 32]>,
+    /// Contracts with events that can't be recognized by their topics
+    unfiltered: usize,
+}
+
+impl TopicFilter {
+    /// Set the signature topics of a contract.
+    fn set(&mut self, addr: &str, topics: Option<Vec<[u8; 32]>>) {
+        if self.contracts.get(addr) == Some(&topics) {
+            return;
+        }
+        self.contracts.insert(addr.to_string(), topics);
+        self.rebuild();
+    }
+
+    /// Stop matching the topics of a contract.
+    fn remove(&mut self, addr: &str) {
+        if self.contracts.remove(addr).is_some() {
+            self.rebuild();
+        }
+    }
+
+    /// Rebuild the union (metadata changes are rare).
+    fn rebuild(&mut self) {
+        self.topics = self.contracts.values().flatten().flatten().copied().collect();
+        self.unfiltered = self.contracts.values().filter(|t| t.is_none()).count();
+    }
+
+    /// Whether an event with these topics may come from one of the contracts.
+    fn may_match(&self, topics: &[[u8; 32]]) -> bool {
+        self.unfiltered > 0 || topics.iter().any(|topic| self.topics.contains(topic))
+    }
+}
+
+/// Hit/miss statistics of the metadata cache.
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+pub struct CacheStats {
+    /// Lookups served from memory
+    pub hits: u64,
+    /// Lookups of evicted entries
+    pub misses: u64,
+    /// Entries (re)loaded from disk
+    pub loads: u64,
+    /// Entries evicted to stay within budget
+    pub evictions: u64,
+    /// Entries currently held
+    pub entries: usize,
+    /// Bytes currently held
+    pub bytes: usize,
+    /// Memory budget in bytes
+    pub capacity_bytes: usize,
+    /// Ratio of hits over lookups
+    pub hit_rate: f64,
+}
+
+/// High speed cache to retrieve important data quickly.
+/// Contract metadata is bounded by a memory budget and evicted with a clock (second chance)
+/// sweep, which approximates least recently used without scanning every entry.
+/// Evicted entries are loaded back from disk on the next lookup.
+/// Maps are sharded so event decoding never waits on a global lock while metadata is uploaded.
 pub struct HighSpeedCache {
     /// Contract hash -> Contract metadata
-    pub contract: HashMap<String, ContractMetadata>,
+    contract: DashMap<String, CachedMetadata>,
+    /// Contract hash -> Location of contract metadata
+    sources: DashMap<String, String>,
+    /// Contract hash -> Decoding mode of the owning project
+    decode_modes: DashMap<String, DecodeMode>,
+    /// Contract hash -> Event routing rules of the owning project (if any)
+    event_routes: DashMap<String, EventRoutes>,
+    /// Contract hash -> Enrichers of the owning project (if any)
+    enrichers: DashMap<String, Arc<Enrichment>>,
+    /// Contracts whose live events also run in the sandbox of their project
+    sandbox_mirrors: DashSet<String>,
+    /// Contracts whose events are executed one at a time, in chain order
+    strict_contracts: DashSet<String>,
+    /// Signature topics of the events of every contract with metadata
+    topics: std::sync::RwLock<TopicFilter>,
+    /// Memory budget in bytes
+    capacity: usize,
+    /// Bytes currently held
+    bytes: AtomicUsize,
+    /// Cached contracts in clock order, the hand being at the front
+    clock: Mutex<VecDeque<String>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    loads: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Default for HighSpeedCache {
+    fn default() -> Self {
+        let capacity = std::env::var("TRIGGR_METADATA_CACHE_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_METADATA_CACHE_BYTES);
+
+        Self::with_capacity(capacity)
+    }
 }
 
 impl HighSpeedCache {
+    /// Create an empty cache with a memory budget in bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            contract: DashMap::new(),
+            sources: DashMap::new(),
+            decode_modes: DashMap::new(),
+            event_routes: DashMap::new(),
+            enrichers: DashMap::new(),
+            sandbox_mirrors: DashSet::new(),
+            strict_contracts: DashSet::new(),
+            topics: Default::default(),
+            capacity,
+            bytes: AtomicUsize::new(0),
+            clock: Mutex::new(VecDeque::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            loads: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
     /// Load contract metadata into cache.
-    pub fn init_contract_metadata(&mut self, store: Arc<Sled>) {
+    pub fn init_contract_metadata(&self, store: Arc<Sled>) {
         // Get metadata entries
         if let Ok(meta_entries) = store.get_metadata_entries() {
             for meta in meta_entries {
-                if let Ok(metadata) = self.load_n_serialize(&meta.path) {
-                    self.save_metadata(meta.addr, metadata);
-                }
+                let _ = self.load_metadata(&meta.addr, &meta.path);
+            }
+        }
+
+        // Load the decoding mode, event routes, enrichers, sandbox mirroring and ordering of every
+        // project
+        if let Ok(projects) = store.all_projects() {
+            for project in projects {
+                self.save_decode_mode(&project.contract_address, project.decode_mode);
+                self.save_enrichers(&project.contract_address, &project.id, project.enrichers);
+                self.save_event_routes(&project.contract_address, project.event_routes);
+                self.save_sandbox_mirror(&project.contract_address, project.sandbox_mirror);
+                self.save_strict_ordering(&project.contract_address, project.strict_ordering);
             }
         }
     }
 
     /// Helper function to load and serialize metadata.
-    pub fn load_n_serialize(&mut self, path: &str) -> StorageResult<ContractMetadata> {
+    /// Returns the metadata and the size of its file.
+    pub fn load_n_serialize(&self, path: &str) -> StorageResult<(ContractMetadata, usize)> {
         // Read metadata content
         let metadata_json = std::fs::read_to_string(path)?;
 
-        // Return metadata
-        Ok(serde_json::from_str::<ContractMetadata>(&metadata_json)?)
+        let metadata = serde_json::from_str::<ContractMetadata>(&metadata_json)?;
+
+        // Reject layouts the decoder does not understand
+        metadata.detect_version()?;
+
+        Ok((metadata, metadata_json.len()))
+    }
+
+    /// Register the metadata location of a contract and load it into cache.
+    pub fn load_metadata(&self, addr: &str, path: &str) -> StorageResult<Arc<ContractMetadata>> {
+        let addr = addr.to_lowercase();
+        self.sources.insert(addr.clone(), path.to_string());
+
+        // Parse outside of any shard lock
+        let (metadata, size) = self.load_n_serialize(path)?;
+        self.loads.fetch_add(1, Ordering::Relaxed);
+
+        Ok(self.save_metadata(addr, metadata, size))
+    }
+
+    /// Save contract address and metadata, evicting older entries if over budget.
+    pub fn save_metadata(
+        &self,
+        addr: String,
+        data: ContractMetadata,
+        size: usize,
+    ) -> Arc<ContractMetadata> {
+        let addr = addr.to_lowercase();
+        if let Ok(mut topics) = self.topics.write() {
+            topics.set(&addr, data.signature_topics());
+        }
+        let metadata = Arc::new(data);
+
+        let entry = CachedMetadata {
+            metadata: metadata.clone(),
+            size,
+            referenced: AtomicBool::new(false),
+        };
+
+        self.bytes.fetch_add(size, Ordering::Relaxed);
+        match self.contract.insert(addr.clone(), entry) {
+            Some(old) => {
+                self.bytes.fetch_sub(old.size, Ordering::Relaxed);
+            }
+            None => {
+                if let Ok(mut clock) = self.clock.lock() {
+                    clock.push_back(addr.clone());
+                }
+            }
+        }
+
+        self.evict(&addr);
+        metadata
+    }
+
+    /// Return cached metadata of a contract, if it is in memory.
+    pub fn get(&self, addr: &str) -> Option<Arc<ContractMetadata>> {
+        match self.contract.get(addr) {
+            Some(entry) => {
+                entry.referenced.store(true, Ordering::Relaxed);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.metadata.clone())
+            }
+            None => {
+                if self.sources.contains_key(addr) {
+                    self.misses.fetch_add(1, Ordering::Relaxed);
+                }
+                None
+            }
+        }
+    }
+
+    /// Whether an event with these topics may come from a contract with metadata.
+    pub fn watches_topics(&self, topics: &[[u8; 32]]) -> bool {
+        self.topics
+            .read()
+            .map(|filter| filter.may_match(topics))
+            .unwrap_or(true)
+    }
+
+    /// Whether metadata is registered for a contract (in memory or on disk).
+    pub fn is_known(&self, addr: &str) -> bool {
+        self.sources.contains_key(addr)
+    }
+
+    /// Load evicted metadata of a contract back from disk.
+    pub fn reload(&self, addr: &str) -> Option<Arc<ContractMetadata>> {
+        let path = self.sources.get(addr)?.clone();
+        self.load_metadata(addr, &path).ok()
+    }
+
+    /// Return cache statistics.
+    pub fn stats(&self) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+
+        CacheStats {
+            hits,
+            misses,
+            loads: self.loads.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.contract.len(),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            capacity_bytes: self.capacity,
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
+        }
+    }
+
+    /// Save the decoding mode used for a contract.
+    pub fn save_decode_mode(&self, addr: &str, mode: DecodeMode) {
+        self.decode_modes.insert(addr.to_lowercase(), mode);
+    }
+
+    /// Return the decoding mode used for a contract.
+    pub fn decode_mode(&self, addr: &str) -> DecodeMode {
+        self.decode_modes
+            .get(addr)
+            .map(|mode| *mode)
+            .unwrap_or_default()
+    }
+
+    /// Save the event routing rules of a contract.
+    pub fn save_event_routes(&self, addr: &str, routes: EventRoutes) {
+        if routes.is_empty() {
+            self.event_routes.remove(&addr.to_lowercase());
+        } else {
+            self.event_routes.insert(addr.to_lowercase(), routes);
+        }
+    }
+
+    /// Whether an event of a contract passes the routing rules of its project.
+    pub fn routes_allow(&self, addr: &str, event: &EventData) -> bool {
+        self.event_routes
+            .get(addr)
+            .is_none_or(|routes| routes.allows(event))
+    }
+
+    /// Save the enrichers of the project owning a contract.
+    pub fn save_enrichers(&self, addr: &str, project_id: &str, enrichers: Vec<Enricher>) {
+        if enrichers.is_empty() {
+            self.enrichers.remove(&addr.to_lowercase());
+        } else {
+            let enrichment = Enrichment {
+                project_id: project_id.to_string(),
+                enrichers,
+            };
+            self.enrichers.insert(addr.to_lowercase(), Arc::new(enrichment));
+        }
+    }
+
+    /// Return the enrichers applied to the events of a contract, if any.
+    pub fn enrichment(&self, addr: &str) -> Option<Arc<Enrichment>> {
+        self.enrichers.get(addr).map(|enrichment| enrichment.clone())
+    }
+
+    /// Save whether the live events of a contract also run in the sandbox of its project.
+    pub fn save_sandbox_mirror(&self, addr: &str, mirror: bool) {
+        if mirror {
+            self.sandbox_mirrors.insert(addr.to_lowercase());
+        } else {
+            self.sandbox_mirrors.remove(&addr.to_lowercase());
+        }
+    }
+
+    /// Whether the live events of a contract also run in the sandbox of its project.
+    pub fn mirrors_to_sandbox(&self, addr: &str) -> bool {
+        self.sandbox_mirrors.contains(addr)
+    }
+
+    /// Save whether the events of a contract are executed one at a time, in chain order.
+    pub fn save_strict_ordering(&self, addr: &str, strict: bool) {
+        if strict {
+            self.strict_contracts.insert(addr.to_lowercase());
+        } else {
+            self.strict_contracts.remove(&addr.to_lowercase());
+        }
+    }
+
+    /// Whether the events of a contract are executed one at a time, in chain order.
+    pub fn orders_strictly(&self, addr: &str) -> bool {
+        self.strict_contracts.contains(addr)
+    }
+
+    /// Drop everything cached about a contract, e.g. once no project uses it.
+    pub fn forget(&self, addr: &str) {
+        let addr = addr.to_lowercase();
+        if let Some((_, entry)) = self.contract.remove(&addr) {
+            self.bytes.fetch_sub(entry.size, Ordering::Relaxed);
+            if let Ok(mut clock) = self.clock.lock() {
+                clock.retain(|cached| *cached != addr);
+            }
+        }
+        self.sources.remove(&addr);
+        self.decode_modes.remove(&addr);
+        self.event_routes.remove(&addr);
+        self.enrichers.remove(&addr);
+        self.sandbox_mirrors.remove(&addr);
+        self.strict_contracts.remove(&addr);
+        if let Ok(mut topics) = self.topics.write() {
+            topics.remove(&addr);
+        }
+    }
+
+    /// Evict entries until within budget, keeping `keep`.
+    /// The hand gives entries used since its last pass a second chance, so each entry is
+    /// visited at most twice before being evicted.
+    fn evict(&self, keep: &str) {
+        let Ok(mut clock) = self.clock.lock() else {
+            return;
+        };
+
+        while self.bytes.load(Ordering::Relaxed) > self.capacity {
+            let Some(addr) = clock.pop_front() else {
+                break;
+            };
+            if addr == keep {
+                // Nothing else is left to evict
+                let alone = clock.is_empty();
+                clock.push_back(addr);
+                if alone {
+                    break;
+                }
+                continue;
+            }
+
+            // The guard is dropped before removing, so the shard is never locked twice
+            let used = match self.contract.get(&addr) {
+                Some(entry) => entry.referenced.swap(false, Ordering::Relaxed),
+                None => continue,
+            };
+            if used {
+                clock.push_back(addr);
+            } else if let Some((_, entry)) = self.contract.remove(&addr) {
+                self.bytes.fetch_sub(entry.size, Ordering::Relaxed);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Number of recent decode outcomes used to compute the failure rate.
+const DECODE_WINDOW: usize = 50;
+
+/// Minimum number of outcomes in the window before alerting.
+const DECODE_MIN_OUTCOMES: usize = 10;
+
+/// Max number of failure samples kept per contract.
+const DECODE_MAX_SAMPLES: usize = 10;
+
+/// Failure rate above which a decoding alert is raised.
+const DECODE_ALERT_RATE: f64 = 0.5;
+
+/// A single event that failed to decode.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct DecodeFailureSample {
+    /// Raw event data (hex)
+    pub event_hex: String,
+    /// Failure summary
+    pub error: String,
+    /// Event specs that were tried and why they were rejected
+    pub tried: Vec<String>,
+    /// When the failure happened
+    pub timestamp: u64,
+}
+
+/// Decoding statistics of a single contract.
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+pub struct ContractDecodeStats {
+    /// Events decoded successfully
+    pub decoded: u64,
+    /// Events that failed to decode
+    pub failed: u64,
+    /// Failure rate over the recent window
+    pub failure_rate: f64,
+    /// Whether the failure rate is currently above the alert threshold
+    pub alerting: bool,
+    /// Most recent failures (newest last)
+    #[schema(value_type = Vec<DecodeFailureSample>)]
+    pub samples: VecDeque<DecodeFailureSample>,
+    /// Recent outcomes (true = failure)
+    #[serde(skip)]
+    window: VecDeque<bool>,
+}
+
+/// Event decoding statistics across contracts.
+#[derive(Default)]
+pub struct DecodeStats {
+    /// Contract address -> stats
+    pub contracts: HashMap<String, ContractDecodeStats>,
+}
+
+impl DecodeStats {
+    /// Record a decoding outcome for a contract.
+    /// Returns `true` when the failure rate has just crossed the alert threshold.
+    pub fn record(&mut self, contract_addr: &str, failure: Option<DecodeFailureSample>) -> bool {
+        let stats = self
+            .contracts
+            .entry(contract_addr.to_lowercase())
+            .or_default();
+
+        let failed = failure.is_some();
+        match failure {
+            Some(sample) => {
+                stats.failed += 1;
+                stats.samples.push_back(sample);
+                if stats.samples.len() > DECODE_MAX_SAMPLES {
+                    stats.samples.pop_front();
+                }
+            }
+            None => stats.decoded += 1,
+        }
+
+        stats.window.push_back(failed);
+        if stats.window.len() > DECODE_WINDOW {
+            stats.window.pop_front();
+        }
+
+        let failures = stats.window.iter().filter(|f| **f).count();
+        stats.failure_rate = failures as f64 / stats.window.len() as f64;
+
+        // Raise once when crossing the threshold, clear when well below it
+        if !stats.alerting
+            && stats.window.len() >= DECODE_MIN_OUTCOMES
+            && stats.failure_rate > DECODE_ALERT_RATE
+        {
+            stats.alerting = true;
+            return true;
+        } else if stats.alerting && stats.failure_rate < DECODE_ALERT_RATE / 2.0 {
+            stats.alerting = false;
+        }
+
+        false
+    }
+
+    /// Return the stats of a contract.
+    pub fn get(&self, contract_addr: &str) -> ContractDecodeStats {
+        self.contracts
+            .get(&contract_addr.to_lowercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Snapshot of an active WebSocket connection.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct WsConnectionInfo {
+    /// Connection id
+    pub id: String,
+    /// Project the connection authenticated as
+    pub project_id: String,
+    /// Subscribed topics
+    pub topics: Vec<String>,
+    /// When the connection was opened (unix ms)
+    pub connected_at: u64,
+    /// Messages waiting to be written to the socket
+    pub queue_depth: usize,
+}
+
+/// State of an active WebSocket connection.
+pub struct WsConnection {
+    pub project_id: String,
+    pub connected_at: u64,
+    pub topics: std::sync::Mutex<Vec<String>>,
+    pub queue_depth: AtomicUsize,
+    /// Notified to force the connection closed
+    pub close: tokio::sync::Notify,
+}
+
+impl WsConnection {
+    /// Replace the subscribed topics.
+    pub fn set_topics(&self, topics: Vec<String>) {
+        if let Ok(mut current) = self.topics.lock() {
+            *current = topics;
+        }
+    }
+}
+
+/// Default maximum number of simultaneous WebSocket connections per project (API key).
+pub const DEFAULT_WS_MAX_CONNECTIONS: usize = 100;
+
+/// Default maximum number of topics subscribed across the WebSocket connections of a project.
+pub const DEFAULT_WS_MAX_TOPICS: usize = 1000;
+
+/// Default maximum number of documents returned by a one-shot WebSocket query.
+pub const DEFAULT_WS_MAX_QUERY_DOCS: usize = 1000;
+
+/// Limits on the WebSocket usage of a project, read from `TRIGGR_WS_MAX_CONNECTIONS`,
+/// `TRIGGR_WS_MAX_TOPICS` and `TRIGGR_WS_MAX_QUERY_DOCS`.
+#[derive(Debug, Clone, Copy)]
+pub struct WsLimits {
+    pub max_connections: usize,
+    pub max_topics: usize,
+    pub max_query_docs: usize,
+}
+
+impl WsLimits {
+    /// Read the limits from the environment.
+    pub fn from_env() -> Self {
+        let limit = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+
+        Self {
+            max_connections: limit("TRIGGR_WS_MAX_CONNECTIONS", DEFAULT_WS_MAX_CONNECTIONS),
+            max_topics: limit("TRIGGR_WS_MAX_TOPICS", DEFAULT_WS_MAX_TOPICS),
+            max_query_docs: limit("TRIGGR_WS_MAX_QUERY_DOCS", DEFAULT_WS_MAX_QUERY_DOCS),
+        }
+    }
+}
+
+/// Registry of active WebSocket connections.
+#[derive(Default)]
+pub struct WsRegistry {
+    connections: DashMap<String, Arc<WsConnection>>,
+    /// Active connections per project
+    counts: DashMap<String, usize>,
+}
+
+impl WsRegistry {
+    /// Register a new connection and return its id and state,
+    /// or `None` if the project already has `max_connections` connections.
+    pub fn register(
+        &self,
+        project_id: &str,
+        max_connections: usize,
+    ) -> Option<(String, Arc<WsConnection>)> {
+        // The entry stays locked until the count is updated, so concurrent upgrades can't overshoot
+        let mut count = self.counts.entry(project_id.to_string()).or_insert(0);
+        if *count >= max_connections {
+            return None;
+        }
+        *count += 1;
+
+        let id = crate::util::generate_uuid();
+        let connection = Arc::new(WsConnection {
+            project_id: project_id.to_string(),
+            connected_at: Utc::now().timestamp_millis() as u64,
+            topics: Default::default(),
+            queue_depth: AtomicUsize::new(0),
+            close: tokio::sync::Notify::new(),
+        });
+
+        self.connections.insert(id.clone(), connection.clone());
+        Some((id, connection))
+    }
+
+    /// Remove a connection once it is closed.
+    pub fn unregister(&self, id: &str) {
+        if let Some((_, conn)) = self.connections.remove(id) {
+            self.counts.remove_if_mut(&conn.project_id, |_, count| {
+                *count = count.saturating_sub(1);
+                *count == 0
+            });
+        }
+    }
+
+    /// Number of open connections of a project.
+    pub fn connection_count(&self, project_id: &str) -> usize {
+        self.counts.get(project_id).map(|count| *count).unwrap_or_default()
+    }
+
+    /// Number of topics subscribed across the connections of a project.
+    pub fn topic_count(&self, project_id: &str) -> usize {
+        self.connections
+            .iter()
+            .filter(|entry| entry.value().project_id == project_id)
+            .map(|entry| entry.value().topics.lock().map(|t| t.len()).unwrap_or_default())
+            .sum()
+    }
+
+    /// List active connections.
+    pub fn list(&self) -> Vec<WsConnectionInfo> {
+        self.connections
+            .iter()
+            .map(|entry| {
+                let conn = entry.value();
+                WsConnectionInfo {
+                    id: entry.key().clone(),
+                    project_id: conn.project_id.clone(),
+                    topics: conn.topics.lock().map(|t| t.clone()).unwrap_or_default(),
+                    connected_at: conn.connected_at,
+                    queue_depth: conn.queue_depth.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+
+    /// Force a connection closed. Returns false if it does not exist.
+    pub fn close(&self, id: &str) -> bool {
+        match self.connections.get(id) {
+            Some(conn) => {
+                conn.close.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Liveness statistics of the chain subscription.
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+pub struct ChainHealthStats {
+    /// Hash of the last block processed
+    pub last_block: Option<String>,
+    /// When the last block was processed (unix ms), 0 if none yet
+    pub last_block_at: u64,
+    /// Milliseconds since the last block (or since startup if none yet)
+    pub gap_ms: u64,
+    /// Whether the subscription is considered stalled
+    pub stalled: bool,
+    /// Stall alerts raised since startup
+    pub alerts: u64,
+}
+
+/// Tracks when blocks were last received from the chain.
+pub struct ChainHealth {
+    last_block: std::sync::Mutex<Option<String>>,
+    last_block_at: AtomicU64,
+    started_at: u64,
+    stalled: AtomicBool,
+    alerts: AtomicU64,
+}
+
+impl Default for ChainHealth {
+    fn default() -> Self {
+        Self {
+            last_block: Default::default(),
+            last_block_at: AtomicU64::new(0),
+            started_at: Utc::now().timestamp_millis() as u64,
+            stalled: AtomicBool::new(false),
+            alerts: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ChainHealth {
+    /// Record that a block was processed. Returns true if the chain was stalled until now.
+    pub fn record_block(&self, block_hash: String) -> bool {
+        if let Ok(mut last) = self.last_block.lock() {
+            *last = Some(block_hash);
+        }
+        self.last_block_at
+            .store(Utc::now().timestamp_millis() as u64, Ordering::Relaxed);
+
+        self.stalled.swap(false, Ordering::Relaxed)
+    }
+
+    /// Milliseconds since the last block (or since startup if none yet).
+    pub fn gap_ms(&self) -> u64 {
+        let last = match self.last_block_at.load(Ordering::Relaxed) {
+            0 => self.started_at,
+            at => at,
+        };
+
+        (Utc::now().timestamp_millis() as u64).saturating_sub(last)
+    }
+
+    /// Compare the gap against a threshold. Returns the gap if the chain just stalled.
+    pub fn check(&self, threshold_ms: u64) -> Option<u64> {
+        let gap = self.gap_ms();
+        if gap > threshold_ms && !self.stalled.swap(true, Ordering::Relaxed) {
+            self.alerts.fetch_add(1, Ordering::Relaxed);
+            return Some(gap);
+        }
+
+        None
+    }
+
+    /// Return liveness statistics.
+    pub fn stats(&self) -> ChainHealthStats {
+        ChainHealthStats {
+            last_block: self.last_block.lock().ok().and_then(|b| b.clone()),
+            last_block_at: self.last_block_at.load(Ordering::Relaxed),
+            gap_ms: self.gap_ms(),
+            stalled: self.stalled.load(Ordering::Relaxed),
+            alerts: self.alerts.load(Ordering::Relaxed),
+        }
     }
+}
+
+/// Capacity of the channel carrying decoded events to the trigger engine.
+pub const EVENT_CHANNEL_CAPACITY: usize = 100;
 
-    /// Save contract address and metadata.
-    pub fn save_metadata(&mut self, addr: String, data: ContractMetadata) {
-        self.contract.insert(addr.to_lowercase(), data);
+/// Consumer name of the trigger engine in the event queue.
+pub const ENGINE_CONSUMER: &str = "engine";
+
+/// Sends decoded events to the trigger engine.
+/// Events are persisted in the event queue before the engine is woken up,
+/// so a crash between decoding and execution cannot lose them.
+#[derive(Clone)]
+pub struct EventSender {
+    store: Arc<Sled>,
+    wake: tokio::sync::mpsc::Sender<()>,
+}
+
+impl EventSender {
+    pub fn new(store: Arc<Sled>, wake: tokio::sync::mpsc::Sender<()>) -> Self {
+        Self { store, wake }
     }
 
-    /// Return inner cache structure.
-    pub fn into_inner(&self) -> HashMap<String, ContractMetadata> {
-        self.contract.clone()
+    /// Queue an event for the trigger engine.
+    /// Waits while the engine is `EVENT_CHANNEL_CAPACITY` events behind.
+    pub async fn send(&self, contract_addr: String, event: EventData) -> StorageResult<()> {
+        self.store.push_event(&contract_addr, &event)?;
+
+        // The event is safe on disk even if the engine stopped
+        self.wake
+            .send(())
+            .await
+            .map_err(|_| StorageError::Other("Trigger engine stopped".to_string()))
+    }
+}
+
+/// Health of the event processing pipeline.
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+pub struct PipelineStats {
+    /// Decoded events waiting in the channel to the trigger engine
+    pub channel_len: usize,
+    /// Capacity of that channel
+    pub channel_capacity: usize,
+    /// Events of the current block not yet decoded
+    pub decode_pending: usize,
+    /// Trigger executions in flight
+    pub executor_pending: usize,
+    /// Age (ms) of the oldest event waiting for the trigger engine, 0 if none
+    pub oldest_pending_ms: u64,
+    /// Time (ms) the last event waited before its triggers were dispatched
+    pub last_lag_ms: u64,
+    /// Events handed to the trigger engine since startup
+    pub processed: u64,
+    /// Events dropped by project routing rules since startup
+    pub filtered: u64,
+    /// Contract events skipped without decoding since startup, as no watched contract emits
+    /// their signature topic
+    pub prefiltered: u64,
+    /// Trigger executions that panicked since startup
+    pub panicked: u64,
+    /// Queued events quarantined since startup, as they could not be read
+    pub quarantined: u64,
+}
+
+/// Tracks occupancy and lag of the event processing pipeline.
+#[derive(Default)]
+pub struct Pipeline {
+    /// Enqueue times (unix ms) of events waiting in the channel, oldest first
+    queued: std::sync::Mutex<VecDeque<u64>>,
+    decode_pending: AtomicUsize,
+    executing: AtomicUsize,
+    last_lag_ms: AtomicU64,
+    processed: AtomicU64,
+    filtered: AtomicU64,
+    prefiltered: AtomicU64,
+    panicked: AtomicU64,
+    quarantined: AtomicU64,
+}
+
+impl Pipeline {
+    /// Set the number of events of a block waiting to be decoded.
+    pub fn set_decode_pending(&self, pending: usize) {
+        self.decode_pending.store(pending, Ordering::Relaxed);
+    }
+
+    /// Record that an event of the current block was decoded (or skipped).
+    pub fn decoded(&self) {
+        let _ = self
+            .decode_pending
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+
+    /// Record that an event is about to be sent to the trigger engine.
+    pub fn enqueued(&self) {
+        if let Ok(mut queued) = self.queued.lock() {
+            queued.push_back(Utc::now().timestamp_millis() as u64);
+        }
+    }
+
+    /// Record that the trigger engine dispatched the triggers of an event.
+    pub fn dequeued(&self) {
+        let enqueued_at = self.queued.lock().ok().and_then(|mut q| q.pop_front());
+        if let Some(at) = enqueued_at {
+            let lag = (Utc::now().timestamp_millis() as u64).saturating_sub(at);
+            self.last_lag_ms.store(lag, Ordering::Relaxed);
+        }
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that an event was dropped by routing rules before trigger matching.
+    pub fn filtered(&self) {
+        self.filtered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a contract event was skipped by the signature topic prefilter.
+    pub fn prefiltered(&self) {
+        self.prefiltered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a trigger execution started.
+    pub fn execution_started(&self) {
+        self.executing.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a trigger execution finished.
+    pub fn execution_finished(&self) {
+        let _ = self
+            .executing
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+
+    /// Record that a trigger execution panicked.
+    pub fn execution_panicked(&self) {
+        self.panicked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a queued event was quarantined.
+    pub fn event_quarantined(&self) {
+        self.quarantined.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Return pipeline health statistics.
+    pub fn stats(&self) -> PipelineStats {
+        let (channel_len, oldest) = self
+            .queued
+            .lock()
+            .map(|q| (q.len(), q.front().copied()))
+            .unwrap_or_default();
+
+        PipelineStats {
+            channel_len,
+            channel_capacity: EVENT_CHANNEL_CAPACITY,
+            decode_pending: self.decode_pending.load(Ordering::Relaxed),
+            executor_pending: self.executing.load(Ordering::Relaxed),
+            oldest_pending_ms: oldest
+                .map(|at| (Utc::now().timestamp_millis() as u64).saturating_sub(at))
+                .unwrap_or(0),
+            last_lag_ms: self.last_lag_ms.load(Ordering::Relaxed),
+            processed: self.processed.load(Ordering::Relaxed),
+            filtered: self.filtered.load(Ordering::Relaxed),
+            prefiltered: self.prefiltered.load(Ordering::Relaxed),
+            panicked: self.panicked.load(Ordering::Relaxed),
+            quarantined: self.quarantined.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Default number of failed authentications before a lockout.
+const DEFAULT_AUTH_MAX_FAILURES: u32 = 10;
+
+/// Default window (seconds) in which failures are counted.
+const DEFAULT_AUTH_FAILURE_WINDOW_SECS: u64 = 60;
+
+/// Default lockout duration (seconds).
+const DEFAULT_AUTH_LOCKOUT_SECS: u64 = 300;
+
+/// Number of tracked clients above which expired counters are pruned.
+const AUTH_PRUNE_THRESHOLD: usize = 10_000;
+
+/// Failed authentication attempts of a single client (IP address or service account).
+#[derive(Clone, Debug, Default)]
+struct AuthFailures {
+    /// Failures in the current window
+    count: u32,
+    /// Start of the current window (unix seconds)
+    window_start: u64,
+    /// Lockout expiry (unix seconds), 0 if not locked
+    locked_until: u64,
+}
+
+/// Authentication failure metrics.
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+pub struct AuthStats {
+    /// Rejected credentials
+    pub failures: u64,
+    /// Lockouts started
+    pub lockouts: u64,
+    /// Requests refused because the client was locked out
+    pub refused: u64,
+    /// Clients (IP addresses and service accounts) currently locked out
+    pub locked: usize,
+}
+
+/// Tracks failed authentications per client and locks out clients that keep failing.
+pub struct AuthGuard {
+    clients: DashMap<String, AuthFailures>,
+    max_failures: u32,
+    window_secs: u64,
+    lockout_secs: u64,
+    failures: AtomicU64,
+    lockouts: AtomicU64,
+    refused: AtomicU64,
+}
+
+impl Default for AuthGuard {
+    fn default() -> Self {
+        fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        Self::new(
+            env_or("TRIGGR_AUTH_MAX_FAILURES", DEFAULT_AUTH_MAX_FAILURES),
+            env_or(
+                "TRIGGR_AUTH_FAILURE_WINDOW_SECS",
+                DEFAULT_AUTH_FAILURE_WINDOW_SECS,
+            ),
+            env_or("TRIGGR_AUTH_LOCKOUT_SECS", DEFAULT_AUTH_LOCKOUT_SECS),
+        )
+    }
+}
+
+impl AuthGuard {
+    /// Lock out clients failing `max_failures` times within `window_secs`, for `lockout_secs`.
+    pub fn new(max_failures: u32, window_secs: u64, lockout_secs: u64) -> Self {
+        Self {
+            clients: DashMap::new(),
+            max_failures,
+            window_secs,
+            lockout_secs,
+            failures: AtomicU64::new(0),
+            lockouts: AtomicU64::new(0),
+            refused: AtomicU64::new(0),
+        }
+    }
+
+    /// Return the remaining lockout (seconds) of a client, if it is locked out.
+    pub fn locked(&self, client: &str) -> Option<u64> {
+        let now = Utc::now().timestamp() as u64;
+        let remaining = self
+            .clients
+            .get(client)
+            .filter(|c| c.locked_until > now)
+            .map(|c| c.locked_until - now)?;
+
+        self.refused.fetch_add(1, Ordering::Relaxed);
+        Some(remaining)
+    }
+
+    /// Record a failed authentication. Returns true if the client just got locked out.
+    pub fn record_failure(&self, client: &str) -> bool {
+        let now = Utc::now().timestamp() as u64;
+        self.failures.fetch_add(1, Ordering::Relaxed);
+
+        if self.clients.len() > AUTH_PRUNE_THRESHOLD {
+            self.prune(now);
+        }
+
+        let mut entry = self.clients.entry(client.to_string()).or_default();
+
+        // Start a new window once the previous one is over
+        if now >= entry.window_start + self.window_secs {
+            entry.count = 0;
+            entry.window_start = now;
+        }
+        entry.count += 1;
+
+        if entry.count >= self.max_failures && entry.locked_until <= now {
+            entry.locked_until = now + self.lockout_secs;
+            entry.count = 0;
+            self.lockouts.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+
+        false
+    }
+
+    /// Forget the failures of a client after a successful authentication.
+    pub fn record_success(&self, client: &str) {
+        self.clients.remove(client);
+    }
+
+    /// Return authentication failure metrics.
+    pub fn stats(&self) -> AuthStats {
+        let now = Utc::now().timestamp() as u64;
+
+        AuthStats {
+            failures: self.failures.load(Ordering::Relaxed),
+            lockouts: self.lockouts.load(Ordering::Relaxed),
+            refused: self.refused.load(Ordering::Relaxed),
+            locked: self
+                .clients
+                .iter()
+                .filter(|c| c.locked_until > now)
+                .count(),
+        }
+    }
+
+    /// Drop counters whose window and lockout are over.
+    fn prune(&self, now: u64) {
+        self.clients
+            .retain(|_, c| c.locked_until > now || now < c.window_start + self.window_secs);
     }
 }
 
@@ -223,6 +1451,32 @@ pub trait DocumentStore {
     /// * `Err` if deletion fails.
     async fn delete(&self, project_id: &str, collection: &str, id: &str) -> StorageResult<()>;
 
+    /// Update a document only if its stored copy satisfies a precondition.
+    ///
+    /// # Returns
+    /// * `Ok(())` if updated successfully.
+    /// * `Err(StorageError::PreconditionFailed)` if the stored copy doesn't satisfy the precondition.
+    async fn update_if(
+        &self,
+        project_id: &str,
+        collection: &str,
+        doc: Document,
+        precondition: &Precondition,
+    ) -> StorageResult<()>;
+
+    /// Delete a document only if its stored copy satisfies a precondition.
+    ///
+    /// # Returns
+    /// * `Ok(())` if deleted successfully.
+    /// * `Err(StorageError::PreconditionFailed)` if the stored copy doesn't satisfy the precondition.
+    async fn delete_if(
+        &self,
+        project_id: &str,
+        collection: &str,
+        id: &str,
+        precondition: &Precondition,
+    ) -> StorageResult<()>;
+
     /// List all documents inside a given collection.
     ///
     /// # Arguments
@@ -271,6 +1525,9 @@ pub struct DocMetadata {
     pub version: Option<u64>,
     /// Arbitrary tags for filtering/grouping (e.g. ["draft", "archived"]).
     pub tags: Vec<String>,
+    /// Hash of the document data, set on every write.
+    #[serde(default)]
+    pub hash: Option<String>,
 }
 
 /// A single JSON-like document stored inside a collection.
@@ -284,6 +1541,76 @@ pub struct Document {
     pub metadata: DocMetadata,
 }
 
+/// Expected state of a stored document for a conditional write.
+#[derive(Debug, Clone)]
+pub enum Precondition {
+    /// The document's entity tag is one of these (`*` for any existing document)
+    Etag(Vec<String>),
+    /// The document is at this version
+    Version(u64),
+}
+
+impl Precondition {
+    /// Whether the stored copy of a document satisfies the precondition.
+    /// A missing document never does.
+    pub fn holds(&self, stored: Option<&Document>) -> bool {
+        let Some(doc) = stored else {
+            return false;
+        };
+
+        match self {
+            Precondition::Etag(tags) => {
+                // Entity tags are weak, so compare them without the `W/` prefix
+                let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+                let etag = opaque(&doc.etag());
+                tags.iter().any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+            }
+            Precondition::Version(version) => doc.metadata.version.unwrap_or(0) == *version,
+        }
+    }
+}
+
+impl Document {
+    /// Return the entity tag of the document, derived from its content hash.
+    /// The tag is weak because the metadata may change while the data doesn't.
+    pub fn etag(&self) -> String {
+        let hash = self
+            .metadata
+            .hash
+            .clone()
+            .unwrap_or_else(|| crate::util::content_hash(&self.data));
+        format!("W/\"{hash}\"")
+    }
+}
+
+/// Origin of a document change.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ChangeSource {
+    /// A request to the HTTP API
+    #[default]
+    Http,
+    /// An action of a trigger
+    Trigger { trigger_id: String },
+}
+
+tokio::task_local! {
+    /// Source of the changes made by the current task.
+    static CHANGE_SOURCE: ChangeSource;
+}
+
+impl ChangeSource {
+    /// Run a future whose document changes are attributed to this source.
+    pub async fn scope<F: std::future::Future>(self, f: F) -> F::Output {
+        CHANGE_SOURCE.scope(self, f).await
+    }
+
+    /// Source of the changes made by the current task (HTTP unless scoped otherwise).
+    pub fn current() -> Self {
+        CHANGE_SOURCE.try_with(Clone::clone).unwrap_or_default()
+    }
+}
+
 /// Response payload for subscribed clients.
 #[derive(Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct WsPayload {
@@ -291,8 +1618,21 @@ pub struct WsPayload {
     pub op: String,
     /// Broadcast topic
     pub topic: String,
+    /// Project the document belongs to
+    pub project_id: String,
+    /// Collection of the document
+    pub collection: String,
+    /// Id of the document
+    pub doc_id: String,
+    /// Position of the message in its topic, increasing by one per message
+    pub seq: u64,
+    /// What made the change
+    pub source: ChangeSource,
     /// Document affected (old copy on delete)
     pub doc: Document,
+    /// Change of state made by the write, in collections with a state machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition: Option<Transition>,
 }
 
 /// Represents a database project on the network.
@@ -312,6 +1652,68 @@ pub struct Project {
     pub contract_file_path: String,
     /// Events emmitted by contract
     pub contract_events: Vec<SimplifiedEvent>,
+    /// How contract events are matched against the metadata
+    #[serde(default)]
+    pub decode_mode: DecodeMode,
+    /// Filters applied to the contract's events before trigger matching
+    #[serde(default)]
+    pub event_routes: EventRoutes,
+    /// Off-chain context attached to the contract's events before rules run
+    #[serde(default)]
+    pub enrichers: Vec<Enricher>,
+    /// Whether the contract's events also run the triggers in the sandbox namespace
+    #[serde(default)]
+    pub sandbox_mirror: bool,
+    /// Whether the contract's events are executed one at a time, in chain order
+    #[serde(default)]
+    pub strict_ordering: bool,
+}
+
+/// Coarse filters applied to a project's events before trigger matching.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct EventRoutes {
+    /// Only process these events (every event if empty)
+    #[serde(default)]
+    pub only_events: Vec<String>,
+    /// Never process these events
+    #[serde(default)]
+    pub ignore_events: Vec<String>,
+    /// Ignore events with a field holding one of these addresses (e.g. a sender)
+    #[serde(default)]
+    pub ignore_addresses: Vec<String>,
+}
+
+impl EventRoutes {
+    /// Whether no rule is set.
+    pub fn is_empty(&self) -> bool {
+        self.only_events.is_empty()
+            && self.ignore_events.is_empty()
+            && self.ignore_addresses.is_empty()
+    }
+
+    /// Whether an event passes the rules. Event names are compared case-insensitively.
+    pub fn allows(&self, event: &EventData) -> bool {
+        let named = |names: &[String]| {
+            names
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&event.event_name))
+        };
+
+        if !self.only_events.is_empty() && !named(&self.only_events) {
+            return false;
+        }
+        if named(&self.ignore_events) {
+            return false;
+        }
+
+        !event.fields.values().any(|value| {
+            value.as_str().is_some_and(|v| {
+                self.ignore_addresses
+                    .iter()
+                    .any(|addr| addr.eq_ignore_ascii_case(v))
+            })
+        })
+    }
 }
 
 /// Trait defining the behavior of a project store.
@@ -320,17 +1722,21 @@ pub struct Project {
 /// pluggable — e.g. we can back it with `Sled`, `MemoryStore`,
 /// or even a database like Postgres in the future.
 pub trait ProjectStore: Send + Sync {
-    /// Create a new project
+    /// Create a new project. Fails if another project has the same id.
     fn create(&self, project: &mut Project) -> StorageResult<ApiKey>;
 
     /// Fetch a project by its API key.
     fn get(&self, api_key: &str) -> StorageResult<Option<Project>>;
 
-    /// Delete a project by its API key and owner.
-    fn delete(&self, api_key: &str, owner: &str) -> StorageResult<()>;
+    /// Delete a project by its API key and owner, with its triggers.
+    /// Returns the deleted project. Its documents are purged in the background.
+    fn delete(&self, api_key: &str, owner: &str) -> StorageResult<Project>;
 
     /// Get all projects owned by a user.
     fn get_user_projects(&self, user_id: &str) -> StorageResult<Vec<Project>>;
+
+    /// Overwrite an existing project identified by its API key.
+    fn update(&self, api_key: &str, project: &Project) -> StorageResult<()>;
 }
 
 /// Struct that describes a trigger.
@@ -350,6 +1756,34 @@ pub struct Trigger {
     pub created: u64,
     /// Last time trigger was run
     pub last_run: u64,
+    /// Free-form labels to organize triggers (e.g. "alerts")
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// URL the `notify` actions of the trigger are POSTed to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+    /// Service account that saved the trigger, if it was not saved with the project key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+impl Trigger {
+    /// Whether the trigger carries a tag (case-insensitive).
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag.trim()))
+    }
+
+    /// Trim tags and drop empty and duplicate ones.
+    pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+        let mut normalized: Vec<String> = Vec::new();
+        for tag in tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !normalized.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                normalized.push(tag.to_string());
+            }
+        }
+        normalized
+    }
 }
 
 /// Streamlined trigger to return as payload.
@@ -365,6 +1799,50 @@ pub struct SlimTrigger {
     pub created: u64,
     /// Last time trigger was run
     pub last_run: u64,
+    /// Free-form labels to organize triggers
+    pub tags: Vec<String>,
+    /// URL the `notify` actions of the trigger are POSTed to
+    pub webhook: Option<String>,
+    /// Service account that saved the trigger, if any
+    pub owner: Option<String>,
+}
+
+impl From<Trigger> for SlimTrigger {
+    fn from(trigger: Trigger) -> Self {
+        SlimTrigger {
+            id: trigger.id,
+            description: trigger.description,
+            dsl: trigger.dsl,
+            active: trigger.active,
+            created: trigger.created,
+            last_run: trigger.last_run,
+            tags: trigger.tags,
+            webhook: trigger.webhook,
+            owner: trigger.owner,
+        }
+    }
+}
+
+/// Record of a single trigger execution.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TriggerRun {
+    /// Unique run id
+    pub id: String,
+    /// Trigger that was executed
+    pub trigger_id: String,
+    /// Project the trigger belongs to
+    pub project_id: String,
+    /// Contract that emitted the event
+    pub contract_addr: String,
+    /// Decoded event (raw payload included when enabled)
+    pub event: EventData,
+    /// Number of actions executed
+    pub actions: usize,
+    /// Execution timestamp
+    pub timestamp: u64,
+    /// Why the execution failed (e.g. it panicked), if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 /// Trait to handle trigger operations internally.
@@ -383,9 +1861,121 @@ pub trait TriggerStore {
         active: bool,
     ) -> StorageResult<()>;
 
+    /// Change the state of several triggers of a project at once, and of those carrying a tag.
+    /// Nothing changes if one of them doesn't exist. Returns the ids of the updated triggers.
+    fn set_triggers_state(
+        &self,
+        contract_addr: &str,
+        project_id: &str,
+        trigger_ids: &[String],
+        tag: Option<&str>,
+        active: bool,
+    ) -> StorageResult<Vec<String>>;
+
+    /// Record when a trigger last ran. The stored trigger itself is not rewritten.
+    fn record_trigger_run(
+        &self,
+        contract_addr: &str,
+        trigger_id: &str,
+        at: u64,
+    ) -> StorageResult<()>;
+
     /// Delete trigger.
     fn delete_trigger(&self, contract_addr: &str, trigger_id: &str) -> StorageResult<()>;
 
-    /// List all triggers for a contract.
+    /// List all triggers for a contract, none if no trigger was ever set on it.
     fn list_triggers(&self, contract_addr: &str) -> StorageResult<Vec<Trigger>>;
+
+    /// Whether triggers are stored for a contract.
+    fn contract_known(&self, contract_addr: &str) -> StorageResult<bool>;
+
+    /// Search the triggers of a project by DSL content, description and tags.
+    /// Returns the matching triggers with their contract address.
+    fn search_triggers(
+        &self,
+        project_id: &str,
+        query: &str,
+    ) -> StorageResult<Vec<(String, Trigger)>>;
+
+    /// Record a trigger run, dropping the runs older than `retention` milliseconds
+    /// (0 keeps them all).
+    fn store_run(&self, run: &TriggerRun, retention: u64) -> StorageResult<()>;
+
+    /// Return a trigger run of a project.
+    fn get_run(&self, project_id: &str, run_id: &str) -> StorageResult<Option<TriggerRun>>;
+
+    /// List the runs of a project, most recent first.
+    fn list_runs(&self, project_id: &str, limit: usize) -> StorageResult<Vec<TriggerRun>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Metadata of the demo contract.
+    fn demo_metadata() -> ContractMetadata {
+        serde_json::from_str(include_str!("../../examples/demo/contract/event_demo.json")).unwrap()
+    }
+
+    #[test]
+    fn cache_evicts_entries_unused_since_the_last_sweep() {
+        let cache = HighSpeedCache::with_capacity(3);
+        for addr in ["a", "b", "c"] {
+            cache.save_metadata(addr.to_string(), demo_metadata(), 1);
+        }
+
+        // "a" is used, so it gets a second chance and "b" goes instead
+        assert!(cache.get("a").is_some());
+        cache.save_metadata("d".to_string(), demo_metadata(), 1);
+
+        assert!(cache.get("b").is_none());
+        for addr in ["a", "c", "d"] {
+            assert!(cache.get(addr).is_some());
+        }
+        assert_eq!((cache.stats().entries, cache.stats().evictions), (3, 1));
+
+        // An entry larger than the budget is kept on its own
+        cache.save_metadata("e".to_string(), demo_metadata(), 10);
+        assert_eq!(cache.stats().entries, 1);
+        assert!(cache.get("e").is_some());
+    }
+
+    #[test]
+    fn auth_guard_locks_out_after_max_failures() {
+        let guard = AuthGuard::new(3, 60, 300);
+
+        assert!(!guard.record_failure("ip:a"));
+        assert!(!guard.record_failure("ip:a"));
+        assert!(guard.locked("ip:a").is_none());
+        assert!(guard.record_failure("ip:a"));
+
+        let remaining = guard.locked("ip:a").expect("locked out");
+        assert!(remaining > 0 && remaining <= 300);
+        assert!(guard.locked("ip:b").is_none());
+
+        let stats = guard.stats();
+        assert_eq!((stats.failures, stats.lockouts, stats.locked), (3, 1, 1));
+    }
+
+    #[test]
+    fn auth_guard_forgets_failures_after_success() {
+        let guard = AuthGuard::new(3, 60, 300);
+        guard.record_failure("ip:a");
+        guard.record_failure("ip:a");
+        guard.record_success("ip:a");
+
+        assert!(!guard.record_failure("ip:a"));
+        assert!(!guard.record_failure("ip:a"));
+        assert!(guard.locked("ip:a").is_none());
+    }
+
+    #[test]
+    fn auth_guard_counts_failures_within_the_window_only() {
+        // Every failure starts a new window
+        let guard = AuthGuard::new(2, 0, 300);
+        for _ in 0..5 {
+            assert!(!guard.record_failure("ip:a"));
+        }
+        assert!(guard.locked("ip:a").is_none());
+    }
 }
@@ -0,0 +1,206 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the soft limits of project quotas.
+// Before a quota (e.g. the WebSocket connections of a project) is reached, its usage crosses
+// warning thresholds, 80% and 90% by default. Each upward crossing raises one alert, broadcast to
+// consoles listening on `alerts:quota:{project_id}`, and the usage reports the warning so consoles
+// can show a banner instead of users finding out when requests start failing.
+
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+use crate::{
+    namespace::{self, Namespace},
+    prelude::{Triggr, WsLimits},
+};
+
+/// Default warning thresholds (percent of a quota).
+const DEFAULT_QUOTA_WARN_THRESHOLDS: [u8; 2] = [80, 90];
+
+/// Usage of a quota of a project.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct QuotaUsage {
+    /// Project the quota applies to
+    pub project_id: String,
+    /// Namespace the usage is counted in
+    pub namespace: Namespace,
+    /// Limited resource (e.g. `connections`)
+    pub limit: String,
+    pub used: usize,
+    pub max: usize,
+    /// Highest warning threshold reached (percent), if any
+    pub warning: Option<u8>,
+}
+
+/// Quota usage above a warning threshold, across projects.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QuotaStats {
+    /// Warning thresholds (percent)
+    pub thresholds: Vec<u8>,
+    pub warnings: Vec<QuotaUsage>,
+}
+
+/// Tracks the warning thresholds crossed by quota usage, so each crossing alerts once.
+pub struct QuotaMonitor {
+    /// Ascending warning thresholds (percent)
+    thresholds: Vec<u8>,
+    /// Highest threshold reached per `{data_id}:{limit}`
+    levels: DashMap<String, u8>,
+}
+
+impl Default for QuotaMonitor {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUOTA_WARN_THRESHOLDS.to_vec())
+    }
+}
+
+impl QuotaMonitor {
+    /// Create a monitor warning at the given thresholds (percent).
+    pub fn new(mut thresholds: Vec<u8>) -> Self {
+        thresholds.retain(|t| (1..=100).contains(t));
+        thresholds.sort_unstable();
+        thresholds.dedup();
+
+        Self {
+            thresholds,
+            levels: DashMap::new(),
+        }
+    }
+
+    /// Read the thresholds from `TRIGGR_QUOTA_WARN_THRESHOLDS` (comma separated percents,
+    /// empty to disable warnings).
+    pub fn from_env() -> Self {
+        match std::env::var("TRIGGR_QUOTA_WARN_THRESHOLDS") {
+            Ok(thresholds) => Self::new(
+                thresholds
+                    .split(',')
+                    .filter_map(|t| t.trim().parse().ok())
+                    .collect(),
+            ),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Return the usage of a quota of the data of a project (see `Namespace::scope`).
+    pub fn usage(&self, data_id: &str, limit: &str, used: usize, max: usize) -> QuotaUsage {
+        let percent = match max {
+            0 => 100,
+            max => used.saturating_mul(100) / max,
+        };
+
+        QuotaUsage {
+            project_id: namespace::project_of(data_id).to_string(),
+            namespace: match namespace::project_of(data_id) == data_id {
+                true => Namespace::Live,
+                false => Namespace::Sandbox,
+            },
+            limit: limit.to_string(),
+            used,
+            max,
+            warning: self
+                .thresholds
+                .iter()
+                .rev()
+                .find(|t| percent >= **t as usize)
+                .copied(),
+        }
+    }
+
+    /// Record the usage of a quota. Returns it when it crossed a threshold it was below.
+    pub fn record(
+        &self,
+        data_id: &str,
+        limit: &str,
+        used: usize,
+        max: usize,
+    ) -> Option<QuotaUsage> {
+        let usage = self.usage(data_id, limit, used, max);
+        let key = format!("{data_id}:{limit}");
+
+        let Some(warning) = usage.warning else {
+            self.levels.remove(&key);
+            return None;
+        };
+        let previous = self.levels.insert(key, warning);
+        previous.is_none_or(|level| level < warning).then_some(usage)
+    }
+
+    /// Return the thresholds and the quotas of connected projects above one.
+    pub fn stats(&self, triggr: &Triggr) -> QuotaStats {
+        let mut warnings: Vec<QuotaUsage> = ws_usage(triggr)
+            .into_iter()
+            .filter(|usage| usage.warning.is_some())
+            .collect();
+        warnings.sort_by(|a, b| (&a.project_id, &a.limit).cmp(&(&b.project_id, &b.limit)));
+
+        QuotaStats {
+            thresholds: self.thresholds.clone(),
+            warnings,
+        }
+    }
+}
+
+/// Return the WebSocket quota usage of every project with open connections.
+fn ws_usage(triggr: &Triggr) -> Vec<QuotaUsage> {
+    let mut data_ids: Vec<String> = triggr
+        .ws_connections
+        .list()
+        .into_iter()
+        .map(|conn| conn.project_id)
+        .collect();
+    data_ids.sort_unstable();
+    data_ids.dedup();
+
+    data_ids
+        .iter()
+        .flat_map(|data_id| project_usage(triggr, data_id))
+        .collect()
+}
+
+/// Return the usage of the quotas of the data of a project.
+pub fn project_usage(triggr: &Triggr, data_id: &str) -> Vec<QuotaUsage> {
+    let limits = WsLimits::from_env();
+    let monitor = &triggr.quotas;
+
+    vec![
+        monitor.usage(
+            data_id,
+            "connections",
+            triggr.ws_connections.connection_count(data_id),
+            limits.max_connections,
+        ),
+        monitor.usage(
+            data_id,
+            "topics",
+            triggr.ws_connections.topic_count(data_id),
+            limits.max_topics,
+        ),
+    ]
+}
+
+/// Record the usage of a quota and alert the project's consoles when it crossed a threshold.
+pub async fn check(triggr: &Triggr, data_id: &str, limit: &str, used: usize, max: usize) {
+    let Some(usage) = triggr.quotas.record(data_id, limit, used, max) else {
+        return;
+    };
+
+    tracing::warn!(
+        "⚠️ Project {data_id} reached {}% of its {limit} quota ({used}/{max})",
+        usage.warning.unwrap_or_default()
+    );
+
+    // Notify consoles listening for quota alerts
+    let topic = format!("alerts:quota:{}", usage.project_id);
+    let message = json!({
+        "op": "alert",
+        "topic": topic,
+        "usage": usage,
+    });
+    triggr
+        .store
+        .subscriptions
+        .broadcast(&topic, message.to_string())
+        .await;
+}
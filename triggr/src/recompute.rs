@@ -0,0 +1,121 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module rebuilds the documents a trigger derives from events (counters, balances...).
+// The run log of a project records the event of every execution, so it doubles as the event
+// journal of its triggers: recomputing clears the collections a trigger writes to, then replays
+// its recorded events through its current rules, which recovers from bugs in earlier versions.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    batch::DeferredWrite,
+    dsl::Action,
+    namespace::Namespace,
+    prelude::{ChangeSource, DocumentStore, StorageResult, Trigger, TriggerStore, Triggr},
+};
+
+/// Outcome of the recomputation of a trigger.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct RecomputeReport {
+    /// Collections cleared before the replay
+    pub collections: Vec<String>,
+    /// Documents deleted from them
+    pub cleared: usize,
+    /// Recorded events replayed
+    pub events: usize,
+    /// Actions executed by the replay
+    pub actions: usize,
+}
+
+/// Collections the actions of a trigger write to.
+fn derived_collections(trigger: &Trigger) -> BTreeSet<String> {
+    trigger
+        .rules
+        .iter()
+        .flat_map(|rule| &rule.actions)
+        .filter_map(|action| match action {
+            Action::Insert { collection, .. }
+            | Action::Update { collection, .. }
+            | Action::Append { collection, .. }
+            | Action::Delete { collection, .. } => Some(collection.clone()),
+            Action::Notify { .. } | Action::SetKv { .. } => None,
+        })
+        .collect()
+}
+
+/// Rebuild the documents of a trigger in a namespace from the events of its recorded runs,
+/// oldest first. Notifications are not sent again and the replay records no new runs.
+pub async fn recompute(
+    triggr: &Triggr,
+    contract_addr: &str,
+    trigger: &Trigger,
+    namespace: Namespace,
+) -> StorageResult<RecomputeReport> {
+    let data_id = namespace.scope(&trigger.project_id);
+    let source = ChangeSource::Trigger {
+        trigger_id: trigger.id.clone(),
+    };
+
+    // Recorded events of the trigger, in the order they were executed
+    let mut events = triggr
+        .store
+        .list_runs(&trigger.project_id, usize::MAX)?
+        .into_iter()
+        .filter(|run| run.trigger_id == trigger.id && run.contract_addr == contract_addr)
+        .filter(|run| run.event.namespace == namespace)
+        .map(|run| run.event)
+        .collect::<Vec<_>>();
+    events.reverse();
+
+    // Start from scratch, clearing each collection in a single transaction
+    let mut report = RecomputeReport::default();
+    for collection in derived_collections(trigger) {
+        let deletes = triggr
+            .store
+            .list(&data_id, &collection)?
+            .into_iter()
+            .map(|doc| DeferredWrite::Delete {
+                id: doc.id,
+                source: source.clone(),
+            })
+            .collect::<Vec<_>>();
+        triggr.store.apply_writes(&data_id, &collection, &deletes).await?;
+
+        report.cleared += deletes.len();
+        report.collections.push(collection);
+    }
+
+    for event in events {
+        let actions = crate::fired_actions(triggr, trigger, &event);
+        for action in actions {
+            if matches!(action, Action::Notify { .. }) {
+                continue;
+            }
+
+            let execution = crate::execute_actions(
+                triggr.clone(),
+                contract_addr,
+                trigger,
+                &data_id,
+                action,
+                event.clone(),
+            );
+            source.clone().scope(execution).await;
+            report.actions += 1;
+        }
+        report.events += 1;
+    }
+
+    tracing::info!(
+        "Recomputed trigger {} from {} event(s): {} document(s) cleared, {} action(s) executed",
+        trigger.id,
+        report.events,
+        report.cleared,
+        report.actions
+    );
+
+    Ok(report)
+}
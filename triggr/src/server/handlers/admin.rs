@@ -0,0 +1,468 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// Module containing handlers for instance administration requests.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc};
+use utoipa::ToSchema;
+
+use super::{db::AppError, *};
+use crate::{
+    chain::polkadot::{harness, metadata::ContractMetadata},
+    gc::{self, GcReport},
+    integrity::{self, IntegrityReport},
+    load::{LoadStatus, SyntheticLoad},
+    metering::UsageRecord,
+    quota::QuotaStats,
+    shard::ShardStats,
+    storage::SubscriptionStats,
+};
+
+/// Default number of recorded events replayed through the decoder.
+const DEFAULT_CORPUS_SIZE: usize = 1000;
+
+/// Default number of fuzzing iterations.
+const DEFAULT_FUZZ_ITERATIONS: usize = 500;
+
+/// Max number of fuzzing iterations of a run.
+const MAX_FUZZ_ITERATIONS: usize = 20_000;
+
+/// Query parameters for decoder harness runs.
+#[derive(Deserialize)]
+pub struct HarnessQuery {
+    /// Max number of recorded events to use
+    pub limit: Option<usize>,
+    /// Number of fuzzing iterations
+    pub iterations: Option<usize>,
+}
+
+/// Replay recorded raw events through the decoder and report output differences.
+#[utoipa::path(
+    post,
+    path = "/api/admin/decoder/replay",
+    params(
+        ("limit" = Option<usize>, Query, description = "Max number of recorded events to replay")
+    ),
+    responses(
+        (status = 200, description = "Replay report", body = harness::ReplayReport),
+        (status = 401, description = "Invalid admin key"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn replay_decoder_corpus(
+    State(triggr): State<Triggr>,
+    Query(query): Query<HarnessQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    // Evicted metadata is read back from disk while collecting it
+    let report = tokio::task::spawn_blocking(move || -> Result<_, AppError> {
+        let corpus = triggr
+            .store
+            .raw_event_corpus(query.limit.unwrap_or(DEFAULT_CORPUS_SIZE))?;
+
+        let metadata = corpus_metadata(&triggr, &corpus);
+        Ok(harness::replay_corpus(&corpus, &metadata))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Replay failed: {e}")))??;
+
+    Ok(Json(json!({ "data": report })))
+}
+
+/// Fuzz the decoder with mutations of recorded raw events.
+#[utoipa::path(
+    post,
+    path = "/api/admin/decoder/fuzz",
+    params(
+        ("limit" = Option<usize>, Query, description = "Max number of recorded events used as seeds"),
+        ("iterations" = Option<usize>, Query, description = "Number of mutated inputs (at most 20000)")
+    ),
+    responses(
+        (status = 200, description = "Fuzzing report", body = harness::FuzzReport),
+        (status = 401, description = "Invalid admin key"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn fuzz_decoder(
+    State(triggr): State<Triggr>,
+    Query(query): Query<HarnessQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let iterations = query
+        .iterations
+        .unwrap_or(DEFAULT_FUZZ_ITERATIONS)
+        .min(MAX_FUZZ_ITERATIONS);
+
+    // Decoding thousands of inputs would stall the runtime's workers
+    let report = tokio::task::spawn_blocking(move || -> Result<_, AppError> {
+        let corpus = triggr
+            .store
+            .raw_event_corpus(query.limit.unwrap_or(DEFAULT_CORPUS_SIZE))?;
+
+        let metadata = corpus_metadata(&triggr, &corpus);
+        Ok(harness::fuzz_corpus(&corpus, &metadata, iterations))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Fuzzing failed: {e}")))??;
+
+    Ok(Json(json!({ "data": report })))
+}
+
+/// Report hit/miss statistics of the contract metadata cache.
+#[utoipa::path(
+    get,
+    path = "/api/admin/cache",
+    responses(
+        (status = 200, description = "Cache statistics", body = CacheStats),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn cache_stats(State(triggr): State<Triggr>) -> impl IntoResponse {
+    let stats = triggr.cache.stats();
+
+    Json(json!({ "data": stats }))
+}
+
+/// Report failed authentication metrics.
+#[utoipa::path(
+    get,
+    path = "/api/admin/auth",
+    responses(
+        (status = 200, description = "Authentication failure metrics", body = AuthStats),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn auth_stats(State(triggr): State<Triggr>) -> impl IntoResponse {
+    let stats = triggr.auth_guard.stats();
+
+    Json(json!({ "data": stats }))
+}
+
+/// Report liveness of the chain subscription.
+#[utoipa::path(
+    get,
+    path = "/api/admin/health",
+    responses(
+        (status = 200, description = "Chain subscription liveness", body = ChainHealthStats),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn chain_health(State(triggr): State<Triggr>) -> impl IntoResponse {
+    let stats = triggr.chain_health.stats();
+
+    Json(json!({ "data": stats }))
+}
+
+/// Report occupancy and lag of the event processing pipeline.
+#[utoipa::path(
+    get,
+    path = "/api/admin/pipeline",
+    responses(
+        (status = 200, description = "Event pipeline health", body = PipelineStats),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn pipeline_stats(State(triggr): State<Triggr>) -> impl IntoResponse {
+    let stats = triggr.pipeline.stats();
+
+    Json(json!({ "data": stats }))
+}
+
+/// Report subscription topic metrics.
+#[utoipa::path(
+    get,
+    path = "/api/admin/subscriptions",
+    responses(
+        (status = 200, description = "Subscription topic metrics", body = SubscriptionStats),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn subscription_stats(State(triggr): State<Triggr>) -> impl IntoResponse {
+    let stats = triggr.store.subscriptions.stats().await;
+
+    Json(json!({ "data": stats }))
+}
+
+/// Report the usage of every project over the current metering period.
+#[utoipa::path(
+    get,
+    path = "/api/admin/metering",
+    responses(
+        (status = 200, description = "Usage of the projects", body = [UsageRecord]),
+        (status = 401, description = "Invalid admin key"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn metering_usage(State(triggr): State<Triggr>) -> Result<impl IntoResponse, AppError> {
+    let usage = triggr.metering.usage(&triggr)?;
+
+    Ok(Json(json!({ "data": usage })))
+}
+
+/// Report the project quotas above a warning threshold.
+#[utoipa::path(
+    get,
+    path = "/api/admin/quotas",
+    responses(
+        (status = 200, description = "Quotas above a warning threshold", body = QuotaStats),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn quota_stats(State(triggr): State<Triggr>) -> impl IntoResponse {
+    let stats = triggr.quotas.stats(&triggr);
+
+    Json(json!({ "data": stats }))
+}
+
+/// List active WebSocket connections.
+#[utoipa::path(
+    get,
+    path = "/api/admin/ws",
+    responses(
+        (status = 200, description = "Active WebSocket connections", body = Vec<WsConnectionInfo>),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn list_ws_connections(State(triggr): State<Triggr>) -> impl IntoResponse {
+    let connections = triggr.ws_connections.list();
+
+    Json(json!({ "data": connections }))
+}
+
+/// Forcibly close a WebSocket connection.
+#[utoipa::path(
+    delete,
+    path = "/api/admin/ws/{id}",
+    params(
+        ("id" = String, Path, description = "Connection ID")
+    ),
+    responses(
+        (status = 200, description = "Connection closed"),
+        (status = 401, description = "Invalid admin key"),
+        (status = 404, description = "Connection not found")
+    )
+)]
+pub async fn close_ws_connection(
+    State(triggr): State<Triggr>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    if !triggr.ws_connections.close(&id) {
+        return Err(AppError::NotFound(format!("Connection {id} not found")));
+    }
+
+    Ok(Json(json!({ "data": { "closed": true } })))
+}
+
+/// Return the sharding status of the instance.
+#[utoipa::path(
+    get,
+    path = "/api/admin/shard",
+    responses(
+        (status = 200, description = "Sharding status, null when sharding is disabled", body = ShardStats),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn shard_stats(State(triggr): State<Triggr>) -> impl IntoResponse {
+    let stats = triggr.sharding.as_ref().map(|s| s.stats());
+
+    Json(json!({ "data": stats }))
+}
+
+/// Check that stored projects, users, metadata and triggers can be decoded.
+#[utoipa::path(
+    get,
+    path = "/api/admin/integrity",
+    responses(
+        (status = 200, description = "Corrupted records", body = IntegrityReport),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn integrity_report(
+    State(triggr): State<Triggr>,
+) -> Result<impl IntoResponse, AppError> {
+    let report = integrity::scan(&triggr.store)?;
+
+    Ok(Json(json!({ "data": report })))
+}
+
+/// Move corrupted records to the quarantine tree of their database.
+/// Writes refused because of a corrupted record succeed once it is quarantined.
+#[utoipa::path(
+    post,
+    path = "/api/admin/integrity/quarantine",
+    responses(
+        (status = 200, description = "Corrupted and quarantined records", body = IntegrityReport),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn quarantine_corrupted(
+    State(triggr): State<Triggr>,
+) -> Result<impl IntoResponse, AppError> {
+    let report = integrity::quarantine(&triggr.store)?;
+
+    Ok(Json(json!({ "data": report })))
+}
+
+/// Report data left behind by deleted projects: their document and run-log trees, their triggers
+/// and metadata entries whose contract file is missing.
+#[utoipa::path(
+    get,
+    path = "/api/admin/gc",
+    responses(
+        (status = 200, description = "Orphaned data", body = GcReport),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn orphan_report(State(triggr): State<Triggr>) -> Result<impl IntoResponse, AppError> {
+    let store = triggr.store.clone();
+    let report = tokio::task::spawn_blocking(move || gc::scan(&store))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))??;
+
+    Ok(Json(json!({ "data": report })))
+}
+
+/// Remove data left behind by deleted projects.
+#[utoipa::path(
+    post,
+    path = "/api/admin/gc",
+    responses(
+        (status = 200, description = "Removed orphaned data", body = GcReport),
+        (status = 401, description = "Invalid admin key"),
+        (status = 500, description = "A project is unreadable")
+    )
+)]
+pub async fn collect_garbage(State(triggr): State<Triggr>) -> Result<impl IntoResponse, AppError> {
+    let (store, cache) = (triggr.store.clone(), triggr.cache.clone());
+    let report = tokio::task::spawn_blocking(move || gc::collect(&store, &cache))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))??;
+
+    Ok(Json(json!({ "data": report })))
+}
+
+/// Start injecting synthetic events, replacing the running load if any.
+/// Events go through the event queue and trigger engine like decoded chain events.
+#[utoipa::path(
+    post,
+    path = "/api/admin/load",
+    request_body = SyntheticLoad,
+    responses(
+        (status = 200, description = "Load started", body = LoadStatus),
+        (status = 400, description = "Invalid load"),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn start_load(
+    State(triggr): State<Triggr>,
+    Json(load): Json<SyntheticLoad>,
+) -> Result<impl IntoResponse, AppError> {
+    triggr
+        .load
+        .start(load, triggr.pipeline.clone())
+        .map_err(AppError::BadRequest)?;
+
+    Ok(Json(json!({ "data": triggr.load.status() })))
+}
+
+/// Return the status of the synthetic load.
+#[utoipa::path(
+    get,
+    path = "/api/admin/load",
+    responses(
+        (status = 200, description = "Synthetic load status", body = LoadStatus),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn load_status(State(triggr): State<Triggr>) -> impl IntoResponse {
+    Json(json!({ "data": triggr.load.status() }))
+}
+
+/// Stop the synthetic load.
+#[utoipa::path(
+    delete,
+    path = "/api/admin/load",
+    responses(
+        (status = 200, description = "Load stopped", body = LoadStatus),
+        (status = 401, description = "Invalid admin key"),
+        (status = 404, description = "No load is running")
+    )
+)]
+pub async fn stop_load(State(triggr): State<Triggr>) -> Result<impl IntoResponse, AppError> {
+    if !triggr.load.stop() {
+        return Err(AppError::NotFound("No load is running".to_string()));
+    }
+
+    Ok(Json(json!({ "data": triggr.load.status() })))
+}
+
+/// Request body to switch maintenance on or off.
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateMaintenance {
+    pub active: bool,
+}
+
+/// Return the maintenance status of the instance.
+#[utoipa::path(
+    get,
+    path = "/api/admin/maintenance",
+    responses(
+        (status = 200, description = "Maintenance status", body = MaintenanceStatus),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn maintenance_status(State(triggr): State<Triggr>) -> impl IntoResponse {
+    let status = MaintenanceStatus {
+        active: triggr.maintenance.is_active(),
+        backlog: triggr.store.events.len(),
+    };
+
+    Json(json!({ "data": status }))
+}
+
+/// Enter or leave maintenance.
+/// While in maintenance, events are queued instead of executing triggers.
+#[utoipa::path(
+    put,
+    path = "/api/admin/maintenance",
+    request_body = UpdateMaintenance,
+    responses(
+        (status = 200, description = "Maintenance updated", body = MaintenanceStatus),
+        (status = 401, description = "Invalid admin key")
+    )
+)]
+pub async fn update_maintenance(
+    State(triggr): State<Triggr>,
+    Json(payload): Json<UpdateMaintenance>,
+) -> Result<impl IntoResponse, AppError> {
+    triggr.maintenance.set(&triggr.store, payload.active)?;
+
+    let status = MaintenanceStatus {
+        active: payload.active,
+        backlog: triggr.store.events.len(),
+    };
+
+    Ok(Json(json!({ "data": status })))
+}
+
+/// Collect the metadata of every contract appearing in a corpus.
+fn corpus_metadata(
+    triggr: &Triggr,
+    corpus: &[TriggerRun],
+) -> HashMap<String, Arc<ContractMetadata>> {
+    let mut metadata = HashMap::new();
+    for run in corpus {
+        if metadata.contains_key(&run.contract_addr) {
+            continue;
+        }
+        if let Some(m) = triggr.contract_metadata_blocking(&run.contract_addr) {
+            metadata.insert(run.contract_addr.clone(), m);
+        }
+    }
+
+    metadata
+}
@@ -0,0 +1,128 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// Module containing handlers for authentication requests.
+// Browsers can not set headers on WebSocket connections, so instead of shipping long-lived API keys
+// in front-end code, a backend exchanges its API key for a short-lived token scoped to WebSockets.
+
+use axum::{response::IntoResponse, Json};
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::env;
+use utoipa::ToSchema;
+
+use super::db::AppError;
+use crate::{namespace::Namespace, server::middleware::RefProject};
+
+/// Scope granted to WebSocket tokens.
+pub const WS_SCOPE: &str = "ws";
+
+/// Default lifetime of a WebSocket token (seconds).
+const DEFAULT_WS_TOKEN_TTL: u64 = 300;
+
+/// Maximum lifetime of a WebSocket token (seconds).
+const MAX_WS_TOKEN_TTL: u64 = 3600;
+
+/// Claims carried by a WebSocket token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WsClaims {
+    /// Project the token was issued for
+    pub sub: String,
+    /// Granted scope
+    pub scope: String,
+    /// Issue time (unix seconds)
+    pub iat: u64,
+    /// Expiry (unix seconds)
+    pub exp: u64,
+    /// Namespace the connection subscribes in
+    #[serde(default, skip_serializing_if = "Namespace::is_live")]
+    pub namespace: Namespace,
+}
+
+/// Struct modelling a WebSocket token request.
+#[derive(Serialize, Deserialize, ToSchema, Default)]
+pub struct WsTokenRequest {
+    /// Lifetime of the token in seconds (default 300, max 3600)
+    pub ttl: Option<u64>,
+}
+
+/// Struct modelling an issued WebSocket token.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WsToken {
+    /// Signed token, passed as `?token=` when connecting to `/ws`
+    pub token: String,
+    /// Expiry (unix seconds)
+    pub expires_at: u64,
+}
+
+/// Return the secret WebSocket tokens are signed with.
+fn ws_token_secret() -> Result<String, env::VarError> {
+    env::var("TRIGGR_WS_TOKEN_SECRET").or_else(|_| env::var("TRIGGR_ENCRYPTION_KEY"))
+}
+
+/// Verify a WebSocket token and return its claims.
+pub fn verify_ws_token(token: &str) -> Result<WsClaims, String> {
+    let secret = ws_token_secret().map_err(|_| "Token secret not set in env".to_string())?;
+
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.leeway = 0;
+
+    let claims = decode::<WsClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation,
+    )
+    .map_err(|e| format!("Invalid token: {e}"))?
+    .claims;
+
+    if claims.scope != WS_SCOPE {
+        return Err(format!("Token scope '{}' does not grant WebSocket access", claims.scope));
+    }
+
+    Ok(claims)
+}
+
+/// Issue a short-lived token for browser WebSocket connections.
+#[utoipa::path(
+    post,
+    path = "/api/auth/ws-token",
+    request_body(content = inline(WsTokenRequest), description = "Token options"),
+    responses(
+        (status = 200, description = "Token issued", body = WsToken),
+        (status = 401, description = "Invalid API key"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn issue_ws_token(
+    ref_project: RefProject,
+    payload: Option<Json<WsTokenRequest>>,
+) -> Result<impl IntoResponse, AppError> {
+    let Json(request) = payload.unwrap_or_default();
+    let ttl = request
+        .ttl
+        .unwrap_or(DEFAULT_WS_TOKEN_TTL)
+        .clamp(1, MAX_WS_TOKEN_TTL);
+
+    let now = Utc::now().timestamp() as u64;
+    let claims = WsClaims {
+        sub: ref_project.project.id,
+        scope: WS_SCOPE.to_string(),
+        iat: now,
+        exp: now + ttl,
+        namespace: ref_project.namespace,
+    };
+
+    let secret =
+        ws_token_secret().map_err(|_| AppError::Internal("Token secret not set in env.".into()))?;
+    let token = encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to sign token: {e}")))?;
+
+    Ok(Json(json!({
+        "data": WsToken { token, expires_at: claims.exp }
+    })))
+}
@@ -2,15 +2,23 @@
 
 // Module containing handlers for console (front-end) requests.
 
-use crate::chain::polkadot::util::SimplifiedEvent;
+use crate::apply::{self, ApplyPlan, Drift, ProjectSpec};
+use crate::bootstrap::{self, Bootstrap};
+use crate::chain::polkadot::util::{explain_decode, DecodeReport};
+use crate::chain::polkadot::{metadata::ContractMetadata, prelude::DecodeMode};
+use crate::enrich::{self, Enricher};
+use crate::name::Name;
+use crate::namespace::{self, Namespace};
+use crate::quota::{self, QuotaUsage};
+use crate::service::{Scope, ServiceAccount, MAX_SERVICE_ACCOUNTS};
 use crate::{chain::polkadot::util::simplify_events, server::middleware::Auth, util::decrypt};
 use axum::{
-    extract::{Multipart, Path, State},
+    extract::{Multipart, Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::{env, path::PathBuf};
 use tokio::io::AsyncWriteExt;
@@ -32,6 +40,8 @@ pub struct CreateProjectResponse {
 }
 
 /// Request schema for Swagger (multipart form)
+/// Only describes the form in the API docs, the handler reads the multipart fields itself.
+#[allow(dead_code)]
 #[derive(ToSchema)]
 pub struct ProjectCreateForm {
     pub project_name: String,
@@ -108,6 +118,9 @@ pub async fn create_project(
                     ));
                 }
 
+                // The project id namespaces storage keys
+                Name::parse("project name", text.trim())?;
+
                 project_name = Some(text.trim().to_string());
             }
             "description" => {
@@ -155,9 +168,12 @@ pub async fn create_project(
                 }
 
                 // Validate JSON
-                serde_json::from_slice::<serde_json::Value>(&data)
+                let metadata = serde_json::from_slice::<ContractMetadata>(&data)
                     .map_err(|e| AppError::BadRequest(format!("Invalid JSON file: {}", e)))?;
 
+                // Validate metadata version (ink! v4, v5 or v6)
+                metadata.detect_version().map_err(AppError::BadRequest)?;
+
                 // Create safe file path
                 let filename = format!("{}.json", hash);
                 let path = PathBuf::from(CONTRACTS_DIR).join(&filename);
@@ -209,18 +225,17 @@ pub async fn create_project(
         .store_metadata_entry(&contract_addr, &contract_file_path)?;
 
     // Add metadata content to high speed cache
-    if let Some(path_str) = contract_path.to_str() {
-        // Acquire cache lock
-        let mut cache = triggr.cache.write().await;
-        if let Ok(metadata) = cache.load_n_serialize(path_str) {
-            // Extract events
-            events = simplify_events(&metadata);
-
-            // Save to high speed cache
-            cache.save_metadata(contract_addr.clone(), metadata);
-        }
+    let metadata = contract_path
+        .to_str()
+        .and_then(|path_str| triggr.cache.load_metadata(&contract_addr, path_str).ok());
+    if let Some(metadata) = metadata {
+        // Extract events
+        events = simplify_events(&metadata);
     }
 
+    // Events of a contract are decoded once for all its projects, so they share a mode
+    let decode_mode = triggr.cache.decode_mode(&contract_addr.to_lowercase());
+
     // Construct project
     let mut project = Project {
         id: project_name.clone(),
@@ -229,7 +244,12 @@ pub async fn create_project(
         description: description.clone(),
         contract_address: contract_addr,
         contract_file_path: contract_file_path.clone(),
-        contract_events: events.clone()
+        contract_events: events.clone(),
+        decode_mode,
+        event_routes: EventRoutes::default(),
+        enrichers: Vec::new(),
+        sandbox_mirror: false,
+        strict_ordering: false,
     };
 
     // Save to database
@@ -242,6 +262,11 @@ pub async fn create_project(
                 tracing::error!("Failed to cleanup file after DB error: {}", _cleanup_err);
             }
 
+            // A taken id is the caller's to fix
+            if let StorageError::InvalidField { .. } = e {
+                return Err(e.into());
+            }
+
             return Err(AppError::Internal(format!(
                 "Failed to create project: {}",
                 e
@@ -259,34 +284,86 @@ pub async fn create_project(
     Ok((StatusCode::CREATED, Json(response)))
 }
 
-/// Delete a project
+/// Query parameters of project deletion.
+#[derive(Deserialize)]
+pub struct DeleteProjectQuery {
+    /// Export the project's data before deleting it
+    #[serde(default)]
+    pub export: bool,
+}
+
+/// Delete a project with its triggers, documents and attachments.
+/// The contract metadata and file are deleted too when no other project uses the contract.
 #[utoipa::path(
     delete,
     path = "/api/console/project/{api_key}",
     params(
         ("api_key" = String, Path, description = "Project Api Key"),
+        ("export" = Option<bool>, Query, description = "Export the project as NDJSON before deleting it")
     ),
     responses(
-        (status = 200, description = "Project deleted successfully"),
+        (status = 200, description = "Project deleted successfully, with the path of its export if requested"),
+        (status = 404, description = "Project not found"),
         (status = 500, description = "Internal server error")
     )
 )]
 pub async fn delete_project(
     State(triggr): State<Triggr>,
     Path(api_key): Path<String>,
+    Query(query): Query<DeleteProjectQuery>,
     auth: Auth,
 ) -> Result<impl IntoResponse, AppError> {
-    // Get API Key from public cypher id
-    let encryption_key = env::var("TRIGGR_ENCRYPTION_KEY")
-        .or_else(|_| Err(AppError::Internal("Encryption key not set in env.".into())))?;
-    let decrypted_key = &decrypt(&api_key, &encryption_key)
-        .or_else(|_| Err(AppError::Internal("Decryption failed".into())))?;
+    let (decrypted_key, project) = owned_project_key(&triggr, &api_key, &auth)?;
+
+    // Export the project before anything is deleted
+    let mut export = None;
+    if query.export {
+        let dir = env::var("TRIGGR_EXPORT_DIR").unwrap_or_else(|_| DEFAULT_EXPORT_DIR.to_string());
+        let path = PathBuf::from(dir).join(format!(
+            "{}-{}.ndjson",
+            project.id,
+            chrono::Utc::now().format("%Y%m%dT%H%M%S")
+        ));
+
+        let (store, project) = (triggr.store.clone(), project.clone());
+        let file = path.clone();
+        tokio::task::spawn_blocking(move || -> StorageResult<u64> {
+            std::fs::create_dir_all(file.parent().unwrap_or(&file))?;
+            let mut out = std::io::BufWriter::new(std::fs::File::create(&file)?);
+            store.export_project(&project, &mut out)
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Export failed: {e}")))??;
+
+        export = Some(path.display().to_string());
+    }
 
     // Use auth id to delete project
-    let _ = ProjectStore::delete(&*triggr.store, &decrypted_key, &auth.claims.user_id)?;
+    let project = ProjectStore::delete(&*triggr.store, &decrypted_key, &auth.claims.user_id)?;
+
+    // Drop the contract metadata and file once no project uses the contract
+    let contract_in_use = triggr
+        .store
+        .all_projects()?
+        .iter()
+        .any(|p| p.contract_address == project.contract_address);
+    if !contract_in_use {
+        if let Some(entry) = triggr.store.remove_metadata_entry(&project.contract_address)? {
+            let _removed = tokio::fs::remove_file(&entry.path).await;
+            #[cfg(feature = "tracing")]
+            if let Err(e) = _removed {
+                tracing::warn!("Failed to remove contract file {}: {}", entry.path, e);
+            }
+        }
+        triggr.cache.forget(&project.contract_address);
+    }
+
+    // Documents and run logs can be large, they are dropped in the background
+    triggr.store.spawn_purge();
 
     Ok(Json(json!({
-        "message": "Project deleted successfully."
+        "message": "Project deleted successfully.",
+        "data": { "export": export }
     })))
 }
 
@@ -306,23 +383,72 @@ pub async fn delete_project(
 pub async fn get_project(
     State(triggr): State<Triggr>,
     Path(api_key): Path<String>,
-    _auth: Auth,
+    auth: Auth,
 ) -> Result<impl IntoResponse, AppError> {
-    // Get API Key from public cypher id
-    let encryption_key = env::var("TRIGGR_ENCRYPTION_KEY")
-        .or_else(|_| Err(AppError::Internal("Encryption key not set in env.".into())))?;
-    let decrypted_key = &decrypt(&api_key, &encryption_key)
-        .or_else(|_| Err(AppError::Internal("Decryption failed".into())))?;
-
-    // Fetch and return projects
-    let project =
-        ProjectStore::get(&*triggr.store, &decrypted_key)?.or_not_found("Project not found")?;
+    let project = owned_project(&triggr, &api_key, &auth)?;
 
     Ok(Json(json!({
         "project": project
     })))
 }
 
+/// Return the usage of a project's quotas, in both namespaces.
+/// Quotas above a warning threshold carry it, so consoles can show a banner.
+#[utoipa::path(
+    get,
+    path = "/api/console/project/{api_key}/usage",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    responses(
+        (status = 200, description = "Usage of the project quotas", body = [QuotaUsage]),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn get_usage(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+) -> Result<impl IntoResponse, AppError> {
+    let project = owned_project(&triggr, &api_key, &auth)?;
+
+    let usage: Vec<QuotaUsage> = [Namespace::Live, Namespace::Sandbox]
+        .iter()
+        .flat_map(|namespace| quota::project_usage(&triggr, &namespace.scope(&project.id)))
+        .collect();
+
+    Ok(Json(json!({
+        "data": usage
+    })))
+}
+
+/// Provision a demo project for the user: the EventDemo contract metadata, sample triggers and
+/// seed documents. Calling it again returns the existing demo project, without its API key.
+#[utoipa::path(
+    post,
+    path = "/api/console/bootstrap",
+    responses(
+        (status = 201, description = "Demo project created, with its API key", body = Bootstrap),
+        (status = 200, description = "The user already has a demo project", body = Bootstrap),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn bootstrap(
+    State(triggr): State<Triggr>,
+    auth: Auth,
+) -> Result<(StatusCode, Json<Bootstrap>), AppError> {
+    let bootstrap =
+        bootstrap::provision(&triggr, &auth.claims.user_id, bootstrap::DEMO_CONTRACT).await?;
+    let status = match bootstrap.created {
+        true => StatusCode::CREATED,
+        false => StatusCode::OK,
+    };
+
+    Ok((status, Json(bootstrap)))
+}
+
 /// List all projects belonging to a specific user.
 /// Fetches all projects associated with the given `user_id`.
 #[utoipa::path(
@@ -348,3 +474,594 @@ pub async fn list_projects(
         )),
     }
 }
+
+/// Return event decoding failures recorded for a project's contract.
+#[utoipa::path(
+    get,
+    path = "/api/console/project/{api_key}/decoding-errors",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    responses(
+        (status = 200, description = "Decoding statistics and latest failures", body = ContractDecodeStats),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn get_decoding_errors(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+) -> Result<impl IntoResponse, AppError> {
+    let project = owned_project(&triggr, &api_key, &auth)?;
+
+    let stats = triggr
+        .decode_stats
+        .read()
+        .await
+        .get(&project.contract_address);
+
+    Ok(Json(json!({
+        "data": stats
+    })))
+}
+
+/// Struct modelling a decode mode change.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpdateDecodeMode {
+    pub mode: DecodeMode,
+}
+
+/// Set how strictly a project's contract events are decoded.
+/// Events are decoded once for every project of a contract, so its projects share a mode.
+#[utoipa::path(
+    put,
+    path = "/api/console/project/{api_key}/decode-mode",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    request_body(content = inline(UpdateDecodeMode)),
+    responses(
+        (status = 200, description = "Decode mode updated", body = Project),
+        (status = 400, description = "Another project of the contract uses another mode"),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn update_decode_mode(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+    Json(payload): Json<UpdateDecodeMode>,
+) -> Result<impl IntoResponse, AppError> {
+    let (decrypted_key, mut project) = owned_project_key(&triggr, &api_key, &auth)?;
+
+    let conflict = triggr.store.all_projects()?.iter().any(|other| {
+        other.contract_address == project.contract_address
+            && other.id != project.id
+            && other.decode_mode != payload.mode
+    });
+    if conflict {
+        return Err(AppError::Validation {
+            field: "mode".to_string(),
+            message: "Another project watching this contract uses another decode mode. \
+                Events of a contract are decoded once for all its projects"
+                .to_string(),
+        });
+    }
+
+    project.decode_mode = payload.mode;
+    ProjectStore::update(&*triggr.store, &decrypted_key, &project)?;
+
+    // Apply to incoming events right away
+    triggr
+        .cache
+        .save_decode_mode(&project.contract_address, project.decode_mode);
+
+    Ok(Json(json!({
+        "data": project
+    })))
+}
+
+/// Struct modelling an ordering mode change.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpdateOrdering {
+    /// Execute the contract's events one at a time, in chain order
+    pub strict: bool,
+}
+
+/// Set whether a project's contract events are executed strictly in chain order.
+#[utoipa::path(
+    put,
+    path = "/api/console/project/{api_key}/ordering",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    request_body(content = inline(UpdateOrdering)),
+    responses(
+        (status = 200, description = "Ordering mode updated", body = Project),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn update_ordering(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+    Json(payload): Json<UpdateOrdering>,
+) -> Result<impl IntoResponse, AppError> {
+    let (decrypted_key, mut project) = owned_project_key(&triggr, &api_key, &auth)?;
+
+    project.strict_ordering = payload.strict;
+    ProjectStore::update(&*triggr.store, &decrypted_key, &project)?;
+
+    // Apply to incoming events right away
+    triggr
+        .cache
+        .save_strict_ordering(&project.contract_address, project.strict_ordering);
+
+    Ok(Json(json!({
+        "data": project
+    })))
+}
+
+/// Set the filters applied to a project's events before trigger matching.
+#[utoipa::path(
+    put,
+    path = "/api/console/project/{api_key}/event-routes",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    request_body(content = EventRoutes),
+    responses(
+        (status = 200, description = "Event routes updated", body = Project),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn update_event_routes(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+    Json(routes): Json<EventRoutes>,
+) -> Result<impl IntoResponse, AppError> {
+    let (decrypted_key, mut project) = owned_project_key(&triggr, &api_key, &auth)?;
+
+    project.event_routes = routes;
+    ProjectStore::update(&*triggr.store, &decrypted_key, &project)?;
+
+    // Apply to incoming events right away
+    triggr
+        .cache
+        .save_event_routes(&project.contract_address, project.event_routes.clone());
+
+    Ok(Json(json!({
+        "data": project
+    })))
+}
+
+/// Set the enrichers attaching off-chain context to a project's events before rules run.
+/// Enrichers run in order, so one can read the field added by a previous one.
+#[utoipa::path(
+    put,
+    path = "/api/console/project/{api_key}/enrichers",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    request_body(content = Vec<Enricher>),
+    responses(
+        (status = 200, description = "Enrichers updated", body = Project),
+        (status = 400, description = "Invalid enricher"),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn update_enrichers(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+    Json(enrichers): Json<Vec<Enricher>>,
+) -> Result<impl IntoResponse, AppError> {
+    enrich::validate(&enrichers).map_err(|message| AppError::Validation {
+        field: "enrichers".to_string(),
+        message,
+    })?;
+
+    let (decrypted_key, mut project) = owned_project_key(&triggr, &api_key, &auth)?;
+
+    project.enrichers = enrichers;
+    ProjectStore::update(&*triggr.store, &decrypted_key, &project)?;
+
+    // Apply to incoming events right away
+    triggr.cache.save_enrichers(
+        &project.contract_address,
+        &project.id,
+        project.enrichers.clone(),
+    );
+
+    Ok(Json(json!({
+        "data": project
+    })))
+}
+
+/// Sandbox settings of a project.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpdateSandbox {
+    /// Whether live events also run the triggers in the sandbox
+    pub mirror: bool,
+}
+
+/// Return the sandbox settings of a project, with a key that can only reach its sandbox.
+#[utoipa::path(
+    get,
+    path = "/api/console/project/{api_key}/sandbox",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    responses(
+        (status = 200, description = "Sandbox settings"),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn get_sandbox(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+) -> Result<impl IntoResponse, AppError> {
+    sandbox_settings(&triggr, &api_key, &auth, None)
+}
+
+/// Set whether live events of a project are mirrored into its sandbox.
+#[utoipa::path(
+    put,
+    path = "/api/console/project/{api_key}/sandbox",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    request_body(content = inline(UpdateSandbox)),
+    responses(
+        (status = 200, description = "Sandbox settings updated"),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn put_sandbox(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+    Json(payload): Json<UpdateSandbox>,
+) -> Result<impl IntoResponse, AppError> {
+    sandbox_settings(&triggr, &api_key, &auth, Some(payload))
+}
+
+/// Apply a sandbox settings change, if any, and return the settings.
+fn sandbox_settings(
+    triggr: &Triggr,
+    api_key: &str,
+    auth: &Auth,
+    payload: Option<UpdateSandbox>,
+) -> Result<Json<Value>, AppError> {
+    let (decrypted_key, mut project) = owned_project_key(triggr, api_key, auth)?;
+
+    if let Some(payload) = payload {
+        project.sandbox_mirror = payload.mirror;
+        ProjectStore::update(&*triggr.store, &decrypted_key, &project)?;
+
+        // Apply to incoming events right away
+        triggr
+            .cache
+            .save_sandbox_mirror(&project.contract_address, project.sandbox_mirror);
+    }
+
+    let encryption_key = env::var("TRIGGR_ENCRYPTION_KEY")
+        .map_err(|_| AppError::Internal("Encryption key not set in env.".into()))?;
+    let sandbox_key = namespace::sandbox_key(&decrypted_key, &encryption_key)
+        .map_err(|_| AppError::Internal("Encryption failed".into()))?;
+
+    Ok(Json(json!({
+        "data": {
+            "mirror": project.sandbox_mirror,
+            "key": sandbox_key,
+        }
+    })))
+}
+
+/// Service account creation request.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateServiceAccount {
+    /// What the account is used for (e.g. `github-deploy`)
+    pub name: String,
+    /// What its token may do
+    pub scopes: Vec<Scope>,
+    /// Lifetime of the token in days (it never expires if not set)
+    pub ttl_days: Option<u64>,
+}
+
+/// Return the service accounts of a project.
+#[utoipa::path(
+    get,
+    path = "/api/console/project/{api_key}/service-accounts",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    responses(
+        (status = 200, description = "Service accounts of the project", body = Vec<ServiceAccount>),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn list_service_accounts(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+) -> Result<impl IntoResponse, AppError> {
+    let project = owned_project(&triggr, &api_key, &auth)?;
+    let accounts = triggr.store.list_service_accounts(&project.id)?;
+
+    Ok(Json(json!({ "data": accounts })))
+}
+
+/// Create a service account of a project. Its token is only returned once.
+#[utoipa::path(
+    post,
+    path = "/api/console/project/{api_key}/service-accounts",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    request_body(content = inline(CreateServiceAccount)),
+    responses(
+        (status = 201, description = "Service account created, with its token"),
+        (status = 400, description = "Invalid name, scopes or lifetime"),
+        (status = 404, description = "Project not found"),
+        (status = 429, description = "The project has too many service accounts"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn create_service_account(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+    Json(payload): Json<CreateServiceAccount>,
+) -> Result<impl IntoResponse, AppError> {
+    let project = owned_project(&triggr, &api_key, &auth)?;
+
+    if payload.scopes.is_empty() {
+        return Err(AppError::Validation {
+            field: "scopes".to_string(),
+            message: "A service account needs at least one scope".to_string(),
+        });
+    }
+    let expires_at = match payload.ttl_days {
+        Some(0) => {
+            return Err(AppError::Validation {
+                field: "ttl_days".to_string(),
+                message: "The lifetime must be at least one day".to_string(),
+            })
+        }
+        Some(days) => Some(
+            (chrono::Utc::now().timestamp_millis() as u64)
+                .saturating_add(days.saturating_mul(86_400_000)),
+        ),
+        None => None,
+    };
+    if triggr.store.list_service_accounts(&project.id)?.len() >= MAX_SERVICE_ACCOUNTS {
+        return Err(AppError::LimitExceeded {
+            limit: "service_accounts".to_string(),
+            max: MAX_SERVICE_ACCOUNTS,
+        });
+    }
+
+    let (account, token) = triggr.store.create_service_account(
+        &project,
+        payload.name.trim(),
+        payload.scopes,
+        expires_at,
+    )?;
+    tracing::info!(
+        "Service account {} ({}) of project {} created by {}",
+        account.name,
+        account.id,
+        project.id,
+        auth.claims.user_id
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "data": { "account": account, "token": token } })),
+    ))
+}
+
+/// Delete a service account of a project, revoking its token.
+#[utoipa::path(
+    delete,
+    path = "/api/console/project/{api_key}/service-accounts/{id}",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+        ("id" = String, Path, description = "Service account id"),
+    ),
+    responses(
+        (status = 200, description = "Service account deleted"),
+        (status = 404, description = "Project or service account not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn delete_service_account(
+    State(triggr): State<Triggr>,
+    Path((api_key, id)): Path<(String, String)>,
+    auth: Auth,
+) -> Result<impl IntoResponse, AppError> {
+    let project = owned_project(&triggr, &api_key, &auth)?;
+
+    if !triggr.store.delete_service_account(&project.id, &id)? {
+        return Err(AppError::NotFound("Service account not found".into()));
+    }
+    tracing::info!(
+        "Service account {id} of project {} deleted by {}",
+        project.id,
+        auth.claims.user_id
+    );
+
+    Ok(Json(json!({ "data": { "deleted": true } })))
+}
+
+/// Apply a declarative configuration (collections, triggers and their webhooks) to a project.
+/// Only what differs from the stored configuration is written, and configuration missing from
+/// the spec is removed.
+#[utoipa::path(
+    post,
+    path = "/api/console/project/{api_key}/apply",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    request_body(content = ProjectSpec),
+    responses(
+        (status = 200, description = "Changes made to the project", body = ApplyPlan),
+        (status = 400, description = "Invalid spec, nothing was changed"),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn apply_spec(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+    Json(spec): Json<ProjectSpec>,
+) -> Result<impl IntoResponse, AppError> {
+    let project = owned_project(&triggr, &api_key, &auth)?;
+    let plan = apply::apply(&triggr, &project, spec).await?;
+
+    Ok(Json(json!({ "data": plan })))
+}
+
+/// Preview the changes applying a declarative configuration would make, without making them.
+#[utoipa::path(
+    post,
+    path = "/api/console/project/{api_key}/plan",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    request_body(content = ProjectSpec),
+    responses(
+        (status = 200, description = "Changes applying the spec would make", body = ApplyPlan),
+        (status = 400, description = "Invalid spec"),
+        (status = 404, description = "Project not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn plan_spec(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+    Json(spec): Json<ProjectSpec>,
+) -> Result<impl IntoResponse, AppError> {
+    let project = owned_project(&triggr, &api_key, &auth)?;
+    let plan = apply::plan(&triggr, &project, spec).await?;
+
+    Ok(Json(json!({ "data": plan })))
+}
+
+/// Compare the configuration of a project with the spec last applied to it, to detect edits
+/// made outside of the spec (e.g. in the console).
+#[utoipa::path(
+    get,
+    path = "/api/console/project/{api_key}/drift",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    responses(
+        (status = 200, description = "Drift from the last applied spec", body = Drift),
+        (status = 404, description = "Project not found, or no spec was applied"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn get_drift(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+) -> Result<impl IntoResponse, AppError> {
+    let project = owned_project(&triggr, &api_key, &auth)?;
+    let drift = apply::drift(&triggr, &project)
+        .await?
+        .or_not_found("No spec was applied to this project")?;
+
+    Ok(Json(json!({ "data": drift })))
+}
+
+/// Struct modelling a raw event to test-decode.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct DecodeRequest {
+    /// SCALE encoded event data (hex)
+    pub data: String,
+    /// Event topics (hex), checked against signature topics in strict mode
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// Decode mode to try, instead of the project's
+    #[serde(default)]
+    pub mode: Option<DecodeMode>,
+}
+
+/// Test-decode a raw event with the project's contract metadata, showing which event spec
+/// matched and why the others were rejected.
+#[utoipa::path(
+    post,
+    path = "/api/console/project/{api_key}/decode",
+    params(
+        ("api_key" = String, Path, description = "Project Api Key"),
+    ),
+    request_body(content = DecodeRequest),
+    responses(
+        (status = 200, description = "Outcome of the decode", body = DecodeReport),
+        (status = 400, description = "Invalid event data"),
+        (status = 404, description = "Project or contract metadata not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn decode_event(
+    State(triggr): State<Triggr>,
+    Path(api_key): Path<String>,
+    auth: Auth,
+    Json(payload): Json<DecodeRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let project = owned_project(&triggr, &api_key, &auth)?;
+    let metadata = triggr
+        .contract_metadata(&project.contract_address)
+        .await
+        .or_not_found("No metadata for the project's contract")?;
+
+    let bytes = hex::decode(payload.data.trim().trim_start_matches("0x"))
+        .map_err(|e| AppError::BadRequest(format!("Invalid event data: {e}")))?;
+    let mode = payload.mode.unwrap_or(project.decode_mode);
+    let report = explain_decode(&bytes, &payload.topics, &metadata, mode);
+
+    Ok(Json(json!({ "data": report })))
+}
+
+/// Return the project of a console key, if the user owns it.
+fn owned_project(triggr: &Triggr, api_key: &str, auth: &Auth) -> Result<Project, AppError> {
+    owned_project_key(triggr, api_key, auth).map(|(_, project)| project)
+}
+
+/// Return the decrypted key and the project of a console key, if the user owns it.
+/// A key that doesn't decrypt names no project, so it is not found rather than a server error.
+fn owned_project_key(
+    triggr: &Triggr,
+    api_key: &str,
+    auth: &Auth,
+) -> Result<(String, Project), AppError> {
+    // Get API Key from public cypher id
+    let encryption_key = env::var("TRIGGR_ENCRYPTION_KEY")
+        .map_err(|_| AppError::Internal("Encryption key not set in env.".into()))?;
+    let decrypted_key = decrypt(api_key, &encryption_key)
+        .map_err(|_| AppError::NotFound("Project not found".into()))?;
+
+    let project =
+        ProjectStore::get(&*triggr.store, &decrypted_key)?.or_not_found("Project not found")?;
+
+    // Only the owner may see or change the project
+    if project.owner != auth.claims.user_id {
+        return Err(AppError::NotFound("Project not found".into()));
+    }
+
+    Ok((decrypted_key, project))
+}
@@ -3,17 +3,49 @@
 // This module contains HTTP(S) route handlers to perform database operations.
 
 use crate::{
-    prelude::{Document, DocumentStore, StorageError, Triggr},
+    aggregate::{self, parse_bucket, Aggregation, Series, SeriesQuery},
+    geo::{GeoIndex, Near},
+    lifecycle::StateMachine,
+    name::{Name, NameError},
+    prelude::{Document, DocumentStore, Precondition, StorageError, Triggr},
     server::middleware::RefProject,
-    storage::CollectionSummary
+    storage::{AttachmentInfo, CollectionSummary, TopicStats},
+    syntax::ParseError,
+    util::content_hash,
+    watchlist::{self, Watchlist},
 };
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use chrono::Utc;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sled::IVec;
+use std::env;
+use utoipa::ToSchema;
+
+/// Media type of newline-delimited JSON, one document per line.
+const NDJSON: &str = "application/x-ndjson";
+
+/// Default max size of a single document attachment
+const DEFAULT_MAX_ATTACHMENT_SIZE: usize = 5 * 1024 * 1024; // 5MB
+
+/// Size of the chunks attachments are streamed in.
+const ATTACHMENT_CHUNK: usize = 64 * 1024;
+
+/// Default max nesting depth of a document
+const DEFAULT_MAX_JSON_DEPTH: usize = 32;
+
+/// Default max number of values (fields and array items) in a document
+const DEFAULT_MAX_JSON_FIELDS: usize = 10_000;
+
+/// Default width of chart buckets.
+const DEFAULT_CHART_BUCKET: &str = "1h";
 
 /// Generic error returned from internal database operations.
 #[derive(Debug)]
@@ -22,8 +54,20 @@ pub enum AppError {
     NotFound(String),
     /// Bad request
     BadRequest(String),
+    /// The request could not be authenticated
+    Unauthorized(String),
     /// Internal server error
     Internal(String),
+    /// Payload too large
+    PayloadTooLarge(String),
+    /// A field of the request failed validation
+    Validation { field: String, message: String },
+    /// A usage limit of the project was reached
+    LimitExceeded { limit: String, max: usize },
+    /// A conditional request's precondition doesn't hold
+    PreconditionFailed(String),
+    /// A trigger script failed to parse
+    InvalidScript(ParseError),
 }
 
 // Implement conversion from generic StorageError to AppError.
@@ -31,9 +75,25 @@ impl From<StorageError> for AppError {
     fn from(err: StorageError) -> Self {
         match err {
             StorageError::NotFound(msg) => AppError::NotFound(msg),
+            StorageError::PreconditionFailed(msg) => AppError::PreconditionFailed(msg),
             StorageError::Sled(e) => AppError::Internal(e.to_string()),
             StorageError::Serde(e) => AppError::BadRequest(e.to_string()),
             StorageError::Other(msg) => AppError::Internal(msg),
+            StorageError::Corrupted(msg) => AppError::Internal(msg),
+            StorageError::InvalidTransition { field, message }
+            | StorageError::InvalidField { field, message } => {
+                AppError::Validation { field, message }
+            }
+        }
+    }
+}
+
+// Name validation failures are reported against the offending field.
+impl From<NameError> for AppError {
+    fn from(err: NameError) -> Self {
+        AppError::Validation {
+            field: err.kind.to_string(),
+            message: err.to_string(),
         }
     }
 }
@@ -44,7 +104,52 @@ impl IntoResponse for AppError {
         let (status, message) = match self {
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
             AppError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            AppError::PayloadTooLarge(msg) => (StatusCode::PAYLOAD_TOO_LARGE, msg),
+            AppError::PreconditionFailed(msg) => (StatusCode::PRECONDITION_FAILED, msg),
+            AppError::Validation { field, message } => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "error": {
+                            "code": "validation_failed",
+                            "field": field,
+                            "message": message,
+                        }
+                    })),
+                )
+                    .into_response();
+            }
+            AppError::InvalidScript(error) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "error": {
+                            "code": "parse_failed",
+                            "message": error.message,
+                            "line": error.line,
+                            "column": error.column,
+                            "snippet": error.snippet,
+                        }
+                    })),
+                )
+                    .into_response();
+            }
+            AppError::LimitExceeded { limit, max } => {
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(json!({
+                        "error": {
+                            "code": "limit_exceeded",
+                            "limit": limit,
+                            "max": max,
+                            "message": format!("Maximum number of {limit} ({max}) reached"),
+                        }
+                    })),
+                )
+                    .into_response();
+            }
         };
 
         (status, Json(json!({ "error": message }))).into_response()
@@ -63,6 +168,240 @@ impl<T> OptionExt<T> for Option<T> {
     }
 }
 
+/// Read a limit from the environment, falling back to a default.
+fn env_limit(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Whether the client's `If-None-Match` header matches an entity tag (weak comparison).
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(value) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    value
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// Respond with a body and its entity tag, or `304 Not Modified` if the client has it already.
+fn with_etag(headers: &HeaderMap, etag: String, body: Value) -> Response {
+    if etag_matches(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    (StatusCode::OK, [(header::ETAG, etag)], Json(body)).into_response()
+}
+
+/// Whether the client accepts a media type.
+fn accepts(headers: &HeaderMap, media_type: &str) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.split(',')
+                .any(|t| t.split(';').next().unwrap_or_default().trim() == media_type)
+        })
+}
+
+/// Stream raw documents as `{"data":[...]}`, one document per chunk.
+fn json_array_body<D: AsRef<[u8]>>(
+    docs: impl Iterator<Item = Result<D, StorageError>> + Send + 'static,
+) -> Body {
+    let mut first = true;
+    let docs = docs.map(move |doc| {
+        doc.map(|doc| {
+            let mut chunk = Vec::with_capacity(doc.as_ref().len() + 1);
+            if !first {
+                chunk.push(b',');
+            }
+            first = false;
+            chunk.extend_from_slice(doc.as_ref());
+            chunk
+        })
+    });
+
+    let chunks = std::iter::once(Ok(b"{\"data\":[".to_vec()))
+        .chain(docs)
+        .chain(std::iter::once(Ok(b"]}".to_vec())));
+    Body::from_stream(futures::stream::iter(chunks))
+}
+
+/// Stream raw documents as newline-delimited JSON.
+fn ndjson_body<D: AsRef<[u8]>>(
+    docs: impl Iterator<Item = Result<D, StorageError>> + Send + 'static,
+) -> Body {
+    let lines = docs.map(|doc| {
+        doc.map(|doc| {
+            let mut line = Vec::with_capacity(doc.as_ref().len() + 1);
+            line.extend_from_slice(doc.as_ref());
+            line.push(b'\n');
+            line
+        })
+    });
+    Body::from_stream(futures::stream::iter(lines))
+}
+
+/// Stream raw documents as JSON or, with `Accept: application/x-ndjson`, as newline-delimited JSON.
+/// With a field selection, only the selected paths of each document are sent.
+fn documents_body<D: AsRef<[u8]>>(
+    headers: &HeaderMap,
+    docs: impl Iterator<Item = Result<D, StorageError>> + Send + 'static,
+    fields: Option<FieldSelection>,
+) -> (&'static str, Body) {
+    let Some(fields) = fields else {
+        return stream_documents(headers, docs);
+    };
+
+    let docs = docs.map(move |doc| {
+        let doc: Value = serde_json::from_slice(doc?.as_ref())?;
+        Ok(serde_json::to_vec(&fields.project(&doc))?)
+    });
+    stream_documents(headers, docs)
+}
+
+/// Pick the body format of a document list from the `Accept` header.
+fn stream_documents<D: AsRef<[u8]>>(
+    headers: &HeaderMap,
+    docs: impl Iterator<Item = Result<D, StorageError>> + Send + 'static,
+) -> (&'static str, Body) {
+    if accepts(headers, NDJSON) {
+        (NDJSON, ndjson_body(docs))
+    } else {
+        ("application/json", json_array_body(docs))
+    }
+}
+
+/// Paths of the documents to return, e.g. `?fields=data.amount,metadata.updated_at`.
+struct FieldSelection(Vec<Vec<String>>);
+
+impl FieldSelection {
+    /// Parse comma-separated dotted paths, `None` selecting whole documents.
+    fn parse(fields: Option<&str>) -> Result<Option<Self>, AppError> {
+        let Some(fields) = fields else {
+            return Ok(None);
+        };
+
+        let paths = fields
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(|path| {
+                let segments: Vec<String> = path.split('.').map(str::to_string).collect();
+                let known_root = matches!(segments[0].as_str(), "id" | "data" | "metadata");
+                if !known_root || segments.iter().any(String::is_empty) {
+                    return Err(AppError::Validation {
+                        field: "fields".to_string(),
+                        message: format!(
+                            "Invalid path '{path}': expected dotted fields of id, data or metadata"
+                        ),
+                    });
+                }
+                Ok(segments)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((!paths.is_empty()).then_some(Self(paths)))
+    }
+
+    /// Keep the selected paths of a document, and its id. Missing paths are left out.
+    fn project(&self, doc: &Value) -> Value {
+        let mut projected = json!({ "id": doc["id"] });
+        for path in &self.0 {
+            let Some(value) = path.iter().try_fold(doc, |value, key| value.get(key)) else {
+                continue;
+            };
+
+            // The parents of a found value are objects in the document, so in the projection too
+            let mut target = &mut projected;
+            for key in path {
+                target = &mut target[key.as_str()];
+            }
+            *target = value.clone();
+        }
+
+        projected
+    }
+
+    /// Entity tag of the selected paths of a document or list tagged `etag`.
+    /// Each selection gets its own tag, so a client never revalidates one projection with another.
+    fn etag(&self, etag: &str) -> String {
+        let opaque = etag.trim_start_matches("W/").trim_matches('"');
+        format!("W/\"{opaque}-{}\"", content_hash(&json!(self.0)))
+    }
+}
+
+/// Query parameters of document reads.
+#[derive(Deserialize)]
+pub struct ReadParams {
+    /// Comma-separated paths of the document to return, e.g. `data.amount,metadata.updated_at`
+    fields: Option<String>,
+}
+
+/// Query parameters of conditional writes.
+#[derive(Deserialize)]
+pub struct WriteParams {
+    /// Version the stored document must be at
+    expected_version: Option<u64>,
+}
+
+/// Read the precondition of a write from the `If-Match` header or `?expected_version=`.
+fn precondition(headers: &HeaderMap, params: &WriteParams) -> Option<Precondition> {
+    let if_match = headers
+        .get(header::IF_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| Precondition::Etag(v.split(',').map(String::from).collect()));
+
+    if_match.or(params.expected_version.map(Precondition::Version))
+}
+
+/// Validate a document before it is stored.
+fn validate_document(doc: &Document) -> Result<(), AppError> {
+    // The id is part of the storage key
+    Name::document_id(&doc.id)?;
+
+    let max_depth = env_limit("TRIGGR_MAX_JSON_DEPTH", DEFAULT_MAX_JSON_DEPTH);
+    let max_fields = env_limit("TRIGGR_MAX_JSON_FIELDS", DEFAULT_MAX_JSON_FIELDS);
+
+    let (depth, fields) = json_shape(&doc.data);
+    if depth > max_depth {
+        return Err(AppError::Validation {
+            field: "data".to_string(),
+            message: format!("Document is nested {depth} levels deep, max is {max_depth}"),
+        });
+    }
+    if fields > max_fields {
+        return Err(AppError::Validation {
+            field: "data".to_string(),
+            message: format!("Document has {fields} values, max is {max_fields}"),
+        });
+    }
+
+    Ok(())
+}
+
+/// Return the nesting depth and the number of nested values of a JSON value.
+fn json_shape(value: &Value) -> (usize, usize) {
+    match value {
+        Value::Object(map) => map.values().fold((1, 0), |(depth, count), v| {
+            let (d, c) = json_shape(v);
+            (depth.max(d + 1), count + c + 1)
+        }),
+        Value::Array(items) => items.iter().fold((1, 0), |(depth, count), v| {
+            let (d, c) = json_shape(v);
+            (depth.max(d + 1), count + c + 1)
+        }),
+        _ => (0, 0),
+    }
+}
+
 /// List all collections for a project
 #[utoipa::path(
     get,
@@ -76,7 +415,7 @@ pub async fn list_collections(
     State(triggr): State<Triggr>,
     ref_project: RefProject,
 ) -> Result<impl IntoResponse, AppError> {
-    let cols = match triggr.store.list_collections(&ref_project.project.id) {
+    let cols = match triggr.store.list_collections(&ref_project.data_id()) {
         Ok(collections) => collections,
         Err(StorageError::NotFound(_)) => {
             // Return empty vec
@@ -113,42 +452,204 @@ pub async fn insert_document(
     Path(name): Path<String>,
     Json(doc): Json<Document>,
 ) -> Result<impl IntoResponse, AppError> {
-    DocumentStore::insert(&*triggr.store, &ref_project.project.id, &name, doc, false).await?;
+    let name = Name::collection(&name)?;
+
+    validate_document(&doc)?;
+
+    DocumentStore::insert(&*triggr.store, &ref_project.data_id(), &name, doc, false).await?;
     Ok((StatusCode::CREATED, Json(json!({ "ok": true }))))
 }
 
-/// List all documents in a collection
+/// Show live subscribers and the last broadcast of each topic of a collection.
 #[utoipa::path(
     get,
-    path = "/api/db/collections/{name}/docs",
+    path = "/api/db/collections/{name}/subscribers",
     params(
         ("name" = String, Path, description = "Collection name")
     ),
     responses(
-        (status = 200, description = "List of documents in the collection", body = [Document]),
+        (status = 200, description = "Topics of the collection and their subscribers", body = [TopicStats]),
+        (status = 400, description = "Invalid collection name")
+    )
+)]
+pub async fn collection_subscribers(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+
+    let topics = triggr.store.subscriptions.collection_topics(&name).await;
+    let subscribers: usize = topics.iter().map(|t| t.subscribers).sum();
+
+    Ok(Json(json!({
+        "data": {
+            "topics": topics,
+            "subscribers": subscribers,
+        }
+    })))
+}
+
+/// Query parameters of document lists.
+#[derive(Deserialize)]
+pub struct ListParams {
+    /// Radius query on the geo field of the collection: `near(lat, lon, radius)`
+    near: Option<String>,
+    /// Comma-separated paths of the documents to return, e.g. `data.amount,metadata.updated_at`
+    fields: Option<String>,
+}
+
+/// List all documents in a collection.
+/// Documents are streamed as they are read, as a JSON array or, with `Accept: application/x-ndjson`,
+/// as newline-delimited JSON.
+/// With `near`, only the documents within the radius (meters) are listed, nearest first.
+#[utoipa::path(
+    get,
+    path = "/api/db/collections/{name}/docs",
+    params(
+        ("name" = String, Path, description = "Collection name"),
+        ("near" = Option<String>, Query, description = "Documents within a radius of a point: `near(lat, lon, radius)`, radius in meters"),
+        ("fields" = Option<String>, Query, description = "Comma-separated paths to return, e.g. `data.amount,metadata.updated_at`")
+    ),
+    responses(
+        (status = 200, description = "List of documents in the collection", content(
+            ([Document] = "application/json"),
+            (Document = "application/x-ndjson")
+        )),
+        (status = 304, description = "Documents unchanged since the `If-None-Match` ETag"),
+        (status = 400, description = "Invalid near query or collection without a geo index"),
         (status = 500, description = "Internal server error")
     )
 )]
 pub async fn list_documents(
     State(triggr): State<Triggr>,
     Path(name): Path<String>,
+    Query(params): Query<ListParams>,
+    headers: HeaderMap,
     ref_project: RefProject,
 ) -> Result<impl IntoResponse, AppError> {
-    let docs = match triggr.store.list(&ref_project.project.id, &name) {
-        Ok(docs) => docs,
-        Err(StorageError::NotFound(_)) => {
-            // Return empty vec
-            vec![]
+    let name = Name::collection(&name)?;
+    let project_id = &ref_project.data_id();
+    let fields = FieldSelection::parse(params.fields.as_deref())?;
+
+    // Radius queries read the geo index
+    if let Some(near) = params.near {
+        let near = Near::parse(&near).map_err(|message| AppError::Validation {
+            field: "near".to_string(),
+            message,
+        })?;
+        if triggr.store.get_geo_index(project_id, &name)?.is_none() {
+            return Err(AppError::BadRequest(format!(
+                "Collection {name} has no geo index"
+            )));
         }
-        Err(e) => return Err(AppError::from(e)),
-    };
-    
+
+        let docs = triggr
+            .store
+            .near_documents(project_id, &name, &near)?
+            .into_iter()
+            .map(Ok);
+        let (content_type, body) = documents_body(&headers, docs, fields);
+        return Ok(([(header::CONTENT_TYPE, content_type)], body).into_response());
+    }
+
+    // The list changes whenever a document is added, removed or changed
+    let mut etag = triggr.store.list_etag(project_id, &name)?;
+    if let Some(fields) = &fields {
+        etag = fields.etag(&etag);
+    }
+    if etag_matches(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+
+    // Documents go out as they are read, the list is never held in memory
+    let docs = triggr.store.scan_documents(project_id, &name)?;
+    let (content_type, body) = documents_body(&headers, docs, fields);
+
     Ok((
         StatusCode::OK,
-        Json(json!({
-            "data": docs
-        })),
-    ))
+        [
+            (header::ETAG, etag),
+            (header::CONTENT_TYPE, content_type.to_string()),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Query parameters of collection charts.
+#[derive(Deserialize)]
+pub struct ChartParams {
+    /// Field aggregated (not needed to count documents)
+    field: Option<String>,
+    /// Width of the buckets, e.g. `5m`, `1h`, `1d`
+    bucket: Option<String>,
+    /// Aggregation of the values of a bucket
+    agg: Option<Aggregation>,
+    /// Time documents are bucketed by: `created_at`, `updated_at` or a data field (unix ms)
+    time: Option<String>,
+    /// Start of the series (unix ms, inclusive)
+    from: Option<u64>,
+    /// End of the series (unix ms, exclusive)
+    to: Option<u64>,
+}
+
+/// Return a time series of a collection, ready to plot.
+/// Documents are grouped in time buckets and a field is aggregated per bucket on the server.
+#[utoipa::path(
+    get,
+    path = "/api/db/collections/{name}/chart",
+    params(
+        ("name" = String, Path, description = "Collection name"),
+        ("field" = Option<String>, Query, description = "Field aggregated (not needed to count documents)"),
+        ("bucket" = Option<String>, Query, description = "Width of the buckets: `30s`, `5m`, `1h` (default), `1d`, `1w`"),
+        ("agg" = Option<Aggregation>, Query, description = "Aggregation: count (default), sum, avg, min or max"),
+        ("time" = Option<String>, Query, description = "Time documents are bucketed by: `created_at` (default), `updated_at` or a data field holding unix ms"),
+        ("from" = Option<u64>, Query, description = "Start of the series (unix ms, inclusive)"),
+        ("to" = Option<u64>, Query, description = "End of the series (unix ms, exclusive)")
+    ),
+    responses(
+        (status = 200, description = "Series of the collection", body = Series),
+        (status = 400, description = "Invalid chart query"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn collection_chart(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    Query(params): Query<ChartParams>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+    let invalid = |field: &'static str| {
+        move |message| AppError::Validation {
+            field: field.to_string(),
+            message,
+        }
+    };
+
+    let query = SeriesQuery {
+        field: params.field,
+        aggregation: params.agg.unwrap_or_default(),
+        bucket: parse_bucket(params.bucket.as_deref().unwrap_or(DEFAULT_CHART_BUCKET))
+            .map_err(invalid("bucket"))?,
+        time: params
+            .time
+            .as_deref()
+            .unwrap_or("created_at")
+            .parse()
+            .map_err(invalid("time"))?,
+        from: params.from,
+        to: params.to,
+    };
+
+    let docs = triggr
+        .store
+        .scan_documents(&ref_project.data_id(), &name)?
+        .map(|value| Ok(serde_json::from_slice::<Document>(&value?)?))
+        .collect::<Result<Vec<_>, StorageError>>()?;
+    let series = aggregate::series(docs.into_iter(), &query).map_err(AppError::BadRequest)?;
+
+    Ok((StatusCode::OK, Json(json!({ "data": series }))))
 }
 
 /// Get a document by ID
@@ -157,10 +658,12 @@ pub async fn list_documents(
     path = "/api/db/collections/{name}/docs/{id}",
     params(
         ("name" = String, Path, description = "Collection name"),
-        ("id" = String, Path, description = "Document ID")
+        ("id" = String, Path, description = "Document ID"),
+        ("fields" = Option<String>, Query, description = "Comma-separated paths to return, e.g. `data.amount,metadata.updated_at`")
     ),
     responses(
         (status = 200, description = "Document retrieved successfully", body = Document),
+        (status = 304, description = "Document unchanged since the `If-None-Match` ETag"),
         (status = 404, description = "Document not found"),
         (status = 500, description = "Internal server error")
     )
@@ -168,18 +671,24 @@ pub async fn list_documents(
 pub async fn get_document(
     State(triggr): State<Triggr>,
     Path((name, id)): Path<(String, String)>,
+    Query(params): Query<ReadParams>,
+    headers: HeaderMap,
     ref_project: RefProject,
 ) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+    let id = Name::document_id(&id)?;
+    let fields = FieldSelection::parse(params.fields.as_deref())?;
+
     let doc = triggr
         .store
-        .get(&ref_project.project.id, &name, &id)?
+        .get(&ref_project.data_id(), &name, &id)?
         .or_not_found("Document {id} not found")?;
-    Ok((
-        StatusCode::OK,
-        Json(json!({
-            "data": doc
-        })),
-    ))
+
+    let (etag, data) = match fields {
+        Some(fields) => (fields.etag(&doc.etag()), fields.project(&json!(doc))),
+        None => (doc.etag(), json!(doc)),
+    };
+    Ok(with_etag(&headers, etag, json!({ "data": data })))
 }
 
 /// Update a document
@@ -189,12 +698,15 @@ pub async fn get_document(
     request_body = inline(Document),
     params(
         ("name" = String, Path, description = "Collection name"),
-        ("id" = String, Path, description = "Document ID")
+        ("id" = String, Path, description = "Document ID"),
+        ("If-Match" = Option<String>, Header, description = "Update only if the document's ETag matches"),
+        ("expected_version" = Option<u64>, Query, description = "Update only if the document is at this version")
     ),
     responses(
         (status = 200, description = "Document updated successfully", body = inline(serde_json::Value)),
         (status = 400, description = "Invalid document or malformed request"),
         (status = 404, description = "Document not found"),
+        (status = 412, description = "Document changed since the expected ETag or version"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -202,12 +714,24 @@ pub async fn update_document(
     ref_project: RefProject,
     State(triggr): State<Triggr>,
     Path((name, _)): Path<(String, String)>,
+    Query(params): Query<WriteParams>,
+    headers: HeaderMap,
     Json(doc): Json<Document>,
 ) -> Result<impl IntoResponse, AppError> {
-    triggr
-        .store
-        .update(&ref_project.project.id, &name, doc)
-        .await?;
+    let name = Name::collection(&name)?;
+
+    validate_document(&doc)?;
+
+    let project_id = &ref_project.data_id();
+    match precondition(&headers, &params) {
+        Some(precondition) => {
+            triggr
+                .store
+                .update_if(project_id, &name, doc, &precondition)
+                .await?
+        }
+        None => triggr.store.update(project_id, &name, doc).await?,
+    }
     Ok((StatusCode::OK, Json(json!({ "ok": true }))))
 }
 
@@ -217,22 +741,780 @@ pub async fn update_document(
     path = "/api/db/collections/{name}/docs/{id}",
     params(
         ("name" = String, Path, description = "Collection name"),
-        ("id" = String, Path, description = "Document ID")
+        ("id" = String, Path, description = "Document ID"),
+        ("If-Match" = Option<String>, Header, description = "Delete only if the document's ETag matches"),
+        ("expected_version" = Option<u64>, Query, description = "Delete only if the document is at this version")
     ),
     responses(
         (status = 204, description = "Document deleted successfully"),
         (status = 404, description = "Document not found"),
+        (status = 412, description = "Document changed since the expected ETag or version"),
         (status = 500, description = "Internal server error")
     )
 )]
 pub async fn delete_document(
     State(triggr): State<Triggr>,
     Path((name, id)): Path<(String, String)>,
+    Query(params): Query<WriteParams>,
+    headers: HeaderMap,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+    let id = Name::document_id(&id)?;
+
+    let project_id = &ref_project.data_id();
+    match precondition(&headers, &params) {
+        Some(precondition) => {
+            triggr
+                .store
+                .delete_if(project_id, &name, &id, &precondition)
+                .await?
+        }
+        None => triggr.store.delete(project_id, &name, &id).await?,
+    }
+    Ok((StatusCode::OK, Json(json!({ "ok": true }))))
+}
+
+/// Upload a binary attachment for a document
+#[utoipa::path(
+    put,
+    path = "/api/db/collections/{name}/docs/{id}/attachments/{key}",
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    params(
+        ("name" = String, Path, description = "Collection name"),
+        ("id" = String, Path, description = "Document ID"),
+        ("key" = String, Path, description = "Attachment key")
+    ),
+    responses(
+        (status = 201, description = "Attachment stored successfully", body = AttachmentInfo),
+        (status = 404, description = "Document not found"),
+        (status = 413, description = "Attachment too large"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn put_attachment(
     ref_project: RefProject,
+    State(triggr): State<Triggr>,
+    Path((name, id, key)): Path<(String, String, String)>,
+    headers: HeaderMap,
+    body: Body,
 ) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+    let id = Name::document_id(&id)?;
+    let key = Name::parse("attachment key", &key)?;
+
+    let project_id = &ref_project.data_id();
+
+    // Attachments always belong to an existing document
+    triggr
+        .store
+        .get(project_id, &name, &id)?
+        .or_not_found(&format!("Document {id} not found"))?;
+
+    let max_size = env::var("TRIGGR_MAX_ATTACHMENT_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_ATTACHMENT_SIZE);
+
+    // Read the body chunk by chunk, bailing out as soon as the cap is exceeded
+    let mut stream = body.into_data_stream();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| AppError::BadRequest(format!("Invalid body: {e}")))?;
+        if bytes.len() + chunk.len() > max_size {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Attachment too large. Max size: {max_size} bytes"
+            )));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+
+    let info = AttachmentInfo {
+        key: key.into(),
+        content_type,
+        size: bytes.len(),
+        uploaded_at: Utc::now().timestamp_millis() as u64,
+    };
+
     triggr
         .store
-        .delete(&ref_project.project.id, &name, &id)
-        .await?;
+        .put_attachment(project_id, &name, &id, info.clone(), &bytes)?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "data": info }))))
+}
+
+/// Download a binary attachment of a document
+#[utoipa::path(
+    get,
+    path = "/api/db/collections/{name}/docs/{id}/attachments/{key}",
+    params(
+        ("name" = String, Path, description = "Collection name"),
+        ("id" = String, Path, description = "Document ID"),
+        ("key" = String, Path, description = "Attachment key")
+    ),
+    responses(
+        (status = 200, description = "Raw attachment bytes"),
+        (status = 404, description = "Attachment not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn get_attachment(
+    State(triggr): State<Triggr>,
+    Path((name, id, key)): Path<(String, String, String)>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+    let id = Name::document_id(&id)?;
+    let key = Name::parse("attachment key", &key)?;
+
+    let (info, data) = triggr
+        .store
+        .get_attachment(&ref_project.data_id(), &name, &id, &key)?
+        .or_not_found(&format!("Attachment {key} not found"))?;
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, info.content_type),
+            (header::CONTENT_LENGTH, data.len().to_string()),
+        ],
+        attachment_body(data),
+    ))
+}
+
+/// Stream an attachment in chunks, so the blob is never copied whole.
+fn attachment_body(data: IVec) -> Body {
+    let len = data.len();
+    let chunks = (0..len).step_by(ATTACHMENT_CHUNK).map(move |start| {
+        let end = (start + ATTACHMENT_CHUNK).min(len);
+        Ok::<_, StorageError>(data[start..end].to_vec())
+    });
+    Body::from_stream(futures::stream::iter(chunks))
+}
+
+/// List the attachments of a document
+#[utoipa::path(
+    get,
+    path = "/api/db/collections/{name}/docs/{id}/attachments",
+    params(
+        ("name" = String, Path, description = "Collection name"),
+        ("id" = String, Path, description = "Document ID")
+    ),
+    responses(
+        (status = 200, description = "Attachments of the document", body = [AttachmentInfo]),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn list_attachments(
+    State(triggr): State<Triggr>,
+    Path((name, id)): Path<(String, String)>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+    let id = Name::document_id(&id)?;
+
+    let infos = triggr
+        .store
+        .list_attachments(&ref_project.data_id(), &name, &id)?;
+
+    Ok((StatusCode::OK, Json(json!({ "data": infos }))))
+}
+
+/// Delete a binary attachment of a document
+#[utoipa::path(
+    delete,
+    path = "/api/db/collections/{name}/docs/{id}/attachments/{key}",
+    params(
+        ("name" = String, Path, description = "Collection name"),
+        ("id" = String, Path, description = "Document ID"),
+        ("key" = String, Path, description = "Attachment key")
+    ),
+    responses(
+        (status = 200, description = "Attachment deleted successfully"),
+        (status = 404, description = "Attachment not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn delete_attachment(
+    State(triggr): State<Triggr>,
+    Path((name, id, key)): Path<(String, String, String)>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+    let id = Name::document_id(&id)?;
+    let key = Name::parse("attachment key", &key)?;
+
+    if !triggr
+        .store
+        .delete_attachment(&ref_project.data_id(), &name, &id, &key)?
+    {
+        return Err(AppError::NotFound(format!("Attachment {key} not found")));
+    }
+
     Ok((StatusCode::OK, Json(json!({ "ok": true }))))
 }
+
+/// Get the state machine of a collection
+#[utoipa::path(
+    get,
+    path = "/api/db/collections/{name}/state-machine",
+    params(
+        ("name" = String, Path, description = "Collection name")
+    ),
+    responses(
+        (status = 200, description = "State machine of the collection", body = StateMachine),
+        (status = 404, description = "The collection has no state machine"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn get_state_machine(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+
+    let machine = triggr
+        .store
+        .get_state_machine(&ref_project.data_id(), &name)?
+        .or_not_found(&format!("Collection {name} has no state machine"))?;
+
+    Ok((StatusCode::OK, Json(json!({ "data": machine }))))
+}
+
+/// Set the state machine of a collection.
+/// Writes to the collection must then keep the state field valid and only make allowed transitions.
+#[utoipa::path(
+    put,
+    path = "/api/db/collections/{name}/state-machine",
+    request_body = StateMachine,
+    params(
+        ("name" = String, Path, description = "Collection name")
+    ),
+    responses(
+        (status = 200, description = "State machine set successfully"),
+        (status = 400, description = "Invalid state machine"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn put_state_machine(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    ref_project: RefProject,
+    Json(machine): Json<StateMachine>,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+    machine.validate().map_err(|message| AppError::Validation {
+        field: "state_machine".to_string(),
+        message,
+    })?;
+
+    triggr
+        .store
+        .put_state_machine(&ref_project.data_id(), &name, &machine)?;
+
+    Ok((StatusCode::OK, Json(json!({ "ok": true }))))
+}
+
+/// Remove the state machine of a collection
+#[utoipa::path(
+    delete,
+    path = "/api/db/collections/{name}/state-machine",
+    params(
+        ("name" = String, Path, description = "Collection name")
+    ),
+    responses(
+        (status = 200, description = "State machine removed successfully"),
+        (status = 404, description = "The collection has no state machine"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn delete_state_machine(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+
+    if !triggr
+        .store
+        .delete_state_machine(&ref_project.data_id(), &name)?
+    {
+        return Err(AppError::NotFound(format!(
+            "Collection {name} has no state machine"
+        )));
+    }
+
+    Ok((StatusCode::OK, Json(json!({ "ok": true }))))
+}
+
+/// Get the geo index of a collection
+#[utoipa::path(
+    get,
+    path = "/api/db/collections/{name}/geo-index",
+    params(
+        ("name" = String, Path, description = "Collection name")
+    ),
+    responses(
+        (status = 200, description = "Geo index of the collection", body = GeoIndex),
+        (status = 404, description = "The collection has no geo index"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn get_geo_index(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+
+    let index = triggr
+        .store
+        .get_geo_index(&ref_project.data_id(), &name)?
+        .or_not_found(&format!("Collection {name} has no geo index"))?;
+
+    Ok((StatusCode::OK, Json(json!({ "data": index }))))
+}
+
+/// Declare the geo point field of a collection and index its documents.
+/// Writes to the collection must then keep the field a point (`{ "lat": .., "lon": .. }`) or null.
+#[utoipa::path(
+    put,
+    path = "/api/db/collections/{name}/geo-index",
+    request_body = GeoIndex,
+    params(
+        ("name" = String, Path, description = "Collection name")
+    ),
+    responses(
+        (status = 200, description = "Geo index set, with the number of documents indexed"),
+        (status = 400, description = "Invalid geo index"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn put_geo_index(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    ref_project: RefProject,
+    Json(index): Json<GeoIndex>,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+    index.validate().map_err(|message| AppError::Validation {
+        field: "field".to_string(),
+        message,
+    })?;
+
+    let indexed = triggr
+        .store
+        .put_geo_index(&ref_project.data_id(), &name, &index)?;
+
+    Ok((StatusCode::OK, Json(json!({ "data": { "indexed": indexed } }))))
+}
+
+/// Remove the geo index of a collection
+#[utoipa::path(
+    delete,
+    path = "/api/db/collections/{name}/geo-index",
+    params(
+        ("name" = String, Path, description = "Collection name")
+    ),
+    responses(
+        (status = 200, description = "Geo index removed successfully"),
+        (status = 404, description = "The collection has no geo index"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn delete_geo_index(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::collection(&name)?;
+
+    if !triggr
+        .store
+        .delete_geo_index(&ref_project.data_id(), &name)?
+    {
+        return Err(AppError::NotFound(format!("Collection {name} has no geo index")));
+    }
+
+    Ok((StatusCode::OK, Json(json!({ "ok": true }))))
+}
+
+/// Get a value of the project's key-value namespace
+#[utoipa::path(
+    get,
+    path = "/api/db/kv/{key}",
+    params(
+        ("key" = String, Path, description = "Key")
+    ),
+    responses(
+        (status = 200, description = "Value of the key", body = inline(serde_json::Value)),
+        (status = 404, description = "Key not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn get_kv(
+    State(triggr): State<Triggr>,
+    Path(key): Path<String>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let key = Name::kv_key(&key)?;
+
+    let value = triggr
+        .store
+        .get_kv(&ref_project.data_id(), &key)?
+        .or_not_found(&format!("Key {key} not found"))?;
+
+    Ok((StatusCode::OK, Json(json!({ "data": value }))))
+}
+
+/// Set a value of the project's key-value namespace
+#[utoipa::path(
+    put,
+    path = "/api/db/kv/{key}",
+    request_body = inline(serde_json::Value),
+    params(
+        ("key" = String, Path, description = "Key")
+    ),
+    responses(
+        (status = 200, description = "Value set successfully"),
+        (status = 400, description = "Invalid key"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn put_kv(
+    State(triggr): State<Triggr>,
+    Path(key): Path<String>,
+    ref_project: RefProject,
+    Json(value): Json<Value>,
+) -> Result<impl IntoResponse, AppError> {
+    let key = Name::kv_key(&key)?;
+
+    triggr.store.put_kv(&ref_project.data_id(), &key, &value)?;
+
+    Ok((StatusCode::OK, Json(json!({ "ok": true }))))
+}
+
+/// Delete a value of the project's key-value namespace
+#[utoipa::path(
+    delete,
+    path = "/api/db/kv/{key}",
+    params(
+        ("key" = String, Path, description = "Key")
+    ),
+    responses(
+        (status = 200, description = "Key deleted successfully"),
+        (status = 404, description = "Key not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn delete_kv(
+    State(triggr): State<Triggr>,
+    Path(key): Path<String>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let key = Name::kv_key(&key)?;
+
+    if !triggr.store.delete_kv(&ref_project.data_id(), &key)? {
+        return Err(AppError::NotFound(format!("Key {key} not found")));
+    }
+
+    Ok((StatusCode::OK, Json(json!({ "ok": true }))))
+}
+
+/// Struct modelling the content of a watchlist.
+#[derive(Deserialize, ToSchema)]
+pub struct PutWatchlist {
+    /// What the addresses have in common
+    #[serde(default)]
+    pub description: String,
+    /// Addresses of the list (hex or SS58)
+    #[serde(default)]
+    pub addresses: Vec<String>,
+}
+
+/// List the project's watchlists
+#[utoipa::path(
+    get,
+    path = "/api/db/watchlists",
+    responses(
+        (status = 200, description = "Watchlists with their size", body = Vec<Watchlist>),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn list_watchlists(
+    State(triggr): State<Triggr>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let watchlists = triggr.store.list_watchlists(&ref_project.project.id)?;
+
+    Ok((StatusCode::OK, Json(json!({ "data": watchlists }))))
+}
+
+/// Get a watchlist and its addresses
+#[utoipa::path(
+    get,
+    path = "/api/db/watchlists/{name}",
+    params(
+        ("name" = String, Path, description = "Watchlist name")
+    ),
+    responses(
+        (status = 200, description = "Watchlist and its addresses"),
+        (status = 404, description = "Watchlist not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn get_watchlist(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::watchlist(&name)?;
+
+    let (watchlist, addresses) = triggr
+        .store
+        .get_watchlist(&ref_project.project.id, &name)?
+        .or_not_found(&format!("Watchlist {name} not found"))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "data": { "watchlist": watchlist, "addresses": addresses } })),
+    ))
+}
+
+/// Create or replace a watchlist
+#[utoipa::path(
+    put,
+    path = "/api/db/watchlists/{name}",
+    request_body = PutWatchlist,
+    params(
+        ("name" = String, Path, description = "Watchlist name")
+    ),
+    responses(
+        (status = 200, description = "Watchlist saved successfully"),
+        (status = 400, description = "Invalid name or address"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn put_watchlist(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    ref_project: RefProject,
+    Json(data): Json<PutWatchlist>,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::watchlist(&name)?;
+    let addresses = data
+        .addresses
+        .iter()
+        .map(|address| watchlist::normalize(address))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    triggr
+        .store
+        .put_watchlist(&ref_project.project.id, &name, &data.description, &addresses)?;
+
+    Ok((StatusCode::OK, Json(json!({ "ok": true }))))
+}
+
+/// Delete a watchlist
+#[utoipa::path(
+    delete,
+    path = "/api/db/watchlists/{name}",
+    params(
+        ("name" = String, Path, description = "Watchlist name")
+    ),
+    responses(
+        (status = 200, description = "Watchlist deleted successfully"),
+        (status = 404, description = "Watchlist not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn delete_watchlist(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::watchlist(&name)?;
+
+    if !triggr.store.delete_watchlist(&ref_project.project.id, &name)? {
+        return Err(AppError::NotFound(format!("Watchlist {name} not found")));
+    }
+
+    Ok((StatusCode::OK, Json(json!({ "ok": true }))))
+}
+
+/// Add addresses to a watchlist in bulk.
+/// The body lists addresses separated by new lines, commas or spaces (e.g. a single column CSV
+/// without header).
+/// Nothing is imported if any entry is not an address.
+#[utoipa::path(
+    post,
+    path = "/api/db/watchlists/{name}/import",
+    request_body(content = String, content_type = "text/plain", description = "Addresses to add"),
+    params(
+        ("name" = String, Path, description = "Watchlist name")
+    ),
+    responses(
+        (status = 200, description = "Number of addresses read and added"),
+        (status = 400, description = "Invalid addresses"),
+        (status = 404, description = "Watchlist not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn import_watchlist(
+    State(triggr): State<Triggr>,
+    Path(name): Path<String>,
+    ref_project: RefProject,
+    body: String,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::watchlist(&name)?;
+    let addresses = watchlist::parse_import(&body).map_err(|message| AppError::Validation {
+        field: "address".to_string(),
+        message,
+    })?;
+
+    let added = triggr
+        .store
+        .add_to_watchlist(&ref_project.project.id, &name, &addresses)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "data": { "read": addresses.len(), "added": added } })),
+    ))
+}
+
+/// Add an address to a watchlist
+#[utoipa::path(
+    put,
+    path = "/api/db/watchlists/{name}/addresses/{address}",
+    params(
+        ("name" = String, Path, description = "Watchlist name"),
+        ("address" = String, Path, description = "Address")
+    ),
+    responses(
+        (status = 200, description = "Address added successfully"),
+        (status = 400, description = "Invalid address"),
+        (status = 404, description = "Watchlist not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn add_watchlist_address(
+    State(triggr): State<Triggr>,
+    Path((name, address)): Path<(String, String)>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::watchlist(&name)?;
+    let address = watchlist::normalize(&address)?;
+
+    triggr
+        .store
+        .add_to_watchlist(&ref_project.project.id, &name, &[address])?;
+
+    Ok((StatusCode::OK, Json(json!({ "ok": true }))))
+}
+
+/// Remove an address from a watchlist
+#[utoipa::path(
+    delete,
+    path = "/api/db/watchlists/{name}/addresses/{address}",
+    params(
+        ("name" = String, Path, description = "Watchlist name"),
+        ("address" = String, Path, description = "Address")
+    ),
+    responses(
+        (status = 200, description = "Address removed successfully"),
+        (status = 404, description = "Address not on the watchlist"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn remove_watchlist_address(
+    State(triggr): State<Triggr>,
+    Path((name, address)): Path<(String, String)>,
+    ref_project: RefProject,
+) -> Result<impl IntoResponse, AppError> {
+    let name = Name::watchlist(&name)?;
+    let address = watchlist::normalize(&address)?;
+
+    if !triggr
+        .store
+        .remove_from_watchlist(&ref_project.project.id, &name, &address)?
+    {
+        return Err(AppError::NotFound(format!(
+            "Address {address} is not on watchlist {name}"
+        )));
+    }
+
+    Ok((StatusCode::OK, Json(json!({ "ok": true }))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn order() -> Document {
+        Document {
+            id: "order-1".to_string(),
+            data: json!({ "amount": 1500, "status": "paid" }),
+            ..Default::default()
+        }
+    }
+
+    fn if_none_match(etag: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(etag).unwrap());
+        headers
+    }
+
+    fn selection(fields: &str) -> FieldSelection {
+        FieldSelection::parse(Some(fields)).unwrap().unwrap()
+    }
+
+    #[test]
+    fn projected_read_has_its_own_etag() {
+        let doc = order();
+        let fields = selection("data.amount");
+        let etag = fields.etag(&doc.etag());
+
+        assert_ne!(etag, doc.etag());
+        assert_ne!(etag, selection("data.status").etag(&doc.etag()));
+        assert_eq!(etag, selection("data.amount").etag(&doc.etag()));
+        assert!(etag.starts_with("W/\"") && etag.ends_with('"'));
+        assert_eq!(
+            fields.project(&json!(doc)),
+            json!({ "id": "order-1", "data": { "amount": 1500 } })
+        );
+    }
+
+    #[test]
+    fn conditional_projected_read_revalidates_only_its_selection() {
+        let doc = order();
+        let amount = selection("data.amount").etag(&doc.etag());
+        let body = || json!({ "data": selection("data.amount").project(&json!(doc)) });
+
+        // The full document's tag, or another projection's, is not a match
+        for etag in [doc.etag(), selection("data.status").etag(&doc.etag())] {
+            let response = with_etag(&if_none_match(&etag), amount.clone(), body());
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = with_etag(&if_none_match(&amount), amount.clone(), body());
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], amount.as_str());
+    }
+
+    #[test]
+    fn projected_etag_follows_document_changes() {
+        let mut doc = order();
+        let before = selection("data.amount").etag(&doc.etag());
+        doc.data["amount"] = json!(2000);
+
+        assert_ne!(selection("data.amount").etag(&doc.etag()), before);
+    }
+}
@@ -0,0 +1,48 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// Module containing handlers for development requests, only served in dev mode.
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+
+use super::{db::AppError, *};
+use crate::{dev::EmitEvent, server::middleware::RefProject};
+
+/// Inject an event of the project's contract into the pipeline, as if the chain emitted it.
+/// The event is either crafted from a name and fields, or decoded from a raw payload.
+#[utoipa::path(
+    post,
+    path = "/api/dev/emit-event",
+    request_body = EmitEvent,
+    responses(
+        (status = 202, description = "Event queued for the trigger engine"),
+        (status = 400, description = "Invalid event"),
+        (status = 404, description = "Not in dev mode, or no metadata for the contract"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn emit_event(
+    ref_project: RefProject,
+    State(triggr): State<Triggr>,
+    Json(request): Json<EmitEvent>,
+) -> Result<impl IntoResponse, AppError> {
+    let dev = triggr
+        .dev
+        .clone()
+        .ok_or_else(|| AppError::NotFound("Dev mode is not enabled".to_string()))?;
+
+    let contract_addr = ref_project.project.contract_address;
+    let metadata = triggr.contract_metadata(&contract_addr).await.ok_or_else(|| {
+        AppError::NotFound(format!("No metadata for contract {contract_addr}"))
+    })?;
+    let mode = triggr.cache.decode_mode(&contract_addr);
+
+    // The event runs the triggers in the namespace of the request
+    let mut event = dev
+        .craft(request, &metadata, mode)
+        .map_err(AppError::BadRequest)?;
+    event.namespace = ref_project.namespace;
+    dev.emit(&triggr, &contract_addr, event.clone()).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(json!({ "data": event }))))
+}
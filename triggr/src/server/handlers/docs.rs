@@ -3,21 +3,69 @@
 // Swagger docs
 
 use super::*;
+use crate::aggregate::{Aggregation, Series, SeriesPoint};
+use crate::apply::{
+    ApplyPlan, Change, ChangeAction, CollectionSpec, Drift, ProjectSpec, TriggerSpec,
+};
+use crate::anomaly::FieldBaseline;
+use crate::bootstrap::Bootstrap;
+use crate::chain::polkadot::{
+    harness::{FuzzReport, ReplayDiff, ReplayReport},
+    prelude::DecodeMode,
+    util::DecodeReport,
+};
+use crate::dev::EmitEvent;
+use crate::dsl::{Advisory, ConditionStep, Diagnostic, Explanation, RuleTrace, Severity};
+use crate::enrich::{EnrichSource, Enricher};
+use crate::load::{LoadStatus, SyntheticLoad};
+use crate::shard::ShardStats;
+use crate::syntax::ParseError;
+use crate::gc::{GcReport, OrphanedMetadata, OrphanedTree, OrphanedTrigger};
+use crate::geo::GeoIndex;
+use crate::inbound::{Delivery, IngestReport, PushedEvent};
+use crate::integrity::{CorruptedRecord, IntegrityReport};
+use crate::lifecycle::{StateMachine, Transition};
+use crate::metering::UsageRecord;
+use crate::namespace::Namespace;
+use crate::quota::{QuotaStats, QuotaUsage};
+use crate::recompute::RecomputeReport;
+use crate::service::{Scope, ServiceAccount};
+use crate::watchlist::Watchlist;
 use crate::server::handlers::{
-    console::CreateProjectResponse,
-    trigger::StoreTrigger,
-    storage::CollectionSummary
+    admin::UpdateMaintenance,
+    auth::{WsToken, WsTokenRequest},
+    console::{
+        CreateProjectResponse, CreateServiceAccount, DecodeRequest, UpdateDecodeMode,
+        UpdateOrdering, UpdateSandbox,
+    },
+    db::PutWatchlist,
+    trigger::{ExplainEvent, PreviewTemplate, StoreTrigger, TriggerSearchResult, ValidateTrigger},
+    storage::{AttachmentInfo, CollectionSummary, SubscriptionStats, TopicStats}
 };
 
 use utoipa::OpenApi;
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(db::insert_document, db::get_document, db::update_document, db::delete_document, db::list_documents, db::list_collections,
-        console::login, console::create_project, console::delete_project, console::list_projects,
-        trigger::save_trigger, trigger::list_triggers, trigger::get_trigger, trigger::delete_trigger, trigger::update_trigger_state
+    paths(db::insert_document, db::get_document, db::update_document, db::delete_document, db::list_documents, db::list_collections, db::collection_subscribers, db::collection_chart,
+        db::put_attachment, db::get_attachment, db::list_attachments, db::delete_attachment,
+        db::get_geo_index, db::put_geo_index, db::delete_geo_index,
+        db::get_state_machine, db::put_state_machine, db::delete_state_machine,
+        db::get_kv, db::put_kv, db::delete_kv,
+        db::list_watchlists, db::get_watchlist, db::put_watchlist, db::delete_watchlist, db::import_watchlist, db::add_watchlist_address, db::remove_watchlist_address,
+        console::login, console::create_project, console::delete_project, console::list_projects, console::bootstrap,
+        auth::issue_ws_token,
+        console::get_decoding_errors, console::update_decode_mode, console::update_ordering, console::update_event_routes, console::update_enrichers, console::get_sandbox, console::put_sandbox, console::get_usage, console::list_service_accounts, console::create_service_account, console::delete_service_account, console::apply_spec, console::plan_spec, console::get_drift, console::decode_event,
+        admin::replay_decoder_corpus, admin::fuzz_decoder, admin::cache_stats, admin::auth_stats, admin::chain_health, admin::pipeline_stats, admin::subscription_stats, admin::quota_stats, admin::metering_usage,
+        admin::list_ws_connections, admin::close_ws_connection, admin::maintenance_status, admin::update_maintenance, admin::shard_stats, admin::integrity_report, admin::quarantine_corrupted, admin::orphan_report, admin::collect_garbage, admin::start_load, admin::load_status, admin::stop_load,
+        trigger::save_trigger, trigger::list_project_triggers, trigger::search_triggers, trigger::list_triggers, trigger::get_trigger, trigger::delete_trigger, trigger::update_trigger_state, trigger::update_triggers_state, trigger::explain_trigger, trigger::recompute_trigger, trigger::anomaly_baselines, events::list_events, inbound::receive,
+        trigger::validate_trigger, trigger::preview_template, trigger::list_runs, trigger::redecode_run,
+        dev::emit_event
     ),
-    components(schemas(Document, DocMetadata, Project, CreateProjectResponse, StoreTrigger, SlimTrigger, CollectionSummary)),
+    components(schemas(Document, DocMetadata, Project, CreateProjectResponse, Bootstrap, StoreTrigger, SlimTrigger, TriggerSearchResult, Advisory, Diagnostic, Severity, ExplainEvent, Explanation, RuleTrace, ConditionStep, RecomputeReport, Delivery, PushedEvent, IngestReport, FieldBaseline, CollectionSummary, TopicStats, PreviewTemplate, AttachmentInfo, StateMachine, Transition, GeoIndex, Watchlist, PutWatchlist, Series, SeriesPoint, Aggregation,
+        ContractDecodeStats, DecodeFailureSample, ReplayReport, ReplayDiff, FuzzReport,
+        DecodeMode, UpdateDecodeMode, UpdateOrdering, EventRoutes, Enricher, EnrichSource, CacheStats, AuthStats, ChainHealthStats, PipelineStats, SubscriptionStats, WsConnectionInfo, MaintenanceStatus, UpdateMaintenance, ShardStats, IntegrityReport, CorruptedRecord, GcReport, OrphanedTree, OrphanedTrigger, OrphanedMetadata, SyntheticLoad, LoadStatus, EmitEvent, UpdateSandbox, Namespace, QuotaUsage, QuotaStats, UsageRecord, ServiceAccount, Scope, CreateServiceAccount, ProjectSpec, CollectionSpec, TriggerSpec, ApplyPlan, Change, ChangeAction, Drift, DecodeRequest, DecodeReport, ParseError, ValidateTrigger,
+        WsTokenRequest, WsToken)),
     tags(
         (name = "Docs", description = "Document REST endpoints")
     )
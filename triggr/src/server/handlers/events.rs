@@ -0,0 +1,71 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// Module containing handlers to read the decoded events of contracts.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{db::AppError, *};
+use crate::server::middleware::RefProject;
+
+/// Default number of events returned per page.
+const DEFAULT_EVENTS_LIMIT: usize = 100;
+
+/// Max number of events returned per page.
+const MAX_EVENTS_LIMIT: usize = 1000;
+
+/// Query parameters for polling events.
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// Only return events of this block or later ones
+    pub since_block: Option<u64>,
+    /// Cursor of the page to return, as returned with the previous one
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// List the decoded events of the project's contract in chain order, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/api/events/{contract_addr}",
+    params(
+        ("contract_addr" = String, Path, description = "Address of the project's contract"),
+        ("since_block" = Option<u64>, Query, description = "First block to return events of"),
+        ("cursor" = Option<String>, Query, description = "Cursor of the next page (`next`)"),
+        ("limit" = Option<usize>, Query, description = "Max number of events to return")
+    ),
+    responses(
+        (status = 200, description = "Decoded events, with the cursor of the next page if any"),
+        (status = 404, description = "Contract not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn list_events(
+    ref_project: RefProject,
+    State(triggr): State<Triggr>,
+    Path(contract_addr): Path<String>,
+    Query(query): Query<EventsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let contract = &ref_project.project.contract_address;
+    if !contract.eq_ignore_ascii_case(&contract_addr) {
+        return Err(AppError::NotFound(format!("Contract {contract_addr} not found")));
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_EVENTS_LIMIT)
+        .clamp(1, MAX_EVENTS_LIMIT);
+    let (events, next) = triggr.store.logged_events(
+        contract,
+        query.since_block.unwrap_or_default(),
+        query.cursor.as_deref(),
+        limit,
+    )?;
+
+    Ok(Json(json!({ "data": events, "next": next })))
+}
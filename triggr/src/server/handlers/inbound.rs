@@ -0,0 +1,65 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// Module containing handlers for the webhooks of external indexers pushing contract events.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+
+use super::{db::AppError, *};
+use crate::inbound::{self, Provider};
+
+/// Receive the events pushed by the webhook of a provider (`alchemy` or `generic`) and queue
+/// them for the trigger engine. The body must be signed with the secret of the provider,
+/// within `TRIGGR_INBOUND_MAX_AGE_SECS` of the request.
+#[utoipa::path(
+    post,
+    path = "/api/inbound/{provider}",
+    params(
+        ("provider" = String, Path, description = "Provider of the webhook (alchemy or generic)")
+    ),
+    request_body(content = inline(inbound::Delivery), description = "Payload (generic format)"),
+    responses(
+        (status = 202, description = "Events queued", body = inbound::IngestReport),
+        (status = 400, description = "Malformed payload"),
+        (status = 401, description = "Missing, invalid or stale signature"),
+        (status = 404, description = "Unknown or disabled provider"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn receive(
+    State(triggr): State<Triggr>,
+    Path(provider): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    let provider: Provider = provider.parse().map_err(AppError::NotFound)?;
+    if triggr.inbound.secret(provider).is_none() {
+        return Err(AppError::NotFound(format!(
+            "Webhooks of {provider} are not enabled"
+        )));
+    }
+
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let signature = header(provider.signature_header());
+    let timestamp = provider.timestamp_header().and_then(header);
+    triggr
+        .inbound
+        .verify(provider, &body, signature, timestamp, Utc::now().timestamp())
+        .map_err(AppError::Unauthorized)?;
+
+    let delivery = provider.normalize(&body).map_err(AppError::BadRequest)?;
+    let report = triggr.inbound.ingest(&triggr, delivery).await?;
+    tracing::info!(
+        "Webhook of {provider}: {} event(s) queued, {} skipped",
+        report.accepted,
+        report.skipped.len()
+    );
+
+    Ok((StatusCode::ACCEPTED, Json(json!({ "data": report }))))
+}
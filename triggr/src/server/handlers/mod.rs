@@ -2,9 +2,14 @@
 
 // Module containing various handlers for module operations.
 
+pub mod admin;
+pub mod auth;
 pub mod console;
 pub mod db;
+pub mod dev;
 pub mod docs;
+pub mod events;
+pub mod inbound;
 pub mod ws;
 pub mod trigger;
 
@@ -3,18 +3,36 @@
 // Module containing handlers for trigger requests.
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use utoipa::ToSchema;
 
 use super::{db::AppError, *};
-use crate::{dsl::DslParser, server::middleware::RefProject};
+use crate::{
+    anomaly::FieldBaseline,
+    chain::polkadot::{prelude::EventData, util::decode_contract_event},
+    dsl::{DslAnalyzer, DslExecutor, DslParser, Explanation, Severity},
+    execute_trigger, fired_actions,
+    namespace::Namespace,
+    recompute::{self, RecomputeReport},
+    server::middleware::RefProject,
+    syntax::ParseError,
+    template,
+    webhook,
+};
+
+/// Default number of runs returned when listing.
+const DEFAULT_RUNS_LIMIT: usize = 50;
+
+/// Max number of runs returned when listing.
+const MAX_RUNS_LIMIT: usize = 1_000;
 
 /// Struct modelling trigger creation
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -23,6 +41,19 @@ pub struct StoreTrigger {
     pub contract_addr: String,
     pub description: String,
     pub trigger: String,
+    /// Free-form labels to organize triggers (e.g. "alerts")
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// URL the `notify` actions of the trigger are POSTed to
+    #[serde(default)]
+    pub webhook: Option<String>,
+}
+
+/// Filter of trigger lists.
+#[derive(Deserialize)]
+pub struct TriggerFilter {
+    /// Only return triggers carrying this tag
+    tag: Option<String>,
 }
 
 /// Create and store a new trigger under a contract.
@@ -31,8 +62,8 @@ pub struct StoreTrigger {
     path = "/api/trigger",
     request_body(content = inline(StoreTrigger), description = "Trigger creation payload"),
     responses(
-        (status = 201, description = "Trigger saved successfully", body = inline(SlimTrigger)),
-        (status = 400, description = "Invalid DSL or malformed request"),
+        (status = 201, description = "Trigger saved successfully, with advisories about conflicting triggers", body = inline(SlimTrigger)),
+        (status = 400, description = "Invalid DSL, unknown events or fields, or malformed request"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -41,10 +72,47 @@ pub async fn save_trigger(
     State(triggr): State<Triggr>,
     Json(data): Json<StoreTrigger>,
 ) -> Result<impl IntoResponse, AppError> {
+    if let Some(url) = &data.webhook {
+        webhook::validate_url(url).map_err(|message| AppError::Validation {
+            field: "webhook".to_string(),
+            message,
+        })?;
+    }
+
     // Parse DSL into internal structure
     match DslParser::parse_script(&data.trigger) {
         Ok(script) => {
+            let contract_addr = data.contract_addr.to_lowercase();
+
+            // Warn about triggers of the project writing to the same documents on the same events
+            let existing: Vec<Trigger> = triggr
+                .store
+                .list_triggers(&contract_addr)?
+                .into_iter()
+                .filter(|t| t.project_id == ref_project.project.id)
+                .collect();
+            let advisories = DslAnalyzer::detect_conflicts(&data.id, &script.rules, &existing);
+
+            // Reject triggers that refer to events or fields the contract doesn't have
+            let diagnostics = triggr
+                .contract_metadata(&contract_addr)
+                .await
+                .map(|metadata| DslAnalyzer::check_against_metadata(&script, &metadata))
+                .unwrap_or_default();
+            let errors: Vec<&str> = diagnostics
+                .iter()
+                .filter(|d| d.severity == Severity::Error)
+                .map(|d| d.message.as_str())
+                .collect();
+            if !errors.is_empty() {
+                return Err(AppError::Validation {
+                    field: "trigger".to_string(),
+                    message: errors.join("; "),
+                });
+            }
+
             // Construct trigger
+            let actor = ref_project.actor();
             let trigger = Trigger {
                 id: data.id.clone(),
                 dsl: data.trigger.clone(),
@@ -54,65 +122,198 @@ pub async fn save_trigger(
                 active: true,
                 created: Utc::now().timestamp_millis() as u64,
                 last_run: 0,
+                tags: Trigger::normalize_tags(data.tags),
+                webhook: data.webhook,
+                owner: ref_project.service.map(|account| account.id),
             };
 
             triggr
                 .store
-                .store_trigger(&data.contract_addr.to_lowercase(), trigger.clone())
+                .store_trigger(&contract_addr, trigger.clone())
                 .map_err(AppError::from)?;
+            tracing::info!(
+                "Trigger {} of project {} saved by {actor}",
+                trigger.id,
+                trigger.project_id
+            );
 
             // Prepare SlimTrigger for response
-            let slim = SlimTrigger {
-                id: trigger.id,
-                dsl: trigger.dsl,
-                description: trigger.description,
-                active: trigger.active,
-                created: trigger.created,
-                last_run: trigger.last_run,
-            };
+            let slim = SlimTrigger::from(trigger);
 
-            Ok((StatusCode::CREATED, Json(json!({ "data": slim }))))
+            Ok((
+                StatusCode::CREATED,
+                Json(json!({
+                    "data": slim,
+                    "advisories": advisories,
+                    "diagnostics": diagnostics,
+                })),
+            ))
         }
-        Err(err) => Err(AppError::Internal(err)),
+        Err(err) => Err(AppError::InvalidScript(err)),
     }
 }
 
+/// Struct modelling a trigger to validate.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ValidateTrigger {
+    /// DSL of the trigger
+    pub trigger: String,
+    /// Contract the trigger is meant for, defaulting to the project's
+    #[serde(default)]
+    pub contract_addr: Option<String>,
+    /// Id the trigger is saved under, so that it isn't reported as conflicting with itself
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+/// Parse a trigger and check it against the contract without saving anything.
+#[utoipa::path(
+    post,
+    path = "/api/trigger/validate",
+    request_body(content = inline(ValidateTrigger), description = "Trigger to validate"),
+    responses(
+        (status = 200, description = "Parsed events and rules, with advisories about conflicting triggers and diagnostics"),
+        (status = 400, description = "Invalid DSL, with the position of the error", body = ParseError)
+    )
+)]
+pub async fn validate_trigger(
+    ref_project: RefProject,
+    State(triggr): State<Triggr>,
+    Json(data): Json<ValidateTrigger>,
+) -> Result<impl IntoResponse, AppError> {
+    let script = DslParser::parse_script(&data.trigger).map_err(AppError::InvalidScript)?;
+    let contract_addr = data
+        .contract_addr
+        .unwrap_or(ref_project.project.contract_address)
+        .to_lowercase();
+
+    // Same checks as when saving, reported rather than enforced
+    let existing: Vec<Trigger> = triggr
+        .store
+        .list_triggers(&contract_addr)?
+        .into_iter()
+        .filter(|t| t.project_id == ref_project.project.id)
+        .collect();
+    let id = data.id.unwrap_or_default();
+    let advisories = DslAnalyzer::detect_conflicts(&id, &script.rules, &existing);
+    let diagnostics = triggr
+        .contract_metadata(&contract_addr)
+        .await
+        .map(|metadata| DslAnalyzer::check_against_metadata(&script, &metadata))
+        .unwrap_or_default();
+    let valid = diagnostics.iter().all(|d| d.severity != Severity::Error);
+
+    Ok(Json(json!({
+        "data": {
+            "valid": valid,
+            "events": script.events,
+            "rules": script.rules,
+            "advisories": advisories,
+            "diagnostics": diagnostics,
+        }
+    })))
+}
+
+/// List all triggers of the project, optionally filtered by tag.
+#[utoipa::path(
+    get,
+    path = "/api/trigger",
+    params(
+        ("tag" = Option<String>, Query, description = "Only return triggers carrying this tag")
+    ),
+    responses(
+        (status = 200, description = "List of triggers", body = Vec<SlimTrigger>),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn list_project_triggers(
+    ref_project: RefProject,
+    State(triggr): State<Triggr>,
+    Query(filter): Query<TriggerFilter>,
+) -> Result<impl IntoResponse, AppError> {
+    let project = ref_project.project;
+    let triggers = triggr.store.list_triggers(&project.contract_address)?;
+
+    let slim: Vec<SlimTrigger> = triggers
+        .into_iter()
+        .filter(|t| t.project_id == project.id)
+        .filter(|t| filter.tag.as_deref().is_none_or(|tag| t.has_tag(tag)))
+        .map(SlimTrigger::from)
+        .collect();
+
+    Ok(Json(json!({ "data": slim })))
+}
+
+/// Query of a trigger search.
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    /// Words to look for
+    q: String,
+}
+
+/// A trigger found by a search.
+#[derive(Serialize, ToSchema)]
+pub struct TriggerSearchResult {
+    /// Contract the trigger is attached to
+    pub contract_addr: String,
+    #[serde(flatten)]
+    pub trigger: SlimTrigger,
+}
+
+/// Search the triggers of the project by DSL content, description and tags.
+#[utoipa::path(
+    get,
+    path = "/api/trigger/search",
+    params(
+        ("q" = String, Query, description = "Words that must all appear (as word prefixes), e.g. `accounts`")
+    ),
+    responses(
+        (status = 200, description = "Matching triggers", body = Vec<TriggerSearchResult>),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn search_triggers(
+    ref_project: RefProject,
+    State(triggr): State<Triggr>,
+    Query(query): Query<SearchQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let results: Vec<TriggerSearchResult> = triggr
+        .store
+        .search_triggers(&ref_project.project.id, &query.q)?
+        .into_iter()
+        .map(|(contract_addr, trigger)| TriggerSearchResult {
+            contract_addr,
+            trigger: SlimTrigger::from(trigger),
+        })
+        .collect();
+
+    Ok(Json(json!({ "data": results })))
+}
+
 /// List all triggers for a contract.
 #[utoipa::path(
     get,
     path = "/api/trigger/{contract_addr}",
     params(
-        ("contract_addr" = String, Path, description = "Address of the contract")
+        ("contract_addr" = String, Path, description = "Address of the contract"),
+        ("tag" = Option<String>, Query, description = "Only return triggers carrying this tag")
     ),
     responses(
-        (status = 200, description = "List of triggers", body = Vec<SlimTrigger>),
-        (status = 404, description = "Contract not found"),
+        (status = 200, description = "List of triggers, empty when the contract has none", body = Vec<SlimTrigger>),
         (status = 500, description = "Internal server error")
     )
 )]
 pub async fn list_triggers(
     State(triggr): State<Triggr>,
     Path(contract_addr): Path<String>,
+    Query(filter): Query<TriggerFilter>,
 ) -> Result<impl IntoResponse, AppError> {
-    let triggers = match triggr.store.list_triggers(&contract_addr) {
-        Ok(triggers) => triggers,
-        Err(StorageError::NotFound(_)) => {
-            // Return empty vec
-            vec![]
-        }
-        Err(e) => return Err(AppError::from(e)),
-    };
+    let triggers = triggr.store.list_triggers(&contract_addr)?;
 
     let slim: Vec<SlimTrigger> = triggers
         .into_iter()
-        .map(|t| SlimTrigger {
-            id: t.id,
-            description: t.description,
-            dsl: t.dsl,
-            active: t.active,
-            created: t.created,
-            last_run: t.last_run,
-        })
+        .filter(|t| filter.tag.as_deref().is_none_or(|tag| t.has_tag(tag)))
+        .map(SlimTrigger::from)
         .collect();
 
     Ok(Json(json!({ "data": slim })))
@@ -141,14 +342,7 @@ pub async fn get_trigger(
         .get_trigger(&contract_addr, &id)
         .map_err(AppError::from)?;
 
-    let slim = SlimTrigger {
-        id: trigger.id,
-        description: trigger.description,
-        dsl: trigger.dsl,
-        active: trigger.active,
-        created: trigger.created,
-        last_run: trigger.last_run,
-    };
+    let slim = SlimTrigger::from(trigger);
 
     Ok(Json(json!({ "data": slim })))
 }
@@ -174,6 +368,7 @@ pub struct UpdateState {
     )
 )]
 pub async fn update_trigger_state(
+    ref_project: RefProject,
     State(triggr): State<Triggr>,
     Path((contract_addr, id)): Path<(String, String)>,
     Json(payload): Json<UpdateState>,
@@ -182,10 +377,73 @@ pub async fn update_trigger_state(
         .store
         .set_trigger_state(&contract_addr, &id, payload.active)
         .map_err(AppError::from)?;
+    tracing::info!(
+        "Trigger {id} of project {} set {} by {}",
+        ref_project.project.id,
+        if payload.active { "active" } else { "inactive" },
+        ref_project.actor()
+    );
 
     Ok(Json(json!({ "data": { "updated": true } })))
 }
 
+/// Update (activate/deactivate) several triggers of a contract at once.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BulkUpdateState {
+    /// Ids of the triggers to update
+    #[serde(default)]
+    pub ids: Vec<String>,
+    /// Also update every trigger carrying this tag
+    pub tag: Option<String>,
+    pub active: bool,
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/trigger/{contract_addr}/state",
+    request_body(content = inline(BulkUpdateState)),
+    params(
+        ("contract_addr" = String, Path)
+    ),
+    responses(
+        (status = 200, description = "Trigger states updated, all or none"),
+        (status = 404, description = "One of the triggers was not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn update_triggers_state(
+    ref_project: RefProject,
+    State(triggr): State<Triggr>,
+    Path(contract_addr): Path<String>,
+    Json(payload): Json<BulkUpdateState>,
+) -> Result<impl IntoResponse, AppError> {
+    let contract_addr = contract_addr.to_lowercase();
+    let project_id = &ref_project.project.id;
+
+    // The tag is resolved against the triggers being written, so a retagged trigger isn't missed
+    // Triggers are read from storage for every event, so the executor sees the change right away
+    let ids = triggr.store.set_triggers_state(
+        &contract_addr,
+        project_id,
+        &payload.ids,
+        payload.tag.as_deref(),
+        payload.active,
+    )?;
+    tracing::info!(
+        "Triggers {} of project {project_id} set {} by {}",
+        ids.join(", "),
+        if payload.active { "active" } else { "inactive" },
+        ref_project.actor()
+    );
+
+    Ok(Json(json!({
+        "data": {
+            "updated": ids,
+            "active": payload.active,
+        }
+    })))
+}
+
 /// Delete a trigger by ID.
 #[utoipa::path(
     delete,
@@ -201,6 +459,7 @@ pub async fn update_trigger_state(
     )
 )]
 pub async fn delete_trigger(
+    ref_project: RefProject,
     State(triggr): State<Triggr>,
     Path((contract_addr, id)): Path<(String, String)>,
 ) -> Result<impl IntoResponse, AppError> {
@@ -208,6 +467,282 @@ pub async fn delete_trigger(
         .store
         .delete_trigger(&contract_addr, &id)
         .map_err(AppError::from)?;
+    tracing::info!(
+        "Trigger {id} of project {} deleted by {}",
+        ref_project.project.id,
+        ref_project.actor()
+    );
 
     Ok(Json(json!({ "data": { "deleted": true } })))
 }
+
+/// Struct modelling a template preview request.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PreviewTemplate {
+    /// Template to render (a message string or an object of action fields)
+    pub template: Value,
+    /// Name of the sample event
+    pub event_name: String,
+    /// Fields of the sample event
+    pub fields: HashMap<String, Value>,
+}
+
+/// Render a template against a sample event without executing anything, exactly as the engine
+/// would: a message is sent with its unresolved placeholders, while action fields with any are
+/// not written (`skipped`).
+#[utoipa::path(
+    post,
+    path = "/api/trigger/preview-template",
+    request_body(content = inline(PreviewTemplate), description = "Template and sample event"),
+    responses(
+        (status = 200, description = "Rendered output, resolution errors and whether the write would be skipped"),
+        (status = 400, description = "Malformed request")
+    )
+)]
+pub async fn preview_template(
+    Json(data): Json<PreviewTemplate>,
+) -> Result<impl IntoResponse, AppError> {
+    let event = EventData {
+        event_name: data.event_name,
+        fields: data.fields,
+        raw: None,
+        scores: HashMap::new(),
+        trace: None,
+        namespace: Namespace::Live,
+        block: None,
+        block_number: None,
+    };
+
+    let (output, errors, skipped) = match data.template {
+        Value::String(message) => {
+            let (message, errors) = template::render_message(&message, &event);
+            (json!(message), errors, false)
+        }
+        Value::Object(fields) => match template::render_fields(fields.into_iter().collect(), &event)
+        {
+            Ok(fields) => (json!(fields), Vec::new(), false),
+            Err(errors) => (Value::Null, errors, true),
+        },
+        _ => {
+            return Err(AppError::BadRequest(
+                "A template is a message or an object of action fields".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(json!({ "data": { "output": output, "errors": errors, "skipped": skipped } })))
+}
+
+/// Struct modelling an event to explain a trigger against.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ExplainEvent {
+    /// Name of the event
+    pub event_name: String,
+    /// Fields of the event
+    pub fields: HashMap<String, Value>,
+}
+
+/// Evaluate a trigger against an event and return a step-by-step trace of why it did or didn't fire.
+#[utoipa::path(
+    post,
+    path = "/api/trigger/{contract_addr}/{id}/explain",
+    params(
+        ("contract_addr" = String, Path, description = "Contract address"),
+        ("id" = String, Path, description = "Trigger ID")
+    ),
+    request_body(content = inline(ExplainEvent), description = "Event to evaluate the trigger against"),
+    responses(
+        (status = 200, description = "Evaluation trace", body = Explanation),
+        (status = 404, description = "Trigger not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn explain_trigger(
+    State(triggr): State<Triggr>,
+    Path((contract_addr, id)): Path<(String, String)>,
+    Json(data): Json<ExplainEvent>,
+) -> Result<impl IntoResponse, AppError> {
+    let trigger = triggr.store.get_trigger(&contract_addr, &id)?;
+
+    let mut event = EventData {
+        event_name: data.event_name,
+        fields: data.fields,
+        raw: None,
+        scores: HashMap::new(),
+        trace: None,
+        namespace: Namespace::Live,
+        block: None,
+        block_number: None,
+    };
+    // Score the event like a live one, without learning from it
+    triggr.anomalies.score(&contract_addr, &mut event);
+
+    let watchlists = |name: &str, address: &str| {
+        triggr
+            .store
+            .in_watchlist(&trigger.project_id, name, address)
+            .unwrap_or(false)
+    };
+
+    Ok(Json(json!({ "data": DslExecutor::explain(&trigger, &event, &watchlists) })))
+}
+
+/// Rebuild the documents a trigger derives from events: clear the collections it writes to, then
+/// replay the events of its recorded runs through its current rules, oldest first.
+#[utoipa::path(
+    post,
+    path = "/api/trigger/{contract_addr}/{id}/recompute",
+    params(
+        ("contract_addr" = String, Path, description = "Contract address"),
+        ("id" = String, Path, description = "Trigger ID")
+    ),
+    responses(
+        (status = 200, description = "Documents rebuilt", body = RecomputeReport),
+        (status = 404, description = "Trigger not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn recompute_trigger(
+    ref_project: RefProject,
+    State(triggr): State<Triggr>,
+    Path((contract_addr, id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let trigger = triggr.store.get_trigger(&contract_addr, &id)?;
+    if trigger.project_id != ref_project.project.id {
+        return Err(AppError::NotFound(format!("Trigger {id} not found")));
+    }
+
+    let report = recompute::recompute(&triggr, &contract_addr, &trigger, ref_project.namespace)
+        .await?;
+    tracing::info!(
+        "Trigger {id} of project {} recomputed by {}",
+        ref_project.project.id,
+        ref_project.actor()
+    );
+
+    Ok(Json(json!({ "data": report })))
+}
+
+/// Return the usual values of the numeric event fields of a contract, which `anomaly(...)`
+/// conditions score events against.
+#[utoipa::path(
+    get,
+    path = "/api/trigger/{contract_addr}/anomalies",
+    params(
+        ("contract_addr" = String, Path, description = "Address of the contract")
+    ),
+    responses(
+        (status = 200, description = "Baselines of the event fields, empty until events are seen", body = Vec<FieldBaseline>)
+    )
+)]
+pub async fn anomaly_baselines(
+    State(triggr): State<Triggr>,
+    Path(contract_addr): Path<String>,
+) -> impl IntoResponse {
+    Json(json!({ "data": triggr.anomalies.baselines(&contract_addr) }))
+}
+
+/// Query parameters for listing runs.
+#[derive(Deserialize)]
+pub struct RunsQuery {
+    pub limit: Option<usize>,
+}
+
+/// List the most recent trigger runs of a project.
+#[utoipa::path(
+    get,
+    path = "/api/trigger/runs",
+    params(
+        ("limit" = Option<usize>, Query, description = "Max number of runs to return (at most 1000)")
+    ),
+    responses(
+        (status = 200, description = "Recent trigger runs"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn list_runs(
+    ref_project: RefProject,
+    State(triggr): State<Triggr>,
+    Query(query): Query<RunsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let runs = triggr.store.list_runs(
+        &ref_project.project.id,
+        query.limit.unwrap_or(DEFAULT_RUNS_LIMIT).min(MAX_RUNS_LIMIT),
+    )?;
+
+    Ok(Json(json!({ "data": runs })))
+}
+
+/// Query parameters for re-decoding a run.
+#[derive(Deserialize)]
+pub struct RedecodeQuery {
+    /// Execute the actions firing on the re-decoded event
+    #[serde(default)]
+    pub execute: bool,
+}
+
+/// Re-decode the raw payload of a run with the current metadata and re-evaluate its trigger.
+/// The actions that fire are only returned, unless `execute=true` is passed to run them.
+#[utoipa::path(
+    post,
+    path = "/api/trigger/runs/{id}/redecode",
+    params(
+        ("id" = String, Path, description = "Run ID"),
+        ("execute" = Option<bool>, Query, description = "Execute the actions that fire (false by default)")
+    ),
+    responses(
+        (status = 200, description = "Event re-decoded and trigger re-evaluated"),
+        (status = 400, description = "Run has no raw payload or it could not be decoded"),
+        (status = 404, description = "Run or contract metadata not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn redecode_run(
+    ref_project: RefProject,
+    State(triggr): State<Triggr>,
+    Path(id): Path<String>,
+    Query(query): Query<RedecodeQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let run = triggr
+        .store
+        .get_run(&ref_project.project.id, &id)?
+        .ok_or_else(|| AppError::NotFound(format!("Run {id} not found")))?;
+
+    let raw = run.event.raw.clone().ok_or_else(|| {
+        AppError::BadRequest(
+            "Run has no raw payload. Enable TRIGGR_STORE_RAW_EVENTS to record it".to_string(),
+        )
+    })?;
+
+    let bytes = hex::decode(raw.data.trim_start_matches("0x"))
+        .map_err(|e| AppError::BadRequest(format!("Invalid raw payload: {e}")))?;
+
+    // Decode with the current metadata
+    let metadata = triggr.contract_metadata(&run.contract_addr).await.ok_or_else(|| {
+        AppError::NotFound(format!("No metadata for contract {}", run.contract_addr))
+    })?;
+    let mode = triggr.cache.decode_mode(&run.contract_addr);
+
+    let decoded = decode_contract_event(&bytes, &raw.topics, &metadata, mode);
+
+    let mut event = decoded.map_err(|failure| {
+        AppError::BadRequest(format!(
+            "Raw payload could not be decoded with current metadata: {}",
+            failure.error
+        ))
+    })?;
+    event.raw = Some(raw);
+
+    // Re-evaluate the trigger against the corrected event
+    let trigger = triggr
+        .store
+        .get_trigger(&run.contract_addr, &run.trigger_id)?;
+    if !query.execute {
+        let actions = fired_actions(&triggr, &trigger, &event);
+        let preview = json!({ "previous": run, "event": event, "actions": actions });
+        return Ok(Json(json!({ "data": preview })));
+    }
+    let new_run = execute_trigger(triggr.clone(), run.contract_addr.clone(), trigger, event).await;
+
+    Ok(Json(json!({ "data": { "previous": run, "run": new_run } })))
+}
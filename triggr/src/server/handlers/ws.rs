@@ -2,7 +2,9 @@
 
 // This module handles websockets request and responses.
 
-use super::*;
+use super::{auth::verify_ws_token, db::AppError, *};
+use crate::namespace::{Namespace, NAMESPACE_HEADER};
+use crate::quota;
 use axum::extract::ws::Message;
 use axum::extract::Query;
 use axum::http::{HeaderMap, StatusCode};
@@ -12,8 +14,11 @@ use axum::{
 };
 use futures::stream::StreamExt;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{atomic::Ordering, Arc},
+};
 use tokio::sync::broadcast::Receiver;
 use tokio::sync::mpsc;
 
@@ -21,11 +26,34 @@ use tokio::sync::mpsc;
 #[derive(Serialize, Deserialize)]
 struct WsJson {
     data: String,
+    /// Member metadata of presence commands
+    #[serde(default)]
+    meta: Option<Value>,
+    /// Id of a query, echoed in its result so clients can match them
+    #[serde(default)]
+    id: Option<Value>,
 }
 
 #[derive(Deserialize)]
 pub struct WsParams {
     api_key: Option<String>,
+    /// Short-lived token issued by `/api/auth/ws-token`
+    token: Option<String>,
+    /// Namespace of API key connections (`live` or `sandbox`)
+    namespace: Option<String>,
+}
+
+/// A registered connection, unregistered when dropped (also when the upgrade never completes).
+struct WsSlot {
+    registry: Arc<WsRegistry>,
+    id: String,
+    conn: Arc<WsConnection>,
+}
+
+impl Drop for WsSlot {
+    fn drop(&mut self) {
+        self.registry.unregister(&self.id);
+    }
 }
 
 // Handle websocket requests.
@@ -35,70 +63,268 @@ pub async fn ws_handler(
     Query(params): Query<WsParams>,
     State(triggr): State<Triggr>,
 ) -> impl IntoResponse {
-    // Try to get API key from header
-    let header_key = headers
-        .get("x-api-key")
-        .and_then(|v| v.to_str().ok())
-        .map(String::from);
-
-    // Or from query parameters
-    let api_key = header_key.or(params.api_key);
-
-    match api_key {
-        Some(key) => match ProjectStore::get(&*triggr.store, &key) {
-            Ok(project) if project.is_some() => {
-                ws.on_upgrade(move |socket| handle_socket(socket, triggr))
-            }
-            _ => StatusCode::UNAUTHORIZED.into_response(),
-        },
-        None => StatusCode::UNAUTHORIZED.into_response(),
-    }
+    // Browsers authenticate with a short-lived token, issued for a namespace
+    let (project_id, namespace) = if let Some(token) = params.token {
+        match verify_ws_token(&token) {
+            Ok(claims) => (claims.sub, claims.namespace),
+            Err(e) => return (StatusCode::UNAUTHORIZED, e).into_response(),
+        }
+    } else {
+        // Try to get API key from header
+        let header_key = headers
+            .get("x-api-key")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+
+        // Or from query parameters
+        let api_key = header_key.or(params.api_key);
+
+        let project_id = match api_key.map(|key| ProjectStore::get(&*triggr.store, &key)) {
+            Some(Ok(Some(project))) => project.id,
+            _ => return StatusCode::UNAUTHORIZED.into_response(),
+        };
+
+        // Namespace from the header, or from the query
+        let namespace = headers
+            .get(NAMESPACE_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from)
+            .or(params.namespace);
+        match namespace.map(|ns| ns.parse::<Namespace>()).transpose() {
+            Ok(namespace) => (project_id, namespace.unwrap_or_default()),
+            Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        }
+    };
+
+    // Subscriptions are scoped to the data of the namespace
+    let data_id = namespace.scope(&project_id);
+
+    // Make the connection visible to admins, within the project's connection limit
+    let limits = WsLimits::from_env();
+    let Some((id, conn)) = triggr
+        .ws_connections
+        .register(&data_id, limits.max_connections)
+    else {
+        return AppError::LimitExceeded {
+            limit: "connections".to_string(),
+            max: limits.max_connections,
+        }
+        .into_response();
+    };
+    let slot = WsSlot {
+        registry: triggr.ws_connections.clone(),
+        id,
+        conn,
+    };
+
+    // Warn the project before its connection quota is reached
+    let used = triggr.ws_connections.connection_count(&data_id);
+    quota::check(&triggr, &data_id, "connections", used, limits.max_connections).await;
+
+    ws.on_upgrade(move |socket| {
+        tenancy::scope(
+            project_id,
+            handle_socket(socket, triggr, data_id, slot, limits),
+        )
+    })
 }
 
 /// Recieve websocket commands and track database events to return to clients.
-async fn handle_socket(mut socket: WebSocket, triggr: Triggr) {
+async fn handle_socket(
+    mut socket: WebSocket,
+    triggr: Triggr,
+    project_id: String,
+    slot: WsSlot,
+    limits: WsLimits,
+) {
     // Outbound channel (task-safe queue for sending messages)
     let (tx, mut rx) = mpsc::unbounded_channel::<String>();
 
+    let conn_id = slot.id.clone();
+    let conn = slot.conn.clone();
+
     // Track client subscriptions
     let mut subscriptions: HashMap<String, Receiver<String>> = HashMap::new();
 
+    // Presence channels joined by the client
+    let mut presence: HashSet<String> = HashSet::new();
+
     loop {
         tokio::select! {
             // Incoming message from client
             Some(Ok(msg)) = socket.next() => {
-                if let Message::Text(text) = msg {
-                    if let Ok(ws_data) = serde_json::from_str::<WsJson>(&text) {
-                        let text = ws_data.data;
+                let ws_data = match msg {
+                    Message::Text(text) => serde_json::from_str::<WsJson>(&text).ok(),
+                    _ => None,
+                };
+                if let Some(ws_data) = ws_data {
+                    let text = ws_data.data;
+
+                    // Topic opened by the command, if any
+                    let new_topic = match text.strip_prefix("join:presence:") {
+                        Some(channel) => Some(format!("presence:{channel}")),
+                        None => text.strip_prefix("subscribe:").map(String::from),
+                    }
+                    .filter(|topic| !subscriptions.contains_key(topic));
 
-                        if text.starts_with("subscribe:") {
-                            let topic = text.trim_start_matches("subscribe:").to_string();
-                            let rx_sub = triggr.store.subscriptions.subscribe(&topic).await;
-                            subscriptions.insert(topic.clone(), rx_sub);
+                    // Topics are limited across the project's connections
+                    let opens_topic = new_topic.is_some();
+                    let over_limit = new_topic.filter(|_| {
+                        triggr.ws_connections.topic_count(&project_id) >= limits.max_topics
+                    });
 
-                            // Send ack through channel
+                    if let Some(topic) = over_limit {
+                        let _ = tx.send(json!({
+                            "op": "error",
+                            "topic": topic,
+                            "code": "limit_exceeded",
+                            "limit": "topics",
+                            "max": limits.max_topics,
+                            "message": format!("Maximum number of topics ({}) reached", limits.max_topics)
+                        }).to_string());
+                    }
+                    else if text.starts_with("join:presence:") {
+                        let channel = text.trim_start_matches("join:presence:").to_string();
+                        let topic = format!("presence:{channel}");
+                        let (rx_sub, members) = triggr
+                            .store
+                            .subscriptions
+                            .join_presence(
+                                &project_id,
+                                &channel,
+                                &conn_id,
+                                ws_data.meta.unwrap_or_default(),
+                            )
+                            .await;
+                        subscriptions.insert(topic.clone(), rx_sub);
+                        presence.insert(channel);
+                        conn.set_topics(subscriptions.keys().cloned().collect());
+
+                        // Ack with the current members
+                        let _ = tx.send(json!({
+                            "op": "join",
+                            "topic": topic,
+                            "id": conn_id,
+                            "members": members
+                        }).to_string());
+                    }
+                    else if text.starts_with("heartbeat:presence:") {
+                        let channel = text.trim_start_matches("heartbeat:presence:");
+                        let alive = triggr
+                            .store
+                            .subscriptions
+                            .presence_heartbeat(&project_id, channel, &conn_id, ws_data.meta)
+                            .await;
+
+                        // Members that timed out must join again
+                        if !alive {
                             let _ = tx.send(json!({
-                                "op": "subscribe",
-                                "topic": topic
+                                "op": "error",
+                                "topic": format!("presence:{channel}"),
+                                "message": "Not a member of the channel"
                             }).to_string());
                         }
-                        else if text.starts_with("unsubscribe:") {
-                            let topic = text.trim_start_matches("unsubscribe:").to_string();
-                            subscriptions.remove(&topic);
+                    }
+                    else if text.starts_with("leave:presence:") {
+                        let channel = text.trim_start_matches("leave:presence:").to_string();
+                        let topic = format!("presence:{channel}");
+                        triggr
+                            .store
+                            .subscriptions
+                            .leave_presence(&project_id, &channel, &conn_id)
+                            .await;
+                        subscriptions.remove(&topic);
+                        presence.remove(&channel);
+                        conn.set_topics(subscriptions.keys().cloned().collect());
 
-                            // Send ack
-                            let _ = tx.send(json!({
-                                "op": "unsubscribe",
-                                "topic": topic
-                            }).to_string());
+                        // Send ack
+                        let _ = tx.send(json!({
+                            "op": "leave",
+                            "topic": topic
+                        }).to_string());
+                    }
+                    else if text.starts_with("subscribe:query:") {
+                        let topic = text.trim_start_matches("subscribe:").to_string();
+                        match triggr.store.subscribe_query(&project_id, &topic).await {
+                            Ok((rx_sub, docs)) => {
+                                subscriptions.insert(topic.clone(), rx_sub);
+                                conn.set_topics(subscriptions.keys().cloned().collect());
+
+                                // Ack with the initial result set, deltas follow
+                                let _ = tx.send(json!({
+                                    "op": "subscribe",
+                                    "topic": topic,
+                                    "docs": docs
+                                }).to_string());
+                            }
+                            Err(e) => {
+                                let _ = tx.send(json!({
+                                    "op": "error",
+                                    "topic": topic,
+                                    "message": e.to_string()
+                                }).to_string());
+                            }
                         }
                     }
+                    else if text.starts_with("subscribe:") {
+                        let topic = text.trim_start_matches("subscribe:").to_string();
+                        let rx_sub = triggr.store.subscriptions.subscribe(&topic).await;
+                        subscriptions.insert(topic.clone(), rx_sub);
+                        conn.set_topics(subscriptions.keys().cloned().collect());
+
+                        // Send ack through channel
+                        let _ = tx.send(json!({
+                            "op": "subscribe",
+                            "topic": topic
+                        }).to_string());
+                    }
+                    else if text.starts_with("query:") {
+                        // One-shot query, answered on the connection without subscribing
+                        let reply = match triggr.store.query_documents(&project_id, &text) {
+                            Ok(mut docs) => {
+                                let truncated = docs.len() > limits.max_query_docs;
+                                docs.truncate(limits.max_query_docs);
+                                json!({
+                                    "op": "query",
+                                    "id": ws_data.id,
+                                    "query": text,
+                                    "docs": docs,
+                                    "truncated": truncated
+                                })
+                            }
+                            Err(e) => json!({
+                                "op": "error",
+                                "id": ws_data.id,
+                                "query": text,
+                                "message": e.to_string()
+                            }),
+                        };
+                        let _ = tx.send(reply.to_string());
+                    }
+                    else if text.starts_with("unsubscribe:") {
+                        let topic = text.trim_start_matches("unsubscribe:").to_string();
+                        subscriptions.remove(&topic);
+                        conn.set_topics(subscriptions.keys().cloned().collect());
+
+                        // Send ack
+                        let _ = tx.send(json!({
+                            "op": "unsubscribe",
+                            "topic": topic
+                        }).to_string());
+                    }
+
+                    // Warn the project before its topic quota is reached
+                    if opens_topic {
+                        let used = triggr.ws_connections.topic_count(&project_id);
+                        quota::check(&triggr, &project_id, "topics", used, limits.max_topics)
+                            .await;
+                    }
                 }
             }
 
             // Messages from subscribed topics
             _ = async {
-                for (_, rx_sub) in &mut subscriptions {
+                for rx_sub in subscriptions.values_mut() {
                     if let Ok(msg) = rx_sub.try_recv() {
                         let _ = tx.send(msg);
                     }
@@ -111,6 +337,22 @@ async fn handle_socket(mut socket: WebSocket, triggr: Triggr) {
                     break; // socket closed
                 }
             }
+
+            // Closed by an admin
+            _ = conn.close.notified() => {
+                let _ = socket.send(Message::Close(None)).await;
+                break;
+            }
         }
+
+        conn.queue_depth.store(rx.len(), Ordering::Relaxed);
     }
+
+    // Leave presence channels so other members see the connection go
+    for channel in presence {
+        triggr.store.subscriptions.leave_presence(&project_id, &channel, &conn_id).await;
+    }
+
+    // Unregisters the connection
+    drop(slot);
 }
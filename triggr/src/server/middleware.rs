@@ -1,51 +1,61 @@
 // Copyright (c) 2025, Algorealm Inc.
 // Middleware layer of the server
 
-use std::env;
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    sync::LazyLock,
+};
 
-use crate::util::decrypt;
+use crate::{
+    identity::{UserClaims, ANONYMOUS_USER},
+    namespace::{self, Namespace, NAMESPACE_HEADER},
+    service::{self, ServiceAccount},
+    util::{decrypt, hash_api_key},
+};
 
 use super::*;
-use async_trait::async_trait;
 use axum::{
     body::Body,
-    extract::FromRequestParts,
-    http::{header, request::Parts, Request, StatusCode},
+    extract::{ConnectInfo, FromRequestParts, OriginalUri},
+    http::{header, request::Parts, HeaderValue, Method, Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
+    Json,
 };
-use futures::Future;
-use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tracing::warn;
 
 /// Represents the project that an incoming request references.
 #[derive(Clone)]
 pub struct RefProject {
     pub project: Project,
+    /// Namespace of the data the request reads and writes
+    pub namespace: Namespace,
+    /// Service account the request authenticated as, if not the project key
+    pub service: Option<ServiceAccount>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Jwk {
-    n: String,
-    e: String,
-    kid: String,
-    alg: String,
-}
+impl RefProject {
+    /// Return the id the project's data is stored under in the namespace of the request.
+    pub fn data_id(&self) -> String {
+        self.namespace.scope(&self.project.id)
+    }
 
-#[derive(Debug, Deserialize)]
-struct Jwks {
-    keys: Vec<Jwk>,
+    /// Who sent the request, as written in logs.
+    pub fn actor(&self) -> String {
+        match &self.service {
+            Some(account) => format!("service account {} ({})", account.name, account.id),
+            None => "the project key".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub struct Auth {
-    pub claims: ClerkClaims,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct ClerkClaims {
-    #[serde(rename = "sub")]
-    pub user_id: String, // <- We alias "sub" directly to user_id
+    pub claims: UserClaims,
 }
 
 #[derive(Debug)]
@@ -63,113 +73,351 @@ where
 {
     type Rejection = (StatusCode, String);
 
-    fn from_request_parts(
+    async fn from_request_parts(
         parts: &mut Parts,
         _state: &S,
-    ) -> impl Future<Output = Result<Self, Self::Rejection>> {
-        async {
-            if let Some(project) = parts.extensions.get::<RefProject>() {
-                Ok(project.clone())
-            } else {
-                Err((StatusCode::UNAUTHORIZED, "Missing project context".into()))
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(project) = parts.extensions.get::<RefProject>() {
+            Ok(project.clone())
+        } else {
+            Err((StatusCode::UNAUTHORIZED, "Missing project context".into()))
+        }
+    }
+}
+
+/// Build a structured authentication error response.
+fn auth_error(status: StatusCode, code: &str, message: &str) -> Response {
+    (status, Json(json!({ "error": { "code": code, "message": message } }))).into_response()
+}
+
+/// Build the response sent to a locked out client.
+fn locked_out(retry_after: u64) -> Response {
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(json!({
+            "error": {
+                "code": "locked_out",
+                "message": "Too many failed authentication attempts",
+                "retry_after": retry_after,
             }
+        })),
+    )
+        .into_response();
+
+    if let Ok(value) = HeaderValue::from_str(&retry_after.to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+
+    response
+}
+
+/// Addresses of the reverse proxies in front of the instance, from `TRIGGR_TRUSTED_PROXIES`
+/// (comma separated). Only they may name the client of a request in `X-Forwarded-For`.
+static TRUSTED_PROXIES: LazyLock<Vec<IpAddr>> = LazyLock::new(|| {
+    env::var("TRIGGR_TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect()
+});
+
+/// Return the address of the client that sent a request.
+/// Behind a trusted proxy, it is the one the proxies recorded in `X-Forwarded-For`.
+fn client_ip(req: &Request<Body>) -> String {
+    let Some(ConnectInfo(peer)) = req.extensions().get::<ConnectInfo<SocketAddr>>() else {
+        return "unknown".to_string();
+    };
+    let forwarded = req
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok());
+
+    forwarded_client(peer.ip(), forwarded, &TRUSTED_PROXIES).to_string()
+}
+
+/// Return the client of a request received from `peer`. Proxies append the address they got
+/// a request from to `X-Forwarded-For`, so the client is the last address not of a trusted
+/// proxy; the ones before it may have been sent by the client itself.
+fn forwarded_client(peer: IpAddr, forwarded: Option<&str>, trusted: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    let mut hops = forwarded.unwrap_or_default().rsplit(',');
+    while trusted.contains(&client) {
+        match hops.next().and_then(|hop| hop.trim().parse().ok()) {
+            Some(hop) => client = hop,
+            None => break,
         }
     }
+
+    client
+}
+
+/// Return the part of a credential its sender can't change between guesses: the service account
+/// a token names, ahead of its secret. Console keys are opaque until decrypted, so only the
+/// address they come from is tracked.
+fn lockout_target(key: &str) -> Option<String> {
+    service::parse_token(key).map(|(id, _)| format!("account:{id}"))
+}
+
+/// Record a failed authentication for the client IP (and targeted service account, if any) and
+/// build the response.
+fn reject(
+    guard: &AuthGuard,
+    ip: &str,
+    target: Option<&str>,
+    code: &str,
+    message: &str,
+) -> Response {
+    if guard.record_failure(&format!("ip:{ip}")) {
+        warn!("🔒 Locked out {ip} after repeated authentication failures");
+    }
+    if let Some(target) = target.filter(|target| guard.record_failure(target)) {
+        warn!("🔒 Locked out {target} after repeated authentication failures");
+    }
+
+    auth_error(StatusCode::UNAUTHORIZED, code, message)
 }
 
 // Middleware to ensure API key correctness.
-pub async fn require_api_key(mut req: Request<Body>, next: Next) -> Result<Response, StatusCode> {
+pub async fn require_api_key(mut req: Request<Body>, next: Next) -> Response {
     // Get the state from extensions
-    let triggr = req
-        .extensions()
-        .get::<Triggr>()
-        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if let Some(key) = req.headers().get("x-api-key") {
-        if let Ok(mut key_str) = key.to_str() {
-            // Check length of key
-            if key_str.len() != 32 {
-                // This request is coming from the console.
-                // Try to decrypt it
-                let encryption_key =
-                    env::var("TRIGGR_ENCRYPTION_KEY").or_else(|_| Err(StatusCode::UNAUTHORIZED))?;
-                let decrypted_str = &decrypt(key_str, &encryption_key)
-                    .or_else(|_| Err(StatusCode::UNAUTHORIZED))?;
-
-                // Assign decrypted key
-                key_str = decrypted_str;
-
-                if let Ok(search_result) = ProjectStore::get(&*triggr.store, key_str) {
-                    if let Some(project) = search_result {
-                        let project = RefProject { project };
-
-                        req.extensions_mut().insert(project);
-                        return Ok(next.run(req).await);
-                    }
-                }
+    let Some(triggr) = req.extensions().get::<Triggr>().cloned() else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let guard = &triggr.auth_guard;
+
+    // Refuse clients that keep failing
+    let ip = client_ip(&req);
+    if let Some(retry_after) = guard.locked(&format!("ip:{ip}")) {
+        return locked_out(retry_after);
+    }
+
+    let Some(key_str) = req.headers().get("x-api-key").and_then(|k| k.to_str().ok()) else {
+        return reject(guard, &ip, None, "missing_api_key", "Missing x-api-key header");
+    };
+
+    // Guesses at the secret of a service account lock the account out, whatever keys and
+    // addresses they come from
+    let target = lockout_target(key_str);
+    if let Some(retry_after) = target.as_deref().and_then(|target| guard.locked(target)) {
+        return locked_out(retry_after);
+    }
+
+    let Ok(encryption_key) = env::var("TRIGGR_ENCRYPTION_KEY") else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    // Resolve the project, with the namespace the key is restricted to and the service account
+    let resolved = if key_str.starts_with(service::TOKEN_PREFIX) {
+        service_account(&triggr, key_str, &encryption_key)
+            .map(|(project, account)| (project, None, Some(account)))
+    } else if key_str.len() != 32 {
+        // This request is coming from the console.
+        // Try to decrypt it
+        decrypt(key_str, &encryption_key).ok().and_then(|decrypted_str| {
+            // Sandbox keys are restricted to the sandbox
+            let (project_key, restricted) = namespace::split_key(&decrypted_str);
+            match ProjectStore::get(&*triggr.store, project_key) {
+                Ok(Some(project)) => Some((project, restricted, None)),
+                _ => None,
             }
+        })
+    } else {
+        None
+    };
+    let Some((project, restricted, service)) = resolved else {
+        return reject(guard, &ip, target.as_deref(), "invalid_api_key", "Invalid API key");
+    };
+    // Failures of the account expire with their window, so a valid token used elsewhere
+    // doesn't reset the count of an ongoing guess
+    guard.record_success(&format!("ip:{ip}"));
+
+    // Service accounts are limited to their scopes
+    if let Some(account) = &service {
+        let path = req
+            .extensions()
+            .get::<OriginalUri>()
+            .map_or(req.uri().path(), |uri| uri.path());
+        let write = !matches!(*req.method(), Method::GET | Method::HEAD);
+        if !account.allows(path, write) {
+            let message = "The scopes of the service account don't allow this request";
+            return auth_error(StatusCode::FORBIDDEN, "scope_forbidden", message);
+        }
+    }
+
+    let requested = match req.headers().get(NAMESPACE_HEADER).map(|v| v.to_str()) {
+        Some(Ok(value)) => match value.parse::<Namespace>() {
+            Ok(namespace) => Some(namespace),
+            Err(e) => return auth_error(StatusCode::BAD_REQUEST, "invalid_namespace", &e),
+        },
+        Some(Err(_)) => {
+            let message = "Invalid namespace header";
+            return auth_error(StatusCode::BAD_REQUEST, "invalid_namespace", message);
+        }
+        None => None,
+    };
+    let namespace = match (restricted, requested) {
+        (Some(restricted), Some(requested)) if restricted != requested => {
+            let message = "A sandbox key can only access the sandbox";
+            return auth_error(StatusCode::FORBIDDEN, "namespace_forbidden", message);
         }
+        (restricted, requested) => restricted.or(requested).unwrap_or_default(),
+    };
+
+    // Tag storage access with the project for the tenancy audit
+    let project_id = project.id.clone();
+    req.extensions_mut().insert(RefProject {
+        project,
+        namespace,
+        service,
+    });
+    tenancy::scope(project_id, next.run(req)).await
+}
+
+/// Resolve a service account token into the project of the account and the account.
+fn service_account(
+    triggr: &Triggr,
+    token: &str,
+    encryption_key: &str,
+) -> Option<(Project, ServiceAccount)> {
+    let (id, secret) = service::parse_token(token)?;
+    let stored = triggr.store.get_service_account(id).ok()??;
+
+    // Compare in constant time so the secret cannot be guessed byte by byte
+    let hash = hash_api_key(secret).ok()?;
+    if !bool::from(hash.as_bytes().ct_eq(stored.secret_hash.as_bytes())) || stored.account.expired()
+    {
+        return None;
     }
 
-    Err(StatusCode::UNAUTHORIZED)
+    let project_key = decrypt(&stored.project_key, encryption_key).ok()?;
+    let project = ProjectStore::get(&*triggr.store, &project_key).ok()??;
+    Some((project, stored.account))
+}
+
+// Middleware to ensure the request carries the instance admin key.
+pub async fn require_admin_key(req: Request<Body>, next: Next) -> Response {
+    // Admin routes are disabled unless a key is configured
+    let admin_key = match env::var("TRIGGR_ADMIN_KEY") {
+        Ok(key) if !key.is_empty() => key,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let Some(triggr) = req.extensions().get::<Triggr>().cloned() else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let guard = &triggr.auth_guard;
+
+    let ip = client_ip(&req);
+    if let Some(retry_after) = guard.locked(&format!("ip:{ip}")) {
+        return locked_out(retry_after);
+    }
+
+    match req.headers().get("x-admin-key").map(|k| k.as_bytes()) {
+        // Compare in constant time so the key cannot be guessed byte by byte
+        Some(key) if bool::from(key.ct_eq(admin_key.as_bytes())) => {
+            guard.record_success(&format!("ip:{ip}"));
+            next.run(req).await
+        }
+        Some(_) => reject(guard, &ip, None, "invalid_admin_key", "Invalid admin key"),
+        None => reject(guard, &ip, None, "missing_admin_key", "Missing x-admin-key header"),
+    }
 }
 
 // Middleware to ensure authentication of session.
-#[async_trait]
 impl<S> FromRequestParts<S> for Auth
 where
     S: Send + Sync,
 {
     type Rejection = AuthError;
 
-    fn from_request_parts(
+    async fn from_request_parts(
         parts: &mut Parts,
         _state: &S,
-    ) -> impl Future<Output = Result<Self, Self::Rejection>> {
-        async {
-            // let headers = &parts.headers;
-            // let token = headers
-            //     .get(header::AUTHORIZATION)
-            //     .and_then(|h| h.to_str().ok())
-            //     .and_then(|h| h.strip_prefix("Bearer "))
-            //     .ok_or_else(|| AuthError("Missing Authorization header".into()))?;
-
-            // // Decode header to get key ID (kid)
-            // let header =
-            //     decode_header(token).map_err(|_| AuthError("Invalid JWT header".into()))?;
-            // let kid = header
-            //     .kid
-            //     .ok_or(AuthError("Missing kid in JWT header".into()))?;
-
-            // // Match JWK by kid
-            // let jwk = extract_matching_jwk(&kid)
-            //     .map_err(|e| AuthError(format!("Failed to extract JWK: {}", e)))?
-            //     .ok_or(AuthError("No matching JWK found".into()))?;
-
-            // // Decode token
-            // let decoding_key = DecodingKey::from_rsa_components(&jwk.n, &jwk.e)
-            //     .map_err(|_| AuthError("Invalid RSA key components".into()))?;
-            // let validation = Validation::new(Algorithm::RS256);
-
-            // let decoded = decode::<ClerkClaims>(token, &decoding_key, &validation)
-            //     .map_err(|_| AuthError("Invalid or expired Clerk token".into()))?;
-
-            // Ok(Auth {
-            //     claims: decoded.claims,
-            // })
-
-            Ok(Auth {
-                claims: ClerkClaims {
-                    user_id: "jasonXX".to_string(),
+    ) -> Result<Self, Self::Rejection> {
+        let Some(triggr) = parts.extensions.get::<Triggr>() else {
+            return Err(AuthError("Missing server state".into()));
+        };
+        let Some(provider) = triggr.auth_provider.clone() else {
+            // Console sessions are not authenticated
+            return Ok(Auth {
+                claims: UserClaims {
+                    user_id: ANONYMOUS_USER.to_string(),
                 },
-            })
-        }
+            });
+        };
+
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or_else(|| AuthError("Missing Authorization header".into()))?;
+
+        let claims = provider.authenticate(token).await.map_err(AuthError)?;
+        Ok(Auth { claims })
     }
 }
 
-/// Extract and find the JWK that matches a given `kid`
-fn extract_matching_jwk(kid: &str) -> anyhow::Result<Option<Jwk>> {
-    let jwks_str = env::var("TRIGGR_CLERKS_JWKS")?;
-    let jwks: Jwks = serde_json::from_str(&jwks_str)?;
-    Ok(jwks.keys.into_iter().find(|k| k.kid == kid))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockout_target_is_the_service_account() {
+        let target = Some("account:abc123".to_string());
+        assert_eq!(lockout_target("trgsa_abc123_secret"), target);
+        assert_eq!(lockout_target("trgsa_abc123_another_guess"), target);
+    }
+
+    #[test]
+    fn console_keys_have_no_lockout_target() {
+        assert_eq!(lockout_target("dGhpcyBpcyBhbiBlbmNyeXB0ZWQga2V5"), None);
+        assert_eq!(lockout_target("trgsa_"), None);
+        assert_eq!(lockout_target("trgsa_abc123_"), None);
+    }
+
+    #[test]
+    fn guessing_secrets_locks_the_account_out() {
+        let guard = AuthGuard::new(3, 60, 300);
+
+        // Every guess comes with a new secret, from a new address
+        for i in 0..3 {
+            let token = format!("trgsa_abc123_guess{i}");
+            let target = lockout_target(&token);
+            let response = reject(&guard, &format!("10.0.0.{i}"), target.as_deref(), "c", "m");
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        assert!(guard.locked("account:abc123").is_some());
+        assert!(guard.locked("account:other").is_none());
+        assert!((0..3).all(|i| guard.locked(&format!("ip:10.0.0.{i}")).is_none()));
+    }
+
+    #[test]
+    fn only_trusted_proxies_name_the_client() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let peer: IpAddr = "203.0.113.9".parse().unwrap();
+        let trusted = [proxy, "10.0.0.2".parse().unwrap()];
+
+        // A client can't pick its own address
+        let forged = Some("198.51.100.1");
+        assert_eq!(forwarded_client(peer, forged, &trusted), peer);
+        assert_eq!(forwarded_client(proxy, None, &[]), proxy);
+
+        // Behind proxies, the client is the last address they didn't add
+        let forwarded = Some("198.51.100.1, 203.0.113.9, 10.0.0.2");
+        assert_eq!(forwarded_client(proxy, forwarded, &trusted), peer);
+        assert_eq!(forwarded_client(proxy, None, &trusted), proxy);
+    }
+
+    #[test]
+    fn failures_from_one_address_lock_it_out() {
+        let guard = AuthGuard::new(3, 60, 300);
+        for _ in 0..3 {
+            reject(&guard, "10.0.0.1", None, "c", "m");
+        }
+
+        assert!(guard.locked("ip:10.0.0.1").is_some());
+        assert!(guard.locked("ip:10.0.0.2").is_none());
+    }
 }
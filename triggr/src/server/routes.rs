@@ -3,21 +3,83 @@
 // This module contains routes to handle incoming http and ws requests.
 
 use super::handlers::docs::ApiDoc;
-use super::handlers::{console, db, trigger, ws};
+use super::handlers::{admin, auth, console, db, dev, events, inbound, trigger, ws};
 use super::middleware as midw;
 use super::*;
-use axum::routing::{get, put}; 
-use axum::{middleware as mw, routing::post, Router};
+use axum::routing::{delete, get, put}; 
+use axum::{extract::DefaultBodyLimit, middleware as mw, routing::post, Router};
+use tower_http::compression::{
+    predicate::{NotForContentType, Predicate, SizeAbove},
+    CompressionLayer,
+};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
-/// Returns routes to handle DB requests (documents only, collections implicit).
+/// Default max body size of document requests (1MB).
+const DEFAULT_MAX_DOCUMENT_BODY: usize = 1024 * 1024;
+
+/// Default max body size of inbound webhooks (1MB).
+const DEFAULT_MAX_INBOUND_BODY: usize = 1024 * 1024;
+
+/// Default max body size of console requests, which carry metadata uploads (12MB).
+const DEFAULT_MAX_METADATA_BODY: usize = 12 * 1024 * 1024;
+
+/// Route groups compressed by default.
+const DEFAULT_COMPRESSION_ROUTES: &str = "db,trigger,console,events";
+
+/// Default minimum size of a response worth compressing (1KB).
+const DEFAULT_COMPRESSION_MIN_SIZE: u16 = 1024;
+
+/// Return the gzip/br compression layer of a route group.
+/// `TRIGGR_COMPRESSION_ROUTES` lists the compressed groups (`none` to disable compression),
+/// and responses under `TRIGGR_COMPRESSION_MIN_SIZE` bytes are sent as is.
+fn compression(group: &str) -> CompressionLayer<impl Predicate> {
+    let routes = std::env::var("TRIGGR_COMPRESSION_ROUTES")
+        .unwrap_or_else(|_| DEFAULT_COMPRESSION_ROUTES.to_string());
+    let enabled = routes.split(',').any(|r| r.trim() == group);
+
+    let min_size = std::env::var("TRIGGR_COMPRESSION_MIN_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_COMPRESSION_MIN_SIZE);
+
+    // Images (attachments) are already compressed
+    CompressionLayer::new()
+        .gzip(enabled)
+        .br(enabled)
+        .compress_when(SizeAbove::new(min_size).and(NotForContentType::IMAGES))
+}
+
+/// Read a body size limit from the environment, falling back to a default.
+fn body_limit(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Returns routes to handle DB requests (documents, collections implicit, key-value entries and
+/// watchlists).
 pub fn db_routes() -> Router<Triggr> {
     Router::new()
         .nest(
             "/api/db/collections",
             Router::new()
                 .route("/", get(db::list_collections))
+                .route("/{name}/subscribers", get(db::collection_subscribers))
+                .route("/{name}/chart", get(db::collection_chart))
+                .route(
+                    "/{name}/geo-index",
+                    get(db::get_geo_index)
+                        .put(db::put_geo_index)
+                        .delete(db::delete_geo_index),
+                )
+                .route(
+                    "/{name}/state-machine",
+                    get(db::get_state_machine)
+                        .put(db::put_state_machine)
+                        .delete(db::delete_state_machine),
+                )
                 .route(
                     "/{name}/docs",
                     post(db::insert_document).get(db::list_documents),
@@ -27,9 +89,51 @@ pub fn db_routes() -> Router<Triggr> {
                     get(db::get_document)
                         .put(db::update_document)
                         .delete(db::delete_document),
+                )
+                .route(
+                    "/{name}/docs/{id}/attachments",
+                    get(db::list_attachments),
+                )
+                .route(
+                    "/{name}/docs/{id}/attachments/{key}",
+                    get(db::get_attachment)
+                        .put(db::put_attachment)
+                        .delete(db::delete_attachment),
+                ),
+        )
+        .route(
+            "/api/db/kv/{key}",
+            get(db::get_kv).put(db::put_kv).delete(db::delete_kv),
+        )
+        .nest(
+            "/api/db/watchlists",
+            Router::new()
+                .route("/", get(db::list_watchlists))
+                .route(
+                    "/{name}",
+                    get(db::get_watchlist)
+                        .put(db::put_watchlist)
+                        .delete(db::delete_watchlist),
+                )
+                .route("/{name}/import", post(db::import_watchlist))
+                .route(
+                    "/{name}/addresses/{address}",
+                    put(db::add_watchlist_address).delete(db::remove_watchlist_address),
                 ),
         )
         .route_layer(mw::from_fn(midw::require_api_key))
+        .layer(DefaultBodyLimit::max(body_limit(
+            "TRIGGR_MAX_DOCUMENT_BODY",
+            DEFAULT_MAX_DOCUMENT_BODY,
+        )))
+        .layer(compression("db"))
+}
+
+/// Returns routes to handle authentication requests.
+pub fn auth_routes() -> Router<Triggr> {
+    Router::new()
+        .route("/api/auth/ws-token", post(auth::issue_ws_token))
+        .route_layer(mw::from_fn(midw::require_api_key))
 }
 
 /// Returns routes to handle console requests.
@@ -41,23 +145,160 @@ pub fn console_routes() -> Router<Triggr> {
             "/api/console/project/{project_id}",
             get(console::get_project).delete(console::delete_project),
         )
+        .route(
+            "/api/console/project/{project_id}/decoding-errors",
+            get(console::get_decoding_errors),
+        )
+        .route(
+            "/api/console/project/{project_id}/decode-mode",
+            put(console::update_decode_mode),
+        )
+        .route(
+            "/api/console/project/{project_id}/ordering",
+            put(console::update_ordering),
+        )
+        .route(
+            "/api/console/project/{project_id}/event-routes",
+            put(console::update_event_routes),
+        )
+        .route(
+            "/api/console/project/{project_id}/enrichers",
+            put(console::update_enrichers),
+        )
+        .route(
+            "/api/console/project/{project_id}/sandbox",
+            get(console::get_sandbox).put(console::put_sandbox),
+        )
+        .route("/api/console/project/{project_id}/usage", get(console::get_usage))
+        .route(
+            "/api/console/project/{project_id}/service-accounts",
+            get(console::list_service_accounts).post(console::create_service_account),
+        )
+        .route(
+            "/api/console/project/{project_id}/service-accounts/{id}",
+            delete(console::delete_service_account),
+        )
+        .route("/api/console/project/{project_id}/apply", post(console::apply_spec))
+        .route("/api/console/project/{project_id}/plan", post(console::plan_spec))
+        .route("/api/console/project/{project_id}/drift", get(console::get_drift))
+        .route("/api/console/project/{project_id}/decode", post(console::decode_event))
         .route("/api/console/projects", get(console::list_projects))
+        .route("/api/console/bootstrap", post(console::bootstrap))
+        .layer(DefaultBodyLimit::max(body_limit(
+            "TRIGGR_MAX_METADATA_BODY",
+            DEFAULT_MAX_METADATA_BODY,
+        )))
+        .layer(compression("console"))
 }
 
 /// Returns routes to handle console requests concerning triggers.
 pub fn trigger_routes() -> Router<Triggr> {
     Router::new()
-        .route("/api/trigger", post(trigger::save_trigger))
+        .route(
+            "/api/trigger",
+            post(trigger::save_trigger).get(trigger::list_project_triggers),
+        )
+        .route("/api/trigger/validate", post(trigger::validate_trigger))
+        .route(
+            "/api/trigger/preview-template",
+            post(trigger::preview_template),
+        )
+        .route("/api/trigger/search", get(trigger::search_triggers))
+        .route("/api/trigger/runs", get(trigger::list_runs))
+        .route(
+            "/api/trigger/runs/{id}/redecode",
+            post(trigger::redecode_run),
+        )
         .route("/api/trigger/{contract_addr}", get(trigger::list_triggers))
+        .route(
+            "/api/trigger/{contract_addr}/state",
+            put(trigger::update_triggers_state),
+        )
+        .route(
+            "/api/trigger/{contract_addr}/anomalies",
+            get(trigger::anomaly_baselines),
+        )
         .route(
             "/api/trigger/{contract_addr}/{id}",
             get(trigger::get_trigger).delete(trigger::delete_trigger),
         )
+        .route(
+            "/api/trigger/{contract_addr}/{id}/explain",
+            post(trigger::explain_trigger),
+        )
+        .route(
+            "/api/trigger/{contract_addr}/{id}/recompute",
+            post(trigger::recompute_trigger),
+        )
         .route(
             "/api/trigger/{contract_addr}/{id}/state",
             put(trigger::update_trigger_state),
         )
         .route_layer(mw::from_fn(midw::require_api_key))
+        .layer(compression("trigger"))
+}
+
+/// Returns routes to read the decoded events of contracts.
+pub fn events_routes() -> Router<Triggr> {
+    Router::new()
+        .route("/api/events/{contract_addr}", get(events::list_events))
+        .route_layer(mw::from_fn(midw::require_api_key))
+        .layer(compression("events"))
+}
+
+/// Returns the routes external indexers push events to, authenticated by their signature.
+pub fn inbound_routes() -> Router<Triggr> {
+    Router::new()
+        .route("/api/inbound/{provider}", post(inbound::receive))
+        .layer(DefaultBodyLimit::max(body_limit(
+            "TRIGGR_MAX_INBOUND_BODY",
+            DEFAULT_MAX_INBOUND_BODY,
+        )))
+}
+
+/// Returns routes reserved to the instance operator.
+pub fn admin_routes() -> Router<Triggr> {
+    Router::new()
+        .route("/api/admin/decoder/replay", post(admin::replay_decoder_corpus))
+        .route("/api/admin/decoder/fuzz", post(admin::fuzz_decoder))
+        .route("/api/admin/cache", get(admin::cache_stats))
+        .route("/api/admin/auth", get(admin::auth_stats))
+        .route("/api/admin/health", get(admin::chain_health))
+        .route("/api/admin/pipeline", get(admin::pipeline_stats))
+        .route("/api/admin/subscriptions", get(admin::subscription_stats))
+        .route("/api/admin/quotas", get(admin::quota_stats))
+        .route("/api/admin/metering", get(admin::metering_usage))
+        .route("/api/admin/ws", get(admin::list_ws_connections))
+        .route("/api/admin/ws/{id}", delete(admin::close_ws_connection))
+        .route("/api/admin/shard", get(admin::shard_stats))
+        .route("/api/admin/integrity", get(admin::integrity_report))
+        .route(
+            "/api/admin/gc",
+            get(admin::orphan_report).post(admin::collect_garbage),
+        )
+        .route(
+            "/api/admin/integrity/quarantine",
+            post(admin::quarantine_corrupted),
+        )
+        .route(
+            "/api/admin/load",
+            get(admin::load_status)
+                .post(admin::start_load)
+                .delete(admin::stop_load),
+        )
+        .route(
+            "/api/admin/maintenance",
+            get(admin::maintenance_status).put(admin::update_maintenance),
+        )
+        .route_layer(mw::from_fn(midw::require_admin_key))
+        .layer(compression("admin"))
+}
+
+/// Returns routes to handle development requests (dev mode only).
+pub fn dev_routes() -> Router<Triggr> {
+    Router::new()
+        .route("/api/dev/emit-event", post(dev::emit_event))
+        .route_layer(mw::from_fn(midw::require_api_key))
 }
 
 /// Returns the 'ws' route.
@@ -5,10 +5,20 @@
 
 use super::*;
 use crate::{
-    chain::polkadot::{prelude::CONTRACTS_NODE_URL, Polkadot},
-    server::routes, util::introduce_triggr,
+    bootstrap,
+    chain::polkadot::{
+        prelude::CONTRACTS_NODE_URL,
+        Polkadot,
+    },
+    gc, journal, metering,
+    server::routes, telemetry, util::introduce_triggr,
 };
-use axum::{http::Method, routing::get, Extension, Router};
+use axum::{
+    http::{header, Method},
+    routing::get,
+    Extension, Router,
+};
+use std::net::SocketAddr;
 use tokio::net::TcpListener;
 use tokio::sync::mpsc;
 use tower_http::cors::{Any, CorsLayer};
@@ -18,35 +28,75 @@ pub async fn run() {
     use dotenvy::dotenv;
     dotenv().ok(); // load from .env
 
-    // Initialize shared system state.
-    let state = Triggr::new();
+    // Export traces of the event pipeline when a collector is configured
+    let tracer_provider = telemetry::init();
 
-    // Create one-way channel to send decoded event from the listener task to the database
-    let (tx, rx) = mpsc::channel(100);
+    // Initialize shared system state, refusing to start on data that can't be opened or migrated
+    let state = match Triggr::new() {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Failed to open stored data: {e}");
+            std::process::exit(1);
+        }
+    };
 
-    // Spin up a task to listen to blockchain events and execute triggers configured to respond to them
-    tokio::task::spawn(handle_chain_events(state.clone(), rx));
+    // Spin up the trigger engine, fed by the chain listener
+    let tx = spawn_engine(state.clone());
+
+    // Drop broadcast topics nobody listens to anymore
+    tokio::task::spawn(state.store.subscriptions.clone().collect_garbage());
+
+    // Flush coalesced writes in the background
+    state.store.flush.run();
+
+    // Finish purging projects deleted before the last shutdown
+    state.store.spawn_purge();
+
+    // Give new users a working example (dev mode registers its own)
+    if state.dev.is_none() {
+        bootstrap::from_env(&state).await;
+    }
+
+    // Report (and clean) data left behind by deleted projects
+    tokio::task::spawn(gc::run(state.clone()));
+
+    // Export the usage of the projects for invoicing
+    tokio::task::spawn(metering::run(state.clone()));
+
+    // Replay a recorded journal instead of listening to the chain
+    let replay = std::env::var("TRIGGR_REPLAY_JOURNAL").ok();
+
+    // Keep this instance's shard lease alive
+    if let Some(sharding) = state.sharding.clone() {
+        tokio::task::spawn(sharding.run());
+    }
+
+    // Watch for a stalled chain subscription
+    if replay.is_none() {
+        tokio::task::spawn(Polkadot::watchdog(state.clone()));
+    }
+
+    // Listen to a local node in dev mode, with the demo contract deployed on it
+    let dev = state.dev.clone();
+    let node_url = match &dev {
+        Some(dev) => {
+            println!("🧪 Running in dev mode");
+            dev.launch_node().await;
+            let (demo, triggr) = (dev.clone(), state.clone());
+            tokio::task::spawn(async move { demo.run(triggr).await });
+            dev.node_url.clone()
+        }
+        None => CONTRACTS_NODE_URL.to_string(),
+    };
+
+    // Leave the shard ring on shutdown
+    let sharding = state.sharding.clone();
 
     // Create LocalSet for !Send futures
     let local = tokio::task::LocalSet::new();
 
-    // CORS configuration
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_headers(Any);
-
     // Server configuration
-    let app = Router::new()
-        .merge(routes::db_routes())
-        .merge(routes::trigger_routes())
-        .merge(routes::console_routes())
-        .merge(routes::ws_route())
-        .merge(routes::docs_routes())
-        .with_state(state.clone())
-        .layer(Extension(state.clone()))
-        .layer(cors)
-        .route("/health", get(|| async { "OK" }));
+    let app = app(state.clone());
 
     let server_address = "0.0.0.0:5190";
     let listener = TcpListener::bind(server_address).await.unwrap();
@@ -59,19 +109,120 @@ pub async fn run() {
     // Run both the watcher and the server inside the LocalSet
     local
         .run_until(async move {
-            // Spawn the !Send watcher locally
-            tokio::task::spawn_local(async move {
-                println!("🎯 Connecting to Polkadot node...");
-                let api = Polkadot::connect(CONTRACTS_NODE_URL).await;
-                println!("🔗 Connected. Starting event watcher...");
-                Polkadot::watch_event(api, tx, state.clone()).await;
-            });
+            if let Some(path) = replay {
+                println!("⏯️ Replaying event journal {path}...");
+                tokio::task::spawn(journal::replay(path, tx, state.clone()));
+            } else {
+                // Spawn the !Send watcher locally
+                tokio::task::spawn_local(async move {
+                    println!("🎯 Connecting to Polkadot node...");
+                    let api = Polkadot::connect(&node_url).await;
+                    println!("🔗 Connected. Starting event watcher...");
+                    Polkadot::watch_event(api, tx, state.clone()).await;
+                });
+            }
 
             // Start the Axum server
             println!("🌐 HTTP server is running...");
-            if let Err(err) = axum::serve(listener, app).await {
+            // Client addresses are needed to track failed authentications
+            let service = app.into_make_service_with_connect_info::<SocketAddr>();
+            let server = axum::serve(listener, service).with_graceful_shutdown(shutdown_signal());
+            if let Err(err) = server.await {
                 eprintln!("Server error: {:?}", err);
             }
         })
         .await;
+
+    // Hand this instance's contracts over right away, instead of when its lease expires
+    if let Some(sharding) = sharding {
+        sharding.leave().await;
+    }
+
+    // Don't leave the local node running
+    if let Some(dev) = dev {
+        dev.stop_node();
+    }
+
+    // Flush the spans still buffered
+    if let Some(provider) = tracer_provider {
+        let _ = provider.shutdown();
+    }
+}
+
+/// Resolve when the process is asked to stop (Ctrl+C, or SIGTERM on unix).
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    println!("🛑 Shutting down...");
+}
+
+/// Spawn the task that executes triggers and return the sender decoded events are queued with.
+pub(crate) fn spawn_engine(state: Triggr) -> EventSender {
+    // Create one-way channel to wake the engine up when the listener task queues an event
+    let (tx, rx) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+
+    // Spin up a task to listen to blockchain events and execute triggers configured to respond to them
+    tokio::task::spawn(handle_chain_events(state.clone(), rx));
+
+    // Synthetic load and webhooks go through the same queue
+    let sender = EventSender::new(state.store.clone(), tx);
+    state.load.attach(sender.clone());
+    state.inbound.attach(sender.clone());
+    state.outbox.start(state.metering.clone());
+    if let Some(dev) = &state.dev {
+        dev.attach(sender.clone());
+    }
+
+    sender
+}
+
+/// Build the HTTP and WebSocket application.
+pub(crate) fn app(state: Triggr) -> Router {
+    // CORS configuration
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers(Any)
+        // Polling clients revalidate documents with their ETag
+        .expose_headers([header::ETAG]);
+
+    // Development endpoints are only served in dev mode
+    let dev_routes = match state.dev {
+        Some(_) => routes::dev_routes(),
+        None => Router::new(),
+    };
+
+    Router::new()
+        .merge(routes::db_routes())
+        .merge(routes::trigger_routes())
+        .merge(routes::events_routes())
+        .merge(routes::inbound_routes())
+        .merge(routes::console_routes())
+        .merge(routes::auth_routes())
+        .merge(routes::admin_routes())
+        .merge(routes::ws_route())
+        .merge(routes::docs_routes())
+        .merge(dev_routes)
+        .with_state(state.clone())
+        .layer(Extension(state))
+        .layer(cors)
+        .route("/health", get(|| async { "OK" }))
 }
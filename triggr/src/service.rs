@@ -0,0 +1,128 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the service accounts of projects: identities of machines (e.g. a CI pipeline
+// deploying triggers) rather than console users. Each account gets a long-lived token, sent as
+// `x-api-key` like the project key but limited to the scopes of the account. Triggers saved with
+// it are owned by the account, and changes it makes are logged under its name.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::util::{generate_nonce, generate_uuid};
+
+/// Prefix of service account tokens, telling them apart from project keys.
+pub const TOKEN_PREFIX: &str = "trgsa_";
+
+/// Max number of service accounts of a project.
+pub const MAX_SERVICE_ACCOUNTS: usize = 20;
+
+/// What a service account token may do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Scope {
+    /// Read triggers and their runs
+    #[serde(rename = "triggers:read")]
+    TriggersRead,
+    /// Create, update and delete triggers
+    #[serde(rename = "triggers:write")]
+    TriggersWrite,
+    /// Read documents and key-value entries
+    #[serde(rename = "db:read")]
+    DbRead,
+    /// Write documents and key-value entries
+    #[serde(rename = "db:write")]
+    DbWrite,
+}
+
+/// Part of the API a request reaches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    Triggers,
+    Db,
+}
+
+impl Resource {
+    /// Return the resource of a request path, if service accounts can reach it.
+    pub fn of_path(path: &str) -> Option<Self> {
+        let under = |prefix: &str| {
+            path.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        };
+
+        if under("/api/trigger") {
+            Some(Self::Triggers)
+        } else if under("/api/db") {
+            Some(Self::Db)
+        } else {
+            None
+        }
+    }
+}
+
+impl Scope {
+    /// Whether the scope grants reading (or writing) a resource. Writing implies reading.
+    pub fn grants(self, resource: Resource, write: bool) -> bool {
+        match self {
+            Scope::TriggersRead => resource == Resource::Triggers && !write,
+            Scope::TriggersWrite => resource == Resource::Triggers,
+            Scope::DbRead => resource == Resource::Db && !write,
+            Scope::DbWrite => resource == Resource::Db,
+        }
+    }
+}
+
+/// A service account of a project.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ServiceAccount {
+    pub id: String,
+    pub project_id: String,
+    /// What the account is used for (e.g. `github-deploy`)
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: u64,
+    /// When the token stops being accepted (unix ms), if ever
+    pub expires_at: Option<u64>,
+}
+
+impl ServiceAccount {
+    /// Whether the token of the account is no longer accepted.
+    pub fn expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now().timestamp_millis() as u64)
+    }
+
+    /// Whether the account may send a request to a path.
+    pub fn allows(&self, path: &str, write: bool) -> bool {
+        Resource::of_path(path).is_some_and(|resource| {
+            self.scopes.iter().any(|scope| scope.grants(resource, write))
+        })
+    }
+}
+
+/// Stored record of a service account: the account, the encrypted key of its project and the
+/// hash of its token secret. Tokens themselves are never stored.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredServiceAccount {
+    #[serde(flatten)]
+    pub account: ServiceAccount,
+    pub project_key: String,
+    pub secret_hash: String,
+}
+
+/// Generate an account id and its token. Returns (id, token, secret).
+pub fn generate_token() -> (String, String, String) {
+    let id = generate_uuid();
+    let secret = generate_nonce::<32>();
+    let token = format!("{TOKEN_PREFIX}{id}_{secret}");
+
+    (id, token, secret)
+}
+
+/// Split a token into its account id and secret.
+pub fn parse_token(token: &str) -> Option<(&str, &str)> {
+    // Account ids have no underscore, secrets may
+    token
+        .strip_prefix(TOKEN_PREFIX)?
+        .split_once('_')
+        .filter(|(id, secret)| !id.is_empty() && !secret.is_empty())
+}
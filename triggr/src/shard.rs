@@ -0,0 +1,310 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module shards contracts across instances watching the same chain.
+// Instances hold leases in a shared directory and place themselves on a consistent hash ring.
+// Each instance only decodes and executes the events of the contracts it owns on the ring.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use blake2::{Blake2b512, Digest};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::util::generate_uuid;
+
+/// Default lease duration (seconds). Leases are renewed three times per period.
+const DEFAULT_SHARD_LEASE_SECS: u64 = 15;
+
+/// Points each instance places on the hash ring, for an even spread of contracts.
+const VIRTUAL_NODES: u32 = 64;
+
+/// File extension of lease files.
+const LEASE_EXT: &str = "lease";
+
+/// A lease held by an instance in the shared directory.
+#[derive(Debug, Serialize, Deserialize)]
+struct Lease {
+    instance: String,
+    /// Expiry (unix ms)
+    expires_at: u64,
+}
+
+/// Sharding status of the instance.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ShardStats {
+    /// ID of this instance
+    pub instance: String,
+    /// Instances holding a live lease, this one included
+    pub members: Vec<String>,
+    /// Lease duration (seconds)
+    pub lease_secs: u64,
+}
+
+/// Membership of this instance in the shard ring.
+pub struct Sharding {
+    instance: String,
+    dir: PathBuf,
+    lease_secs: u64,
+    /// Ring points (hash, instance), sorted by hash
+    ring: RwLock<Vec<(u64, String)>>,
+    /// Expiry of the lease this instance last wrote (unix ms), 0 before the first one
+    lease_expires_at: AtomicU64,
+    /// Held while the lease file is written or removed
+    lease: tokio::sync::Mutex<()>,
+    /// Set once the instance left the ring, so its lease is not renewed anymore
+    stopped: AtomicBool,
+}
+
+impl Sharding {
+    /// Enable sharding when `TRIGGR_SHARD_DIR` points to a directory shared by the instances.
+    /// The instance ID is read from `TRIGGR_SHARD_ID` and generated when unset.
+    pub fn from_env() -> Option<Arc<Self>> {
+        let dir = std::env::var("TRIGGR_SHARD_DIR").ok()?;
+        let instance = std::env::var("TRIGGR_SHARD_ID").unwrap_or_else(|_| generate_uuid());
+        let lease_secs = std::env::var("TRIGGR_SHARD_LEASE_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|s| *s > 0)
+            .unwrap_or(DEFAULT_SHARD_LEASE_SECS);
+
+        Some(Arc::new(Self::new(instance, PathBuf::from(dir), lease_secs)))
+    }
+
+    /// Own no contract until the lease is written and the other instances are known, so two
+    /// instances never execute the same contract.
+    fn new(instance: String, dir: PathBuf, lease_secs: u64) -> Self {
+        Self {
+            instance,
+            dir,
+            lease_secs,
+            ring: RwLock::new(Vec::new()),
+            lease_expires_at: AtomicU64::new(0),
+            lease: tokio::sync::Mutex::new(()),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether this instance owns a contract.
+    /// Nothing is owned once the lease of the instance expired (e.g. its renewals failed or
+    /// stalled), as the other instances have taken its contracts over by then.
+    pub fn owns(&self, contract_addr: &str) -> bool {
+        if now_ms() >= self.lease_expires_at.load(Ordering::Acquire) {
+            return false;
+        }
+        let Ok(ring) = self.ring.read() else {
+            return false;
+        };
+
+        // The owner is the first point clockwise from the contract's hash
+        let hash = hash(&contract_addr.to_lowercase());
+        let index = ring.partition_point(|(point, _)| *point < hash);
+        ring.get(index)
+            .or_else(|| ring.first())
+            .is_some_and(|(_, owner)| *owner == self.instance)
+    }
+
+    /// Return the sharding status.
+    pub fn stats(&self) -> ShardStats {
+        let mut members = self
+            .ring
+            .read()
+            .map(|ring| ring.iter().map(|(_, i)| i.clone()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        members.sort();
+        members.dedup();
+
+        ShardStats {
+            instance: self.instance.clone(),
+            members,
+            lease_secs: self.lease_secs,
+        }
+    }
+
+    /// Renew the lease of this instance and rebuild the ring from the live leases.
+    pub async fn run(self: Arc<Self>) {
+        if let Err(e) = tokio::fs::create_dir_all(&self.dir).await {
+            warn!("Failed to create shard directory {}: {e}", self.dir.display());
+            return;
+        }
+
+        info!("🧩 Sharding contracts as instance {}", self.instance);
+
+        let mut interval = tokio::time::interval(Duration::from_secs((self.lease_secs / 3).max(1)));
+        loop {
+            interval.tick().await;
+            if self.stopped.load(Ordering::Acquire) {
+                return;
+            }
+
+            if let Err(e) = self.renew().await {
+                warn!("Failed to renew shard lease: {e}");
+                continue;
+            }
+
+            let members = match self.members().await {
+                Ok(members) => members,
+                Err(e) => {
+                    warn!("Failed to read shard leases: {e}");
+                    continue;
+                }
+            };
+
+            // Rebalance when instances join or leave
+            if members != self.stats().members {
+                info!("🧩 Shard ring changed: {} instance(s)", members.len());
+                if let Ok(mut ring) = self.ring.write() {
+                    *ring = Self::ring(&members);
+                }
+            }
+        }
+    }
+
+    /// Leave the ring on shutdown: stop owning contracts and remove the lease, so the other
+    /// instances take the contracts over without waiting for it to expire.
+    pub async fn leave(&self) {
+        let _lease = self.lease.lock().await;
+        self.stopped.store(true, Ordering::Release);
+        self.lease_expires_at.store(0, Ordering::Release);
+        if let Ok(mut ring) = self.ring.write() {
+            ring.clear();
+        }
+
+        if let Err(e) = tokio::fs::remove_file(self.lease_path()).await {
+            warn!("Failed to remove shard lease: {e}");
+        }
+    }
+
+    /// Write the lease of this instance.
+    async fn renew(&self) -> std::io::Result<()> {
+        let _lease = self.lease.lock().await;
+        if self.stopped.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let lease = Lease {
+            instance: self.instance.clone(),
+            expires_at: now_ms() + self.lease_secs * 1000,
+        };
+
+        // Write then rename, so other instances never read a partial lease
+        let path = self.lease_path();
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec(&lease)?).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+
+        self.lease_expires_at.store(lease.expires_at, Ordering::Release);
+        Ok(())
+    }
+
+    /// Path of the lease file of this instance.
+    fn lease_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.{LEASE_EXT}", self.instance))
+    }
+
+    /// Return the instances holding a live lease, sorted.
+    async fn members(&self) -> std::io::Result<Vec<String>> {
+        let now = now_ms();
+        let mut members = vec![self.instance.clone()];
+
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != LEASE_EXT) {
+                continue;
+            }
+
+            // Leases being written or removed are picked up on the next renewal
+            let Ok(bytes) = tokio::fs::read(&path).await else {
+                continue;
+            };
+            match serde_json::from_slice::<Lease>(&bytes) {
+                Ok(lease) if lease.expires_at > now => members.push(lease.instance),
+                Ok(_) => {}
+                Err(e) => warn!("Skipping malformed shard lease {}: {e}", path.display()),
+            }
+        }
+
+        members.sort();
+        members.dedup();
+        Ok(members)
+    }
+
+    /// Build the hash ring of a set of instances.
+    fn ring(members: &[String]) -> Vec<(u64, String)> {
+        let mut ring = members
+            .iter()
+            .flat_map(|m| (0..VIRTUAL_NODES).map(move |i| (hash(&format!("{m}#{i}")), m.clone())))
+            .collect::<Vec<_>>();
+        ring.sort();
+        ring
+    }
+}
+
+/// Current time (unix ms).
+fn now_ms() -> u64 {
+    Utc::now().timestamp_millis() as u64
+}
+
+/// Position of a key on the hash ring.
+fn hash(key: &str) -> u64 {
+    let digest = Blake2b512::digest(key.as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sharding(dir: &std::path::Path) -> Sharding {
+        Sharding::new("a".to_string(), dir.to_path_buf(), 60)
+    }
+
+    #[tokio::test]
+    async fn nothing_is_owned_without_a_live_lease() {
+        let dir = std::env::temp_dir().join(format!("triggr-shard-{}", generate_uuid()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let sharding = sharding(&dir);
+
+        // Before the first lease, and with an empty ring
+        assert!(!sharding.owns("5Contract"));
+
+        sharding.renew().await.unwrap();
+        *sharding.ring.write().unwrap() = Sharding::ring(&["a".to_string()]);
+        assert!(sharding.owns("5Contract"));
+
+        // A lease past its expiry fences the instance off
+        sharding.lease_expires_at.store(now_ms() - 1, Ordering::Release);
+        assert!(!sharding.owns("5Contract"));
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn leaving_removes_the_lease() {
+        let dir = std::env::temp_dir().join(format!("triggr-shard-{}", generate_uuid()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let sharding = sharding(&dir);
+
+        sharding.renew().await.unwrap();
+        assert!(sharding.lease_path().exists());
+
+        sharding.leave().await;
+        assert!(!sharding.lease_path().exists());
+        assert!(!sharding.owns("5Contract"));
+
+        // The lease is not written again
+        sharding.renew().await.unwrap();
+        assert!(!sharding.lease_path().exists());
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+}
@@ -4,15 +4,49 @@
 // We are using sled for the internal database storage. This is because it is fast and composable in a single binary.
 // No external (network) dependencies.
 
-use crate::util::encrypt;
+use crate::{
+    apply::AppliedSpec,
+    chain::polkadot::prelude::EventData,
+    codec::Codec,
+    durability::FlushPolicy,
+    geo::{GeoIndex, GeoPoint, Near, GEOHASH_PRECISION},
+    batch::{BlockBatch, DeferredWrite},
+    lifecycle::{StateMachine, Transition},
+    dsl::{Condition, DslExecutor, DslParser},
+    name::Name,
+    namespace::{self, Namespace},
+    service::{self, Scope, ServiceAccount, StoredServiceAccount},
+    tenancy,
+    util::{
+        api_key_salt_id, content_hash, encrypt, hash_api_key, merge_patch, API_KEY_HASH_LEN,
+    },
+    watchlist::{Watchlist, WatchlistInfo},
+};
 
 use super::*;
 use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
+use blake2::{Blake2b512, Digest};
 use chrono::Utc;
-use serde::{Deserialize, Serialize};
-use sled::{Db, IVec};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sled::{
+    transaction::{
+        ConflictableTransactionError, ConflictableTransactionResult, TransactionalTree,
+        UnabortableTransactionError,
+    },
+    Db, IVec,
+};
 use utoipa::ToSchema;
-use std::{collections::HashMap, env, fs, path::Path, sync::Arc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 use tokio::sync::{
     broadcast::{self, Receiver, Sender},
     RwLock,
@@ -25,6 +59,15 @@ pub struct Metadata {
     pub path: String,
 }
 
+/// Metadata describing a binary attachment.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AttachmentInfo {
+    pub key: String,
+    pub content_type: String,
+    pub size: usize,
+    pub uploaded_at: u64,
+}
+
 /// Summary statistics for a collection.
 #[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct CollectionSummary {
@@ -33,10 +76,366 @@ pub struct CollectionSummary {
     pub last_updated: u64,
 }
 
+/// Document count and last write time of a collection,
+/// updated in the same transaction as every document write.
+#[derive(Debug, Clone, Copy, Default)]
+struct CollectionStats {
+    count: u64,
+    last_updated: u64,
+}
+
+impl CollectionStats {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let (count, last_updated) = bytes.split_at(8.min(bytes.len()));
+        Self {
+            count: u64::from_be_bytes(count.try_into().unwrap_or_default()),
+            last_updated: u64::from_be_bytes(last_updated.try_into().unwrap_or_default()),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.count.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.last_updated.to_be_bytes());
+        bytes
+    }
+
+    /// Change the stats stored at `key` within a transaction.
+    /// Stats of an empty collection are removed.
+    fn update(
+        tree: &TransactionalTree,
+        key: &[u8],
+        change: impl FnOnce(&mut Self),
+    ) -> Result<(), UnabortableTransactionError> {
+        let mut stats = tree.get(key)?.map(|v| Self::from_bytes(&v)).unwrap_or_default();
+        change(&mut stats);
+
+        if stats.count > 0 {
+            tree.insert(key, &stats.to_bytes()[..])?;
+        } else {
+            tree.remove(key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Identity and write time of a stored document, read without decoding its data.
+#[derive(Deserialize)]
+struct DocStamp {
+    #[serde(default)]
+    id: String,
+    metadata: DocStampMetadata,
+}
+
+#[derive(Deserialize)]
+struct DocStampMetadata {
+    updated_at: u64,
+    #[serde(default)]
+    hash: Option<String>,
+}
+
+thread_local! {
+    /// Buffers keys are built into, reused across operations.
+    /// A key built while another is in use (e.g. a document and its stats) takes the next one.
+    static KEY_BUFS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Builds `{kind}::{...}` keys of a project tree into a reusable buffer,
+/// instead of formatting a new String per operation.
+struct KeyBuf;
+
+impl KeyBuf {
+    /// Key of a document: `document::{collection}::{doc_id}`.
+    fn document<R>(collection: &str, doc_id: &str, f: impl FnOnce(&[u8]) -> R) -> R {
+        Self::build("document", &[collection, doc_id], false, f)
+    }
+
+    /// Prefix of the documents of a collection: `document::{collection}::`.
+    fn collection<R>(collection: &str, f: impl FnOnce(&[u8]) -> R) -> R {
+        Self::build("document", &[collection], true, f)
+    }
+
+    /// Key of the stats of a collection: `collection::{collection}`.
+    fn stats<R>(collection: &str, f: impl FnOnce(&[u8]) -> R) -> R {
+        Self::build("collection", &[collection], false, f)
+    }
+
+    /// Prefix of the collection stats of a project: `collection::`.
+    fn project_stats<R>(f: impl FnOnce(&[u8]) -> R) -> R {
+        Self::build("collection", &[], true, f)
+    }
+
+    fn build<R>(kind: &str, parts: &[&str], prefix: bool, f: impl FnOnce(&[u8]) -> R) -> R {
+        let write = |buf: &mut String| {
+            buf.clear();
+            buf.push_str(kind);
+            for part in parts {
+                buf.push_str("::");
+                buf.push_str(part);
+            }
+            if prefix {
+                buf.push_str("::");
+            }
+        };
+
+        KEY_BUFS.with(|bufs| {
+            let mut buf = bufs
+                .borrow_mut()
+                .pop()
+                .unwrap_or_else(|| String::with_capacity(128));
+            write(&mut buf);
+
+            let result = f(buf.as_bytes());
+            bufs.borrow_mut().push(buf);
+            result
+        })
+    }
+}
+
+/// Document and attachment storage of a data region (a storage root on its own volume).
+#[derive(Clone)]
+pub struct Region {
+    /// Project trees of the projects mapped to the region
+    pub app: Arc<Db>,
+}
+
+impl Region {
+    /// Open (or create) a region rooted at `path`.
+    fn open(path: &str) -> StorageResult<Self> {
+        Ok(Self {
+            app: Arc::new(open_db(path)?),
+        })
+    }
+}
+
+/// Open (or create) a sled database at `path`.
+fn open_db(path: &str) -> StorageResult<Db> {
+    fs::create_dir_all(path)
+        .map_err(|e| StorageError::Other(format!("Failed to create {path}: {e}")))?;
+    ::sled::open(Path::new(path))
+        .map_err(|e| StorageError::Other(format!("Failed to open the database at {path}: {e}")))
+}
+
+/// Parse a `name=value,name=value` list from the environment.
+fn env_pairs(name: &str) -> Vec<(String, String)> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| {
+            let (k, v) = pair.split_once('=')?;
+            let (k, v) = (k.trim(), v.trim());
+            (!k.is_empty() && !v.is_empty()).then(|| (k.to_string(), v.to_string()))
+        })
+        .collect()
+}
+
+/// A broadcast topic and its delivery counters.
+pub struct Topic {
+    pub sender: Sender<String>,
+    /// Last time a message was broadcast (unix ms), 0 if never
+    pub last_broadcast: AtomicU64,
+    /// Messages broadcast since the topic was created
+    pub messages: AtomicU64,
+    /// Sequence number of the last change published on the topic
+    pub seq: AtomicU64,
+}
+
+impl Topic {
+    /// Create a topic without subscribers.
+    fn new() -> Self {
+        let (tx, _rx) = broadcast::channel(100);
+        Self {
+            sender: tx,
+            last_broadcast: AtomicU64::new(0),
+            messages: AtomicU64::new(0),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Send a message to every subscriber and record the broadcast.
+    fn send(&self, message: String) {
+        self.last_broadcast
+            .store(Utc::now().timestamp_millis() as u64, Ordering::Relaxed);
+        self.messages.fetch_add(1, Ordering::Relaxed);
+
+        // Ignore error if no active subscribers
+        let _ = self.sender.send(message);
+    }
+}
+
+/// A live query: the documents of a collection matching a condition, kept up to date at write time.
+/// Subscribers receive `add`, `update` and `remove` deltas as documents enter, change in or leave the result set.
+pub struct LiveQuery {
+    /// Topic as subscribed by clients, e.g. `query:orders where status == "flagged"`
+    pub name: String,
+    pub project_id: String,
+    pub collection: String,
+    /// Filter on the document fields, `None` to match every document
+    pub condition: Option<Condition>,
+    /// Ids of the documents currently in the result set
+    members: Mutex<HashSet<String>>,
+    pub topic: Topic,
+}
+
+impl LiveQuery {
+    /// Parse a query topic: `query:{collection}`, optionally followed by `where {condition}`.
+    fn parse(project_id: &str, name: &str) -> Result<Self, String> {
+        let query = name
+            .strip_prefix("query:")
+            .ok_or_else(|| format!("Invalid query topic: {name}"))?;
+
+        let (collection, condition) = match query.split_once(" where ") {
+            Some((collection, condition)) => {
+                (collection.trim(), Some(DslParser::parse_condition(condition)?))
+            }
+            None => (query.trim(), None),
+        };
+        Name::internal("collection name", collection).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            name: name.to_string(),
+            project_id: project_id.to_string(),
+            collection: collection.to_string(),
+            condition,
+            members: Mutex::new(HashSet::new()),
+            topic: Topic::new(),
+        })
+    }
+
+    /// Whether a document belongs to the result set.
+    /// Conditions apply to the fields of the document data and to `id`. Nested values are read
+    /// with dot-paths, with or without the `data.` prefix (`meta.tier`, `data.meta.tier`).
+    fn matches(&self, doc: &Document) -> bool {
+        let Some(condition) = &self.condition else {
+            return true;
+        };
+
+        let mut fields: HashMap<String, Value> = doc
+            .data
+            .as_object()
+            .map(|data| data.clone().into_iter().collect())
+            .unwrap_or_default();
+        fields.insert("id".to_string(), Value::String(doc.id.clone()));
+        fields
+            .entry("data".to_string())
+            .or_insert_with(|| doc.data.clone());
+
+        let doc = EventData {
+            event_name: self.collection.clone(),
+            fields,
+            raw: None,
+            scores: HashMap::new(),
+            trace: None,
+            namespace: Namespace::Live,
+            block: None,
+            block_number: None,
+        };
+        DslExecutor::evaluate_condition(condition, &doc)
+    }
+
+    /// Apply a change to the result set and broadcast the resulting delta, if any.
+    fn apply(&self, change: &WsPayload) {
+        let is_member = change.op != "delete" && self.matches(&change.doc);
+        let was_member = {
+            let mut members = self.members.lock().unwrap_or_else(|e| e.into_inner());
+            if is_member {
+                !members.insert(change.doc_id.clone())
+            } else {
+                members.remove(&change.doc_id)
+            }
+        };
+
+        let op = match (was_member, is_member) {
+            (false, true) => "add",
+            (true, true) => "update",
+            (true, false) => "remove",
+            (false, false) => return,
+        };
+
+        let delta = WsPayload {
+            op: op.to_string(),
+            topic: self.name.clone(),
+            seq: self.topic.seq.fetch_add(1, Ordering::Relaxed) + 1,
+            ..change.clone()
+        };
+        if let Ok(json_string) = serde_json::to_string(&delta) {
+            self.topic.send(json_string);
+        }
+    }
+}
+
+/// A member of a presence channel (one WebSocket connection).
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PresenceMember {
+    /// Connection id of the member
+    pub id: String,
+    /// Client-provided metadata (e.g. user name, trigger being edited)
+    pub meta: Value,
+    /// When the member joined (unix ms)
+    pub joined_at: u64,
+    /// Last join or heartbeat (unix ms)
+    pub last_seen: u64,
+}
+
+/// Live statistics of a topic.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TopicStats {
+    pub topic: String,
+    /// Receivers currently subscribed
+    pub subscribers: usize,
+    /// Last time a message was broadcast (unix ms), 0 if never
+    pub last_broadcast: u64,
+    /// Messages broadcast since the topic was created
+    pub messages: u64,
+}
+
+/// Subscription metrics.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SubscriptionStats {
+    /// Live topics
+    pub topics: usize,
+    /// Receivers across all topics
+    pub subscribers: usize,
+    /// Topics removed because nobody listened to them anymore
+    pub pruned: u64,
+}
+
+/// Split text into lowercase search tokens (runs of letters, digits and underscores).
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Return the distinct search tokens of a trigger: its id, DSL, description and tags.
+fn trigger_tokens(trigger: &Trigger) -> std::collections::BTreeSet<String> {
+    let text = format!(
+        "{} {} {} {}",
+        trigger.id,
+        trigger.dsl,
+        trigger.description,
+        trigger.tags.join(" ")
+    );
+    tokenize(&text).into_iter().collect()
+}
+
+/// Default number of seconds between two collections of empty topics.
+const DEFAULT_TOPIC_GC_INTERVAL_SECS: u64 = 60;
+
+/// Default number of seconds without heartbeat after which a presence member is dropped.
+const DEFAULT_PRESENCE_TIMEOUT_SECS: u64 = 30;
+
 /// Subscriptions to track topics and help broadcast database changes to clients.
 #[derive(Clone, Default)]
 pub struct DbSubscriptions {
-    pub topics: Arc<RwLock<HashMap<String, Sender<String>>>>,
+    pub topics: Arc<RwLock<HashMap<String, Topic>>>,
+    /// Live queries, keyed by `{project_id}:{topic}`
+    pub queries: Arc<RwLock<HashMap<String, LiveQuery>>>,
+    /// Members of presence channels, keyed by topic (`presence:{project_id}:{channel}`)
+    pub presence: Arc<RwLock<HashMap<String, HashMap<String, PresenceMember>>>>,
+    /// Topics pruned since startup
+    pruned: Arc<AtomicU64>,
 }
 
 impl DbSubscriptions {
@@ -49,44 +448,255 @@ impl DbSubscriptions {
 
 // Implement DbSubscription
 impl DbSubscriptions {
-    /// Publish a message to all subscribers of a topic.
-    async fn publish(&self, collection: &str, doc_id: &str, mut json: WsPayload) {
+    /// Publish a document change to the subscribers of its collection and document topics.
+    async fn publish(&self, mut json: WsPayload) {
         let topics = self.topics.read().await;
-        // Collection subscribers
-        let key = format!("collection:{collection}:change");
-        if let Some(sender) = topics.get(&key) {
-            // Assign topic
-            json.topic = key;
-            if let Ok(json_string) = serde_json::to_string(&json) {
-                // Ignore error if no active subscribers
-                let _ = sender.send(json_string);
-            }
+        let mut keys = vec![
+            // Collection subscribers
+            format!("collection:{}:change", json.collection),
+            // Document subscribers
+            format!("document:{}:{}:change", json.collection, json.doc_id),
+        ];
+
+        // Subscribers of state changes only
+        if json.transition.is_some() {
+            keys.push(format!("collection:{}:transition", json.collection));
         }
 
-        // Document subscribers
-        let key = format!("document:{collection}:{doc_id}:change");
-        if let Some(sender) = topics.get(&key) {
-            // Assign topic
-            json.topic = key;
-            if let Ok(json_string) = serde_json::to_string(&json) {
-                // Ignore error if no active subscribers
-                let _ = sender.send(json_string);
+        for key in keys {
+            if let Some(topic) = topics.get(&key) {
+                // Assign topic and its next sequence number
+                json.topic = key;
+                json.seq = topic.seq.fetch_add(1, Ordering::Relaxed) + 1;
+                if let Ok(json_string) = serde_json::to_string(&json) {
+                    topic.send(json_string);
+                }
             }
         }
+
+        // Live queries over the collection
+        let queries = self.queries.read().await;
+        for query in queries
+            .values()
+            .filter(|q| q.project_id == json.project_id && q.collection == json.collection)
+        {
+            query.apply(&json);
+        }
+    }
+
+    /// Broadcast a raw message on a topic, if anyone is listening.
+    pub async fn broadcast(&self, topic: &str, message: String) {
+        let topics = self.topics.read().await;
+        if let Some(topic) = topics.get(topic) {
+            topic.send(message);
+        }
     }
 
     /// Subscribe to a topic (doc_id or collection).
     /// Creates the topic if it doesn't exist yet.
     pub async fn subscribe(&self, topic: &str) -> Receiver<String> {
+        tenancy::check_topic(topic);
+
         let mut topics = self.topics.write().await;
 
         // Get or insert the broadcast channel
-        let sender = topics.entry(topic.to_string()).or_insert_with(|| {
-            let (tx, _rx) = broadcast::channel(100);
-            tx
+        let topic = topics.entry(topic.to_string()).or_insert_with(Topic::new);
+
+        topic.sender.subscribe()
+    }
+
+    /// Join the presence channel of a project and return the receiver of its events with the current members.
+    /// Members are announced with a `join` event, and leave with a `leave` event.
+    pub async fn join_presence(
+        &self,
+        project_id: &str,
+        channel: &str,
+        member_id: &str,
+        meta: Value,
+    ) -> (Receiver<String>, Vec<PresenceMember>) {
+        let key = format!("presence:{project_id}:{channel}");
+        let rx = self.subscribe(&key).await;
+
+        let now = Utc::now().timestamp_millis() as u64;
+        let member = PresenceMember {
+            id: member_id.to_string(),
+            meta,
+            joined_at: now,
+            last_seen: now,
+        };
+
+        let members = {
+            let mut presence = self.presence.write().await;
+            let members = presence.entry(key.clone()).or_default();
+            members.insert(member_id.to_string(), member.clone());
+            members.values().cloned().collect()
+        };
+
+        self.presence_event(&key, channel, "join", &member).await;
+        (rx, members)
+    }
+
+    /// Record a heartbeat of a member, updating its metadata if given.
+    /// Returns `false` if the member is not in the channel (e.g. it timed out).
+    pub async fn presence_heartbeat(
+        &self,
+        project_id: &str,
+        channel: &str,
+        member_id: &str,
+        meta: Option<Value>,
+    ) -> bool {
+        let key = format!("presence:{project_id}:{channel}");
+
+        let updated = {
+            let mut presence = self.presence.write().await;
+            let Some(member) = presence.get_mut(&key).and_then(|m| m.get_mut(member_id)) else {
+                return false;
+            };
+            member.last_seen = Utc::now().timestamp_millis() as u64;
+            meta.map(|meta| {
+                member.meta = meta;
+                member.clone()
+            })
+        };
+
+        // Metadata changes are broadcast, plain heartbeats are not
+        if let Some(member) = updated {
+            self.presence_event(&key, channel, "update", &member).await;
+        }
+        true
+    }
+
+    /// Leave a presence channel.
+    pub async fn leave_presence(&self, project_id: &str, channel: &str, member_id: &str) {
+        let key = format!("presence:{project_id}:{channel}");
+
+        let member = {
+            let mut presence = self.presence.write().await;
+            let member = presence.get_mut(&key).and_then(|m| m.remove(member_id));
+            presence.retain(|_, members| !members.is_empty());
+            member
+        };
+
+        if let Some(member) = member {
+            self.presence_event(&key, channel, "leave", &member).await;
+        }
+    }
+
+    /// Drop the members that missed their heartbeats for longer than the timeout.
+    async fn expire_presence(&self, timeout_secs: u64) {
+        let deadline = (Utc::now().timestamp_millis() as u64).saturating_sub(timeout_secs * 1000);
+
+        let expired: Vec<(String, PresenceMember)> = {
+            let mut presence = self.presence.write().await;
+            let mut expired = Vec::new();
+            for (key, members) in presence.iter_mut() {
+                members.retain(|_, member| {
+                    let alive = member.last_seen >= deadline;
+                    if !alive {
+                        expired.push((key.clone(), member.clone()));
+                    }
+                    alive
+                });
+            }
+            presence.retain(|_, members| !members.is_empty());
+            expired
+        };
+
+        for (key, member) in expired {
+            // Keys are `presence:{project_id}:{channel}`
+            let channel = key.splitn(3, ':').nth(2).unwrap_or_default().to_string();
+            self.presence_event(&key, &channel, "leave", &member).await;
+        }
+    }
+
+    /// Broadcast a presence event to the members of a channel.
+    async fn presence_event(&self, key: &str, channel: &str, op: &str, member: &PresenceMember) {
+        let message = serde_json::json!({
+            "op": op,
+            "topic": format!("presence:{channel}"),
+            "member": member
         });
+        self.broadcast(key, message.to_string()).await;
+    }
+
+    /// Remove topics that no receiver listens to anymore. Returns how many were removed.
+    /// Receivers are counted by the channel itself, so a topic is empty once the last
+    /// connection subscribed to it unsubscribes or closes.
+    pub async fn prune(&self) -> usize {
+        let mut topics = self.topics.write().await;
+        let before = topics.len();
+        topics.retain(|_, topic| topic.sender.receiver_count() > 0);
+
+        let mut queries = self.queries.write().await;
+        let before = before + queries.len();
+        queries.retain(|_, query| query.topic.sender.receiver_count() > 0);
+
+        let pruned = before - topics.len() - queries.len();
+        self.pruned.fetch_add(pruned as u64, Ordering::Relaxed);
+        pruned
+    }
+
+    /// Periodically prune empty topics so memory stays flat on long-running instances,
+    /// and drop presence members without heartbeat for `TRIGGR_PRESENCE_TIMEOUT_SECS`.
+    pub async fn collect_garbage(self) {
+        let interval_secs = env::var("TRIGGR_TOPIC_GC_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_TOPIC_GC_INTERVAL_SECS);
+        let presence_timeout_secs = env::var("TRIGGR_PRESENCE_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_PRESENCE_TIMEOUT_SECS);
+
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+
+            self.expire_presence(presence_timeout_secs).await;
+            let pruned = self.prune().await;
+            if pruned > 0 {
+                tracing::info!("Pruned {pruned} empty subscription topic(s)");
+            }
+        }
+    }
+
+    /// Return subscription metrics.
+    pub async fn stats(&self) -> SubscriptionStats {
+        let topics = self.topics.read().await;
+        let queries = self.queries.read().await;
+
+        SubscriptionStats {
+            topics: topics.len() + queries.len(),
+            subscribers: topics
+                .values()
+                .chain(queries.values().map(|q| &q.topic))
+                .map(|t| t.sender.receiver_count())
+                .sum(),
+            pruned: self.pruned.load(Ordering::Relaxed),
+        }
+    }
 
-        sender.subscribe()
+    /// Return statistics of the topics of a collection (the collection topic and its documents').
+    pub async fn collection_topics(&self, collection: &str) -> Vec<TopicStats> {
+        let collection_topic = format!("collection:{collection}:change");
+        let document_prefix = format!("document:{collection}:");
+
+        let topics = self.topics.read().await;
+        let mut stats: Vec<TopicStats> = topics
+            .iter()
+            .filter(|(name, _)| **name == collection_topic || name.starts_with(&document_prefix))
+            .map(|(name, topic)| TopicStats {
+                topic: name.clone(),
+                subscribers: topic.sender.receiver_count(),
+                last_broadcast: topic.last_broadcast.load(Ordering::Relaxed),
+                messages: topic.messages.load(Ordering::Relaxed),
+            })
+            .collect();
+        stats.sort_by(|a, b| a.topic.cmp(&b.topic));
+
+        stats
     }
 }
 
@@ -96,11 +706,15 @@ impl DbSubscriptions {
 /// - `projects`: for storing projects belonging to a user
 /// - `app`: for storing document data (kv data)
 /// - 'users`: for storing user data
+///
+/// Document data of a project lives in its data region when one is mapped
+/// (`TRIGGR_PROJECT_REGIONS`), and in the default `app` store otherwise.
 #[derive(Clone)]
 pub struct Sled {
     /// Project store
     pub projects: Arc<Db>,
-    /// App data store containing documents and collections
+    /// App data store, holding one tree per project (`project::{project_id}`) with its
+    /// documents, collection stats and attachments
     pub app: Arc<Db>,
     /// Users store
     pub users: Arc<Db>,
@@ -108,13 +722,38 @@ pub struct Sled {
     pub metadata: Arc<Db>,
     /// Trigger store
     pub triggers: Arc<Db>,
+    /// Search index of triggers (`{project_id}::{token}::{contract_addr}::{trigger_id}`)
+    pub trigger_index: sled::Tree,
+    /// Last run time of the triggers (`{contract_addr}::{trigger_id}`), kept apart from the
+    /// triggers so runs don't rewrite them
+    pub last_runs: sled::Tree,
+    /// Decoded events waiting for the trigger engine, keyed by arrival order
+    pub events: sled::Tree,
+    /// Queued events that could not be read, set aside as they were so the queue moves on
+    pub quarantined_events: sled::Tree,
+    /// Decoded events of the contracts, in chain order (`{contract_addr}::{block}::{seq}`)
+    pub event_log: sled::Tree,
+    /// Instance settings (e.g. the maintenance switch, consumer offsets)
+    pub settings: sled::Tree,
+    /// Format documents are written in
+    pub doc_codec: Codec,
+    /// Format triggers are written in
+    pub trigger_codec: Codec,
+    /// Whether corrupted records are replaced on write instead of refusing the write
+    pub overwrite_corrupted: bool,
+    /// When writes to the projects, users, metadata and trigger databases are flushed
+    pub flush: Arc<FlushPolicy>,
+    /// Data regions by name
+    pub regions: Arc<HashMap<String, Region>>,
+    /// Region of each mapped project
+    pub project_regions: Arc<HashMap<String, String>>,
     /// Subscription mechanism
     pub subscriptions: DbSubscriptions,
 }
 
 impl Sled {
     /// Initialize the Sled store at the default paths.
-    pub fn new() -> Self {
+    pub fn new() -> StorageResult<Self> {
         let projects_path = std::env::var("TRIGGR_DB_PATH_PROJECTS")
             .unwrap_or_else(|_| DEFAULT_DB_PATH_PROJECTS.to_string());
         let app_path =
@@ -126,108 +765,2041 @@ impl Sled {
         let trigger_path = std::env::var("TRIGGR_TRIGGER_PATH_METADATA")
             .unwrap_or_else(|_| DEFAULT_TRIGGER_PATH_METADATA.to_string());
 
-        // Open or create storage directory
-        fs::create_dir_all(&projects_path).expect(&format!("Failed to create {}", projects_path));
-        fs::create_dir_all(&app_path).expect(&format!("Failed to create {}", app_path));
-        fs::create_dir_all(&users_path).expect(&format!("Failed to create {}", users_path));
-        fs::create_dir_all(&meta_path).expect(&format!("Failed to create {}", meta_path));
-        fs::create_dir_all(&trigger_path).expect(&format!("Failed to create {}", trigger_path));
+        Self::open(&projects_path, &app_path, &users_path, &meta_path, &trigger_path)
+    }
+
+    /// Initialize the Sled store with every database under a single root directory.
+    pub fn at(root: &Path) -> StorageResult<Self> {
+        let path = |name: &str| root.join(name).to_string_lossy().to_string();
+
+        Self::open(
+            &path("projects"),
+            &path("app"),
+            &path("users"),
+            &path("metadata"),
+            &path("triggers"),
+        )
+    }
 
+    /// Open (or create) the databases at the given paths.
+    fn open(
+        projects_path: &str,
+        app_path: &str,
+        users_path: &str,
+        meta_path: &str,
+        trigger_path: &str,
+    ) -> StorageResult<Self> {
         // Initialize database
-        let projects_db =
-            ::sled::open(Path::new(&projects_path)).expect("Failed to open sled database");
-        let app_db = ::sled::open(Path::new(&app_path)).expect("Failed to open sled database");
-        let users_db = ::sled::open(Path::new(&users_path)).expect("Failed to open sled database");
-        let meta_db = ::sled::open(Path::new(&meta_path)).expect("Failed to open sled database");
-        let trigger_db =
-            ::sled::open(Path::new(&trigger_path)).expect("Failed to open sled database");
-
-        Self {
-            projects: Arc::new(projects_db),
+        let projects_db = open_db(projects_path)?;
+        let app_db = open_db(app_path)?;
+        let users_db = open_db(users_path)?;
+        let meta_db = open_db(meta_path)?;
+        let trigger_db = open_db(trigger_path)?;
+        let trigger_index = trigger_db.open_tree("index")?;
+        let last_runs = trigger_db.open_tree("last_runs")?;
+        let events = trigger_db.open_tree("events")?;
+        let quarantined_events = trigger_db.open_tree("quarantined_events")?;
+        let event_log = trigger_db.open_tree("event_log")?;
+        let settings = trigger_db.open_tree("settings")?;
+
+        // Open data regions (e.g. `nvme=/mnt/nvme/triggr,hdd=/mnt/hdd/triggr`)
+        let regions = env_pairs("TRIGGR_DATA_REGIONS")
+            .into_iter()
+            .map(|(name, path)| Ok((name, Region::open(&path)?)))
+            .collect::<StorageResult<HashMap<_, _>>>()?;
+
+        // Map projects to regions (e.g. `<project_id>=nvme`)
+        let project_regions = env_pairs("TRIGGR_PROJECT_REGIONS")
+            .into_iter()
+            .filter(|(project, region)| {
+                let known = regions.contains_key(region);
+                if !known {
+                    tracing::warn!("Project {project} is mapped to unknown data region '{region}'");
+                }
+                known
+            })
+            .collect::<HashMap<_, _>>();
+
+        let projects = Arc::new(projects_db);
+        let users = Arc::new(users_db);
+        let metadata = Arc::new(meta_db);
+        let triggers = Arc::new(trigger_db);
+        let flush = FlushPolicy::from_env(&projects, &users, &metadata, &triggers);
+
+        let store = Self {
+            projects,
             app: Arc::new(app_db),
-            users: Arc::new(users_db),
-            metadata: Arc::new(meta_db),
-            triggers: Arc::new(trigger_db),
+            users,
+            metadata,
+            triggers,
+            trigger_index,
+            last_runs,
+            events,
+            quarantined_events,
+            event_log,
+            settings,
+            doc_codec: Codec::from_env("documents"),
+            trigger_codec: Codec::from_env("triggers"),
+            overwrite_corrupted: env::var("TRIGGR_OVERWRITE_CORRUPTED")
+                .is_ok_and(|v| matches!(v.to_lowercase().as_str(), "1" | "true")),
+            flush: Arc::new(flush),
+            regions: Arc::new(regions),
+            project_regions: Arc::new(project_regions),
             subscriptions: DbSubscriptions::default(),
+        };
+
+        // Projects must not lose sight of their data
+        store.check_regions()?;
+        store.check_api_key_salt()?;
+
+        // Index triggers saved before search existed
+        let reindexed = match store.trigger_index.is_empty() {
+            true => store.reindex_triggers(),
+            false => Ok(()),
+        };
+        if let Err(e) = reindexed {
+            tracing::warn!("Failed to index triggers: {e}");
         }
+
+        Ok(store)
     }
 
-    /// Helper function that receives a user ID and stores the API keys
-    /// of projects associated with it.
-    pub fn add_user_project(&self, user_id: &str, project: Project) -> StorageResult<()> {
-        let mut projects: Vec<Project> = match self.users.get(user_id)? {
-            Some(value) => {
-                // Try to deserialize, fallback to empty vec if corrupted
-                serde_json::from_slice(&value).unwrap_or_else(|_| Vec::new())
+    /// Refuse to move a project to another data region (or out of one), as its data would stay
+    /// behind, hidden. Project trees (`project::{id}`) must be in the database of their region.
+    fn check_regions(&self) -> StorageResult<()> {
+        let stores = std::iter::once((None, &*self.app))
+            .chain(self.regions.iter().map(|(name, r)| (Some(name.as_str()), &*r.app)));
+
+        for (region, db) in stores {
+            for name in db.tree_names() {
+                let Some(data_id) = name.strip_prefix(b"project::") else {
+                    continue;
+                };
+                let data_id = String::from_utf8_lossy(data_id);
+                let project_id = namespace::project_of(&data_id);
+                let mapped = self.project_regions.get(project_id).map(String::as_str);
+                if mapped == region || db.open_tree(&name)?.is_empty() {
+                    continue;
+                }
+
+                return Err(StorageError::Other(format!(
+                    "Project {project_id} has data in region '{}' but is mapped to '{}' by \
+                    TRIGGR_PROJECT_REGIONS. Move its data or restore the mapping",
+                    region.unwrap_or("default"),
+                    mapped.unwrap_or("default"),
+                )));
             }
-            None => Vec::new(),
+        }
+
+        Ok(())
+    }
+
+    /// Refuse to serve projects indexed with another API key salt, as none of their keys would
+    /// be found anymore. The salt is recorded by its id the first time the store is opened.
+    fn check_api_key_salt(&self) -> StorageResult<()> {
+        // Keys can't be hashed at all without a salt
+        let Ok(salt_id) = api_key_salt_id() else {
+            return Ok(());
         };
 
-        // Avoid duplicates by checking project.id
-        if !projects.iter().any(|p| p.id == project.id) {
-            projects.push(project);
+        match self.settings.get("api_key_salt")? {
+            Some(stored) if stored.as_ref() == salt_id.as_bytes() => Ok(()),
+            Some(_) => Err(StorageError::Other(
+                "The API key salt (TRIGGR_API_KEY_SALT, or TRIGGR_ENCRYPTION_KEY when it is \
+                unset) changed since the projects were indexed. Restore it, or their API keys \
+                won't be found"
+                    .to_string(),
+            )),
+            None => {
+                self.settings.insert("api_key_salt", salt_id.as_bytes())?;
+                Ok(())
+            }
         }
+    }
 
-        let encoded = serde_json::to_vec(&projects)
-            .map_err(|e| format!("Failed to serialize projects: {}", e))?;
-        self.users.insert(user_id, encoded)?;
+    /// Add the search tokens of a trigger to the index.
+    fn index_trigger(&self, contract_addr: &str, trigger: &Trigger) -> StorageResult<()> {
+        let mut batch = sled::Batch::default();
+        for token in trigger_tokens(trigger) {
+            let key = format!(
+                "{}::{token}::{contract_addr}::{}",
+                trigger.project_id, trigger.id
+            );
+            batch.insert(key.as_bytes(), &[]);
+        }
+        self.trigger_index.apply_batch(batch)?;
+        Ok(())
+    }
 
+    /// Remove the search tokens of a trigger from the index.
+    fn unindex_trigger(&self, contract_addr: &str, trigger: &Trigger) -> StorageResult<()> {
+        let mut batch = sled::Batch::default();
+        for token in trigger_tokens(trigger) {
+            let key = format!(
+                "{}::{token}::{contract_addr}::{}",
+                trigger.project_id, trigger.id
+            );
+            batch.remove(key.as_bytes());
+        }
+        self.trigger_index.apply_batch(batch)?;
         Ok(())
     }
 
-    /// Store or update unique (addr, path) entries under a single key ("HANNAH")
-    pub fn store_metadata_entry(&self, addr: &str, path: &str) -> StorageResult<()> {
-        const KEY: &str = "HANNAH";
+    /// Set the last run time of triggers of a contract from their recorded runs.
+    fn load_last_runs(&self, contract_addr: &str, triggers: &mut [Trigger]) -> StorageResult<()> {
+        for trigger in triggers {
+            if let Some(at) = self.last_runs.get(last_run_key(contract_addr, &trigger.id))? {
+                trigger.last_run = trigger.last_run.max(decode_last_run(&at));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuild the search index from every stored trigger.
+    pub fn reindex_triggers(&self) -> StorageResult<()> {
+        self.trigger_index.clear()?;
+        for item in self.triggers.iter() {
+            let (contract_addr, bytes) = item?;
+            let contract_addr = String::from_utf8_lossy(&contract_addr).to_string();
+            let triggers: Vec<Trigger> = Codec::decode(&bytes).unwrap_or_default();
+            for trigger in &triggers {
+                self.index_trigger(&contract_addr, trigger)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the instance is in maintenance.
+    pub fn maintenance(&self) -> bool {
+        matches!(self.settings.get("maintenance"), Ok(Some(v)) if v.as_ref() == [1])
+    }
+
+    /// Persist the maintenance switch.
+    pub fn set_maintenance(&self, active: bool) -> StorageResult<()> {
+        self.settings.insert("maintenance", &[active as u8])?;
+        self.settings.flush()?;
+        Ok(())
+    }
+
+    /// Decode a stored record that is about to be read or rewritten.
+    /// A corrupted record is an error, unless `TRIGGR_OVERWRITE_CORRUPTED` allows starting it over.
+    fn decode_stored<T: DeserializeOwned + Default>(
+        &self,
+        what: &str,
+        key: &[u8],
+        bytes: &[u8],
+    ) -> StorageResult<T> {
+        match Codec::decode(bytes) {
+            Ok(value) => Ok(value),
+            Err(e) if self.overwrite_corrupted => {
+                tracing::warn!(
+                    "Starting over corrupted {what} {}: {e}",
+                    String::from_utf8_lossy(key)
+                );
+                Ok(T::default())
+            }
+            Err(e) => Err(StorageError::Corrupted(format!(
+                "{what} {}: {e}. Quarantine it through /api/admin/integrity/quarantine",
+                String::from_utf8_lossy(key)
+            ))),
+        }
+    }
+
+    /// Return the schema version of a database, 0 if it was never migrated.
+    pub fn schema_version(&self, db: &str) -> u32 {
+        match self.settings.get(format!("schema:{db}")) {
+            Ok(Some(v)) => u32::from_be_bytes(v.as_ref().try_into().unwrap_or_default()),
+            _ => 0,
+        }
+    }
+
+    /// Persist the schema version of a database.
+    pub fn set_schema_version(&self, db: &str, version: u32) -> StorageResult<()> {
+        self.settings
+            .insert(format!("schema:{db}"), &version.to_be_bytes())?;
+        self.settings.flush()?;
+        Ok(())
+    }
+
+    /// Return a database by name (`projects`, `app`, `users`, `metadata` or `triggers`).
+    pub fn database(&self, name: &str) -> Option<&Db> {
+        match name {
+            "projects" => Some(&self.projects),
+            "app" => Some(&self.app),
+            "users" => Some(&self.users),
+            "metadata" => Some(&self.metadata),
+            "triggers" => Some(&self.triggers),
+            _ => None,
+        }
+    }
+
+    /// Append an event to the event queue and return its sequence number.
+    /// The event is flushed to disk before returning.
+    pub fn push_event(&self, contract_addr: &str, event: &EventData) -> StorageResult<u64> {
+        // Ids increase monotonically, so big-endian keys keep arrival order
+        let seq = self.triggers.generate_id()?;
+        let value = serde_json::to_vec(&(contract_addr, event))?;
+        self.events.insert(seq.to_be_bytes(), value)?;
+        self.events.flush()?;
+        Ok(seq)
+    }
+
+    /// Return the first queued event at or after position `from`, with its position.
+    /// An event that can't be read comes with its error, to be quarantined.
+    pub fn next_event(&self, from: u64) -> StorageResult<Option<QueuedEvent>> {
+        match self.events.range(from.to_be_bytes()..).next() {
+            Some(entry) => {
+                let (key, value) = entry?;
+                let seq = u64::from_be_bytes(key.as_ref().try_into().unwrap_or_default());
+                Ok(Some((seq, serde_json::from_slice(&value))))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Move a queued event, as it is, to the `quarantined_events` tree.
+    pub fn quarantine_event(&self, seq: u64) -> StorageResult<()> {
+        let key = seq.to_be_bytes();
+        if let Some(value) = self.events.get(key)? {
+            // Keep the event aside before it leaves the queue
+            self.quarantined_events.insert(key, value)?;
+            self.quarantined_events.flush()?;
+            self.events.remove(key)?;
+        }
+
+        Ok(())
+    }
+
+    /// Return the position a consumer resumes reading the event queue from.
+    pub fn event_offset(&self, consumer: &str) -> u64 {
+        match self.settings.get(format!("offset:{consumer}")) {
+            Ok(Some(v)) => u64::from_be_bytes(v.as_ref().try_into().unwrap_or_default()),
+            _ => 0,
+        }
+    }
+
+    /// Move a consumer's offset past a processed event.
+    /// The trigger engine is the only consumer, so the event leaves the queue.
+    pub fn commit_event(&self, consumer: &str, seq: u64) -> StorageResult<()> {
+        self.settings
+            .insert(format!("offset:{consumer}"), &(seq + 1).to_be_bytes())?;
+        self.events.remove(seq.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Record a decoded event in the event log of its contract, and drop the events of blocks
+    /// more than `retention` blocks older.
+    /// Key pattern: `{contract_addr}::{block_number}::{seq}` so events sort in chain order.
+    pub fn log_event(
+        &self,
+        contract_addr: &str,
+        block_number: u64,
+        seq: u64,
+        event: &EventData,
+        retention: u64,
+    ) -> StorageResult<()> {
+        let key = format!("{contract_addr}::{block_number:020}::{seq:020}");
+        self.event_log.insert(key.as_bytes(), serde_json::to_vec(event)?)?;
+
+        let start = format!("{contract_addr}::");
+        let end = format!("{contract_addr}::{:020}", block_number.saturating_sub(retention));
+        let mut batch = sled::Batch::default();
+        for item in self.event_log.range(start.as_bytes()..end.as_bytes()) {
+            let (key, _) = item?;
+            batch.remove(key);
+        }
+        self.event_log.apply_batch(batch)?;
+
+        Ok(())
+    }
+
+    /// Return up to `limit` logged events of a contract in chain order, from block `since_block`
+    /// on and after the `cursor` of a previous page, with the cursor of the next page if any.
+    pub fn logged_events(
+        &self,
+        contract_addr: &str,
+        since_block: u64,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> StorageResult<(Vec<EventData>, Option<String>)> {
+        let prefix = format!("{contract_addr}::");
+        let mut start = format!("{prefix}{since_block:020}");
+        if let Some(cursor) = cursor {
+            // Resume right after the last event of the previous page
+            start = start.max(format!("{prefix}{cursor}\0"));
+        }
+
+        let mut events = Vec::new();
+        let mut last = None;
+        for item in self.event_log.range(start.as_bytes()..).take(limit) {
+            let (key, value) = item?;
+            let Some(position) = key.strip_prefix(prefix.as_bytes()) else {
+                break;
+            };
+            events.push(serde_json::from_slice(&value)?);
+            last = Some(String::from_utf8_lossy(position).to_string());
+        }
+
+        // A full page may be followed by more events
+        let next = last.filter(|_| events.len() == limit);
+        Ok((events, next))
+    }
+
+    /// Return the data region of a project, if it is mapped to one.
+    /// The sandbox of a project lives in the region of the project.
+    fn region(&self, project_id: &str) -> Option<&Region> {
+        self.project_regions
+            .get(namespace::project_of(project_id))
+            .and_then(|name| self.regions.get(name))
+    }
+
+    /// Return the document store of a project.
+    fn app_db(&self, project_id: &str) -> &Db {
+        match self.region(project_id) {
+            Some(region) => &region.app,
+            None => &self.app,
+        }
+    }
+
+    /// Reserve the id of a new project for the project with key hash `index`.
+    /// Fails if another project has the id, as projects share trees (and triggers) by id.
+    fn reserve_project_id(&self, project_id: &str, index: &str) -> StorageResult<()> {
+        let taken = || StorageError::InvalidField {
+            field: "project_name".to_string(),
+            message: format!("Project {project_id} already exists"),
+        };
+
+        // Projects created before ids were reserved
+        if self.project_id_shared(project_id, index)? {
+            return Err(taken());
+        }
+
+        let key = format!("project_id:{project_id}");
+        if self
+            .settings
+            .compare_and_swap(key, None::<&[u8]>, Some(index.as_bytes()))?
+            .is_err()
+        {
+            return Err(taken());
+        }
+
+        Ok(())
+    }
+
+    /// Whether a project other than the one with key hash `index` has an id.
+    /// Ids are reserved on creation, but projects created before may share one.
+    fn project_id_shared(&self, project_id: &str, index: &str) -> StorageResult<bool> {
+        for item in self.projects.iter() {
+            let (key, value) = item?;
+            let project: Project = serde_json::from_slice(&value)?;
+            if project.id == project_id && key != index.as_bytes() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Release the id of a project, if the project with key hash `index` holds it.
+    fn release_project_id(&self, project_id: &str, index: &str) -> StorageResult<()> {
+        let key = format!("project_id:{project_id}");
+        let _ = self
+            .settings
+            .compare_and_swap(key, Some(index.as_bytes()), None::<&[u8]>)?;
+        Ok(())
+    }
+
+    /// Return the tree of a project, holding its documents, collection stats and attachments.
+    fn project_tree(&self, project_id: &str) -> StorageResult<sled::Tree> {
+        let name = format!("project::{project_id}");
+        tenancy::check_key(&name);
+        Ok(self.app_db(project_id).open_tree(name)?)
+    }
+
+    /// Return the run log of a project.
+    fn runs_tree(&self, project_id: &str) -> StorageResult<sled::Tree> {
+        let name = format!("runs::{project_id}");
+        tenancy::check_key(&name);
+        Ok(self.triggers.open_tree(name)?)
+    }
+
+    /// Drop the trees of the deleted projects, with their documents, attachments and run logs.
+    /// Dropping a tree takes time proportional to its size, so this runs as a background job.
+    /// Returns the number of projects purged.
+    pub fn purge_projects(&self) -> StorageResult<usize> {
+        let mut purged = 0;
+
+        for key in self.settings.scan_prefix(b"purge:").keys() {
+            let key = key?;
+            let project_id = String::from_utf8_lossy(&key["purge:".len()..]).to_string();
+
+            for namespace in [Namespace::Live, Namespace::Sandbox] {
+                self.app_db(&project_id)
+                    .drop_tree(format!("project::{}", namespace.scope(&project_id)))?;
+            }
+            self.triggers.drop_tree(format!("runs::{project_id}"))?;
+
+            self.settings.remove(&key)?;
+            purged += 1;
+        }
+
+        self.settings.flush()?;
+        Ok(purged)
+    }
+
+    /// Purge the deleted projects on a blocking thread.
+    pub fn spawn_purge(self: &Arc<Self>) {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || match store.purge_projects() {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!("Purged the data of {purged} deleted project(s)"),
+            Err(e) => tracing::warn!("Failed to purge deleted projects: {e}"),
+        });
+    }
+
+    /// Iterate over the documents of a collection as raw JSON, in key order.
+    /// JSON documents are not decoded, so collections of any size can be streamed.
+    pub fn scan_documents(
+        &self,
+        project_id: &str,
+        collection: &str,
+    ) -> StorageResult<impl Iterator<Item = StorageResult<IVec>> + Send + 'static> {
+        let tree = self.project_tree(project_id)?;
+        Ok(KeyBuf::collection(collection, |prefix| tree.scan_prefix(prefix))
+            .values()
+            .map(|value| Codec::to_json(value?)))
+    }
+
+    /// Return the entity tag of the document list of a collection, computed without holding the list.
+    /// It changes whenever a document is added, removed or changed.
+    pub fn list_etag(&self, project_id: &str, collection: &str) -> StorageResult<String> {
+        let mut hasher = Blake2b512::new();
+
+        for value in self.scan_documents(project_id, collection)? {
+            let value = value?;
+            let stamp: DocStamp = Codec::decode(&value)?;
+
+            // Documents written before content hashes were stored
+            let hash = match stamp.metadata.hash {
+                Some(hash) => hash,
+                None => content_hash(&Codec::decode::<Document>(&value)?.data),
+            };
+
+            hasher.update(stamp.id.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(hash.as_bytes());
+            hasher.update(b"\n");
+        }
+
+        Ok(format!("W/\"{}\"", hex::encode(&hasher.finalize()[..16])))
+    }
+
+    /// Subscribe to a live query (e.g. `query:orders where status == "flagged"`) and return
+    /// its current result set. Writes wait while the result set is read, so no change is missed.
+    pub async fn subscribe_query(
+        &self,
+        project_id: &str,
+        name: &str,
+    ) -> StorageResult<(Receiver<String>, Vec<Document>)> {
+        let key = format!("{project_id}:{name}");
+        tenancy::check_topic(&key);
+
+        let mut queries = self.subscriptions.queries.write().await;
+        let query = match queries.entry(key) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(LiveQuery::parse(project_id, name)?)
+            }
+        };
+
+        let docs: Vec<Document> = <Self as DocumentStore>::list(self, project_id, &query.collection)?
+            .into_iter()
+            .filter(|doc| query.matches(doc))
+            .collect();
+
+        // Result set of a new query (or refreshed for an existing one)
+        *query.members.lock().unwrap_or_else(|e| e.into_inner()) =
+            docs.iter().map(|doc| doc.id.clone()).collect();
+
+        Ok((query.topic.sender.subscribe(), docs))
+    }
+
+    /// Run a query once, in the syntax of live queries (`query:{collection} where {condition}`).
+    /// Returns the matching documents, without subscribing to their changes.
+    pub fn query_documents(&self, project_id: &str, name: &str) -> StorageResult<Vec<Document>> {
+        let query = LiveQuery::parse(project_id, name)?;
+
+        Ok(<Self as DocumentStore>::list(self, project_id, &query.collection)?
+            .into_iter()
+            .filter(|doc| query.matches(doc))
+            .collect())
+    }
+
+    /// Helper function that receives a user ID and stores the API keys
+    /// of projects associated with it.
+    pub fn add_user_project(&self, user_id: &str, project: Project) -> StorageResult<()> {
+        let mut projects: Vec<Project> = match self.users.get(user_id)? {
+            Some(value) => self.decode_stored("user projects", user_id.as_bytes(), &value)?,
+            None => Vec::new(),
+        };
+
+        // Avoid duplicates by checking project.id
+        if !projects.iter().any(|p| p.id == project.id) {
+            projects.push(project);
+        }
+
+        let encoded = serde_json::to_vec(&projects)
+            .map_err(|e| format!("Failed to serialize projects: {}", e))?;
+        self.users.insert(user_id, encoded)?;
+        self.flush.users.written()?;
+
+        Ok(())
+    }
+
+    /// Re-index projects stored under a plaintext API key by the hash of the key.
+    /// Returns the number of projects migrated, or to migrate on a dry run.
+    pub fn migrate_project_keys(&self, dry_run: bool) -> StorageResult<usize> {
+        let mut migrated = 0;
+
+        for entry in self.projects.iter() {
+            let (key, value) = entry?;
+            if key.len() == API_KEY_HASH_LEN {
+                continue;
+            }
+            if dry_run {
+                migrated += 1;
+                continue;
+            }
+
+            let plain = String::from_utf8(key.to_vec())?;
+            self.projects
+                .insert(hash_api_key(&plain)?.as_bytes(), value)?;
+            self.projects.remove(&key)?;
+            migrated += 1;
+        }
+
+        self.flush.projects.sync()?;
+        Ok(migrated)
+    }
+
+    /// Return every project in the database.
+    pub fn all_projects(&self) -> StorageResult<Vec<Project>> {
+        let mut projects = Vec::new();
+        for entry in self.projects.iter() {
+            let (_, v) = entry?;
+            if let Ok(project) = serde_json::from_slice::<Project>(&v) {
+                projects.push(project);
+            }
+        }
+
+        Ok(projects)
+    }
+
+    /// Store or update unique (addr, path) entries under a single key ("HANNAH")
+    pub fn store_metadata_entry(&self, addr: &str, path: &str) -> StorageResult<()> {
+        const KEY: &str = "HANNAH";
+
+        // Fetch existing entries (or start with an empty vector)
+        let mut entries: Vec<Metadata> = match self.metadata.get(KEY)? {
+            Some(bytes) => self.decode_stored("metadata entries", KEY.as_bytes(), &bytes)?,
+            None => vec![],
+        };
+
+        // Check if an entry with the same addr already exists
+        if !entries.iter().any(|e| e.addr == addr) {
+            entries.push(Metadata {
+                addr: addr.to_string(),
+                path: path.to_string(),
+            });
+        }
+
+        // Serialize updated entries
+        let bytes = serde_json::to_vec(&entries)
+            .map_err(|e| format!("Failed to serialize entries: {}", e))?;
+
+        self.metadata.insert(KEY, bytes)?;
+        self.flush.metadata.written()?;
+
+        Ok(())
+    }
+
+    /// Build the key prefix for all attachments of a document in its project tree.
+    /// Pattern: `attachment::{collection}::{doc_id}::`
+    fn attachment_prefix(collection: &str, doc_id: &str) -> String {
+        format!("attachment::{collection}::{doc_id}::")
+    }
+
+    /// Store a binary attachment for a document, replacing any previous one with the same key.
+    pub fn put_attachment(
+        &self,
+        project_id: &str,
+        collection: &str,
+        doc_id: &str,
+        info: AttachmentInfo,
+        bytes: &[u8],
+    ) -> StorageResult<()> {
+        Name::internal("attachment key", &info.key)?;
+
+        let prefix = Self::attachment_prefix(collection, doc_id);
+        let info_bytes = serde_json::to_vec(&info)?;
+
+        // Data and info are written together so they can't drift apart
+        let mut batch = sled::Batch::default();
+        batch.insert(format!("{prefix}{}::data", info.key).as_bytes(), bytes);
+        batch.insert(format!("{prefix}{}::info", info.key).as_bytes(), info_bytes);
+        self.project_tree(project_id)?.apply_batch(batch)?;
+
+        Ok(())
+    }
+
+    /// Fetch a binary attachment and its info.
+    pub fn get_attachment(
+        &self,
+        project_id: &str,
+        collection: &str,
+        doc_id: &str,
+        key: &str,
+    ) -> StorageResult<Option<(AttachmentInfo, IVec)>> {
+        let prefix = Self::attachment_prefix(collection, doc_id);
+        let attachments = self.project_tree(project_id)?;
+
+        let Some(info) = attachments.get(format!("{prefix}{key}::info"))? else {
+            return Ok(None);
+        };
+        let Some(data) = attachments.get(format!("{prefix}{key}::data"))? else {
+            return Ok(None);
+        };
+
+        Ok(Some((serde_json::from_slice(&info)?, data)))
+    }
+
+    /// List the attachments of a document.
+    pub fn list_attachments(
+        &self,
+        project_id: &str,
+        collection: &str,
+        doc_id: &str,
+    ) -> StorageResult<Vec<AttachmentInfo>> {
+        let prefix = Self::attachment_prefix(collection, doc_id);
+        let mut infos = Vec::new();
+
+        for item in self.project_tree(project_id)?.scan_prefix(prefix.as_bytes()) {
+            let (k, v) = item?;
+            if k.ends_with(b"::info") {
+                infos.push(serde_json::from_slice(&v)?);
+            }
+        }
+
+        Ok(infos)
+    }
+
+    /// Delete a single attachment. Returns whether it existed.
+    pub fn delete_attachment(
+        &self,
+        project_id: &str,
+        collection: &str,
+        doc_id: &str,
+        key: &str,
+    ) -> StorageResult<bool> {
+        let prefix = Self::attachment_prefix(collection, doc_id);
+        let (info_key, data_key) = (format!("{prefix}{key}::info"), format!("{prefix}{key}::data"));
+
+        // Info and data are removed together so neither is left behind
+        let existed = self.project_tree(project_id)?.transaction(|attachments| {
+            let existed = attachments.remove(info_key.as_bytes())?.is_some();
+            attachments.remove(data_key.as_bytes())?;
+            Ok::<_, ConflictableTransactionError<StorageError>>(existed)
+        })?;
+
+        Ok(existed)
+    }
+
+    /// Delete every attachment of a document.
+    fn delete_all_attachments(
+        &self,
+        project_id: &str,
+        collection: &str,
+        doc_id: &str,
+    ) -> StorageResult<()> {
+        let prefix = Self::attachment_prefix(collection, doc_id);
+        let mut batch = sled::Batch::default();
+
+        let attachments = self.project_tree(project_id)?;
+        for key in attachments.scan_prefix(prefix.as_bytes()).keys() {
+            batch.remove(key?);
+        }
+
+        attachments.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Return the state machine of a collection, if it has one.
+    pub fn get_state_machine(
+        &self,
+        project_id: &str,
+        collection: &str,
+    ) -> StorageResult<Option<StateMachine>> {
+        match self.project_tree(project_id)?.get(format!("machine::{collection}"))? {
+            Some(bytes) => Ok(Some(Codec::decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the state machine of a collection. Stored documents are checked on their next write.
+    pub fn put_state_machine(
+        &self,
+        project_id: &str,
+        collection: &str,
+        machine: &StateMachine,
+    ) -> StorageResult<()> {
+        Name::internal("collection name", collection)?;
+
+        self.project_tree(project_id)?
+            .insert(format!("machine::{collection}"), serde_json::to_vec(machine)?)?;
+        Ok(())
+    }
+
+    /// Remove the state machine of a collection. Returns whether it had one.
+    pub fn delete_state_machine(&self, project_id: &str, collection: &str) -> StorageResult<bool> {
+        Ok(self
+            .project_tree(project_id)?
+            .remove(format!("machine::{collection}"))?
+            .is_some())
+    }
+
+    /// Return the collections of a project with a geo index or a state machine.
+    pub fn configured_collections(&self, project_id: &str) -> StorageResult<Vec<String>> {
+        let tree = self.project_tree(project_id)?;
+
+        let mut collections = std::collections::BTreeSet::new();
+        for prefix in ["geo::", "machine::"] {
+            for key in tree.scan_prefix(prefix).keys() {
+                let key = key?;
+                collections.insert(String::from_utf8_lossy(&key[prefix.len()..]).to_string());
+            }
+        }
+
+        Ok(collections.into_iter().collect())
+    }
+
+    /// Return the spec last applied to a project, if any.
+    pub fn get_applied_spec(&self, project_id: &str) -> StorageResult<Option<AppliedSpec>> {
+        match self.project_tree(project_id)?.get("spec::applied")? {
+            Some(bytes) => Ok(Some(Codec::decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Record the spec last applied to a project.
+    pub fn put_applied_spec(&self, project_id: &str, spec: &AppliedSpec) -> StorageResult<()> {
+        self.project_tree(project_id)?
+            .insert("spec::applied", serde_json::to_vec(spec)?)?;
+        Ok(())
+    }
+
+    /// Return the geo index of a collection, if it has one.
+    pub fn get_geo_index(
+        &self,
+        project_id: &str,
+        collection: &str,
+    ) -> StorageResult<Option<GeoIndex>> {
+        match self.project_tree(project_id)?.get(format!("geo::{collection}"))? {
+            Some(bytes) => Ok(Some(Codec::decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the geo index of a collection and index its stored documents.
+    /// Returns the number of documents indexed. Documents whose field is not a point are skipped.
+    pub fn put_geo_index(
+        &self,
+        project_id: &str,
+        collection: &str,
+        index: &GeoIndex,
+    ) -> StorageResult<usize> {
+        Name::internal("collection name", collection)?;
+
+        // New writes index themselves from now on
+        let tree = self.project_tree(project_id)?;
+        tree.insert(format!("geo::{collection}"), serde_json::to_vec(index)?)?;
+
+        let mut batch = sled::Batch::default();
+        for key in tree.scan_prefix(format!("geohash::{collection}::")).keys() {
+            batch.remove(key?);
+        }
+
+        let mut indexed = 0;
+        for value in KeyBuf::collection(collection, |prefix| tree.scan_prefix(prefix)).values() {
+            let doc: Document = Codec::decode(&value?)?;
+            if let Ok(Some(point)) = index.point(&doc.data) {
+                batch.insert(geo_key(collection, &point, &doc.id).as_bytes(), &[]);
+                indexed += 1;
+            }
+        }
+        tree.apply_batch(batch)?;
+
+        Ok(indexed)
+    }
+
+    /// Remove the geo index of a collection. Returns whether it had one.
+    pub fn delete_geo_index(&self, project_id: &str, collection: &str) -> StorageResult<bool> {
+        let tree = self.project_tree(project_id)?;
+
+        let mut batch = sled::Batch::default();
+        for key in tree.scan_prefix(format!("geohash::{collection}::")).keys() {
+            batch.remove(key?);
+        }
+        tree.apply_batch(batch)?;
+
+        Ok(tree.remove(format!("geo::{collection}"))?.is_some())
+    }
+
+    /// Return the documents of a collection within the radius of a point, nearest first.
+    /// Only the documents in the index cells covering the circle are read.
+    pub fn near_documents(
+        &self,
+        project_id: &str,
+        collection: &str,
+        near: &Near,
+    ) -> StorageResult<Vec<IVec>> {
+        let index = self
+            .get_geo_index(project_id, collection)?
+            .ok_or_else(|| format!("Collection {collection} has no geo index"))?;
+        let tree = self.project_tree(project_id)?;
+
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        for cell in near.cells() {
+            for key in tree.scan_prefix(format!("geohash::{collection}::{cell}")).keys() {
+                let key = String::from_utf8(key?.to_vec())?;
+
+                // key format: geohash::{collection}::{geohash}::{doc_id}
+                let Some(doc_id) = key.splitn(4, "::").nth(3) else {
+                    continue;
+                };
+                if !seen.insert(doc_id.to_string()) {
+                    continue;
+                }
+
+                // The index only narrows the search, the stored point decides
+                let Some(value) = KeyBuf::document(collection, doc_id, |key| tree.get(key))? else {
+                    continue;
+                };
+                let doc: Document = Codec::decode(&value)?;
+                let Ok(Some(point)) = index.point(&doc.data) else {
+                    continue;
+                };
+                let distance = near.center.distance(&point);
+                if distance <= near.radius {
+                    found.push((distance, value));
+                }
+            }
+        }
+
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        found
+            .into_iter()
+            .map(|(_, value)| Codec::to_json(value))
+            .collect()
+    }
+
+    /// Return a value of the key-value namespace of a project.
+    pub fn get_kv(&self, project_id: &str, key: &str) -> StorageResult<Option<Value>> {
+        match self.project_tree(project_id)?.get(format!("kv::{key}"))? {
+            Some(bytes) => Ok(Some(Codec::decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Set a value of the key-value namespace of a project.
+    pub fn put_kv(&self, project_id: &str, key: &str, value: &Value) -> StorageResult<()> {
+        Name::internal("kv key", key)?;
+
+        self.project_tree(project_id)?
+            .insert(format!("kv::{key}"), self.doc_codec.encode(value)?)?;
+        Ok(())
+    }
+
+    /// Delete a value of the key-value namespace of a project. Returns whether it existed.
+    pub fn delete_kv(&self, project_id: &str, key: &str) -> StorageResult<bool> {
+        Ok(self
+            .project_tree(project_id)?
+            .remove(format!("kv::{key}"))?
+            .is_some())
+    }
+
+    /// Return the watchlists of a project, with their size.
+    pub fn list_watchlists(&self, project_id: &str) -> StorageResult<Vec<Watchlist>> {
+        let tree = self.project_tree(project_id)?;
+
+        let mut watchlists = Vec::new();
+        for item in tree.scan_prefix(b"watchlist::") {
+            let (key, value) = item?;
+            let name = String::from_utf8_lossy(&key["watchlist::".len()..]).to_string();
+            let info: WatchlistInfo = serde_json::from_slice(&value)?;
+            let size = tree.scan_prefix(format!("watch::{name}::")).count();
+
+            watchlists.push(Watchlist {
+                name,
+                description: info.description,
+                created_at: info.created_at,
+                size,
+            });
+        }
+
+        Ok(watchlists)
+    }
+
+    /// Return a watchlist of a project and its addresses, if it exists.
+    pub fn get_watchlist(
+        &self,
+        project_id: &str,
+        name: &str,
+    ) -> StorageResult<Option<(Watchlist, Vec<String>)>> {
+        let tree = self.project_tree(project_id)?;
+        let Some(value) = tree.get(format!("watchlist::{name}"))? else {
+            return Ok(None);
+        };
+        let info: WatchlistInfo = serde_json::from_slice(&value)?;
+
+        let prefix = format!("watch::{name}::");
+        let addresses = tree
+            .scan_prefix(&prefix)
+            .keys()
+            .map(|key| Ok(String::from_utf8(key?[prefix.len()..].to_vec())?))
+            .collect::<StorageResult<Vec<String>>>()?;
+
+        let watchlist = Watchlist {
+            name: name.to_string(),
+            description: info.description,
+            created_at: info.created_at,
+            size: addresses.len(),
+        };
+        Ok(Some((watchlist, addresses)))
+    }
+
+    /// Create or replace a watchlist with the given (normalized) addresses.
+    /// The list is replaced in one batch, so conditions never see it half written.
+    pub fn put_watchlist(
+        &self,
+        project_id: &str,
+        name: &str,
+        description: &str,
+        addresses: &[String],
+    ) -> StorageResult<()> {
+        Name::internal("watchlist name", name)?;
+        let tree = self.project_tree(project_id)?;
+
+        // A replaced list keeps its creation time
+        let created_at = match tree.get(format!("watchlist::{name}"))? {
+            Some(value) => serde_json::from_slice::<WatchlistInfo>(&value)?.created_at,
+            None => Utc::now().timestamp_millis() as u64,
+        };
+        let info = WatchlistInfo {
+            description: description.to_string(),
+            created_at,
+        };
+
+        let mut batch = sled::Batch::default();
+        for key in tree.scan_prefix(format!("watch::{name}::")).keys() {
+            batch.remove(key?);
+        }
+        for address in addresses {
+            batch.insert(format!("watch::{name}::{address}").as_bytes(), &[]);
+        }
+        batch.insert(format!("watchlist::{name}").as_bytes(), serde_json::to_vec(&info)?);
+        tree.apply_batch(batch)?;
+
+        Ok(())
+    }
+
+    /// Add (normalized) addresses to an existing watchlist. Returns the number of new addresses.
+    pub fn add_to_watchlist(
+        &self,
+        project_id: &str,
+        name: &str,
+        addresses: &[String],
+    ) -> StorageResult<usize> {
+        let tree = self.project_tree(project_id)?;
+        if !tree.contains_key(format!("watchlist::{name}"))? {
+            return Err(StorageError::NotFound(format!("Watchlist {name} not found")));
+        }
+
+        let mut batch = sled::Batch::default();
+        let mut added = 0;
+        for address in addresses {
+            let key = format!("watch::{name}::{address}");
+            if !tree.contains_key(&key)? {
+                batch.insert(key.as_bytes(), &[]);
+                added += 1;
+            }
+        }
+        tree.apply_batch(batch)?;
+
+        Ok(added)
+    }
+
+    /// Remove an address from a watchlist. Returns whether it was listed.
+    pub fn remove_from_watchlist(
+        &self,
+        project_id: &str,
+        name: &str,
+        address: &str,
+    ) -> StorageResult<bool> {
+        Ok(self
+            .project_tree(project_id)?
+            .remove(format!("watch::{name}::{address}"))?
+            .is_some())
+    }
+
+    /// Delete a watchlist and its addresses. Returns whether it existed.
+    pub fn delete_watchlist(&self, project_id: &str, name: &str) -> StorageResult<bool> {
+        let tree = self.project_tree(project_id)?;
+
+        let mut batch = sled::Batch::default();
+        for key in tree.scan_prefix(format!("watch::{name}::")).keys() {
+            batch.remove(key?);
+        }
+        tree.apply_batch(batch)?;
+
+        Ok(tree.remove(format!("watchlist::{name}"))?.is_some())
+    }
+
+    /// Whether a (normalized) address is on a watchlist of a project.
+    pub fn in_watchlist(&self, project_id: &str, name: &str, address: &str) -> StorageResult<bool> {
+        Ok(self
+            .project_tree(project_id)?
+            .contains_key(format!("watch::{name}::{address}"))?)
+    }
+
+    /// Return the tree of the service accounts of every project, keyed by account id.
+    fn service_accounts_tree(&self) -> StorageResult<sled::Tree> {
+        Ok(self.projects.open_tree("service_accounts")?)
+    }
+
+    /// Create a service account of a project. Returns the account and its token, which is
+    /// only known at this point.
+    pub fn create_service_account(
+        &self,
+        project: &Project,
+        name: &str,
+        scopes: Vec<Scope>,
+        expires_at: Option<u64>,
+    ) -> StorageResult<(ServiceAccount, String)> {
+        Name::internal("service account name", name)?;
+
+        let (id, token, secret) = service::generate_token();
+        let account = ServiceAccount {
+            id: id.clone(),
+            project_id: project.id.clone(),
+            name: name.to_string(),
+            scopes,
+            created_at: Utc::now().timestamp_millis() as u64,
+            expires_at,
+        };
+        let stored = StoredServiceAccount {
+            account: account.clone(),
+            project_key: project.api_key.clone(),
+            secret_hash: hash_api_key(&secret)?,
+        };
+
+        self.service_accounts_tree()?
+            .insert(id.as_bytes(), serde_json::to_vec(&stored)?)?;
+        // The token is only shown once, so the account must survive a crash
+        self.flush.projects.sync()?;
+
+        Ok((account, token))
+    }
+
+    /// Return a stored service account by id.
+    pub fn get_service_account(&self, id: &str) -> StorageResult<Option<StoredServiceAccount>> {
+        match self.service_accounts_tree()?.get(id.as_bytes())? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Return the service accounts of a project, oldest first.
+    pub fn list_service_accounts(&self, project_id: &str) -> StorageResult<Vec<ServiceAccount>> {
+        let mut accounts = Vec::new();
+        for value in self.service_accounts_tree()?.iter().values() {
+            let stored: StoredServiceAccount = serde_json::from_slice(&value?)?;
+            if stored.account.project_id == project_id {
+                accounts.push(stored.account);
+            }
+        }
+
+        accounts.sort_by_key(|a| a.created_at);
+        Ok(accounts)
+    }
+
+    /// Delete a service account of a project, revoking its token. Returns whether it existed.
+    pub fn delete_service_account(&self, project_id: &str, id: &str) -> StorageResult<bool> {
+        let tree = self.service_accounts_tree()?;
+        match self.get_service_account(id)? {
+            Some(stored) if stored.account.project_id == project_id => {
+                tree.remove(id.as_bytes())?;
+                // A revoked token must stay revoked after a crash
+                self.flush.projects.sync()?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Return recorded runs that carry a raw event payload, across all projects.
+    pub fn raw_event_corpus(&self, limit: usize) -> StorageResult<Vec<TriggerRun>> {
+        let mut corpus = Vec::new();
+
+        for name in self.triggers.tree_names() {
+            if !name.starts_with(b"runs::") {
+                continue;
+            }
+
+            for item in self.triggers.open_tree(name)?.scan_prefix(b"run::").rev() {
+                let (_k, v) = item?;
+                let run: TriggerRun = serde_json::from_slice(&v)?;
+                if run.event.raw.is_some() {
+                    corpus.push(run);
+                    if corpus.len() >= limit {
+                        return Ok(corpus);
+                    }
+                }
+            }
+        }
+
+        Ok(corpus)
+    }
+
+    /// Retrieve all stored entries
+    pub fn get_metadata_entries(&self) -> StorageResult<Vec<Metadata>> {
+        const KEY: &str = "HANNAH";
+
+        match self.metadata.get(KEY)? {
+            Some(bytes) => self.decode_stored("metadata entries", KEY.as_bytes(), &bytes),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Remove the metadata entry of a contract and return it, if there was one.
+    pub fn remove_metadata_entry(&self, addr: &str) -> StorageResult<Option<Metadata>> {
+        const KEY: &str = "HANNAH";
+
+        let mut entries = self.get_metadata_entries()?;
+        let Some(index) = entries.iter().position(|e| e.addr == addr) else {
+            return Ok(None);
+        };
+        let removed = entries.remove(index);
+
+        self.metadata.insert(KEY, serde_json::to_vec(&entries)?)?;
+        self.flush.metadata.written()?;
+        Ok(Some(removed))
+    }
+
+    /// Return the triggers of a project with their contract address.
+    /// Triggers are found through the search index and the project's own contract.
+    fn project_triggers(
+        &self,
+        project_id: &str,
+        contract_addr: &str,
+    ) -> StorageResult<Vec<(String, Trigger)>> {
+        let mut contracts = std::collections::BTreeSet::from([contract_addr.to_string()]);
+        for key in self
+            .trigger_index
+            .scan_prefix(format!("{project_id}::").as_bytes())
+            .keys()
+        {
+            // Keys are `{project_id}::{token}::{contract_addr}::{trigger_id}`
+            let key = String::from_utf8_lossy(&key?).to_string();
+            if let Some(contract_addr) = key.split("::").nth(2) {
+                contracts.insert(contract_addr.to_string());
+            }
+        }
+
+        let mut triggers = Vec::new();
+        for contract_addr in contracts {
+            for trigger in self.list_triggers(&contract_addr)? {
+                if trigger.project_id == project_id {
+                    triggers.push((contract_addr.clone(), trigger));
+                }
+            }
+        }
+
+        Ok(triggers)
+    }
+
+    /// Delete every trigger of a project and its search index entries.
+    /// Returns the number of triggers deleted.
+    pub(crate) fn delete_project_triggers(
+        &self,
+        project_id: &str,
+        contract_addr: &str,
+    ) -> StorageResult<usize> {
+        let triggers = self.project_triggers(project_id, contract_addr)?;
+        for (contract_addr, trigger) in &triggers {
+            self.delete_trigger(contract_addr, &trigger.id)?;
+        }
+
+        // Drop any index entry left behind
+        let mut batch = sled::Batch::default();
+        for key in self
+            .trigger_index
+            .scan_prefix(format!("{project_id}::").as_bytes())
+            .keys()
+        {
+            batch.remove(key?);
+        }
+        self.trigger_index.apply_batch(batch)?;
+
+        Ok(triggers.len())
+    }
+
+    /// Write a project, its triggers, documents, state machines, geo indexes, key-value entries,
+    /// watchlists and attachments as NDJSON, one record per line. Geo index entries are rebuilt on import.
+    /// The API key is left out. Returns the number of records written.
+    pub fn export_project(&self, project: &Project, out: &mut impl std::io::Write) -> StorageResult<u64> {
+        let mut records = 0u64;
+        let mut line = |value: Value| -> StorageResult<()> {
+            serde_json::to_writer(&mut *out, &value)?;
+            out.write_all(b"\n")?;
+            records += 1;
+            Ok(())
+        };
+
+        let mut project = project.clone();
+        project.api_key.clear();
+        line(json!({ "kind": "project", "project": project }))?;
+
+        for (contract_addr, trigger) in self.project_triggers(&project.id, &project.contract_address)? {
+            line(json!({ "kind": "trigger", "contract_addr": contract_addr, "trigger": trigger }))?;
+        }
+
+        let tree = self.project_tree(&project.id)?;
+        for item in tree.scan_prefix(b"document::") {
+            let (key, value) = item?;
+            let key = String::from_utf8_lossy(&key).to_string();
+
+            // key format: document::{collection}::{doc_id}
+            let collection = key.split("::").nth(1).unwrap_or_default();
+            let document: Value = Codec::decode(&value)?;
+            line(json!({ "kind": "document", "collection": collection, "document": document }))?;
+        }
+
+        for item in tree.scan_prefix(b"machine::") {
+            let (key, value) = item?;
+            let machine: Value = Codec::decode(&value)?;
+            line(json!({
+                "kind": "state_machine",
+                "collection": String::from_utf8_lossy(&key["machine::".len()..]),
+                "state_machine": machine,
+            }))?;
+        }
+
+        for item in tree.scan_prefix(b"geo::") {
+            let (key, value) = item?;
+            let index: Value = Codec::decode(&value)?;
+            line(json!({
+                "kind": "geo_index",
+                "collection": String::from_utf8_lossy(&key["geo::".len()..]),
+                "geo_index": index,
+            }))?;
+        }
+
+        for item in tree.scan_prefix(b"kv::") {
+            let (key, value) = item?;
+            let value: Value = Codec::decode(&value)?;
+            line(json!({
+                "kind": "kv",
+                "key": String::from_utf8_lossy(&key["kv::".len()..]),
+                "value": value,
+            }))?;
+        }
+
+        for watchlist in self.list_watchlists(&project.id)? {
+            let prefix = format!("watch::{}::", watchlist.name);
+            let addresses = tree
+                .scan_prefix(&prefix)
+                .keys()
+                .map(|key| Ok(String::from_utf8_lossy(&key?[prefix.len()..]).to_string()))
+                .collect::<StorageResult<Vec<String>>>()?;
+            line(json!({ "kind": "watchlist", "watchlist": watchlist, "addresses": addresses }))?;
+        }
+
+        for item in tree.scan_prefix(b"attachment::") {
+            let (key, info) = item?;
+            let Some(data_key) = key.strip_suffix(b"::info") else {
+                continue;
+            };
+            let key = String::from_utf8_lossy(data_key).to_string();
+
+            // key format: attachment::{collection}::{doc_id}::{key}
+            let mut parts = key.splitn(4, "::").skip(1);
+            let (Some(collection), Some(doc_id)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let data = tree
+                .get([data_key, b"::data"].concat())?
+                .unwrap_or_default();
+            let info: Value = serde_json::from_slice(&info)?;
+            line(json!({
+                "kind": "attachment",
+                "collection": collection,
+                "doc_id": doc_id,
+                "info": info,
+                "data": general_purpose::STANDARD.encode(&data),
+            }))?;
+        }
+
+        out.flush()?;
+        Ok(records)
+    }
+}
+
+// Document writes
+impl Sled {
+    /// Write a document, checking the precondition (if any) against its stored copy.
+    /// The document and its collection stats are written in one transaction,
+    /// so the stored copy can't change between the check and the write.
+    /// Unconditional writes of a block batch are deferred to the end of the block.
+    async fn write_document(
+        &self,
+        project_id: &str,
+        collection: &str,
+        doc: Document,
+        update: bool,
+        precondition: Option<&Precondition>,
+    ) -> StorageResult<()> {
+        // Names end up in the key, so they must not break prefix scans
+        Name::internal("collection name", collection)?;
+        Name::internal("document id", &doc.id)?;
+
+        if let Some(batch) = BlockBatch::current().filter(|_| precondition.is_none()) {
+            let source = ChangeSource::current();
+            batch.defer(project_id, collection, DeferredWrite::Put { doc, update, source });
+            return Ok(());
+        }
+
+        let tree = self.project_tree(project_id)?;
+        let (doc, transition) = tree.transaction(|docs| {
+            self.stage_write(docs, collection, &doc, update, precondition)
+        })?;
+
+        // Broadcast the insert event to all subscribed clients
+        let source = ChangeSource::current();
+        self.publish_change("insert", project_id, collection, doc, transition, source)
+            .await;
+
+        Ok(())
+    }
+
+    /// Write a document and its collection stats within a transaction of its project tree.
+    /// Nothing is written when the write is rejected (an abort).
+    fn stage_write(
+        &self,
+        docs: &TransactionalTree,
+        collection: &str,
+        doc: &Document,
+        update: bool,
+        precondition: Option<&Precondition>,
+    ) -> ConflictableTransactionResult<(Document, Option<Transition>), StorageError> {
+        KeyBuf::document(collection, &doc.id, |key| {
+            KeyBuf::stats(collection, |stats_key| {
+                let current = docs.get(key)?;
+                let stored: Option<Document> = current
+                    .as_deref()
+                    .map(Codec::decode)
+                    .transpose()
+                    .map_err(abort)?;
+
+                if precondition.is_some_and(|p| !p.holds(stored.as_ref())) {
+                    return Err(abort(StorageError::PreconditionFailed(format!(
+                        "Document {} has changed",
+                        doc.id
+                    ))));
+                }
+
+                // The state machine of the collection (if any) is read with the document
+                let transition = match docs.get(format!("machine::{collection}"))? {
+                    Some(bytes) => {
+                        let machine: StateMachine = Codec::decode(&bytes).map_err(abort)?;
+                        machine
+                            .check(stored.as_ref().map(|d| &d.data), &doc.data)
+                            .map_err(|message| {
+                                abort(StorageError::InvalidTransition {
+                                    field: machine.field.clone(),
+                                    message,
+                                })
+                            })?
+                    }
+                    None => None,
+                };
+
+                // Move the document in the geo index of the collection (if any)
+                if let Some(bytes) = docs.get(format!("geo::{collection}"))? {
+                    let index: GeoIndex = Codec::decode(&bytes).map_err(abort)?;
+                    let point = index.point(&doc.data).map_err(|message| {
+                        abort(StorageError::InvalidField {
+                            field: index.field.clone(),
+                            message,
+                        })
+                    })?;
+
+                    let old = stored.as_ref().and_then(|d| index.point(&d.data).ok().flatten());
+                    if let Some(old) = old {
+                        docs.remove(geo_key(collection, &old, &doc.id).as_bytes())?;
+                    }
+                    if let Some(point) = point {
+                        docs.insert(geo_key(collection, &point, &doc.id).as_bytes(), &[])?;
+                    }
+                }
+
+                // Unix timestamp
+                let now = Utc::now().timestamp_millis() as u64;
+
+                // Document metadata
+                let mut doc = doc.clone();
+                let metadata = if !update {
+                    DocMetadata {
+                        created_at: now,
+                        updated_at: now,
+                        version: None,
+                        tags: Default::default(),
+                        hash: None,
+                    }
+                } else {
+                    DocMetadata {
+                        updated_at: now,
+                        ..doc.metadata
+                    }
+                };
+
+                doc.metadata = metadata;
+                doc.metadata.hash = Some(content_hash(&doc.data));
+                // Every write bumps the version of the stored copy
+                doc.metadata.version = Some(
+                    stored
+                        .as_ref()
+                        .and_then(|d| d.metadata.version)
+                        .unwrap_or(0)
+                        + 1,
+                );
+
+                docs.insert(key, self.doc_codec.encode(&doc).map_err(abort)?)?;
+
+                // A new document adds to the collection, any write moves its last update
+                CollectionStats::update(docs, stats_key, |s| {
+                    s.count += current.is_none() as u64;
+                    s.last_updated = s.last_updated.max(now);
+                })?;
+
+                Ok((doc, transition))
+            })
+        })
+    }
+
+    /// Push items onto array fields of a document, keeping the last `max` items of each.
+    /// The document is created if missing. Appends of a block batch are deferred to the end
+    /// of the block.
+    pub async fn append_document(
+        &self,
+        project_id: &str,
+        collection: &str,
+        id: &str,
+        items: HashMap<String, Value>,
+        max: usize,
+    ) -> StorageResult<()> {
+        Name::internal("collection name", collection)?;
+        Name::internal("document id", id)?;
+
+        let source = ChangeSource::current();
+        if let Some(batch) = BlockBatch::current() {
+            let id = id.to_string();
+            batch.defer(project_id, collection, DeferredWrite::Append { id, items, max, source });
+            return Ok(());
+        }
+
+        let tree = self.project_tree(project_id)?;
+        let (doc, transition) =
+            tree.transaction(|docs| self.stage_append(docs, collection, id, &items, max))?;
+
+        self.publish_change("insert", project_id, collection, doc, transition, source)
+            .await;
+
+        Ok(())
+    }
+
+    /// Push items onto array fields of a document within a transaction of its project tree.
+    /// Fields holding something else than an array reject the append.
+    fn stage_append(
+        &self,
+        docs: &TransactionalTree,
+        collection: &str,
+        id: &str,
+        items: &HashMap<String, Value>,
+        max: usize,
+    ) -> ConflictableTransactionResult<(Document, Option<Transition>), StorageError> {
+        let stored: Option<Document> = KeyBuf::document(collection, id, |key| docs.get(key))?
+            .as_deref()
+            .map(Codec::decode)
+            .transpose()
+            .map_err(abort)?;
+        let update = stored.is_some();
+
+        let mut doc = stored.unwrap_or_else(|| Document {
+            id: id.to_string(),
+            data: json!({}),
+            metadata: DocMetadata::default(),
+        });
+        let Some(data) = doc.data.as_object_mut() else {
+            return Err(abort(StorageError::InvalidField {
+                field: format!("document {id}"),
+                message: "not an object".to_string(),
+            }));
+        };
+
+        for (field, item) in items {
+            let value = data.entry(field.as_str()).or_insert_with(|| json!([]));
+            let Some(array) = value.as_array_mut() else {
+                return Err(abort(StorageError::InvalidField {
+                    field: field.clone(),
+                    message: "not an array".to_string(),
+                }));
+            };
+
+            // The oldest items make room for the new one
+            array.push(item.clone());
+            let excess = array.len().saturating_sub(max);
+            array.drain(..excess);
+        }
+
+        self.stage_write(docs, collection, &doc, update, None)
+    }
+
+    /// Merge fields into a document (RFC 7386): nested objects are merged and `null` removes a
+    /// field. The document is created if missing. Merges of a block batch are deferred to the
+    /// end of the block.
+    pub async fn merge_document(
+        &self,
+        project_id: &str,
+        collection: &str,
+        id: &str,
+        patch: Value,
+    ) -> StorageResult<()> {
+        Name::internal("collection name", collection)?;
+        Name::internal("document id", id)?;
+
+        let source = ChangeSource::current();
+        if let Some(batch) = BlockBatch::current() {
+            let id = id.to_string();
+            batch.defer(project_id, collection, DeferredWrite::Merge { id, patch, source });
+            return Ok(());
+        }
+
+        let tree = self.project_tree(project_id)?;
+        let (doc, transition) =
+            tree.transaction(|docs| self.stage_merge(docs, collection, id, &patch))?;
+
+        self.publish_change("insert", project_id, collection, doc, transition, source)
+            .await;
+
+        Ok(())
+    }
+
+    /// Merge fields into a document within a transaction of its project tree.
+    fn stage_merge(
+        &self,
+        docs: &TransactionalTree,
+        collection: &str,
+        id: &str,
+        patch: &Value,
+    ) -> ConflictableTransactionResult<(Document, Option<Transition>), StorageError> {
+        let stored: Option<Document> = KeyBuf::document(collection, id, |key| docs.get(key))?
+            .as_deref()
+            .map(Codec::decode)
+            .transpose()
+            .map_err(abort)?;
+        let update = stored.is_some();
+
+        let mut doc = stored.unwrap_or_else(|| Document {
+            id: id.to_string(),
+            data: json!({}),
+            metadata: DocMetadata::default(),
+        });
+        merge_patch(&mut doc.data, patch);
+
+        self.stage_write(docs, collection, &doc, update, None)
+    }
+
+    /// Delete a document, checking the precondition (if any) against its stored copy.
+    /// Unconditional deletions of a block batch are deferred to the end of the block.
+    async fn remove_document(
+        &self,
+        project_id: &str,
+        collection: &str,
+        id: &str,
+        precondition: Option<&Precondition>,
+    ) -> StorageResult<()> {
+        if let Some(batch) = BlockBatch::current().filter(|_| precondition.is_none()) {
+            let source = ChangeSource::current();
+            let id = id.to_string();
+            batch.defer(project_id, collection, DeferredWrite::Delete { id, source });
+            return Ok(());
+        }
+
+        let tree = self.project_tree(project_id)?;
+
+        // Delete and returns the old value (if any)
+        let old_value =
+            tree.transaction(|docs| Self::stage_removal(docs, collection, id, precondition))?;
+
+        // Attachments can't outlive their document
+        self.delete_all_attachments(project_id, collection, id)?;
+
+        // Only use the old value to notify subscribers, not in the publish API
+        if let Some(doc) = old_value.and_then(|doc| Codec::decode(&doc).ok()) {
+            let source = ChangeSource::current();
+            self.publish_change("delete", project_id, collection, doc, None, source)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    /// Delete a document and update its collection stats within a transaction of its project
+    /// tree, returning the stored copy (if any).
+    fn stage_removal(
+        docs: &TransactionalTree,
+        collection: &str,
+        id: &str,
+        precondition: Option<&Precondition>,
+    ) -> ConflictableTransactionResult<Option<IVec>, StorageError> {
+        KeyBuf::document(collection, id, |key| {
+            KeyBuf::stats(collection, |stats_key| {
+                if let Some(precondition) = precondition {
+                    let stored: Option<Document> = docs
+                        .get(key)?
+                        .as_deref()
+                        .map(Codec::decode)
+                        .transpose()
+                        .map_err(abort)?;
+
+                    if !precondition.holds(stored.as_ref()) {
+                        return Err(abort(StorageError::PreconditionFailed(format!(
+                            "Document {id} has changed"
+                        ))));
+                    }
+                }
+
+                let current = docs.remove(key)?;
+
+                // Drop the document from the geo index of the collection (if any)
+                if let (Some(bytes), Some(current)) =
+                    (docs.get(format!("geo::{collection}"))?, &current)
+                {
+                    let index: GeoIndex = Codec::decode(&bytes).map_err(abort)?;
+                    let stored: Document = Codec::decode(current).map_err(abort)?;
+                    if let Ok(Some(point)) = index.point(&stored.data) {
+                        docs.remove(geo_key(collection, &point, id).as_bytes())?;
+                    }
+                }
+
+                // A collection disappears with its last document
+                if current.is_some() {
+                    CollectionStats::update(docs, stats_key, |s| {
+                        s.count = s.count.saturating_sub(1)
+                    })?;
+                }
+
+                Ok(current)
+            })
+        })
+    }
+
+    /// Apply the deferred writes of a block to a collection in one transaction, then publish
+    /// the final state of each document once. Writes the collection rejects (e.g. an invalid
+    /// transition) are skipped, like they would have failed on their own.
+    pub async fn apply_writes(
+        &self,
+        project_id: &str,
+        collection: &str,
+        writes: &[DeferredWrite],
+    ) -> StorageResult<()> {
+        let tree = self.project_tree(project_id)?;
+
+        let (changes, skipped) = tree.transaction(|docs| {
+            // Latest change of each document, in the order the documents were first written
+            let mut changes: Vec<(&str, Document, Option<Transition>, ChangeSource)> = Vec::new();
+            let mut positions: HashMap<&str, usize> = HashMap::new();
+            let mut skipped = Vec::new();
+
+            for write in writes {
+                let staged = match write {
+                    DeferredWrite::Put { doc, update, source } => self
+                        .stage_write(docs, collection, doc, *update, None)
+                        .map(|(doc, transition)| Some(("insert", doc, transition, source.clone()))),
+                    DeferredWrite::Delete { id, source } => {
+                        Self::stage_removal(docs, collection, id, None).map(|old| {
+                            old.and_then(|bytes| Codec::decode(&bytes).ok())
+                                .map(|doc| ("delete", doc, None, source.clone()))
+                        })
+                    }
+                    DeferredWrite::Append {
+                        id,
+                        items,
+                        max,
+                        source,
+                    } => self
+                        .stage_append(docs, collection, id, items, *max)
+                        .map(|(doc, transition)| Some(("insert", doc, transition, source.clone()))),
+                    DeferredWrite::Merge { id, patch, source } => self
+                        .stage_merge(docs, collection, id, patch)
+                        .map(|(doc, transition)| Some(("insert", doc, transition, source.clone()))),
+                };
+
+                match staged {
+                    Ok(Some(change)) => match positions.get(write.doc_id()) {
+                        Some(&i) => changes[i] = change,
+                        None => {
+                            positions.insert(write.doc_id(), changes.len());
+                            changes.push(change);
+                        }
+                    },
+                    Ok(None) => {}
+                    Err(ConflictableTransactionError::Abort(e)) => {
+                        skipped.push(format!("{}: {e}", write.doc_id()));
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            Ok((changes, skipped))
+        })?;
+
+        for reason in skipped {
+            tracing::warn!("Skipped a write to {collection} of project {project_id}: {reason}");
+        }
+
+        for (op, doc, transition, source) in changes {
+            // Attachments can't outlive their document
+            if op == "delete" {
+                self.delete_all_attachments(project_id, collection, &doc.id)?;
+            }
+            self.publish_change(op, project_id, collection, doc, transition, source)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    /// Broadcast a document change to all subscribed clients.
+    async fn publish_change(
+        &self,
+        op: &str,
+        project_id: &str,
+        collection: &str,
+        doc: Document,
+        transition: Option<Transition>,
+        source: ChangeSource,
+    ) {
+        self.subscriptions
+            .publish(WsPayload {
+                op: op.to_string(),
+                topic: String::with_capacity(100),
+                project_id: project_id.to_string(),
+                collection: collection.to_string(),
+                doc_id: doc.id.clone(),
+                seq: 0,
+                source,
+                doc,
+                transition,
+            })
+            .await;
+    }
+}
+
+/// Rewrite a record stored in another format than `codec`.
+/// Concurrent writes win: the record is only replaced if it didn't change since it was read.
+fn migrate<T: Serialize>(
+    tree: &sled::Tree,
+    key: &[u8],
+    stored: &IVec,
+    codec: Codec,
+    value: &T,
+) -> StorageResult<()> {
+    if codec.is_stale(stored) {
+        let _ = tree.compare_and_swap(key, Some(stored), Some(codec.encode(value)?))?;
+    }
+    Ok(())
+}
+
+/// Key of a document in the geo index of its collection:
+/// `geohash::{collection}::{geohash}::{doc_id}`.
+fn geo_key(collection: &str, point: &GeoPoint, doc_id: &str) -> String {
+    format!("geohash::{collection}::{}::{doc_id}", point.geohash(GEOHASH_PRECISION))
+}
+
+/// Abort a transaction with a storage error.
+/// Key of the last run time of a trigger.
+fn last_run_key(contract_addr: &str, trigger_id: &str) -> String {
+    format!("{contract_addr}::{trigger_id}")
+}
+
+/// Decode a stored last run time.
+fn decode_last_run(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().unwrap_or_default())
+}
+
+fn abort(err: impl Into<StorageError>) -> ConflictableTransactionError<StorageError> {
+    ConflictableTransactionError::Abort(err.into())
+}
+
+// Collection stats
+impl Sled {
+    /// Recompute the stats of every collection of a project tree from its documents.
+    fn rebuild_collection_stats(tree: &sled::Tree) -> StorageResult<()> {
+        let mut stats: HashMap<String, CollectionStats> = HashMap::new();
+
+        for item in tree.scan_prefix(b"document::") {
+            let (k, v) = item?;
+            let key_str = String::from_utf8(k.to_vec())?;
+
+            // key format: document::{collection}::{doc_id}
+            let Some((collection, _)) = key_str
+                .strip_prefix("document::")
+                .and_then(|rest| rest.split_once("::"))
+            else {
+                continue;
+            };
+
+            let stamp: DocStamp = Codec::decode(&v)?;
+            let entry = stats.entry(format!("collection::{collection}")).or_default();
+            entry.count += 1;
+            entry.last_updated = entry.last_updated.max(stamp.metadata.updated_at);
+        }
+
+        let mut batch = sled::Batch::default();
+        for key in tree.scan_prefix(b"collection::").keys() {
+            batch.remove(key?);
+        }
+        for (key, stats) in stats {
+            batch.insert(key.as_bytes(), &stats.to_bytes());
+        }
+        tree.apply_batch(batch)?;
+        Ok(())
+    }
+}
+
+// Per-project trees
+impl Sled {
+    /// Move documents and attachments from the shared trees of the app stores, where keys are
+    /// prefixed by project, into the tree of their project. Collection stats are recomputed.
+    /// Returns the number of records moved, or to move on a dry run.
+    pub fn migrate_project_trees(&self, dry_run: bool) -> StorageResult<usize> {
+        let mut moved = 0;
+
+        let stores = std::iter::once(&*self.app).chain(self.regions.values().map(|r| &*r.app));
+        for app in stores {
+            let mut projects = HashSet::new();
+
+            // `document::{project_id}::...` in the default tree, `attachment::{project_id}::...`
+            // in the attachments tree
+            let mut sources = vec![("document", (**app).clone())];
+            if app.tree_names().iter().any(|n| n == b"attachments") {
+                sources.push(("attachment", app.open_tree("attachments")?));
+            }
+
+            for (kind, tree) in sources {
+                for item in tree.scan_prefix(format!("{kind}::").as_bytes()) {
+                    let (key, value) = item?;
+                    let key_str = String::from_utf8(key.to_vec())?;
+                    let mut parts = key_str.splitn(3, "::").skip(1);
+                    let (Some(project_id), Some(rest)) = (parts.next(), parts.next()) else {
+                        continue;
+                    };
+
+                    moved += 1;
+                    if dry_run {
+                        continue;
+                    }
+
+                    // Copy then remove, so an interrupted migration can run again
+                    app.open_tree(format!("project::{project_id}"))?
+                        .insert(format!("{kind}::{rest}").as_bytes(), value)?;
+                    tree.remove(&key)?;
+                    projects.insert(project_id.to_string());
+                }
+            }
 
-        // Fetch existing entries (or start with an empty vector)
-        let mut entries: Vec<Metadata> = match self.metadata.get(KEY)? {
-            Some(bytes) => {
-                // Try to deserialize, fallback to empty vec if corrupted
-                serde_json::from_slice(&bytes).unwrap_or_else(|_| Vec::new())
+            if dry_run {
+                continue;
             }
-            None => vec![],
-        };
 
-        // Check if an entry with the same addr already exists
-        if !entries.iter().any(|e| e.addr == addr) {
-            entries.push(Metadata {
-                addr: addr.to_string(),
-                path: path.to_string(),
-            });
+            for project_id in projects {
+                Self::rebuild_collection_stats(&app.open_tree(format!("project::{project_id}"))?)?;
+            }
+            for name in ["attachments", "collections"] {
+                app.drop_tree(name)?;
+            }
+            app.flush()?;
         }
 
-        // Serialize updated entries
-        let bytes = serde_json::to_vec(&entries)
-            .map_err(|e| format!("Failed to serialize entries: {}", e))?;
-
-        // Store and flush
-        self.metadata.insert(KEY, bytes)?;
-        self.metadata.flush()?; // persist immediately
-
-        Ok(())
+        Ok(moved)
     }
 
-    /// Retrieve all stored entries
-    pub fn get_metadata_entries(&self) -> StorageResult<Vec<Metadata>> {
-        const KEY: &str = "HANNAH";
+    /// Move trigger runs from the shared run log into the run log of their project.
+    /// Returns the number of runs moved, or to move on a dry run.
+    pub fn migrate_run_trees(&self, dry_run: bool) -> StorageResult<usize> {
+        if !self.triggers.tree_names().iter().any(|n| n == b"runs") {
+            return Ok(0);
+        }
 
-        match self.metadata.get(KEY)? {
-            Some(bytes) => {
-                let entries: Vec<Metadata> = serde_json::from_slice(&bytes)
-                    .unwrap_or_else(|_| Vec::new());
-                Ok(entries)
+        let runs = self.triggers.open_tree("runs")?;
+        let mut moved = 0;
+
+        // `run::{project_id}::{timestamp}::{run_id}` and `run_id::{project_id}::{run_id}`
+        for item in runs.iter() {
+            let (key, value) = item?;
+            let key_str = String::from_utf8(key.to_vec())?;
+            let mut parts = key_str.splitn(3, "::");
+            let (Some(kind), Some(project_id), Some(rest)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+
+            // Index entries point to the old run keys
+            let value = match kind {
+                "run_id" => match String::from_utf8_lossy(&value).splitn(3, "::").nth(2) {
+                    Some(run_key) => IVec::from(format!("run::{run_key}").as_bytes()),
+                    None => continue,
+                },
+                _ => value,
+            };
+
+            moved += (kind == "run") as usize;
+            if dry_run {
+                continue;
             }
-            None => Ok(vec![]),
+
+            self.triggers
+                .open_tree(format!("runs::{project_id}"))?
+                .insert(format!("{kind}::{rest}").as_bytes(), value)?;
         }
+
+        if !dry_run {
+            self.triggers.drop_tree("runs")?;
+            self.triggers.flush()?;
+        }
+
+        Ok(moved)
     }
 }
 
 #[async_trait]
 impl DocumentStore for Sled {
-    /// Build a namespaced key for storing a document.
-    /// Pattern: `document::{project_id}::{collection}::{doc_id}`
-    fn key(project_id: &str, collection: &str, doc_id: &str) -> String {
-        format!("document::{project_id}::{collection}::{doc_id}")
+    /// Build the key of a document in the tree of its project.
+    /// Pattern: `document::{collection}::{doc_id}`
+    fn key(_project_id: &str, collection: &str, doc_id: &str) -> String {
+        format!("document::{collection}::{doc_id}")
     }
 
     /// Insert a new document into a collection.
@@ -236,58 +2808,23 @@ impl DocumentStore for Sled {
         &self,
         project_id: &str,
         collection: &str,
-        mut doc: Document,
+        doc: Document,
         update: bool,
     ) -> StorageResult<()> {
-        // Unix timestamp
-        let now = Utc::now().timestamp_millis() as u64;
-
-        // Document metadata
-        let metadata = if !update {
-            DocMetadata {
-                created_at: now,
-                updated_at: now,
-                version: None,
-                tags: Default::default(),
-            }
-        } else {
-            DocMetadata {
-                updated_at: now,
-                ..doc.metadata
-            }
-        };
-
-        doc.metadata = metadata;
-
-        let key = <Sled as DocumentStore>::key(project_id, collection, &doc.id);
-        let value = serde_json::to_vec(&doc)?;
-        self.app.insert(key.as_bytes(), value)?;
-
-        // Broadcast the insert event to all subscribed clients
-        self.subscriptions
-            .publish(
-                collection,
-                &doc.id,
-                WsPayload {
-                    op: String::from("insert"),
-                    topic: String::with_capacity(100),
-                    doc: doc.clone(),
-                },
-            )
-            .await;
-
-        Ok(())
+        self.write_document(project_id, collection, doc, update, None).await
     }
 
     /// Fetch a single document by ID.
     fn get(&self, project_id: &str, collection: &str, id: &str) -> StorageResult<Option<Document>> {
-        let key = <Sled as DocumentStore>::key(project_id, collection, id);
-        if let Some(val) = self.app.get(key.as_bytes())? {
-            let doc: Document = serde_json::from_slice(&val)?;
+        let tree = self.project_tree(project_id)?;
+        KeyBuf::document(collection, id, |key| {
+            let Some(val) = tree.get(key)? else {
+                return Ok(None);
+            };
+            let doc: Document = Codec::decode(&val)?;
+            migrate(&tree, key, &val, self.doc_codec, &doc)?;
             Ok(Some(doc))
-        } else {
-            Ok(None)
-        }
+        })
     }
 
     /// Update an existing document.
@@ -298,43 +2835,40 @@ impl DocumentStore for Sled {
 
     /// Delete a document from a collection by ID.
     async fn delete(&self, project_id: &str, collection: &str, id: &str) -> StorageResult<()> {
-        let key = <Self as DocumentStore>::key(project_id, collection, id);
-
-        // Delete and returns the old value (if any)
-        let old_value = self
-            .app
-            .remove(&key)?
-            .map(|ivec| String::from_utf8_lossy(&ivec).to_string());
+        self.remove_document(project_id, collection, id, None).await
+    }
 
-        // Only use the old value to notify subscribers, not in the publish API
-        if let Some(doc) = old_value {
-            if let Ok(doc) = serde_json::from_str(&doc) {
-                self.subscriptions
-                    .publish(
-                        collection,
-                        id,
-                        WsPayload {
-                            op: String::from("delete"),
-                            topic: String::with_capacity(100),
-                            doc,
-                        },
-                    )
-                    .await;
-            }
-        }
+    /// Update a document only if its stored copy satisfies a precondition.
+    async fn update_if(
+        &self,
+        project_id: &str,
+        collection: &str,
+        doc: Document,
+        precondition: &Precondition,
+    ) -> StorageResult<()> {
+        self.write_document(project_id, collection, doc, true, Some(precondition)).await
+    }
 
-        Ok(())
+    /// Delete a document only if its stored copy satisfies a precondition.
+    async fn delete_if(
+        &self,
+        project_id: &str,
+        collection: &str,
+        id: &str,
+        precondition: &Precondition,
+    ) -> StorageResult<()> {
+        self.remove_document(project_id, collection, id, Some(precondition)).await
     }
 
     /// List all documents in a given collection.
-    /// Uses prefix iteration over keys: `document::{project_id}::{collection}::`
+    /// Uses prefix iteration over the project tree: `document::{collection}::`
     fn list(&self, project_id: &str, collection: &str) -> StorageResult<Vec<Document>> {
-        let prefix = format!("document::{project_id}::{collection}::");
+        let tree = self.project_tree(project_id)?;
         let mut docs = Vec::new();
 
-        for item in self.app.scan_prefix(prefix.as_bytes()) {
+        for item in KeyBuf::collection(collection, |prefix| tree.scan_prefix(prefix)) {
             let (_k, v): (IVec, IVec) = item?;
-            let doc: Document = serde_json::from_slice(&v)?;
+            let doc: Document = Codec::decode(&v)?;
             docs.push(doc);
         }
 
@@ -344,60 +2878,43 @@ impl DocumentStore for Sled {
     /// List all collections for a given project, including document count and
     /// latest update timestamp.
     ///
-    /// Scans keys with the prefix: `document::{project_id}::`
+    /// Reads the stats kept on write (`collection::{collection}`), not the documents.
     fn list_collections(&self, project_id: &str) -> StorageResult<Vec<CollectionSummary>> {
-        let prefix = format!("document::{project_id}::");
-        let mut collections = std::collections::HashSet::new();
+        let tree = self.project_tree(project_id)?;
+        let mut summaries = Vec::new();
 
-        // 🧩 1. Extract unique collection names
-        for item in self.app.scan_prefix(prefix.as_bytes()) {
-            let (k, _v): (IVec, IVec) = item?;
+        for item in KeyBuf::project_stats(|prefix| tree.scan_prefix(prefix)) {
+            let (k, v): (IVec, IVec) = item?;
             let key_str = String::from_utf8(k.to_vec())?;
 
-            // key format: document::{project_id}::{collection}::{doc_id}
-            if let Some(parts) = key_str.split("::").collect::<Vec<_>>().get(2) {
-                collections.insert(parts.to_string());
+            // key format: collection::{collection}
+            if let Some((_, name)) = key_str.split_once("::") {
+                let stats = CollectionStats::from_bytes(&v);
+                summaries.push(CollectionSummary {
+                    name: name.to_string(),
+                    count: stats.count as usize,
+                    last_updated: stats.last_updated,
+                });
             }
         }
 
-        // 🧮 2. For each collection, compute stats (count + last_updated)
-        let mut summaries = Vec::new();
-
-        for collection in collections {
-            let (count, last_updated) = self.collection_stats(project_id, &collection)?;
-            summaries.push(CollectionSummary {
-                name: collection,
-                count,
-                last_updated,
-            });
-        }
-
         Ok(summaries)
     }
 
     /// Helper to return stats for a single collection
     fn collection_stats(&self, project_id: &str, collection: &str) -> StorageResult<(usize, u64)> {
-        let prefix = format!("document::{project_id}::{collection}::");
-        let mut count = 0usize;
-        let mut latest_update = 0u64;
+        let tree = self.project_tree(project_id)?;
+        let stats = KeyBuf::stats(collection, |key| tree.get(key))?
+            .map(|v| CollectionStats::from_bytes(&v))
+            .unwrap_or_default();
 
-        for item in self.app.scan_prefix(prefix.as_bytes()) {
-            let (_k, v): (IVec, IVec) = item?;
-            let doc: Document = serde_json::from_slice(&v)?;
-
-            count += 1;
-            if doc.metadata.updated_at > latest_update {
-                latest_update = doc.metadata.updated_at;
-            }
-        }
-
-        Ok((count, latest_update))
+        Ok((stats.count as usize, stats.last_updated))
     }
 
     /// Check if a collection exists for a project.
     fn collection_exists(&self, project_id: &str, name: &str) -> StorageResult<bool> {
-        let prefix = format!("document::{project_id}::{name}::");
-        let mut iter = self.app.scan_prefix(prefix.as_bytes());
+        let tree = self.project_tree(project_id)?;
+        let mut iter = KeyBuf::collection(name, |prefix| tree.scan_prefix(prefix));
         Ok(iter.next().is_some())
     }
 }
@@ -405,8 +2922,17 @@ impl DocumentStore for Sled {
 // Implement ProjectStore for Sled
 impl ProjectStore for Sled {
     fn create(&self, project: &mut Project) -> StorageResult<ApiKey> {
+        // Trees of a deleted project with the same id would be dropped under the new one
+        if self.settings.contains_key(format!("purge:{}", project.id))? {
+            return Err(format!("Project {} is still being deleted, try again later", project.id).into());
+        }
+
         // Generate a random 32-character alphanumeric key.
         let key = util::generate_nonce::<32>();
+        let index = hash_api_key(&key)?;
+
+        // Projects share trees by id, so an id belongs to a single project
+        self.reserve_project_id(&project.id, &index)?;
 
         // addr the API key to be used as project ID
         let encryption_key = env::var("TRIGGR_ENCRYPTION_KEY")?;
@@ -419,19 +2945,24 @@ impl ProjectStore for Sled {
         let bytes = serde_json::to_vec(&project)
             .map_err(|e| format!("Failed to serialize project: {}", e))?;
 
-        // Store in the `projects` tree
-        self.projects
-            .insert(key.as_bytes(), bytes)
-            .map_err(|e| e.to_string())?;
+        // Store in the `projects` tree, indexed by the hash of the key
+        if let Err(e) = self.projects.insert(index.as_bytes(), bytes) {
+            self.release_project_id(&project.id, &index)?;
+            return Err(e.into());
+        }
 
         // Store the new project in relation to a user.
         self.add_user_project(&project.owner.clone(), project.clone())?;
 
+        // The key is only shown once, so the project must survive a crash
+        self.flush.projects.sync()?;
+        self.flush.users.sync()?;
+
         Ok(key)
     }
 
     fn get(&self, key: &str) -> StorageResult<Option<Project>> {
-        match self.projects.get(key.as_bytes()) {
+        match self.projects.get(hash_api_key(key)?.as_bytes()) {
             // Found key → deserialize into Project
             Ok(Some(ivec)) => {
                 let project: Project = serde_json::from_slice(&ivec)
@@ -445,11 +2976,13 @@ impl ProjectStore for Sled {
         }
     }
 
-    fn delete(&self, key: &str, owner: &str) -> StorageResult<()> {
+    fn delete(&self, key: &str, owner: &str) -> StorageResult<Project> {
+        let index = hash_api_key(key)?;
+
         // Look up the project
         let Some(bytes) = self
             .projects
-            .get(key.as_bytes())
+            .get(index.as_bytes())
             .map_err(|e| e.to_string())?
         else {
             return Err(format!("Project with key {} not found", key).into());
@@ -464,17 +2997,42 @@ impl ProjectStore for Sled {
             return Err("Unauthorized: owner mismatch".into());
         }
 
+        // Trees, triggers and service accounts belong to a project id. A project sharing its id
+        // with an older one keeps them for that one, the last project of the id drops them.
+        let shared = self.project_id_shared(&project.id, &index)?;
+        if shared {
+            tracing::warn!(
+                "Project {} of {owner} shares its id with another project, its data is kept",
+                project.id
+            );
+        } else {
+            // Its trees are dropped by the purge job, which resumes after a restart
+            self.settings
+                .insert(format!("purge:{}", project.id), &[])?;
+            self.settings.flush()?;
+        }
+
         // Delete the project
         self.projects
-            .remove(key.as_bytes())
+            .remove(index.as_bytes())
             .map_err(|e| e.to_string())?;
 
+        if !shared {
+            // Its triggers stop firing right away
+            self.delete_project_triggers(&project.id, &project.contract_address)?;
+
+            // And its service accounts can no longer authenticate
+            for account in self.list_service_accounts(&project.id)? {
+                self.service_accounts_tree()?.remove(account.id.as_bytes())?;
+            }
+        }
+
+        // The pending purge keeps the id from being reused until the trees are dropped
+        self.release_project_id(&project.id, &index)?;
+
         // Load user projects
         let mut projects: Vec<Project> = match self.users.get(owner.as_bytes())? {
-            Some(value) => {
-                // Try to deserialize, fallback to empty vec if corrupted
-                serde_json::from_slice(&value).unwrap_or_else(|_| Vec::new())
-            }
+            Some(value) => self.decode_stored("user projects", owner.as_bytes(), &value)?,
             None => Vec::new(),
         };
 
@@ -485,6 +3043,36 @@ impl ProjectStore for Sled {
         let serialized = serde_json::to_vec(&projects)
             .map_err(|e| format!("Failed to serialize user projects: {}", e))?;
         self.users.insert(owner.as_bytes(), serialized)?;
+        self.flush.projects.written()?;
+        self.flush.users.written()?;
+
+        Ok(project)
+    }
+
+    fn update(&self, key: &str, project: &Project) -> StorageResult<()> {
+        let index = hash_api_key(key)?;
+        if !self.projects.contains_key(index.as_bytes())? {
+            return Err(StorageError::NotFound(format!(
+                "Project {} not found",
+                project.id
+            )));
+        }
+
+        let bytes = serde_json::to_vec(project)
+            .map_err(|e| format!("Failed to serialize project: {}", e))?;
+        self.projects.insert(index.as_bytes(), bytes)?;
+
+        // Keep the owner's copy in sync
+        let mut projects = self.get_user_projects(&project.owner)?;
+        for p in projects.iter_mut().filter(|p| p.id == project.id) {
+            *p = project.clone();
+        }
+
+        let serialized = serde_json::to_vec(&projects)
+            .map_err(|e| format!("Failed to serialize user projects: {}", e))?;
+        self.users.insert(project.owner.as_bytes(), serialized)?;
+        self.flush.projects.written()?;
+        self.flush.users.written()?;
 
         Ok(())
     }
@@ -492,11 +3080,7 @@ impl ProjectStore for Sled {
     /// Get all projects of a user
     fn get_user_projects(&self, user_id: &str) -> StorageResult<Vec<Project>> {
         match self.users.get(user_id)? {
-            Some(value) => {
-                let projects: Vec<Project> = serde_json::from_slice(&value)
-                    .unwrap_or_else(|_| Vec::new());
-                Ok(projects)
-            }
+            Some(value) => self.decode_stored("user projects", user_id.as_bytes(), &value),
             None => Ok(Vec::new()),
         }
     }
@@ -504,33 +3088,47 @@ impl ProjectStore for Sled {
 
 impl TriggerStore for Sled {
     /// Store (append) a new trigger for a given contract.
+    /// The stored triggers are read again within a transaction, so a save never reverts
+    /// another change of the contract's triggers. The last run time of a replaced trigger is kept.
     fn store_trigger(&self, contract_addr: &str, trigger: Trigger) -> StorageResult<()> {
         let key = contract_addr.as_bytes();
-    
-        // Try to load existing triggers, fallback to empty vec on error
-        let mut triggers: Vec<Trigger> = match self.triggers.get(key)? {
-            Some(bytes) => match serde_json::from_slice(&bytes) {
-                Ok(list) => list,
-                Err(_) => {
-                    // corrupted data, start fresh
-                    vec![]
-                }
-            },
-            None => vec![],
-        };
-    
+        tenancy::check_owner("trigger", &trigger.project_id);
+
         // Add or replace trigger with same ID
-        if let Some(existing) = triggers.iter_mut().find(|t| t.id == trigger.id) {
-            *existing = trigger;
-        } else {
-            triggers.push(trigger);
+        let replaced = self.triggers.transaction(|tx| {
+            let mut triggers: Vec<Trigger> = match tx.get(key)? {
+                Some(bytes) => self.decode_stored("triggers", key, &bytes).map_err(abort)?,
+                None => vec![],
+            };
+
+            let replaced = match triggers.iter_mut().find(|t| t.id == trigger.id) {
+                Some(existing) => {
+                    let last_run = trigger.last_run.max(existing.last_run);
+                    let trigger = Trigger { last_run, ..trigger.clone() };
+                    Some(std::mem::replace(existing, trigger))
+                }
+                None => {
+                    triggers.push(trigger.clone());
+                    None
+                }
+            };
+
+            tx.insert(key, self.trigger_codec.encode(&triggers).map_err(abort)?)?;
+            Ok(replaced)
+        })?;
+
+        match replaced {
+            Some(replaced) => {
+                tenancy::check_owner("replaced trigger", &replaced.project_id);
+                self.unindex_trigger(contract_addr, &replaced)?;
+            }
+            // A new trigger has never run, whatever a deleted one of the same id did
+            None => {
+                self.last_runs.remove(last_run_key(contract_addr, &trigger.id))?;
+            }
         }
-    
-        // Serialize and store
-        let encoded = serde_json::to_vec(&triggers)
-            .map_err(|e| format!("Failed to serialize triggers: {}", e))?;
-        self.triggers.insert(key, encoded)?;
-        self.triggers.flush()?;
+        self.index_trigger(contract_addr, &trigger)?;
+        self.flush.triggers.written()?;
         Ok(())
     }
 
@@ -542,12 +3140,16 @@ impl TriggerStore for Sled {
             StorageError::NotFound(format!("No triggers found for contract {contract_addr}"))
         })?;
 
-        let triggers: Vec<Trigger> = serde_json::from_slice(&bytes)
+        let triggers: Vec<Trigger> = Codec::decode(&bytes)
             .map_err(|e| format!("Failed to deserialize triggers: {}", e))?;
 
-        triggers.into_iter().find(|t| t.id == name).ok_or_else(|| {
+        let mut trigger = triggers.into_iter().find(|t| t.id == name).ok_or_else(|| {
             StorageError::NotFound(format!("No trigger with id {name} for {contract_addr}"))
-        })
+        })?;
+        tenancy::check_owner("trigger", &trigger.project_id);
+        self.load_last_runs(contract_addr, std::slice::from_mut(&mut trigger))?;
+
+        Ok(trigger)
     }
 
     /// Update active/inactive state of a specific trigger.
@@ -563,7 +3165,7 @@ impl TriggerStore for Sled {
             StorageError::NotFound(format!("No triggers found for contract {contract_addr}"))
         })?;
 
-        let mut triggers: Vec<Trigger> = serde_json::from_slice(&bytes)
+        let mut triggers: Vec<Trigger> = Codec::decode(&bytes)
             .map_err(|e| format!("Failed to deserialize triggers: {}", e))?;
 
         let Some(trigger) = triggers.iter_mut().find(|t| t.id == trigger_id) else {
@@ -572,12 +3174,91 @@ impl TriggerStore for Sled {
             )));
         };
 
+        tenancy::check_owner("trigger", &trigger.project_id);
         trigger.active = active;
 
-        let encoded = serde_json::to_vec(&triggers)
+        let encoded = self.trigger_codec.encode(&triggers)
             .map_err(|e| format!("Failed to serialize triggers: {}", e))?;
         self.triggers.insert(key, encoded)?;
-        self.triggers.flush()?;
+        self.flush.triggers.written()?;
+        Ok(())
+    }
+
+    /// Update the active/inactive state of several triggers of a project in a single write.
+    /// The write is a compare-and-swap, so concurrent trigger saves are never lost, and a tag
+    /// selects the triggers carrying it in the written triggers.
+    fn set_triggers_state(
+        &self,
+        contract_addr: &str,
+        project_id: &str,
+        trigger_ids: &[String],
+        tag: Option<&str>,
+        active: bool,
+    ) -> StorageResult<Vec<String>> {
+        let key = contract_addr.as_bytes();
+
+        let updated = loop {
+            let bytes = self.triggers.get(key)?.ok_or_else(|| {
+                StorageError::NotFound(format!("No triggers found for contract {contract_addr}"))
+            })?;
+
+            let mut triggers: Vec<Trigger> = Codec::decode(&bytes)
+                .map_err(|e| format!("Failed to deserialize triggers: {}", e))?;
+
+            // Every trigger must exist before any of them changes
+            if let Some(missing) = trigger_ids.iter().find(|id| {
+                !triggers
+                    .iter()
+                    .any(|t| t.id == **id && t.project_id == project_id)
+            }) {
+                return Err(StorageError::NotFound(format!(
+                    "Trigger {missing} not found"
+                )));
+            }
+
+            let mut updated = trigger_ids.to_vec();
+            for trigger in triggers.iter_mut().filter(|t| t.project_id == project_id) {
+                let tagged = tag.is_some_and(|tag| trigger.has_tag(tag));
+                if !tagged && !trigger_ids.contains(&trigger.id) {
+                    continue;
+                }
+
+                tenancy::check_owner("trigger", &trigger.project_id);
+                trigger.active = active;
+                if !updated.contains(&trigger.id) {
+                    updated.push(trigger.id.clone());
+                }
+            }
+
+            let encoded = self.trigger_codec.encode(&triggers)
+                .map_err(|e| format!("Failed to serialize triggers: {}", e))?;
+            if self
+                .triggers
+                .compare_and_swap(key, Some(bytes), Some(encoded))?
+                .is_ok()
+            {
+                break updated;
+            }
+        };
+
+        self.flush.triggers.written()?;
+        Ok(updated)
+    }
+
+    /// Record when a trigger last ran, apart from the stored triggers so they are not rewritten
+    /// by runs (their bytes are the key of the evaluation plans).
+    fn record_trigger_run(
+        &self,
+        contract_addr: &str,
+        trigger_id: &str,
+        at: u64,
+    ) -> StorageResult<()> {
+        self.last_runs
+            .fetch_and_update(last_run_key(contract_addr, trigger_id), |stored| {
+                Some(stored.map_or(at, |v| decode_last_run(v).max(at)).to_be_bytes().to_vec())
+            })?;
+
+        self.flush.triggers.written()?;
         Ok(())
     }
 
@@ -589,10 +3270,14 @@ impl TriggerStore for Sled {
             StorageError::NotFound(format!("No triggers found for contract {contract_addr}"))
         })?;
 
-        let mut triggers: Vec<Trigger> = serde_json::from_slice(&bytes)
+        let mut triggers: Vec<Trigger> = Codec::decode(&bytes)
             .map_err(|e| format!("Failed to deserialize triggers: {}", e))?;
 
         let len_before = triggers.len();
+        for trigger in triggers.iter().filter(|t| t.id == trigger_id) {
+            tenancy::check_owner("trigger", &trigger.project_id);
+            self.unindex_trigger(contract_addr, trigger)?;
+        }
         triggers.retain(|t| t.id != trigger_id);
 
         if triggers.len() == len_before {
@@ -601,26 +3286,476 @@ impl TriggerStore for Sled {
             )));
         }
 
-        let encoded = serde_json::to_vec(&triggers)
+        let encoded = self.trigger_codec.encode(&triggers)
             .map_err(|e| format!("Failed to serialize triggers: {}", e))?;
         self.triggers.insert(key, encoded)?;
-        self.triggers.flush()?;
+        self.last_runs.remove(last_run_key(contract_addr, trigger_id))?;
+        self.flush.triggers.written()?;
         Ok(())
     }
 
+    /// Search the triggers of a project. Every word of the query must prefix a token of the
+    /// trigger's id, DSL, description or tags; results are sorted by contract and id.
+    fn search_triggers(
+        &self,
+        project_id: &str,
+        query: &str,
+    ) -> StorageResult<Vec<(String, Trigger)>> {
+        let mut matches: Option<std::collections::BTreeSet<(String, String)>> = None;
+
+        for word in tokenize(query) {
+            let prefix = format!("{project_id}::{word}");
+            let mut found = std::collections::BTreeSet::new();
+            for item in self.trigger_index.scan_prefix(prefix.as_bytes()) {
+                let (key, _) = item?;
+                let key = String::from_utf8_lossy(&key).to_string();
+                // Keys are `{project_id}::{token}::{contract_addr}::{trigger_id}`
+                let mut parts = key.splitn(4, "::").skip(2);
+                if let (Some(contract_addr), Some(trigger_id)) = (parts.next(), parts.next()) {
+                    found.insert((contract_addr.to_string(), trigger_id.to_string()));
+                }
+            }
+
+            matches = Some(match matches {
+                Some(previous) => previous.intersection(&found).cloned().collect(),
+                None => found,
+            });
+        }
+
+        let mut triggers = Vec::new();
+        for (contract_addr, trigger_id) in matches.unwrap_or_default() {
+            // Skip index entries of triggers deleted in the meantime
+            let trigger = self
+                .get_trigger(&contract_addr, &trigger_id)
+                .ok()
+                .filter(|trigger| trigger.project_id == project_id);
+            if let Some(trigger) = trigger {
+                triggers.push((contract_addr, trigger));
+            }
+        }
+
+        Ok(triggers)
+    }
+
     /// List all triggers for a specific contract address.
     fn list_triggers(&self, contract_addr: &str) -> StorageResult<Vec<Trigger>> {
         let key = contract_addr.as_bytes();
 
         let Some(bytes) = self.triggers.get(key)? else {
-            return Err(StorageError::NotFound(format!(
-                "No triggers found for contract {contract_addr}"
-            )));
+            return Ok(vec![]);
         };
 
-        let triggers: Vec<Trigger> = serde_json::from_slice(&bytes)
-            .map_err(|e| StorageError::Other(e.to_string()))?;
+        let mut triggers: Vec<Trigger> = Codec::decode(&bytes)?;
+        for trigger in &triggers {
+            tenancy::check_owner("trigger", &trigger.project_id);
+        }
+        migrate(&self.triggers, key, &bytes, self.trigger_codec, &triggers)?;
+        self.load_last_runs(contract_addr, &mut triggers)?;
 
         Ok(triggers)
     }
-}
\ No newline at end of file
+
+    fn contract_known(&self, contract_addr: &str) -> StorageResult<bool> {
+        Ok(self.triggers.contains_key(contract_addr.as_bytes())?)
+    }
+
+    /// Record a trigger run in the run log of its project.
+    /// Key pattern: `run::{timestamp}::{run_id}` so runs sort chronologically.
+    fn store_run(&self, run: &TriggerRun, retention: u64) -> StorageResult<()> {
+        tenancy::check_owner("run", &run.project_id);
+
+        let key = format!("run::{:020}::{}", run.timestamp, run.id);
+
+        // Secondary index to find a run by id
+        let index_key = format!("run_id::{}", run.id);
+
+        let mut batch = sled::Batch::default();
+        batch.insert(key.as_bytes(), serde_json::to_vec(run)?);
+        batch.insert(index_key.as_bytes(), key.as_bytes());
+
+        // Expired runs sort first, so only they are scanned
+        let runs = self.runs_tree(&run.project_id)?;
+        if retention > 0 {
+            let end = format!("run::{:020}", run.timestamp.saturating_sub(retention));
+            for expired in runs.range(b"run::".as_slice()..end.as_bytes()).keys() {
+                let expired = expired?;
+                if let Some((_, run_id)) = String::from_utf8_lossy(&expired).rsplit_once("::") {
+                    batch.remove(format!("run_id::{run_id}").as_bytes());
+                }
+                batch.remove(expired);
+            }
+        }
+        runs.apply_batch(batch)?;
+
+        Ok(())
+    }
+
+    /// Return a trigger run of a project.
+    fn get_run(&self, project_id: &str, run_id: &str) -> StorageResult<Option<TriggerRun>> {
+        let runs = self.runs_tree(project_id)?;
+
+        let Some(key) = runs.get(format!("run_id::{run_id}").as_bytes())? else {
+            return Ok(None);
+        };
+
+        match runs.get(key)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// List the runs of a project, most recent first.
+    fn list_runs(&self, project_id: &str, limit: usize) -> StorageResult<Vec<TriggerRun>> {
+        let mut runs = Vec::new();
+
+        for item in self.runs_tree(project_id)?.scan_prefix(b"run::").rev().take(limit) {
+            let (_k, v) = item?;
+            runs.push(serde_json::from_slice(&v)?);
+        }
+
+        Ok(runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempStore;
+
+    const CONTRACT: &str = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+
+    fn trigger(id: &str) -> Trigger {
+        Trigger {
+            id: id.to_string(),
+            description: String::new(),
+            project_id: "project".to_string(),
+            dsl: String::new(),
+            rules: Vec::new(),
+            active: true,
+            created: 1,
+            last_run: 0,
+            tags: Vec::new(),
+            webhook: None,
+            owner: None,
+        }
+    }
+
+    fn project(id: &str, owner: &str) -> Project {
+        Project {
+            id: id.to_string(),
+            owner: owner.to_string(),
+            contract_address: CONTRACT.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn projects_cant_be_moved_away_from_their_data() {
+        let mut temp = TempStore::new().unwrap();
+        let tree = temp.store.app.open_tree("project::demo").unwrap();
+        tree.insert("doc", "data").unwrap();
+        temp.store.check_regions().unwrap();
+
+        let region = temp.dir.path().join("hdd").to_string_lossy().to_string();
+        temp.store.regions = Arc::new(HashMap::from([(
+            "hdd".to_string(),
+            Region::open(&region).unwrap(),
+        )]));
+        temp.store.project_regions =
+            Arc::new(HashMap::from([("demo".to_string(), "hdd".to_string())]));
+        assert!(temp.store.check_regions().is_err());
+
+        // Projects without data may move
+        tree.clear().unwrap();
+        temp.store.check_regions().unwrap();
+    }
+
+    #[test]
+    fn unreadable_queued_events_are_quarantined() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.events.insert(7u64.to_be_bytes(), b"not an event".as_slice()).unwrap();
+
+        let (seq, event) = store.next_event(0).unwrap().unwrap();
+        assert_eq!(seq, 7);
+        assert!(event.is_err());
+
+        store.quarantine_event(seq).unwrap();
+        assert!(store.next_event(0).unwrap().is_none());
+        assert_eq!(
+            store.quarantined_events.get(7u64.to_be_bytes()).unwrap().as_deref(),
+            Some(b"not an event".as_slice())
+        );
+    }
+
+    #[test]
+    fn project_ids_belong_to_one_project() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.create(&mut project("demo", "alice")).unwrap();
+
+        let taken = store.create(&mut project("demo", "bob"));
+        assert!(matches!(taken, Err(StorageError::InvalidField { .. })));
+        assert!(store.get_user_projects("bob").unwrap().is_empty());
+        store.create(&mut project("other", "bob")).unwrap();
+    }
+
+    #[test]
+    fn a_deleted_project_id_can_be_reused_once_purged() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        let key = store.create(&mut project("demo", "alice")).unwrap();
+        ProjectStore::delete(store, &key, "alice").unwrap();
+
+        assert!(store.create(&mut project("demo", "bob")).is_err());
+        store.purge_projects().unwrap();
+        store.create(&mut project("demo", "bob")).unwrap();
+    }
+
+    #[test]
+    fn deleting_a_project_keeps_the_data_of_another_owner_with_its_id() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        let alice_key = store.create(&mut project("demo", "alice")).unwrap();
+        store
+            .store_trigger(CONTRACT, Trigger { project_id: "demo".to_string(), ..trigger("a") })
+            .unwrap();
+
+        // A project of bob with the same id, from before ids were reserved
+        let bob_key = "bob-project-key";
+        let bob = project("demo", "bob");
+        let bytes = serde_json::to_vec(&bob).unwrap();
+        store.projects.insert(hash_api_key(bob_key).unwrap(), bytes).unwrap();
+        store.add_user_project("bob", bob).unwrap();
+
+        ProjectStore::delete(store, bob_key, "bob").unwrap();
+        assert_eq!(store.purge_projects().unwrap(), 0);
+        assert_eq!(store.project_triggers("demo", CONTRACT).unwrap().len(), 1);
+        assert!(ProjectStore::get(store, &alice_key).unwrap().is_some());
+
+        // The last project of the id takes its data along
+        ProjectStore::delete(store, &alice_key, "alice").unwrap();
+        assert!(store.project_triggers("demo", CONTRACT).unwrap().is_empty());
+        assert_eq!(store.purge_projects().unwrap(), 1);
+    }
+
+    #[test]
+    fn recording_a_run_keeps_state_changes_made_meanwhile() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.store_trigger(CONTRACT, trigger("a")).unwrap();
+        store.store_trigger(CONTRACT, trigger("b")).unwrap();
+
+        // The triggers are disabled while "a" runs
+        let ids = ["a".to_string(), "b".to_string()];
+        store.set_triggers_state(CONTRACT, "project", &ids, None, false).unwrap();
+        store.record_trigger_run(CONTRACT, "a", 42).unwrap();
+
+        let a = store.get_trigger(CONTRACT, "a").unwrap();
+        assert!(!a.active);
+        assert_eq!(a.last_run, 42);
+        assert!(!store.get_trigger(CONTRACT, "b").unwrap().active);
+    }
+
+    #[test]
+    fn recording_a_run_does_not_bring_back_a_deleted_trigger() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.store_trigger(CONTRACT, trigger("a")).unwrap();
+        store.store_trigger(CONTRACT, trigger("b")).unwrap();
+
+        store.delete_trigger(CONTRACT, "a").unwrap();
+        store.record_trigger_run(CONTRACT, "a", 42).unwrap();
+        store.record_trigger_run("unknown", "a", 42).unwrap();
+
+        let ids: Vec<String> = store
+            .list_triggers(CONTRACT)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(ids, ["b"]);
+        assert!(!store.contract_known("unknown").unwrap());
+    }
+
+    #[test]
+    fn retagging_a_trigger_survives_its_run() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.store_trigger(CONTRACT, trigger("a")).unwrap();
+        store.record_trigger_run(CONTRACT, "a", 42).unwrap();
+
+        // The trigger is saved with new tags while it runs again
+        let tags = vec!["alerts".to_string()];
+        store
+            .store_trigger(CONTRACT, Trigger { tags, ..trigger("a") })
+            .unwrap();
+        store.record_trigger_run(CONTRACT, "a", 43).unwrap();
+
+        let a = store.get_trigger(CONTRACT, "a").unwrap();
+        assert!(a.has_tag("alerts"));
+        assert_eq!(a.last_run, 43);
+        let found = store.search_triggers("project", "alerts").unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+    #[test]
+    fn saving_a_trigger_keeps_its_last_run() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.store_trigger(CONTRACT, trigger("a")).unwrap();
+        store.record_trigger_run(CONTRACT, "a", 42).unwrap();
+
+        store.store_trigger(CONTRACT, trigger("a")).unwrap();
+
+        assert_eq!(store.get_trigger(CONTRACT, "a").unwrap().last_run, 42);
+    }
+
+    #[test]
+    fn bulk_state_by_tag_uses_the_current_tags() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        let alerts = |id: &str| Trigger {
+            tags: vec!["Alerts".to_string()],
+            ..trigger(id)
+        };
+        store.store_trigger(CONTRACT, alerts("a")).unwrap();
+        store.store_trigger(CONTRACT, trigger("b")).unwrap();
+        store.store_trigger(CONTRACT, trigger("c")).unwrap();
+        store.store_trigger(CONTRACT, alerts("b")).unwrap();
+
+        let ids = ["c".to_string()];
+        let updated = store
+            .set_triggers_state(CONTRACT, "project", &ids, Some("alerts"), false)
+            .unwrap();
+
+        assert_eq!(updated, ["c", "a", "b"]);
+        let triggers = store.list_triggers(CONTRACT).unwrap();
+        assert!(triggers.iter().all(|t| !t.active));
+    }
+
+    #[test]
+    fn bulk_state_changes_nothing_if_a_trigger_is_missing() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.store_trigger(CONTRACT, trigger("a")).unwrap();
+
+        let ids = ["a".to_string(), "gone".to_string()];
+        let result = store.set_triggers_state(CONTRACT, "project", &ids, None, false);
+
+        assert!(matches!(result, Err(StorageError::NotFound(_))));
+        assert!(store.get_trigger(CONTRACT, "a").unwrap().active);
+    }
+
+    #[test]
+    fn runs_keep_the_evaluation_plan() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.store_trigger(CONTRACT, trigger("a")).unwrap();
+
+        let plans = crate::plan::PlanCache::default();
+        let plan = plans.get(store, CONTRACT).unwrap().unwrap();
+        let stored = store.triggers.get(CONTRACT).unwrap();
+        store.record_trigger_run(CONTRACT, "a", 42).unwrap();
+
+        assert_eq!(store.triggers.get(CONTRACT).unwrap(), stored);
+        assert!(Arc::ptr_eq(&plans.get(store, CONTRACT).unwrap().unwrap(), &plan));
+        assert_eq!(store.list_triggers(CONTRACT).unwrap()[0].last_run, 42);
+
+        // Changing the triggers does rebuild it
+        store.set_trigger_state(CONTRACT, "a", false).unwrap();
+        assert!(!Arc::ptr_eq(&plans.get(store, CONTRACT).unwrap().unwrap(), &plan));
+    }
+
+    #[test]
+    fn a_recreated_trigger_has_not_run() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.store_trigger(CONTRACT, trigger("a")).unwrap();
+        store.record_trigger_run(CONTRACT, "a", 42).unwrap();
+
+        store.delete_trigger(CONTRACT, "a").unwrap();
+        store.store_trigger(CONTRACT, trigger("a")).unwrap();
+
+        assert_eq!(store.get_trigger(CONTRACT, "a").unwrap().last_run, 0);
+    }
+
+    #[test]
+    fn last_run_never_goes_back() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.store_trigger(CONTRACT, trigger("a")).unwrap();
+
+        store.record_trigger_run(CONTRACT, "a", 42).unwrap();
+        store.record_trigger_run(CONTRACT, "a", 7).unwrap();
+
+        assert_eq!(store.get_trigger(CONTRACT, "a").unwrap().last_run, 42);
+    }
+
+    #[test]
+    fn deleting_an_attachment_removes_its_info_and_data() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        let info = AttachmentInfo {
+            key: "avatar".to_string(),
+            content_type: "image/png".to_string(),
+            size: 3,
+            uploaded_at: 1,
+        };
+        store.put_attachment("p", "users", "alice", info, b"png").unwrap();
+
+        assert!(store.delete_attachment("p", "users", "alice", "avatar").unwrap());
+        let tree = store.project_tree("p").unwrap();
+        let prefix = Sled::attachment_prefix("users", "alice");
+        assert_eq!(tree.scan_prefix(prefix.as_bytes()).count(), 0);
+        assert!(!store.delete_attachment("p", "users", "alice", "avatar").unwrap());
+    }
+
+    #[test]
+    fn runs_expire_after_the_retention() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        let run = |id: &str, timestamp: u64| TriggerRun {
+            id: id.to_string(),
+            trigger_id: "a".to_string(),
+            project_id: "project".to_string(),
+            contract_addr: CONTRACT.to_string(),
+            event: EventData {
+                event_name: "Transfer".to_string(),
+                fields: HashMap::new(),
+                raw: None,
+                scores: HashMap::new(),
+                trace: None,
+                namespace: Namespace::Live,
+                block: None,
+                block_number: None,
+            },
+            actions: 0,
+            timestamp,
+            error: None,
+        };
+
+        store.store_run(&run("old", 1_000), 500).unwrap();
+        store.store_run(&run("kept", 1_400), 500).unwrap();
+        store.store_run(&run("new", 1_600), 500).unwrap();
+
+        let ids: Vec<String> = store
+            .list_runs("project", 10)
+            .unwrap()
+            .into_iter()
+            .map(|run| run.id)
+            .collect();
+        assert_eq!(ids, ["new", "kept"]);
+        assert!(store.get_run("project", "old").unwrap().is_none());
+    }
+
+    #[test]
+    fn a_changed_api_key_salt_is_refused() {
+        let temp = TempStore::new().unwrap();
+        let store = &temp.store;
+        store.check_api_key_salt().unwrap();
+
+        store.settings.insert("api_key_salt", "another salt").unwrap();
+        assert!(store.check_api_key_salt().is_err());
+    }
+}
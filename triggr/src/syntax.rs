@@ -0,0 +1,1456 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the lexer and parser of the trigger DSL.
+// Scripts are split into tokens (words, strings, targets and symbols, without whitespace and
+// comments), then parsed by recursive descent into the events of the script and the statements of
+// its main function. Conditions and actions are parsed into the `Condition` and `Action` types of
+// `dsl`, which turns the statements into rules.
+
+use std::{fmt, str::FromStr};
+
+use bigdecimal::BigDecimal;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use utoipa::ToSchema;
+
+use crate::{
+    anomaly::DEFAULT_ANOMALY_THRESHOLD,
+    dsl::{Action, Condition, DslParser, EventDefinition, Pattern},
+    expr::{self, Comparison, Expr, Operator},
+    name::Name,
+    util::{generate_uuid, is_uuid},
+};
+
+/// Words that can't name a variable.
+const RESERVED: [&str; 9] = ["events", "let", "if", "else", "match", "true", "false", "null", "_"];
+
+/// Max nesting of blocks, conditions and expressions, so parsing can't overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Symbols of the DSL, longest first so `>=` is not read as `>`.
+const SYMBOLS: [&str; 27] = [
+    "=>", "==", "!=", ">=", "<=", "&&", "||", "!~", ">", "<", "=", "!", "~", "+", "-", "*", "/",
+    "%", "(", ")", "{", "}", "[", "]", ",", ":", ";",
+];
+
+/// Statement of the main function of a script.
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Action(Action),
+    /// `if ... else if ... else ...`: the branches, then the else block
+    If {
+        branches: Vec<Branch>,
+        otherwise: Option<Vec<Stmt>>,
+    },
+    /// `match events { ... }`: an arm per event, `_` for the events without an arm
+    Match(Vec<Arm>),
+}
+
+/// Branch of an if/else chain.
+#[derive(Debug, Clone)]
+pub struct Branch {
+    pub condition: Condition,
+    pub block: Vec<Stmt>,
+    /// Offset of the condition in the script
+    pub at: usize,
+}
+
+/// Arm of a match on the events.
+#[derive(Debug, Clone)]
+pub struct Arm {
+    /// Event the arm handles, or `_`
+    pub event: String,
+    pub block: Vec<Stmt>,
+    /// Offset of the arm in the script
+    pub at: usize,
+}
+
+/// Syntax tree of a script.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub events: Vec<EventDefinition>,
+    /// Statements of the main function
+    pub main: Vec<Stmt>,
+}
+
+/// Error in a script, with where it was found so the console can highlight it.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ParseError {
+    pub message: String,
+    /// Line of the error, from 1
+    pub line: usize,
+    /// Column of the error (in characters), from 1
+    pub column: usize,
+    /// Line of the script the error is on
+    pub snippet: String,
+}
+
+impl ParseError {
+    /// Locate an error at an offset of a script.
+    pub fn at(input: &str, offset: usize, message: impl Into<String>) -> Self {
+        let offset = offset.min(input.len());
+        let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |i| offset + i);
+
+        Self {
+            message: message.into(),
+            line: input[..offset].matches('\n').count() + 1,
+            column: input[line_start..offset].chars().count() + 1,
+            snippet: input[line_start..line_end].trim_end().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+/// Parse a script: its `const events = [...]` declaration and its `fn main(events) { ... }`.
+pub fn parse_script(input: &str) -> Result<Program, ParseError> {
+    let mut parser =
+        Parser::new(input).map_err(|(offset, e)| ParseError::at(input, offset, e))?;
+    parser
+        .script()
+        .map_err(|e| ParseError::at(input, parser.offset(), e))
+}
+
+/// Parse a condition, e.g. `status == "flagged" && total > 100`.
+/// Fields are read as written: trigger conditions name them `events.<Event>.<field>`.
+pub fn parse_condition(input: &str) -> Result<Condition, String> {
+    let mut parser = Parser::new(input).map_err(|(_, e)| e)?;
+    let condition = parser.condition()?;
+    parser.finish()?;
+    Ok(condition)
+}
+
+/// Parse an arithmetic expression, e.g. `amount / 1000000000000` or `(fee + tip) * 2`.
+pub fn parse_expr(input: &str) -> Result<Expr, String> {
+    let mut parser = Parser::new(input).map_err(|(_, e)| e)?;
+    let expr = parser.sum()?;
+    match parser.at_end() {
+        true => Ok(expr),
+        false => Err(format!("Invalid expression '{}'", input.trim())),
+    }
+}
+
+/// Token of the DSL.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Keyword, name, field path or number
+    Word(String),
+    /// Quoted string, without its quotes
+    Str(String),
+    /// Target of an action, without its `@`: `collection:id`
+    Target(String),
+    Symbol(&'static str),
+}
+
+/// A token and where it is in the input.
+#[derive(Debug)]
+struct Lexeme {
+    token: Token,
+    start: usize,
+    end: usize,
+    line: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Split an input into tokens. Whitespace and comments (`// ...` and `/* ... */`) are skipped.
+/// Errors come with their offset.
+fn tokenize(input: &str) -> Result<Vec<Lexeme>, (usize, String)> {
+    let mut lexemes = Vec::new();
+    let mut line = 1;
+    let mut pos = 0;
+
+    while let Some(c) = input[pos..].chars().next() {
+        let rest = &input[pos..];
+        let (start, start_line) = (pos, line);
+
+        let token = if c.is_whitespace() {
+            line += usize::from(c == '\n');
+            pos += c.len_utf8();
+            continue;
+        } else if rest.starts_with("//") {
+            pos += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        } else if rest.starts_with("/*") {
+            let end = rest
+                .find("*/")
+                .ok_or((pos, "Unterminated comment".to_string()))?;
+            line += rest[..end].matches('\n').count();
+            pos += end + 2;
+            continue;
+        } else if c == '"' || c == '\'' {
+            let (text, len) =
+                scan_string(rest, c).ok_or((pos, "Unterminated string".to_string()))?;
+            line += rest[..len].matches('\n').count();
+            pos += len;
+            Token::Str(text)
+        } else if c == '@' {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || "{}(),;".contains(c))
+                .unwrap_or(rest.len());
+            pos += len;
+            Token::Target(rest[1..len].to_string())
+        } else if rest.starts_with("${") {
+            // `${ ... }` is the same as `{ ... }`
+            pos += 2;
+            Token::Symbol("{")
+        } else if is_word_char(c) {
+            let len = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+            pos += len;
+            Token::Word(rest[..len].to_string())
+        } else if let Some(symbol) = SYMBOLS.into_iter().find(|s| rest.starts_with(s)) {
+            pos += symbol.len();
+            Token::Symbol(symbol)
+        } else {
+            return Err((pos, format!("Unexpected '{c}'")));
+        };
+
+        lexemes.push(Lexeme {
+            token,
+            start,
+            end: pos,
+            line: start_line,
+        });
+    }
+
+    Ok(lexemes)
+}
+
+/// Read the quoted string at the start of an input. Returns its text and length, quotes included.
+/// A backslash escapes the quote; other backslashes are kept, so patterns read as written.
+/// `\\` is kept as written too (templates read it as a backslash), but can't escape the quote
+/// after it, so `"a\\"` ends with a backslash.
+fn scan_string(input: &str, quote: char) -> Option<(String, usize)> {
+    let mut text = String::new();
+    let mut chars = input.char_indices().skip(1);
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '\\' if input[pos + 1..].starts_with(quote) => {
+                text.push(quote);
+                chars.next();
+            }
+            '\\' if input[pos + 1..].starts_with('\\') => {
+                text.push_str("\\\\");
+                chars.next();
+            }
+            c if c == quote => return Some((text, pos + 1)),
+            c => text.push(c),
+        }
+    }
+
+    None
+}
+
+/// Parse a target: `collection:id`, or a document id alone.
+/// Without an id (`collection:`), a random one is generated.
+fn parse_target(input: &str) -> Result<(String, String), String> {
+    let input = input.trim();
+    let input = input.strip_prefix('@').unwrap_or(input);
+
+    // Handle common placeholders
+    if input == "id" || input == "ID" {
+        return Ok(("__placeholder__".to_string(), "id".to_string()));
+    }
+
+    match input.split_once(':') {
+        Some((collection, id)) => {
+            let collection = collection.trim().to_string();
+            let mut id = id.trim().to_string();
+            if collection.is_empty() {
+                return Err("Empty collection name".to_string());
+            }
+            if id.is_empty() {
+                id = generate_uuid();
+            }
+
+            Name::collection(&collection).map_err(|e| e.to_string())?;
+            Name::document_id(&id).map_err(|e| e.to_string())?;
+            Ok((collection, id))
+        }
+        None => {
+            if input.is_empty() {
+                return Err("Empty target".to_string());
+            }
+            Name::document_id(input).map_err(|e| e.to_string())?;
+
+            // Use placeholder for collection when not specified
+            Ok(("__placeholder__".to_string(), input.to_string()))
+        }
+    }
+}
+
+/// Take the field an operator applies to.
+fn into_field(expr: Expr, operator: &str) -> Result<String, String> {
+    match expr {
+        Expr::Field(field) => Ok(field),
+        _ => Err(format!("Expected a field before '{operator}'")),
+    }
+}
+
+/// Recursive descent parser over the tokens of an input.
+struct Parser<'a> {
+    input: &'a str,
+    lexemes: Vec<Lexeme>,
+    pos: usize,
+    /// Tokens from `end` on are out of reach, while a value is parsed on its own
+    end: usize,
+    depth: usize,
+    /// Whether numbers may group their digits (`1,000,000`), as in conditions
+    grouping: bool,
+    /// Variables in scope (`let name = expr`), innermost last
+    bindings: Vec<(String, Expr)>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Result<Self, (usize, String)> {
+        let lexemes = tokenize(input)?;
+        Ok(Self {
+            input,
+            end: lexemes.len(),
+            lexemes,
+            pos: 0,
+            depth: 0,
+            grouping: false,
+            bindings: Vec::new(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, n: usize) -> Option<&Token> {
+        match self.pos + n < self.end {
+            true => Some(&self.lexemes[self.pos + n].token),
+            false => None,
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek()?.clone();
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.end
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = self.is_symbol(symbol);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let found = self.is_word(word);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &str, context: &str) -> Result<(), String> {
+        match self.eat_symbol(symbol) {
+            true => Ok(()),
+            false => Err(format!("Expected '{symbol}' {context}, found {}", self.found())),
+        }
+    }
+
+    /// Consume a word, e.g. a name.
+    fn word(&mut self, what: &str) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Word(word)) => {
+                let word = word.clone();
+                self.pos += 1;
+                Ok(word)
+            }
+            _ => Err(format!("Expected {what}, found {}", self.found())),
+        }
+    }
+
+    /// Fail unless every token was read.
+    fn finish(&self) -> Result<(), String> {
+        match self.at_end() {
+            true => Ok(()),
+            false => Err(format!("Unexpected {}", self.found())),
+        }
+    }
+
+    /// The current token as written, for errors.
+    fn found(&self) -> String {
+        match self.at_end() {
+            true => "the end of the input".to_string(),
+            false => format!("'{}'", self.text(self.pos, self.pos + 1)),
+        }
+    }
+
+    /// Input of the tokens from `from` to `to` (exclusive), as written.
+    fn text(&self, from: usize, to: usize) -> &'a str {
+        &self.input[self.lexemes[from].start..self.lexemes[to - 1].end]
+    }
+
+    /// Offset of the current token in the input.
+    fn offset(&self) -> usize {
+        match self.lexemes.get(self.pos) {
+            Some(lexeme) => lexeme.start,
+            None => self.input.trim_end().len(),
+        }
+    }
+
+    /// Line of the current token.
+    fn line(&self) -> usize {
+        self.lexemes
+            .get(self.pos)
+            .or(self.lexemes.last())
+            .map_or(1, |lexeme| lexeme.line)
+    }
+
+    /// Run a parse one level deeper.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("Input nested deeper than {MAX_DEPTH} levels"));
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    /// script := ('const' 'events' '=' events | 'fn' 'main' '(' name? ')' block)*
+    fn script(&mut self) -> Result<Program, String> {
+        let mut events = None;
+        let mut main = None;
+
+        while !self.at_end() {
+            if self.eat_word("const") {
+                if !self.eat_word("events") {
+                    return Err(format!("Expected const events, found {}", self.found()));
+                }
+                self.expect_symbol("=", "after const events")?;
+                events = Some(self.events()?);
+            } else if self.eat_word("fn") {
+                if !self.eat_word("main") {
+                    return Err(format!("Expected fn main, found {}", self.found()));
+                }
+                if main.is_some() {
+                    return Err("Only one fn main is allowed".to_string());
+                }
+                self.expect_symbol("(", "after fn main")?;
+                // Name of the events parameter
+                if let Some(Token::Word(_)) = self.peek() {
+                    self.pos += 1;
+                }
+                self.expect_symbol(")", "after the parameter of fn main")?;
+                main = Some(self.block()?);
+            } else {
+                return Err(format!(
+                    "Unexpected {}, expected const events or fn main",
+                    self.found()
+                ));
+            }
+        }
+
+        Ok(Program {
+            events: events.ok_or("No events definition found")?,
+            main: main.ok_or("No fn main found")?,
+        })
+    }
+
+    /// events := '[' (name '{' (field ','?)* '}' ','?)* ']'
+    fn events(&mut self) -> Result<Vec<EventDefinition>, String> {
+        self.expect_symbol("[", "before the events")?;
+
+        let mut events = Vec::new();
+        while !self.eat_symbol("]") {
+            let name = self.word("an event name")?;
+            self.expect_symbol("{", "after the event name")?;
+            let mut fields = Vec::new();
+            while !self.eat_symbol("}") {
+                fields.push(self.word("a field of the event")?);
+                if !self.eat_symbol(",") {
+                    self.expect_symbol("}", "after the fields of the event")?;
+                    break;
+                }
+            }
+            events.push(EventDefinition { name, fields });
+            self.eat_symbol(",");
+        }
+
+        Ok(events)
+    }
+
+    /// block := '{' (let | statement)* '}'
+    /// Variables are visible from their `let` to the end of their block.
+    fn block(&mut self) -> Result<Vec<Stmt>, String> {
+        self.expect_symbol("{", "to open a block")?;
+        let scope = self.bindings.len();
+        let stmts = self.nested(|parser| {
+            let mut stmts = Vec::new();
+            while !parser.eat_symbol("}") {
+                if parser.at_end() {
+                    return Err("No matching closing brace found".to_string());
+                }
+                if parser.eat_word("let") {
+                    parser.binding()?;
+                } else if !parser.eat_symbol(";") {
+                    stmts.push(parser.statement()?);
+                }
+            }
+            Ok(stmts)
+        });
+        self.bindings.truncate(scope);
+        stmts
+    }
+
+    /// let := 'let' name '=' expr
+    fn binding(&mut self) -> Result<(), String> {
+        let name = self.word("the name of the variable")?;
+        if name.contains('.')
+            || name.starts_with(|c: char| c.is_ascii_digit())
+            || RESERVED.contains(&name.as_str())
+        {
+            return Err(format!("Invalid variable name '{name}'"));
+        }
+        self.expect_symbol("=", &format!("after let {name}"))?;
+        let expr = self.expression(false)?;
+        self.bindings.push((name, expr));
+        Ok(())
+    }
+
+    /// Expression a variable is bound to, if it is in scope.
+    fn variable(&self, name: &str) -> Option<&Expr> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(bound, _)| bound == name)
+            .map(|(_, expr)| expr)
+    }
+
+    /// Replace the variables read by the `${...}` placeholders of a string with their
+    /// expressions, as placeholders are evaluated when the action runs.
+    fn substitute(&self, text: &str) -> String {
+        if self.bindings.is_empty() {
+            return text.to_string();
+        }
+
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+                break;
+            };
+            output.push_str(&rest[..start + 2]);
+            // Escaped placeholders are text, `\\` being an escaped backslash
+            let backslashes = rest[..start].chars().rev().take_while(|&c| c == '\\').count();
+            match backslashes % 2 == 1 {
+                true => output.push_str(&rest[start + 2..end]),
+                false => output.push_str(&self.substitute_words(&rest[start + 2..end])),
+            }
+            output.push('}');
+            rest = &rest[end + 1..];
+        }
+
+        output.push_str(rest);
+        output
+    }
+
+    /// Replace the words of a placeholder naming a variable with its expression.
+    fn substitute_words(&self, text: &str) -> String {
+        let Ok(lexemes) = tokenize(text) else {
+            return text.to_string();
+        };
+
+        let mut output = String::with_capacity(text.len());
+        let mut copied = 0;
+        for lexeme in lexemes {
+            let Token::Word(word) = &lexeme.token else {
+                continue;
+            };
+            let Some(expr) = self.variable(word) else {
+                continue;
+            };
+
+            output.push_str(&text[copied..lexeme.start]);
+            match expr.is_computed() {
+                true => output.push_str(&format!("({expr})")),
+                false => output.push_str(&expr.to_string()),
+            }
+            copied = lexeme.end;
+        }
+
+        output.push_str(&text[copied..]);
+        output
+    }
+
+    /// statement := if | match | action
+    fn statement(&mut self) -> Result<Stmt, String> {
+        if self.eat_word("if") {
+            return self.if_chain();
+        }
+        if self.eat_word("match") {
+            return self.match_arms();
+        }
+        self.action().map(Stmt::Action)
+    }
+
+    /// if := 'if' condition block ('else' (if | block))?
+    fn if_chain(&mut self) -> Result<Stmt, String> {
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        loop {
+            let at = self.offset();
+            let condition = self.condition()?;
+            branches.push(Branch {
+                condition,
+                block: self.block()?,
+                at,
+            });
+
+            if !self.eat_word("else") {
+                break;
+            }
+            if !self.eat_word("if") {
+                otherwise = Some(self.block()?);
+                break;
+            }
+        }
+
+        Ok(Stmt::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    /// match := 'match' 'events' '{' ((event | '_') '=>' block ','?)* '}'
+    fn match_arms(&mut self) -> Result<Stmt, String> {
+        let subject = self.word("the events to match")?;
+        if subject != "events" {
+            return Err(format!("Invalid match on '{subject}', expected match events"));
+        }
+        self.expect_symbol("{", "to open the match")?;
+
+        let mut arms = Vec::new();
+        while !self.eat_symbol("}") {
+            let at = self.offset();
+            let event = self.word("an event name or _")?;
+            self.expect_symbol("=>", &format!("after {event}"))?;
+            arms.push(Arm {
+                event,
+                block: self.block()?,
+                at,
+            });
+            self.eat_symbol(",");
+        }
+
+        Ok(Stmt::Match(arms))
+    }
+
+    /// action := 'update' target ('with' | 'merge')? object | 'insert' target 'with'? object
+    ///         | 'delete' target
+    ///         | 'append' target 'with'? object ('max' number)?
+    ///         | 'notify' message | 'set' 'kv.'key '=' value
+    fn action(&mut self) -> Result<Action, String> {
+        let start = self.pos;
+        let keyword = match self.next() {
+            Some(Token::Word(keyword)) => keyword,
+            Some(_) => {
+                self.pos = start;
+                return Err(format!("Unknown action: {}", self.rest_of_line()));
+            }
+            None => return Err("Missing action".to_string()),
+        };
+
+        match keyword.as_str() {
+            "update" | "insert" | "append" => {
+                let (collection, id) = self.target()?;
+                let merge = keyword == "update" && self.eat_word("merge");
+                if !merge {
+                    self.eat_word("with");
+                }
+                if !self.is_symbol("{") {
+                    return Err(format!(
+                        "Expected {{ fields }} after the target of {keyword}, found {}",
+                        self.found()
+                    ));
+                }
+                let fields = self.object()?.into_iter().collect();
+
+                Ok(match keyword.as_str() {
+                    "update" => Action::Update {
+                        collection,
+                        id,
+                        fields,
+                        merge,
+                    },
+                    "append" => {
+                        // Appending to a generated id would start a new document every time
+                        if is_uuid(&id) {
+                            return Err(format!(
+                                "append needs the id of its document: @{collection}:<id>"
+                            ));
+                        }
+                        Action::Append {
+                            collection,
+                            id,
+                            fields,
+                            max: self.max_items()?,
+                        }
+                    }
+                    _ => Action::Insert {
+                        id,
+                        collection,
+                        fields,
+                    },
+                })
+            }
+            "delete" => {
+                let (collection, id) = self.target()?;
+                Ok(Action::Delete { collection, id })
+            }
+            "notify" => {
+                let message = match self.peek() {
+                    Some(Token::Str(message)) => {
+                        let message = self.substitute(message);
+                        self.pos += 1;
+                        message
+                    }
+                    // Unquoted messages run to the end of the line
+                    _ if !self.at_end() && self.line() == self.lexemes[start].line => {
+                        let end = self.value_end(&[";"], true);
+                        let message = self.text(self.pos, end.max(self.pos + 1)).to_string();
+                        self.pos = end.max(self.pos + 1);
+                        message
+                    }
+                    _ => return Err("Missing message of notify".to_string()),
+                };
+                Ok(Action::Notify { message })
+            }
+            "set" => self.set(),
+            _ => {
+                self.pos = start;
+                Err(format!("Unknown action: {}", self.rest_of_line()))
+            }
+        }
+    }
+
+    /// max := 'max' number
+    /// Max number of items an append keeps, if given.
+    fn max_items(&mut self) -> Result<Option<usize>, String> {
+        if !self.eat_word("max") {
+            return Ok(None);
+        }
+        match self.word("the max number of items")?.parse::<usize>() {
+            Ok(max) if max > 0 => Ok(Some(max)),
+            _ => Err("The max number of items must be a positive integer".to_string()),
+        }
+    }
+
+    /// The current token and the ones after it on the same line, as written.
+    fn rest_of_line(&self) -> &'a str {
+        let line = self.lexemes[self.pos].line;
+        let end = (self.pos..self.end)
+            .find(|&i| self.lexemes[i].line != line)
+            .unwrap_or(self.end);
+        self.text(self.pos, end)
+    }
+
+    /// target := '@' collection ':' id? | id
+    fn target(&mut self) -> Result<(String, String), String> {
+        match self.peek() {
+            Some(Token::Target(target) | Token::Word(target)) => {
+                let target = parse_target(target)?;
+                self.pos += 1;
+                Ok(target)
+            }
+            _ => Err(format!("Expected a target (@collection:id), found {}", self.found())),
+        }
+    }
+
+    /// set := 'set' 'kv.'key '=' value
+    fn set(&mut self) -> Result<Action, String> {
+        // The key runs to the '=', as keys may contain '-'
+        let start = self.pos;
+        let line = self.line();
+        while !self.at_end() && !self.is_symbol("=") && self.lexemes[self.pos].line == line {
+            self.pos += 1;
+        }
+        if self.pos == start || !self.eat_symbol("=") {
+            return Err("Missing '=' in set".to_string());
+        }
+        let target = self.text(start, self.pos - 1);
+        let key = target
+            .trim()
+            .strip_prefix("kv.")
+            .ok_or("Only kv.<key> can be set")?;
+        Name::kv_key(key).map_err(|e| e.to_string())?;
+
+        Ok(Action::SetKv {
+            key: key.to_string(),
+            value: self.value(&[";"], true)?,
+        })
+    }
+
+    /// object := '{' (key ':' value ','?)* '}'
+    fn object(&mut self) -> Result<Map<String, Value>, String> {
+        self.expect_symbol("{", "to open the fields")?;
+        self.nested(|parser| {
+            let mut fields = Map::new();
+            loop {
+                // Placeholders of the console editor, e.g. `{ ... }`
+                while parser.eat_word("...") {}
+                if parser.eat_symbol("}") {
+                    break;
+                }
+                if parser.at_end() {
+                    return Err("Fields must be wrapped in { }".to_string());
+                }
+
+                let key = parser.key()?;
+                let value = parser.value(&[",", "}", ":"], false)?;
+                if parser.is_symbol(":") {
+                    return Err("Missing ',' between fields".to_string());
+                }
+                fields.insert(key, value);
+
+                if !parser.eat_symbol(",") {
+                    while parser.eat_word("...") {}
+                    parser.expect_symbol("}", "after a field")?;
+                    break;
+                }
+            }
+            Ok(fields)
+        })
+    }
+
+    /// Key of a field: a string, or the words up to the ':' (keys may contain '-').
+    fn key(&mut self) -> Result<String, String> {
+        if let Some(Token::Str(key)) = self.peek() {
+            let key = key.clone();
+            self.pos += 1;
+            self.expect_symbol(":", "after the key of a field")?;
+            return Ok(key);
+        }
+
+        let start = self.pos;
+        while !self.at_end() && ![":", ",", "}"].iter().any(|s| self.is_symbol(s)) {
+            self.pos += 1;
+        }
+        if self.pos == start || !self.eat_symbol(":") {
+            return Err("Missing ':' in field".to_string());
+        }
+        Ok(self.text(start, self.pos - 1).to_string())
+    }
+
+    /// list := '[' (value ','?)* ']'
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect_symbol("[", "to open the list")?;
+        self.nested(|parser| {
+            let mut items = Vec::new();
+            while !parser.eat_symbol("]") {
+                items.push(parser.value(&[",", "]"], false)?);
+                if !parser.eat_symbol(",") {
+                    parser.expect_symbol("]", "after a value of the list")?;
+                    break;
+                }
+            }
+            Ok(Value::Array(items))
+        })
+    }
+
+    /// End of the value starting at the current token: the first of the `stops` symbols outside
+    /// brackets, an unmatched closing bracket, or (`same_line`) the end of the line.
+    fn value_end(&self, stops: &[&str], same_line: bool) -> usize {
+        let line = self.line();
+        let mut depth = 0usize;
+        let mut i = self.pos;
+        while i < self.end {
+            let lexeme = &self.lexemes[i];
+            if depth == 0 && same_line && lexeme.line != line {
+                break;
+            }
+            match lexeme.token {
+                Token::Symbol(s) if depth == 0 && stops.contains(&s) => break,
+                Token::Symbol("(" | "[" | "{") => depth += 1,
+                Token::Symbol(")" | "]" | "}") => match depth {
+                    0 => break,
+                    _ => depth -= 1,
+                },
+                _ => {}
+            }
+            i += 1;
+        }
+
+        i
+    }
+
+    /// Parse a value of an action, up to one of the `stops` symbols (or the end of the line).
+    /// Values are strings, numbers, booleans, null, objects, lists or expressions on event
+    /// fields; anything else is kept as written.
+    fn value(&mut self, stops: &[&str], same_line: bool) -> Result<Value, String> {
+        let start = self.pos;
+        let end = self.value_end(stops, same_line);
+        if end == start {
+            return Err(format!("Missing value, found {}", self.found()));
+        }
+
+        let outer = std::mem::replace(&mut self.end, end);
+        let value = self.value_within(start)?;
+        self.end = outer;
+        self.pos = end;
+        Ok(value)
+    }
+
+    /// Parse the value made of all the tokens within reach.
+    fn value_within(&mut self, start: usize) -> Result<Value, String> {
+        let raw = self.text(start, self.end);
+        let single = self.end - start == 1;
+
+        match self.peek() {
+            Some(Token::Str(text)) if single => return Ok(json!(self.substitute(text))),
+            Some(Token::Word(word)) if single => match word.as_str() {
+                "true" => return Ok(json!(true)),
+                "false" => return Ok(json!(false)),
+                "null" => return Ok(Value::Null),
+                // Numbers keep their precision
+                word => {
+                    if let Ok(n) = word.parse::<i64>() {
+                        return Ok(json!(n));
+                    }
+                    if let Ok(n) = word.parse::<serde_json::Number>() {
+                        return Ok(Value::Number(n));
+                    }
+                }
+            },
+            Some(Token::Symbol("{")) => {
+                let object = self.object()?;
+                self.finish()?;
+                return Ok(Value::Object(object));
+            }
+            Some(Token::Symbol("[")) => {
+                let list = self.array()?;
+                self.finish()?;
+                return Ok(list);
+            }
+            _ => {}
+        }
+
+        // Computed values (e.g. events.Transfer.amount * 2) are kept as written, and computed
+        // when the action runs
+        let expr = match self.sum() {
+            Ok(expr) if self.at_end() => expr,
+            _ => return Ok(json!(raw)),
+        };
+        Ok(match expr {
+            Expr::Field(field) => json!(field),
+            expr if expr.fields().iter().any(|field| field.starts_with("events.")) => {
+                json!(expr.to_string())
+            }
+            expr => match expr.constant() {
+                Some(n) => expr::to_value(&n),
+                None => json!(raw),
+            },
+        })
+    }
+
+    /// condition := and ('||' and)*
+    fn condition(&mut self) -> Result<Condition, String> {
+        self.nested(|parser| {
+            let mut condition = parser.conjunction()?;
+            while parser.eat_symbol("||") {
+                let right = parser.conjunction()?;
+                condition = Condition::Or(Box::new(condition), Box::new(right));
+            }
+            Ok(condition)
+        })
+    }
+
+    /// and := not ('&&' not)*
+    fn conjunction(&mut self) -> Result<Condition, String> {
+        let mut condition = self.negation()?;
+        while self.eat_symbol("&&") {
+            let right = self.negation()?;
+            condition = Condition::And(Box::new(condition), Box::new(right));
+        }
+        Ok(condition)
+    }
+
+    /// not := '!' not | '(' condition ')' | predicate
+    fn negation(&mut self) -> Result<Condition, String> {
+        if self.eat_symbol("!") {
+            return self
+                .nested(|parser| parser.negation())
+                .map(DslParser::negate_condition);
+        }
+
+        // Parentheses group conditions, or an expression (e.g. `(fee + tip) * 2 > 10`)
+        if self.is_symbol("(") {
+            let start = self.pos;
+            self.pos += 1;
+            let condition = self.condition().ok();
+            if let Some(condition) = condition.filter(|_| self.eat_symbol(")")) {
+                return Ok(condition);
+            }
+            self.pos = start;
+        }
+
+        self.predicate()
+    }
+
+    /// predicate := 'anomaly' '(' field (',' score)? ')'
+    ///            | expr (comparison | text operator | membership)
+    fn predicate(&mut self) -> Result<Condition, String> {
+        if self.is_word("anomaly") && matches!(self.peek_at(1), Some(Token::Symbol("("))) {
+            self.pos += 2;
+            let field = self.word("the field of the anomaly")?;
+            let score = match self.eat_symbol(",") {
+                true => self
+                    .word("an anomaly score")?
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|t| t.is_finite() && *t > 0.0)
+                    .ok_or(
+                        "Invalid anomaly score, expected a positive number of standard deviations",
+                    )?,
+                false => DEFAULT_ANOMALY_THRESHOLD,
+            };
+            self.expect_symbol(")", "after the anomaly condition")?;
+            return Ok(Condition::Anomaly(field, score));
+        }
+
+        let left = self.expression(true)?;
+        match self.next() {
+            Some(Token::Word(word)) if word == "in" => {
+                self.membership(into_field(left, "in")?, false)
+            }
+            Some(Token::Word(word)) if word == "not" => {
+                if !self.eat_word("in") {
+                    return Err(format!("Expected 'in' after 'not', found {}", self.found()));
+                }
+                self.membership(into_field(left, "not in")?, true)
+            }
+            Some(Token::Word(word))
+                if matches!(word.as_str(), "contains" | "starts_with" | "ends_with") =>
+            {
+                let field = into_field(left, &word)?;
+                let text = self.operand_text(&word)?;
+                Ok(match word.as_str() {
+                    "contains" => Condition::Contains(field, text),
+                    "starts_with" => Condition::StartsWith(field, text),
+                    _ => Condition::EndsWith(field, text),
+                })
+            }
+            Some(Token::Symbol(operator @ ("~" | "!~"))) => {
+                let field = into_field(left, operator)?;
+                let pattern = Pattern::new(&self.operand_text(operator)?)?;
+                Ok(match operator {
+                    "~" => Condition::Matches(field, pattern),
+                    _ => Condition::NotMatches(field, pattern),
+                })
+            }
+            Some(Token::Symbol(operator)) => {
+                match Comparison::ALL.into_iter().find(|c| c.as_str() == operator) {
+                    Some(comparison) => self.comparison(left, comparison),
+                    None => Err("Unable to parse comparison".to_string()),
+                }
+            }
+            _ => Err("Unable to parse comparison".to_string()),
+        }
+    }
+
+    /// Quoted operand of a text operator.
+    fn operand_text(&mut self, operator: &str) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Str(text)) => Ok(text),
+            _ => Err(format!(
+                "Invalid operand of '{operator}', expected a quoted string"
+            )),
+        }
+    }
+
+    /// membership := 'watchlist' '(' name ')' | list
+    fn membership(&mut self, field: String, negated: bool) -> Result<Condition, String> {
+        if self.eat_word("watchlist") {
+            let name = match (self.eat_symbol("("), self.next(), self.eat_symbol(")")) {
+                (true, Some(Token::Str(name) | Token::Word(name)), true) => name,
+                _ => return Err("Invalid watchlist, expected watchlist(\"name\")".to_string()),
+            };
+            let name = Name::watchlist(&name).map_err(|e| e.to_string())?.to_string();
+            return Ok(match negated {
+                true => Condition::NotInWatchlist(field, name),
+                false => Condition::InWatchlist(field, name),
+            });
+        }
+
+        let values = self.list()?;
+        Ok(match negated {
+            true => Condition::NotIn(field, values),
+            false => Condition::In(field, values),
+        })
+    }
+
+    /// list := '[' (string | number) (',' (string | number))* ','? ']'
+    fn list(&mut self) -> Result<Vec<Value>, String> {
+        if !self.eat_symbol("[") {
+            return Err("Invalid list, expected [value, ...]".to_string());
+        }
+
+        let mut values = Vec::new();
+        while !self.eat_symbol("]") {
+            let negative = self.eat_symbol("-");
+            let value = match self.next() {
+                Some(Token::Str(text)) if !negative => Value::String(text),
+                Some(Token::Word(word)) => {
+                    let number = match negative {
+                        true => format!("-{word}"),
+                        false => word,
+                    };
+                    number
+                        .parse()
+                        .map(Value::Number)
+                        .map_err(|_| format!("Invalid list value '{number}'"))?
+                }
+                None => return Err("Invalid list, expected a closing ']'".to_string()),
+                Some(_) => {
+                    return Err(format!(
+                        "Invalid list value {}",
+                        self.text(self.pos - 1, self.pos)
+                    ))
+                }
+            };
+            values.push(value);
+
+            if !self.eat_symbol(",") {
+                if !self.eat_symbol("]") {
+                    return Err("Invalid list, expected a closing ']'".to_string());
+                }
+                break;
+            }
+        }
+        if values.is_empty() {
+            return Err("Invalid list, expected at least one value".to_string());
+        }
+
+        Ok(values)
+    }
+
+    /// Right side of a comparison: a string, boolean or null (for equality), or an expression.
+    fn comparison(&mut self, left: Expr, comparison: Comparison) -> Result<Condition, String> {
+        let literal = match self.peek() {
+            Some(Token::Str(text)) => Some(json!(text)),
+            Some(Token::Word(word)) if word == "true" => Some(json!(true)),
+            Some(Token::Word(word)) if word == "false" => Some(json!(false)),
+            Some(Token::Word(word)) if word == "null" => Some(Value::Null),
+            _ => None,
+        };
+        if let Some(value) = literal {
+            self.pos += 1;
+            let field = into_field(left, comparison.as_str())?;
+            return match comparison {
+                Comparison::Equal => Ok(Condition::Equals(field, value)),
+                Comparison::NotEqual => Ok(Condition::NotEquals(field, value)),
+                _ => Err(format!(
+                    "Invalid operand of '{}', expected a number",
+                    comparison.as_str()
+                )),
+            };
+        }
+
+        let right = self.expression(true)?;
+
+        // A field compared with a number keeps its own condition, which triggers are analyzed by
+        let Expr::Field(field) = &left else {
+            return Ok(Condition::Compare(left, comparison, right));
+        };
+        let Some(value) = right.constant() else {
+            return Ok(Condition::Compare(left, comparison, right));
+        };
+        let field = field.clone();
+
+        Ok(match comparison {
+            Comparison::Greater => Condition::GreaterThan(field, value),
+            Comparison::Less => Condition::LessThan(field, value),
+            Comparison::GreaterOrEqual => Condition::GreaterOrEqual(field, value),
+            Comparison::LessOrEqual => Condition::LessOrEqual(field, value),
+            Comparison::Equal => Condition::Equals(field, expr::to_value(&value)),
+            Comparison::NotEqual => Condition::NotEquals(field, expr::to_value(&value)),
+        })
+    }
+
+    /// Parse an expression, with digit grouping in numbers or not.
+    fn expression(&mut self, grouping: bool) -> Result<Expr, String> {
+        let outer = std::mem::replace(&mut self.grouping, grouping);
+        let expr = self.sum();
+        self.grouping = outer;
+        expr
+    }
+
+    /// sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(operator) = self.operator(&["+", "-"]) {
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(self.product()?));
+        }
+
+        Ok(expr)
+    }
+
+    /// product := unary (('*' | '/' | '%') unary)*
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while let Some(operator) = self.operator(&["*", "/", "%"]) {
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(self.unary()?));
+        }
+
+        Ok(expr)
+    }
+
+    /// unary := '-' unary | number | field | '(' sum ')'
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.nested(|parser| match parser.next() {
+            Some(Token::Symbol("-")) => Ok(Expr::Neg(Box::new(parser.unary()?))),
+            Some(Token::Symbol("(")) => {
+                let expr = parser.sum()?;
+                match parser.eat_symbol(")") {
+                    true => Ok(expr),
+                    false => Err("Missing ')' in expression".to_string()),
+                }
+            }
+            Some(Token::Word(word)) => parser.operand(word),
+            Some(_) => Err(format!(
+                "Unexpected {} in expression",
+                parser.text(parser.pos - 1, parser.pos)
+            )),
+            None => Err("Incomplete expression".to_string()),
+        })
+    }
+
+    /// Number, variable or field of an expression.
+    fn operand(&mut self, word: String) -> Result<Expr, String> {
+        if matches!(word.as_str(), "true" | "false" | "null") {
+            return Err(format!("'{word}' is not a number"));
+        }
+        if let Some(expr) = self.variable(&word) {
+            return Ok(expr.clone());
+        }
+        if !word.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            return Ok(Expr::Field(word));
+        }
+
+        // Digits may be grouped in conditions, e.g. 1,000,000
+        let mut number = word;
+        while self.grouping && number.bytes().all(|b| b.is_ascii_digit()) && self.is_symbol(",") {
+            let group = match self.peek_at(1) {
+                Some(Token::Word(group))
+                    if group.len() >= 3
+                        && group.as_bytes()[..3].iter().all(u8::is_ascii_digit)
+                        && (group.len() == 3 || group.as_bytes()[3] == b'.') =>
+                {
+                    group.clone()
+                }
+                _ => break,
+            };
+            number.push_str(&group);
+            self.pos += 2;
+        }
+
+        BigDecimal::from_str(&number)
+            .map(Expr::Number)
+            .map_err(|_| format!("Invalid number '{number}'"))
+    }
+
+    /// Consume the next token if it is one of the given operators.
+    fn operator(&mut self, operators: &[&str]) -> Option<Operator> {
+        let operator = operators.iter().find(|operator| self.is_symbol(operator))?;
+        self.pos += 1;
+        operator.chars().next().and_then(Operator::from_char)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token> {
+        tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|lexeme| lexeme.token)
+            .collect()
+    }
+
+    fn script(main: &str) -> Program {
+        let input = format!("const events = [Transfer {{ from, amount }}]\nfn main(events) {main}");
+        parse_script(&input).unwrap_or_else(|e| panic!("{e}: {}", e.snippet))
+    }
+
+    #[test]
+    fn strings_escape_their_quote_and_backslashes() {
+        assert_eq!(tokens(r#""say \"hi\"""#), [Token::Str(r#"say "hi""#.into())]);
+        assert_eq!(tokens(r"'it\'s'"), [Token::Str("it's".into())]);
+        // `\\` doesn't escape the quote after it
+        assert_eq!(
+            tokens(r#""a\\" == "b""#),
+            [
+                Token::Str(r"a\\".into()),
+                Token::Symbol("=="),
+                Token::Str("b".into())
+            ]
+        );
+        // Other backslashes are kept, so patterns read as written
+        assert_eq!(tokens(r#""\d+""#), [Token::Str(r"\d+".into())]);
+        assert!(tokenize(r#""a\""#).is_err());
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(
+            tokens("a // one\n/* two\nthree */ b"),
+            [Token::Word("a".into()), Token::Word("b".into())]
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let condition = parse_condition("a > 1 || b > 2 && c > 3").unwrap();
+        let Condition::Or(left, right) = condition else {
+            panic!("expected ||, got {condition:?}");
+        };
+        assert!(matches!(*left, Condition::GreaterThan(ref field, _) if field == "a"));
+        assert!(matches!(*right, Condition::And(..)));
+
+        // Parentheses group conditions
+        let condition = parse_condition("(a > 1 || b > 2) && c > 3").unwrap();
+        let Condition::And(left, _) = condition else {
+            panic!("expected &&, got {condition:?}");
+        };
+        assert!(matches!(*left, Condition::Or(..)));
+    }
+
+    #[test]
+    fn else_if_chains_keep_every_branch() {
+        let program = script(
+            r#"{
+                if events.Transfer.amount > 100 {
+                    notify "large"
+                } else if events.Transfer.amount > 10 {
+                    notify "medium"
+                } else {
+                    notify "small"
+                }
+            }"#,
+        );
+
+        let [Stmt::If { branches, otherwise }] = program.main.as_slice() else {
+            panic!("expected an if, got {:?}", program.main);
+        };
+        assert_eq!(branches.len(), 2);
+        assert!(matches!(otherwise.as_deref(), Some([Stmt::Action(Action::Notify { .. })])));
+    }
+
+    #[test]
+    fn blocks_nest() {
+        let program = script(
+            r#"{
+                match events {
+                    Transfer => {
+                        if events.Transfer.amount > 1 {
+                            if events.Transfer.from == "alice" { delete @users:alice }
+                        }
+                    },
+                    _ => { notify "other" }
+                }
+            }"#,
+        );
+
+        let [Stmt::Match(arms)] = program.main.as_slice() else {
+            panic!("expected a match, got {:?}", program.main);
+        };
+        assert_eq!(arms.len(), 2);
+        let [Stmt::If { branches, .. }] = arms[0].block.as_slice() else {
+            panic!("expected an if, got {:?}", arms[0].block);
+        };
+        assert!(matches!(branches[0].block.as_slice(), [Stmt::If { .. }]));
+    }
+
+    #[test]
+    fn objects_span_lines() {
+        let program = script(
+            r#"{
+                insert @users:tx1 {
+                    amount: events.Transfer.amount,
+                    "status": "created",
+                    meta: {
+                        tags: ["a", "b"],
+                        count: 2
+                    }
+                }
+            }"#,
+        );
+
+        let [Stmt::Action(Action::Insert { fields, .. })] = program.main.as_slice() else {
+            panic!("expected an insert, got {:?}", program.main);
+        };
+        assert_eq!(fields["amount"], json!("events.Transfer.amount"));
+        assert_eq!(fields["status"], json!("created"));
+        assert_eq!(fields["meta"], json!({ "tags": ["a", "b"], "count": 2 }));
+    }
+
+    #[test]
+    fn errors_point_at_their_token() {
+        let input = "const events = [Transfer { amount }]\nfn main(events) {\n    explode @a:b\n}";
+        let error = parse_script(input).unwrap_err();
+        assert_eq!((error.line, error.column), (3, 5));
+        assert_eq!(error.snippet, "    explode @a:b");
+
+        let input = "const events = [Transfer { amount }]\nfn main(events) {";
+        let error = parse_script(input).unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(error.message.contains("closing brace"), "{}", error.message);
+
+        let error = parse_script("const events = [Transfer { amount }]\n\"open").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+        assert_eq!(error.message, "Unterminated string");
+    }
+
+    #[test]
+    fn the_documented_script_parses() {
+        let script = DslParser::parse_script(
+            r#"
+            const events = [
+                Transferred { amount },
+                MoneyWithdrawn { amount, recipient }
+            ]
+
+            fn main(events) {
+                if events.Transferred.amount > 200000 {
+                    update @transfers:latest with { status: "flagged" }
+                } else {
+                    delete @transfers:latest
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(script.events.len(), 2);
+        assert_eq!(script.rules.len(), 2);
+    }
+}
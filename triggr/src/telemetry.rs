@@ -0,0 +1,99 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the OpenTelemetry tracing of the event pipeline.
+// Every block is a trace: decoding a contract event, matching it against the triggers, executing each
+// trigger and each of its actions are nested spans. The context travels with the queued event as a
+// W3C `traceparent`, and is sent with outgoing HTTP requests so the spans of the services they reach
+// join the trace. Spans are only recorded, and exported over OTLP, when an endpoint is configured.
+
+use std::collections::HashMap;
+
+use opentelemetry::{
+    global,
+    propagation::TextMapPropagator,
+    trace::{SpanKind, Status, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource};
+
+/// Name spans are recorded under, and service name of the exported traces.
+const TRACER_NAME: &str = "triggr";
+
+/// Header (and carrier key) of the W3C trace context.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Export spans to the OTLP/HTTP collector at `TRIGGR_OTLP_ENDPOINT`
+/// (e.g. `http://localhost:4318/v1/traces`). Returns the provider so spans can be flushed on shutdown.
+pub fn init() -> Option<SdkTracerProvider> {
+    let endpoint = std::env::var("TRIGGR_OTLP_ENDPOINT").ok()?;
+
+    let exporter = match SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint.as_str())
+        .build()
+    {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            tracing::warn!("Failed to set up trace export to {endpoint}: {e}");
+            return None;
+        }
+    };
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(TRACER_NAME).build())
+        .build();
+    global::set_tracer_provider(provider.clone());
+
+    Some(provider)
+}
+
+/// Start a span under `parent` and return the context holding it.
+/// The span ends when the last clone of the context is dropped.
+pub fn start(
+    name: &'static str,
+    kind: SpanKind,
+    parent: &Context,
+    attributes: Vec<KeyValue>,
+) -> Context {
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer
+        .span_builder(name)
+        .with_kind(kind)
+        .with_attributes(attributes)
+        .start_with_context(&tracer, parent);
+
+    parent.with_span(span)
+}
+
+/// Add attributes to the span of a context.
+pub fn annotate(cx: &Context, attributes: Vec<KeyValue>) {
+    cx.span().set_attributes(attributes);
+}
+
+/// Mark the span of a context as failed.
+pub fn fail(cx: &Context, error: impl ToString) {
+    cx.span().set_status(Status::error(error.to_string()));
+}
+
+/// Return the `traceparent` of the span of a context, if it is recorded.
+pub fn traceparent(cx: &Context) -> Option<String> {
+    if !cx.span().span_context().is_valid() {
+        return None;
+    }
+
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(cx, &mut carrier);
+    carrier.remove(TRACEPARENT)
+}
+
+/// Return the context of a `traceparent`, or an empty context (so spans start a new trace).
+pub fn resume(traceparent: Option<&str>) -> Context {
+    let Some(traceparent) = traceparent else {
+        return Context::new();
+    };
+
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    TraceContextPropagator::new().extract(&carrier)
+}
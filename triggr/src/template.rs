@@ -0,0 +1,555 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module renders templates (action fields and notification messages) against event data.
+// Placeholders take the form `${events.<EventName>.<field>}` and are resolved from the decoded event.
+// Placeholders may also call helpers, e.g. `${truncate_middle(events.Transfer.from, 6)}`.
+// A backslash escapes a placeholder: `\${...}` is rendered as `${...}`, without evaluating it,
+// and `\\` is rendered as a single backslash.
+
+use std::collections::HashMap;
+
+use blake2::{Blake2b512, Digest};
+use chrono::DateTime;
+use serde::Serialize;
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+use crate::{chain::polkadot::prelude::EventData, expr, util};
+
+/// Error raised while resolving a template placeholder.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TemplateError {
+    /// The raw placeholder expression
+    pub placeholder: String,
+    /// Why it could not be resolved
+    pub reason: String,
+}
+
+/// Render a template value (string, object or array) against an event.
+/// Returns the rendered value and any resolution errors.
+pub fn render_value(template: &Value, event: &EventData) -> (Value, Vec<TemplateError>) {
+    let mut errors = Vec::new();
+    let output = render_value_inner(template, event, &mut errors);
+    (output, errors)
+}
+
+/// Render the fields of a write action. The engine skips the write if any placeholder can't be
+/// resolved, so the errors are returned instead of partly rendered fields.
+pub fn render_fields(
+    fields: HashMap<String, Value>,
+    event: &EventData,
+) -> Result<HashMap<String, Value>, Vec<TemplateError>> {
+    let mut errors = Vec::new();
+    let rendered = fields
+        .into_iter()
+        .map(|(name, value)| (name, render_value_inner(&value, event, &mut errors)))
+        .collect();
+
+    match errors.is_empty() {
+        true => Ok(rendered),
+        false => Err(errors),
+    }
+}
+
+/// Render a notification message. Unresolved placeholders are kept as written, and a message
+/// made of a single reference is its value as text.
+pub fn render_message(message: &str, event: &EventData) -> (String, Vec<TemplateError>) {
+    let (rendered, errors) = render_value(&Value::String(message.to_string()), event);
+    (value_to_text(&rendered), errors)
+}
+
+/// Recursively render a JSON value.
+fn render_value_inner(template: &Value, event: &EventData, errors: &mut Vec<TemplateError>) -> Value {
+    match template {
+        Value::String(s) => {
+            // A computed value (e.g. `events.Transfer.amount * 2`) is a number
+            if let Some(computed) = expr::compute(s, event) {
+                return match computed {
+                    Ok(value) => value,
+                    Err(reason) => {
+                        errors.push(TemplateError {
+                            placeholder: s.clone(),
+                            reason,
+                        });
+                        template.clone()
+                    }
+                };
+            }
+
+            // A whole-value reference keeps the type of the event field
+            if s.starts_with("events.") {
+                return match resolve_reference(s, event) {
+                    Ok(value) => value,
+                    Err(reason) => {
+                        errors.push(TemplateError {
+                            placeholder: s.clone(),
+                            reason,
+                        });
+                        template.clone()
+                    }
+                };
+            }
+
+            Value::String(interpolate(s, event, errors))
+        }
+        Value::Object(obj) => Value::Object(
+            obj.iter()
+                .map(|(k, v)| (k.clone(), render_value_inner(v, event, errors)))
+                .collect(),
+        ),
+        Value::Array(arr) => Value::Array(
+            arr.iter()
+                .map(|v| render_value_inner(v, event, errors))
+                .collect(),
+        ),
+        _ => template.clone(),
+    }
+}
+
+/// Replace every `${...}` placeholder inside a string, except escaped ones (`\${...}`).
+/// `\\` is a backslash, so a backslash can precede a placeholder (`\\${...}`).
+fn interpolate(template: &str, event: &EventData, errors: &mut Vec<TemplateError>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['\\', '$']) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        // Escapes: `\\` is a backslash, `\${` opens no placeholder
+        if let Some(after) = rest.strip_prefix("\\\\") {
+            output.push('\\');
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("\\${") {
+            output.push_str("${");
+            rest = after;
+            continue;
+        }
+
+        // Any other backslash or dollar sign is text
+        let Some(after) = rest.strip_prefix("${") else {
+            output.push_str(&rest[..1]);
+            rest = &rest[1..];
+            continue;
+        };
+
+        let Some(end) = after.find('}') else {
+            errors.push(TemplateError {
+                placeholder: rest.to_string(),
+                reason: "Unterminated placeholder".to_string(),
+            });
+            output.push_str(rest);
+            return output;
+        };
+
+        let expr = after[..end].trim();
+        match evaluate(expr, event) {
+            Ok(value) => output.push_str(&value_to_text(&value)),
+            Err(reason) => {
+                errors.push(TemplateError {
+                    placeholder: expr.to_string(),
+                    reason,
+                });
+                // Leave the placeholder untouched so the author can spot it
+                output.push_str(&rest[..end + 3]);
+            }
+        }
+
+        rest = &after[end + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Resolve an `events.<EventName>.<field>` reference, the field possibly being a dot-path into
+/// a nested value (`events.Transfer.meta.tier`).
+pub fn resolve_reference(expr: &str, event: &EventData) -> Result<Value, String> {
+    let parts: Vec<&str> = expr.trim().splitn(3, '.').collect();
+
+    // Format: events.<EventName>.<field_name>
+    if parts.len() != 3 || parts[0] != "events" {
+        return Err(format!("Invalid reference '{expr}', expected events.<Event>.<field>"));
+    }
+
+    if !parts[1].eq_ignore_ascii_case(&event.event_name) {
+        return Err(format!(
+            "Reference targets event '{}' but the event is '{}'",
+            parts[1], event.event_name
+        ));
+    }
+
+    util::resolve_path(&event.fields, parts[2])
+        .map(util::process_event_value)
+        .ok_or_else(|| format!("Event '{}' has no field '{}'", event.event_name, parts[2]))
+}
+
+/// Evaluate a placeholder expression: a helper call, an event reference or a literal.
+pub fn evaluate(expr: &str, event: &EventData) -> Result<Value, String> {
+    let expr = expr.trim();
+
+    // Arithmetic on event fields, e.g. `${events.Transfer.amount / 1000}`
+    if let Some(computed) = expr::compute(expr, event) {
+        return computed;
+    }
+
+    // Helper call: name(arg, arg, ...)
+    if let Some(open) = expr.find('(').filter(|_| expr.ends_with(')')) {
+        let name = expr[..open].trim();
+        let args = split_args(&expr[open + 1..expr.len() - 1])
+            .into_iter()
+            .map(|arg| evaluate(arg, event))
+            .collect::<Result<Vec<Value>, String>>()?;
+
+        return call_helper(name, &args);
+    }
+
+    // Event reference
+    if expr.starts_with("events.") {
+        return resolve_reference(expr, event);
+    }
+
+    // String literal
+    if expr.len() >= 2
+        && ((expr.starts_with('"') && expr.ends_with('"'))
+            || (expr.starts_with('\'') && expr.ends_with('\'')))
+    {
+        return Ok(Value::String(expr[1..expr.len() - 1].to_string()));
+    }
+
+    // Numeric literal
+    if let Ok(num) = expr.parse::<u128>() {
+        return Ok(json!(num));
+    }
+    if let Ok(num) = expr.parse::<f64>() {
+        return Ok(json!(num));
+    }
+
+    Err(format!("Unable to evaluate '{expr}'"))
+}
+
+/// Split helper arguments on top-level commas.
+fn split_args(input: &str) -> Vec<&str> {
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut in_quotes = false;
+    let mut start = 0;
+
+    for (i, c) in input.char_indices() {
+        match c {
+            '"' | '\'' => in_quotes = !in_quotes,
+            '(' if !in_quotes => depth += 1,
+            ')' if !in_quotes => depth -= 1,
+            ',' if !in_quotes && depth == 0 => {
+                args.push(input[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    let last = input[start..].trim();
+    if !last.is_empty() {
+        args.push(last);
+    }
+
+    args
+}
+
+/// Dispatch a helper call by name.
+fn call_helper(name: &str, args: &[Value]) -> Result<Value, String> {
+    match name {
+        "hex_to_ss58" => {
+            let addr = arg_text(args, 0, name)?;
+            let prefix = match args.get(1) {
+                Some(v) => arg_int::<u16>(v, name)?,
+                None => DEFAULT_SS58_PREFIX,
+            };
+            hex_to_ss58(&addr, prefix).map(Value::String)
+        }
+        "planck_to_dot" => {
+            let amount = arg_u128(args.first().ok_or("planck_to_dot expects an amount")?, name)?;
+            let decimals = match args.get(1) {
+                Some(v) => arg_int::<u32>(v, name)?,
+                None => DEFAULT_DOT_DECIMALS,
+            };
+            planck_to_dot(amount, decimals).map(Value::String)
+        }
+        "truncate_middle" => {
+            let text = arg_text(args, 0, name)?;
+            let keep = match args.get(1) {
+                Some(v) => arg_int::<usize>(v, name)?,
+                None => 6,
+            };
+            Ok(Value::String(truncate_middle(&text, keep)))
+        }
+        "format_ts" => {
+            let ms = arg_int::<i64>(args.first().ok_or("format_ts expects a timestamp")?, name)?;
+            format_ts(ms).map(Value::String)
+        }
+        _ => Err(format!("Unknown helper '{name}'")),
+    }
+}
+
+/// Return an argument as text.
+fn arg_text(args: &[Value], index: usize, helper: &str) -> Result<String, String> {
+    match args.get(index) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(other) => Ok(other.to_string()),
+        None => Err(format!("{helper} expects at least {} argument(s)", index + 1)),
+    }
+}
+
+/// Return an argument as an unsigned integer.
+fn arg_u128(value: &Value, helper: &str) -> Result<u128, String> {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+
+    text.trim()
+        .parse::<u128>()
+        .map_err(|_| format!("{helper} expects an unsigned integer, got '{text}'"))
+}
+
+/// Return an argument as an unsigned integer, rejecting values out of the range of `T`.
+fn arg_int<T: TryFrom<u128>>(value: &Value, helper: &str) -> Result<T, String> {
+    let n = arg_u128(value, helper)?;
+    T::try_from(n).map_err(|_| format!("{helper} argument {n} is out of range"))
+}
+
+/// Default SS58 network prefix (generic Substrate).
+const DEFAULT_SS58_PREFIX: u16 = 42;
+
+/// Default number of decimals for DOT denominated amounts.
+const DEFAULT_DOT_DECIMALS: u32 = 10;
+
+/// Encode a hex account id as an SS58 address.
+/// 20-byte (H160) addresses are padded with `0xEE` into their fallback AccountId32.
+pub fn hex_to_ss58(addr: &str, prefix: u16) -> Result<String, String> {
+    let mut bytes = hex::decode(addr.trim().trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid hex address '{addr}': {e}"))?;
+
+    match bytes.len() {
+        32 => {}
+        20 => bytes.extend_from_slice(&[0xEE; 12]),
+        len => return Err(format!("Expected a 20 or 32 byte address, got {len} bytes")),
+    }
+
+    if prefix > 16_383 {
+        return Err(format!("Invalid SS58 prefix {prefix}"));
+    }
+
+    // Prefix is encoded in one byte below 64, otherwise in two
+    let mut data = if prefix < 64 {
+        vec![prefix as u8]
+    } else {
+        vec![
+            ((prefix & 0b0000_0000_1111_1100) >> 2) as u8 | 0b0100_0000,
+            ((prefix >> 8) as u8) | (((prefix & 0b0000_0000_0000_0011) as u8) << 6),
+        ]
+    };
+    data.extend_from_slice(&bytes);
+
+    // Checksum is the first two bytes of blake2b-512("SS58PRE" ++ data)
+    let mut hasher = Blake2b512::new();
+    hasher.update(b"SS58PRE");
+    hasher.update(&data);
+    let checksum = hasher.finalize();
+    data.extend_from_slice(&checksum[..2]);
+
+    Ok(bs58::encode(data).into_string())
+}
+
+/// Max number of decimals of an amount, `10^38` being the largest power of ten in a u128.
+const MAX_DECIMALS: u32 = 38;
+
+/// Convert an integer amount in planck into a decimal string.
+pub fn planck_to_dot(amount: u128, decimals: u32) -> Result<String, String> {
+    if decimals > MAX_DECIMALS {
+        return Err(format!("At most {MAX_DECIMALS} decimals are supported, got {decimals}"));
+    }
+
+    let divisor = 10u128.pow(decimals);
+    let whole = amount / divisor;
+    let fraction = amount % divisor;
+
+    if fraction == 0 {
+        return Ok(whole.to_string());
+    }
+
+    let fraction = format!("{:0width$}", fraction, width = decimals as usize);
+    Ok(format!("{}.{}", whole, fraction.trim_end_matches('0')))
+}
+
+/// Shorten a string by keeping `keep` characters on each side.
+pub fn truncate_middle(text: &str, keep: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= keep.saturating_mul(2).saturating_add(1) {
+        return text.to_string();
+    }
+
+    let head: String = chars[..keep].iter().collect();
+    let tail: String = chars[chars.len() - keep..].iter().collect();
+    format!("{head}…{tail}")
+}
+
+/// Format a unix timestamp in milliseconds as a UTC date-time.
+pub fn format_ts(ms: i64) -> Result<String, String> {
+    DateTime::from_timestamp_millis(ms)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .ok_or_else(|| format!("Invalid timestamp {ms}"))
+}
+
+/// Convert a resolved value into text for interpolation.
+fn value_to_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::namespace::Namespace;
+
+    fn transfer() -> EventData {
+        EventData {
+            event_name: "Transfer".to_string(),
+            fields: HashMap::from([
+                ("from".to_string(), json!("alice")),
+                ("to".to_string(), json!("bob")),
+                ("amount".to_string(), json!(1500)),
+                ("urgent".to_string(), json!(true)),
+                ("meta".to_string(), json!({ "tier": "gold" })),
+            ]),
+            raw: None,
+            scores: HashMap::new(),
+            trace: None,
+            namespace: Namespace::Live,
+            block: None,
+            block_number: None,
+        }
+    }
+
+    fn render(template: &str) -> (Value, Vec<TemplateError>) {
+        render_value(&json!(template), &transfer())
+    }
+
+    #[test]
+    fn whole_value_reference_keeps_the_field_type() {
+        let (value, errors) = render("events.Transfer.amount");
+        assert_eq!(value, json!(1500));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn interpolates_inside_a_string() {
+        let (value, errors) = render("Sent ${events.Transfer.amount} planck");
+        assert_eq!(value, json!("Sent 1500 planck"));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn interpolates_multiple_references() {
+        let (value, errors) = render("${events.Transfer.from} -> ${ events.Transfer.to }");
+        assert_eq!(value, json!("alice -> bob"));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn escaped_placeholder_is_text() {
+        let (value, errors) = render(r"Use \${events.Transfer.amount} in templates");
+        assert_eq!(value, json!("Use ${events.Transfer.amount} in templates"));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn escaped_backslash_precedes_a_placeholder() {
+        let (value, errors) = render(r"C:\\${events.Transfer.to} and a\b");
+        assert_eq!(value, json!(r"C:\bob and a\b"));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn unterminated_placeholder_is_reported() {
+        let (value, errors) = render("Sent ${events.Transfer.amount");
+        assert_eq!(value, json!("Sent ${events.Transfer.amount"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].reason, "Unterminated placeholder");
+    }
+
+    #[test]
+    fn missing_field_is_reported_and_kept() {
+        let (value, errors) = render("Fee: ${events.Transfer.fee}");
+        assert_eq!(value, json!("Fee: ${events.Transfer.fee}"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].placeholder, "events.Transfer.fee");
+        assert!(errors[0].reason.contains("has no field 'fee'"));
+    }
+
+    #[test]
+    fn fields_with_unresolved_placeholders_are_not_written() {
+        let fields = HashMap::from([
+            ("amount".to_string(), json!("events.Transfer.amount")),
+            ("note".to_string(), json!("fee ${events.Transfer.fee}")),
+        ]);
+        let errors = render_fields(fields, &transfer()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+
+        let fields = HashMap::from([("amount".to_string(), json!("events.Transfer.amount"))]);
+        let rendered = render_fields(fields, &transfer()).unwrap();
+        assert_eq!(rendered["amount"], json!(1500));
+    }
+
+    #[test]
+    fn message_made_of_a_reference_is_its_text() {
+        let (message, errors) = render_message("events.Transfer.amount", &transfer());
+        assert_eq!(message, "1500");
+        assert!(errors.is_empty());
+
+        let (message, errors) = render_message("Fee: ${events.Transfer.fee}", &transfer());
+        assert_eq!(message, "Fee: ${events.Transfer.fee}");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn helpers_convert_chain_values() {
+        let alice = "0xd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+        assert_eq!(
+            hex_to_ss58(alice, 42).unwrap(),
+            "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"
+        );
+        assert_eq!(planck_to_dot(15_000_000_000, 10).unwrap(), "1.5");
+        assert_eq!(planck_to_dot(1, 38).unwrap(), format!("0.{}1", "0".repeat(37)));
+    }
+
+    #[test]
+    fn out_of_range_helper_arguments_are_errors() {
+        let event = transfer();
+        let alice = "0xd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+
+        // 65578 would wrap to 42 as a u16
+        let error = evaluate(&format!("hex_to_ss58('{alice}', 65578)"), &event).unwrap_err();
+        assert!(error.contains("out of range"), "{error}");
+
+        let error = evaluate("planck_to_dot(events.Transfer.amount, 39)", &event).unwrap_err();
+        assert!(error.contains("38 decimals"), "{error}");
+
+        let error = evaluate("truncate_middle(events.Transfer.from, 99999999999999999999)", &event)
+            .unwrap_err();
+        assert!(error.contains("out of range"), "{error}");
+    }
+
+    #[test]
+    fn non_string_values_are_rendered_as_text() {
+        let (value, errors) =
+            render("urgent=${events.Transfer.urgent} meta=${events.Transfer.meta}");
+        assert_eq!(value, json!(r#"urgent=true meta={"tier":"gold"}"#));
+        assert!(errors.is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the tenancy audit mode, a debug aid for integration tests and staging.
+// Authenticated requests run with the requesting project id attached to their task, and storage
+// checks every key, trigger and topic it touches against it. Anything owned by another project
+// (or not scoped to a project at all) is a tenancy leak and is reported. The namespaces of a
+// project (live and sandbox) belong to the same tenant.
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
+use crate::namespace::project_of;
+
+tokio::task_local! {
+    /// Project the current request is acting for.
+    static PROJECT: String;
+}
+
+/// What to do when a tenancy leak is detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditMode {
+    /// No auditing (default)
+    Off,
+    /// Log the leak and count it
+    Warn,
+    /// Panic, failing the test that caused it
+    Panic,
+}
+
+/// Leaks detected since startup.
+static VIOLATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Return the audit mode, read once from `TRIGGR_TENANCY_AUDIT` (`off`, `warn` or `panic`).
+pub fn mode() -> AuditMode {
+    static MODE: OnceLock<AuditMode> = OnceLock::new();
+
+    *MODE.get_or_init(|| match std::env::var("TRIGGR_TENANCY_AUDIT").as_deref() {
+        Ok("warn") | Ok("1") | Ok("true") => AuditMode::Warn,
+        Ok("panic") => AuditMode::Panic,
+        _ => AuditMode::Off,
+    })
+}
+
+/// Run a future on behalf of a project.
+pub async fn scope<F: Future>(project_id: String, f: F) -> F::Output {
+    PROJECT.scope(project_id, f).await
+}
+
+/// Number of leaks detected since startup.
+pub fn violations() -> u64 {
+    VIOLATIONS.load(Ordering::Relaxed)
+}
+
+/// Check that a storage key is scoped to the requesting project.
+/// Keys follow the pattern `{kind}::{project_id}::...`.
+pub fn check_key(key: &str) {
+    audit(|project| {
+        let owner = project_of(key.split("::").nth(1).unwrap_or_default());
+        (owner != project).then(|| format!("key '{key}' is not scoped to project {project}"))
+    });
+}
+
+/// Check that a record read or written belongs to the requesting project.
+pub fn check_owner(what: &str, owner: &str) {
+    audit(|project| {
+        (project_of(owner) != project)
+            .then(|| format!("{what} belongs to project {owner}, not {project}"))
+    });
+}
+
+/// Check that a subscription topic is scoped to the requesting project.
+pub fn check_topic(topic: &str) {
+    audit(|project| {
+        (!topic.split(':').any(|part| project_of(part) == project))
+            .then(|| format!("topic '{topic}' is not scoped to project {project}"))
+    });
+}
+
+/// Run a check against the requesting project and report any leak.
+/// Calls made outside of a request (e.g. by the chain listener) are not audited.
+fn audit(check: impl FnOnce(&str) -> Option<String>) {
+    if mode() == AuditMode::Off {
+        return;
+    }
+
+    let Some(leak) = PROJECT.try_with(|project| check(project)).ok().flatten() else {
+        return;
+    };
+
+    VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+    match mode() {
+        AuditMode::Panic => panic!("Tenancy leak: {leak}"),
+        _ => tracing::error!("🚨 Tenancy leak: {leak}"),
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains a harness to write end-to-end tests without a real node.
+// `TestInstance` runs an ephemeral Triggr (temporary sled directories, random port) and
+// `MockChain` feeds it scripted events, exactly as the chain listener would after decoding.
+// `TempStore` is a store on its own, for tests of the storage layer.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde_json::Value;
+use tokio::{net::TcpListener, task::JoinHandle};
+
+pub use crate::chain::polkadot::prelude::EventData;
+use crate::{
+    chain::polkadot::prelude::DecodeMode,
+    journal,
+    namespace::Namespace,
+    server::startup,
+    util::generate_uuid,
+    storage::Sled,
+    EventSender, Project, ProjectStore, StorageResult, Triggr,
+};
+
+/// A directory under the system temp directory, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Name a fresh directory, e.g. `triggr-test-<uuid>`. It is created by its first user.
+    pub fn new(prefix: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("{prefix}-{}", generate_uuid())))
+    }
+
+    /// Path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A store in a fresh directory, removed when dropped.
+pub struct TempStore {
+    pub store: Sled,
+    /// Directory of the store, dropped after it
+    pub dir: TempDir,
+}
+
+impl TempStore {
+    /// Open a store in a fresh directory.
+    pub fn new() -> StorageResult<Self> {
+        let dir = TempDir::new("triggr-store");
+        Ok(Self {
+            store: Sled::at(dir.path())?,
+            dir,
+        })
+    }
+}
+
+/// An event emitted by the mock chain, optionally after a delay.
+#[derive(Debug, Clone)]
+pub struct ScriptedEvent {
+    /// Contract that emits the event
+    pub contract_addr: String,
+    /// The decoded event
+    pub event: EventData,
+    /// Time to wait before emitting it
+    pub delay: Duration,
+}
+
+impl ScriptedEvent {
+    /// Create an event emitted right away.
+    pub fn new(contract_addr: &str, event_name: &str, fields: HashMap<String, Value>) -> Self {
+        Self {
+            contract_addr: contract_addr.to_lowercase(),
+            event: EventData {
+                event_name: event_name.to_string(),
+                fields,
+                raw: None,
+                scores: HashMap::new(),
+                trace: None,
+                namespace: Namespace::Live,
+                block: None,
+                block_number: None,
+            },
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Emit the event after a delay.
+    pub fn after(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// In-process chain source that emits decoded events to the trigger engine.
+#[derive(Clone)]
+pub struct MockChain {
+    tx: EventSender,
+    triggr: Triggr,
+}
+
+impl MockChain {
+    /// Emit a single event.
+    pub async fn emit(&self, contract_addr: &str, event: EventData) {
+        // Blocks keep the watchdog quiet and the health stats realistic
+        self.triggr.chain_health.record_block(generate_uuid());
+
+        self.triggr.pipeline.enqueued();
+        let _ = self.tx.send(contract_addr.to_lowercase(), event).await;
+    }
+
+    /// Emit a sequence of events, honouring their delays.
+    pub async fn play(&self, script: Vec<ScriptedEvent>) {
+        for scripted in script {
+            if !scripted.delay.is_zero() {
+                tokio::time::sleep(scripted.delay).await;
+            }
+            self.emit(&scripted.contract_addr, scripted.event).await;
+        }
+    }
+
+    /// Emit the events of a recorded journal (see `TRIGGR_EVENT_JOURNAL`) in order,
+    /// letting each event's triggers finish before the next.
+    pub async fn replay(&self, path: &str) -> std::io::Result<()> {
+        for entry in journal::load(path).await? {
+            self.emit(&entry.contract_addr, entry.event).await;
+            self.settle().await;
+        }
+
+        Ok(())
+    }
+
+    /// Wait until every emitted event has been picked up and its triggers have run.
+    pub async fn settle(&self) {
+        loop {
+            let stats = self.triggr.pipeline.stats();
+            if stats.channel_len == 0 && stats.executor_pending == 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+/// An ephemeral Triggr instance serving on a random local port.
+/// Its storage is removed when it is dropped.
+pub struct TestInstance {
+    /// Shared state of the instance
+    pub triggr: Triggr,
+    /// Chain source feeding the instance
+    pub chain: MockChain,
+    /// Address the server listens on
+    pub addr: SocketAddr,
+    server: JoinHandle<()>,
+    /// Storage directory, removed with the instance
+    _dir: TempDir,
+}
+
+impl TestInstance {
+    /// Start an instance with fresh storage.
+    /// Like a normal instance, creating projects requires `TRIGGR_ENCRYPTION_KEY`.
+    pub async fn start() -> std::io::Result<Self> {
+        let dir = TempDir::new("triggr-test");
+        let store = Sled::at(dir.path()).map_err(std::io::Error::other)?;
+        let triggr = Triggr::with_store(store).map_err(std::io::Error::other)?;
+
+        let tx = startup::spawn_engine(triggr.clone());
+        let chain = MockChain {
+            tx,
+            triggr: triggr.clone(),
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let service =
+            startup::app(triggr.clone()).into_make_service_with_connect_info::<SocketAddr>();
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, service).await;
+        });
+
+        Ok(Self {
+            triggr,
+            chain,
+            addr,
+            server,
+            _dir: dir,
+        })
+    }
+
+    /// Return the URL of an HTTP endpoint.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Return the URL of the WebSocket endpoint.
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}/ws", self.addr)
+    }
+
+    /// Create a project watching a contract.
+    /// Its `api_key` is the value to send in the `x-api-key` header.
+    pub fn create_project(&self, name: &str, contract_addr: &str) -> StorageResult<Project> {
+        let mut project = Project {
+            id: name.to_string(),
+            api_key: String::new(),
+            owner: "test".to_string(),
+            contract_address: contract_addr.to_lowercase(),
+            description: String::new(),
+            contract_file_path: String::new(),
+            contract_events: Vec::new(),
+            decode_mode: DecodeMode::default(),
+            event_routes: Default::default(),
+            enrichers: Vec::new(),
+            sandbox_mirror: false,
+            strict_ordering: false,
+        };
+
+        ProjectStore::create(&*self.triggr.store, &mut project)?;
+        Ok(project)
+    }
+}
+
+impl Drop for TestInstance {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{dsl::DslParser, prelude::DocumentStore, Trigger, TriggerStore};
+
+    const CONTRACT: &str = "5grwvaef5zxb26fz9rcqpdws57cterhpnehxcpcnohgkutqy";
+
+    #[tokio::test]
+    async fn emitted_events_run_the_triggers_of_their_contract() {
+        let instance = TestInstance::start().await.unwrap();
+        let project = instance.create_project("harness", CONTRACT).unwrap();
+
+        let dsl = r#"
+            const events = [Transfer { amount }]
+            fn main(events) {
+                if events.Transfer.amount > 100 {
+                    insert @transfers:large { amount: events.Transfer.amount }
+                }
+            }"#;
+        let trigger = Trigger {
+            id: "large-transfers".to_string(),
+            description: String::new(),
+            project_id: project.id.clone(),
+            dsl: dsl.to_string(),
+            rules: DslParser::parse_script(dsl).unwrap().rules,
+            active: true,
+            created: 1,
+            last_run: 0,
+            tags: Vec::new(),
+            webhook: None,
+            owner: None,
+        };
+        instance.triggr.store.store_trigger(CONTRACT, trigger).unwrap();
+
+        let transfer = |amount: u64| {
+            let fields = HashMap::from([("amount".to_string(), json!(amount))]);
+            ScriptedEvent::new(CONTRACT, "Transfer", fields)
+        };
+        instance.chain.play(vec![transfer(5)]).await;
+        instance.chain.settle().await;
+        let store = &*instance.triggr.store;
+        assert!(DocumentStore::get(store, &project.id, "transfers", "large").unwrap().is_none());
+
+        instance.chain.play(vec![transfer(500)]).await;
+        instance.chain.settle().await;
+        let document = DocumentStore::get(store, &project.id, "transfers", "large").unwrap();
+        let document = document.unwrap();
+        assert_eq!(document.data["amount"], json!(500));
+    }
+}
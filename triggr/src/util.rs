@@ -1,6 +1,14 @@
 // Copyright (c) 2025, Algorealm Inc.
 
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
 use base64::{Engine as _, engine::general_purpose};
+use bigdecimal::BigDecimal;
+use blake2::{Blake2b512, Digest};
 use rand::{TryRngCore, rngs::OsRng, RngCore};
 use uuid::Uuid;
 
@@ -14,7 +22,7 @@ use uuid::Uuid;
 pub fn generate_nonce<const N: usize>() -> String {
     let mut bytes = [0u8; N]; // 128-bit nonce
     let _ = OsRng.try_fill_bytes(&mut bytes); // ✅ no &, call directly on OsRng
-    general_purpose::URL_SAFE_NO_PAD.encode(&bytes)
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
 }
 
 
@@ -56,8 +64,8 @@ impl std::error::Error for CryptoError {}
 /// Base64-encoded string containing: nonce (12 bytes) + ciphertext + auth tag
 /// 
 /// # Example
-/// ```
-/// let key = generate_nonce<32>();
+/// ```ignore
+/// let key = general_purpose::STANDARD.encode([7u8; 32]);
 /// let encrypted = encrypt("Hello, World!", &key).unwrap();
 /// let decrypted = decrypt(&encrypted, &key).unwrap();
 /// assert_eq!(decrypted, "Hello, World!");
@@ -206,6 +214,37 @@ pub fn strip_wrappers(value: &str) -> &str {
     result.trim()
 }
 
+/// Length of a hashed API key (hex encoded blake2b-512).
+pub const API_KEY_HASH_LEN: usize = 128;
+
+/// Hash an API key so that it can be used as a storage index without storing the key itself.
+/// The hash is salted with `TRIGGR_API_KEY_SALT`, or the encryption key if no salt is set.
+/// Stores refuse to open once the salt changed, as no stored key would match anymore.
+pub fn hash_api_key(key: &str) -> Result<String, std::env::VarError> {
+    let salt = std::env::var("TRIGGR_API_KEY_SALT")
+        .or_else(|_| std::env::var("TRIGGR_ENCRYPTION_KEY"))?;
+
+    let mut hasher = Blake2b512::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(key.as_bytes());
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Identify the salt API keys are hashed with, without revealing it.
+pub fn api_key_salt_id() -> Result<String, std::env::VarError> {
+    Ok(hash_api_key("api key salt id")?[..32].to_string())
+}
+
+/// Hash a JSON value (hex encoded, first 16 bytes of blake2b-512).
+/// Object keys are sorted when serialized, so equal values have equal hashes.
+pub fn content_hash(value: &Value) -> String {
+    let mut hasher = Blake2b512::new();
+    hasher.update(value.to_string().as_bytes());
+
+    hex::encode(&hasher.finalize()[..16])
+}
+
 /// Generate random UUID
 pub fn generate_uuid() -> String {
     Uuid::new_v4().to_string()
@@ -214,4 +253,261 @@ pub fn generate_uuid() -> String {
 /// Check if a string is a UUID
 pub fn is_uuid(input: &str) -> bool {
     Uuid::parse_str(input).is_ok()
-}
\ No newline at end of file
+}
+
+/// Key used to tag a high-precision decimal inside a document, e.g. `{ "$decimal": "0.000000000000000001" }`.
+pub const DECIMAL_TAG: &str = "$decimal";
+
+/// Interpret a JSON value as an exact decimal.
+/// Accepts numbers, numeric strings (including `Some(..)` wrapped ones) and tagged decimals.
+pub fn to_decimal(value: &Value) -> Option<BigDecimal> {
+    match value {
+        Value::Number(num) => BigDecimal::from_str(&num.to_string()).ok(),
+        Value::String(s) => BigDecimal::from_str(strip_wrappers(s)).ok(),
+        Value::Object(obj) => obj.get(DECIMAL_TAG).and_then(to_decimal),
+        _ => None,
+    }
+}
+
+/// Compare two JSON values for equality.
+/// Numeric values are compared exactly as decimals, so `"1000000000000000000000"` equals `1e21`.
+pub fn values_equal(left: &Value, right: &Value) -> bool {
+    let numeric = |v: &Value| matches!(v, Value::Number(_)) || v.get(DECIMAL_TAG).is_some();
+
+    let decimals = (numeric(left) || numeric(right))
+        .then(|| to_decimal(left).zip(to_decimal(right)))
+        .flatten();
+
+    match decimals {
+        Some((l, r)) => l == r,
+        None => left == right,
+    }
+}
+
+/// Read the value at a dot-path into JSON, e.g. `meta.tier`. Array items are addressed by index,
+/// and a key containing dots is read as is.
+pub fn value_at<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if let Some(found) = value.get(path) {
+        return Some(found);
+    }
+
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+/// Resolve a dot-path into the fields of an event or document, e.g. `meta.tier`: the first
+/// segment names a field and the others descend into its nested value.
+pub fn resolve_path<'a>(fields: &'a HashMap<String, Value>, path: &str) -> Option<&'a Value> {
+    if let Some(found) = fields.get(path) {
+        return Some(found);
+    }
+
+    let (field, rest) = path.split_once('.')?;
+    value_at(fields.get(field)?, rest)
+}
+
+/// Apply a JSON merge patch (RFC 7386) to a value.
+/// Objects are merged recursively and `null` removes a field; anything else replaces the target.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// Whether outbound requests (webhooks, lookups) may reach an address by default.
+/// Loopback, private, link-local and unspecified addresses belong to the instance's own network
+/// (e.g. its admin API or the cloud metadata service at 169.254.169.254).
+pub fn public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            let shared = a == 100 && (64..128).contains(&b);
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || a == 0
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => public_address(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                let unique_local = first & 0xfe00 == 0xfc00;
+                let link_local = first & 0xffc0 == 0xfe80;
+                !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+            }
+        },
+    }
+}
+
+/// Host of a url, without credentials, port or IPv6 brackets.
+pub fn url_host(url: &str) -> &str {
+    let authority = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = authority.split(['/', '?', '#']).next().unwrap_or_default();
+    // Credentials are not part of the host
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+
+    match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    }
+}
+
+/// Whether a host is in a comma separated list of hosts.
+fn listed(hosts: &str, host: &str) -> bool {
+    hosts
+        .split(',')
+        .any(|allowed| allowed.trim().eq_ignore_ascii_case(host))
+}
+
+/// Whether outbound requests may reach the host of a url. With `allowlist_var` set, only the
+/// hosts it lists (comma separated) are allowed, internal ones included. Otherwise any host is
+/// allowed but internal addresses; names are checked again when they resolve.
+pub fn host_allowed(url: &str, allowlist_var: &str) -> bool {
+    url_allowed(url, std::env::var(allowlist_var).ok().as_deref())
+}
+
+/// Whether the host of a url is allowed, given the allowlisted hosts, if any.
+fn url_allowed(url: &str, allowlist: Option<&str>) -> bool {
+    let host = url_host(url);
+    if let Some(hosts) = allowlist {
+        return listed(hosts, host);
+    }
+
+    match host.parse::<IpAddr>() {
+        Ok(ip) => public_address(ip),
+        Err(_) => {
+            let host = host.trim_end_matches('.').to_lowercase();
+            !host.is_empty() && host != "localhost" && !host.ends_with(".localhost")
+        }
+    }
+}
+
+/// DNS resolver of outbound requests, leaving out internal addresses unless the host is
+/// allowlisted. A name is checked where it resolves, so it can't be pointed at an internal
+/// address once its url was accepted.
+struct OutboundResolver {
+    allowlist_var: &'static str,
+}
+
+impl reqwest::dns::Resolve for OutboundResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        let allowlisted = std::env::var(self.allowlist_var)
+            .is_ok_and(|hosts| listed(&hosts, &host));
+
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| allowlisted || public_address(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{host} has no public address").into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// HTTP client of outbound requests, restricted to the hosts allowed by `allowlist_var`
+/// (see `host_allowed`). Redirects are not followed, so a request can't leave the allowed hosts.
+pub fn outbound_client(allowlist_var: &'static str) -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(std::sync::Arc::new(OutboundResolver { allowlist_var }))
+        .build()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.100.100.200",
+            "::1",
+            "::",
+            "fe80::1",
+            "fd00::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!public_address(ip.parse().unwrap()), "{ip}");
+        }
+
+        for ip in ["93.184.216.34", "8.8.8.8", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(public_address(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn hosts_are_read_from_urls() {
+        assert_eq!(url_host("https://user:pw@example.com:8443/hook?a=b"), "example.com");
+        assert_eq!(url_host("http://[::1]:8080/"), "::1");
+        assert_eq!(url_host("http://127.0.0.1"), "127.0.0.1");
+    }
+
+    #[test]
+    fn internal_hosts_are_refused_without_an_allowlist() {
+        for url in [
+            "http://169.254.169.254/latest/meta-data",
+            "http://127.0.0.1:3000/api/admin",
+            "http://[::1]/",
+            "http://localhost:3000/",
+            "http://api.localhost/",
+            "http://10.0.0.5/hook",
+        ] {
+            assert!(!url_allowed(url, None), "{url}");
+        }
+        assert!(url_allowed("https://hooks.example.com/triggr", None));
+    }
+
+    #[test]
+    fn an_allowlist_allows_only_its_hosts() {
+        let hosts = Some("hooks.example.com, 10.0.0.5");
+        assert!(url_allowed("https://HOOKS.example.com/x", hosts));
+        assert!(url_allowed("http://10.0.0.5/hook", hosts));
+        assert!(!url_allowed("https://other.example.com/", hosts));
+        assert!(!url_allowed("http://127.0.0.1/", hosts));
+    }
+
+    #[test]
+    fn encrypted_text_decrypts_with_its_key() {
+        let key = general_purpose::STANDARD.encode([7u8; 32]);
+        let encrypted = encrypt("Hello, World!", &key).unwrap();
+        assert_eq!(decrypt(&encrypted, &key).unwrap(), "Hello, World!");
+
+        let other = general_purpose::STANDARD.encode([8u8; 32]);
+        assert!(decrypt(&encrypted, &other).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the address watchlists of projects.
+// A watchlist is a named set of addresses (e.g. known exchanges) that conditions reference with
+// `events.Transfer.source in watchlist("exchanges")`. Every address is stored under its own key,
+// so checking an event against a list is a single lookup, however long the list is.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::name::{Name, NameError};
+
+/// Max number of invalid entries reported when an import is rejected.
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// Stored record of a watchlist. Its addresses are stored apart.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WatchlistInfo {
+    pub description: String,
+    pub created_at: u64,
+}
+
+/// A watchlist of a project.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Watchlist {
+    pub name: String,
+    /// What the addresses have in common
+    pub description: String,
+    pub created_at: u64,
+    /// Number of addresses
+    pub size: usize,
+}
+
+/// Validate an address and bring it to the form it is stored in.
+/// Hex addresses are lowercased, other formats (e.g. SS58) are case sensitive and kept as is.
+pub fn normalize(address: &str) -> Result<String, NameError> {
+    let address = address.trim();
+    let address = match address.starts_with("0x") || address.starts_with("0X") {
+        true => address.to_lowercase(),
+        false => address.to_string(),
+    };
+
+    Name::parse("address", &address).map(|_| address)
+}
+
+/// Read the address held by an event field: a string, or a decoded account (`{ "value": "0x.." }`).
+pub fn address_of(value: &Value) -> Option<String> {
+    let address = match value {
+        Value::String(s) => s,
+        Value::Object(obj) => obj.get("value")?.as_str()?,
+        _ => return None,
+    };
+
+    normalize(address).ok()
+}
+
+/// Parse a bulk import: addresses separated by new lines, commas, semicolons or spaces,
+/// optionally quoted (e.g. a single column CSV without header). Duplicates are dropped.
+/// Fails, listing the first invalid entries, if any entry is not an address.
+pub fn parse_import(input: &str) -> Result<Vec<String>, String> {
+    let mut addresses = Vec::new();
+    let mut seen = HashSet::new();
+    let mut errors = Vec::new();
+
+    for entry in input.split(|c: char| c.is_whitespace() || matches!(c, ',' | ';')) {
+        let entry = entry.trim_matches(|c| c == '"' || c == '\'');
+        if entry.is_empty() {
+            continue;
+        }
+
+        match normalize(entry) {
+            Ok(address) if seen.insert(address.clone()) => addresses.push(address),
+            Ok(_) => {}
+            Err(e) if errors.len() < MAX_REPORTED_ERRORS => errors.push(e.to_string()),
+            Err(_) => {}
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(addresses),
+        false => Err(errors.join("; ")),
+    }
+}
@@ -0,0 +1,284 @@
+// Copyright (c) 2025, Algorealm Inc.
+
+// This module contains the outbound webhooks `notify` actions are delivered through.
+// A trigger can carry a webhook URL; each `notify` action of the trigger POSTs a JSON payload with
+// the rendered message, the event and the trigger to it. Notifications go through a bounded queue
+// delivered in the background, so the trigger engine never waits on a slow webhook. Failed
+// deliveries are retried a few times with a growing delay, then logged. Other payloads (e.g. usage
+// records) are sent the same way.
+
+use std::{
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
+
+use opentelemetry::{context::FutureExt, trace::SpanKind, Context, KeyValue};
+use serde::Serialize;
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::{chain::polkadot::prelude::EventData, metering::Metering, telemetry, util};
+
+/// Default timeout of a delivery (milliseconds).
+const DEFAULT_WEBHOOK_TIMEOUT_MS: u64 = 5_000;
+
+/// Default number of retries of a failed delivery.
+const DEFAULT_WEBHOOK_RETRIES: u32 = 2;
+
+/// Default number of notifications waiting for delivery.
+const DEFAULT_WEBHOOK_QUEUE: usize = 1_024;
+
+/// Default number of notifications delivered at once.
+const DEFAULT_WEBHOOK_CONCURRENCY: usize = 16;
+
+/// Variable listing the hosts webhooks are allowed to reach.
+const WEBHOOK_HOSTS: &str = "TRIGGR_WEBHOOK_HOSTS";
+
+/// Delay before the first retry, doubled on every following one.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Client shared by deliveries, so connections are reused.
+/// `TRIGGR_WEBHOOK_HOSTS` restricts webhooks to a comma separated list of hosts, otherwise
+/// they may reach any host but the internal addresses of the instance's network.
+static CLIENT: LazyLock<reqwest::Client> =
+    LazyLock::new(|| util::outbound_client(WEBHOOK_HOSTS));
+
+/// Payload POSTed to a webhook.
+#[derive(Debug, Serialize)]
+pub struct Notification<'a> {
+    /// Trigger whose `notify` action fired
+    pub trigger_id: &'a str,
+    /// Project the trigger belongs to
+    pub project_id: &'a str,
+    /// Contract that emitted the event
+    pub contract_addr: &'a str,
+    /// Rendered message of the action
+    pub message: String,
+    /// Event the trigger ran on
+    pub event: &'a EventData,
+    /// Unix timestamp of the notification (milliseconds)
+    pub timestamp: u64,
+}
+
+/// Check that a webhook URL can be delivered to.
+pub fn validate_url(url: &str) -> Result<(), String> {
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(format!("Invalid webhook url '{url}', expected http(s)://"));
+    }
+    if !util::host_allowed(url, WEBHOOK_HOSTS) {
+        return Err(format!("Webhooks to the host of '{url}' are not allowed"));
+    }
+
+    Ok(())
+}
+
+/// A notification waiting for delivery.
+struct Queued {
+    url: String,
+    trigger_id: String,
+    project_id: String,
+    body: Vec<u8>,
+    /// Trace of the event the notification comes from
+    context: Context,
+}
+
+/// Bounded queue of the notifications of `notify` actions.
+/// Notifications are dropped (and logged) when the queue is full.
+pub struct Outbox {
+    sender: mpsc::Sender<Queued>,
+    /// Taken by the delivery task when it starts
+    receiver: Mutex<Option<mpsc::Receiver<Queued>>>,
+    /// Number of notifications delivered at once
+    concurrency: usize,
+}
+
+impl Outbox {
+    /// Create a queue holding `capacity` notifications, delivering `concurrency` of them at once.
+    pub fn new(capacity: usize, concurrency: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        Self {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    /// Size the queue with `TRIGGR_WEBHOOK_QUEUE`, delivering `TRIGGR_WEBHOOK_CONCURRENCY`
+    /// notifications at once.
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("TRIGGR_WEBHOOK_QUEUE")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_WEBHOOK_QUEUE);
+        let concurrency = std::env::var("TRIGGR_WEBHOOK_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_WEBHOOK_CONCURRENCY);
+
+        Self::new(capacity, concurrency)
+    }
+
+    /// Spawn the task delivering queued notifications. Successful deliveries are metered.
+    /// Does nothing if it is already running.
+    pub fn start(&self, metering: Arc<Metering>) {
+        let Some(mut receiver) = self.receiver.lock().ok().and_then(|mut r| r.take()) else {
+            return;
+        };
+
+        let permits = Arc::new(Semaphore::new(self.concurrency));
+        tokio::spawn(async move {
+            while let Some(queued) = receiver.recv().await {
+                // Wait for a slot, so at most `concurrency` deliveries are in flight
+                let Ok(permit) = permits.clone().acquire_owned().await else {
+                    break;
+                };
+
+                let metering = metering.clone();
+                tokio::spawn(async move {
+                    let attributes = vec![KeyValue::new("trigger.id", queued.trigger_id.clone())];
+                    let result = deliver(&queued.url, queued.body, attributes)
+                        .with_context(queued.context)
+                        .await;
+                    match result {
+                        Ok(()) => metering.record_webhook(&queued.project_id),
+                        Err(e) => tracing::warn!(
+                            "Failed to notify {} for trigger {}: {e}",
+                            queued.url,
+                            queued.trigger_id
+                        ),
+                    }
+                    drop(permit);
+                });
+            }
+        });
+    }
+
+    /// Queue a notification for delivery.
+    /// Returns `false` if it was dropped because the queue is full.
+    pub fn dispatch(&self, url: &str, notification: &Notification<'_>) -> bool {
+        let body = match serde_json::to_vec(notification) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!("Failed to encode notification of {}: {e}", notification.trigger_id);
+                return false;
+            }
+        };
+        let queued = Queued {
+            url: url.to_string(),
+            trigger_id: notification.trigger_id.to_string(),
+            project_id: notification.project_id.to_string(),
+            body,
+            context: Context::current(),
+        };
+
+        match self.sender.try_send(queued) {
+            Ok(()) => true,
+            Err(_) => {
+                tracing::warn!(
+                    "Webhook queue is full, notification of trigger {} dropped",
+                    notification.trigger_id
+                );
+                false
+            }
+        }
+    }
+}
+
+/// POST a JSON body to a webhook, retrying failed attempts.
+pub async fn deliver(
+    url: &str,
+    body: Vec<u8>,
+    mut attributes: Vec<KeyValue>,
+) -> Result<(), String> {
+    // The allowed hosts may have changed since the webhook was saved
+    validate_url(url)?;
+
+    let timeout = std::env::var("TRIGGR_WEBHOOK_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_WEBHOOK_TIMEOUT_MS);
+    let retries = std::env::var("TRIGGR_WEBHOOK_RETRIES")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_WEBHOOK_RETRIES);
+
+    attributes.push(KeyValue::new("http.request.method", "POST"));
+    let span = telemetry::start("webhook", SpanKind::Client, &Context::current(), attributes);
+
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        let mut request = CLIENT
+            .post(url)
+            .timeout(Duration::from_millis(timeout))
+            .header("content-type", "application/json")
+            .body(body.clone());
+        // The receiver's own spans join the trace of the event
+        if let Some(traceparent) = telemetry::traceparent(&span) {
+            request = request.header(telemetry::TRACEPARENT, traceparent.as_str());
+        }
+
+        let result = match request.send().await {
+            Ok(response) => {
+                telemetry::annotate(
+                    &span,
+                    vec![KeyValue::new(
+                        "http.response.status_code",
+                        response.status().as_u16() as i64,
+                    )],
+                );
+                response.error_for_status().map(|_| ()).map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e.to_string()),
+        };
+
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt >= retries => {
+                telemetry::fail(&span, &e);
+                return Err(e);
+            }
+            Err(e) => {
+                tracing::debug!("Webhook delivery to {url} failed, retrying: {e}");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::namespace::Namespace;
+
+    #[test]
+    fn notifications_are_dropped_once_the_queue_is_full() {
+        let outbox = Outbox::new(2, 1);
+        let event = EventData {
+            event_name: "Transfer".to_string(),
+            fields: HashMap::new(),
+            raw: None,
+            scores: HashMap::new(),
+            trace: None,
+            namespace: Namespace::Live,
+            block: None,
+            block_number: None,
+        };
+        let notification = Notification {
+            trigger_id: "t",
+            project_id: "p",
+            contract_addr: "c",
+            message: "hello".to_string(),
+            event: &event,
+            timestamp: 0,
+        };
+
+        // Nothing delivers yet, so the queue fills up
+        assert!(outbox.dispatch("https://example.com", &notification));
+        assert!(outbox.dispatch("https://example.com", &notification));
+        assert!(!outbox.dispatch("https://example.com", &notification));
+    }
+}